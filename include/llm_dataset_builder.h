@@ -0,0 +1,50 @@
+/*
+ * C interface to the llm_dataset_builder markdown parser.
+ *
+ * Build the library with:
+ *   cargo rustc --release --lib --features ffi --crate-type cdylib
+ * and link against target/release/libllm_dataset_builder.so (.dylib, .dll).
+ *
+ * Strings passed in are borrowed and must be NUL-terminated UTF-8. Strings
+ * returned by ldb_parse_markdown are owned by the caller and must be released
+ * with ldb_string_free.
+ */
+
+#ifndef LLM_DATASET_BUILDER_H
+#define LLM_DATASET_BUILDER_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* {"nodes": [...], "edges": [...]} */
+#define LDB_FORMAT_JSON 0
+/* Graphviz DOT */
+#define LDB_FORMAT_DOT 1
+
+/*
+ * Parse markdown into a document graph and serialize it in `format`.
+ *
+ * With a `name` (usually the document's path), node IDs are stable: the same
+ * name and content always give the same IDs. With `name` NULL they are
+ * random. Returns NULL on failure; ldb_last_error tells why.
+ */
+char *ldb_parse_markdown(const char *content, const char *name, int format);
+
+/*
+ * Why the last call on this thread failed, or NULL if it succeeded. Valid
+ * until the next call on the same thread; must not be freed.
+ */
+const char *ldb_last_error(void);
+
+/* Release a string returned by this library; NULL is ignored. */
+void ldb_string_free(char *string);
+
+/* Version of the library, e.g. "0.1.0"; must not be freed. */
+const char *ldb_version(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* LLM_DATASET_BUILDER_H */
@@ -0,0 +1,547 @@
+//! Round trip of records through a human annotation tool: records are
+//! pushed into a Label Studio project or an Argilla dataset as tasks, and the
+//! annotators' verdicts are pulled back into the dataset as each record's
+//! `review_status`, as the `review` command would set it.
+//!
+//! Records are matched by their `id`, so only records that have one can be
+//! sent. The project or dataset must already exist. Annotators pick `accept`
+//! or `reject` in a choice named `review`, and may correct the record in
+//! optional text answers named `question` and `answer`; the README has a
+//! Label Studio labeling config and Argilla dataset settings that do so.
+
+use anyhow::{anyhow, Context, Result};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::processor::ProcessedItem;
+use crate::review::ReviewStatus;
+
+pub const LABEL_STUDIO_URL_VAR: &str = "LABEL_STUDIO_URL";
+pub const LABEL_STUDIO_API_KEY_VAR: &str = "LABEL_STUDIO_API_KEY";
+pub const ARGILLA_API_URL_VAR: &str = "ARGILLA_API_URL";
+pub const ARGILLA_API_KEY_VAR: &str = "ARGILLA_API_KEY";
+
+/// Records sent per request
+const PUSH_BATCH: usize = 500;
+/// Records fetched per request from Argilla
+const PULL_PAGE: usize = 1000;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Annotation tool records are reviewed in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnnotationTool {
+    LabelStudio,
+    Argilla,
+}
+
+impl AnnotationTool {
+    pub const ALL: [AnnotationTool; 2] = [AnnotationTool::LabelStudio, AnnotationTool::Argilla];
+
+    /// Name used on the command line
+    pub fn name(&self) -> &'static str {
+        match self {
+            AnnotationTool::LabelStudio => "label-studio",
+            AnnotationTool::Argilla => "argilla",
+        }
+    }
+
+    /// Environment variables holding the tool's URL and API key
+    fn vars(&self) -> (&'static str, &'static str) {
+        match self {
+            AnnotationTool::LabelStudio => (LABEL_STUDIO_URL_VAR, LABEL_STUDIO_API_KEY_VAR),
+            AnnotationTool::Argilla => (ARGILLA_API_URL_VAR, ARGILLA_API_KEY_VAR),
+        }
+    }
+
+    fn default_url(&self) -> &'static str {
+        match self {
+            AnnotationTool::LabelStudio => "http://localhost:8080",
+            AnnotationTool::Argilla => "http://localhost:6900",
+        }
+    }
+}
+
+impl fmt::Display for AnnotationTool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for AnnotationTool {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "label-studio" | "labelstudio" => Ok(AnnotationTool::LabelStudio),
+            "argilla" => Ok(AnnotationTool::Argilla),
+            other => Err(anyhow!(
+                "Unknown annotation tool '{}' (expected label-studio or argilla)",
+                other
+            )),
+        }
+    }
+}
+
+/// An annotator's verdict on one record
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Annotation {
+    pub id: Uuid,
+    pub accepted: bool,
+    /// Corrections made by the annotator
+    pub question: Option<String>,
+    pub answer: Option<String>,
+}
+
+/// Client of one Label Studio project or Argilla dataset
+pub struct AnnotationClient {
+    tool: AnnotationTool,
+    url: String,
+    api_key: String,
+    /// Label Studio project ID or Argilla dataset ID
+    project: String,
+    http: reqwest::Client,
+}
+
+impl AnnotationClient {
+    pub fn new(tool: AnnotationTool, url: &str, api_key: &str, project: &str) -> Self {
+        Self {
+            tool,
+            url: url.trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+            project: project.to_string(),
+            http: reqwest::Client::new(),
+        }
+    }
+
+    /// Client configured from the tool's URL and API key variables
+    pub fn from_env(tool: AnnotationTool, project: &str) -> Result<Self> {
+        let (url_var, key_var) = tool.vars();
+        let url = std::env::var(url_var).unwrap_or_else(|_| tool.default_url().to_string());
+        let api_key = std::env::var(key_var)
+            .map_err(|_| anyhow!("{} needs an API key in {}", tool, key_var))?;
+        Ok(Self::new(tool, &url, &api_key, project))
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        let request = self
+            .http
+            .request(method, format!("{}{}", self.url, path))
+            .timeout(REQUEST_TIMEOUT);
+        match self.tool {
+            AnnotationTool::LabelStudio => {
+                request.header("Authorization", format!("Token {}", self.api_key))
+            }
+            AnnotationTool::Argilla => request.header("X-Argilla-Api-Key", &self.api_key),
+        }
+    }
+
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<Value> {
+        let response = request
+            .send()
+            .await
+            .with_context(|| format!("Could not reach {} at {}", self.tool, self.url))?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(anyhow!("{} replied {}: {}", self.tool, status, body.trim()));
+        }
+        Ok(response.json().await.unwrap_or(Value::Null))
+    }
+
+    /// Send records as tasks, returning how many were sent; records without
+    /// an ID are left out
+    pub async fn push(&self, items: &[ProcessedItem]) -> Result<usize> {
+        let identified: Vec<&ProcessedItem> =
+            items.iter().filter(|item| item.id.is_some()).collect();
+        for batch in identified.chunks(PUSH_BATCH) {
+            let request = match self.tool {
+                AnnotationTool::LabelStudio => self
+                    .request(
+                        reqwest::Method::POST,
+                        &format!("/api/projects/{}/import", self.project),
+                    )
+                    .json(
+                        &batch
+                            .iter()
+                            .map(|item| label_studio_task(item))
+                            .collect::<Vec<_>>(),
+                    ),
+                AnnotationTool::Argilla => self
+                    .request(
+                        reqwest::Method::POST,
+                        &format!("/api/v1/datasets/{}/records/bulk", self.project),
+                    )
+                    .json(&json!({
+                        "items": batch.iter().map(|item| argilla_record(item)).collect::<Vec<_>>()
+                    })),
+            };
+            self.send(request).await?;
+        }
+        Ok(identified.len())
+    }
+
+    /// Fetch the verdicts annotators have submitted so far
+    pub async fn pull(&self) -> Result<Vec<Annotation>> {
+        match self.tool {
+            AnnotationTool::LabelStudio => {
+                let export = self
+                    .send(self.request(
+                        reqwest::Method::GET,
+                        &format!("/api/projects/{}/export?exportType=JSON", self.project),
+                    ))
+                    .await?;
+                Ok(label_studio_annotations(&export))
+            }
+            AnnotationTool::Argilla => {
+                let mut annotations = Vec::new();
+                let mut offset = 0;
+                loop {
+                    let page = self
+                        .send(self.request(
+                            reqwest::Method::GET,
+                            &format!(
+                                "/api/v1/datasets/{}/records?include=responses&offset={}&limit={}",
+                                self.project, offset, PULL_PAGE
+                            ),
+                        ))
+                        .await?;
+                    let records = page["items"].as_array().map_or(0, Vec::len);
+                    annotations.extend(argilla_annotations(&page));
+                    if records < PULL_PAGE {
+                        return Ok(annotations);
+                    }
+                    offset += records;
+                }
+            }
+        }
+    }
+}
+
+/// Label Studio task for a record
+fn label_studio_task(item: &ProcessedItem) -> Value {
+    json!({
+        "data": {
+            "record_id": item.id,
+            "question": item.question,
+            "answer": item.answer,
+            "context": item.context.as_deref().unwrap_or_default(),
+        }
+    })
+}
+
+/// Argilla record for a record
+fn argilla_record(item: &ProcessedItem) -> Value {
+    let mut fields = json!({ "question": item.question, "answer": item.answer });
+    if let Some(context) = &item.context {
+        fields["context"] = json!(context);
+    }
+    json!({ "external_id": item.id, "fields": fields })
+}
+
+/// Corrected text, if the annotator wrote any
+fn correction(text: Option<&str>) -> Option<String> {
+    text.map(str::trim)
+        .filter(|text| !text.is_empty())
+        .map(str::to_string)
+}
+
+/// Verdicts in a Label Studio JSON export: the latest annotation of each
+/// task that was not skipped
+fn label_studio_annotations(export: &Value) -> Vec<Annotation> {
+    let mut annotations = Vec::new();
+    for task in export.as_array().into_iter().flatten() {
+        let Some(id) = task["data"]["record_id"]
+            .as_str()
+            .and_then(|id| id.parse().ok())
+        else {
+            continue;
+        };
+        let Some(annotation) = task["annotations"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .rfind(|annotation| annotation["was_cancelled"] != json!(true))
+        else {
+            continue;
+        };
+        let results: HashMap<&str, &Value> = annotation["result"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|result| Some((result["from_name"].as_str()?, &result["value"])))
+            .collect();
+        let Some(choice) = results
+            .get("review")
+            .and_then(|value| value["choices"][0].as_str())
+        else {
+            continue;
+        };
+        let text = |name: &str| {
+            correction(
+                results
+                    .get(name)
+                    .and_then(|value| value["text"][0].as_str()),
+            )
+        };
+        annotations.push(Annotation {
+            id,
+            accepted: choice.eq_ignore_ascii_case("accept"),
+            question: text("question"),
+            answer: text("answer"),
+        });
+    }
+    annotations
+}
+
+/// Verdicts in a page of Argilla records: the first submitted response of
+/// each record; a discarded record counts as rejected
+fn argilla_annotations(page: &Value) -> Vec<Annotation> {
+    let mut annotations = Vec::new();
+    for record in page["items"].as_array().into_iter().flatten() {
+        let Some(id) = record["external_id"]
+            .as_str()
+            .and_then(|id| id.parse().ok())
+        else {
+            continue;
+        };
+        let responses = record["responses"].as_array().into_iter().flatten();
+        let Some(response) = responses
+            .clone()
+            .find(|response| response["status"] == "submitted")
+            .or_else(|| {
+                responses
+                    .clone()
+                    .find(|response| response["status"] == "discarded")
+            })
+        else {
+            continue;
+        };
+        let values = &response["values"];
+        let accepted = response["status"] == "submitted"
+            && values["review"]["value"]
+                .as_str()
+                .is_some_and(|value| value.eq_ignore_ascii_case("accept"));
+        annotations.push(Annotation {
+            id,
+            accepted,
+            question: correction(values["question"]["value"].as_str()),
+            answer: correction(values["answer"]["value"].as_str()),
+        });
+    }
+    annotations
+}
+
+/// Set the review status of the records annotated, applying corrections to
+/// accepted ones, and return how many records were updated
+pub fn apply_annotations(items: &mut [ProcessedItem], annotations: &[Annotation]) -> usize {
+    let by_id: HashMap<Uuid, &Annotation> = annotations
+        .iter()
+        .map(|annotation| (annotation.id, annotation))
+        .collect();
+    let mut updated = 0;
+    for item in items.iter_mut() {
+        let Some(annotation) = item.id.and_then(|id| by_id.get(&id)) else {
+            continue;
+        };
+        item.review_status = Some(if !annotation.accepted {
+            ReviewStatus::Rejected
+        } else {
+            let mut edited = false;
+            for (text, correction) in [
+                (&mut item.question, &annotation.question),
+                (&mut item.answer, &annotation.answer),
+            ] {
+                if let Some(correction) = correction.as_ref().filter(|c| *c != text.trim()) {
+                    *text = correction.clone();
+                    edited = true;
+                }
+            }
+            if edited {
+                ReviewStatus::Edited
+            } else {
+                ReviewStatus::Accepted
+            }
+        });
+        updated += 1;
+    }
+    updated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(question: &str) -> ProcessedItem {
+        let mut item = ProcessedItem::new(question, "An answer.");
+        item.assign_id("guide.md", "span");
+        item
+    }
+
+    #[test]
+    fn test_label_studio_round_trip() {
+        let items = [item("One?"), item("Two?"), item("Three?")];
+        let task = label_studio_task(&items[0]);
+        assert_eq!(task["data"]["question"], "One?");
+        assert_eq!(task["data"]["context"], "");
+
+        let id = |i: usize| items[i].id.unwrap().to_string();
+        let export = json!([
+            {"data": {"record_id": id(0)}, "annotations": [
+                {"was_cancelled": false, "result": [
+                    {"from_name": "review", "value": {"choices": ["Reject"]}}
+                ]},
+                {"was_cancelled": false, "result": [
+                    {"from_name": "review", "value": {"choices": ["Accept"]}},
+                    {"from_name": "answer", "value": {"text": ["A better answer."]}}
+                ]}
+            ]},
+            {"data": {"record_id": id(1)}, "annotations": [
+                {"was_cancelled": false, "result": [
+                    {"from_name": "review", "value": {"choices": ["Reject"]}}
+                ]}
+            ]},
+            {"data": {"record_id": id(2)}, "annotations": [
+                {"was_cancelled": true, "result": []}
+            ]}
+        ]);
+        let annotations = label_studio_annotations(&export);
+        assert_eq!(annotations.len(), 2);
+        assert!(annotations[0].accepted);
+        assert_eq!(annotations[0].answer.as_deref(), Some("A better answer."));
+        assert!(!annotations[1].accepted);
+    }
+
+    #[test]
+    fn test_argilla_round_trip() {
+        let mut items = vec![item("One?"), item("Two?"), item("Three?")];
+        items[0].context = Some("Some context.".to_string());
+        let record = argilla_record(&items[0]);
+        assert_eq!(record["external_id"], items[0].id.unwrap().to_string());
+        assert_eq!(record["fields"]["context"], "Some context.");
+        assert!(argilla_record(&items[1])["fields"].get("context").is_none());
+
+        let id = |i: usize| items[i].id.unwrap().to_string();
+        let page = json!({"items": [
+            {"external_id": id(0), "responses": [
+                {"status": "submitted", "values": {
+                    "review": {"value": "accept"},
+                    "question": {"value": "  "}
+                }}
+            ]},
+            {"external_id": id(1), "responses": [
+                {"status": "discarded", "values": {}}
+            ]},
+            {"external_id": id(2), "responses": [
+                {"status": "draft", "values": {"review": {"value": "accept"}}}
+            ]}
+        ]});
+        let annotations = argilla_annotations(&page);
+        assert_eq!(annotations.len(), 2);
+        assert!(annotations[0].accepted);
+        assert_eq!(annotations[0].question, None);
+        assert!(!annotations[1].accepted);
+
+        assert_eq!(apply_annotations(&mut items, &annotations), 2);
+        assert_eq!(items[0].review_status, Some(ReviewStatus::Accepted));
+        assert_eq!(items[1].review_status, Some(ReviewStatus::Rejected));
+        assert_eq!(items[2].review_status, None);
+    }
+
+    #[test]
+    fn test_apply_annotations_with_corrections() {
+        let mut items = vec![item("One?"), item("Two?")];
+        let annotations = [
+            Annotation {
+                id: items[0].id.unwrap(),
+                accepted: true,
+                question: Some("One, exactly?".to_string()),
+                answer: Some("An answer.".to_string()),
+            },
+            Annotation {
+                id: items[1].id.unwrap(),
+                accepted: false,
+                question: None,
+                answer: Some("Ignored.".to_string()),
+            },
+        ];
+        assert_eq!(apply_annotations(&mut items, &annotations), 2);
+        assert_eq!(items[0].review_status, Some(ReviewStatus::Edited));
+        assert_eq!(items[0].question, "One, exactly?");
+        assert_eq!(items[1].review_status, Some(ReviewStatus::Rejected));
+        assert_eq!(items[1].answer, "An answer.");
+    }
+
+    #[tokio::test]
+    async fn test_label_studio_over_http() {
+        use hyper::service::{make_service_fn, service_fn};
+        use hyper::{Body, Method, Response, Server};
+        use std::convert::Infallible;
+        use std::sync::{Arc, Mutex};
+
+        let items = [item("One?"), item("Two?")];
+        let export = json!([{"data": {"record_id": items[1].id}, "annotations": [
+            {"result": [{"from_name": "review", "value": {"choices": ["Reject"]}}]}
+        ]}]);
+        let imported = Arc::new(Mutex::new(Vec::new()));
+        let received = imported.clone();
+        let make_service = make_service_fn(move |_| {
+            let (export, received) = (export.clone(), received.clone());
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let (export, received) = (export.clone(), received.clone());
+                    async move {
+                        let authorized = request.headers()["authorization"] == "Token secret";
+                        let route = (request.method().clone(), request.uri().path().to_string());
+                        let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+                        let reply = match (authorized, route) {
+                            (false, _) => Response::builder().status(401).body(Body::empty()),
+                            (true, (Method::POST, path)) if path == "/api/projects/7/import" => {
+                                let tasks: Vec<Value> = serde_json::from_slice(&body).unwrap();
+                                received.lock().unwrap().extend(tasks);
+                                Response::builder().body(Body::from("{}"))
+                            }
+                            (true, (Method::GET, path)) if path == "/api/projects/7/export" => {
+                                Response::builder().body(Body::from(export.to_string()))
+                            }
+                            _ => Response::builder().status(404).body(Body::empty()),
+                        };
+                        Ok::<_, Infallible>(reply.unwrap())
+                    }
+                }))
+            }
+        });
+        let server = Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_service);
+        let url = format!("http://{}/", server.local_addr());
+        tokio::spawn(server);
+
+        let client = AnnotationClient::new(AnnotationTool::LabelStudio, &url, "secret", "7");
+        let mut unidentified = item("Three?");
+        unidentified.id = None;
+        let pushed = client
+            .push(&[items[0].clone(), items[1].clone(), unidentified])
+            .await
+            .unwrap();
+        assert_eq!(pushed, 2);
+        assert_eq!(imported.lock().unwrap()[1]["data"]["question"], "Two?");
+
+        let annotations = client.pull().await.unwrap();
+        assert_eq!(annotations.len(), 1);
+        assert_eq!(annotations[0].id, items[1].id.unwrap());
+
+        let wrong_key = AnnotationClient::new(AnnotationTool::LabelStudio, &url, "guess", "7");
+        let error = wrong_key.pull().await.unwrap_err();
+        assert!(error.to_string().starts_with("label-studio replied 401"));
+    }
+
+    #[test]
+    fn test_annotation_tool_names() {
+        for tool in AnnotationTool::ALL {
+            assert_eq!(tool.name().parse::<AnnotationTool>().unwrap(), tool);
+        }
+        assert!("prodigy".parse::<AnnotationTool>().is_err());
+    }
+}
@@ -0,0 +1,746 @@
+//! Dataset augmentation, adding variants of each record's question.
+//!
+//! Back-translation paraphrases questions by translating them into a pivot
+//! language and back with the generation model, so a small corpus yields
+//! several phrasings of each question. A round trip can change what is
+//! asked, so every paraphrase is compared with its original by the cosine
+//! similarity of their embeddings, and discarded when it drifted too far.
+//!
+//! Noise injection writes questions the way users type them, with typos,
+//! missing punctuation and casual phrasing, so a model trained on them
+//! copes with real input. It needs no model: the noise is picked by a hash
+//! of the question, so the same dataset always gets the same variants.
+
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use std::collections::HashSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+use crate::external::{ChatMessage, ChatRequest, Embedder, ModelProvider};
+use crate::graph::similarity::cosine_similarity;
+use crate::hash::stable_u64;
+use crate::metadata::detect_lang;
+use crate::processor::ProcessedItem;
+use crate::review::ReviewStatus;
+
+/// Cosine similarity to the original question a paraphrase needs to be kept
+pub const DEFAULT_MIN_SIMILARITY: f32 = 0.85;
+
+/// Instructions for translating; `{language}` is the target language
+pub const TRANSLATE_PROMPT: &str = "You translate questions about technical documentation. \
+     Translate the user's text into {language}. \
+     Keep code, identifiers, file names, product names and numbers exactly as they are. \
+     Reply with the translation only, without quotes, notes or the original text.";
+
+/// Language questions are translated back into when theirs can't be told
+const DEFAULT_LANGUAGE: &str = "English";
+
+/// English name of a language given by its ISO 639-1 code
+fn language_name(code: &str) -> Option<&'static str> {
+    let name = match code.to_lowercase().as_str() {
+        "en" => "English",
+        "de" => "German",
+        "fr" => "French",
+        "es" => "Spanish",
+        "it" => "Italian",
+        "pt" => "Portuguese",
+        "nl" => "Dutch",
+        "ru" => "Russian",
+        "el" => "Greek",
+        "he" => "Hebrew",
+        "ar" => "Arabic",
+        "hi" => "Hindi",
+        "th" => "Thai",
+        "zh" => "Chinese",
+        "ja" => "Japanese",
+        "ko" => "Korean",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// Lowercased words of a question, so paraphrases differing only in case,
+/// spacing or punctuation count as unchanged
+fn normalized(text: &str) -> String {
+    text.split_whitespace()
+        .map(|word| {
+            word.trim_matches(|c: char| !c.is_alphanumeric())
+                .to_lowercase()
+        })
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Mark of a record made by back-translating through `pivot`
+pub fn back_translation(pivot: &str) -> String {
+    format!("back_translation:{}", pivot)
+}
+
+/// Mark of a record with noise injected
+pub const NOISE: &str = "noise";
+
+/// What augmenting a dataset did
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AugmentReport {
+    /// Records in the dataset
+    pub records: usize,
+    /// Records given variants; evaluation, rejected and augmented records are
+    /// not
+    pub augmented: usize,
+    /// Variants added, paraphrases and noisy ones
+    pub variants: usize,
+    /// Noisy variants among them
+    pub noisy: usize,
+    /// Paraphrases discarded for drifting from the original's meaning
+    pub drifted: usize,
+    /// Round trips that gave back the original or an earlier paraphrase
+    pub unchanged: usize,
+    /// Round trips the model failed
+    pub failed: usize,
+}
+
+impl fmt::Display for AugmentReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Added {} variants ({} noisy) of {} of {} records; paraphrases: {} drifted, {} unchanged, {} failed",
+            self.variants,
+            self.noisy,
+            self.augmented,
+            self.records,
+            self.drifted,
+            self.unchanged,
+            self.failed
+        )
+    }
+}
+
+/// Whether a record gets variants: evaluation records stay as they are so no
+/// phrasing of them leaks into training, records rejected in review are not
+/// trained on, and variants are not varied again
+fn augments(item: &ProcessedItem) -> bool {
+    !item.is_eval()
+        && item.review_status != Some(ReviewStatus::Rejected)
+        && item.metadata.augmentation.is_none()
+}
+
+/// What a variant's ID is made from: its original's ID, or question when it
+/// has none
+fn original_key(item: &ProcessedItem) -> String {
+    item.id
+        .map(|id| id.to_string())
+        .unwrap_or_else(|| item.question.trim().to_string())
+}
+
+/// A copy of `item` asking `question`, marked as derived from it
+fn variant(
+    item: &ProcessedItem,
+    question: String,
+    id: Uuid,
+    augmentation: String,
+) -> ProcessedItem {
+    let mut variant = item.clone();
+    variant.id = Some(id);
+    variant.question = question;
+    variant.review_status = None;
+    variant.metadata.augmentation = Some(augmentation);
+    variant.metadata.augmented_from = item.id;
+    if variant.metadata.created_at.is_some() {
+        variant.metadata.created_at = Some(Utc::now());
+    }
+    variant
+}
+
+/// Paraphrases questions by back-translation
+pub struct BackTranslator<'a> {
+    provider: &'a dyn ModelProvider,
+    embedder: &'a dyn Embedder,
+    min_similarity: f32,
+}
+
+impl<'a> BackTranslator<'a> {
+    pub fn new(provider: &'a dyn ModelProvider, embedder: &'a dyn Embedder) -> Self {
+        Self {
+            provider,
+            embedder,
+            min_similarity: DEFAULT_MIN_SIMILARITY,
+        }
+    }
+
+    /// Cosine similarity to the original a paraphrase needs to be kept
+    pub fn with_min_similarity(mut self, min_similarity: f32) -> Self {
+        self.min_similarity = min_similarity;
+        self
+    }
+
+    /// Translate `text` into `language`, given by name or ISO 639-1 code
+    pub async fn translate(&self, text: &str, language: &str) -> Result<String> {
+        let language = language_name(language).unwrap_or(language);
+        let reply = self
+            .provider
+            .chat(ChatRequest {
+                messages: vec![
+                    ChatMessage::system(TRANSLATE_PROMPT.replace("{language}", language)),
+                    ChatMessage::user(text),
+                ],
+                format: None,
+                max_tokens: None,
+            })
+            .await?;
+        let translation = reply.trim().trim_matches('"').trim();
+        if translation.is_empty() {
+            return Err(anyhow!("Empty translation into {}", language));
+        }
+        Ok(translation.to_string())
+    }
+
+    /// Translate a question into `pivot` and back into its own language
+    pub async fn back_translate(&self, question: &str, pivot: &str) -> Result<String> {
+        let language = detect_lang(question)
+            .and_then(language_name)
+            .unwrap_or(DEFAULT_LANGUAGE);
+        let translated = self.translate(question, pivot).await?;
+        self.translate(&translated, language).await
+    }
+
+    /// Paraphrases of one record through each of `pivots`
+    ///
+    /// Paraphrases get their own ID, made from the original's, and keep its
+    /// answer, context and lineage. `known` holds the IDs already in the
+    /// dataset, so augmenting twice doesn't add the same paraphrase again.
+    pub async fn variants(
+        &self,
+        item: &ProcessedItem,
+        pivots: &[String],
+        known: &mut HashSet<Uuid>,
+        report: &mut AugmentReport,
+    ) -> Result<Vec<ProcessedItem>> {
+        let original = original_key(item);
+        let mut seen = HashSet::from([normalized(&item.question)]);
+        let mut candidates = Vec::new();
+        for pivot in pivots {
+            let question = match self.back_translate(&item.question, pivot).await {
+                Ok(question) => question,
+                Err(_) => {
+                    report.failed += 1;
+                    continue;
+                }
+            };
+            let id = ProcessedItem::item_id(&original, &back_translation(pivot), &question);
+            if !seen.insert(normalized(&question)) || known.contains(&id) {
+                report.unchanged += 1;
+                continue;
+            }
+            candidates.push((pivot, question, id));
+        }
+        if candidates.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let texts: Vec<String> = std::iter::once(item.question.clone())
+            .chain(candidates.iter().map(|(_, question, _)| question.clone()))
+            .collect();
+        let embeddings = self.embedder.embed_batch(&texts).await?;
+        let (first, rest) = embeddings
+            .split_first()
+            .ok_or_else(|| anyhow!("No embeddings returned"))?;
+
+        let mut variants = Vec::new();
+        for ((pivot, question, id), embedding) in candidates.into_iter().zip(rest) {
+            if cosine_similarity(first, embedding) < self.min_similarity {
+                report.drifted += 1;
+                continue;
+            }
+            known.insert(id);
+            variants.push(variant(item, question, id, back_translation(pivot)));
+        }
+        Ok(variants)
+    }
+}
+
+/// Deterministic pseudo-random numbers (SplitMix64), seeded from a hash of
+/// the text being varied
+struct NoiseRng(u64);
+
+impl NoiseRng {
+    fn seeded(text: &str, variant: usize) -> Self {
+        Self(stable_u64(&(variant as u64).to_le_bytes(), text.trim()))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A number below `n`
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n.max(1) as u64) as usize
+    }
+}
+
+/// Formal openings of questions and how users type them
+const CASUAL_OPENINGS: &[(&str, &str)] = &[
+    ("how do i ", "how do i "),
+    ("how can i ", "how to "),
+    ("how do you ", "how to "),
+    ("what is ", "whats "),
+    ("what are ", "what r "),
+    ("is it possible to ", "can i "),
+    ("can you ", "can u "),
+    ("could you ", "can u "),
+    ("why does ", "why does "),
+    ("where is ", "wheres "),
+];
+
+/// Openings added to questions that have none of the above
+const CASUAL_PREFIXES: &[&str] = &["hey ", "quick question, ", "so ", "hi, "];
+
+/// Letters next to each other on a QWERTY keyboard
+const KEYBOARD_ROWS: [&str; 3] = ["qwertyuiop", "asdfghjkl", "zxcvbnm"];
+
+/// Rephrase the opening of a question the way users type it
+fn casual(text: &str, rng: &mut NoiseRng) -> String {
+    let lower = text.to_lowercase();
+    for (formal, informal) in CASUAL_OPENINGS {
+        // Compared by bytes, so a question starting with non-ASCII text is
+        // not cut inside a character
+        if lower.starts_with(formal) && text.is_char_boundary(formal.len()) {
+            return format!("{}{}", informal, &text[formal.len()..]);
+        }
+    }
+    let prefix = CASUAL_PREFIXES[rng.below(CASUAL_PREFIXES.len())];
+    let mut chars = text.chars();
+    match chars.next() {
+        // Only a capital opening a plain word is lowered, not an identifier
+        Some(first) if text.split(' ').next().is_some_and(is_plain_word) => {
+            format!("{}{}{}", prefix, first.to_lowercase(), chars.as_str())
+        }
+        _ => format!("{}{}", prefix, text),
+    }
+}
+
+/// A word of letters only, capitalized at most at its start, so code and
+/// names like `cache_dir` or `OpenAI` are never misspelled
+fn is_plain_word(word: &str) -> bool {
+    let mut chars = word.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic()) && chars.all(|c| c.is_ascii_lowercase())
+}
+
+/// A letter next to `c` on the keyboard
+fn neighbour(c: char, rng: &mut NoiseRng) -> char {
+    for row in KEYBOARD_ROWS {
+        if let Some(at) = row.find(c) {
+            let row = row.as_bytes();
+            let next = match (at, rng.below(2)) {
+                (0, _) => 1,
+                (at, _) if at + 1 == row.len() => at - 1,
+                (at, 0) => at - 1,
+                (at, _) => at + 1,
+            };
+            return row[next] as char;
+        }
+    }
+    c
+}
+
+/// Misspell a word: swap, drop, double or mistype one of its letters after
+/// the first
+fn misspell(word: &str, rng: &mut NoiseRng) -> String {
+    let mut chars: Vec<char> = word.chars().collect();
+    let at = 1 + rng.below(chars.len() - 1);
+    match rng.below(4) {
+        0 if at + 1 < chars.len() => chars.swap(at, at + 1),
+        1 => {
+            chars.remove(at);
+        }
+        2 => chars.insert(at, chars[at]),
+        _ => chars[at] = neighbour(chars[at], rng),
+    }
+    chars.into_iter().collect()
+}
+
+/// Misspell one plain word of at least four letters, or two in questions
+/// of eight words or more
+fn typos(text: &str, rng: &mut NoiseRng) -> String {
+    let mut words: Vec<String> = text.split(' ').map(str::to_string).collect();
+    let mut eligible: Vec<usize> = (0..words.len())
+        .filter(|&i| words[i].len() >= 4 && is_plain_word(&words[i]))
+        .collect();
+    let count = if words.len() >= 8 { 2 } else { 1 };
+    for _ in 0..count.min(eligible.len()) {
+        let i = eligible.remove(rng.below(eligible.len()));
+        words[i] = misspell(&words[i], rng);
+    }
+    words.join(" ")
+}
+
+/// Drop the closing punctuation, and often the commas and apostrophes
+fn unpunctuated(text: &str, rng: &mut NoiseRng) -> String {
+    let text = text.trim_end_matches(['?', '.', '!', ' ']);
+    match rng.below(2) {
+        0 => text.to_string(),
+        _ => text.replace([',', '\''], ""),
+    }
+}
+
+/// A noisy version of a question, the `variant`th for it
+///
+/// Casual phrasing, typos and missing punctuation are each injected or not,
+/// at least one of them, as the question's hash picks.
+pub fn add_noise(question: &str, variant: usize) -> String {
+    let mut rng = NoiseRng::seeded(question, variant);
+    let kinds = 1 + rng.below(7);
+    let mut text = question.trim().to_string();
+    if kinds & 1 != 0 {
+        text = casual(&text, &mut rng);
+    }
+    if kinds & 2 != 0 {
+        text = typos(&text, &mut rng);
+    }
+    if kinds & 4 != 0 {
+        text = unpunctuated(&text, &mut rng);
+    }
+    text
+}
+
+/// Up to `count` noisy variants of a record, tagged `noisy`
+///
+/// Like paraphrases they get their own ID and keep the original's answer.
+/// The same record always gets the same variants, and those already in
+/// `known` are not made again.
+pub fn noisy_variants(
+    item: &ProcessedItem,
+    count: usize,
+    known: &mut HashSet<Uuid>,
+) -> Vec<ProcessedItem> {
+    let original = original_key(item);
+    let mut questions: Vec<String> = Vec::new();
+    // Noise may leave a short question as it was, so a few more are tried
+    for attempt in 0..count * 3 {
+        if questions.len() == count {
+            break;
+        }
+        let question = add_noise(&item.question, attempt);
+        if question != item.question.trim() && !questions.contains(&question) {
+            questions.push(question);
+        }
+    }
+    let mut variants = Vec::new();
+    for question in questions {
+        let id = ProcessedItem::item_id(&original, NOISE, &question);
+        if known.insert(id) {
+            let mut noisy = variant(item, question, id, NOISE.to_string());
+            noisy.metadata.noisy = Some(true);
+            variants.push(noisy);
+        }
+    }
+    variants
+}
+
+/// Adds variants to the records of a dataset
+#[derive(Default)]
+pub struct Augmenter<'a> {
+    back_translation: Option<(BackTranslator<'a>, Vec<String>)>,
+    noise: usize,
+}
+
+impl<'a> Augmenter<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Paraphrase each record through each of `pivots`
+    pub fn with_back_translation(
+        mut self,
+        translator: BackTranslator<'a>,
+        pivots: Vec<String>,
+    ) -> Self {
+        self.back_translation = Some((translator, pivots)).filter(|(_, pivots)| !pivots.is_empty());
+        self
+    }
+
+    /// Add `count` noisy variants of each record
+    pub fn with_noise(mut self, count: usize) -> Self {
+        self.noise = count;
+        self
+    }
+
+    /// Each record of a dataset followed by its variants; only the first
+    /// `limit` records get any
+    pub async fn augment(
+        &self,
+        items: Vec<ProcessedItem>,
+        limit: Option<usize>,
+    ) -> Result<(Vec<ProcessedItem>, AugmentReport)> {
+        let mut report = AugmentReport {
+            records: items.len(),
+            ..Default::default()
+        };
+        let mut known: HashSet<Uuid> = items.iter().filter_map(|item| item.id).collect();
+        let limit = limit.unwrap_or(usize::MAX);
+        let mut augmented = Vec::with_capacity(items.len());
+        for (index, item) in items.into_iter().enumerate() {
+            let mut variants = Vec::new();
+            if index < limit && augments(&item) {
+                if let Some((translator, pivots)) = &self.back_translation {
+                    variants = translator
+                        .variants(&item, pivots, &mut known, &mut report)
+                        .await?;
+                }
+                let noisy = noisy_variants(&item, self.noise, &mut known);
+                report.noisy += noisy.len();
+                variants.extend(noisy);
+            }
+            if !variants.is_empty() {
+                report.augmented += 1;
+                report.variants += variants.len();
+            }
+            augmented.push(item);
+            augmented.extend(variants);
+        }
+        Ok((augmented, report))
+    }
+}
+
+/// Where an augmented dataset is written: `NAME.augmented.jsonl`
+pub fn augmented_path(path: &Path) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("dataset");
+    path.with_file_name(format!("{}.augmented.jsonl", stem))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::external::embedding::MockEmbedder;
+    use crate::external::{ProviderUsage, TokenStream};
+    use async_trait::async_trait;
+    use std::sync::Mutex;
+
+    /// Translates by looking replies up by the request's text and target
+    /// language, recording each request
+    struct Dictionary {
+        entries: Vec<(&'static str, &'static str, &'static str)>,
+        requests: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl ModelProvider for Dictionary {
+        fn model(&self) -> &str {
+            "dictionary"
+        }
+
+        fn endpoint(&self) -> &str {
+            "http://dictionary"
+        }
+
+        async fn generate(&self, _prompt: &str) -> Result<String> {
+            unimplemented!()
+        }
+
+        async fn generate_stream(&self, _prompt: &str) -> Result<TokenStream> {
+            unimplemented!()
+        }
+
+        async fn chat(&self, request: ChatRequest) -> Result<String> {
+            let (system, text) = (&request.messages[0].content, &request.messages[1].content);
+            self.requests.lock().unwrap().push(text.clone());
+            self.entries
+                .iter()
+                .find(|(from, language, _)| {
+                    from == text && system.contains(&format!("into {}.", language))
+                })
+                .map(|(_, _, to)| to.to_string())
+                .ok_or_else(|| anyhow!("no translation of {:?}", text))
+        }
+
+        async fn chat_stream(&self, _request: ChatRequest) -> Result<TokenStream> {
+            unimplemented!()
+        }
+
+        async fn list_models(&self) -> Result<Vec<String>> {
+            Ok(Vec::new())
+        }
+
+        async fn pull_model(&self) -> Result<()> {
+            Ok(())
+        }
+
+        fn usage(&self) -> ProviderUsage {
+            ProviderUsage::default()
+        }
+    }
+
+    /// Embeds questions about caching close together, and others apart
+    fn embedder() -> MockEmbedder {
+        let mut embedder = MockEmbedder::new();
+        embedder.expect_embed_batch().returning(|texts| {
+            Ok(texts
+                .iter()
+                .map(|text| match text.to_lowercase().contains("cache") {
+                    true => vec![1.0, 0.1],
+                    false => vec![0.0, 1.0],
+                })
+                .collect())
+        });
+        embedder
+    }
+
+    fn item(question: &str) -> ProcessedItem {
+        let mut item = ProcessedItem::new(question, "Set cache_dir in config.toml.");
+        item.assign_id("guide.md", "section");
+        item
+    }
+
+    const QUESTION: &str = "How is the cache configured?";
+
+    fn dictionary() -> Dictionary {
+        Dictionary {
+            entries: vec![
+                (QUESTION, "German", "Wie wird der Cache konfiguriert?"),
+                (
+                    "Wie wird der Cache konfiguriert?",
+                    "English",
+                    "How do you configure the cache?",
+                ),
+                (QUESTION, "French", "Comment configure-t-on le cache ?"),
+                (
+                    "Comment configure-t-on le cache ?",
+                    "English",
+                    "How is the cache configured?",
+                ),
+                (QUESTION, "Spanish", "¿Cómo se configura la caché?"),
+                (
+                    "¿Cómo se configura la caché?",
+                    "English",
+                    "How do you set up the storage?",
+                ),
+            ],
+            requests: Mutex::new(Vec::new()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_back_translation_keeps_close_paraphrases() {
+        let (provider, embedder) = (dictionary(), embedder());
+        let pivots: Vec<String> = ["de", "fr", "es", "ja"].map(String::from).to_vec();
+        let augmenter = Augmenter::new()
+            .with_back_translation(BackTranslator::new(&provider, &embedder), pivots);
+        let original = item(QUESTION);
+        let (items, report) = augmenter
+            .augment(vec![original.clone()], None)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            report,
+            AugmentReport {
+                records: 1,
+                augmented: 1,
+                variants: 1,
+                noisy: 0,
+                drifted: 1,
+                unchanged: 1,
+                failed: 1,
+            }
+        );
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].id, original.id);
+        let variant = &items[1];
+        assert_eq!(variant.question, "How do you configure the cache?");
+        assert_eq!(variant.answer, original.answer);
+        assert_eq!(
+            variant.metadata.augmentation.as_deref(),
+            Some("back_translation:de")
+        );
+        assert_eq!(variant.metadata.augmented_from, original.id);
+        assert!(variant.id.is_some() && variant.id != original.id);
+
+        // Augmenting the augmented dataset adds nothing new
+        let (again, report) = augmenter.augment(items.clone(), None).await.unwrap();
+        let ids = |items: &[ProcessedItem]| items.iter().map(|item| item.id).collect::<Vec<_>>();
+        assert_eq!(ids(&again), ids(&items));
+        assert_eq!(report.variants, 0);
+    }
+
+    #[tokio::test]
+    async fn test_eval_and_rejected_records_are_not_augmented() {
+        let (provider, embedder) = (dictionary(), embedder());
+        let augmenter = Augmenter::new()
+            .with_back_translation(
+                BackTranslator::new(&provider, &embedder),
+                vec!["de".to_string()],
+            )
+            .with_noise(2);
+        let mut eval = item(QUESTION);
+        eval.rubric = Some(vec!["Mentions cache_dir".to_string()]);
+        let mut rejected = item(QUESTION);
+        rejected.review_status = Some(ReviewStatus::Rejected);
+        let (items, report) = augmenter
+            .augment(vec![eval, rejected, item(QUESTION)], Some(2))
+            .await
+            .unwrap();
+        assert_eq!(items.len(), 3);
+        assert_eq!(report.augmented, 0);
+        assert!(provider.requests.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_noisy_variants() {
+        let question = "How do I set the embedding model for a collection?";
+        for variant in 0..20 {
+            let noisy = add_noise(question, variant);
+            assert_ne!(noisy, question);
+            assert_eq!(noisy, add_noise(question, variant));
+        }
+        // Identifiers are never misspelled
+        for variant in 0..20 {
+            assert!(
+                add_noise("Where is cache_dir set in config.toml?", variant).contains("cache_dir")
+            );
+        }
+        assert_eq!(
+            casual("How can I enable TLS?", &mut NoiseRng(0)),
+            "how to enable TLS?"
+        );
+
+        let original = item(question);
+        let (items, report) = Augmenter::new()
+            .with_noise(3)
+            .augment(vec![original.clone()], None)
+            .await
+            .unwrap();
+        assert_eq!((report.variants, report.noisy), (3, 3));
+        for noisy in &items[1..] {
+            assert_ne!(noisy.question, question);
+            assert_eq!(noisy.metadata.noisy, Some(true));
+            assert_eq!(noisy.metadata.augmentation.as_deref(), Some(NOISE));
+            assert_eq!(noisy.metadata.augmented_from, original.id);
+        }
+        let value = serde_json::to_value(&items[1]).unwrap();
+        assert_eq!(value["noisy"], true);
+
+        let (_, report) = Augmenter::new()
+            .with_noise(3)
+            .augment(items, None)
+            .await
+            .unwrap();
+        assert_eq!(report.variants, 0);
+    }
+
+    #[test]
+    fn test_augmented_path() {
+        assert_eq!(
+            augmented_path(Path::new("out/all_qa.jsonl")),
+            Path::new("out/all_qa.augmented.jsonl")
+        );
+    }
+}
@@ -0,0 +1,249 @@
+//! Balanced sampling of a dataset, so no single source document (or
+//! directory of documents) makes up more than a set share of it. Without it
+//! one long changelog can contribute most of the questions.
+
+use anyhow::{anyhow, Result};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::processor::ProcessedItem;
+
+/// What records are grouped by when capping their share
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BalanceBy {
+    /// The document each record was generated from
+    #[default]
+    Document,
+    /// The directory of that document, as a stand-in for its topic
+    Directory,
+}
+
+impl BalanceBy {
+    pub const ALL: [BalanceBy; 2] = [BalanceBy::Document, BalanceBy::Directory];
+
+    /// Name used on the command line
+    pub fn name(&self) -> &'static str {
+        match self {
+            BalanceBy::Document => "document",
+            BalanceBy::Directory => "directory",
+        }
+    }
+
+    /// Group of a record; records without lineage share one group
+    pub fn group(&self, item: &ProcessedItem) -> String {
+        let Some(lineage) = &item.lineage else {
+            return String::new();
+        };
+        match self {
+            BalanceBy::Document => lineage.document.clone(),
+            BalanceBy::Directory => Path::new(&lineage.document)
+                .parent()
+                .map(|dir| dir.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl fmt::Display for BalanceBy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for BalanceBy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "document" => Ok(BalanceBy::Document),
+            "directory" => Ok(BalanceBy::Directory),
+            other => Err(anyhow!(
+                "Unknown balance grouping '{}' (expected document or directory)",
+                other
+            )),
+        }
+    }
+}
+
+/// Settings of balanced sampling (`--max-source-share`)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Balance {
+    /// Largest share of the dataset one group may make up, in percent
+    pub max_share: f64,
+    pub by: BalanceBy,
+}
+
+/// Records a group had and kept
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupCount {
+    pub group: String,
+    pub records: usize,
+    pub kept: usize,
+}
+
+/// The outcome of balancing a dataset
+#[derive(Debug, Clone)]
+pub struct Balanced {
+    pub items: Vec<ProcessedItem>,
+    /// Records a group may keep at most
+    pub cap: usize,
+    /// Every group, largest first
+    pub groups: Vec<GroupCount>,
+}
+
+impl Balanced {
+    pub fn dropped(&self) -> usize {
+        self.groups.iter().map(|g| g.records - g.kept).sum()
+    }
+}
+
+impl fmt::Display for Balanced {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Kept {} records, dropped {} (at most {} per group)",
+            self.items.len(),
+            self.dropped(),
+            self.cap
+        )?;
+        for group in self.groups.iter().filter(|g| g.kept < g.records) {
+            let name = if group.group.is_empty() {
+                "(no lineage)"
+            } else {
+                &group.group
+            };
+            write!(f, "\n  {}: {} of {}", name, group.kept, group.records)?;
+        }
+        Ok(())
+    }
+}
+
+impl Balance {
+    /// Drop records of groups over their share, keeping dataset order
+    ///
+    /// The cap is found for the balanced dataset rather than the original:
+    /// dropping records shrinks the dataset and so every group's allowance.
+    /// With fewer groups than the share allows for, e.g. two documents at
+    /// 20% each, every group is capped at an equal share instead. Within a
+    /// group the kept records are spread evenly over its records, so later
+    /// sections of a long document are still represented.
+    pub fn apply(&self, items: Vec<ProcessedItem>) -> Balanced {
+        let mut groups: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+        for (index, item) in items.iter().enumerate() {
+            groups.entry(self.by.group(item)).or_default().push(index);
+        }
+        let share = (self.max_share / 100.0).max(1.0 / groups.len().max(1) as f64);
+
+        let mut total = items.len();
+        let cap = loop {
+            let cap = ((share * total as f64).floor() as usize).max(1);
+            let kept: usize = groups.values().map(|g| g.len().min(cap)).sum();
+            if kept >= total {
+                break cap;
+            }
+            total = kept;
+        };
+
+        let mut keep = vec![false; items.len()];
+        let mut counts: Vec<GroupCount> = Vec::with_capacity(groups.len());
+        for (group, indices) in groups {
+            let kept = indices.len().min(cap);
+            for i in 0..kept {
+                keep[indices[i * indices.len() / kept]] = true;
+            }
+            counts.push(GroupCount {
+                group,
+                records: indices.len(),
+                kept,
+            });
+        }
+        counts.sort_by(|a, b| b.records.cmp(&a.records).then(a.group.cmp(&b.group)));
+
+        let items = items
+            .into_iter()
+            .zip(keep)
+            .filter_map(|(item, keep)| keep.then_some(item))
+            .collect();
+        Balanced {
+            items,
+            cap,
+            groups: counts,
+        }
+    }
+}
+
+/// Where a balanced copy of a dataset is written by the `balance` command:
+/// `NAME.balanced.jsonl`
+pub fn balanced_path(path: &Path) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("dataset");
+    path.with_file_name(format!("{}.balanced.jsonl", stem))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lineage::Lineage;
+
+    fn items(counts: &[(&str, usize)]) -> Vec<ProcessedItem> {
+        counts
+            .iter()
+            .flat_map(|&(document, count)| {
+                (0..count).map(move |i| ProcessedItem {
+                    question: format!("{} Q{}", document, i),
+                    answer: "A".to_string(),
+                    lineage: Some(Lineage {
+                        document: document.to_string(),
+                        span: 0..1,
+                        nodes: Vec::new(),
+                    }),
+                    ..Default::default()
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_caps_the_dominant_document() {
+        let balance = Balance {
+            max_share: 40.0,
+            by: BalanceBy::Document,
+        };
+        let balanced = balance.apply(items(&[
+            ("CHANGELOG.md", 90),
+            ("docs/a.md", 5),
+            ("docs/b.md", 5),
+        ]));
+        // 6 changelog records are at most 40% of the 16 left
+        assert_eq!(balanced.cap, 6);
+        assert_eq!(balanced.items.len(), 16);
+        assert_eq!(balanced.dropped(), 84);
+        assert_eq!(balanced.groups[0].kept, 6);
+        // Spread over the whole changelog, in dataset order
+        assert_eq!(balanced.items[0].question, "CHANGELOG.md Q0");
+        assert_eq!(balanced.items[5].question, "CHANGELOG.md Q75");
+        assert_eq!(balanced.items[6].question, "docs/a.md Q0");
+    }
+
+    #[test]
+    fn test_balances_by_directory_and_few_groups() {
+        let balance = Balance {
+            max_share: 10.0,
+            by: BalanceBy::Directory,
+        };
+        // Two directories can't each stay under 10%; both get half
+        let balanced = balance.apply(items(&[
+            ("api/a.md", 6),
+            ("api/b.md", 6),
+            ("guide/c.md", 4),
+        ]));
+        assert_eq!(balanced.cap, 4);
+        assert_eq!(balanced.items.len(), 8);
+        let groups: Vec<&str> = balanced.groups.iter().map(|g| &*g.group).collect();
+        assert_eq!(groups, ["api", "guide"]);
+    }
+}
@@ -0,0 +1,314 @@
+//! Throughput benchmarks for the configured backends, run with a fixed prompt
+//! set so results from different machines and settings can be compared.
+
+use futures::{stream, StreamExt};
+use std::fmt;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use crate::external::{Embedder, ModelProvider};
+
+/// Prompts sent to the generation model, cycled through in order
+pub const BENCHMARK_PROMPTS: &[&str] = &[
+    "Write one question a reader could answer from this text: TLS encrypts traffic between a client and a server.",
+    "Summarize in two sentences why databases use indexes.",
+    "Generate three questions about the following section.\n\n## Retries\nFailed requests are retried with exponential backoff, up to a configurable number of attempts. Connection errors and timeouts are retried; invalid requests are not.",
+    "Answer briefly: what is the difference between a process and a thread?",
+    "Explain what a vector database stores and how similarity search uses it.",
+    "Write a question and its answer about this code:\n\n```rust\nfn add(a: i32, b: i32) -> i32 { a + b }\n```",
+];
+
+/// Texts sent to the embedding model, cycled through in order
+pub const BENCHMARK_TEXTS: &[&str] = &[
+    "TLS encrypts traffic between a client and a server.",
+    "Databases use indexes to find rows without scanning the whole table.",
+    "Failed requests are retried with exponential backoff, up to a configurable number of attempts. Connection errors and timeouts are retried; invalid requests are not.",
+    "A vector database stores embeddings and finds the ones closest to a query vector.",
+];
+
+/// Latency percentiles of the successful requests
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LatencySummary {
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl LatencySummary {
+    pub fn from_latencies(latencies: &[Duration]) -> Self {
+        let mut sorted = latencies.to_vec();
+        sorted.sort();
+        Self {
+            p50: percentile(&sorted, 50.0),
+            p90: percentile(&sorted, 90.0),
+            p99: percentile(&sorted, 99.0),
+            max: sorted.last().copied().unwrap_or_default(),
+        }
+    }
+}
+
+/// Nearest-rank percentile of sorted values
+fn percentile(sorted: &[Duration], percent: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = (percent / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Outcome of one backend at one concurrency level
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchmarkResult {
+    /// Backend and model, e.g. "generation (mistral)"
+    pub backend: String,
+    pub concurrency: usize,
+    pub requests: usize,
+    pub failures: usize,
+    /// Wall-clock time for all requests
+    pub elapsed: Duration,
+    pub latency: LatencySummary,
+    /// Tokens generated, when the backend reports them
+    pub tokens: Option<u64>,
+    /// First error, to tell why requests failed
+    pub first_error: Option<String>,
+}
+
+impl BenchmarkResult {
+    pub fn requests_per_sec(&self) -> f64 {
+        per_sec((self.requests - self.failures) as f64, self.elapsed)
+    }
+
+    pub fn tokens_per_sec(&self) -> Option<f64> {
+        self.tokens
+            .map(|tokens| per_sec(tokens as f64, self.elapsed))
+    }
+
+    pub fn failure_rate(&self) -> f64 {
+        if self.requests == 0 {
+            return 0.0;
+        }
+        self.failures as f64 / self.requests as f64
+    }
+}
+
+fn per_sec(count: f64, elapsed: Duration) -> f64 {
+    if elapsed.is_zero() {
+        return 0.0;
+    }
+    count / elapsed.as_secs_f64()
+}
+
+impl fmt::Display for BenchmarkResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        write!(
+            f,
+            "{:<28} {:>4} {:>8.2} {:>9} {:>8.0} {:>8.0} {:>8.0} {:>8.0} {:>7.1}%",
+            self.backend,
+            self.concurrency,
+            self.requests_per_sec(),
+            self.tokens_per_sec()
+                .map(|rate| format!("{:.1}", rate))
+                .unwrap_or_else(|| "-".to_string()),
+            ms(self.latency.p50),
+            ms(self.latency.p90),
+            ms(self.latency.p99),
+            ms(self.latency.max),
+            self.failure_rate() * 100.0
+        )
+    }
+}
+
+/// Column headings matching [`BenchmarkResult`]'s display
+pub const RESULT_HEADER: &str = "backend                      conc    req/s  tokens/s  p50(ms)  p90(ms)  p99(ms)  max(ms)  failed";
+
+/// Run `requests` calls with at most `concurrency` in flight, returning the
+/// latency or error of each and the total elapsed time
+async fn measure<F, Fut, T>(
+    requests: usize,
+    concurrency: usize,
+    call: F,
+) -> (Vec<anyhow::Result<Duration>>, Duration)
+where
+    F: Fn(usize) -> Fut,
+    Fut: Future<Output = anyhow::Result<T>>,
+{
+    let started = Instant::now();
+    let outcomes = stream::iter(0..requests)
+        .map(|index| {
+            let request = call(index);
+            async move {
+                let sent = Instant::now();
+                request.await.map(|_| sent.elapsed())
+            }
+        })
+        .buffer_unordered(concurrency.max(1))
+        .collect()
+        .await;
+    (outcomes, started.elapsed())
+}
+
+fn summarize(
+    backend: String,
+    concurrency: usize,
+    outcomes: Vec<anyhow::Result<Duration>>,
+    elapsed: Duration,
+    tokens: Option<u64>,
+) -> BenchmarkResult {
+    let requests = outcomes.len();
+    let mut latencies = Vec::new();
+    let mut first_error = None;
+    for outcome in outcomes {
+        match outcome {
+            Ok(latency) => latencies.push(latency),
+            Err(e) => {
+                first_error.get_or_insert_with(|| format!("{:#}", e));
+            }
+        }
+    }
+    BenchmarkResult {
+        backend,
+        concurrency,
+        requests,
+        failures: requests - latencies.len(),
+        elapsed,
+        latency: LatencySummary::from_latencies(&latencies),
+        tokens,
+        first_error,
+    }
+}
+
+/// Send the benchmark prompts to the generation model
+pub async fn benchmark_generation(
+    provider: &dyn ModelProvider,
+    requests: usize,
+    concurrency: usize,
+) -> BenchmarkResult {
+    let before = provider.usage().completion_tokens;
+    let (outcomes, elapsed) = measure(requests, concurrency, |index| {
+        provider.generate(BENCHMARK_PROMPTS[index % BENCHMARK_PROMPTS.len()])
+    })
+    .await;
+    let tokens = provider.usage().completion_tokens - before;
+    summarize(
+        format!("generation ({})", provider.model()),
+        concurrency,
+        outcomes,
+        elapsed,
+        Some(tokens),
+    )
+}
+
+/// Send the benchmark texts to the embedding model
+pub async fn benchmark_embeddings(
+    embedder: &dyn Embedder,
+    model: &str,
+    requests: usize,
+    concurrency: usize,
+) -> BenchmarkResult {
+    let (outcomes, elapsed) = measure(requests, concurrency, |index| {
+        embedder.embed(BENCHMARK_TEXTS[index % BENCHMARK_TEXTS.len()])
+    })
+    .await;
+    summarize(
+        format!("embedding ({})", model),
+        concurrency,
+        outcomes,
+        elapsed,
+        None,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::external::{ChatRequest, ProviderUsage, TokenStream};
+    use anyhow::{anyhow, Result};
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    /// Replies with five tokens, failing every fourth request
+    #[derive(Default)]
+    struct StubProvider {
+        requests: AtomicU64,
+        tokens: AtomicU64,
+    }
+
+    #[async_trait]
+    impl ModelProvider for StubProvider {
+        fn model(&self) -> &str {
+            "stub"
+        }
+
+        fn endpoint(&self) -> &str {
+            "http://stub"
+        }
+
+        async fn generate(&self, _prompt: &str) -> Result<String> {
+            if self.requests.fetch_add(1, Ordering::SeqCst) % 4 == 3 {
+                return Err(anyhow!("overloaded"));
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            self.tokens.fetch_add(5, Ordering::SeqCst);
+            Ok("a b c d e".to_string())
+        }
+
+        async fn generate_stream(&self, _prompt: &str) -> Result<TokenStream> {
+            unimplemented!()
+        }
+
+        async fn chat(&self, _request: ChatRequest) -> Result<String> {
+            unimplemented!()
+        }
+
+        async fn chat_stream(&self, _request: ChatRequest) -> Result<TokenStream> {
+            unimplemented!()
+        }
+
+        async fn list_models(&self) -> Result<Vec<String>> {
+            Ok(vec!["stub".to_string()])
+        }
+
+        async fn pull_model(&self) -> Result<()> {
+            Ok(())
+        }
+
+        fn usage(&self) -> ProviderUsage {
+            ProviderUsage {
+                completion_tokens: self.tokens.load(Ordering::SeqCst),
+                ..Default::default()
+            }
+        }
+    }
+
+    #[test]
+    fn test_latency_percentiles() {
+        let latencies: Vec<Duration> = (1..=100).rev().map(Duration::from_millis).collect();
+        let summary = LatencySummary::from_latencies(&latencies);
+        assert_eq!(summary.p50, Duration::from_millis(50));
+        assert_eq!(summary.p90, Duration::from_millis(90));
+        assert_eq!(summary.p99, Duration::from_millis(99));
+        assert_eq!(summary.max, Duration::from_millis(100));
+
+        assert_eq!(
+            LatencySummary::from_latencies(&[]),
+            LatencySummary::default()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_benchmark_generation_counts_tokens_and_failures() {
+        let provider = StubProvider::default();
+        let result = benchmark_generation(&provider, 8, 2).await;
+
+        assert_eq!(result.backend, "generation (stub)");
+        assert_eq!(result.requests, 8);
+        assert_eq!(result.failures, 2);
+        assert_eq!(result.failure_rate(), 0.25);
+        assert_eq!(result.tokens, Some(30));
+        assert!(result.tokens_per_sec().unwrap() > 0.0);
+        assert!(result.latency.p50 >= Duration::from_millis(5));
+        assert_eq!(result.first_error.as_deref(), Some("overloaded"));
+    }
+}
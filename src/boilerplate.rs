@@ -0,0 +1,486 @@
+//! Stripping of boilerplate from collected documents before questions are
+//! generated from them, so no records are spent on badges and footers.
+//!
+//! Documents are split into blocks at blank lines. A block is boilerplate if
+//! it repeats, ignoring whitespace and case, across a large share of the
+//! corpus (navigation footers, "see also" boxes, shared notices), or if it is
+//! one of the usual suspects on its own: a wall of status badges, an "Edit
+//! this page" link, or a license header. Headings and code blocks are always
+//! kept.
+
+use anyhow::Result;
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::hash::hash_text;
+
+/// Share of the documents a block must appear in to be boilerplate
+pub const DEFAULT_MIN_SHARE: f64 = 0.3;
+
+/// Documents a block must appear in to be boilerplate, however small the
+/// corpus; below this only the patterns apply
+pub const MIN_DOCUMENTS: usize = 3;
+
+/// Blocks shorter than this are never counted as repeated, so short lines
+/// like "Example:" survive
+const MIN_REPEATED_CHARS: usize = 20;
+
+/// Longest "Edit this page" style block
+const MAX_EDIT_LINK_CHARS: usize = 120;
+
+/// Why a block was stripped
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Reason {
+    /// Repeated across the corpus
+    Repeated,
+    Badges,
+    EditLink,
+    LicenseHeader,
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Reason::Repeated => "repeated blocks",
+            Reason::Badges => "badge walls",
+            Reason::EditLink => "edit links",
+            Reason::LicenseHeader => "license headers",
+        })
+    }
+}
+
+/// A run of lines between blank lines
+struct Block<'a> {
+    lines: Vec<&'a str>,
+    /// Headings and fenced code, which are never stripped
+    keep: bool,
+}
+
+impl Block<'_> {
+    fn text(&self) -> String {
+        self.lines.join("\n")
+    }
+
+    /// Text with whitespace collapsed and case folded
+    fn normalized(&self) -> String {
+        self.lines
+            .iter()
+            .flat_map(|line| line.split_whitespace())
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase()
+    }
+}
+
+/// Split a document into blocks; a heading is a block of its own and a
+/// fenced code block stays in one block, blank lines and all
+fn blocks(text: &str) -> Vec<Block<'_>> {
+    let mut blocks = Vec::new();
+    let mut current = Block {
+        lines: Vec::new(),
+        keep: false,
+    };
+    let mut fence: Option<&str> = None;
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        if let Some(marker) = fence {
+            current.lines.push(line);
+            if trimmed.starts_with(marker) {
+                fence = None;
+            }
+            continue;
+        }
+        let opens_fence = ["```", "~~~"]
+            .into_iter()
+            .find(|marker| trimmed.starts_with(marker));
+        let heading = trimmed.starts_with('#');
+        if line.trim().is_empty() || heading {
+            if !current.lines.is_empty() {
+                blocks.push(std::mem::replace(
+                    &mut current,
+                    Block {
+                        lines: Vec::new(),
+                        keep: false,
+                    },
+                ));
+            }
+            if heading {
+                blocks.push(Block {
+                    lines: vec![line],
+                    keep: true,
+                });
+            }
+            continue;
+        }
+        if let Some(marker) = opens_fence {
+            fence = Some(marker);
+            current.keep = true;
+        }
+        current.lines.push(line);
+    }
+    if !current.lines.is_empty() {
+        blocks.push(current);
+    }
+    blocks
+}
+
+fn badge_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| {
+        Regex::new(
+            r#"\[!\[[^\]]*\]\(([^)\s]*)[^)]*\)\]\([^)]*\)|!\[[^\]]*\]\(([^)\s]*)[^)]*\)|<a\s[^>]*>\s*<img\s[^>]*src="([^"]*)"[^>]*>\s*</a>|<img\s[^>]*src="([^"]*)"[^>]*>"#,
+        )
+        .expect("valid regex")
+    })
+}
+
+fn edit_link_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| {
+        Regex::new(
+            r"(?i)^\W*(edit this page|edit (this page )?on github|improve this (page|doc)|suggest an? (edit|change)|view (page )?source)\b",
+        )
+        .expect("valid regex")
+    })
+}
+
+/// Whether an image URL is a status badge rather than a figure
+fn is_badge_url(url: &str) -> bool {
+    let url = url.to_lowercase();
+    [
+        "shields.io",
+        "badge",
+        "badgen.net",
+        "travis-ci",
+        "codecov.io",
+        "circleci.com",
+        "/actions/workflows/",
+    ]
+    .iter()
+    .any(|host| url.contains(host))
+}
+
+/// A block made only of images, at least one of them a badge
+fn is_badge_wall(text: &str) -> bool {
+    let pattern = badge_pattern();
+    let rest = pattern.replace_all(text, "");
+    let rest = rest.replace(['<', '>'], " ");
+    let only_images = rest
+        .split_whitespace()
+        .all(|word| matches!(word, "p" | "/p" | "div" | "/div" | "br" | "br/" | "|"));
+    only_images
+        && pattern.captures_iter(text).any(|captures| {
+            captures
+                .iter()
+                .skip(1)
+                .flatten()
+                .any(|url| is_badge_url(url.as_str()))
+        })
+}
+
+fn is_license_header(text: &str, first: bool) -> bool {
+    let comment = text.trim_start().starts_with("<!--") || text.trim_start().starts_with("/*");
+    if !first && !comment {
+        return false;
+    }
+    let lower = text.to_lowercase();
+    lower.contains("spdx-license-identifier")
+        || (lower.contains("copyright") && (lower.contains("license") || lower.contains("licence")))
+}
+
+/// A document with its boilerplate removed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stripped {
+    pub text: String,
+    /// Each block removed, with why
+    pub removed: Vec<(Reason, String)>,
+}
+
+/// The blocks that repeat across a corpus
+#[derive(Debug, Clone, Default)]
+pub struct Boilerplate {
+    /// Hashes of the normalized repeated blocks, with the documents each
+    /// appears in
+    repeated: HashMap<String, usize>,
+}
+
+impl Boilerplate {
+    /// Learn which blocks repeat across at least `min_share` of `documents`
+    pub fn learn<'a>(documents: impl IntoIterator<Item = &'a str>, min_share: f64) -> Self {
+        let mut counts = HashMap::new();
+        let mut total = 0;
+        for document in documents {
+            total += 1;
+            for key in document_keys(document) {
+                *counts.entry(key).or_insert(0usize) += 1;
+            }
+        }
+        Self::from_counts(counts, total, min_share)
+    }
+
+    fn from_counts(counts: HashMap<String, usize>, documents: usize, min_share: f64) -> Self {
+        let threshold = MIN_DOCUMENTS.max((documents as f64 * min_share).ceil() as usize);
+        Self {
+            repeated: counts
+                .into_iter()
+                .filter(|&(_, count)| count >= threshold)
+                .collect(),
+        }
+    }
+
+    /// Number of distinct repeated blocks
+    pub fn repeated_blocks(&self) -> usize {
+        self.repeated.len()
+    }
+
+    fn reason(&self, block: &Block, first: bool) -> Option<Reason> {
+        if block.keep {
+            return None;
+        }
+        let text = block.text();
+        let normalized = block.normalized();
+        if normalized.len() >= MIN_REPEATED_CHARS
+            && self.repeated.contains_key(&hash_text(&normalized))
+        {
+            Some(Reason::Repeated)
+        } else if is_badge_wall(&text) {
+            Some(Reason::Badges)
+        } else if normalized.len() <= MAX_EDIT_LINK_CHARS && edit_link_pattern().is_match(&text) {
+            Some(Reason::EditLink)
+        } else if is_license_header(&text, first) {
+            Some(Reason::LicenseHeader)
+        } else {
+            None
+        }
+    }
+
+    /// Remove the boilerplate blocks of a document
+    ///
+    /// Kept blocks are separated by one blank line; a document without
+    /// boilerplate comes back unchanged.
+    pub fn strip(&self, text: &str) -> Stripped {
+        let mut kept = Vec::new();
+        let mut removed = Vec::new();
+        for (index, block) in blocks(text).iter().enumerate() {
+            match self.reason(block, index == 0) {
+                Some(reason) => removed.push((reason, block.text())),
+                None => kept.push(block.text()),
+            }
+        }
+        if removed.is_empty() {
+            return Stripped {
+                text: text.to_string(),
+                removed,
+            };
+        }
+        let mut text = kept.join("\n\n");
+        text.push('\n');
+        Stripped { text, removed }
+    }
+}
+
+/// Hashes of the distinct normalized blocks of a document that could count
+/// as repeated
+fn document_keys(document: &str) -> HashSet<String> {
+    blocks(document)
+        .iter()
+        .filter(|block| !block.keep)
+        .map(Block::normalized)
+        .filter(|normalized| normalized.len() >= MIN_REPEATED_CHARS)
+        .map(|normalized| hash_text(&normalized))
+        .collect()
+}
+
+/// What cleaning a set of files removed
+#[derive(Debug, Clone, Default)]
+pub struct CleanReport {
+    pub files: usize,
+    pub files_changed: usize,
+    pub bytes_removed: usize,
+    /// Blocks removed for each reason
+    pub removed: HashMap<Reason, usize>,
+    /// The repeated blocks removed most often, with how many times
+    pub top_repeated: Vec<(String, usize)>,
+}
+
+impl fmt::Display for CleanReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Boilerplate in {} of {} files ({} bytes)",
+            self.files_changed, self.files, self.bytes_removed
+        )?;
+        let mut reasons: Vec<_> = self.removed.iter().collect();
+        reasons.sort();
+        for (reason, count) in reasons {
+            writeln!(f, "  {}: {}", reason, count)?;
+        }
+        for (block, count) in &self.top_repeated {
+            let first_line = block.lines().next().unwrap_or_default();
+            let shown: String = first_line.chars().take(70).collect();
+            let ellipsis = if shown.len() < block.len() { "..." } else { "" };
+            writeln!(f, "  {:>5}x {}{}", count, shown, ellipsis)?;
+        }
+        Ok(())
+    }
+}
+
+/// Repeated blocks listed in a [`CleanReport`]
+const TOP_REPEATED: usize = 10;
+
+/// Strip boilerplate from `files` in place, learning the repeated blocks
+/// from all of them; with `dry_run` only report what would be removed
+///
+/// Files are read twice rather than held in memory together.
+pub fn clean_files(files: &[PathBuf], min_share: f64, dry_run: bool) -> Result<CleanReport> {
+    let mut counts = HashMap::new();
+    for file in files {
+        for key in document_keys(&read(file)?) {
+            *counts.entry(key).or_insert(0usize) += 1;
+        }
+    }
+    let boilerplate = Boilerplate::from_counts(counts, files.len(), min_share);
+
+    let mut report = CleanReport {
+        files: files.len(),
+        ..Default::default()
+    };
+    let mut repeated: HashMap<String, usize> = HashMap::new();
+    for file in files {
+        let content = read(file)?;
+        let stripped = boilerplate.strip(&content);
+        if stripped.removed.is_empty() {
+            continue;
+        }
+        report.files_changed += 1;
+        report.bytes_removed += content.len().saturating_sub(stripped.text.len());
+        for (reason, block) in &stripped.removed {
+            *report.removed.entry(*reason).or_default() += 1;
+            if *reason == Reason::Repeated {
+                *repeated.entry(block.trim().to_string()).or_default() += 1;
+            }
+        }
+        if !dry_run {
+            fs::write(file, &stripped.text)?;
+        }
+    }
+    let mut top: Vec<_> = repeated.into_iter().collect();
+    top.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    top.truncate(TOP_REPEATED);
+    report.top_repeated = top;
+    Ok(report)
+}
+
+fn read(path: &Path) -> Result<String> {
+    Ok(String::from_utf8_lossy(&fs::read(path)?).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FOOTER: &str = "Was this page helpful? Join the discussion on our forum.";
+
+    fn page(body: &str) -> String {
+        format!("# Page\n\n{}\n\n{}\n", body, FOOTER)
+    }
+
+    #[test]
+    fn test_strips_blocks_repeated_across_the_corpus() {
+        let pages: Vec<String> = (0..4)
+            .map(|i| page(&format!("Body of page {} with its own text.", i)))
+            .collect();
+        let boilerplate = Boilerplate::learn(pages.iter().map(String::as_str), DEFAULT_MIN_SHARE);
+        assert_eq!(boilerplate.repeated_blocks(), 1);
+
+        let stripped = boilerplate.strip(&pages[0]);
+        assert_eq!(
+            stripped.text,
+            "# Page\n\nBody of page 0 with its own text.\n"
+        );
+        assert_eq!(stripped.removed, [(Reason::Repeated, FOOTER.to_string())]);
+        // Reflowed and recased copies count too
+        let reflowed = "Was this page   helpful?\njoin the discussion on our forum.";
+        assert_eq!(boilerplate.strip(reflowed).removed.len(), 1);
+
+        // Too few documents for anything to count as repeated
+        let few = Boilerplate::learn(pages[..2].iter().map(String::as_str), DEFAULT_MIN_SHARE);
+        assert_eq!(few.repeated_blocks(), 0);
+    }
+
+    #[test]
+    fn test_keeps_headings_and_code() {
+        let pages: Vec<String> = (0..3)
+            .map(|i| {
+                format!(
+                    "## Installation instructions\n\n```sh\ncargo install the-tool-{}\n```\n\n```sh\ncargo install shared-tool\n```\n",
+                    i
+                )
+            })
+            .collect();
+        let boilerplate = Boilerplate::learn(pages.iter().map(String::as_str), DEFAULT_MIN_SHARE);
+        assert_eq!(boilerplate.strip(&pages[0]).text, pages[0]);
+    }
+
+    #[test]
+    fn test_strips_boilerplate_patterns() {
+        let boilerplate = Boilerplate::default();
+        let text = "<!-- Copyright 2024 Example Inc. Licensed under the Apache License 2.0 -->\n\
+                    \n\
+                    # Tool\n\
+                    [![CI](https://github.com/x/y/actions/workflows/ci.yml/badge.svg)](https://github.com/x/y/actions) \
+                    [![crates.io](https://img.shields.io/crates/v/y.svg)](https://crates.io/crates/y)\n\
+                    \n\
+                    ![Architecture](docs/architecture.png)\n\
+                    \n\
+                    The tool is licensed under MIT; copyright notices are kept.\n\
+                    \n\
+                    [Edit this page on GitHub](https://github.com/x/y/edit/main/README.md)\n";
+        let stripped = boilerplate.strip(text);
+        let reasons: Vec<_> = stripped.removed.iter().map(|(reason, _)| *reason).collect();
+        assert_eq!(
+            reasons,
+            [Reason::LicenseHeader, Reason::Badges, Reason::EditLink]
+        );
+        assert_eq!(
+            stripped.text,
+            "# Tool\n\n![Architecture](docs/architecture.png)\n\n\
+             The tool is licensed under MIT; copyright notices are kept.\n"
+        );
+    }
+
+    #[test]
+    fn test_clean_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let files: Vec<PathBuf> = (0..3)
+            .map(|i| {
+                let path = dir.path().join(format!("page{}.md", i));
+                fs::write(&path, page(&format!("Page {} body text.", i))).unwrap();
+                path
+            })
+            .collect();
+
+        let report = clean_files(&files, DEFAULT_MIN_SHARE, true).unwrap();
+        assert_eq!((report.files, report.files_changed), (3, 3));
+        assert_eq!(report.removed[&Reason::Repeated], 3);
+        assert_eq!(report.top_repeated, [(FOOTER.to_string(), 3)]);
+        assert!(fs::read_to_string(&files[0]).unwrap().contains(FOOTER));
+
+        clean_files(&files, DEFAULT_MIN_SHARE, false).unwrap();
+        assert_eq!(
+            fs::read_to_string(&files[0]).unwrap(),
+            "# Page\n\nPage 0 body text.\n"
+        );
+        // Cleaning again finds nothing more
+        assert_eq!(
+            clean_files(&files, DEFAULT_MIN_SHARE, false)
+                .unwrap()
+                .files_changed,
+            0
+        );
+    }
+}
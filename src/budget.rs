@@ -0,0 +1,222 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::graph::{node::NodeType, DocumentGraph, DocumentNode};
+use crate::parser::parse_markdown;
+use crate::processor::DefaultOllamaProcessor;
+
+/// Question budget driven by document structure
+///
+/// Each content node earns `words / words_per_question` questions, scaled by
+/// a weight for its node type and a weight for the level of the section it
+/// sits in. This replaces the flat words/10 rule of
+/// `calculate_question_targets` when enabled: code blocks can be asked about
+/// more, while an H1 introduction gets fewer questions.
+#[derive(Debug, Clone)]
+pub struct QuestionBudget {
+    pub words_per_question: f64,
+    /// Multiplier per node type; types not listed get no questions
+    pub type_weights: HashMap<NodeType, f64>,
+    /// Multiplier per enclosing section level (1 = H1); unlisted levels use 1.0
+    pub level_weights: HashMap<i32, f64>,
+}
+
+impl Default for QuestionBudget {
+    fn default() -> Self {
+        let type_weights = [
+            (NodeType::Text, 1.0),
+            (NodeType::Paragraph, 1.0),
+            (NodeType::ListItem, 1.0),
+            (NodeType::Code, 1.5),
+            (NodeType::CodeBlock, 1.5),
+            (NodeType::TableRow, 1.2),
+            (NodeType::Quote, 0.8),
+            (NodeType::Footnote, 0.5),
+        ]
+        .into_iter()
+        .collect();
+
+        Self {
+            words_per_question: 10.0,
+            type_weights,
+            level_weights: [(1, 0.5)].into_iter().collect(),
+        }
+    }
+}
+
+impl QuestionBudget {
+    /// Fractional number of questions a single node is worth
+    fn node_weight(&self, graph: &DocumentGraph, node: &DocumentNode) -> Result<f64> {
+        let Some(type_weight) = self.type_weights.get(&node.node_type) else {
+            return Ok(0.0);
+        };
+
+        let level = graph
+            .ancestors(&node.id)?
+            .into_iter()
+            .find(|ancestor| ancestor.node_type == NodeType::Section)
+            .and_then(|section| section.metadata.level);
+        let level_weight = level
+            .and_then(|level| self.level_weights.get(&level))
+            .copied()
+            .unwrap_or(1.0);
+
+        let words = DefaultOllamaProcessor::count_words(&node.content) as f64;
+        Ok(words / self.words_per_question * type_weight * level_weight)
+    }
+
+    /// Questions allotted to each content node of a graph
+    ///
+    /// Nodes worth less than one question are left out.
+    pub fn allocate(&self, graph: &DocumentGraph) -> Result<HashMap<Uuid, usize>> {
+        let mut budget = HashMap::new();
+        for node in graph.nodes() {
+            let questions = self.node_weight(graph, node)?.round() as usize;
+            if questions > 0 {
+                budget.insert(node.id, questions);
+            }
+        }
+        Ok(budget)
+    }
+
+    /// Total questions for everything under a node (inclusive)
+    pub fn for_subtree(&self, graph: &DocumentGraph, id: &Uuid) -> Result<usize> {
+        let mut total = 0.0;
+        for node in graph.descendants(id)? {
+            total += self.node_weight(graph, node)?;
+        }
+        Ok(total.ceil() as usize)
+    }
+
+    /// Questions for a piece of markdown, at least `minimum`
+    pub fn for_markdown(&self, markdown: &str, minimum: usize) -> Result<usize> {
+        let graph = parse_markdown(markdown)?;
+        let mut total = 0.0;
+        for node in graph.nodes() {
+            total += self.node_weight(&graph, node)?;
+        }
+        Ok((total.ceil() as usize).max(minimum))
+    }
+}
+
+/// Default cap on the tokens of one reply (`--max-output-tokens`)
+pub const DEFAULT_MAX_OUTPUT_TOKENS: usize = 4096;
+
+/// Output tokens a generated question and answer take, about
+const TOKENS_PER_QUESTION: usize = 160;
+
+/// Output tokens of a question with its rubric, about
+const TOKENS_PER_EVAL_QUESTION: usize = 256;
+
+/// Output tokens of the reply around its questions: braces, the key, a fence
+const REPLY_OVERHEAD_TOKENS: usize = 32;
+
+/// Output token budget of question requests
+///
+/// Each request gets an output limit (Ollama's `num_predict`) sized to the
+/// questions it asks for, and no request asks for more questions than fit
+/// under `max_output_tokens`. A reply cut off at a limit is invalid JSON, so
+/// larger targets are asked for in several smaller batches instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TokenBudget {
+    pub max_output_tokens: usize,
+}
+
+impl Default for TokenBudget {
+    fn default() -> Self {
+        Self {
+            max_output_tokens: DEFAULT_MAX_OUTPUT_TOKENS,
+        }
+    }
+}
+
+impl TokenBudget {
+    fn per_question(rubric: bool) -> usize {
+        if rubric {
+            TOKENS_PER_EVAL_QUESTION
+        } else {
+            TOKENS_PER_QUESTION
+        }
+    }
+
+    /// Most questions one request may ask for, at least one
+    pub fn questions_per_request(&self, rubric: bool) -> usize {
+        (self.max_output_tokens.saturating_sub(REPLY_OVERHEAD_TOKENS) / Self::per_question(rubric))
+            .max(1)
+    }
+
+    /// Output limit of a request for `count` questions
+    pub fn max_tokens(&self, count: usize, rubric: bool) -> usize {
+        (REPLY_OVERHEAD_TOKENS + count * Self::per_question(rubric)).min(self.max_output_tokens)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(count: usize) -> String {
+        vec!["word"; count].join(" ")
+    }
+
+    #[test]
+    fn test_budget_by_type_and_level() {
+        let markdown = format!(
+            "# Intro\n\n{}\n\n## Usage\n\n{}\n\n```\n{}\n```\n",
+            words(20),
+            words(20),
+            words(20)
+        );
+        let graph = parse_markdown(&markdown).unwrap();
+        let budget = QuestionBudget::default();
+        let allocation = budget.allocate(&graph).unwrap();
+
+        let questions_for = |node_type: NodeType, level: i32| {
+            graph
+                .get_nodes_by_type(node_type)
+                .into_iter()
+                .find(|node| graph.ancestors(&node.id).unwrap()[0].metadata.level == Some(level))
+                .map(|node| allocation[&node.id])
+                .unwrap()
+        };
+
+        // H1 introduction text counts half, code counts one and a half times
+        assert_eq!(questions_for(NodeType::Text, 1), 1);
+        assert_eq!(questions_for(NodeType::Text, 2), 2);
+        assert_eq!(questions_for(NodeType::Code, 2), 3);
+
+        // Headings themselves get no questions
+        let intro = graph.find_by_heading("Intro")[0];
+        assert!(!allocation.contains_key(&intro.id));
+        assert_eq!(budget.for_subtree(&graph, &intro.id).unwrap(), 6);
+    }
+
+    #[test]
+    fn test_for_markdown_minimum() {
+        let budget = QuestionBudget::default();
+        assert_eq!(budget.for_markdown("## Short\n\nTiny.\n", 2).unwrap(), 2);
+        assert_eq!(
+            budget
+                .for_markdown(&format!("## Long\n\n{}\n", words(50)), 2)
+                .unwrap(),
+            5
+        );
+    }
+
+    #[test]
+    fn test_token_budget() {
+        let budget = TokenBudget::default();
+        assert_eq!(budget.questions_per_request(false), 25);
+        assert_eq!(budget.questions_per_request(true), 15);
+        assert_eq!(budget.max_tokens(3, false), 512);
+        assert_eq!(budget.max_tokens(100, false), 4096);
+
+        // Even a tiny limit asks for one question at a time
+        let tiny = TokenBudget {
+            max_output_tokens: 100,
+        };
+        assert_eq!(tiny.questions_per_request(true), 1);
+        assert_eq!(tiny.max_tokens(1, true), 100);
+    }
+}
@@ -0,0 +1,259 @@
+//! Library entry point to the whole generation pipeline, for services that
+//! embed it instead of running the binary:
+//!
+//! ```no_run
+//! # async fn build() -> anyhow::Result<()> {
+//! use llm_dataset_builder::builder::DatasetBuilder;
+//! use llm_dataset_builder::datasource::LocalSource;
+//! use llm_dataset_builder::Config;
+//!
+//! let built = DatasetBuilder::new(Config::from_env()?)
+//!     .add_source(LocalSource::new("docs"))
+//!     .with_filter(|item| item.answer.len() > 20)
+//!     .run()
+//!     .await?;
+//! println!("{} records in {:?}", built.records, built.dataset);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Sources are collected into the output directory and generated from as
+//! they arrive, exactly as by `generate`, and the run ends with the same
+//! `all_qa.jsonl` and run manifest.
+
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+use crate::balance::Balance;
+use crate::config::Config;
+use crate::datasource::DataSource;
+use crate::eval::EVAL_FILE;
+use crate::exit::RunReport;
+use crate::external::ModelProvider;
+use crate::manifest::RunManifest;
+use crate::observer::PipelineObserver;
+use crate::pipeline::{self, Pipeline};
+use crate::processor::{
+    prompt_templates, DefaultOllamaClient, DefaultOllamaProcessor, OllamaClient, ProcessedItem,
+    ProcessorError,
+};
+use crate::schema::{self, DatasetWriter};
+
+/// Combined dataset written to the output directory
+pub const DATASET_FILE: &str = "all_qa.jsonl";
+
+/// Decides whether a generated record goes into the dataset
+pub type RecordFilter = dyn Fn(&ProcessedItem) -> bool + Send + Sync;
+
+type Configure = Box<dyn FnOnce(DefaultOllamaProcessor) -> DefaultOllamaProcessor>;
+
+/// What a [`DatasetBuilder`] run produced
+#[derive(Debug)]
+pub struct BuiltDataset {
+    /// The combined dataset, `all_qa.jsonl`
+    pub dataset: PathBuf,
+    pub records: usize,
+    /// `eval.jsonl` and its record count, when sections were held out
+    pub eval: Option<(PathBuf, usize)>,
+    pub manifest: PathBuf,
+    /// Files processed and failed
+    pub report: RunReport,
+}
+
+/// Builds a dataset from sources: collection, generation, filtering and
+/// writing in one call
+pub struct DatasetBuilder {
+    config: Config,
+    output_dir: PathBuf,
+    sources: Vec<Box<dyn DataSource>>,
+    filters: Vec<Box<RecordFilter>>,
+    client: Option<Box<dyn OllamaClient>>,
+    configure: Option<Configure>,
+    observers: Vec<Arc<dyn PipelineObserver>>,
+    balance: Option<Balance>,
+}
+
+impl DatasetBuilder {
+    /// Generate with the model of `config.llm` into `config.output.output_dir`
+    pub fn new(config: Config) -> Self {
+        let output_dir = PathBuf::from(&config.output.output_dir);
+        Self {
+            config,
+            output_dir,
+            sources: Vec::new(),
+            filters: Vec::new(),
+            client: None,
+            configure: None,
+            observers: Vec::new(),
+            balance: None,
+        }
+    }
+
+    pub fn add_source(mut self, source: impl DataSource + 'static) -> Self {
+        self.sources.push(Box::new(source));
+        self
+    }
+
+    /// Keep only records `filter` accepts in the combined dataset; with
+    /// several filters a record must pass all of them
+    ///
+    /// Records are filtered after schema validation. The per-file QA files
+    /// still hold every record, so incremental runs can reuse them.
+    pub fn with_filter(
+        mut self,
+        filter: impl Fn(&ProcessedItem) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.filters.push(Box::new(filter));
+        self
+    }
+
+    pub fn with_output_dir(mut self, output_dir: impl Into<PathBuf>) -> Self {
+        self.output_dir = output_dir.into();
+        self
+    }
+
+    /// Generate through this client instead of the model of `config.llm`
+    pub fn with_client(mut self, client: Box<dyn OllamaClient>) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Adjust the processor, e.g. its limits, evaluation split or rejection
+    /// sampling, with its own `with_*` methods
+    pub fn configure_processor(
+        mut self,
+        configure: impl FnOnce(DefaultOllamaProcessor) -> DefaultOllamaProcessor + 'static,
+    ) -> Self {
+        self.configure = Some(Box::new(configure));
+        self
+    }
+
+    /// Tell `observer` about files, sections and records as they are
+    /// generated
+    pub fn with_observer(mut self, observer: Arc<dyn PipelineObserver>) -> Self {
+        self.observers.push(observer);
+        self
+    }
+
+    /// Cap each source's share of the dataset once it is written
+    pub fn with_balance(mut self, balance: Option<Balance>) -> Self {
+        self.balance = balance;
+        self
+    }
+
+    /// Collect the sources and generate the dataset
+    ///
+    /// Files that fail are listed in the returned report rather than
+    /// failing the run; run limits that leave input unprocessed fail it
+    /// with [`ProcessorError::BudgetExceeded`], after the dataset is written.
+    pub async fn run(self) -> Result<BuiltDataset> {
+        let output_dir = self.output_dir.as_path();
+        std::fs::create_dir_all(output_dir).map_err(ProcessorError::io(output_dir))?;
+        let llm = &self.config.llm;
+
+        let mut manifest = RunManifest::new(serde_json::json!({
+            "output_dir": output_dir,
+            "llm_backend": llm.backend.to_string(),
+            "ollama_endpoint": llm.get_url()?,
+            "model": &llm.model,
+        }));
+        manifest.add_model("generation", &llm.model);
+        for (name, template) in prompt_templates() {
+            manifest.add_prompt_template(name, template);
+        }
+
+        let mut provider = None;
+        let client = match self.client {
+            Some(client) => client,
+            None => {
+                let backend: Arc<dyn ModelProvider> = Arc::from(llm.provider()?);
+                provider = Some(Arc::clone(&backend));
+                Box::new(DefaultOllamaClient::from_provider(backend))
+            }
+        };
+        let mut processor = DefaultOllamaProcessor::new_with_client(
+            llm.get_url()?,
+            llm.model.clone(),
+            client,
+            Some(output_dir.to_path_buf()),
+        );
+        for observer in self.observers {
+            processor = processor.with_observer(observer);
+        }
+        if let Some(configure) = self.configure {
+            processor = configure(processor);
+        }
+
+        let dataset_path = output_dir.join(DATASET_FILE);
+        let mut dataset = DatasetWriter::create(&dataset_path)?;
+        let eval_path = output_dir.join(EVAL_FILE);
+        let mut eval_dataset = None;
+        let filters = &self.filters;
+        let mut report = RunReport {
+            output_dir: Some(output_dir.to_path_buf()),
+            ..RunReport::default()
+        };
+
+        let (files, received) = mpsc::channel(pipeline::CHANNEL_CAPACITY);
+        let feed = pipeline::collect_sources(self.sources, output_dir, files);
+        let mut append = |items: &[ProcessedItem]| {
+            let (eval, train): (Vec<_>, Vec<_>) = items
+                .iter()
+                .filter(|item| filters.iter().all(|keep| keep(item)))
+                .cloned()
+                .partition(ProcessedItem::is_eval);
+            if !eval.is_empty() {
+                if eval_dataset.is_none() {
+                    eval_dataset = Some(DatasetWriter::create(&eval_path)?);
+                }
+                eval_dataset.as_mut().unwrap().append(&eval)?;
+            }
+            dataset.append(&train).map(drop)
+        };
+        let mut record = |file_path: &Path, result: Result<usize>| match result {
+            Ok(_) => report.file_succeeded(),
+            Err(e) => report.file_failed(file_path, &e),
+        };
+        let (sources, held_back) = tokio::join!(
+            feed,
+            Pipeline::new(&processor).run(received, &mut append, &mut record)
+        );
+        for source in sources? {
+            manifest.add_source(source);
+        }
+
+        let mut records = dataset.finish()?;
+        if let Some(balance) = self.balance {
+            let balanced = balance.apply(schema::read_dataset(&dataset_path)?.items);
+            records = schema::write_dataset(&dataset_path, &balanced.items)?;
+        }
+        manifest.add_output(&dataset_path);
+        let eval = match eval_dataset {
+            Some(eval_dataset) => {
+                manifest.add_output(&eval_path);
+                Some((eval_path, eval_dataset.finish()?))
+            }
+            None => None,
+        };
+
+        if let Some(provider) = provider {
+            manifest.add_usage("generation", provider.usage());
+        }
+        manifest.interrupted = processor.shutdown().is_requested();
+        manifest.finish(records);
+        let manifest = manifest.write(output_dir)?;
+
+        if let Some(exceeded) = processor.limits().exceeded(held_back) {
+            return Err(ProcessorError::BudgetExceeded(exceeded).into());
+        }
+        Ok(BuiltDataset {
+            dataset: dataset_path,
+            records,
+            eval,
+            manifest,
+            report,
+        })
+    }
+}
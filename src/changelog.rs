@@ -0,0 +1,468 @@
+//! Structured reading of changelogs and release notes.
+//!
+//! A changelog is split at its version headings into [`Release`]s, each
+//! with the date and the entries under its Added/Fixed/Changed style
+//! categories. Every release is then generated from as a section of its
+//! own that starts with the version, so no question loses track of which
+//! version a change belongs to, even when the section is split further.
+
+use anyhow::Result;
+use regex::Regex;
+use serde::Serialize;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::density::DocumentSize;
+use crate::sections::SpannedSection;
+
+/// File names (without extension, lowercased) of changelogs
+const CHANGELOG_NAMES: &[&str] = &[
+    "changelog",
+    "changes",
+    "history",
+    "news",
+    "releases",
+    "release-notes",
+    "release_notes",
+    "releasenotes",
+];
+
+/// First line of a release section; the version follows
+const SECTION_PREFIX: &str = "# Changelog: version ";
+
+/// Name of the category of entries listed before any category heading
+const UNCATEGORIZED: &str = "Changes";
+
+/// Entries of one kind in a release, e.g. "Fixed"
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Category {
+    pub name: String,
+    pub entries: Vec<String>,
+}
+
+/// One version of a changelog
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Release {
+    /// The version as written, without a leading `v`, or "Unreleased"
+    pub version: String,
+    pub date: Option<String>,
+    pub categories: Vec<Category>,
+    /// Text of the release that isn't a list entry
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub notes: String,
+    /// Byte range of the release in the file
+    pub span: Range<usize>,
+}
+
+fn version_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| {
+        Regex::new(r"(?i)(?:^|[\s\[(])v?(\d+\.\d+(?:\.\d+)*(?:-[0-9a-z.]+)?)\b|\[?\b(unreleased)\b")
+            .expect("valid regex")
+    })
+}
+
+fn date_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| Regex::new(r"\d{4}-\d{2}-\d{2}").expect("valid regex"))
+}
+
+/// Level and text of a markdown heading
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.chars().take_while(|&c| c == '#').count();
+    let text = line[level..].strip_prefix([' ', '\t'])?;
+    (1..=6).contains(&level).then_some((level, text.trim()))
+}
+
+/// The version named by a heading or file name
+fn version_in(text: &str) -> Option<String> {
+    let captures = version_pattern().captures(text)?;
+    match (captures.get(1), captures.get(2)) {
+        (Some(version), _) => Some(version.as_str().to_string()),
+        (None, Some(_)) => Some("Unreleased".to_string()),
+        _ => None,
+    }
+}
+
+/// A category heading, e.g. `### Fixed`, `**Fixed**` or `Fixed:`
+fn category_name(line: &str) -> Option<String> {
+    let text = match heading(line) {
+        Some((_, text)) => text,
+        None => {
+            let line = line.trim();
+            let bold = line
+                .strip_prefix("**")
+                .and_then(|rest| rest.trim_end_matches(':').strip_suffix("**"));
+            let label = line
+                .strip_suffix(':')
+                .filter(|label| label.split_whitespace().count() <= 3);
+            bold.or(label)?
+        }
+    };
+    let name = text.trim_matches(|c: char| c == '*' || c == ':' || c.is_whitespace());
+    (!name.is_empty() && !name.starts_with(['-', '*', '+'])).then(|| name.to_string())
+}
+
+fn list_entry(line: &str) -> Option<&str> {
+    let trimmed = line.trim_start();
+    ["- ", "* ", "+ "]
+        .iter()
+        .find_map(|marker| trimmed.strip_prefix(marker))
+        .map(str::trim)
+}
+
+impl Release {
+    fn new(version: String, date: Option<String>, start: usize) -> Self {
+        Self {
+            version,
+            date,
+            categories: Vec::new(),
+            notes: String::new(),
+            span: start..start,
+        }
+    }
+
+    fn category(&mut self, name: &str) -> &mut Category {
+        if self.categories.last().is_none_or(|c| c.name != name) {
+            self.categories.push(Category {
+                name: name.to_string(),
+                entries: Vec::new(),
+            });
+        }
+        self.categories.last_mut().expect("category just pushed")
+    }
+
+    fn add_line(&mut self, category: &mut Option<String>, line: &str) {
+        if line.trim().is_empty() {
+            return;
+        }
+        if let Some(entry) = list_entry(line) {
+            let name = category
+                .get_or_insert_with(|| UNCATEGORIZED.to_string())
+                .clone();
+            self.category(&name).entries.push(entry.to_string());
+        } else if let Some(name) = category_name(line) {
+            *category = Some(name);
+        } else if line.starts_with([' ', '\t']) && category.is_some() {
+            // Continuation of the previous entry
+            let name = category.clone().unwrap_or_default();
+            match self.category(&name).entries.last_mut() {
+                Some(entry) => {
+                    entry.push(' ');
+                    entry.push_str(line.trim());
+                }
+                None => self.push_note(line),
+            }
+        } else {
+            self.push_note(line);
+        }
+    }
+
+    fn push_note(&mut self, line: &str) {
+        if !self.notes.is_empty() {
+            self.notes.push('\n');
+        }
+        self.notes.push_str(line.trim());
+    }
+
+    fn is_empty(&self) -> bool {
+        self.notes.is_empty() && self.categories.iter().all(|c| c.entries.is_empty())
+    }
+
+    /// The release as a section to generate from, starting with its version
+    pub fn to_section(&self) -> String {
+        let mut text = format!("{}{}\n", SECTION_PREFIX, self.version);
+        if let Some(date) = &self.date {
+            text.push_str(&format!("Released: {}\n", date));
+        }
+        if !self.notes.is_empty() {
+            text.push('\n');
+            text.push_str(&self.notes);
+            text.push('\n');
+        }
+        for category in self.categories.iter().filter(|c| !c.entries.is_empty()) {
+            text.push_str(&format!(
+                "\n## {} in version {}\n",
+                category.name, self.version
+            ));
+            for entry in &category.entries {
+                text.push_str(&format!("- {}\n", entry));
+            }
+        }
+        text
+    }
+}
+
+/// The releases of a changelog, in file order
+///
+/// Releases start at headings naming a version (`## [1.2.0] - 2024-05-01`,
+/// `## v1.2`, `# Release 1.2.0 (2024-05-01)`, `## [Unreleased]`); the level
+/// of the first such heading is the release level, and deeper headings are
+/// categories. Without any version heading, `fallback_version` (e.g. from
+/// a release file named `v1.2.0.md`) makes the whole text one release.
+pub fn parse(content: &str, fallback_version: Option<&str>) -> Vec<Release> {
+    let mut releases: Vec<Release> = Vec::new();
+    let mut release_level = None;
+    let mut category = None;
+    let mut offset = 0;
+    for raw in content.split_inclusive('\n') {
+        let start = offset;
+        offset += raw.len();
+        let line = raw.trim_end_matches(['\n', '\r']);
+        if let Some((level, text)) = heading(line) {
+            let starts_release = release_level.is_none_or(|release| level <= release);
+            if starts_release {
+                match version_in(text) {
+                    Some(version) => {
+                        release_level = Some(level);
+                        let date = date_pattern().find(text).map(|m| m.as_str().to_string());
+                        releases.push(Release::new(version, date, start));
+                        category = None;
+                    }
+                    // A heading above the releases ends the last one
+                    None if release_level.is_some() => {
+                        if let Some(release) = releases.last_mut() {
+                            release.span.end = start;
+                        }
+                        release_level = None;
+                    }
+                    None => {}
+                }
+                continue;
+            }
+        }
+        if release_level.is_some() {
+            if let Some(release) = releases.last_mut() {
+                release.add_line(&mut category, line);
+                release.span.end = offset;
+            }
+        }
+    }
+
+    if releases.is_empty() {
+        if let Some(version) = fallback_version.and_then(version_in) {
+            let mut release = Release::new(version, None, 0);
+            let mut category = None;
+            for line in content.lines() {
+                release.add_line(&mut category, line);
+            }
+            release.span.end = content.len();
+            releases.push(release);
+        }
+    }
+    releases.retain(|release| !release.is_empty());
+    releases
+}
+
+/// Whether a file is a changelog or release notes, going by its name, or by
+/// a changelog title followed by version headings
+pub fn is_changelog(path: &Path, content: &str) -> bool {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    if CHANGELOG_NAMES.iter().any(|name| stem.starts_with(name)) {
+        return true;
+    }
+    let titled = content
+        .lines()
+        .filter_map(heading)
+        .take(3)
+        .any(|(_, text)| {
+            let text = text.to_lowercase();
+            text.contains("changelog") || text.contains("release notes")
+        });
+    titled && !parse(content, None).is_empty()
+}
+
+/// Release version a file is named after, e.g. `v1.2.0.md` from a GitHub
+/// releases source
+fn file_version(path: &Path) -> Option<&str> {
+    let stem = path.file_stem()?.to_str()?;
+    let version = stem.strip_prefix(['v', 'V']).unwrap_or(stem);
+    version
+        .split('.')
+        .next()
+        .is_some_and(|major| !major.is_empty() && major.chars().all(|c| c.is_ascii_digit()))
+        .then_some(stem)
+}
+
+/// The releases of a file, if it is a changelog or a release notes file
+/// named after its version
+pub fn read_releases(path: &Path) -> Result<Option<Vec<Release>>> {
+    let fallback = file_version(path);
+    if !is_input_name(path) {
+        return Ok(None);
+    }
+    let content = String::from_utf8_lossy(&fs::read(path)?).into_owned();
+    if !is_changelog(path, &content) && fallback.is_none() {
+        return Ok(None);
+    }
+    let releases = parse(&content, fallback);
+    Ok((!releases.is_empty()).then_some(releases))
+}
+
+/// Changelogs are markdown or text; other files keep their usual sections
+fn is_input_name(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("md") || ext.eq_ignore_ascii_case("txt"))
+}
+
+/// The sections to generate from for releases, one per release
+pub fn sections(releases: &[Release]) -> Vec<SpannedSection> {
+    releases
+        .iter()
+        .map(|release| SpannedSection {
+            text: release.to_section(),
+            span: release.span.clone(),
+        })
+        .collect()
+}
+
+/// Size of releases, as the targets of a file are based on
+pub fn measure(releases: &[Release]) -> DocumentSize {
+    releases.iter().map(Release::to_section).collect()
+}
+
+/// The version a release section is about
+pub fn section_version(section: &str) -> Option<&str> {
+    section
+        .lines()
+        .next()?
+        .strip_prefix(SECTION_PREFIX)
+        .map(str::trim)
+}
+
+/// Part of a release section, with the section's version and date lines
+/// put back in front if the part lost them
+pub fn with_release_header(section: &str, part: &str) -> String {
+    if section_version(section).is_none() || section_version(part).is_some() {
+        return part.to_string();
+    }
+    let header: String = section
+        .lines()
+        .take_while(|line| !line.trim().is_empty())
+        .map(|line| format!("{}\n", line))
+        .collect();
+    format!("{}\n{}", header, part)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHANGELOG: &str = "# Changelog\n\
+        All notable changes are documented here.\n\
+        \n\
+        ## [Unreleased]\n\
+        \n\
+        ## [1.2.0] - 2024-05-01\n\
+        ### Added\n\
+        - Streaming responses\n\
+        - A `--watch` flag that keeps\n  \
+          polling sources\n\
+        ### Fixed\n\
+        * Crash on empty files\n\
+        \n\
+        ## v1.1 (2024-03-10)\n\
+        Maintenance release.\n\
+        \n\
+        - Faster startup\n";
+
+    #[test]
+    fn test_parse_changelog() {
+        let releases = parse(CHANGELOG, None);
+        let versions: Vec<_> = releases.iter().map(|r| r.version.as_str()).collect();
+        // The empty Unreleased section is left out
+        assert_eq!(versions, ["1.2.0", "1.1"]);
+
+        let release = &releases[0];
+        assert_eq!(release.date.as_deref(), Some("2024-05-01"));
+        assert_eq!(
+            release.categories,
+            [
+                Category {
+                    name: "Added".to_string(),
+                    entries: vec![
+                        "Streaming responses".to_string(),
+                        "A `--watch` flag that keeps polling sources".to_string(),
+                    ],
+                },
+                Category {
+                    name: "Fixed".to_string(),
+                    entries: vec!["Crash on empty files".to_string()],
+                },
+            ]
+        );
+        assert!(CHANGELOG[release.span.clone()].starts_with("## [1.2.0]"));
+        assert!(CHANGELOG[release.span.clone()].ends_with("empty files\n\n"));
+
+        let release = &releases[1];
+        assert_eq!(release.notes, "Maintenance release.");
+        assert_eq!(release.categories[0].name, UNCATEGORIZED);
+        assert_eq!(release.span.end, CHANGELOG.len());
+    }
+
+    #[test]
+    fn test_release_sections_keep_the_version() {
+        let releases = parse(CHANGELOG, None);
+        let section = releases[0].to_section();
+        assert_eq!(
+            section,
+            "# Changelog: version 1.2.0\nReleased: 2024-05-01\n\
+             \n## Added in version 1.2.0\n- Streaming responses\n\
+             - A `--watch` flag that keeps polling sources\n\
+             \n## Fixed in version 1.2.0\n- Crash on empty files\n"
+        );
+        assert_eq!(section_version(&section), Some("1.2.0"));
+        assert_eq!(section_version("# Guide"), None);
+
+        let part = "## Fixed in version 1.2.0\n- Crash on empty files\n";
+        assert_eq!(
+            with_release_header(&section, part),
+            format!(
+                "# Changelog: version 1.2.0\nReleased: 2024-05-01\n\n{}",
+                part
+            )
+        );
+        assert_eq!(with_release_header(&section, &section), section);
+        assert_eq!(with_release_header("# Guide\n\ntext", part), part);
+    }
+
+    #[test]
+    fn test_recognizes_changelogs() {
+        assert!(is_changelog(Path::new("CHANGELOG.md"), ""));
+        assert!(is_changelog(Path::new("docs/guide.md"), CHANGELOG));
+        assert!(!is_changelog(
+            Path::new("guide.md"),
+            "# Guide\n\n## Install 2.0 first\n\ntext\n"
+        ));
+        assert!(!is_changelog(
+            Path::new("guide.md"),
+            "# Guide\n\n## Changelog of features\n"
+        ));
+    }
+
+    #[test]
+    fn test_release_notes_named_after_their_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("v2.0.1.md");
+        fs::write(&path, "## What's Changed\n\n- Fix TLS on Windows\n").unwrap();
+        let releases = read_releases(&path).unwrap().unwrap();
+        assert_eq!(releases[0].version, "2.0.1");
+        assert_eq!(
+            releases[0].categories,
+            [Category {
+                name: "What's Changed".to_string(),
+                entries: vec!["Fix TLS on Windows".to_string()],
+            }]
+        );
+
+        let guide = dir.path().join("guide.md");
+        fs::write(&guide, "# Guide\n\n- a step\n").unwrap();
+        assert!(read_releases(&guide).unwrap().is_none());
+    }
+}
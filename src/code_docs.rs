@@ -0,0 +1,492 @@
+//! Extraction of documentation comments from source code, so a codebase can
+//! be collected as a documentation corpus (`--source-type code`).
+//!
+//! Rust `///` and `//!` comments, Python docstrings and JSDoc `/** */`
+//! blocks are read with the signature of the item they document, and each
+//! file's documentation is written as markdown: the module documentation
+//! first, then one `##` section per documented item.
+
+use std::path::Path;
+
+/// Lines of a multi-line signature kept before it is cut off
+const MAX_SIGNATURE_LINES: usize = 8;
+
+/// Languages documentation comments are read from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    Rust,
+    Python,
+    JavaScript,
+}
+
+impl Language {
+    /// Language of a source file, going by its extension
+    pub fn of(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "rs" => Some(Language::Rust),
+            "py" | "pyi" => Some(Language::Python),
+            "js" | "jsx" | "mjs" | "cjs" | "ts" | "tsx" | "mts" | "cts" => {
+                Some(Language::JavaScript)
+            }
+            _ => None,
+        }
+    }
+
+    /// Name of the language in fenced code blocks
+    fn fence_name(&self) -> &'static str {
+        match self {
+            Language::Rust => "rust",
+            Language::Python => "python",
+            Language::JavaScript => "typescript",
+        }
+    }
+}
+
+/// A documented item of a source file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocItem {
+    /// Declaration of the item, without its body
+    pub signature: String,
+    /// Class the item is defined in (Python)
+    pub parent: Option<String>,
+    /// 1-based line of the declaration
+    pub line: usize,
+    pub docs: String,
+}
+
+/// Documentation of one source file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileDocs {
+    /// Documentation of the file as a whole (`//!`, a module docstring, or
+    /// a JSDoc `@file`/`@module` block)
+    pub module: Option<String>,
+    pub items: Vec<DocItem>,
+}
+
+impl FileDocs {
+    pub fn is_empty(&self) -> bool {
+        self.module.is_none() && self.items.is_empty()
+    }
+
+    /// The documentation as markdown, under a title naming `label`, the
+    /// file's path
+    pub fn to_markdown(&self, label: &str, language: Language) -> String {
+        let mut text = format!("# {}\n", label);
+        if let Some(module) = &self.module {
+            text.push_str(&format!("\n{}\n", module));
+        }
+        for item in &self.items {
+            let name = match &item.parent {
+                Some(parent) => format!("{}: {}", parent, first_line(&item.signature)),
+                None => first_line(&item.signature).to_string(),
+            };
+            text.push_str(&format!(
+                "\n## `{}`\n\nDefined in `{}` at line {}:\n\n```{}\n{}\n```\n\n{}\n",
+                name.replace('`', "'"),
+                label,
+                item.line,
+                language.fence_name(),
+                item.signature,
+                item.docs
+            ));
+        }
+        text
+    }
+}
+
+fn first_line(text: &str) -> &str {
+    text.lines().next().unwrap_or_default().trim()
+}
+
+/// Read the documentation comments of a source file
+pub fn extract(language: Language, source: &str) -> FileDocs {
+    let lines: Vec<&str> = source.lines().collect();
+    let mut docs = match language {
+        Language::Rust => extract_rust(&lines),
+        Language::Python => extract_python(&lines),
+        Language::JavaScript => extract_jsdoc(&lines),
+    };
+    let hide_lines = language == Language::Rust;
+    docs.module = docs
+        .module
+        .map(|module| as_markdown(&module, hide_lines))
+        .filter(|module| !module.is_empty());
+    for item in &mut docs.items {
+        item.docs = as_markdown(&item.docs, hide_lines);
+    }
+    docs.items.retain(|item| !item.docs.is_empty());
+    docs
+}
+
+/// Documentation text made safe to embed in a section: headings are moved
+/// below the item's `##`, and rustdoc's hidden `# ` lines in code blocks
+/// are dropped
+fn as_markdown(docs: &str, hide_lines: bool) -> String {
+    let mut fenced = false;
+    let mut out = Vec::new();
+    for line in docs.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            fenced = !fenced;
+            out.push(line.to_string());
+        } else if fenced {
+            if !(hide_lines && (trimmed == "#" || trimmed.starts_with("# "))) {
+                out.push(line.to_string());
+            }
+        } else if trimmed.starts_with('#') {
+            out.push(format!("##{}", trimmed));
+        } else {
+            out.push(line.to_string());
+        }
+    }
+    out.join("\n").trim().to_string()
+}
+
+/// The declaration starting at `start`, up to its body
+fn signature(lines: &[&str], start: usize, ends: &[&str]) -> String {
+    let mut signature = Vec::new();
+    for line in lines.iter().skip(start).take(MAX_SIGNATURE_LINES) {
+        let mut line = line.trim_end();
+        let end = ends
+            .iter()
+            .filter_map(|end| line.find(end))
+            .min()
+            .filter(|&at| at > 0 || !signature.is_empty());
+        if let Some(at) = end {
+            line = line[..at].trim_end();
+        }
+        signature.push(line);
+        if end.is_some() || line.ends_with(';') {
+            break;
+        }
+    }
+    dedent(&signature.join("\n"))
+}
+
+/// Strip the whitespace all non-blank lines start with
+fn dedent(text: &str) -> String {
+    let indent = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    text.lines()
+        .map(|line| line.get(indent..).unwrap_or(line.trim_start()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn strip_comment<'a>(line: &'a str, marker: &str) -> Option<&'a str> {
+    let rest = line.trim_start().strip_prefix(marker)?;
+    Some(rest.strip_prefix(' ').unwrap_or(rest))
+}
+
+fn extract_rust(lines: &[&str]) -> FileDocs {
+    let mut docs = FileDocs::default();
+    let mut module = Vec::new();
+    let mut pending: Vec<&str> = Vec::new();
+    let mut index = 0;
+    while index < lines.len() {
+        let line = lines[index];
+        let trimmed = line.trim_start();
+        if let Some(text) = strip_comment(line, "//!") {
+            module.push(text);
+        } else if trimmed.starts_with("////") {
+            pending.clear();
+        } else if let Some(text) = strip_comment(line, "///") {
+            pending.push(text);
+        } else if trimmed.starts_with("#[") || trimmed.starts_with("#![") {
+            // Attributes sit between the docs and the item
+        } else if trimmed.is_empty() || trimmed.starts_with("//") {
+            pending.clear();
+        } else if !pending.is_empty() {
+            docs.items.push(DocItem {
+                signature: signature(lines, index, &["{", " where"]),
+                parent: None,
+                line: index + 1,
+                docs: pending.join("\n"),
+            });
+            pending.clear();
+        }
+        index += 1;
+    }
+    if !module.is_empty() {
+        docs.module = Some(module.join("\n"));
+    }
+    docs
+}
+
+/// The docstring starting at `start`, if the line opens one, and the index
+/// of the line after it
+fn docstring(lines: &[&str], start: usize) -> Option<(String, usize)> {
+    let first = lines.get(start)?.trim_start();
+    let first = first
+        .strip_prefix(['r', 'R', 'u', 'U'])
+        .filter(|rest| rest.starts_with(['"', '\'']))
+        .unwrap_or(first);
+    let quote = ["\"\"\"", "'''"]
+        .into_iter()
+        .find(|quote| first.starts_with(quote))?;
+    let rest = &first[quote.len()..];
+    if let Some(end) = rest.find(quote) {
+        return Some((rest[..end].trim().to_string(), start + 1));
+    }
+    let mut body = vec![rest];
+    for (offset, line) in lines.iter().enumerate().skip(start + 1) {
+        if let Some(end) = line.find(quote) {
+            body.push(&line[..end]);
+            let head = body[0].trim();
+            let tail = dedent(&body[1..].join("\n"));
+            let text = format!("{}\n{}", head, tail);
+            return Some((text.trim().to_string(), offset + 1));
+        }
+        body.push(line);
+    }
+    None
+}
+
+fn indent_of(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+fn extract_python(lines: &[&str]) -> FileDocs {
+    let mut docs = FileDocs::default();
+    // The module docstring is the first statement
+    let first = lines
+        .iter()
+        .position(|line| !line.trim().is_empty() && !line.trim_start().starts_with('#'));
+    if let Some((text, _)) = first.and_then(|first| docstring(lines, first)) {
+        docs.module = Some(text);
+    }
+
+    // Classes enclosing the current line, with their indentation
+    let mut classes: Vec<(usize, String)> = Vec::new();
+    let mut index = 0;
+    while index < lines.len() {
+        let line = lines[index];
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            index += 1;
+            continue;
+        }
+        let indent = indent_of(line);
+        classes.retain(|(class_indent, _)| *class_indent < indent);
+        let declaration = trimmed
+            .strip_prefix("async ")
+            .unwrap_or(trimmed)
+            .strip_prefix("def ")
+            .map(|_| false)
+            .or_else(|| trimmed.strip_prefix("class ").map(|_| true));
+        let Some(is_class) = declaration else {
+            index += 1;
+            continue;
+        };
+        // The declaration ends at the line ending with its colon
+        let end = (index..lines.len().min(index + MAX_SIGNATURE_LINES))
+            .find(|&end| {
+                let line = lines[end].split(" #").next().unwrap_or_default();
+                line.trim_end().ends_with(':')
+            })
+            .unwrap_or(index);
+        let signature = dedent(&lines[index..=end].join("\n"));
+        let signature = signature.trim_end().trim_end_matches(':').to_string();
+        let body = (end + 1..lines.len()).find(|&next| !lines[next].trim().is_empty());
+        if let Some((text, _)) = body.and_then(|body| docstring(lines, body)) {
+            docs.items.push(DocItem {
+                signature,
+                parent: classes.last().map(|(_, name)| name.clone()),
+                line: index + 1,
+                docs: text,
+            });
+        }
+        if is_class {
+            let name = trimmed["class ".len()..]
+                .split(['(', ':'])
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_string();
+            classes.push((indent, name));
+        }
+        index = end + 1;
+    }
+    docs
+}
+
+fn extract_jsdoc(lines: &[&str]) -> FileDocs {
+    let mut docs = FileDocs::default();
+    let mut index = 0;
+    while index < lines.len() {
+        let trimmed = lines[index].trim_start();
+        if !trimmed.starts_with("/**") || trimmed.starts_with("/**/") {
+            index += 1;
+            continue;
+        }
+        // Collect the block up to its closing `*/`
+        let mut body = Vec::new();
+        let mut end = index;
+        let mut rest = &trimmed[3..];
+        loop {
+            let (text, closed) = match rest.find("*/") {
+                Some(at) => (&rest[..at], true),
+                None => (rest, false),
+            };
+            let text = text.trim_start();
+            let text = text.strip_prefix('*').unwrap_or(text);
+            body.push(text.strip_prefix(' ').unwrap_or(text).trim_end());
+            if closed || end + 1 >= lines.len() {
+                break;
+            }
+            end += 1;
+            rest = lines[end];
+        }
+        let text = body.join("\n").trim().to_string();
+        let is_module = text.contains("@file") || text.contains("@module");
+        let next = (end + 1..lines.len()).find(|&next| !lines[next].trim().is_empty());
+        match next {
+            _ if is_module && docs.module.is_none() => docs.module = Some(text),
+            Some(next) if !lines[next].trim_start().starts_with("/*") => docs.items.push(DocItem {
+                signature: signature(lines, next, &[" {", "{", " =>"]),
+                parent: None,
+                line: next + 1,
+                docs: text,
+            }),
+            _ => {}
+        }
+        index = end + 1;
+    }
+    docs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rust_doc_comments() {
+        let source = "//! Parsing of config files.\n\
+                      \n\
+                      use std::fs;\n\
+                      \n\
+                      /// Read a config file.\n\
+                      ///\n\
+                      /// # Examples\n\
+                      ///\n\
+                      /// ```\n\
+                      /// # use demo::load;\n\
+                      /// let config = load(\"a.toml\")?;\n\
+                      /// ```\n\
+                      #[must_use]\n\
+                      pub fn load(\n    path: &str,\n) -> Result<Config> {\n    todo!()\n}\n\
+                      \n\
+                      // Not documentation\n\
+                      fn helper() {}\n\
+                      \n\
+                      /// Limit on retries\n\
+                      pub const RETRIES: u32 = 3;\n";
+        let docs = extract(Language::Rust, source);
+        assert_eq!(docs.module.as_deref(), Some("Parsing of config files."));
+        assert_eq!(docs.items.len(), 2);
+        let load = &docs.items[0];
+        assert_eq!(
+            load.signature,
+            "pub fn load(\n    path: &str,\n) -> Result<Config>"
+        );
+        assert_eq!(load.line, 14);
+        assert_eq!(
+            load.docs,
+            "Read a config file.\n\n### Examples\n\n```\nlet config = load(\"a.toml\")?;\n```"
+        );
+        assert_eq!(docs.items[1].signature, "pub const RETRIES: u32 = 3;");
+    }
+
+    #[test]
+    fn test_python_docstrings() {
+        let source = "\"\"\"Helpers for HTTP clients.\"\"\"\n\
+                      import json\n\
+                      \n\
+                      class Client(Base):\n    \
+                          \"\"\"A client with retries.\n\n    \
+                          Retries use exponential backoff.\n    \
+                          \"\"\"\n\
+                      \n    \
+                          async def get(self, url,\n                  timeout=10):\n        \
+                              '''Fetch a URL.'''\n        \
+                              return None\n\
+                      \n    \
+                          def _private(self):\n        \
+                              return 1\n\
+                      \n\
+                      def parse(text):  # noqa\n    \
+                          r\"\"\"Parse JSON text.\"\"\"\n";
+        let docs = extract(Language::Python, source);
+        assert_eq!(docs.module.as_deref(), Some("Helpers for HTTP clients."));
+        let items: Vec<_> = docs
+            .items
+            .iter()
+            .map(|item| {
+                (
+                    item.parent.as_deref(),
+                    item.signature.as_str(),
+                    item.docs.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            items,
+            [
+                (
+                    None,
+                    "class Client(Base)",
+                    "A client with retries.\n\nRetries use exponential backoff."
+                ),
+                (
+                    Some("Client"),
+                    "async def get(self, url,\n              timeout=10)",
+                    "Fetch a URL."
+                ),
+                (None, "def parse(text):  # noqa", "Parse JSON text."),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_jsdoc_blocks() {
+        let source = "/**\n * @file Date helpers.\n */\n\
+                      \n\
+                      /**\n * Format a date.\n * @param {Date} date - The date\n * @returns {string}\n */\n\
+                      export function format(date) {\n  return '';\n}\n\
+                      \n\
+                      /** Current time. */\n\
+                      export const now = () => new Date();\n";
+        let docs = extract(Language::JavaScript, source);
+        assert_eq!(docs.module.as_deref(), Some("@file Date helpers."));
+        assert_eq!(docs.items[0].signature, "export function format(date)");
+        assert_eq!(
+            docs.items[0].docs,
+            "Format a date.\n@param {Date} date - The date\n@returns {string}"
+        );
+        assert_eq!(docs.items[1].signature, "export const now = ()");
+        assert_eq!(docs.items[1].docs, "Current time.");
+    }
+
+    #[test]
+    fn test_markdown_has_a_section_per_item() {
+        let docs = extract(
+            Language::Rust,
+            "/// Adds one.\nfn inc(x: u8) -> u8 { x + 1 }\n",
+        );
+        assert_eq!(
+            docs.to_markdown("src/math.rs", Language::Rust),
+            "# src/math.rs\n\n## `fn inc(x: u8) -> u8`\n\n\
+             Defined in `src/math.rs` at line 2:\n\n```rust\nfn inc(x: u8) -> u8\n```\n\nAdds one.\n"
+        );
+        assert_eq!(
+            Language::of(Path::new("app.tsx")),
+            Some(Language::JavaScript)
+        );
+        assert_eq!(Language::of(Path::new("notes.md")), None);
+    }
+}
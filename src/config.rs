@@ -1,8 +1,15 @@
-use anyhow::Result;
+use anyhow::{anyhow, bail, Context, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::env;
+use std::fmt;
+use std::fs;
+use std::path::Path;
 
-use crate::external::{EmbeddingConfig, LLMConfig, VectorDBConfig};
+use crate::external::vectordb::DEFAULT_UPSERT_BATCH_SIZE;
+use crate::external::{
+    parse_headers, EmbeddingConfig, EmbeddingProvider, LLMConfig, RetryPolicy, VectorDBConfig,
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProcessingConfig {
@@ -26,11 +33,761 @@ pub struct Config {
     pub output: OutputConfig,
 }
 
+/// Config file read from the working directory when no `--config` is given
+pub const DEFAULT_CONFIG_FILE: &str = "llm_dataset_builder.toml";
+
+/// Keyring service that config file secrets are looked up under by default
+pub const KEYRING_SERVICE: &str = env!("CARGO_PKG_NAME");
+
+/// Settings from a TOML config file, with optional named profiles
+///
+/// Every key names one of the environment variables read by
+/// [`Config::from_env`], in any case and with nested tables joined by `_`, so
+/// `[ollama] host = "gpu-01"` and `ollama_host = "gpu-01"` both set
+/// `OLLAMA_HOST`. Tables under `[profile.NAME]` override the top-level
+/// settings when that profile is selected, and `default_profile` names the
+/// profile used when none is requested.
+///
+/// Values may reference environment variables as `${NAME}` (`$${` for a
+/// literal `${`), and `{ keyring = "entry" }` reads a value from the OS
+/// keyring, so tokens never have to be written into the file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConfigFile {
+    settings: BTreeMap<String, FileValue>,
+    profiles: BTreeMap<String, BTreeMap<String, FileValue>>,
+    default_profile: Option<String>,
+}
+
+/// A config file value as written, resolved only when it is used
+#[derive(Debug, Clone, PartialEq)]
+enum FileValue {
+    /// Text that may reference environment variables
+    Text(String),
+    /// Secret stored in the OS keyring
+    Keyring { service: String, entry: String },
+}
+
+impl FileValue {
+    /// Inline table `{ keyring = "entry", service = "..." }`
+    fn keyring(name: &str, mut table: toml::Table) -> Result<Self> {
+        let mut string = |key: &str| match table.remove(key) {
+            Some(toml::Value::String(value)) => Ok(Some(value)),
+            Some(_) => Err(anyhow!("'{}' keyring {} must be a string", name, key)),
+            None => Ok(None),
+        };
+        let entry = string("keyring")?.unwrap_or_default();
+        let service = string("service")?.unwrap_or_else(|| KEYRING_SERVICE.to_string());
+        if let Some(key) = table.keys().next() {
+            bail!("'{}' has unknown keyring option '{}'", name, key);
+        }
+        Ok(Self::Keyring { service, entry })
+    }
+
+    fn resolve(&self, keyring: &dyn Fn(&str, &str) -> Result<String>) -> Result<String> {
+        match self {
+            Self::Text(text) => interpolate(text),
+            Self::Keyring { service, entry } => keyring(service, entry),
+        }
+    }
+}
+
+/// Replace `${NAME}` with the value of environment variable `NAME`
+fn interpolate(text: &str) -> Result<String> {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("${") {
+        if let Some(before) = rest[..start].strip_suffix('$') {
+            out.push_str(before);
+            out.push_str("${");
+            rest = &rest[start + 2..];
+            continue;
+        }
+        out.push_str(&rest[..start]);
+        let name_len = rest[start + 2..]
+            .find('}')
+            .ok_or_else(|| anyhow!("unterminated '${{' in '{}'", text))?;
+        let name = &rest[start + 2..start + 2 + name_len];
+        let value =
+            env::var(name).map_err(|_| anyhow!("environment variable '{}' is not set", name))?;
+        out.push_str(&value);
+        rest = &rest[start + 3 + name_len..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Read a secret from the OS keyring (macOS Keychain, Windows Credential
+/// Manager or the Secret Service on Linux)
+fn keyring_secret(service: &str, entry: &str) -> Result<String> {
+    let (service, entry) = (service.to_string(), entry.to_string());
+    // The Secret Service client drives its own executor, so keep it off the
+    // runtime's threads
+    std::thread::spawn(move || {
+        keyring::Entry::new(&service, &entry)
+            .and_then(|item| item.get_password())
+            .with_context(|| format!("keyring entry '{}' of service '{}'", entry, service))
+    })
+    .join()
+    .map_err(|_| anyhow!("keyring lookup panicked"))?
+}
+
+/// Flatten `table` into environment variable names and values
+fn flatten_table(
+    prefix: &str,
+    table: toml::Table,
+    out: &mut BTreeMap<String, FileValue>,
+) -> Result<()> {
+    for (key, value) in table {
+        let name = if prefix.is_empty() {
+            key.to_uppercase().replace('-', "_")
+        } else {
+            format!("{}_{}", prefix, key.to_uppercase().replace('-', "_"))
+        };
+        let value = match value {
+            toml::Value::Table(table) if table.contains_key("keyring") => {
+                FileValue::keyring(&name, table)?
+            }
+            toml::Value::Table(table) => {
+                flatten_table(&name, table, out)?;
+                continue;
+            }
+            toml::Value::String(value) => FileValue::Text(value),
+            toml::Value::Array(_) => bail!("'{}' must be a single value, not an array", name),
+            other => FileValue::Text(other.to_string()),
+        };
+        out.insert(name, value);
+    }
+    Ok(())
+}
+
+impl ConfigFile {
+    /// Parse config file contents
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut table: toml::Table = text.parse()?;
+        let mut file = Self::default();
+
+        if let Some(profiles) = table.remove("profile") {
+            let toml::Value::Table(profiles) = profiles else {
+                bail!("'profile' must be a table of named profiles, e.g. [profile.local]");
+            };
+            for (name, profile) in profiles {
+                let toml::Value::Table(profile) = profile else {
+                    bail!("profile '{}' must be a table", name);
+                };
+                let mut settings = BTreeMap::new();
+                flatten_table("", profile, &mut settings)
+                    .with_context(|| format!("Invalid profile '{}'", name))?;
+                file.profiles.insert(name, settings);
+            }
+        }
+        if let Some(default) = table.remove("default_profile") {
+            let toml::Value::String(default) = default else {
+                bail!("'default_profile' must be a profile name");
+            };
+            file.default_profile = Some(default);
+        }
+        flatten_table("", table, &mut file.settings)?;
+        Ok(file)
+    }
+
+    /// Read and parse a config file
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Invalid config file {}", path.display()))
+    }
+
+    pub fn profile_names(&self) -> impl Iterator<Item = &str> {
+        self.profiles.keys().map(String::as_str)
+    }
+
+    /// The profile to use: the requested one, else the file's `default_profile`
+    pub fn selected_profile<'a>(&'a self, requested: Option<&'a str>) -> Option<&'a str> {
+        requested.or(self.default_profile.as_deref())
+    }
+
+    /// Unresolved values with the selected profile applied on top of the top-level ones
+    fn values(&self, profile: Option<&str>) -> Result<BTreeMap<String, FileValue>> {
+        let mut values = self.settings.clone();
+        if let Some(name) = self.selected_profile(profile) {
+            let overrides = self.profiles.get(name).ok_or_else(|| {
+                anyhow!(
+                    "Unknown profile '{}'; available profiles: {}",
+                    name,
+                    if self.profiles.is_empty() {
+                        "(none defined)".to_string()
+                    } else {
+                        self.profile_names().collect::<Vec<_>>().join(", ")
+                    }
+                )
+            })?;
+            values.extend(overrides.clone());
+        }
+        Ok(values)
+    }
+
+    /// Settings with the selected profile applied, references resolved
+    pub fn settings(&self, profile: Option<&str>) -> Result<BTreeMap<String, String>> {
+        self.resolve(profile, &keyring_secret, |_| true)
+    }
+
+    /// Resolve the values of the settings `wanted` accepts
+    fn resolve(
+        &self,
+        profile: Option<&str>,
+        keyring: &dyn Fn(&str, &str) -> Result<String>,
+        wanted: impl Fn(&str) -> bool,
+    ) -> Result<BTreeMap<String, String>> {
+        self.values(profile)?
+            .into_iter()
+            .filter(|(name, _)| wanted(name))
+            .map(|(name, value)| {
+                let resolved = value
+                    .resolve(keyring)
+                    .with_context(|| format!("Cannot resolve config setting '{}'", name))?;
+                Ok((name, resolved))
+            })
+            .collect()
+    }
+
+    /// Export the settings as environment variables that aren't already set,
+    /// so real environment variables and command line flags keep precedence
+    ///
+    /// Only these settings are resolved, so a keyring entry shadowed by an
+    /// environment variable is never looked up. Returns the names of the
+    /// variables that were set.
+    pub fn apply_to_env(&self, profile: Option<&str>) -> Result<Vec<String>> {
+        self.apply_with(profile, &keyring_secret)
+    }
+
+    fn apply_with(
+        &self,
+        profile: Option<&str>,
+        keyring: &dyn Fn(&str, &str) -> Result<String>,
+    ) -> Result<Vec<String>> {
+        let settings = self.resolve(profile, keyring, |name| env::var_os(name).is_none())?;
+        for (name, value) in &settings {
+            env::set_var(name, value);
+        }
+        Ok(settings.into_keys().collect())
+    }
+}
+
+/// A setting read from the environment or the config file
+#[derive(Debug, Clone, Copy)]
+pub struct Setting {
+    /// Environment variable name; the config file key is the lower case form
+    pub name: &'static str,
+    /// Heading the setting is listed under in a generated config file
+    pub section: &'static str,
+    /// Value used when nothing sets it; `None` if unset or derived from other settings
+    pub default: Option<&'static str>,
+    /// Credentials that `config show` masks
+    pub secret: bool,
+    pub help: &'static str,
+}
+
+const fn setting(
+    name: &'static str,
+    section: &'static str,
+    default: Option<&'static str>,
+    help: &'static str,
+) -> Setting {
+    Setting {
+        name,
+        section,
+        default,
+        secret: false,
+        help,
+    }
+}
+
+const fn secret(name: &'static str, section: &'static str, help: &'static str) -> Setting {
+    Setting {
+        name,
+        section,
+        default: None,
+        secret: true,
+        help,
+    }
+}
+
+/// Every setting the application reads, in the order `config init` writes them
+pub const SETTINGS: &[Setting] = &[
+    setting(
+        "LLM_BACKEND",
+        "Generation",
+        Some("ollama"),
+        "Generation backend: ollama, or mock for offline runs with template questions from headings",
+    ),
+    setting(
+        "OLLAMA_ENDPOINT",
+        "Generation",
+        Some("http://localhost:11434"),
+        "Ollama API endpoint used for question generation (also -e)",
+    ),
+    setting(
+        "OLLAMA_MODEL",
+        "Generation",
+        Some("m/qwen2514bmax"),
+        "Model that generates questions (also -m)",
+    ),
+    setting(
+        "OLLAMA_KEEP_ALIVE",
+        "Generation",
+        Some("30m"),
+        "How long the model stays loaded between requests, -1 for always (also --keep-alive)",
+    ),
+    setting(
+        "QUESTION_DENSITY",
+        "Generation",
+        Some("words:10"),
+        "Questions per file: words, tokens, section, file or information, with an amount (also --question-density)",
+    ),
+    secret(
+        "OLLAMA_API_KEY",
+        "Generation",
+        "Bearer token for Ollama behind an authenticating reverse proxy",
+    ),
+    secret(
+        "OLLAMA_HEADERS",
+        "Generation",
+        "Extra headers for every Ollama request, as Name=value pairs separated by commas",
+    ),
+    setting(
+        "OLLAMA_HOST",
+        "Library models",
+        Some("localhost"),
+        "Ollama host for embeddings and RAG generation",
+    ),
+    setting(
+        "OLLAMA_PORT",
+        "Library models",
+        Some("11434"),
+        "Ollama port for embeddings and RAG generation",
+    ),
+    setting(
+        "OLLAMA_LLM_MODEL",
+        "Library models",
+        Some("mistral"),
+        "Model that writes RAG answers",
+    ),
+    setting(
+        "OLLAMA_TEMPERATURE",
+        "Library models",
+        Some("0.7"),
+        "Sampling temperature",
+    ),
+    setting(
+        "OLLAMA_TOP_P",
+        "Library models",
+        Some("0.9"),
+        "Nucleus sampling threshold",
+    ),
+    setting(
+        "EMBEDDING_PROVIDER",
+        "Embeddings",
+        Some("ollama"),
+        "Embedding backend: ollama, openai or local",
+    ),
+    setting(
+        "OLLAMA_EMBEDDING_MODEL",
+        "Embeddings",
+        Some("nomic-embed-text"),
+        "Embedding model",
+    ),
+    secret(
+        "EMBEDDING_API_KEY",
+        "Embeddings",
+        "Bearer token for the embedding endpoint (defaults to OLLAMA_API_KEY for ollama)",
+    ),
+    secret(
+        "EMBEDDING_HEADERS",
+        "Embeddings",
+        "Extra headers for embedding requests (defaults to OLLAMA_HEADERS for ollama)",
+    ),
+    secret(
+        "HF_TOKEN",
+        "Embeddings",
+        "Hugging Face token for downloading gated models with EMBEDDING_PROVIDER=local",
+    ),
+    setting(
+        "EMBEDDING_CACHE_DIR",
+        "Embeddings",
+        None,
+        "Embedding cache directory, empty disables it (default: VECTOR_DB_PATH/embedding_cache)",
+    ),
+    setting(
+        "QDRANT_HOST",
+        "Vector database",
+        Some("localhost"),
+        "Qdrant host",
+    ),
+    setting(
+        "QDRANT_PORT",
+        "Vector database",
+        Some("6334"),
+        "Qdrant gRPC port",
+    ),
+    setting(
+        "QDRANT_COLLECTION",
+        "Vector database",
+        Some("documents"),
+        "Collection that holds the indexed content",
+    ),
+    setting(
+        "QDRANT_VECTOR_SIZE",
+        "Vector database",
+        None,
+        "Vector dimension (default: detected from the embedding model)",
+    ),
+    setting(
+        "QDRANT_RECREATE",
+        "Vector database",
+        Some("false"),
+        "Drop and rebuild the collection on startup",
+    ),
+    setting(
+        "QDRANT_UPSERT_BATCH_SIZE",
+        "Vector database",
+        Some("256"),
+        "Points per upsert request",
+    ),
+    secret(
+        "QDRANT_API_KEY",
+        "Vector database",
+        "API key for Qdrant Cloud or secured deployments",
+    ),
+    setting(
+        "QDRANT_USE_TLS",
+        "Vector database",
+        Some("false"),
+        "Connect to Qdrant over TLS",
+    ),
+    setting(
+        "OLLAMA_TIMEOUT_SECS",
+        "Timeouts and retries",
+        Some("300"),
+        "Seconds a generation request may take, 0 waits forever",
+    ),
+    setting(
+        "OLLAMA_MAX_RETRIES",
+        "Timeouts and retries",
+        Some("3"),
+        "Retries after a failed generation request",
+    ),
+    setting(
+        "OLLAMA_RETRY_BACKOFF_MS",
+        "Timeouts and retries",
+        Some("1000"),
+        "Wait before the first generation retry, doubled after each",
+    ),
+    setting(
+        "EMBEDDING_TIMEOUT_SECS",
+        "Timeouts and retries",
+        Some("60"),
+        "Seconds an embedding request may take",
+    ),
+    setting(
+        "EMBEDDING_MAX_RETRIES",
+        "Timeouts and retries",
+        Some("3"),
+        "Retries after a failed embedding request",
+    ),
+    setting(
+        "EMBEDDING_RETRY_BACKOFF_MS",
+        "Timeouts and retries",
+        Some("1000"),
+        "Wait before the first embedding retry",
+    ),
+    setting(
+        "QDRANT_TIMEOUT_SECS",
+        "Timeouts and retries",
+        Some("60"),
+        "Seconds a Qdrant request may take",
+    ),
+    setting(
+        "QDRANT_MAX_RETRIES",
+        "Timeouts and retries",
+        Some("3"),
+        "Retries after a failed Qdrant request",
+    ),
+    setting(
+        "QDRANT_RETRY_BACKOFF_MS",
+        "Timeouts and retries",
+        Some("1000"),
+        "Wait before the first Qdrant retry",
+    ),
+    secret(
+        "GITHUB_TOKEN",
+        "Sources",
+        "GitHub token for private repositories and higher API rate limits",
+    ),
+    setting(
+        "GITHUB_DOWNLOAD_CONCURRENCY",
+        "Sources",
+        Some("8"),
+        "Files downloaded at once from a GitHub directory",
+    ),
+    setting(
+        "GITHUB_TIMEOUT_SECS",
+        "Sources",
+        Some("60"),
+        "Seconds a GitHub file download may take",
+    ),
+    setting(
+        "GITHUB_MAX_RETRIES",
+        "Sources",
+        Some("3"),
+        "Retries after a failed GitHub file download",
+    ),
+    setting(
+        "GITHUB_RETRY_BACKOFF_MS",
+        "Sources",
+        Some("1000"),
+        "Wait before the first download retry, doubled after each",
+    ),
+    setting(
+        "BATCH_SIZE",
+        "Processing",
+        Some("32"),
+        "Items processed per batch",
+    ),
+    setting(
+        "MAX_CONCURRENT_REQUESTS",
+        "Processing",
+        Some("4"),
+        "Requests in flight at once",
+    ),
+    setting("LOG_LEVEL", "Processing", Some("info"), "Log verbosity"),
+    secret(
+        "QUEUE_TOKEN",
+        "Processing",
+        "Shared secret workers present to the coordinator of a distributed run",
+    ),
+    setting(
+        "NOTIFY_WEBHOOK_URL",
+        "Processing",
+        None,
+        "Webhook (e.g. Slack) to POST a run summary to when a run finishes or aborts",
+    ),
+    setting(
+        "PUBLISH_URL",
+        "Processing",
+        None,
+        "Kafka topic (kafka://rest-proxy:8082/topic) or NATS subject (nats://host:4222/subject) to publish accepted records to (also --publish)",
+    ),
+    setting(
+        "OTEL_EXPORTER_OTLP_ENDPOINT",
+        "Processing",
+        None,
+        "OpenTelemetry collector to export trace spans to over OTLP/HTTP (needs --features otel)",
+    ),
+    setting(
+        "OTEL_SERVICE_NAME",
+        "Processing",
+        Some("llm_dataset_builder"),
+        "Service name of the exported spans",
+    ),
+    setting(
+        "LABEL_STUDIO_URL",
+        "Annotation",
+        Some("http://localhost:8080"),
+        "Label Studio server for annotate --tool label-studio",
+    ),
+    secret(
+        "LABEL_STUDIO_API_KEY",
+        "Annotation",
+        "Label Studio access token",
+    ),
+    setting(
+        "ARGILLA_API_URL",
+        "Annotation",
+        Some("http://localhost:6900"),
+        "Argilla server for annotate --tool argilla",
+    ),
+    secret("ARGILLA_API_KEY", "Annotation", "Argilla API key"),
+    setting(
+        "OUTPUT_DIR",
+        "Output",
+        Some("./output"),
+        "Output directory for collected data (also -d)",
+    ),
+    setting(
+        "VECTOR_DB_PATH",
+        "Output",
+        Some("./vector_db"),
+        "Directory for local vector data",
+    ),
+];
+
+/// TOML form of a value: numbers and booleans bare, everything else quoted
+fn toml_value(value: &str) -> String {
+    if value.parse::<i64>().is_ok() || value.parse::<f64>().is_ok() || value.parse::<bool>().is_ok()
+    {
+        value.to_string()
+    } else {
+        toml::Value::String(value.to_string()).to_string()
+    }
+}
+
+/// Contents of the config file written by `config init`: every setting,
+/// commented out at its default, followed by secret and profile examples
+pub fn config_template() -> String {
+    let mut out = format!(
+        "# {} configuration\n\
+         #\n\
+         # Uncomment a setting to change it. Environment variables (and .env) take\n\
+         # precedence over this file, and command line flags over both.\n",
+        env!("CARGO_PKG_NAME")
+    );
+    let mut section = "";
+    for setting in SETTINGS {
+        if setting.section != section {
+            section = setting.section;
+            out.push_str(&format!("\n# --- {} ---\n", section));
+        }
+        let value = setting
+            .default
+            .map(toml_value)
+            .unwrap_or_else(|| "\"\"".to_string());
+        out.push_str(&format!(
+            "\n# {}\n# {} = {}\n",
+            setting.help,
+            setting.name.to_lowercase(),
+            value
+        ));
+    }
+    out.push_str(&format!(
+        "\n# --- Secrets ---\n\
+         # Any value may reference environment variables, and tokens can be read\n\
+         # from the OS keyring instead of being written here (stored under the\n\
+         # service \"{service}\" unless `service` says otherwise):\n\
+         # github_token = \"${{GITHUB_TOKEN_DOCS}}\"\n\
+         # qdrant_api_key = {{ keyring = \"qdrant\" }}\n\
+         # embedding_api_key = {{ keyring = \"openai\", service = \"openai\" }}\n\
+         \n\
+         # --- Profiles ---\n\
+         # Select with --profile NAME; default_profile applies when none is given.\n\
+         # default_profile = \"local\"\n\
+         #\n\
+         # [profile.local]\n\
+         # ollama_endpoint = \"http://localhost:11434\"\n\
+         #\n\
+         # [profile.gpu-server]\n\
+         # ollama_endpoint = \"https://gpu-01.internal:11434\"\n\
+         # ollama_model = \"llama3:70b\"\n\
+         # ollama_timeout_secs = 900\n",
+        service = KEYRING_SERVICE
+    ));
+    out
+}
+
+/// Where the value of a setting in effect came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SettingSource {
+    Cli,
+    Env,
+    ConfigFile,
+    Default,
+}
+
+impl fmt::Display for SettingSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Cli => "command line",
+            Self::Env => "environment",
+            Self::ConfigFile => "config file",
+            Self::Default => "default",
+        })
+    }
+}
+
+/// The value a setting has after merging all sources
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EffectiveSetting {
+    pub name: &'static str,
+    /// `None` when unset, with secrets already masked
+    pub value: Option<String>,
+    pub source: SettingSource,
+}
+
+/// Resolve every setting as command line > environment > config file > default
+///
+/// `cli` holds the settings given as flags and `from_file` the variables that
+/// [`ConfigFile::apply_to_env`] set, which are otherwise indistinguishable
+/// from real environment variables.
+pub fn effective_settings(cli: &[(&str, String)], from_file: &[String]) -> Vec<EffectiveSetting> {
+    SETTINGS
+        .iter()
+        .map(|setting| {
+            let (value, source) =
+                if let Some((_, value)) = cli.iter().find(|(name, _)| *name == setting.name) {
+                    (Some(value.clone()), SettingSource::Cli)
+                } else if let Ok(value) = env::var(setting.name) {
+                    let source = if from_file.iter().any(|name| name == setting.name) {
+                        SettingSource::ConfigFile
+                    } else {
+                        SettingSource::Env
+                    };
+                    (Some(value), source)
+                } else {
+                    (setting.default.map(str::to_string), SettingSource::Default)
+                };
+            EffectiveSetting {
+                name: setting.name,
+                value: value.map(|value| {
+                    if setting.secret && !value.is_empty() {
+                        "********".to_string()
+                    } else {
+                        value
+                    }
+                }),
+                source,
+            }
+        })
+        .collect()
+}
+
+/// Read `{prefix}_TIMEOUT_SECS`, `{prefix}_MAX_RETRIES` and
+/// `{prefix}_RETRY_BACKOFF_MS`, keeping `default` for unset values
+pub(crate) fn retry_policy_from_env(prefix: &str, default: RetryPolicy) -> RetryPolicy {
+    let var = |name: &str| env::var(format!("{}_{}", prefix, name)).ok();
+    RetryPolicy {
+        timeout_secs: var("TIMEOUT_SECS")
+            .and_then(|secs| secs.parse().ok())
+            .unwrap_or(default.timeout_secs),
+        max_retries: var("MAX_RETRIES")
+            .and_then(|retries| retries.parse().ok())
+            .unwrap_or(default.max_retries),
+        backoff_ms: var("RETRY_BACKOFF_MS")
+            .and_then(|ms| ms.parse().ok())
+            .unwrap_or(default.backoff_ms),
+    }
+}
+
+/// Non-empty value of an environment variable
+fn non_empty_var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.is_empty())
+}
+
 impl Config {
     /// Load configuration from environment variables
     pub fn from_env() -> Result<Self> {
+        // Credentials for an authenticating proxy in front of Ollama
+        let ollama_api_key = non_empty_var("OLLAMA_API_KEY");
+        let ollama_headers = parse_headers(&env::var("OLLAMA_HEADERS").unwrap_or_default())?;
+
         // Load embedding config
-        let embedding = EmbeddingConfig {
+        let provider: EmbeddingProvider = match env::var("EMBEDDING_PROVIDER") {
+            Ok(provider) => provider.parse()?,
+            Err(_) => Default::default(),
+        };
+        // An Ollama embedding backend sits behind the same proxy unless told otherwise;
+        // Ollama credentials are never sent to other providers
+        let uses_ollama = provider == EmbeddingProvider::Ollama;
+        let mut embedding = EmbeddingConfig {
+            provider,
             model: env::var("OLLAMA_EMBEDDING_MODEL")
                 .unwrap_or_else(|_| "nomic-embed-text".to_string()),
             host: env::var("OLLAMA_HOST").unwrap_or_else(|_| "localhost".to_string()),
@@ -38,10 +795,23 @@ impl Config {
                 .unwrap_or_else(|_| "11434".to_string())
                 .parse()
                 .unwrap_or(11434),
+            api_key: non_empty_var("EMBEDDING_API_KEY")
+                .or_else(|| ollama_api_key.clone().filter(|_| uses_ollama)),
+            headers: match env::var("EMBEDDING_HEADERS") {
+                Ok(headers) => parse_headers(&headers)?,
+                Err(_) if uses_ollama => ollama_headers.clone(),
+                Err(_) => Default::default(),
+            },
+            cache_dir: None,
+            retry: retry_policy_from_env("EMBEDDING", RetryPolicy::default()),
         };
 
         // Load LLM config
         let llm = LLMConfig {
+            backend: match env::var("LLM_BACKEND") {
+                Ok(backend) => backend.parse()?,
+                Err(_) => Default::default(),
+            },
             model: env::var("OLLAMA_LLM_MODEL").unwrap_or_else(|_| "mistral".to_string()),
             host: env::var("OLLAMA_HOST").unwrap_or_else(|_| "localhost".to_string()),
             port: env::var("OLLAMA_PORT")
@@ -56,6 +826,9 @@ impl Config {
                 .unwrap_or_else(|_| "0.9".to_string())
                 .parse()
                 .unwrap_or(0.9),
+            api_key: ollama_api_key,
+            headers: ollama_headers,
+            retry: retry_policy_from_env("OLLAMA", LLMConfig::default().retry),
         };
 
         // Load vector DB config
@@ -67,10 +840,25 @@ impl Config {
                 .unwrap_or_else(|_| "6334".to_string())
                 .parse()
                 .unwrap_or(6334),
+            // Unset means: detect from the embedding model at startup
             vector_size: env::var("QDRANT_VECTOR_SIZE")
-                .unwrap_or_else(|_| "384".to_string())
-                .parse()
-                .unwrap_or(384),
+                .ok()
+                .and_then(|size| size.parse().ok())
+                .unwrap_or(0),
+            recreate: env::var("QDRANT_RECREATE")
+                .map(|value| value == "true" || value == "1")
+                .unwrap_or(false),
+            upsert_batch_size: env::var("QDRANT_UPSERT_BATCH_SIZE")
+                .ok()
+                .and_then(|size| size.parse().ok())
+                .unwrap_or(DEFAULT_UPSERT_BATCH_SIZE),
+            api_key: env::var("QDRANT_API_KEY")
+                .ok()
+                .filter(|key| !key.is_empty()),
+            use_tls: env::var("QDRANT_USE_TLS")
+                .map(|value| value == "true" || value == "1")
+                .unwrap_or(false),
+            retry: retry_policy_from_env("QDRANT", RetryPolicy::default()),
         };
 
         // Load processing config
@@ -93,6 +881,14 @@ impl Config {
                 .unwrap_or_else(|_| "./vector_db".to_string()),
         };
 
+        // The embedding cache lives next to the vector DB unless configured;
+        // an empty EMBEDDING_CACHE_DIR disables it
+        embedding.cache_dir = match env::var("EMBEDDING_CACHE_DIR") {
+            Ok(dir) if dir.is_empty() => None,
+            Ok(dir) => Some(dir),
+            Err(_) => Some(format!("{}/embedding_cache", output.vector_db_path)),
+        };
+
         Ok(Self {
             embedding,
             llm,
@@ -110,21 +906,9 @@ mod tests {
     use std::env;
 
     fn clean_env() {
-        env::remove_var("OLLAMA_EMBEDDING_MODEL");
-        env::remove_var("OLLAMA_LLM_MODEL");
-        env::remove_var("OLLAMA_HOST");
-        env::remove_var("OLLAMA_PORT");
-        env::remove_var("OLLAMA_TEMPERATURE");
-        env::remove_var("OLLAMA_TOP_P");
-        env::remove_var("QDRANT_COLLECTION");
-        env::remove_var("QDRANT_HOST");
-        env::remove_var("QDRANT_PORT");
-        env::remove_var("QDRANT_VECTOR_SIZE");
-        env::remove_var("BATCH_SIZE");
-        env::remove_var("MAX_CONCURRENT_REQUESTS");
-        env::remove_var("LOG_LEVEL");
-        env::remove_var("OUTPUT_DIR");
-        env::remove_var("VECTOR_DB_PATH");
+        for setting in SETTINGS {
+            env::remove_var(setting.name);
+        }
     }
 
     #[test]
@@ -145,6 +929,15 @@ mod tests {
             config.vector_db.collection_name, "documents",
             "wrong default collection name"
         );
+        assert_eq!(
+            config.embedding.cache_dir.as_deref(),
+            Some("./vector_db/embedding_cache"),
+            "wrong default embedding cache dir"
+        );
+        assert_eq!(
+            config.llm.retry.timeout_secs, 300,
+            "wrong default llm timeout"
+        );
         assert_eq!(config.processing.batch_size, 32, "wrong default batch size");
         assert_eq!(
             config.output.output_dir, "./output",
@@ -152,6 +945,229 @@ mod tests {
         );
     }
 
+    #[test]
+    #[serial_test::serial]
+    fn test_ollama_credentials() {
+        clean_env();
+        let _guard = guard((), |_| clean_env());
+
+        env::set_var("OLLAMA_API_KEY", "proxy-token");
+        env::set_var("OLLAMA_HEADERS", "X-Team=docs");
+        let config = Config::from_env().unwrap();
+        assert_eq!(config.llm.api_key.as_deref(), Some("proxy-token"));
+        assert_eq!(config.llm.headers["X-Team"], "docs");
+        assert_eq!(config.embedding.api_key.as_deref(), Some("proxy-token"));
+        assert_eq!(config.embedding.headers["X-Team"], "docs");
+
+        // Other embedding providers never receive the Ollama credentials
+        env::set_var("EMBEDDING_PROVIDER", "openai");
+        let config = Config::from_env().unwrap();
+        assert_eq!(config.embedding.api_key, None);
+        assert!(config.embedding.headers.is_empty());
+
+        env::set_var("OLLAMA_HEADERS", "broken");
+        assert!(Config::from_env().is_err());
+    }
+
+    #[test]
+    fn test_llm_backend() {
+        use crate::external::LLMBackend;
+
+        clean_env();
+        let _guard = guard((), |_| clean_env());
+
+        assert_eq!(Config::from_env().unwrap().llm.backend, LLMBackend::Ollama);
+        env::set_var("LLM_BACKEND", "Mock");
+        assert_eq!(Config::from_env().unwrap().llm.backend, LLMBackend::Mock);
+        env::set_var("LLM_BACKEND", "gpt");
+        assert!(Config::from_env().is_err());
+    }
+
+    const PROFILES: &str = r#"
+        default_profile = "local"
+        output_dir = "./datasets"
+
+        [ollama]
+        llm_model = "mistral"
+
+        [profile.local]
+        ollama_host = "localhost"
+
+        [profile.gpu-server]
+        ollama_host = "gpu-01.internal"
+        ollama_timeout_secs = 900
+
+        [profile.gpu-server.ollama]
+        llm_model = "llama3:70b"
+    "#;
+
+    #[test]
+    fn test_config_file_profiles() {
+        let file = ConfigFile::parse(PROFILES).unwrap();
+        assert_eq!(
+            file.profile_names().collect::<Vec<_>>(),
+            ["gpu-server", "local"]
+        );
+
+        let local = file.settings(None).unwrap();
+        assert_eq!(local["OLLAMA_HOST"], "localhost");
+        assert_eq!(local["OLLAMA_LLM_MODEL"], "mistral");
+        assert_eq!(local["OUTPUT_DIR"], "./datasets");
+
+        let gpu = file.settings(Some("gpu-server")).unwrap();
+        assert_eq!(gpu["OLLAMA_HOST"], "gpu-01.internal");
+        assert_eq!(gpu["OLLAMA_LLM_MODEL"], "llama3:70b");
+        assert_eq!(gpu["OLLAMA_TIMEOUT_SECS"], "900");
+        assert_eq!(gpu["OUTPUT_DIR"], "./datasets");
+
+        let err = file.settings(Some("openai")).unwrap_err().to_string();
+        assert!(err.contains("available profiles: gpu-server, local"));
+    }
+
+    #[test]
+    fn test_invalid_config_file() {
+        assert!(ConfigFile::parse("profile = \"local\"").is_err());
+        assert!(ConfigFile::parse("[profile]\nlocal = 1").is_err());
+        assert!(ConfigFile::parse("ollama_headers = [\"X-Team=docs\"]").is_err());
+        assert!(ConfigFile::parse("ollama_host =").is_err());
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_config_file_yields_to_env() {
+        clean_env();
+        let _guard = guard((), |_| clean_env());
+
+        env::set_var("OLLAMA_LLM_MODEL", "from-env");
+        let file = ConfigFile::parse(PROFILES).unwrap();
+        let applied = file.apply_to_env(Some("gpu-server")).unwrap();
+        assert!(!applied.contains(&"OLLAMA_LLM_MODEL".to_string()));
+
+        let config = Config::from_env().unwrap();
+        assert_eq!(config.llm.model, "from-env");
+        assert_eq!(config.llm.host, "gpu-01.internal");
+        assert_eq!(config.llm.retry.timeout_secs, 900);
+        assert_eq!(config.output.output_dir, "./datasets");
+    }
+
+    /// Stand-in for the OS keyring that knows every entry except "missing"
+    fn fake_keyring(service: &str, entry: &str) -> Result<String> {
+        match entry {
+            "missing" => Err(anyhow!("no such entry")),
+            _ => Ok(format!("{}/{}", service, entry)),
+        }
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_config_file_secrets() {
+        clean_env();
+        let _guard = guard((), |_| {
+            clean_env();
+            env::remove_var("DOCS_TOKEN");
+        });
+        env::set_var("DOCS_TOKEN", "ghp_docs");
+
+        let file = ConfigFile::parse(
+            r#"
+            github_token = "${DOCS_TOKEN}"
+            ollama_headers = "X-Token=${DOCS_TOKEN},X-Literal=$${HOME}"
+            qdrant_api_key = { keyring = "qdrant" }
+            embedding_api_key = { keyring = "openai", service = "openai" }
+            "#,
+        )
+        .unwrap();
+        let settings = file.resolve(None, &fake_keyring, |_| true).unwrap();
+        assert_eq!(settings["GITHUB_TOKEN"], "ghp_docs");
+        assert_eq!(
+            settings["OLLAMA_HEADERS"],
+            "X-Token=ghp_docs,X-Literal=${HOME}"
+        );
+        assert_eq!(
+            settings["QDRANT_API_KEY"],
+            format!("{}/qdrant", KEYRING_SERVICE)
+        );
+        assert_eq!(settings["EMBEDDING_API_KEY"], "openai/openai");
+
+        let missing = ConfigFile::parse("github_token = \"${NOT_SET_ANYWHERE}\"").unwrap();
+        let err = missing.resolve(None, &fake_keyring, |_| true).unwrap_err();
+        assert!(format!("{:#}", err).contains("'NOT_SET_ANYWHERE' is not set"));
+
+        // Entries shadowed by the environment are never looked up
+        let file = ConfigFile::parse("qdrant_api_key = { keyring = \"missing\" }").unwrap();
+        assert!(file.apply_with(None, &fake_keyring).is_err());
+        env::set_var("QDRANT_API_KEY", "from-env");
+        assert!(file.apply_with(None, &fake_keyring).unwrap().is_empty());
+
+        assert!(ConfigFile::parse("qdrant_api_key = { keyring = 1 }").is_err());
+        assert!(ConfigFile::parse("qdrant_api_key = { keyring = \"q\", user = \"x\" }").is_err());
+        assert!(ConfigFile::parse("github_token = \"${UNTERMINATED\"")
+            .unwrap()
+            .settings(None)
+            .is_err());
+    }
+
+    #[test]
+    fn test_config_template_lists_every_setting() {
+        let template = config_template();
+        assert_eq!(ConfigFile::parse(&template).unwrap(), ConfigFile::default());
+
+        // Uncommenting every setting gives a valid file that sets all of them
+        let settings_part = template.split("# --- Secrets ---").next().unwrap();
+        let uncommented: String = settings_part
+            .lines()
+            .filter_map(|line| line.strip_prefix("# "))
+            .filter(|line| line.contains(" = "))
+            .map(|line| format!("{}\n", line))
+            .collect();
+        let settings = ConfigFile::parse(&uncommented)
+            .unwrap()
+            .settings(None)
+            .unwrap();
+        let names: Vec<&str> = SETTINGS.iter().map(|s| s.name).collect();
+        assert_eq!(settings.len(), names.len());
+        assert!(settings.keys().all(|key| names.contains(&key.as_str())));
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_setting_defaults_match_config() {
+        clean_env();
+        let _guard = guard((), |_| clean_env());
+
+        let defaults = serde_json::to_value(Config::from_env().unwrap()).unwrap();
+        for setting in SETTINGS {
+            if let Some(default) = setting.default {
+                env::set_var(setting.name, default);
+            }
+        }
+        let explicit = serde_json::to_value(Config::from_env().unwrap()).unwrap();
+        assert_eq!(defaults, explicit);
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_effective_settings() {
+        clean_env();
+        let _guard = guard((), |_| clean_env());
+
+        env::set_var("OLLAMA_HOST", "from-env");
+        env::set_var("QDRANT_HOST", "from-file");
+        env::set_var("QDRANT_API_KEY", "secret-key");
+        let cli = [("OLLAMA_MODEL", "cli-model".to_string())];
+        let settings = effective_settings(&cli, &["QDRANT_HOST".to_string()]);
+        let get = |name: &str| settings.iter().find(|s| s.name == name).unwrap().clone();
+
+        assert_eq!(get("OLLAMA_MODEL").value.as_deref(), Some("cli-model"));
+        assert_eq!(get("OLLAMA_MODEL").source, SettingSource::Cli);
+        assert_eq!(get("OLLAMA_HOST").source, SettingSource::Env);
+        assert_eq!(get("QDRANT_HOST").source, SettingSource::ConfigFile);
+        assert_eq!(get("QDRANT_API_KEY").value.as_deref(), Some("********"));
+        assert_eq!(get("BATCH_SIZE").value.as_deref(), Some("32"));
+        assert_eq!(get("BATCH_SIZE").source, SettingSource::Default);
+        assert_eq!(get("OLLAMA_API_KEY").value, None);
+    }
+
     #[test]
     #[serial_test::serial]
     fn test_custom_config() {
@@ -159,9 +1175,13 @@ mod tests {
         let _guard = guard((), |_| clean_env());
 
         // Set custom environment variables
+        env::set_var("EMBEDDING_PROVIDER", "openai");
+        env::set_var("EMBEDDING_CACHE_DIR", "");
         env::set_var("OLLAMA_EMBEDDING_MODEL", "custom-embed");
         env::set_var("OLLAMA_LLM_MODEL", "custom-llm");
         env::set_var("QDRANT_COLLECTION", "custom-collection");
+        env::set_var("QDRANT_TIMEOUT_SECS", "5");
+        env::set_var("QDRANT_MAX_RETRIES", "0");
         env::set_var("BATCH_SIZE", "64");
         env::set_var("OUTPUT_DIR", "/custom/output");
 
@@ -173,11 +1193,29 @@ mod tests {
             config.embedding.model, "custom-embed",
             "embedding model mismatch"
         );
+        assert_eq!(
+            config.embedding.provider,
+            crate::external::EmbeddingProvider::OpenAI,
+            "embedding provider mismatch"
+        );
+        assert_eq!(
+            config.embedding.cache_dir, None,
+            "embedding cache not disabled"
+        );
         assert_eq!(config.llm.model, "custom-llm", "llm model mismatch");
         assert_eq!(
             config.vector_db.collection_name, "custom-collection",
             "collection name mismatch"
         );
+        assert_eq!(
+            config.vector_db.retry,
+            RetryPolicy {
+                timeout_secs: 5,
+                max_retries: 0,
+                ..Default::default()
+            },
+            "vector db retry policy mismatch"
+        );
         assert_eq!(config.processing.batch_size, 64, "batch size mismatch");
         assert_eq!(
             config.output.output_dir, "/custom/output",
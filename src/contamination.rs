@@ -0,0 +1,394 @@
+//! Contamination checks: finding generated questions that overlap the
+//! questions of public benchmarks (MMLU, GSM8K, ...), which would inflate a
+//! model's benchmark scores if trained on.
+//!
+//! Overlap is found by shared word n-grams, and optionally by the cosine
+//! similarity of embeddings to catch paraphrases.
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+use crate::external::{embed_all, Embedder};
+use crate::graph::similarity::cosine_similarity;
+use crate::processor::ProcessedItem;
+
+/// Words per n-gram; long enough that sharing one is rarely chance
+pub const DEFAULT_NGRAM: usize = 8;
+
+/// Cosine similarity from which an embedding match counts as contamination
+pub const DEFAULT_SIMILARITY: f32 = 0.9;
+
+/// Fields holding the question of a benchmark record, in order of preference
+const QUESTION_FIELDS: [&str; 6] = [
+    "question",
+    "prompt",
+    "problem",
+    "input",
+    "instruction",
+    "text",
+];
+
+/// One question of a benchmark file
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchmarkItem {
+    /// Name of the benchmark file
+    pub benchmark: String,
+    /// 1-based line in the file
+    pub line: usize,
+    pub text: String,
+}
+
+/// Read the questions of a benchmark JSONL file
+///
+/// Each line's question is taken from the first of `question`, `prompt`,
+/// `problem`, `input`, `instruction` or `text` it has. Lines without any
+/// are skipped.
+pub fn load_benchmark(path: &Path) -> Result<Vec<BenchmarkItem>> {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string());
+    let file = fs::File::open(path).with_context(|| format!("Cannot open benchmark {:?}", path))?;
+    let mut items = Vec::new();
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let value: Value = serde_json::from_str(&line)
+            .map_err(|e| anyhow!("{}:{} is not JSON: {}", name, number + 1, e))?;
+        let text = QUESTION_FIELDS
+            .iter()
+            .find_map(|field| value[field].as_str())
+            .filter(|text| !text.trim().is_empty());
+        if let Some(text) = text {
+            items.push(BenchmarkItem {
+                benchmark: name.clone(),
+                line: number + 1,
+                text: text.to_string(),
+            });
+        }
+    }
+    Ok(items)
+}
+
+/// Lowercased words of a text, without punctuation
+fn words(text: &str) -> Vec<String> {
+    text.split_whitespace()
+        .map(|word| {
+            word.trim_matches(|c: char| !c.is_alphanumeric())
+                .to_lowercase()
+        })
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+/// Word n-grams of a text; a text shorter than `n` words is one n-gram, so
+/// short questions are still matched when repeated verbatim
+fn ngrams(text: &str, n: usize) -> HashSet<String> {
+    let words = words(text);
+    if words.len() < n {
+        return HashSet::from([words.join(" ")]);
+    }
+    words.windows(n).map(|gram| gram.join(" ")).collect()
+}
+
+/// How a record was found to overlap a benchmark question
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Method {
+    Ngram,
+    Embedding,
+}
+
+/// A record overlapping a benchmark question
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Overlap {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<Uuid>,
+    pub question: String,
+    pub benchmark: String,
+    pub line: usize,
+    pub benchmark_question: String,
+    pub method: Method,
+    /// Share of the record's n-grams found in the benchmark question, or
+    /// the cosine similarity of their embeddings
+    pub score: f64,
+}
+
+/// Benchmark questions indexed by their n-grams
+pub struct NgramIndex<'a> {
+    n: usize,
+    items: &'a [BenchmarkItem],
+    grams: HashMap<String, Vec<usize>>,
+}
+
+impl<'a> NgramIndex<'a> {
+    pub fn new(items: &'a [BenchmarkItem], n: usize) -> Self {
+        let n = n.max(1);
+        let mut grams: HashMap<String, Vec<usize>> = HashMap::new();
+        for (index, item) in items.iter().enumerate() {
+            for gram in ngrams(&item.text, n) {
+                grams.entry(gram).or_default().push(index);
+            }
+        }
+        Self { n, items, grams }
+    }
+
+    /// The benchmark question sharing the most n-grams with `text`, and
+    /// the share of the text's n-grams it has
+    pub fn best_match(&self, text: &str) -> Option<(&'a BenchmarkItem, f64)> {
+        let grams = ngrams(text, self.n);
+        let mut shared: HashMap<usize, usize> = HashMap::new();
+        for gram in &grams {
+            for &index in self.grams.get(gram).into_iter().flatten() {
+                *shared.entry(index).or_default() += 1;
+            }
+        }
+        shared
+            .into_iter()
+            .max_by_key(|&(index, count)| (count, std::cmp::Reverse(index)))
+            .map(|(index, count)| (&self.items[index], count as f64 / grams.len() as f64))
+    }
+}
+
+/// Checks records against benchmark questions
+pub struct ContaminationCheck<'a> {
+    benchmarks: &'a [BenchmarkItem],
+    ngrams: NgramIndex<'a>,
+    /// Benchmark embeddings and the similarity a match needs
+    embeddings: Option<(&'a dyn Embedder, Vec<Vec<f32>>, f32)>,
+}
+
+impl<'a> ContaminationCheck<'a> {
+    pub fn new(benchmarks: &'a [BenchmarkItem], n: usize) -> Self {
+        Self {
+            benchmarks,
+            ngrams: NgramIndex::new(benchmarks, n),
+            embeddings: None,
+        }
+    }
+
+    /// Also match records whose question embedding is at least `similarity`
+    /// close to a benchmark question's; embeds every benchmark question
+    pub async fn with_embeddings(
+        mut self,
+        embedder: &'a dyn Embedder,
+        similarity: f32,
+    ) -> Result<Self> {
+        let texts: Vec<String> = self.benchmarks.iter().map(|b| b.text.clone()).collect();
+        let embeddings = embed_all(embedder, &texts).await?;
+        self.embeddings = Some((embedder, embeddings, similarity));
+        Ok(self)
+    }
+
+    /// Records of `items` that overlap a benchmark question, in dataset order
+    ///
+    /// An n-gram match is reported in preference to an embedding match.
+    pub async fn check(&self, items: &[ProcessedItem]) -> Result<Vec<(usize, Overlap)>> {
+        let overlap = |item: &ProcessedItem, benchmark: &BenchmarkItem, method, score| Overlap {
+            id: item.id,
+            question: item.question.clone(),
+            benchmark: benchmark.benchmark.clone(),
+            line: benchmark.line,
+            benchmark_question: benchmark.text.clone(),
+            method,
+            score,
+        };
+        let mut found = BTreeMap::new();
+        for (index, item) in items.iter().enumerate() {
+            if let Some((benchmark, score)) = self.ngrams.best_match(&item.question) {
+                found.insert(index, overlap(item, benchmark, Method::Ngram, score));
+            }
+        }
+
+        if let Some((embedder, embeddings, similarity)) = &self.embeddings {
+            let pending: Vec<usize> = (0..items.len())
+                .filter(|index| !found.contains_key(index))
+                .collect();
+            let questions: Vec<String> = pending
+                .iter()
+                .map(|&index| items[index].question.clone())
+                .collect();
+            let vectors = embed_all(*embedder, &questions).await?;
+            for (&index, vector) in pending.iter().zip(&vectors) {
+                let closest = embeddings
+                    .iter()
+                    .map(|embedding| cosine_similarity(vector, embedding))
+                    .enumerate()
+                    .max_by(|a, b| a.1.total_cmp(&b.1));
+                if let Some((benchmark, score)) = closest.filter(|(_, s)| s >= similarity) {
+                    let item = &items[index];
+                    let benchmark = &self.benchmarks[benchmark];
+                    found.insert(
+                        index,
+                        overlap(item, benchmark, Method::Embedding, score as f64),
+                    );
+                }
+            }
+        }
+        Ok(found.into_iter().collect())
+    }
+}
+
+/// Overlap of one dataset with the benchmarks
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ContaminationReport {
+    pub records: usize,
+    pub contaminated: usize,
+    /// Contaminated records per benchmark file
+    pub benchmarks: BTreeMap<String, usize>,
+    pub overlaps: Vec<Overlap>,
+}
+
+impl ContaminationReport {
+    pub fn new(records: usize, overlaps: Vec<Overlap>) -> Self {
+        let mut benchmarks = BTreeMap::new();
+        for overlap in &overlaps {
+            *benchmarks.entry(overlap.benchmark.clone()).or_default() += 1;
+        }
+        Self {
+            records,
+            contaminated: overlaps.len(),
+            benchmarks,
+            overlaps,
+        }
+    }
+}
+
+impl fmt::Display for ContaminationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Contaminated: {} of {} records",
+            self.contaminated, self.records
+        )?;
+        for (benchmark, count) in &self.benchmarks {
+            write!(f, "\n  {}: {}", benchmark, count)?;
+        }
+        Ok(())
+    }
+}
+
+/// Where the contamination report of a dataset is written:
+/// `NAME.contamination.json`
+pub fn report_path(path: &Path) -> PathBuf {
+    sibling(path, "contamination.json")
+}
+
+/// Where a dataset without its contaminated records is written (`--remove`):
+/// `NAME.decontaminated.jsonl`
+pub fn decontaminated_path(path: &Path) -> PathBuf {
+    sibling(path, "decontaminated.jsonl")
+}
+
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("dataset");
+    path.with_file_name(format!("{}.{}", stem, suffix))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::external::embedding::MockEmbedder;
+
+    fn benchmark(lines: &[&str]) -> Vec<BenchmarkItem> {
+        lines
+            .iter()
+            .enumerate()
+            .map(|(i, text)| BenchmarkItem {
+                benchmark: "gsm8k.jsonl".to_string(),
+                line: i + 1,
+                text: text.to_string(),
+            })
+            .collect()
+    }
+
+    fn item(question: &str) -> ProcessedItem {
+        ProcessedItem::new(question, "A")
+    }
+
+    #[test]
+    fn test_load_benchmark() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mmlu.jsonl");
+        fs::write(
+            &path,
+            "{\"question\": \"What is 2+2?\", \"choices\": [\"3\", \"4\"]}\n\n{\"prompt\": \"Name a prime.\"}\n{\"answer\": \"no question\"}\n",
+        )
+        .unwrap();
+        let items = load_benchmark(&path).unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!((items[1].line, &*items[1].text), (3, "Name a prime."));
+        assert_eq!(items[0].benchmark, "mmlu.jsonl");
+
+        fs::write(&path, "not json\n").unwrap();
+        assert!(load_benchmark(&path).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_ngram_overlap() {
+        let benchmarks = benchmark(&[
+            "Natalia sold clips to 48 of her friends in April, and then she sold half as many clips in May.",
+            "What is the capital of France?",
+        ]);
+        let check = ContaminationCheck::new(&benchmarks, 8);
+        let items = [
+            item(
+                "How many clips did Natalia sell if she sold clips to 48 of her friends in April?",
+            ),
+            item("what is the capital of France"),
+            item("How is TLS enabled in config.toml?"),
+        ];
+        let found = check.check(&items).await.unwrap();
+        let lines: Vec<(usize, usize)> = found.iter().map(|(i, o)| (*i, o.line)).collect();
+        assert_eq!(lines, [(0, 1), (1, 2)]);
+        assert_eq!(found[1].1.score, 1.0);
+        assert_eq!(found[0].1.method, Method::Ngram);
+    }
+
+    #[tokio::test]
+    async fn test_embedding_overlap() {
+        let mut embedder = MockEmbedder::new();
+        embedder.expect_embed_batch().returning(|texts| {
+            Ok(texts
+                .iter()
+                .map(
+                    |text| match text.contains("France") || text.contains("French") {
+                        true => vec![1.0, 0.1],
+                        false => vec![0.0, 1.0],
+                    },
+                )
+                .collect())
+        });
+        let benchmarks = benchmark(&["What is the capital of France?"]);
+        let check = ContaminationCheck::new(&benchmarks, 8)
+            .with_embeddings(&embedder, 0.9)
+            .await
+            .unwrap();
+        let items = [
+            item("Which city is the French capital?"),
+            item("How is TLS enabled?"),
+        ];
+        let found = check.check(&items).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].1.method, Method::Embedding);
+
+        let report = ContaminationReport::new(items.len(), vec![found[0].1.clone()]);
+        assert_eq!(report.benchmarks["gsm8k.jsonl"], 1);
+        assert_eq!(
+            decontaminated_path(Path::new("out/all_qa.jsonl")),
+            Path::new("out/all_qa.decontaminated.jsonl")
+        );
+    }
+}
@@ -0,0 +1,286 @@
+//! Question coverage of the source sections: which sections of the input
+//! files records were generated from, which got none, and which got far more
+//! than their share of their document's questions.
+//!
+//! Records are mapped back to sections by their lineage, so datasets from
+//! before lineage was recorded can't be analyzed.
+
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+use crate::changelog;
+use crate::density::{DocumentSize, QuestionDensity};
+use crate::lineage::Lineage;
+use crate::sections::{SectionReader, SpannedSection};
+
+/// File in the output directory with the coverage of the last run
+pub const COVERAGE_FILE: &str = "coverage.json";
+
+/// A section with more than this many times its share of questions is
+/// over-represented
+pub const OVER_REPRESENTED_FACTOR: f64 = 2.0;
+
+/// Sections of each kind listed when printing a report
+const LISTED_SECTIONS: usize = 10;
+
+/// An input file split into sections as generation splits it
+#[derive(Debug, Clone)]
+pub struct DocumentSections {
+    /// The file as records name it: its path within the output directory
+    pub document: String,
+    pub sections: Vec<SpannedSection>,
+}
+
+impl DocumentSections {
+    /// Read the sections of a file; a changelog has one section per version
+    pub fn read(path: &Path, document: String) -> Result<Self> {
+        let sections = match changelog::read_releases(path)? {
+            Some(releases) => changelog::sections(&releases),
+            None => SectionReader::open(path)?
+                .spanned()
+                .collect::<Result<_>>()?,
+        };
+        Ok(Self { document, sections })
+    }
+}
+
+/// The questions generated from one section
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SectionCoverage {
+    pub document: String,
+    /// Position of the section in its document, from 1
+    pub number: usize,
+    /// Byte range of the section in the document
+    pub span: Range<usize>,
+    /// First line of the section
+    pub heading: String,
+    pub words: usize,
+    /// The section's share of its document's questions under the density
+    /// strategy
+    pub expected: usize,
+    pub questions: usize,
+}
+
+impl SectionCoverage {
+    pub fn is_uncovered(&self) -> bool {
+        self.questions == 0
+    }
+
+    pub fn is_over_represented(&self) -> bool {
+        self.questions as f64 > self.expected as f64 * OVER_REPRESENTED_FACTOR
+    }
+}
+
+impl fmt::Display for SectionCoverage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} section {} ({}): {} questions, {} expected",
+            self.document, self.number, self.heading, self.questions, self.expected
+        )
+    }
+}
+
+/// How the records of a dataset cover the sections of its documents
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CoverageReport {
+    /// Every section, in document order
+    pub sections: Vec<SectionCoverage>,
+    /// Records tracing to no single section: without lineage, from a
+    /// document not analyzed, or spanning a whole file like glossary
+    /// definitions
+    pub unmatched: usize,
+}
+
+impl CoverageReport {
+    /// Map records, by their lineage, to the sections of `documents`, each
+    /// expected to have its share of the questions `density` gives its
+    /// document
+    ///
+    /// A record counts for the section its span lies within. `None` stands
+    /// for a record without lineage.
+    pub fn new<'a>(
+        documents: &[DocumentSections],
+        density: QuestionDensity,
+        lineages: impl IntoIterator<Item = Option<&'a Lineage>>,
+    ) -> Self {
+        let mut sections = Vec::new();
+        let mut first_section = HashMap::new();
+        for document in documents {
+            first_section.insert(document.document.as_str(), sections.len());
+            let sizes: Vec<DocumentSize> = document
+                .sections
+                .iter()
+                .map(|section| DocumentSize::of_section(&section.text))
+                .collect();
+            let mut total = DocumentSize::default();
+            for size in &sizes {
+                total += *size;
+            }
+            let (_, total_questions, _) = density.targets(&total);
+            for (index, (section, size)) in document.sections.iter().zip(sizes).enumerate() {
+                sections.push(SectionCoverage {
+                    document: document.document.clone(),
+                    number: index + 1,
+                    span: section.span.clone(),
+                    heading: section
+                        .text
+                        .lines()
+                        .next()
+                        .unwrap_or_default()
+                        .trim()
+                        .to_string(),
+                    words: size.words,
+                    expected: density.section_share(&size, &total, total_questions),
+                    questions: 0,
+                });
+            }
+        }
+
+        let mut unmatched = 0;
+        for lineage in lineages {
+            let section = lineage.and_then(|lineage| {
+                let first = *first_section.get(lineage.document.as_str())?;
+                sections[first..]
+                    .iter()
+                    .take_while(|section| section.document == lineage.document)
+                    .position(|section| {
+                        section.span.start <= lineage.span.start
+                            && lineage.span.end <= section.span.end
+                    })
+                    .map(|position| first + position)
+            });
+            match section {
+                Some(index) => sections[index].questions += 1,
+                None => unmatched += 1,
+            }
+        }
+        Self {
+            sections,
+            unmatched,
+        }
+    }
+
+    /// Sections no record was generated from
+    pub fn uncovered(&self) -> impl Iterator<Item = &SectionCoverage> {
+        self.sections
+            .iter()
+            .filter(|section| section.is_uncovered())
+    }
+
+    /// Sections with more than [`OVER_REPRESENTED_FACTOR`] times their share
+    pub fn over_represented(&self) -> impl Iterator<Item = &SectionCoverage> {
+        self.sections
+            .iter()
+            .filter(|section| section.is_over_represented())
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for CoverageReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let uncovered: Vec<_> = self.uncovered().collect();
+        let over: Vec<_> = self.over_represented().collect();
+        write!(
+            f,
+            "{} of {} sections have questions; {} have none, {} have over {}x their share",
+            self.sections.len() - uncovered.len(),
+            self.sections.len(),
+            uncovered.len(),
+            over.len(),
+            OVER_REPRESENTED_FACTOR
+        )?;
+        if self.unmatched > 0 {
+            write!(f, "\n{} records trace to no single section", self.unmatched)?;
+        }
+        for (title, listed) in [("Without questions", uncovered), ("Over-represented", over)] {
+            if listed.is_empty() {
+                continue;
+            }
+            write!(f, "\n{}:", title)?;
+            for section in listed.iter().take(LISTED_SECTIONS) {
+                write!(f, "\n  {}", section)?;
+            }
+            if listed.len() > LISTED_SECTIONS {
+                write!(f, "\n  ... and {} more", listed.len() - LISTED_SECTIONS)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(name: &str, text: &str) -> DocumentSections {
+        DocumentSections {
+            document: name.to_string(),
+            sections: SectionReader::new(text.as_bytes())
+                .spanned()
+                .collect::<Result<_>>()
+                .unwrap(),
+        }
+    }
+
+    fn lineage(document: &str, span: Range<usize>) -> Lineage {
+        Lineage {
+            document: document.to_string(),
+            span,
+            nodes: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_records_are_mapped_to_their_sections() {
+        let text = format!("# One\n{}\n# Two\nShort.\n", "word ".repeat(40));
+        let documents = [document("guide.md", &text)];
+        let one = documents[0].sections[0].span.clone();
+        let records: Vec<Lineage> = std::iter::repeat_n(lineage("guide.md", one), 3)
+            .chain([
+                lineage("other.md", 0..4),
+                lineage("guide.md", 0..text.len()),
+            ])
+            .collect();
+        let lineages = records.iter().map(Some).chain([None]);
+        let report = CoverageReport::new(&documents, QuestionDensity::default(), lineages);
+
+        assert_eq!(report.sections.len(), 2);
+        assert_eq!(report.sections[0].questions, 3);
+        assert_eq!(report.sections[1].heading, "# Two");
+        assert_eq!(report.unmatched, 3);
+        let uncovered: Vec<usize> = report.uncovered().map(|s| s.number).collect();
+        assert_eq!(uncovered, [2]);
+        assert_eq!(report.over_represented().count(), 0);
+        assert!(report
+            .to_string()
+            .starts_with("1 of 2 sections have questions"));
+    }
+
+    #[test]
+    fn test_over_represented_sections() {
+        let documents = [document(
+            "a.md",
+            "# A\nOne two three.\n# B\nFour five six.\n",
+        )];
+        let span = documents[0].sections[1].span.clone();
+        let records = vec![lineage("a.md", span); 9];
+        let report = CoverageReport::new(
+            &documents,
+            QuestionDensity::default(),
+            records.iter().map(Some),
+        );
+        let over: Vec<usize> = report.over_represented().map(|s| s.number).collect();
+        assert_eq!(over, [2]);
+        assert!(report.sections[1].expected < 9);
+    }
+}
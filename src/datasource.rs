@@ -1,15 +1,64 @@
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
+use futures::{stream, StreamExt};
 use regex::Regex;
 use reqwest::Client;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use tokio::sync::OnceCell;
 use url::Url;
 use walkdir::WalkDir;
 
+use crate::code_docs::{self, Language};
+use crate::config::retry_policy_from_env;
+use crate::external::{http_client, status_error, RetryPolicy};
+
+/// Where a source's content came from, recorded in the run manifest
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceProvenance {
+    pub kind: String,
+    pub location: String,
+    /// Commit, tag, or other revision the content was taken from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revision: Option<String>,
+    /// Files collected from the source, relative to the output directory
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<String>,
+}
+
+/// File in the output directory listing the sources collected into it
+pub const SOURCES_FILE: &str = "sources.json";
+
+/// Sources previously collected into `output_dir`
+pub fn recorded_sources(output_dir: &Path) -> Result<Vec<SourceProvenance>> {
+    let path = output_dir.join(SOURCES_FILE);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+}
+
+/// Add `sources` to the list kept in `output_dir`, replacing earlier
+/// records of the same location
+pub fn record_sources(output_dir: &Path, sources: &[SourceProvenance]) -> Result<()> {
+    let mut recorded = recorded_sources(output_dir)?;
+    recorded.retain(|old| !sources.iter().any(|new| new.location == old.location));
+    recorded.extend(sources.iter().cloned());
+    std::fs::write(
+        output_dir.join(SOURCES_FILE),
+        serde_json::to_string_pretty(&recorded)?,
+    )?;
+    Ok(())
+}
+
 #[async_trait]
 pub trait DataSource {
     async fn collect(&self, output_dir: &Path) -> Result<Vec<PathBuf>>;
+
+    /// Describe the origin of this source for reproducibility
+    async fn provenance(&self) -> SourceProvenance;
 }
 
 pub struct UrlSource {
@@ -34,14 +83,23 @@ impl DataSource for UrlSource {
         let filename = self
             .url
             .path_segments()
-            .and_then(|segments| segments.last())
+            .and_then(|mut segments| segments.next_back())
             .unwrap_or("downloaded_content.txt");
 
         let output_path = output_dir.join(filename);
-        std::fs::write(&output_path, content)?;
+        tokio::fs::write(&output_path, content).await?;
 
         Ok(vec![output_path])
     }
+
+    async fn provenance(&self) -> SourceProvenance {
+        SourceProvenance {
+            kind: "url".to_string(),
+            location: self.url.to_string(),
+            revision: None,
+            files: Vec::new(),
+        }
+    }
 }
 
 pub struct LocalSource {
@@ -54,25 +112,25 @@ impl LocalSource {
             path: path.as_ref().to_owned(),
         }
     }
-}
 
-#[async_trait]
-impl DataSource for LocalSource {
-    async fn collect(&self, output_dir: &Path) -> Result<Vec<PathBuf>> {
+    fn copy_into(path: &Path, output_dir: &Path) -> Result<Vec<PathBuf>> {
         let mut collected = Vec::new();
 
-        if self.path.is_file() {
-            let filename = self
-                .path
+        if path.is_file() {
+            let filename = path
                 .file_name()
                 .ok_or_else(|| anyhow!("Invalid filename"))?;
             let dest_path = output_dir.join(filename);
-            std::fs::copy(&self.path, &dest_path)?;
+            std::fs::copy(path, &dest_path)?;
             collected.push(dest_path);
-        } else if self.path.is_dir() {
-            for entry in WalkDir::new(&self.path).into_iter().filter_map(|e| e.ok()) {
+        } else if path.is_dir() {
+            for entry in WalkDir::new(path)
+                .sort_by_file_name()
+                .into_iter()
+                .filter_map(|e| e.ok())
+            {
                 if entry.file_type().is_file() {
-                    let relative_path = entry.path().strip_prefix(&self.path)?;
+                    let relative_path = entry.path().strip_prefix(path)?;
                     let dest_path = output_dir.join(relative_path);
                     if let Some(parent) = dest_path.parent() {
                         std::fs::create_dir_all(parent)?;
@@ -87,6 +145,181 @@ impl DataSource for LocalSource {
     }
 }
 
+#[async_trait]
+impl DataSource for LocalSource {
+    async fn collect(&self, output_dir: &Path) -> Result<Vec<PathBuf>> {
+        // Walking and copying a directory tree is blocking work
+        let (path, output_dir) = (self.path.clone(), output_dir.to_path_buf());
+        tokio::task::spawn_blocking(move || Self::copy_into(&path, &output_dir)).await?
+    }
+
+    async fn provenance(&self) -> SourceProvenance {
+        let location = self
+            .path
+            .canonicalize()
+            .unwrap_or_else(|_| self.path.clone());
+        SourceProvenance {
+            kind: "local".to_string(),
+            location: location.display().to_string(),
+            revision: None,
+            files: Vec::new(),
+        }
+    }
+}
+
+/// Directories of dependencies, build output and tooling that are never
+/// walked for documentation comments
+const SKIPPED_CODE_DIRS: [&str; 8] = [
+    "target",
+    "node_modules",
+    "dist",
+    "build",
+    "vendor",
+    "venv",
+    "__pycache__",
+    "site-packages",
+];
+
+/// Source code whose documentation comments are collected as markdown, one
+/// `<file>.md` per source file with any documented items
+pub struct CodeSource {
+    path: PathBuf,
+}
+
+impl CodeSource {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_owned(),
+        }
+    }
+
+    fn is_skipped(entry: &walkdir::DirEntry) -> bool {
+        let name = entry.file_name().to_string_lossy();
+        entry.depth() > 0
+            && entry.file_type().is_dir()
+            && (name.starts_with('.') || SKIPPED_CODE_DIRS.contains(&name.as_ref()))
+    }
+
+    fn extract_into(path: &Path, output_dir: &Path) -> Result<Vec<PathBuf>> {
+        if !path.exists() {
+            return Err(anyhow!("'{}' does not exist", path.display()));
+        }
+        // Labels are relative to the directory walked, or the file's name
+        let root = if path.is_file() {
+            path.parent().unwrap_or(Path::new(""))
+        } else {
+            path
+        };
+
+        let mut collected = Vec::new();
+        for entry in WalkDir::new(path)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_entry(|entry| !Self::is_skipped(entry))
+            .filter_map(|e| e.ok())
+        {
+            let Some(language) = Language::of(entry.path()) else {
+                continue;
+            };
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            // Files that are not UTF-8 have no documentation worth reading
+            let Ok(source) = std::fs::read_to_string(entry.path()) else {
+                continue;
+            };
+            let docs = code_docs::extract(language, &source);
+            if docs.is_empty() {
+                continue;
+            }
+
+            let relative_path = entry.path().strip_prefix(root)?;
+            let label = relative_path.to_string_lossy().replace('\\', "/");
+            let mut dest_name = relative_path.as_os_str().to_owned();
+            dest_name.push(".md");
+            let dest_path = output_dir.join(dest_name);
+            if let Some(parent) = dest_path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(&dest_path, docs.to_markdown(&label, language))?;
+            collected.push(dest_path);
+        }
+
+        Ok(collected)
+    }
+}
+
+#[async_trait]
+impl DataSource for CodeSource {
+    async fn collect(&self, output_dir: &Path) -> Result<Vec<PathBuf>> {
+        let (path, output_dir) = (self.path.clone(), output_dir.to_path_buf());
+        tokio::task::spawn_blocking(move || Self::extract_into(&path, &output_dir)).await?
+    }
+
+    async fn provenance(&self) -> SourceProvenance {
+        let location = self
+            .path
+            .canonicalize()
+            .unwrap_or_else(|_| self.path.clone());
+        SourceProvenance {
+            kind: "code".to_string(),
+            location: location.display().to_string(),
+            revision: None,
+            files: Vec::new(),
+        }
+    }
+}
+
+/// Client for GitHub requests, authenticated with `GITHUB_TOKEN` when it is set
+fn github_client() -> Result<Client> {
+    let token = std::env::var("GITHUB_TOKEN")
+        .ok()
+        .filter(|token| !token.is_empty());
+    http_client(token.as_deref(), &Default::default())
+}
+
+/// Owner, repository, branch and path named by a GitHub URL
+///
+/// Accepts a bare repository (`https://github.com/OWNER/REPO`), a directory
+/// (`.../tree/BRANCH/PATH`) or a file (`.../blob/BRANCH/PATH`). Branches
+/// containing `/` can't be told apart from the path; pass them separately.
+fn parse_github_url(url: &str) -> Result<(String, String, Option<String>, String)> {
+    let parsed = Url::parse(url).map_err(|e| anyhow!("Invalid GitHub URL '{}': {}", url, e))?;
+    if !matches!(parsed.host_str(), Some("github.com" | "www.github.com")) {
+        return Err(anyhow!("'{}' is not a github.com URL", url));
+    }
+    let segments: Vec<&str> = parsed
+        .path_segments()
+        .map(|segments| segments.filter(|segment| !segment.is_empty()).collect())
+        .unwrap_or_default();
+    match segments.as_slice() {
+        [owner, repo] => Ok((
+            owner.to_string(),
+            repo.trim_end_matches(".git").to_string(),
+            None,
+            String::new(),
+        )),
+        [owner, repo, "tree" | "blob", branch, path @ ..] => Ok((
+            owner.to_string(),
+            repo.to_string(),
+            Some(branch.to_string()),
+            path.join("/"),
+        )),
+        _ => Err(anyhow!(
+            "Unsupported GitHub URL '{}' (expected https://github.com/OWNER/REPO, optionally followed by /tree/BRANCH/PATH or /blob/BRANCH/PATH)",
+            url
+        )),
+    }
+}
+
+/// What the contents API returns: a directory listing, or a single file
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum GithubApiListing {
+    Directory(Vec<GithubApiContent>),
+    File(GithubApiContent),
+}
+
 #[derive(Debug, Deserialize)]
 struct GithubApiContent {
     name: String,
@@ -96,34 +329,102 @@ struct GithubApiContent {
     download_url: Option<String>,
 }
 
+/// Files downloaded at once from a GitHub directory, unless
+/// `GITHUB_DOWNLOAD_CONCURRENCY` says otherwise
+pub const DEFAULT_DOWNLOAD_CONCURRENCY: usize = 8;
+
 pub struct GitHubSource {
     owner: String,
     repo: String,
-    branch: String,
+    /// The repository's default branch when unset
+    branch: Option<String>,
     path: String,
+    concurrency: usize,
+    retry: RetryPolicy,
+    /// Commit the reference pointed to when first resolved; files are
+    /// downloaded from it and the manifest records it, so both agree even
+    /// if the branch moves during the run
+    commit: OnceCell<String>,
 }
 
 impl GitHubSource {
-    pub fn new(url: &str, _branch: Option<String>, _path: Option<String>) -> Self {
-        let re = Regex::new(r"https://github\.com/([^/]+)/([^/]+)/tree/([^/]+)/(.*)").unwrap();
-        let caps = re.captures(url).expect("Invalid GitHub URL format");
+    /// Source for a GitHub repository, directory or file URL; `branch` and
+    /// `path` override the ones in the URL
+    pub fn new(url: &str, branch: Option<String>, path: Option<String>) -> Result<Self> {
+        let (owner, repo, url_branch, url_path) = parse_github_url(url)?;
 
-        Self {
-            owner: caps[1].to_string(),
-            repo: caps[2].to_string(),
-            branch: caps[3].to_string(),
-            path: caps[4].to_string(),
+        Ok(Self {
+            owner,
+            repo,
+            branch: branch.or(url_branch),
+            path: path.unwrap_or(url_path).trim_matches('/').to_string(),
+            concurrency: std::env::var("GITHUB_DOWNLOAD_CONCURRENCY")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(DEFAULT_DOWNLOAD_CONCURRENCY),
+            retry: retry_policy_from_env("GITHUB", RetryPolicy::default()),
+            commit: OnceCell::new(),
+        })
+    }
+
+    /// Branch, tag or commit to read from; `HEAD` is the default branch
+    fn reference(&self) -> &str {
+        self.branch.as_deref().unwrap_or("HEAD")
+    }
+
+    /// Download at most `concurrency` files at once
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    /// Timeout and retries of each file download
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    async fn download_file(
+        client: &Client,
+        item: &GithubApiContent,
+        download_url: &str,
+        output_dir: &Path,
+    ) -> Result<PathBuf> {
+        let response = client
+            .get(download_url)
+            .header("User-Agent", "rust-github-raw-fetcher")
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(status_error(response).await.into());
         }
+
+        let content = response.text().await?;
+        let output_path = output_dir.join(&item.name);
+        tokio::fs::write(&output_path, content).await?;
+        Ok(output_path)
     }
 
-    async fn list_directory_contents(&self, client: &Client) -> Result<Vec<GithubApiContent>> {
-        let url = format!(
-            "https://api.github.com/repos/{}/{}/contents/{}?ref={}",
-            self.owner, self.repo, self.path, self.branch
+    /// Contents API URL of the path at `revision`, or at the configured
+    /// branch when the commit could not be resolved
+    fn contents_url(&self, revision: Option<&str>) -> String {
+        let mut url = format!(
+            "https://api.github.com/repos/{}/{}/contents/{}",
+            self.owner, self.repo, self.path
         );
+        if let Some(revision) = revision.or(self.branch.as_deref()) {
+            url.push_str(&format!("?ref={}", revision));
+        }
+        url
+    }
 
+    async fn list_directory_contents(
+        &self,
+        client: &Client,
+        revision: Option<&str>,
+    ) -> Result<Vec<GithubApiContent>> {
         let response = client
-            .get(&url)
+            .get(self.contents_url(revision))
             .header("User-Agent", "rust-github-raw-fetcher")
             .send()
             .await?;
@@ -135,8 +436,51 @@ impl GitHubSource {
             ));
         }
 
-        let contents: Vec<GithubApiContent> = response.json().await?;
-        Ok(contents)
+        Ok(match response.json().await? {
+            GithubApiListing::Directory(contents) => contents,
+            GithubApiListing::File(file) => vec![file],
+        })
+    }
+
+    /// Resolve the configured branch to a commit SHA via the GitHub API
+    async fn resolve_commit(&self, client: &Client) -> Result<String> {
+        let url = format!(
+            "https://api.github.com/repos/{}/{}/commits/{}",
+            self.owner,
+            self.repo,
+            self.reference()
+        );
+
+        let response = client
+            .get(&url)
+            .header("User-Agent", "rust-github-raw-fetcher")
+            .header("Accept", "application/vnd.github.sha")
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(anyhow!("Failed to resolve commit: {}", response.status()));
+        }
+
+        Ok(response.text().await?.trim().to_string())
+    }
+
+    /// Commit SHA of the configured branch, resolved once per source
+    ///
+    /// Logs and returns `None` when the commit cannot be resolved, in which
+    /// case the branch itself is read and recorded.
+    async fn pinned_commit(&self, client: &Client) -> Option<&str> {
+        match self
+            .commit
+            .get_or_try_init(|| self.resolve_commit(client))
+            .await
+        {
+            Ok(sha) => Some(sha),
+            Err(e) => {
+                println!("Could not resolve commit for {}: {}", self.reference(), e);
+                None
+            }
+        }
     }
 
     fn is_supported_file(filename: &str) -> bool {
@@ -151,37 +495,59 @@ impl GitHubSource {
 #[async_trait]
 impl DataSource for GitHubSource {
     async fn collect(&self, output_dir: &Path) -> Result<Vec<PathBuf>> {
-        let client = Client::new();
+        let client = github_client()?;
         let mut collected = Vec::new();
 
+        let revision = self.pinned_commit(&client).await;
         println!("Fetching contents from GitHub directory...");
-        let contents = self.list_directory_contents(&client).await?;
-
-        for item in contents {
-            if item.content_type != "file" || !Self::is_supported_file(&item.name) {
-                continue;
-            }
+        let contents = self.list_directory_contents(&client, revision).await?;
+
+        let files: Vec<(GithubApiContent, String)> = contents
+            .into_iter()
+            .filter(|item| item.content_type == "file" && Self::is_supported_file(&item.name))
+            .filter_map(|item| {
+                let download_url = item.download_url.clone()?;
+                Some((item, download_url))
+            })
+            .collect();
 
-            if let Some(download_url) = item.download_url {
+        let downloads = download_concurrently(files.len(), self.concurrency, |index| {
+            let (item, download_url) = &files[index];
+            let client = &client;
+            async move {
                 println!("Downloading: {}", item.path);
-                let response = client
-                    .get(&download_url)
-                    .header("User-Agent", "rust-github-raw-fetcher")
-                    .send()
-                    .await?;
-
-                if !response.status().is_success() {
-                    println!("Failed to download {}: {}", item.path, response.status());
-                    continue;
+                let downloaded = self
+                    .retry
+                    .run(&format!("Download of {}", item.path), || {
+                        Self::download_file(client, item, download_url, output_dir)
+                    })
+                    .await;
+                if downloaded.is_ok() {
+                    println!("Successfully downloaded: {}", item.name);
+                }
+                downloaded
+            }
+        })
+        .await;
+
+        let mut failures = 0;
+        for ((item, _), downloaded) in files.iter().zip(downloads) {
+            match downloaded {
+                Ok(path) => collected.push(path),
+                Err(e) => {
+                    println!("Failed to download {}: {:#}", item.path, e);
+                    failures += 1;
                 }
-
-                let content = response.text().await?;
-                let output_path = output_dir.join(&item.name);
-                std::fs::write(&output_path, content)?;
-                collected.push(output_path);
-                println!("Successfully downloaded: {}", item.name);
             }
         }
+        if failures > 0 && collected.is_empty() {
+            return Err(anyhow!(
+                "All {} downloads from {}/{} failed",
+                failures,
+                self.repo,
+                self.path
+            ));
+        }
 
         if collected.is_empty() {
             println!("No supported files found in the specified directory.");
@@ -191,6 +557,49 @@ impl DataSource for GitHubSource {
 
         Ok(collected)
     }
+
+    async fn provenance(&self) -> SourceProvenance {
+        let revision = match github_client() {
+            Ok(client) => self.pinned_commit(&client).await.map(str::to_string),
+            Err(e) => {
+                println!("Could not resolve commit for {}: {}", self.reference(), e);
+                None
+            }
+        }
+        .unwrap_or_else(|| self.reference().to_string());
+        SourceProvenance {
+            kind: "github".to_string(),
+            location: format!(
+                "https://github.com/{}/{}/tree/{}/{}",
+                self.owner,
+                self.repo,
+                self.reference(),
+                self.path
+            )
+            .trim_end_matches('/')
+            .to_string(),
+            revision: Some(revision),
+            files: Vec::new(),
+        }
+    }
+}
+
+/// Run `download` for items `0..count` with at most `concurrency` in flight,
+/// returning the results in item order
+async fn download_concurrently<F, Fut>(
+    count: usize,
+    concurrency: usize,
+    download: F,
+) -> Vec<Result<PathBuf>>
+where
+    F: Fn(usize) -> Fut,
+    Fut: std::future::Future<Output = Result<PathBuf>>,
+{
+    stream::iter(0..count)
+        .map(download)
+        .buffered(concurrency.max(1))
+        .collect()
+        .await
 }
 
 pub struct GitHubReleaseSource {
@@ -213,7 +622,7 @@ impl GitHubReleaseSource {
 #[async_trait]
 impl DataSource for GitHubReleaseSource {
     async fn collect(&self, output_dir: &Path) -> Result<Vec<PathBuf>> {
-        let client = Client::new();
+        let client = github_client()?;
         let url = format!("https://api.github.com/repos/{}/releases", self.repo);
 
         println!("Fetching releases from {}", url);
@@ -229,13 +638,22 @@ impl DataSource for GitHubReleaseSource {
         for release in releases {
             let filename = format!("{}.md", release.tag_name);
             let file_path = output_dir.join(&filename);
-            std::fs::write(&file_path, release.body)?;
+            tokio::fs::write(&file_path, release.body).await?;
             println!("Saved release notes for version {}", release.tag_name);
             files.push(file_path);
         }
 
         Ok(files)
     }
+
+    async fn provenance(&self) -> SourceProvenance {
+        SourceProvenance {
+            kind: "github_releases".to_string(),
+            location: format!("https://github.com/{}/releases", self.repo),
+            revision: None,
+            files: Vec::new(),
+        }
+    }
 }
 
 #[derive(Deserialize)]
@@ -243,3 +661,292 @@ struct Release {
     tag_name: String,
     body: String,
 }
+
+/// Kind of data source, given with `--source-type` or detected from the input
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SourceType {
+    #[default]
+    Auto,
+    Url,
+    Local,
+    GitHub,
+    GitHubReleases,
+    /// Documentation comments of a local codebase; never detected
+    Code,
+}
+
+impl SourceType {
+    pub const ALL: [SourceType; 6] = [
+        SourceType::Auto,
+        SourceType::Url,
+        SourceType::Local,
+        SourceType::GitHub,
+        SourceType::GitHubReleases,
+        SourceType::Code,
+    ];
+
+    /// Name used on the command line
+    pub fn name(&self) -> &'static str {
+        match self {
+            SourceType::Auto => "auto",
+            SourceType::Url => "url",
+            SourceType::Local => "local",
+            SourceType::GitHub => "github",
+            SourceType::GitHubReleases => "github-releases",
+            SourceType::Code => "code",
+        }
+    }
+
+    /// Guess the type of a source from its URL or path
+    pub fn detect(input: &str) -> Result<Self> {
+        if input.contains("/releases") {
+            Ok(SourceType::GitHubReleases)
+        } else if parse_github_url(input).is_ok() {
+            Ok(SourceType::GitHub)
+        } else if input.starts_with("http://") || input.starts_with("https://") {
+            Ok(SourceType::Url)
+        } else if Path::new(input).exists() {
+            Ok(SourceType::Local)
+        } else {
+            Err(anyhow!("'{}' is not a URL or an existing path", input))
+        }
+    }
+}
+
+impl fmt::Display for SourceType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for SourceType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "auto" => Ok(SourceType::Auto),
+            "url" => Ok(SourceType::Url),
+            "local" => Ok(SourceType::Local),
+            "github" => Ok(SourceType::GitHub),
+            "github-releases" => Ok(SourceType::GitHubReleases),
+            "code" => Ok(SourceType::Code),
+            other => Err(anyhow!(
+                "Unknown source type '{}' (expected auto, url, local, github, github-releases or code)",
+                other
+            )),
+        }
+    }
+}
+
+/// Open `input` as a source of the given type, detecting the type for
+/// [`SourceType::Auto`]
+pub fn open_source(input: &str, kind: SourceType) -> Result<Box<dyn DataSource>> {
+    let kind = match kind {
+        SourceType::Auto => SourceType::detect(input)?,
+        kind => kind,
+    };
+    Ok(match kind {
+        SourceType::Auto => unreachable!("detect never returns Auto"),
+        SourceType::Url => Box::new(UrlSource::new(input)?),
+        SourceType::Local => Box::new(LocalSource::new(input)),
+        SourceType::GitHub => Box::new(GitHubSource::new(input, None, None)?),
+        SourceType::GitHubReleases => Box::new(GitHubReleaseSource::new(input)?),
+        SourceType::Code => Box::new(CodeSource::new(input)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::external::ExternalError;
+
+    fn provenance(location: &str, revision: &str) -> SourceProvenance {
+        SourceProvenance {
+            kind: "github".to_string(),
+            location: location.to_string(),
+            revision: Some(revision.to_string()),
+            files: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_detect_source_type() {
+        let detect = |input| SourceType::detect(input).unwrap();
+        assert_eq!(
+            detect("https://github.com/user/repo/releases"),
+            SourceType::GitHubReleases
+        );
+        assert_eq!(
+            detect("https://github.com/user/repo/tree/main/docs"),
+            SourceType::GitHub
+        );
+        assert_eq!(detect("https://github.com/user/repo"), SourceType::GitHub);
+        assert_eq!(detect("https://example.com/guide.md"), SourceType::Url);
+        assert_eq!(detect("."), SourceType::Local);
+        assert!(SourceType::detect("no/such/path").is_err());
+
+        for kind in SourceType::ALL {
+            assert_eq!(kind.name().parse::<SourceType>().unwrap(), kind);
+        }
+        assert!("ftp".parse::<SourceType>().is_err());
+    }
+
+    #[test]
+    fn test_github_source_accepts_repo_tree_and_blob_urls() {
+        let source = |url| GitHubSource::new(url, None, None).unwrap();
+
+        let bare = source("https://github.com/user/repo.git/");
+        assert_eq!((bare.owner.as_str(), bare.repo.as_str()), ("user", "repo"));
+        assert_eq!((bare.branch, bare.path.as_str()), (None, ""));
+
+        let tree = source("https://github.com/user/repo/tree/main/docs/guide");
+        assert_eq!(tree.branch.as_deref(), Some("main"));
+        assert_eq!(tree.path, "docs/guide");
+
+        let blob = source("https://github.com/user/repo/blob/v2/README.md");
+        assert_eq!(blob.branch.as_deref(), Some("v2"));
+        assert_eq!(blob.path, "README.md");
+
+        let overridden = GitHubSource::new(
+            "https://github.com/user/repo/tree/main",
+            Some("release/1.0".to_string()),
+            Some("/docs/".to_string()),
+        )
+        .unwrap();
+        assert_eq!(overridden.reference(), "release/1.0");
+        assert_eq!(overridden.path, "docs");
+
+        for url in [
+            "https://github.com/user",
+            "https://github.com/user/repo/issues/1",
+            "https://gitlab.com/user/repo",
+            "not a url",
+        ] {
+            assert!(GitHubSource::new(url, None, None).is_err(), "{}", url);
+        }
+    }
+
+    #[test]
+    fn test_github_contents_are_read_at_the_pinned_commit() {
+        let tree =
+            GitHubSource::new("https://github.com/user/repo/tree/main/docs", None, None).unwrap();
+        assert_eq!(
+            tree.contents_url(Some("0123abc")),
+            "https://api.github.com/repos/user/repo/contents/docs?ref=0123abc"
+        );
+        // Without a resolved commit the branch is read
+        assert_eq!(
+            tree.contents_url(None),
+            "https://api.github.com/repos/user/repo/contents/docs?ref=main"
+        );
+        let bare = GitHubSource::new("https://github.com/user/repo", None, None).unwrap();
+        assert_eq!(
+            bare.contents_url(None),
+            "https://api.github.com/repos/user/repo/contents/"
+        );
+    }
+
+    #[test]
+    fn test_record_sources_replaces_same_location() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(recorded_sources(dir.path()).unwrap().is_empty());
+
+        record_sources(
+            dir.path(),
+            &[provenance("docs", "abc"), provenance("api", "1")],
+        )
+        .unwrap();
+        record_sources(dir.path(), &[provenance("docs", "def")]).unwrap();
+
+        let recorded = recorded_sources(dir.path()).unwrap();
+        assert_eq!(
+            recorded,
+            [provenance("api", "1"), provenance("docs", "def")]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_code_source_writes_documented_files() {
+        let code = tempfile::tempdir().unwrap();
+        let write = |path: &str, content: &str| {
+            let path = code.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        };
+        write(
+            "src/lib.rs",
+            "/// Adds one.\npub fn inc(x: u8) -> u8 { x + 1 }\n",
+        );
+        write("src/undocumented.rs", "fn helper() {}\n");
+        write(
+            "tools/run.py",
+            "def run():\n    \"\"\"Run the tool.\"\"\"\n",
+        );
+        write(
+            "node_modules/dep/index.js",
+            "/** Dependency. */\nfunction dep() {}\n",
+        );
+        write("README.md", "# Readme\n");
+
+        let output = tempfile::tempdir().unwrap();
+        let source = open_source(code.path().to_str().unwrap(), SourceType::Code).unwrap();
+        let collected = source.collect(output.path()).await.unwrap();
+        assert_eq!(
+            collected,
+            [
+                output.path().join("src/lib.rs.md"),
+                output.path().join("tools/run.py.md")
+            ]
+        );
+        let markdown = std::fs::read_to_string(&collected[0]).unwrap();
+        assert!(markdown.starts_with("# src/lib.rs\n\n## `pub fn inc(x: u8) -> u8`"));
+        assert_eq!(source.provenance().await.kind, "code");
+    }
+
+    #[tokio::test]
+    async fn test_download_concurrently_bounds_requests_and_keeps_order() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::time::Duration;
+
+        let in_flight = AtomicUsize::new(0);
+        let most_in_flight = AtomicUsize::new(0);
+        let attempts = AtomicUsize::new(0);
+        let retry = RetryPolicy {
+            timeout_secs: 1,
+            max_retries: 1,
+            backoff_ms: 0,
+        };
+
+        let downloads = download_concurrently(10, 3, |index| {
+            let (in_flight, most_in_flight, attempts) = (&in_flight, &most_in_flight, &attempts);
+            let retry = &retry;
+            async move {
+                retry
+                    .run("Download", || async {
+                        let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                        most_in_flight.fetch_max(now, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(5)).await;
+                        in_flight.fetch_sub(1, Ordering::SeqCst);
+                        // The first attempt of file 4 fails and is retried
+                        if index == 4 && attempts.fetch_add(1, Ordering::SeqCst) == 0 {
+                            return Err(
+                                ExternalError::ConnectionError("connection reset".into()).into()
+                            );
+                        }
+                        Ok(PathBuf::from(format!("{}.md", index)))
+                    })
+                    .await
+            }
+        })
+        .await;
+
+        let paths: Vec<PathBuf> = downloads.into_iter().map(Result::unwrap).collect();
+        let expected: Vec<PathBuf> = (0..10)
+            .map(|i| PathBuf::from(format!("{}.md", i)))
+            .collect();
+        assert_eq!(paths, expected);
+        assert!(most_in_flight.load(Ordering::SeqCst) <= 3);
+        assert!(most_in_flight.load(Ordering::SeqCst) > 1);
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
+}
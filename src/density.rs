@@ -0,0 +1,320 @@
+//! How many questions a document is worth: the strategy behind the question
+//! targets of every file, chosen with `--question-density` or
+//! `QUESTION_DENSITY`.
+
+use anyhow::{anyhow, Result};
+use std::fmt;
+use std::ops::AddAssign;
+use std::str::FromStr;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::graph::tagging::is_stopword;
+use crate::sections::count_words;
+
+/// Characters of text per model token, about
+const CHARS_PER_TOKEN: usize = 4;
+
+/// Share of words that aren't stopwords in typical documentation prose; a
+/// document at this share gets as many questions as it would per words
+const TYPICAL_CONTENT_SHARE: f64 = 0.55;
+
+/// Range the information weighting may scale a document's questions by
+const MIN_DENSITY_WEIGHT: f64 = 0.5;
+const MAX_DENSITY_WEIGHT: f64 = 2.0;
+
+/// The size of a document, or of one of its sections, in the units the
+/// density strategies count
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DocumentSize {
+    pub words: usize,
+    /// Model tokens, estimated from the characters
+    pub tokens: usize,
+    pub sections: usize,
+    /// Words that aren't stopwords
+    pub content_words: usize,
+}
+
+impl DocumentSize {
+    /// Size of one section
+    pub fn of_section(text: &str) -> Self {
+        let content_words = text
+            .unicode_words()
+            .filter(|word| !is_stopword(&word.to_lowercase()))
+            .count();
+        Self {
+            words: count_words(text),
+            tokens: text.chars().count().div_ceil(CHARS_PER_TOKEN),
+            sections: 1,
+            content_words,
+        }
+    }
+
+    /// How far the share of content words is from typical prose, within
+    /// [`MIN_DENSITY_WEIGHT`] and [`MAX_DENSITY_WEIGHT`]
+    pub fn density_weight(&self) -> f64 {
+        if self.words == 0 {
+            return 1.0;
+        }
+        let share = self.content_words as f64 / self.words as f64;
+        (share / TYPICAL_CONTENT_SHARE).clamp(MIN_DENSITY_WEIGHT, MAX_DENSITY_WEIGHT)
+    }
+}
+
+impl AddAssign for DocumentSize {
+    fn add_assign(&mut self, other: Self) {
+        self.words += other.words;
+        self.tokens += other.tokens;
+        self.sections += other.sections;
+        self.content_words += other.content_words;
+    }
+}
+
+impl<S: AsRef<str>> FromIterator<S> for DocumentSize {
+    /// Size of a document made of these sections
+    fn from_iter<I: IntoIterator<Item = S>>(sections: I) -> Self {
+        let mut size = Self::default();
+        for section in sections {
+            size += Self::of_section(section.as_ref());
+        }
+        size
+    }
+}
+
+/// How the base question goal of a document is set
+///
+/// Whatever the strategy, at least two questions are asked for and a
+/// quarter more than the goal are generated.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QuestionDensity {
+    /// One question per this many words
+    PerWords(f64),
+    /// One question per this many model tokens, so dense scripts and code
+    /// aren't undercounted by their words
+    PerTokens(f64),
+    /// This many questions per section
+    PerSection(usize),
+    /// This many questions per file, however long
+    PerFile(usize),
+    /// One question per this many words, scaled by the share of words that
+    /// aren't stopwords: terse reference text gets more questions than
+    /// wordy prose of the same length
+    Information(f64),
+}
+
+impl Default for QuestionDensity {
+    fn default() -> Self {
+        QuestionDensity::PerWords(10.0)
+    }
+}
+
+impl QuestionDensity {
+    /// Names of the strategies, each with its default amount
+    pub const ALL: [QuestionDensity; 5] = [
+        QuestionDensity::PerWords(10.0),
+        QuestionDensity::PerTokens(15.0),
+        QuestionDensity::PerSection(3),
+        QuestionDensity::PerFile(20),
+        QuestionDensity::Information(10.0),
+    ];
+
+    /// Name used on the command line and in the config
+    pub fn name(&self) -> &'static str {
+        match self {
+            QuestionDensity::PerWords(_) => "words",
+            QuestionDensity::PerTokens(_) => "tokens",
+            QuestionDensity::PerSection(_) => "section",
+            QuestionDensity::PerFile(_) => "file",
+            QuestionDensity::Information(_) => "information",
+        }
+    }
+
+    /// What a document or section counts for under this strategy; a
+    /// section's share of the questions is its share of this
+    fn measure(&self, size: &DocumentSize) -> f64 {
+        match self {
+            QuestionDensity::PerWords(_) | QuestionDensity::PerFile(_) => size.words as f64,
+            QuestionDensity::PerTokens(_) => size.tokens as f64,
+            QuestionDensity::PerSection(_) => size.sections as f64,
+            QuestionDensity::Information(_) => size.content_words as f64,
+        }
+    }
+
+    /// Questions a document of this size is worth, at least two
+    pub fn base_goal(&self, size: &DocumentSize) -> usize {
+        let goal = match *self {
+            QuestionDensity::PerWords(words) => (size.words as f64 / words).ceil() as usize,
+            QuestionDensity::PerTokens(tokens) => (size.tokens as f64 / tokens).ceil() as usize,
+            QuestionDensity::PerSection(questions) => size.sections * questions,
+            QuestionDensity::PerFile(questions) => questions,
+            QuestionDensity::Information(words) => {
+                (size.words as f64 / words * size.density_weight()).ceil() as usize
+            }
+        };
+        goal.max(2)
+    }
+
+    /// The base goal, how many to generate and the minimum accepted, as
+    /// [`crate::sections::question_targets`] gives them for words
+    pub fn targets(&self, size: &DocumentSize) -> (usize, usize, usize) {
+        targets_for_goal(self.base_goal(size))
+    }
+
+    /// A section's share of `total_questions` for a document of `total` size
+    pub fn section_share(
+        &self,
+        section: &DocumentSize,
+        total: &DocumentSize,
+        total_questions: usize,
+    ) -> usize {
+        let total_measure = self.measure(total);
+        if total_measure == 0.0 {
+            return 0;
+        }
+        (total_questions as f64 * (self.measure(section) / total_measure)).ceil() as usize
+    }
+}
+
+/// Targets around a base goal: a quarter extra to generate, and 80% of the
+/// goal accepted, each at least two
+pub(crate) fn targets_for_goal(base_goal: usize) -> (usize, usize, usize) {
+    let extra_questions = ((base_goal as f64 * 0.25).ceil() as usize).max(2);
+    let min_acceptable = ((base_goal as f64 * 0.8).ceil() as usize).max(2);
+    (base_goal, base_goal + extra_questions, min_acceptable)
+}
+
+impl fmt::Display for QuestionDensity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QuestionDensity::PerWords(amount)
+            | QuestionDensity::PerTokens(amount)
+            | QuestionDensity::Information(amount) => write!(f, "{}:{}", self.name(), amount),
+            QuestionDensity::PerSection(amount) | QuestionDensity::PerFile(amount) => {
+                write!(f, "{}:{}", self.name(), amount)
+            }
+        }
+    }
+}
+
+impl FromStr for QuestionDensity {
+    type Err = anyhow::Error;
+
+    /// A strategy name, optionally with its amount after a colon, e.g.
+    /// `words:12` or `file`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, amount) = match s.trim().split_once(':') {
+            Some((name, amount)) => (name.trim().to_lowercase(), Some(amount.trim())),
+            None => (s.trim().to_lowercase(), None),
+        };
+        let default = Self::ALL
+            .into_iter()
+            .find(|density| density.name() == name)
+            .ok_or_else(|| {
+                anyhow!(
+                    "Unknown question density '{}' (expected {})",
+                    name,
+                    Self::ALL.map(|density| density.name()).join(", ")
+                )
+            })?;
+        let Some(amount) = amount else {
+            return Ok(default);
+        };
+        let invalid = || anyhow!("Invalid amount '{}' for question density {}", amount, name);
+        let density = match default {
+            QuestionDensity::PerWords(_)
+            | QuestionDensity::PerTokens(_)
+            | QuestionDensity::Information(_) => {
+                let amount: f64 = amount.parse().map_err(|_| invalid())?;
+                if !(amount.is_finite() && amount > 0.0) {
+                    return Err(invalid());
+                }
+                match default {
+                    QuestionDensity::PerWords(_) => QuestionDensity::PerWords(amount),
+                    QuestionDensity::PerTokens(_) => QuestionDensity::PerTokens(amount),
+                    _ => QuestionDensity::Information(amount),
+                }
+            }
+            QuestionDensity::PerSection(_) | QuestionDensity::PerFile(_) => {
+                let amount: usize = amount.parse().map_err(|_| invalid())?;
+                if amount == 0 {
+                    return Err(invalid());
+                }
+                match default {
+                    QuestionDensity::PerSection(_) => QuestionDensity::PerSection(amount),
+                    _ => QuestionDensity::PerFile(amount),
+                }
+            }
+        };
+        Ok(density)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sections::question_targets;
+
+    #[test]
+    fn test_per_words_matches_word_targets() {
+        let text = "word ".repeat(400);
+        let size = DocumentSize::of_section(&text);
+        assert_eq!(
+            QuestionDensity::default().targets(&size),
+            question_targets(400)
+        );
+        assert_eq!(
+            QuestionDensity::default().targets(&DocumentSize::default()),
+            (2, 4, 2)
+        );
+    }
+
+    #[test]
+    fn test_strategies() {
+        let size: DocumentSize = ["# One\nalpha beta gamma delta", "# Two\nepsilon"]
+            .into_iter()
+            .collect();
+        assert_eq!(size.sections, 2);
+        assert_eq!(QuestionDensity::PerSection(3).base_goal(&size), 6);
+        assert_eq!(QuestionDensity::PerFile(20).base_goal(&size), 20);
+        assert_eq!(QuestionDensity::PerWords(1.0).base_goal(&size), size.words);
+        assert_eq!(
+            QuestionDensity::PerTokens(1.0).base_goal(&size),
+            size.tokens
+        );
+
+        // Each section gets its count, whatever its length
+        let first = DocumentSize::of_section("# One\nalpha beta gamma delta");
+        assert_eq!(
+            QuestionDensity::PerSection(3).section_share(&first, &size, 6),
+            3
+        );
+    }
+
+    #[test]
+    fn test_information_weighting() {
+        let terse = DocumentSize::of_section(&"tokio runtime spawns futures ".repeat(25));
+        let wordy = DocumentSize::of_section(&"it is what it is and so on ".repeat(12));
+        let density = QuestionDensity::Information(10.0);
+        assert!(density.base_goal(&terse) > QuestionDensity::default().base_goal(&terse));
+        assert!(density.base_goal(&wordy) < QuestionDensity::default().base_goal(&wordy));
+    }
+
+    #[test]
+    fn test_parse_density() {
+        assert_eq!(
+            "words".parse::<QuestionDensity>().unwrap(),
+            QuestionDensity::PerWords(10.0)
+        );
+        assert_eq!(
+            "File:12".parse::<QuestionDensity>().unwrap(),
+            QuestionDensity::PerFile(12)
+        );
+        assert_eq!(
+            "tokens:7.5".parse::<QuestionDensity>().unwrap(),
+            QuestionDensity::PerTokens(7.5)
+        );
+        assert_eq!(QuestionDensity::PerSection(4).to_string(), "section:4");
+        assert!("section:0".parse::<QuestionDensity>().is_err());
+        assert!("words:-1".parse::<QuestionDensity>().is_err());
+        assert!("pages".parse::<QuestionDensity>().is_err());
+    }
+}
@@ -0,0 +1,159 @@
+//! Difficulty labels of records, for ordering a dataset into a curriculum.
+//!
+//! The model labels each question as it generates it. Records without a
+//! label, such as those of older datasets, get an estimate from the wording
+//! of the question and the length of the answer.
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::processor::{DefaultOllamaProcessor, ProcessedItem};
+
+/// How hard a question is to answer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Difficulty {
+    /// Recall of a single stated fact
+    Easy,
+    /// Combining a few facts, or applying one
+    Medium,
+    /// Reasoning across the content: explaining, comparing, troubleshooting
+    Hard,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "easy",
+            Difficulty::Medium => "medium",
+            Difficulty::Hard => "hard",
+        }
+    }
+}
+
+impl fmt::Display for Difficulty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Difficulty {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "easy" => Ok(Difficulty::Easy),
+            "medium" => Ok(Difficulty::Medium),
+            "hard" => Ok(Difficulty::Hard),
+            other => Err(anyhow!(
+                "Unknown difficulty '{}' (expected easy, medium or hard)",
+                other
+            )),
+        }
+    }
+}
+
+/// Read a difficulty label, treating anything unrecognized as no label
+///
+/// Models don't always stick to the three names, and a label they made up
+/// shouldn't cost the whole reply.
+pub fn deserialize_label<'de, D>(deserializer: D) -> Result<Option<Difficulty>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let label = Option::<serde_json::Value>::deserialize(deserializer)?;
+    Ok(label
+        .as_ref()
+        .and_then(|label| label.as_str())
+        .and_then(|label| label.parse().ok()))
+}
+
+/// Words in a question that ask for reasoning rather than recall
+const REASONING_WORDS: [&str; 6] = [
+    "why",
+    "explain",
+    "compare",
+    "difference",
+    "troubleshoot",
+    "trade-off",
+];
+
+/// Answers up to this many words are short enough for recall
+const SHORT_ANSWER_WORDS: usize = 15;
+
+/// Answers longer than this many words take reasoning to give
+const LONG_ANSWER_WORDS: usize = 50;
+
+/// Difficulty of a record without a label
+pub fn estimate(item: &ProcessedItem) -> Difficulty {
+    let question = item.question.to_lowercase();
+    let reasoning = REASONING_WORDS.iter().any(|word| question.contains(word));
+    let words = DefaultOllamaProcessor::count_words(&item.answer);
+    match (reasoning, words) {
+        (true, words) if words > SHORT_ANSWER_WORDS => Difficulty::Hard,
+        (false, words) if words > LONG_ANSWER_WORDS => Difficulty::Hard,
+        (false, words) if words <= SHORT_ANSWER_WORDS => Difficulty::Easy,
+        _ => Difficulty::Medium,
+    }
+}
+
+/// A record's difficulty: its label, or else an estimate
+pub fn of(item: &ProcessedItem) -> Difficulty {
+    item.difficulty.unwrap_or_else(|| estimate(item))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(question: &str, answer_words: usize) -> ProcessedItem {
+        ProcessedItem::new(question, vec!["word"; answer_words].join(" "))
+    }
+
+    #[test]
+    fn test_estimate() {
+        assert_eq!(
+            estimate(&item("What is the default port?", 3)),
+            Difficulty::Easy
+        );
+        assert_eq!(
+            estimate(&item("How is TLS enabled?", 30)),
+            Difficulty::Medium
+        );
+        assert_eq!(
+            estimate(&item("Why does the cache need a lock?", 30)),
+            Difficulty::Hard
+        );
+        assert_eq!(estimate(&item("Why is it off?", 5)), Difficulty::Medium);
+
+        let mut labelled = item("What is the default port?", 3);
+        labelled.difficulty = Some(Difficulty::Hard);
+        assert_eq!(of(&labelled), Difficulty::Hard);
+    }
+
+    #[test]
+    fn test_unknown_labels_are_dropped() {
+        let parse = |json: &str| {
+            serde_json::from_str::<ProcessedItem>(json)
+                .unwrap()
+                .difficulty
+        };
+        assert_eq!(
+            parse(r#"{"question": "Q", "answer": "A", "difficulty": "Hard"}"#),
+            Some(Difficulty::Hard)
+        );
+        assert_eq!(
+            parse(r#"{"question": "Q", "answer": "A", "difficulty": "trivial"}"#),
+            None
+        );
+        assert_eq!(
+            parse(r#"{"question": "Q", "answer": "A", "difficulty": 2}"#),
+            None
+        );
+        assert_eq!(parse(r#"{"question": "Q", "answer": "A"}"#), None);
+    }
+}
@@ -0,0 +1,246 @@
+//! Teacher–student distillation: the generation model is the teacher, and a
+//! smaller student model answers each generated question without the
+//! section. Sections where the student's answers disagree with the
+//! teacher's get more questions, and are listed in a report of where the
+//! student is weak.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+
+use crate::processor::{OllamaClient, ProcessedItem};
+
+/// Report of the student's weak areas, written to the output directory
+pub const STUDENT_REPORT_FILE: &str = "student_report.json";
+
+/// Student answers agreeing less than this with the teacher's are failures
+pub const DEFAULT_MIN_AGREEMENT: f64 = 0.5;
+
+/// Overlap of two answers from 0 to 1: the F1 score of their words
+///
+/// Words are compared lowercased without punctuation, counting repeats, so
+/// a student answer restating the teacher's in another order still agrees.
+pub fn agreement(reference: &str, answer: &str) -> f64 {
+    let words = |text: &str| -> Vec<String> {
+        text.split_whitespace()
+            .map(|word| {
+                word.trim_matches(|c: char| !c.is_alphanumeric())
+                    .to_lowercase()
+            })
+            .filter(|word| !word.is_empty())
+            .collect()
+    };
+    let reference = words(reference);
+    let mut unmatched = words(answer);
+    let answer_len = unmatched.len();
+    if reference.is_empty() || answer_len == 0 {
+        return 0.0;
+    }
+    let mut common = 0;
+    for word in &reference {
+        if let Some(position) = unmatched.iter().position(|w| w == word) {
+            unmatched.swap_remove(position);
+            common += 1;
+        }
+    }
+    if common == 0 {
+        return 0.0;
+    }
+    let precision = common as f64 / answer_len as f64;
+    let recall = common as f64 / reference.len() as f64;
+    2.0 * precision * recall / (precision + recall)
+}
+
+/// How the student did on one section
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WeakArea {
+    /// Input file, named as in record IDs
+    pub document: String,
+    /// Byte range of the section in the document
+    pub span: Range<usize>,
+    /// First line of the section, usually its heading
+    pub heading: String,
+    pub asked: usize,
+    pub failed: usize,
+    /// Questions generated for the section because the student failed
+    pub follow_up: usize,
+}
+
+impl WeakArea {
+    /// Share of the questions the student failed
+    pub fn failure_rate(&self) -> f64 {
+        if self.asked == 0 {
+            0.0
+        } else {
+            self.failed as f64 / self.asked as f64
+        }
+    }
+}
+
+/// Where the student disagreed with the teacher over a run
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StudentReport {
+    pub student: String,
+    pub min_agreement: f64,
+    pub asked: usize,
+    pub failed: usize,
+    pub follow_up: usize,
+    /// Sections with at least one failure, weakest first
+    pub areas: Vec<WeakArea>,
+}
+
+impl StudentReport {
+    fn add(&mut self, area: WeakArea) {
+        self.asked += area.asked;
+        self.failed += area.failed;
+        self.follow_up += area.follow_up;
+        if area.failed > 0 {
+            let position = self
+                .areas
+                .partition_point(|a| a.failure_rate() >= area.failure_rate());
+            self.areas.insert(position, area);
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for StudentReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Student:      {}", self.student)?;
+        writeln!(
+            f,
+            "Failed:       {} of {} (agreement below {})",
+            self.failed, self.asked, self.min_agreement
+        )?;
+        write!(f, "Follow-up:    {} questions", self.follow_up)?;
+        for area in self.areas.iter().take(5) {
+            write!(
+                f,
+                "\n  {:>3.0}%  {} {}",
+                area.failure_rate() * 100.0,
+                area.document,
+                area.heading
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The student model and what it got wrong so far
+pub struct Student {
+    client: Box<dyn OllamaClient>,
+    min_agreement: f64,
+    report: Mutex<StudentReport>,
+}
+
+impl Student {
+    pub fn new(client: Box<dyn OllamaClient>, model: &str, min_agreement: f64) -> Self {
+        Self {
+            client,
+            min_agreement,
+            report: Mutex::new(StudentReport {
+                student: model.to_string(),
+                min_agreement,
+                ..StudentReport::default()
+            }),
+        }
+    }
+
+    /// Questions among `items` the student answers differently from the
+    /// teacher; ones it can't answer at all count as failed
+    pub async fn failures(&self, items: &[ProcessedItem]) -> Vec<String> {
+        let mut failed = Vec::new();
+        for item in items {
+            let answer = self
+                .client
+                .answer_unaided(&item.question)
+                .await
+                .unwrap_or_else(|e| {
+                    println!("Student failed to answer: {}", e);
+                    String::new()
+                });
+            if agreement(&item.answer, &answer) < self.min_agreement {
+                failed.push(item.question.clone());
+            }
+        }
+        failed
+    }
+
+    pub fn record(&self, area: WeakArea) {
+        self.report
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .add(area);
+    }
+
+    pub fn report(&self) -> StudentReport {
+        self.report
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+}
+
+/// Questions not already among `asked`, compared without case or
+/// surrounding whitespace
+pub fn new_questions(
+    asked: &[ProcessedItem],
+    candidates: Vec<ProcessedItem>,
+) -> Vec<ProcessedItem> {
+    let mut seen: HashSet<String> = asked
+        .iter()
+        .map(|item| item.question.trim().to_lowercase())
+        .collect();
+    candidates
+        .into_iter()
+        .filter(|item| seen.insert(item.question.trim().to_lowercase()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn area(heading: &str, asked: usize, failed: usize) -> WeakArea {
+        WeakArea {
+            document: "guide.md".to_string(),
+            span: 0..10,
+            heading: heading.to_string(),
+            asked,
+            failed,
+            follow_up: failed,
+        }
+    }
+
+    #[test]
+    fn test_agreement() {
+        let reference = "Set use_tls to true in config.toml.";
+        assert_eq!(
+            agreement(reference, "set USE_TLS to true in config.toml"),
+            1.0
+        );
+        assert!(agreement(reference, "In config.toml, set use_tls to true.") > 0.99);
+        assert!(agreement(reference, "Restart the server.") < DEFAULT_MIN_AGREEMENT);
+        assert_eq!(agreement(reference, ""), 0.0);
+    }
+
+    #[test]
+    fn test_report_lists_weakest_areas_first() {
+        let mut report = StudentReport::default();
+        report.add(area("# Setup", 4, 1));
+        report.add(area("# Usage", 4, 0));
+        report.add(area("# TLS", 2, 2));
+        assert_eq!((report.asked, report.failed, report.follow_up), (10, 3, 3));
+        let headings: Vec<&str> = report.areas.iter().map(|a| &*a.heading).collect();
+        assert_eq!(headings, ["# TLS", "# Setup"]);
+    }
+}
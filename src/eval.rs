@@ -0,0 +1,47 @@
+//! Evaluation sets: a share of sections held out of the training data, with
+//! questions, reference answers and grading rubrics generated for them.
+
+use crate::hash::stable_fraction;
+
+/// File in the output directory holding the evaluation records of a run
+pub const EVAL_FILE: &str = "eval.jsonl";
+
+/// Which sections are held out for the evaluation set (`--eval`)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EvalSplit {
+    /// Percentage of sections held out
+    pub percent: f64,
+}
+
+impl EvalSplit {
+    pub fn new(percent: f64) -> Self {
+        Self { percent }
+    }
+
+    /// Whether a section is held out
+    ///
+    /// Like `--sample`, the choice is a hash of the section's content, so
+    /// the same sections are held out on every run and an `--incremental`
+    /// rerun never moves a section between the sets. The hash differs from
+    /// the sampling one, so the two choices are independent.
+    pub fn held_out(&self, section: &str) -> bool {
+        stable_fraction(b"eval\n", section.trim()) * 100.0 < self.percent
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_held_out_share_is_stable() {
+        let sections: Vec<String> = (0..1000).map(|i| format!("## Section {}", i)).collect();
+        let split = EvalSplit::new(10.0);
+        let held_out = sections.iter().filter(|s| split.held_out(s)).count();
+        assert!((60..140).contains(&held_out), "held out {}", held_out);
+        assert!(sections
+            .iter()
+            .all(|s| split.held_out(s) == split.held_out(&format!("  {}\n", s))));
+        assert!(!sections.iter().any(|s| EvalSplit::new(0.0).held_out(s)));
+    }
+}
@@ -0,0 +1,276 @@
+//! Exit codes and the `errors.json` summary of failed runs, so scripts and CI
+//! can tell a misconfiguration from an unreachable server or a run where only
+//! some files failed.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::external::ExternalError;
+use crate::preflight::PreflightError;
+use crate::processor::ProcessorError;
+
+/// File name of the error summary written next to the dataset
+pub const ERRORS_FILE: &str = "errors.json";
+
+/// Why a run ended, each with its own process exit code
+///
+/// Exit code 2 is left to command line usage errors, which clap reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExitReason {
+    Success,
+    /// An error not covered by a more specific reason
+    Failed,
+    /// Some input files failed while others produced records
+    PartialFailure,
+    /// Invalid settings, config file, profile or sources
+    Config,
+    /// The LLM, embedding or vector DB server could not be reached or used
+    BackendUnreachable,
+    /// A run limit stopped the run before all input was processed
+    BudgetExceeded,
+    /// A dataset checked by `validate` has problems
+    InvalidDataset,
+    /// Stopped by Ctrl-C or SIGTERM
+    Interrupted,
+}
+
+impl ExitReason {
+    pub fn code(self) -> i32 {
+        match self {
+            ExitReason::Success => 0,
+            ExitReason::Failed => 1,
+            ExitReason::PartialFailure => 3,
+            ExitReason::Config => 4,
+            ExitReason::BackendUnreachable => 5,
+            ExitReason::BudgetExceeded => 6,
+            ExitReason::InvalidDataset => 7,
+            // 128 + SIGINT, as shells report a process killed by Ctrl-C
+            ExitReason::Interrupted => 130,
+        }
+    }
+
+    /// The reason an error ended a run, from the first cause that tells
+    ///
+    /// Errors are tagged with a reason by adding it as context, e.g.
+    /// `result.context(ExitReason::Config)`.
+    ///
+    /// A backend the processor reports unavailable counts as unreachable,
+    /// unless a cause further down shows a configuration error.
+    pub fn of(error: &anyhow::Error) -> Self {
+        if let Some(reason) = error.downcast_ref::<ExitReason>() {
+            return *reason;
+        }
+        let mut reason = ExitReason::Failed;
+        for cause in error.chain() {
+            if cause.downcast_ref::<PreflightError>().is_some() {
+                return ExitReason::BackendUnreachable;
+            }
+            match cause.downcast_ref::<ProcessorError>() {
+                Some(ProcessorError::BudgetExceeded(_)) => return ExitReason::BudgetExceeded,
+                Some(ProcessorError::BackendUnavailable(_)) => {
+                    reason = ExitReason::BackendUnreachable
+                }
+                _ => {}
+            }
+            match cause.downcast_ref::<ExternalError>() {
+                Some(ExternalError::ConfigError(_)) => return ExitReason::Config,
+                Some(ExternalError::ConnectionError(_) | ExternalError::Timeout(_)) => {
+                    return ExitReason::BackendUnreachable
+                }
+                _ => {}
+            }
+        }
+        reason
+    }
+}
+
+impl fmt::Display for ExitReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            ExitReason::Success => "success",
+            ExitReason::Failed => "failed",
+            ExitReason::PartialFailure => "some files failed",
+            ExitReason::Config => "configuration error",
+            ExitReason::BackendUnreachable => "backend unreachable",
+            ExitReason::BudgetExceeded => "budget exceeded",
+            ExitReason::InvalidDataset => "invalid dataset",
+            ExitReason::Interrupted => "interrupted",
+        };
+        f.write_str(text)
+    }
+}
+
+/// An input file that produced no records
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileFailure {
+    pub path: PathBuf,
+    pub reason: ExitReason,
+    pub error: String,
+}
+
+/// Files processed and failed during a run
+#[derive(Debug, Default)]
+pub struct RunReport {
+    /// Where `errors.json` goes, once the output directory is known
+    pub output_dir: Option<PathBuf>,
+    pub files_processed: usize,
+    pub failed_files: Vec<FileFailure>,
+}
+
+impl RunReport {
+    pub fn file_succeeded(&mut self) {
+        self.files_processed += 1;
+    }
+
+    pub fn file_failed(&mut self, path: &Path, error: &anyhow::Error) {
+        self.files_processed += 1;
+        self.failed_files.push(FileFailure {
+            path: path.to_path_buf(),
+            reason: ExitReason::of(error),
+            error: format!("{:#}", error),
+        });
+    }
+
+    /// Summarize the run; a fatal error takes precedence over an
+    /// interruption, which takes precedence over failed files
+    pub fn finish(self, error: Option<&anyhow::Error>, interrupted: bool) -> ErrorSummary {
+        let reason = if let Some(error) = error {
+            ExitReason::of(error)
+        } else if interrupted {
+            ExitReason::Interrupted
+        } else if self.failed_files.is_empty() {
+            ExitReason::Success
+        } else if self.failed_files.len() < self.files_processed {
+            ExitReason::PartialFailure
+        } else {
+            self.failed_files[0].reason
+        };
+        ErrorSummary {
+            exit_code: reason.code(),
+            reason,
+            error: error.map(|e| format!("{:#}", e)),
+            files_processed: self.files_processed,
+            failed_files: self.failed_files,
+        }
+    }
+}
+
+/// Contents of `errors.json`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorSummary {
+    pub exit_code: i32,
+    pub reason: ExitReason,
+    /// The error that ended the run, if it did not run to completion
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub files_processed: usize,
+    pub failed_files: Vec<FileFailure>,
+}
+
+impl ErrorSummary {
+    /// Write the summary into the given directory
+    pub fn write(&self, dir: &Path) -> Result<PathBuf> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(ERRORS_FILE);
+        fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{anyhow, Context};
+
+    #[test]
+    fn test_exit_reason_of_error() {
+        let unreachable = anyhow::Error::from(ExternalError::ConnectionError("refused".into()))
+            .context("Failed to process section");
+        assert_eq!(ExitReason::of(&unreachable), ExitReason::BackendUnreachable);
+
+        let tagged: Result<()> = Err(anyhow!("Unknown profile 'gpu'")).context(ExitReason::Config);
+        let tagged = tagged.unwrap_err();
+        assert_eq!(ExitReason::of(&tagged), ExitReason::Config);
+        assert_eq!(
+            format!("{:#}", tagged),
+            "configuration error: Unknown profile 'gpu'"
+        );
+
+        assert_eq!(ExitReason::of(&anyhow!("disk full")), ExitReason::Failed);
+    }
+
+    #[test]
+    fn test_exit_reason_of_processor_error() {
+        let server_error = anyhow::Error::from(ProcessorError::BackendUnavailable(
+            ExternalError::OllamaError("model not found".into()).into(),
+        ));
+        assert_eq!(
+            ExitReason::of(&server_error),
+            ExitReason::BackendUnreachable
+        );
+        assert_eq!(
+            format!("{:#}", server_error),
+            "Model backend unavailable: Ollama error: model not found"
+        );
+
+        let bad_header = anyhow::Error::from(ProcessorError::BackendUnavailable(
+            ExternalError::ConfigError("Invalid API key".into()).into(),
+        ));
+        assert_eq!(ExitReason::of(&bad_header), ExitReason::Config);
+
+        let exceeded = anyhow::Error::from(ProcessorError::BudgetExceeded(
+            "Stopped after 10 of 12 files".into(),
+        ));
+        assert_eq!(ExitReason::of(&exceeded), ExitReason::BudgetExceeded);
+
+        let malformed = anyhow::Error::from(ProcessorError::MalformedResponse {
+            attempts: 3,
+            reason: "expected value".into(),
+        });
+        assert_eq!(ExitReason::of(&malformed), ExitReason::Failed);
+        assert!(ProcessorError::of(&malformed).unwrap().is_retryable());
+    }
+
+    #[test]
+    fn test_run_report_reasons() {
+        let unreachable = anyhow::Error::from(ExternalError::Timeout("30s".into()));
+        let mut report = RunReport::default();
+        report.file_failed(Path::new("a.md"), &unreachable);
+        let summary = report.finish(None, false);
+        assert_eq!(summary.reason, ExitReason::BackendUnreachable);
+        assert_eq!(summary.exit_code, 5);
+
+        let mut report = RunReport::default();
+        report.file_succeeded();
+        report.file_failed(Path::new("b.md"), &anyhow!("bad"));
+        assert_eq!(
+            report.finish(None, false).reason,
+            ExitReason::PartialFailure
+        );
+
+        let mut report = RunReport::default();
+        report.file_failed(Path::new("c.md"), &anyhow!("bad"));
+        assert_eq!(report.finish(None, true).reason, ExitReason::Interrupted);
+
+        let summary = RunReport::default().finish(None, false);
+        assert_eq!(summary.exit_code, 0);
+    }
+
+    #[test]
+    fn test_error_summary_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut report = RunReport::default();
+        report.file_failed(Path::new("doc.md"), &anyhow!("bad"));
+        let summary = report.finish(Some(&anyhow!("fatal")), false);
+
+        let path = summary.write(dir.path()).unwrap();
+        let loaded: ErrorSummary =
+            serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+        assert_eq!(loaded, summary);
+        assert_eq!(loaded.error.as_deref(), Some("fatal"));
+    }
+}
@@ -0,0 +1,518 @@
+//! Named experiments: runs given a `--run-name` are recorded in a registry
+//! in the output directory, with their settings, the statistics of their
+//! dataset and a snapshot of it, so later runs can be compared against them.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+use crate::judge::{self, QualityReport};
+use crate::manifest::RunManifest;
+use crate::processor::ProcessedItem;
+use crate::schema;
+use crate::stats::DatasetStats;
+
+/// Directory of the registry, within the output directory
+pub const EXPERIMENTS_DIR: &str = "experiments";
+
+/// File with the settings and metrics of a run, in the run's directory
+pub const EXPERIMENT_FILE: &str = "experiment.json";
+
+/// Snapshot of a run's dataset, in the run's directory
+pub const DATASET_FILE: &str = "dataset.jsonl";
+
+/// Config keys that differ between runs without changing what is generated
+const IGNORED_SETTINGS: [&str; 2] = ["output_dir", "run_name"];
+
+/// Characters of a prompt template hash shown when comparing runs
+const HASH_CHARS: usize = 12;
+
+/// Check that a run name can name its directory: letters, digits, `-`, `_`
+/// and `.`, not starting with a `.`
+pub fn check_name(name: &str) -> Result<(), String> {
+    let valid = name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if name.is_empty() || name.starts_with('.') || !valid {
+        return Err(format!(
+            "'{}' is not a run name; use letters, digits, '-', '_' and '.'",
+            name
+        ));
+    }
+    Ok(())
+}
+
+/// A run as recorded in the registry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Experiment {
+    pub name: String,
+    pub manifest: RunManifest,
+    /// Statistics of the run's dataset
+    pub stats: DatasetStats,
+}
+
+/// The runs recorded in an output directory
+#[derive(Debug, Clone)]
+pub struct Registry {
+    dir: PathBuf,
+}
+
+impl Registry {
+    pub fn new(output_dir: &Path) -> Self {
+        Self {
+            dir: output_dir.join(EXPERIMENTS_DIR),
+        }
+    }
+
+    fn run_dir(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    /// Where the snapshot of a run's dataset is kept; `evaluate` writes the
+    /// run's quality report next to it
+    pub fn dataset_path(&self, name: &str) -> PathBuf {
+        self.run_dir(name).join(DATASET_FILE)
+    }
+
+    /// Record a run with the dataset it wrote, replacing an earlier run of
+    /// the same name
+    pub fn record(&self, name: &str, manifest: &RunManifest, dataset: &Path) -> Result<Experiment> {
+        check_name(name).map_err(anyhow::Error::msg)?;
+        let items = schema::read_dataset(dataset)?.items;
+        let dir = self.run_dir(name);
+        if dir.exists() {
+            fs::remove_dir_all(&dir)?;
+        }
+        fs::create_dir_all(&dir)?;
+        schema::write_dataset(&self.dataset_path(name), &items)?;
+
+        let experiment = Experiment {
+            name: name.to_string(),
+            manifest: manifest.clone(),
+            stats: DatasetStats::from_items(&items),
+        };
+        fs::write(
+            dir.join(EXPERIMENT_FILE),
+            serde_json::to_string_pretty(&experiment)?,
+        )?;
+        Ok(experiment)
+    }
+
+    /// Names of the recorded runs, sorted
+    pub fn names(&self) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        if !self.dir.is_dir() {
+            return Ok(names);
+        }
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            if entry.path().join(EXPERIMENT_FILE).is_file() {
+                names.push(entry.file_name().to_string_lossy().into_owned());
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// Load a recorded run
+    pub fn load(&self, name: &str) -> Result<Experiment> {
+        let path = self.run_dir(name).join(EXPERIMENT_FILE);
+        if !path.is_file() {
+            let names = self.names()?;
+            anyhow::bail!(
+                "No run named '{}' in {}; recorded runs: {}",
+                name,
+                self.dir.display(),
+                match names.is_empty() {
+                    true => "none (name runs with --run-name)".to_string(),
+                    false => names.join(", "),
+                }
+            );
+        }
+        let content = fs::read_to_string(&path)?;
+        serde_json::from_str(&content).with_context(|| format!("Could not read {:?}", path))
+    }
+
+    /// The quality report of a run, once `evaluate` has scored its snapshot
+    pub fn quality(&self, name: &str) -> Result<Option<QualityReport>> {
+        let path = judge::report_path(&self.dataset_path(name));
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&path)?;
+        Ok(Some(serde_json::from_str(&content)?))
+    }
+}
+
+/// Settings of a run by name: its config, the model of each role, prompt
+/// template hashes and the tool version
+pub fn settings(manifest: &RunManifest) -> BTreeMap<String, String> {
+    let mut settings = BTreeMap::new();
+    if let Value::Object(config) = &manifest.config {
+        for (key, value) in config {
+            if IGNORED_SETTINGS.contains(&key.as_str()) {
+                continue;
+            }
+            let value = match value {
+                Value::Null => continue,
+                Value::String(value) => value.clone(),
+                value => value.to_string(),
+            };
+            settings.insert(key.clone(), value);
+        }
+    }
+    for (role, model) in &manifest.models {
+        settings.insert(format!("model ({})", role), model.clone());
+    }
+    for (name, hash) in &manifest.prompt_templates {
+        let hash = hash.get(..HASH_CHARS).unwrap_or(hash);
+        settings.insert(format!("prompt ({})", name), hash.to_string());
+    }
+    settings.insert("version".to_string(), manifest.tool.version.clone());
+    settings
+}
+
+/// A setting that differs between two runs; `None` where a run lacks it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SettingChange {
+    pub key: String,
+    pub a: Option<String>,
+    pub b: Option<String>,
+}
+
+/// Settings that differ between two runs, by name
+pub fn setting_changes(a: &RunManifest, b: &RunManifest) -> Vec<SettingChange> {
+    let (a, b) = (settings(a), settings(b));
+    let keys: BTreeSet<&String> = a.keys().chain(b.keys()).collect();
+    keys.into_iter()
+        .filter(|key| a.get(*key) != b.get(*key))
+        .map(|key| SettingChange {
+            key: key.clone(),
+            a: a.get(key).cloned(),
+            b: b.get(key).cloned(),
+        })
+        .collect()
+}
+
+/// Questions two datasets share, compared case-insensitively after trimming
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Overlap {
+    pub shared_questions: usize,
+    pub only_a: usize,
+    pub only_b: usize,
+    /// Records with the same ID in both
+    pub shared_ids: usize,
+}
+
+impl Overlap {
+    pub fn between(a: &[ProcessedItem], b: &[ProcessedItem]) -> Self {
+        let questions = |items: &[ProcessedItem]| -> HashSet<String> {
+            items
+                .iter()
+                .map(|item| item.question.trim().to_lowercase())
+                .collect()
+        };
+        let ids = |items: &[ProcessedItem]| -> HashSet<Uuid> {
+            items.iter().filter_map(|item| item.id).collect()
+        };
+        let (questions_a, questions_b) = (questions(a), questions(b));
+        let shared_questions = questions_a.intersection(&questions_b).count();
+        Self {
+            shared_questions,
+            only_a: questions_a.len() - shared_questions,
+            only_b: questions_b.len() - shared_questions,
+            shared_ids: ids(a).intersection(&ids(b)).count(),
+        }
+    }
+
+    /// Shared questions as a share of the distinct questions of both runs
+    /// (Jaccard similarity)
+    pub fn similarity(&self) -> f64 {
+        let union = self.shared_questions + self.only_a + self.only_b;
+        match union {
+            0 => 0.0,
+            union => self.shared_questions as f64 / union as f64,
+        }
+    }
+}
+
+/// How two recorded runs differ
+#[derive(Debug, Clone)]
+pub struct Comparison {
+    pub a: Experiment,
+    pub b: Experiment,
+    pub settings: Vec<SettingChange>,
+    pub quality: (Option<QualityReport>, Option<QualityReport>),
+    pub overlap: Overlap,
+}
+
+impl Comparison {
+    /// Compare two runs of a registry
+    pub fn of(registry: &Registry, a: &str, b: &str) -> Result<Self> {
+        let (a, b) = (registry.load(a)?, registry.load(b)?);
+        let items_a = schema::read_dataset(&registry.dataset_path(&a.name))?.items;
+        let items_b = schema::read_dataset(&registry.dataset_path(&b.name))?.items;
+        Ok(Self {
+            settings: setting_changes(&a.manifest, &b.manifest),
+            quality: (registry.quality(&a.name)?, registry.quality(&b.name)?),
+            overlap: Overlap::between(&items_a, &items_b),
+            a,
+            b,
+        })
+    }
+}
+
+fn metric(f: &mut fmt::Formatter<'_>, label: &str, a: f64, b: f64, decimals: usize) -> fmt::Result {
+    writeln!(
+        f,
+        "  {:<20}{:>10.*} {:>10.*} {:>+10.*}",
+        label,
+        decimals,
+        a,
+        decimals,
+        b,
+        decimals,
+        b - a
+    )
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (a, b) = (&self.a, &self.b);
+        writeln!(f, "Comparing {} with {}", a.name, b.name)?;
+
+        writeln!(f, "\nSettings:")?;
+        if self.settings.is_empty() {
+            writeln!(f, "  (the same)")?;
+        }
+        for change in &self.settings {
+            let value = |value: &Option<String>| value.clone().unwrap_or_else(|| "-".to_string());
+            writeln!(
+                f,
+                "  {}: {} -> {}",
+                change.key,
+                value(&change.a),
+                value(&change.b)
+            )?;
+        }
+
+        writeln!(
+            f,
+            "\n  {:<20}{:>10} {:>10} {:>10}",
+            "", a.name, b.name, "change"
+        )?;
+        let (stats_a, stats_b) = (&a.stats, &b.stats);
+        let count = |count: usize| count as f64;
+        metric(
+            f,
+            "Records",
+            count(stats_a.records),
+            count(stats_b.records),
+            0,
+        )?;
+        metric(
+            f,
+            "Unique questions",
+            count(stats_a.unique_questions),
+            count(stats_b.unique_questions),
+            0,
+        )?;
+        metric(
+            f,
+            "Duplicate questions",
+            count(stats_a.duplicate_questions()),
+            count(stats_b.duplicate_questions()),
+            0,
+        )?;
+        metric(
+            f,
+            "Empty answers",
+            count(stats_a.empty_answers),
+            count(stats_b.empty_answers),
+            0,
+        )?;
+        metric(
+            f,
+            "Avg question words",
+            stats_a.avg_question_words,
+            stats_b.avg_question_words,
+            1,
+        )?;
+        metric(
+            f,
+            "Avg answer words",
+            stats_a.avg_answer_words,
+            stats_b.avg_answer_words,
+            1,
+        )?;
+        match &self.quality {
+            (Some(quality_a), Some(quality_b)) => {
+                metric(f, "Quality", quality_a.overall, quality_b.overall, 2)?;
+                metric(
+                    f,
+                    "Low quality",
+                    count(quality_a.low_quality),
+                    count(quality_b.low_quality),
+                    0,
+                )?;
+            }
+            (quality_a, quality_b) => {
+                writeln!(f, "  Quality: score both runs to compare it:")?;
+                for (experiment, quality) in [(a, quality_a), (b, quality_b)] {
+                    if quality.is_none() {
+                        writeln!(
+                            f,
+                            "    evaluate {}",
+                            Path::new(EXPERIMENTS_DIR)
+                                .join(&experiment.name)
+                                .join(DATASET_FILE)
+                                .display()
+                        )?;
+                    }
+                }
+            }
+        }
+
+        let overlap = &self.overlap;
+        writeln!(
+            f,
+            "\nOverlap: {} questions in both ({:.0}% of all), {} only in {}, {} only in {}",
+            overlap.shared_questions,
+            overlap.similarity() * 100.0,
+            overlap.only_a,
+            a.name,
+            overlap.only_b,
+            b.name
+        )?;
+        write!(f, "Records with the same ID: {}", overlap.shared_ids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn item(question: &str) -> ProcessedItem {
+        let mut item = ProcessedItem::new(question, "An answer");
+        item.assign_id("guide.md", "section");
+        item
+    }
+
+    fn manifest(model: &str, config: Value) -> RunManifest {
+        let mut manifest = RunManifest::new(config);
+        manifest.add_model("generation", model);
+        manifest.add_prompt_template("documentation", "Generate {count} questions");
+        manifest
+    }
+
+    #[test]
+    fn test_check_name() {
+        assert!(check_name("baseline").is_ok());
+        assert!(check_name("llama3-density_1.5").is_ok());
+        for name in ["", ".hidden", "a/b", "../up", "with space"] {
+            assert!(check_name(name).is_err(), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn test_setting_changes() {
+        let a = manifest(
+            "mistral",
+            json!({"output_dir": "a", "run_name": "a", "stream": false, "eval": null}),
+        );
+        let b = manifest(
+            "llama3",
+            json!({"output_dir": "b", "run_name": "b", "stream": false, "eval": 10.0}),
+        );
+        let changes = setting_changes(&a, &b);
+        assert_eq!(
+            changes,
+            [
+                SettingChange {
+                    key: "eval".to_string(),
+                    a: None,
+                    b: Some("10.0".to_string()),
+                },
+                SettingChange {
+                    key: "model (generation)".to_string(),
+                    a: Some("mistral".to_string()),
+                    b: Some("llama3".to_string()),
+                },
+            ]
+        );
+        assert!(setting_changes(&a, &a).is_empty());
+    }
+
+    #[test]
+    fn test_overlap() {
+        let a = [
+            item("What is TLS?"),
+            item("Which port?"),
+            item("How to install?"),
+        ];
+        let b = [
+            item("what is tls? "),
+            item("Which port?"),
+            item("Why Rust?"),
+        ];
+        let overlap = Overlap::between(&a, &b);
+        assert_eq!(overlap.shared_questions, 2);
+        assert_eq!((overlap.only_a, overlap.only_b), (1, 1));
+        // IDs hash the question as written
+        assert_eq!(overlap.shared_ids, 1);
+        assert_eq!(overlap.similarity(), 0.5);
+        assert_eq!(Overlap::default().similarity(), 0.0);
+    }
+
+    #[test]
+    fn test_record_and_compare() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let registry = Registry::new(temp_dir.path());
+        let dataset = temp_dir.path().join("all_qa.jsonl");
+
+        schema::write_dataset(&dataset, &[item("What is TLS?"), item("Which port?")]).unwrap();
+        let mut baseline = manifest("mistral", json!({"stream": false}));
+        baseline.finish(2);
+        registry.record("baseline", &baseline, &dataset).unwrap();
+
+        // The next run overwrites the dataset; the registry keeps a snapshot
+        schema::write_dataset(&dataset, &[item("Which port?")]).unwrap();
+        registry
+            .record(
+                "tuned",
+                &manifest("llama3", json!({"stream": false})),
+                &dataset,
+            )
+            .unwrap();
+        assert_eq!(registry.names().unwrap(), ["baseline", "tuned"]);
+
+        let quality = QualityReport {
+            overall: 4.0,
+            ..Default::default()
+        };
+        fs::write(
+            judge::report_path(&registry.dataset_path("tuned")),
+            serde_json::to_string(&quality).unwrap(),
+        )
+        .unwrap();
+
+        let comparison = Comparison::of(&registry, "baseline", "tuned").unwrap();
+        assert_eq!(comparison.a.stats.records, 2);
+        assert_eq!(comparison.b.stats.records, 1);
+        assert_eq!(comparison.settings.len(), 1);
+        assert_eq!(comparison.quality, (None, Some(quality)));
+        assert_eq!(comparison.overlap.shared_questions, 1);
+        let shown = comparison.to_string();
+        assert!(shown.contains("model (generation): mistral -> llama3"));
+        assert!(shown.contains("evaluate experiments/baseline/dataset.jsonl"));
+
+        let missing = registry.load("other").unwrap_err().to_string();
+        assert!(missing.contains("baseline, tuned"));
+    }
+}
@@ -0,0 +1,363 @@
+//! Conversion of generated datasets into the formats fine-tuning tools expect.
+
+use anyhow::{anyhow, Result};
+use serde_json::{json, Value};
+use std::fmt;
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::difficulty::{self, Difficulty};
+use crate::processor::ProcessedItem;
+
+/// Output format of the `export` command
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExportFormat {
+    /// One bare record per line, without the schema header
+    #[default]
+    Jsonl,
+    /// JSON array of `instruction`/`input`/`output` objects
+    Alpaca,
+    /// JSONL of `conversations` with `human`/`gpt` turns
+    ShareGpt,
+    /// JSONL of chat `messages`, as used by OpenAI fine-tuning
+    OpenAi,
+    /// JSONL of `prompt`/`chosen`/`rejected` pairs for preference tuning
+    /// (DPO), one per rejected answer; records without any are left out
+    Preference,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 5] = [
+        ExportFormat::Jsonl,
+        ExportFormat::Alpaca,
+        ExportFormat::ShareGpt,
+        ExportFormat::OpenAi,
+        ExportFormat::Preference,
+    ];
+
+    /// Name used on the command line
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExportFormat::Jsonl => "jsonl",
+            ExportFormat::Alpaca => "alpaca",
+            ExportFormat::ShareGpt => "sharegpt",
+            ExportFormat::OpenAi => "openai",
+            ExportFormat::Preference => "preference",
+        }
+    }
+
+    /// File extension of the exported file
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Alpaca => "json",
+            _ => "jsonl",
+        }
+    }
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "jsonl" => Ok(ExportFormat::Jsonl),
+            "alpaca" => Ok(ExportFormat::Alpaca),
+            "sharegpt" => Ok(ExportFormat::ShareGpt),
+            "openai" => Ok(ExportFormat::OpenAi),
+            "preference" => Ok(ExportFormat::Preference),
+            other => Err(anyhow!(
+                "Unknown export format '{}' (expected jsonl, alpaca, sharegpt, openai or preference)",
+                other
+            )),
+        }
+    }
+}
+
+/// How `export --curriculum` arranges records by difficulty
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Curriculum {
+    /// One file per difficulty: `NAME.easy.EXT`, `NAME.medium.EXT`, `NAME.hard.EXT`
+    Shards,
+    /// One file from easy to hard, keeping the dataset order within each
+    Sorted,
+}
+
+impl Curriculum {
+    pub const ALL: [Curriculum; 2] = [Curriculum::Shards, Curriculum::Sorted];
+
+    /// Name used on the command line
+    pub fn name(&self) -> &'static str {
+        match self {
+            Curriculum::Shards => "shards",
+            Curriculum::Sorted => "sorted",
+        }
+    }
+}
+
+impl fmt::Display for Curriculum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Curriculum {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "shards" => Ok(Curriculum::Shards),
+            "sorted" => Ok(Curriculum::Sorted),
+            other => Err(anyhow!(
+                "Unknown curriculum '{}' (expected shards or sorted)",
+                other
+            )),
+        }
+    }
+}
+
+/// The user turn for an item: the question, preceded by its context if any
+fn prompt(item: &ProcessedItem) -> String {
+    match &item.context {
+        Some(context) => format!("{}\n\n{}", context, item.question),
+        None => item.question.clone(),
+    }
+}
+
+/// The records an item becomes in the given format: one, except for
+/// preference pairs, of which there is one per rejected answer
+pub fn export_records(item: &ProcessedItem, format: ExportFormat) -> Vec<Value> {
+    let record = match format {
+        ExportFormat::Jsonl => json!(item),
+        ExportFormat::Alpaca => json!({
+            "instruction": item.question,
+            "input": item.context.clone().unwrap_or_default(),
+            "output": item.answer,
+        }),
+        ExportFormat::ShareGpt => json!({
+            "conversations": [
+                { "from": "human", "value": prompt(item) },
+                { "from": "gpt", "value": item.answer },
+            ]
+        }),
+        ExportFormat::OpenAi => json!({
+            "messages": [
+                { "role": "user", "content": prompt(item) },
+                { "role": "assistant", "content": item.answer },
+            ]
+        }),
+        ExportFormat::Preference => {
+            return item
+                .rejected
+                .iter()
+                .flatten()
+                .map(|rejected| {
+                    json!({
+                        "prompt": prompt(item),
+                        "chosen": item.answer,
+                        "rejected": rejected,
+                    })
+                })
+                .collect()
+        }
+    };
+    vec![record]
+}
+
+/// Write `items` to `path` in the given format, returning how many records
+/// were written
+pub fn export_dataset(path: &Path, items: &[ProcessedItem], format: ExportFormat) -> Result<usize> {
+    let mut writer = BufWriter::new(fs::File::create(path)?);
+    let records: Vec<Value> = items
+        .iter()
+        .flat_map(|item| export_records(item, format))
+        .collect();
+    if format == ExportFormat::Alpaca {
+        serde_json::to_writer_pretty(&mut writer, &records)?;
+    } else {
+        for record in &records {
+            serde_json::to_writer(&mut writer, record)?;
+            writeln!(writer)?;
+        }
+    }
+    writer.flush()?;
+    Ok(records.len())
+}
+
+/// Where the shard of `path` holding one difficulty is written:
+/// `NAME.DIFFICULTY.EXT`
+pub fn shard_path(path: &Path, difficulty: Difficulty) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("export");
+    let name = match path.extension().and_then(|ext| ext.to_str()) {
+        Some(extension) => format!("{}.{}.{}", stem, difficulty, extension),
+        None => format!("{}.{}", stem, difficulty),
+    };
+    path.with_file_name(name)
+}
+
+/// Write `items` ordered for curriculum learning, returning each file
+/// written with its number of records
+///
+/// Records without a difficulty label get an estimate. Every shard is
+/// written, even an empty one, so trainers find all three.
+pub fn export_curriculum(
+    path: &Path,
+    items: &[ProcessedItem],
+    format: ExportFormat,
+    curriculum: Curriculum,
+) -> Result<Vec<(PathBuf, usize)>> {
+    match curriculum {
+        Curriculum::Sorted => {
+            let mut sorted = items.to_vec();
+            sorted.sort_by_key(difficulty::of);
+            Ok(vec![(
+                path.to_path_buf(),
+                export_dataset(path, &sorted, format)?,
+            )])
+        }
+        Curriculum::Shards => Difficulty::ALL
+            .into_iter()
+            .map(|level| {
+                let shard: Vec<ProcessedItem> = items
+                    .iter()
+                    .filter(|item| difficulty::of(item) == level)
+                    .cloned()
+                    .collect();
+                let shard_path = shard_path(path, level);
+                let written = export_dataset(&shard_path, &shard, format)?;
+                Ok((shard_path, written))
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(context: Option<&str>) -> ProcessedItem {
+        ProcessedItem {
+            question: "How do I enable TLS?".to_string(),
+            answer: "Set use_tls to true.".to_string(),
+            context: context.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_format_names_round_trip() {
+        for format in ExportFormat::ALL {
+            assert_eq!(format.to_string().parse::<ExportFormat>().unwrap(), format);
+        }
+        assert!("parquet".parse::<ExportFormat>().is_err());
+    }
+
+    #[test]
+    fn test_export_records() {
+        let alpaca = export_records(&item(Some("TLS docs")), ExportFormat::Alpaca).remove(0);
+        assert_eq!(alpaca["instruction"], "How do I enable TLS?");
+        assert_eq!(alpaca["input"], "TLS docs");
+
+        let openai = export_records(&item(Some("TLS docs")), ExportFormat::OpenAi).remove(0);
+        assert_eq!(
+            openai["messages"][0]["content"],
+            "TLS docs\n\nHow do I enable TLS?"
+        );
+        assert_eq!(openai["messages"][1]["role"], "assistant");
+
+        let sharegpt = export_records(&item(None), ExportFormat::ShareGpt).remove(0);
+        assert_eq!(
+            sharegpt["conversations"][0]["value"],
+            "How do I enable TLS?"
+        );
+    }
+
+    #[test]
+    fn test_export_dataset() {
+        let dir = tempfile::tempdir().unwrap();
+        let items = vec![item(None), item(Some("TLS docs"))];
+
+        let jsonl = dir.path().join("out.jsonl");
+        assert_eq!(
+            export_dataset(&jsonl, &items, ExportFormat::OpenAi).unwrap(),
+            2
+        );
+        assert_eq!(fs::read_to_string(&jsonl).unwrap().lines().count(), 2);
+
+        let alpaca = dir.path().join("out.json");
+        export_dataset(&alpaca, &items, ExportFormat::Alpaca).unwrap();
+        let records: Vec<Value> =
+            serde_json::from_str(&fs::read_to_string(&alpaca).unwrap()).unwrap();
+        assert_eq!(records.len(), 2);
+    }
+
+    #[test]
+    fn test_export_curriculum() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut hard = item(None);
+        hard.question = "Hard one".to_string();
+        hard.difficulty = Some(Difficulty::Hard);
+        let mut medium = item(None);
+        medium.question = "Medium one".to_string();
+        medium.difficulty = Some(Difficulty::Medium);
+        // Unlabelled, and estimated easy
+        let items = vec![hard, item(None), medium];
+
+        let path = dir.path().join("export_jsonl.jsonl");
+        let written =
+            export_curriculum(&path, &items, ExportFormat::Jsonl, Curriculum::Sorted).unwrap();
+        assert_eq!(written, [(path.clone(), 3)]);
+        let questions: Vec<String> = fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| {
+                serde_json::from_str::<ProcessedItem>(line)
+                    .unwrap()
+                    .question
+            })
+            .collect();
+        assert_eq!(
+            questions,
+            ["How do I enable TLS?", "Medium one", "Hard one"]
+        );
+
+        let shards =
+            export_curriculum(&path, &items, ExportFormat::Jsonl, Curriculum::Shards).unwrap();
+        let counts: Vec<usize> = shards.iter().map(|(_, count)| *count).collect();
+        assert_eq!(counts, [1, 1, 1]);
+        assert_eq!(shards[0].0, dir.path().join("export_jsonl.easy.jsonl"));
+        assert_eq!(
+            shard_path(Path::new("out/export_alpaca.json"), Difficulty::Hard),
+            Path::new("out/export_alpaca.hard.json")
+        );
+    }
+
+    #[test]
+    fn test_export_preference_pairs() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut sampled = item(Some("TLS docs"));
+        sampled.rejected = Some(vec!["Restart it.".to_string(), "No idea.".to_string()]);
+        let items = vec![item(None), sampled];
+
+        let path = dir.path().join("out.jsonl");
+        assert_eq!(
+            export_dataset(&path, &items, ExportFormat::Preference).unwrap(),
+            2
+        );
+        let content = fs::read_to_string(&path).unwrap();
+        let pair: Value = serde_json::from_str(content.lines().next().unwrap()).unwrap();
+        assert_eq!(pair["prompt"], "TLS docs\n\nHow do I enable TLS?");
+        assert_eq!(pair["chosen"], "Set use_tls to true.");
+        assert_eq!(pair["rejected"], "Restart it.");
+    }
+}
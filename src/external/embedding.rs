@@ -1,15 +1,82 @@
-use anyhow::Result;
-use ollama_rs::{generation::options::GenerationOptions, Ollama};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use tokio::sync::OnceCell;
+use tracing::Instrument;
 use url::Url;
 
+use crate::external::embedding_cache::CachedEmbedder;
 use crate::external::error::ExternalError;
+use crate::external::http::{http_client, status_error};
+use crate::external::retry::RetryPolicy;
+
+#[cfg(test)]
+use mockall::automock;
+
+/// Backend used to compute embeddings
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EmbeddingProvider {
+    /// Ollama's native embeddings API
+    #[default]
+    Ollama,
+    /// Any OpenAI-compatible `/v1/embeddings` endpoint
+    OpenAI,
+    /// A sentence-transformers model run in-process with candle
+    Local,
+}
+
+impl fmt::Display for EmbeddingProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            EmbeddingProvider::Ollama => "ollama",
+            EmbeddingProvider::OpenAI => "openai",
+            EmbeddingProvider::Local => "local",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for EmbeddingProvider {
+    type Err = ExternalError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "ollama" => Ok(EmbeddingProvider::Ollama),
+            "openai" => Ok(EmbeddingProvider::OpenAI),
+            "local" => Ok(EmbeddingProvider::Local),
+            other => Err(ExternalError::ConfigError(format!(
+                "Unknown embedding provider '{}' (expected ollama, openai or local)",
+                other
+            ))),
+        }
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EmbeddingConfig {
+    #[serde(default)]
+    pub provider: EmbeddingProvider,
     pub model: String,
     pub host: String,
     pub port: u16,
+    /// Bearer token sent with every request, for OpenAI-compatible endpoints
+    /// or Ollama behind an authenticating proxy
+    #[serde(default, skip_serializing)]
+    pub api_key: Option<String>,
+    /// Extra headers sent with every request; may carry credentials
+    #[serde(default, skip_serializing)]
+    pub headers: HashMap<String, String>,
+    /// Directory of the on-disk embedding cache; `None` disables caching
+    #[serde(default)]
+    pub cache_dir: Option<String>,
+    /// Timeout and retries for each embedding request
+    #[serde(default)]
+    pub retry: RetryPolicy,
 }
 
 impl EmbeddingConfig {
@@ -31,101 +98,455 @@ impl EmbeddingConfig {
 impl Default for EmbeddingConfig {
     fn default() -> Self {
         Self {
+            provider: EmbeddingProvider::default(),
             model: "nomic-embed-text".to_string(),
             host: "localhost".to_string(),
             port: 11434,
+            api_key: None,
+            headers: HashMap::new(),
+            cache_dir: None,
+            retry: RetryPolicy::default(),
+        }
+    }
+}
+
+/// A backend that turns text into embedding vectors
+#[cfg_attr(test, automock)]
+#[async_trait]
+pub trait Embedder: Send + Sync {
+    /// Embed a single text
+    async fn embed(&self, text: &str) -> Result<Vec<f32>>;
+
+    /// Embed several texts, returning one vector per input in the same order
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let mut embeddings = Vec::with_capacity(texts.len());
+        for text in texts {
+            embeddings.push(self.embed(text).await?);
+        }
+        Ok(embeddings)
+    }
+
+    /// Name of the model producing the embeddings
+    fn model(&self) -> &str;
+
+    /// Length of the vectors this model produces, found by embedding a probe text
+    async fn dimension(&self) -> Result<usize> {
+        let embedding = self.embed(DIMENSION_PROBE).await?;
+        if embedding.is_empty() {
+            return Err(anyhow!(
+                "Embedding model {} returned an empty vector",
+                self.model()
+            ));
+        }
+        Ok(embedding.len())
+    }
+}
+
+/// Texts embedded per request by [`embed_all`]
+pub const EMBED_BATCH: usize = 64;
+
+/// Embed any number of texts in batches of [`EMBED_BATCH`]
+pub async fn embed_all(embedder: &dyn Embedder, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+    let mut embeddings = Vec::with_capacity(texts.len());
+    for batch in texts.chunks(EMBED_BATCH) {
+        embeddings.extend(embedder.embed_batch(batch).await?);
+    }
+    Ok(embeddings)
+}
+
+/// Text embedded once to discover a model's output dimension
+const DIMENSION_PROBE: &str = "dimension probe";
+
+#[derive(Serialize)]
+struct OllamaEmbeddingRequest<'a> {
+    model: &'a str,
+    prompt: &'a str,
+}
+
+#[derive(Deserialize)]
+struct OllamaEmbeddingResponse {
+    embedding: Vec<f32>,
+}
+
+/// Embeddings from Ollama's native API
+pub struct OllamaEmbedder {
+    client: reqwest::Client,
+    endpoint: String,
+    model: String,
+}
+
+impl OllamaEmbedder {
+    pub fn new(config: &EmbeddingConfig) -> Result<Self> {
+        Ok(Self {
+            client: http_client(config.api_key.as_deref(), &config.headers)?,
+            endpoint: format!("{}/api/embeddings", config.get_url()?),
+            model: config.model.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl Embedder for OllamaEmbedder {
+    async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        let response = self
+            .client
+            .post(&self.endpoint)
+            .json(&OllamaEmbeddingRequest {
+                model: &self.model,
+                prompt: text,
+            })
+            .send()
+            .await
+            .map_err(|e| ExternalError::ConnectionError(e.to_string()))?;
+
+        if !response.status().is_success() {
+            return Err(status_error(response).await.into());
         }
+        let response: OllamaEmbeddingResponse = response.json().await.map_err(|e| {
+            ExternalError::OllamaError(format!("Invalid embeddings response: {}", e))
+        })?;
+        Ok(response.embedding)
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+}
+
+#[derive(Serialize)]
+struct OpenAIEmbeddingRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+}
+
+#[derive(Deserialize)]
+struct OpenAIEmbeddingResponse {
+    data: Vec<OpenAIEmbedding>,
+}
+
+#[derive(Deserialize)]
+struct OpenAIEmbedding {
+    #[serde(default)]
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+/// Embeddings from an OpenAI-compatible `/v1/embeddings` endpoint
+pub struct OpenAIEmbedder {
+    client: reqwest::Client,
+    endpoint: String,
+    model: String,
+}
+
+impl OpenAIEmbedder {
+    pub fn new(config: &EmbeddingConfig) -> Result<Self> {
+        Ok(Self {
+            client: http_client(config.api_key.as_deref(), &config.headers)?,
+            endpoint: format!("{}/v1/embeddings", config.get_url()?),
+            model: config.model.clone(),
+        })
+    }
+
+    /// Order the returned vectors by their input index
+    fn parse_response(body: &str, expected: usize) -> Result<Vec<Vec<f32>>> {
+        let mut response: OpenAIEmbeddingResponse = serde_json::from_str(body)
+            .map_err(|e| anyhow!("Invalid embeddings response: {}", e))?;
+        if response.data.len() != expected {
+            return Err(anyhow!(
+                "Expected {} embeddings, got {}",
+                expected,
+                response.data.len()
+            ));
+        }
+        response.data.sort_by_key(|item| item.index);
+        Ok(response
+            .data
+            .into_iter()
+            .map(|item| item.embedding)
+            .collect())
+    }
+}
+
+#[async_trait]
+impl Embedder for OpenAIEmbedder {
+    async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        self.embed_batch(&[text.to_string()])
+            .await?
+            .pop()
+            .ok_or_else(|| anyhow!("No embedding returned"))
+    }
+
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        if texts.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let response = self
+            .client
+            .post(&self.endpoint)
+            .json(&OpenAIEmbeddingRequest {
+                model: &self.model,
+                input: texts,
+            })
+            .send()
+            .await
+            .map_err(|e| ExternalError::ConnectionError(e.to_string()))?;
+        if !response.status().is_success() {
+            return Err(status_error(response).await.into());
+        }
+        let body = response
+            .text()
+            .await
+            .map_err(|e| ExternalError::ConnectionError(e.to_string()))?;
+
+        Self::parse_response(&body, texts.len())
+    }
+
+    fn model(&self) -> &str {
+        &self.model
     }
 }
 
-/// Wrapper for Ollama embedding engine
+/// Embedding engine dispatching to the backend selected by `EmbeddingConfig.provider`
 pub struct EmbeddingEngine {
-    client: Ollama,
-    config: EmbeddingConfig,
+    backend: Box<dyn Embedder>,
+    dimension: OnceCell<usize>,
+    retry: RetryPolicy,
 }
 
 impl EmbeddingEngine {
     /// Create a new embedding engine with the given configuration
     pub async fn new(config: EmbeddingConfig) -> Result<Self> {
-        let url = config.get_url()?;
-        let url = Url::parse(&url)
-            .map_err(|e| ExternalError::ConfigError(format!("Invalid URL: {}", e)))?;
+        let backend: Box<dyn Embedder> = match config.provider {
+            EmbeddingProvider::Ollama => Box::new(OllamaEmbedder::new(&config)?),
+            EmbeddingProvider::OpenAI => Box::new(OpenAIEmbedder::new(&config)?),
+            EmbeddingProvider::Local => local_embedder(&config).await?,
+        };
+        let backend = match &config.cache_dir {
+            Some(dir) => Box::new(CachedEmbedder::open(backend, Path::new(dir))?),
+            None => backend,
+        };
+        Ok(Self::from_embedder(backend).with_retry(config.retry))
+    }
 
-        let client = Ollama::new(
-            url.host_str().unwrap_or("localhost").to_string(),
-            config.port,
-        );
+    /// Wrap an already constructed backend
+    pub fn from_embedder(backend: Box<dyn Embedder>) -> Self {
+        Self {
+            backend,
+            dimension: OnceCell::new(),
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    /// Replace the timeout and retry settings applied to every backend call
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
 
-        Ok(Self { client, config })
+    fn span(&self, texts: usize) -> tracing::Span {
+        tracing::info_span!("embedding", model = self.backend.model(), texts)
     }
 
     /// Generate embeddings for a text
     pub async fn generate_embeddings(&self, text: &str) -> Result<Vec<f32>> {
-        let response = self
-            .client
-            .generate_embeddings(
-                self.config.model.clone(),
-                text.to_string(),
-                Some(GenerationOptions::default()),
-            )
+        self.embed(text).await
+    }
+}
+
+#[async_trait]
+impl Embedder for EmbeddingEngine {
+    async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        self.retry
+            .run("Embedding", || self.backend.embed(text))
+            .instrument(self.span(1))
             .await
-            .map_err(|e| ExternalError::OllamaError(e.to_string()))?;
+            .inspect_err(|e| tracing::error!("{:#}", e))
+    }
+
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        self.retry
+            .run("Batch embedding", || self.backend.embed_batch(texts))
+            .instrument(self.span(texts.len()))
+            .await
+            .inspect_err(|e| tracing::error!("{:#}", e))
+    }
 
-        // Convert from Vec<f64> to Vec<f32>
-        Ok(response.embeddings.into_iter().map(|x| x as f32).collect())
+    fn model(&self) -> &str {
+        self.backend.model()
     }
+
+    /// Probed once and remembered for the lifetime of the engine
+    async fn dimension(&self) -> Result<usize> {
+        self.dimension
+            .get_or_try_init(|| {
+                self.retry
+                    .run("Embedding dimension probe", || self.backend.dimension())
+            })
+            .await
+            .copied()
+    }
+}
+
+#[cfg(feature = "local-embeddings")]
+async fn local_embedder(config: &EmbeddingConfig) -> Result<Box<dyn Embedder>> {
+    let model = config.model.clone();
+    let embedder =
+        tokio::task::spawn_blocking(move || super::local_embedding::LocalEmbedder::load(&model))
+            .await??;
+    Ok(Box::new(embedder))
+}
+
+#[cfg(not(feature = "local-embeddings"))]
+async fn local_embedder(_config: &EmbeddingConfig) -> Result<Box<dyn Embedder>> {
+    Err(ExternalError::ConfigError(
+        "The local embedding provider requires building with --features local-embeddings"
+            .to_string(),
+    )
+    .into())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use mockall::automock;
 
-    #[automock]
-    trait EmbeddingClient {
-        async fn generate_embedding(&self, text: &str) -> Result<Vec<f32>>;
+    fn config(host: &str) -> EmbeddingConfig {
+        EmbeddingConfig {
+            host: host.to_string(),
+            model: "test".to_string(),
+            ..Default::default()
+        }
     }
 
     #[test]
     fn test_url_generation() {
         // Test with plain hostname
-        let config = EmbeddingConfig {
-            host: "localhost".to_string(),
-            port: 11434,
-            model: "test".to_string(),
-        };
-        assert_eq!(config.get_url().unwrap(), "http://localhost:11434");
+        assert_eq!(
+            config("localhost").get_url().unwrap(),
+            "http://localhost:11434"
+        );
 
         // Test with http:// prefix
-        let config = EmbeddingConfig {
-            host: "http://example.com".to_string(),
-            port: 11434,
-            model: "test".to_string(),
-        };
-        assert_eq!(config.get_url().unwrap(), "http://example.com:11434");
+        assert_eq!(
+            config("http://example.com").get_url().unwrap(),
+            "http://example.com:11434"
+        );
 
         // Test with https:// prefix
-        let config = EmbeddingConfig {
-            host: "https://example.com".to_string(),
-            port: 11434,
-            model: "test".to_string(),
-        };
-        assert_eq!(config.get_url().unwrap(), "https://example.com:11434");
+        assert_eq!(
+            config("https://example.com").get_url().unwrap(),
+            "https://example.com:11434"
+        );
+    }
+
+    #[test]
+    fn test_provider_parsing() {
+        assert_eq!(
+            "ollama".parse::<EmbeddingProvider>().unwrap(),
+            EmbeddingProvider::Ollama
+        );
+        assert_eq!(
+            "OpenAI".parse::<EmbeddingProvider>().unwrap(),
+            EmbeddingProvider::OpenAI
+        );
+        assert_eq!(
+            "local".parse::<EmbeddingProvider>().unwrap(),
+            EmbeddingProvider::Local
+        );
+        assert!("bert".parse::<EmbeddingProvider>().is_err());
+
+        let parsed: EmbeddingConfig =
+            serde_json::from_str(r#"{"model":"m","host":"localhost","port":11434}"#).unwrap();
+        assert_eq!(parsed.provider, EmbeddingProvider::Ollama);
+    }
+
+    #[test]
+    fn test_openai_response_ordering() {
+        let body = r#"{"data":[
+            {"index":1,"embedding":[0.0,1.0]},
+            {"index":0,"embedding":[1.0,0.0]}
+        ]}"#;
+        let embeddings = OpenAIEmbedder::parse_response(body, 2).unwrap();
+        assert_eq!(embeddings, vec![vec![1.0, 0.0], vec![0.0, 1.0]]);
+
+        assert!(OpenAIEmbedder::parse_response(body, 3).is_err());
     }
 
     #[tokio::test]
-    async fn test_embedding_generation() {
-        let mut mock = MockEmbeddingClient::new();
+    async fn test_openai_endpoint() {
+        let embedder = OpenAIEmbedder::new(&EmbeddingConfig {
+            provider: EmbeddingProvider::OpenAI,
+            host: "https://api.openai.com".to_string(),
+            port: 443,
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(
+            embedder.endpoint,
+            "https://api.openai.com:443/v1/embeddings"
+        );
+        assert!(embedder.embed_batch(&[]).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_engine_delegates_to_backend() {
+        let mut mock = MockEmbedder::new();
+        mock.expect_embed()
+            .times(1)
+            .returning(|text| Ok(vec![text.len() as f32]));
+        mock.expect_embed_batch()
+            .times(1)
+            .returning(|texts| Ok(texts.iter().map(|t| vec![t.len() as f32]).collect()));
+        mock.expect_model().return_const("mock".to_string());
+
+        let engine = EmbeddingEngine::from_embedder(Box::new(mock));
+        assert_eq!(engine.model(), "mock");
+        assert_eq!(engine.generate_embeddings("abc").await.unwrap(), vec![3.0]);
+        let batch = engine
+            .embed_batch(&["a".to_string(), "ab".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(batch, vec![vec![1.0], vec![2.0]]);
+    }
+
+    #[tokio::test]
+    async fn test_dimension_is_probed_once() {
+        let mut mock = MockEmbedder::new();
+        mock.expect_dimension().times(1).returning(|| Ok(768));
+
+        let engine = EmbeddingEngine::from_embedder(Box::new(mock));
+        assert_eq!(engine.dimension().await.unwrap(), 768);
+        assert_eq!(engine.dimension().await.unwrap(), 768);
+    }
 
-        mock.expect_generate_embedding()
+    #[tokio::test]
+    async fn test_engine_retries_failed_requests() {
+        let mut mock = MockEmbedder::new();
+        let mut seq = mockall::Sequence::new();
+        mock.expect_embed()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_| Err(ExternalError::ConnectionError("connection reset".into()).into()));
+        mock.expect_embed()
             .times(1)
-            .returning(|_| Ok(vec![0.1, 0.2, 0.3]));
+            .in_sequence(&mut seq)
+            .returning(|_| Ok(vec![1.0]));
 
-        let text = "This is a test text for embedding generation.";
-        let embedding = mock.generate_embedding(text).await.unwrap();
+        let engine = EmbeddingEngine::from_embedder(Box::new(mock)).with_retry(RetryPolicy {
+            max_retries: 1,
+            backoff_ms: 0,
+            ..Default::default()
+        });
+        assert_eq!(engine.embed("text").await.unwrap(), vec![1.0]);
+    }
 
-        assert!(!embedding.is_empty());
-        assert_eq!(embedding.len(), 3);
-        assert_eq!(embedding, vec![0.1, 0.2, 0.3]);
+    #[cfg(not(feature = "local-embeddings"))]
+    #[tokio::test]
+    async fn test_local_provider_requires_feature() {
+        let config = EmbeddingConfig {
+            provider: EmbeddingProvider::Local,
+            ..Default::default()
+        };
+        assert!(EmbeddingEngine::new(config).await.is_err());
     }
 }
@@ -0,0 +1,192 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use sled::Db;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::external::embedding::Embedder;
+use crate::hash::hash_text;
+
+/// Embedder wrapper that persists every embedding on disk
+///
+/// Entries are keyed by model name and the SHA-256 of the text, so
+/// re-embedding an unchanged corpus never reaches the backend, and
+/// switching models never returns vectors from a different one.
+pub struct CachedEmbedder {
+    inner: Box<dyn Embedder>,
+    db: Db,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+impl CachedEmbedder {
+    /// Open (or create) a cache at the given directory in front of `inner`
+    pub fn open(inner: Box<dyn Embedder>, path: &Path) -> Result<Self> {
+        // Same reasoning as GraphStore: a background flusher would keep the
+        // database locked after the cache is dropped.
+        let db = sled::Config::new()
+            .path(path)
+            .flush_every_ms(None)
+            .open()
+            .with_context(|| format!("Failed to open embedding cache at {:?}", path))?;
+        Ok(Self {
+            inner,
+            db,
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+        })
+    }
+
+    /// Number of embeddings served from the cache
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Number of embeddings computed by the backend
+    pub fn misses(&self) -> usize {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    /// Number of cached embeddings, across all models
+    pub fn len(&self) -> usize {
+        self.db.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.db.is_empty()
+    }
+
+    fn key(&self, text: &str) -> Vec<u8> {
+        let mut key = self.inner.model().as_bytes().to_vec();
+        key.push(0);
+        key.extend_from_slice(hash_text(text).as_bytes());
+        key
+    }
+
+    fn get(&self, text: &str) -> Result<Option<Vec<f32>>> {
+        Ok(self.db.get(self.key(text))?.map(|bytes| {
+            bytes
+                .chunks_exact(4)
+                .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+                .collect()
+        }))
+    }
+
+    fn put(&self, text: &str, embedding: &[f32]) -> Result<()> {
+        let bytes: Vec<u8> = embedding.iter().flat_map(|x| x.to_le_bytes()).collect();
+        self.db.insert(self.key(text), bytes)?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Embedder for CachedEmbedder {
+    async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        if let Some(embedding) = self.get(text)? {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(embedding);
+        }
+
+        let embedding = self.inner.embed(text).await?;
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        self.put(text, &embedding)?;
+        self.db.flush_async().await?;
+        Ok(embedding)
+    }
+
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let mut embeddings = Vec::with_capacity(texts.len());
+        let mut missing = Vec::new();
+        for (i, text) in texts.iter().enumerate() {
+            let cached = self.get(text)?;
+            if cached.is_none() {
+                missing.push(i);
+            }
+            embeddings.push(cached);
+        }
+        self.hits
+            .fetch_add(texts.len() - missing.len(), Ordering::Relaxed);
+
+        if !missing.is_empty() {
+            let batch: Vec<String> = missing.iter().map(|&i| texts[i].clone()).collect();
+            let computed = self.inner.embed_batch(&batch).await?;
+            self.misses.fetch_add(missing.len(), Ordering::Relaxed);
+            for (&i, embedding) in missing.iter().zip(computed) {
+                self.put(&texts[i], &embedding)?;
+                embeddings[i] = Some(embedding);
+            }
+            self.db.flush_async().await?;
+        }
+
+        Ok(embeddings
+            .into_iter()
+            .map(Option::unwrap_or_default)
+            .collect())
+    }
+
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::external::embedding::MockEmbedder;
+
+    fn mock(model: &str, calls: usize) -> Box<MockEmbedder> {
+        let mut mock = MockEmbedder::new();
+        mock.expect_embed()
+            .times(calls)
+            .returning(|text| Ok(vec![text.len() as f32, 0.5]));
+        mock.expect_embed_batch()
+            .returning(|texts| Ok(texts.iter().map(|t| vec![t.len() as f32, 0.5]).collect()));
+        mock.expect_model().return_const(model.to_string());
+        Box::new(mock)
+    }
+
+    #[tokio::test]
+    async fn test_repeated_text_is_served_from_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = CachedEmbedder::open(mock("m", 1), dir.path()).unwrap();
+
+        assert_eq!(cache.embed("hello").await.unwrap(), vec![5.0, 0.5]);
+        assert_eq!(cache.embed("hello").await.unwrap(), vec![5.0, 0.5]);
+        assert_eq!((cache.hits(), cache.misses()), (1, 1));
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_cache_survives_reopen_and_is_per_model() {
+        let dir = tempfile::tempdir().unwrap();
+        {
+            let cache = CachedEmbedder::open(mock("m", 1), dir.path()).unwrap();
+            cache.embed("hello").await.unwrap();
+        }
+
+        let cache = CachedEmbedder::open(mock("m", 0), dir.path()).unwrap();
+        assert_eq!(cache.embed("hello").await.unwrap(), vec![5.0, 0.5]);
+        assert_eq!(cache.hits(), 1);
+        drop(cache);
+
+        let other = CachedEmbedder::open(mock("other", 1), dir.path()).unwrap();
+        other.embed("hello").await.unwrap();
+        assert_eq!(other.misses(), 1);
+        assert_eq!(other.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_batch_only_embeds_misses() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = CachedEmbedder::open(mock("m", 1), dir.path()).unwrap();
+        cache.embed("a").await.unwrap();
+
+        let texts = vec!["a".to_string(), "bbb".to_string(), "cc".to_string()];
+        let embeddings = cache.embed_batch(&texts).await.unwrap();
+        assert_eq!(
+            embeddings,
+            vec![vec![1.0, 0.5], vec![3.0, 0.5], vec![2.0, 0.5]]
+        );
+        assert_eq!((cache.hits(), cache.misses()), (1, 3));
+    }
+}
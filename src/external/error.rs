@@ -1,3 +1,4 @@
+use reqwest::StatusCode;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -11,9 +12,42 @@ pub enum ExternalError {
     #[error("Ollama error: {0}")]
     OllamaError(String),
 
+    #[error("Timed out: {0}")]
+    Timeout(String),
+
+    #[error("Request failed with {status}: {message}")]
+    HttpStatus { status: StatusCode, message: String },
+
     #[error("Vector DB error: {0}")]
     VectorDBError(String),
 
+    #[error(
+        "Embedding model produces {actual}-dimensional vectors but the vector DB expects \
+         {expected}; set QDRANT_VECTOR_SIZE={actual} or leave it unset to detect it"
+    )]
+    DimensionMismatch { expected: usize, actual: usize },
+
+    #[error(
+        "Collection '{collection}' cannot be reused: {reason}. Recreate it with --recreate \
+         (QDRANT_RECREATE=true) or choose another QDRANT_COLLECTION"
+    )]
+    CollectionMismatch { collection: String, reason: String },
+
     #[error(transparent)]
     Other(#[from] anyhow::Error),
 }
+
+impl ExternalError {
+    /// Whether the call may succeed when tried again: the service could not be
+    /// reached or did not answer in time, is rate limiting (429) or failed
+    /// itself (5xx)
+    pub fn is_transient(&self) -> bool {
+        match self {
+            ExternalError::ConnectionError(_) | ExternalError::Timeout(_) => true,
+            ExternalError::HttpStatus { status, .. } => {
+                *status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+            }
+            _ => false,
+        }
+    }
+}
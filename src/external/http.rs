@@ -0,0 +1,88 @@
+use anyhow::Result;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION};
+use std::collections::HashMap;
+
+use crate::external::error::ExternalError;
+
+/// HTTP client that sends the bearer token and extra headers with every request
+///
+/// Meant for servers behind an authenticating reverse proxy.
+pub fn http_client(
+    api_key: Option<&str>,
+    headers: &HashMap<String, String>,
+) -> Result<reqwest::Client> {
+    let mut defaults = HeaderMap::new();
+    for (name, value) in headers {
+        let name = HeaderName::from_bytes(name.as_bytes()).map_err(|e| {
+            ExternalError::ConfigError(format!("Invalid header name '{}': {}", name, e))
+        })?;
+        let value = HeaderValue::from_str(value).map_err(|e| {
+            ExternalError::ConfigError(format!("Invalid value for header '{}': {}", name, e))
+        })?;
+        defaults.insert(name, value);
+    }
+    if let Some(key) = api_key {
+        let mut value = HeaderValue::from_str(&format!("Bearer {}", key))
+            .map_err(|e| ExternalError::ConfigError(format!("Invalid API key: {}", e)))?;
+        value.set_sensitive(true);
+        defaults.insert(AUTHORIZATION, value);
+    }
+
+    Ok(reqwest::Client::builder()
+        .default_headers(defaults)
+        .build()
+        .map_err(|e| ExternalError::ConfigError(e.to_string()))?)
+}
+
+/// The error of a response with an unsuccessful status, with its body as
+/// the message
+pub(crate) async fn status_error(response: reqwest::Response) -> ExternalError {
+    let status = response.status();
+    let message = response.text().await.unwrap_or_default();
+    ExternalError::HttpStatus { status, message }
+}
+
+/// Parse `Name=value` pairs separated by commas, as used by the `*_HEADERS`
+/// environment variables
+pub fn parse_headers(spec: &str) -> Result<HashMap<String, String>> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((name, value)) if !name.trim().is_empty() => {
+                Ok((name.trim().to_string(), value.trim().to_string()))
+            }
+            _ => Err(ExternalError::ConfigError(format!(
+                "Invalid header '{}' (expected Name=value)",
+                pair
+            ))
+            .into()),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_headers() {
+        let headers = parse_headers("X-Team=docs, CF-Access-Client-Id = abc=def ,").unwrap();
+        assert_eq!(headers.len(), 2);
+        assert_eq!(headers["X-Team"], "docs");
+        assert_eq!(headers["CF-Access-Client-Id"], "abc=def");
+
+        assert!(parse_headers("").unwrap().is_empty());
+        assert!(parse_headers("no-value").is_err());
+        assert!(parse_headers("=value").is_err());
+    }
+
+    #[test]
+    fn test_http_client_rejects_invalid_headers() {
+        let headers = HashMap::from([("X-Team".to_string(), "docs".to_string())]);
+        assert!(http_client(Some("token"), &headers).is_ok());
+
+        let headers = HashMap::from([("Bad Name".to_string(), "x".to_string())]);
+        assert!(http_client(None, &headers).is_err());
+    }
+}
@@ -1,20 +1,81 @@
 use anyhow::Result;
-use ollama_rs::{
-    generation::{completion::request::GenerationRequest, options::GenerationOptions},
-    Ollama,
-};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
 use url::Url;
 
 use crate::external::error::ExternalError;
+use crate::external::mock::MockBackend;
+use crate::external::provider::{ChatRequest, ModelProvider, OllamaProvider};
+use crate::external::retry::RetryPolicy;
+use crate::external::stream::TokenStream;
+use crate::processor::{question_prompt, DefaultOllamaClient, ProcessedItem};
+
+/// Backend that generates text
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LLMBackend {
+    /// An Ollama server
+    #[default]
+    Ollama,
+    /// Template replies made in-process, for offline runs and tests
+    Mock,
+}
+
+impl fmt::Display for LLMBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            LLMBackend::Ollama => "ollama",
+            LLMBackend::Mock => "mock",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for LLMBackend {
+    type Err = ExternalError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "ollama" => Ok(LLMBackend::Ollama),
+            "mock" => Ok(LLMBackend::Mock),
+            other => Err(ExternalError::ConfigError(format!(
+                "Unknown LLM backend '{}' (expected ollama or mock)",
+                other
+            ))),
+        }
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LLMConfig {
+    #[serde(default)]
+    pub backend: LLMBackend,
     pub model: String,
     pub host: String,
     pub port: u16,
     pub temperature: f32,
     pub top_p: f32,
+    /// Bearer token for Ollama behind an authenticating proxy
+    #[serde(default, skip_serializing)]
+    pub api_key: Option<String>,
+    /// Extra headers sent with every request; may carry credentials
+    #[serde(default, skip_serializing)]
+    pub headers: HashMap<String, String>,
+    /// Timeout and retries for each request
+    #[serde(default = "default_retry")]
+    pub retry: RetryPolicy,
+}
+
+/// Generation is slow on large models, so a request gets longer than other calls
+pub const DEFAULT_LLM_TIMEOUT_SECS: u64 = 300;
+
+fn default_retry() -> RetryPolicy {
+    RetryPolicy {
+        timeout_secs: DEFAULT_LLM_TIMEOUT_SECS,
+        ..Default::default()
+    }
 }
 
 impl LLMConfig {
@@ -31,58 +92,150 @@ impl LLMConfig {
 
         Ok(url)
     }
+
+    /// Provider for the configured backend
+    pub fn provider(&self) -> Result<Box<dyn ModelProvider>> {
+        Ok(match self.backend {
+            LLMBackend::Ollama => Box::new(OllamaProvider::from_config(self)?),
+            LLMBackend::Mock => Box::new(MockBackend::new(self.model.clone())),
+        })
+    }
 }
 
 impl Default for LLMConfig {
     fn default() -> Self {
         Self {
+            backend: LLMBackend::default(),
             model: "mistral".to_string(),
             host: "localhost".to_string(),
             port: 11434,
             temperature: 0.7,
             top_p: 0.9,
+            api_key: None,
+            headers: HashMap::new(),
+            retry: default_retry(),
+        }
+    }
+}
+
+/// Author of a chat message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatRole {
+    System,
+    User,
+    Assistant,
+}
+
+/// One message of a chat conversation, in Ollama's wire format
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: ChatRole,
+    pub content: String,
+}
+
+impl ChatMessage {
+    pub fn system(content: impl Into<String>) -> Self {
+        Self {
+            role: ChatRole::System,
+            content: content.into(),
+        }
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        Self {
+            role: ChatRole::User,
+            content: content.into(),
+        }
+    }
+
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Self {
+            role: ChatRole::Assistant,
+            content: content.into(),
         }
     }
 }
 
 /// Wrapper for Ollama LLM engine
 pub struct LLMEngine {
-    client: Ollama,
-    config: LLMConfig,
+    provider: Box<dyn ModelProvider>,
 }
 
 impl LLMEngine {
     /// Create a new LLM engine with the given configuration
     pub async fn new(config: LLMConfig) -> Result<Self> {
-        let url = config.get_url()?;
-        let url = Url::parse(&url)
-            .map_err(|e| ExternalError::ConfigError(format!("Invalid URL: {}", e)))?;
+        Ok(Self::from_provider(config.provider()?))
+    }
 
-        let client = Ollama::new(
-            url.host_str().unwrap_or("localhost").to_string(),
-            config.port,
-        );
+    /// Wrap an already constructed provider
+    pub fn from_provider(provider: Box<dyn ModelProvider>) -> Self {
+        Self { provider }
+    }
+
+    pub fn provider(&self) -> &dyn ModelProvider {
+        self.provider.as_ref()
+    }
 
-        Ok(Self { client, config })
+    /// Generate text completion as a stream of tokens
+    ///
+    /// Tokens arrive as the model produces them; dropping the stream
+    /// aborts the request.
+    pub async fn generate_stream(&self, prompt: &str) -> Result<TokenStream> {
+        self.provider.generate_stream(prompt).await
     }
 
     /// Generate text completion
     pub async fn generate(&self, prompt: &str) -> Result<String> {
-        let mut request = GenerationRequest::new(self.config.model.clone(), prompt.to_string());
-
-        let options = GenerationOptions::default()
-            .temperature(self.config.temperature)
-            .top_p(self.config.top_p);
+        self.provider.generate(prompt).await
+    }
 
-        request.options = Some(options);
+    /// Send a conversation and return the assistant's reply
+    pub async fn chat(&self, messages: Vec<ChatMessage>) -> Result<String> {
+        self.provider
+            .chat(ChatRequest {
+                messages,
+                format: None,
+                max_tokens: None,
+            })
+            .await
+    }
 
-        let response = self
-            .client
-            .generate(request)
+    /// Like `chat`, but constrains the reply to valid JSON
+    pub async fn chat_json(&self, messages: Vec<ChatMessage>) -> Result<String> {
+        self.provider
+            .chat(ChatRequest {
+                messages,
+                format: Some(serde_json::json!("json")),
+                max_tokens: None,
+            })
             .await
-            .map_err(|e| ExternalError::OllamaError(e.to_string()))?;
+    }
+
+    /// Generate questions with the same prompts and JSON shape as the processor
+    pub async fn generate_questions(
+        &self,
+        content: &str,
+        target_count: usize,
+    ) -> Result<Vec<ProcessedItem>> {
+        let (system_msg, user_msg) = question_prompt(content, target_count);
+        let reply = self
+            .chat_json(vec![
+                ChatMessage::system(system_msg),
+                ChatMessage::user(user_msg),
+            ])
+            .await?;
+
+        #[derive(Deserialize)]
+        struct QuestionResponse {
+            questions: Vec<ProcessedItem>,
+        }
 
-        Ok(response.response)
+        let parsed: QuestionResponse =
+            serde_json::from_str(&DefaultOllamaClient::sanitize_json(&reply)).map_err(|e| {
+                ExternalError::OllamaError(format!("Failed to parse questions: {}", e))
+            })?;
+        Ok(parsed.questions)
     }
 
     /// Generate question-answer pair from context
@@ -163,6 +316,7 @@ impl LLMEngine {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::external::provider::MockModelProvider;
     use mockall::automock;
 
     #[automock]
@@ -180,35 +334,93 @@ mod tests {
     fn test_url_generation() {
         // Test with plain hostname
         let config = LLMConfig {
+            backend: LLMBackend::Ollama,
             host: "localhost".to_string(),
             port: 11434,
             model: "test".to_string(),
             temperature: 0.7,
             top_p: 0.9,
+            api_key: None,
+            headers: HashMap::new(),
+            retry: RetryPolicy::default(),
         };
         assert_eq!(config.get_url().unwrap(), "http://localhost:11434");
 
         // Test with http:// prefix
         let config = LLMConfig {
+            backend: LLMBackend::Ollama,
             host: "http://example.com".to_string(),
             port: 11434,
             model: "test".to_string(),
             temperature: 0.7,
             top_p: 0.9,
+            api_key: None,
+            headers: HashMap::new(),
+            retry: RetryPolicy::default(),
         };
         assert_eq!(config.get_url().unwrap(), "http://example.com:11434");
 
         // Test with https:// prefix
         let config = LLMConfig {
+            backend: LLMBackend::Ollama,
             host: "https://example.com".to_string(),
             port: 11434,
             model: "test".to_string(),
             temperature: 0.7,
             top_p: 0.9,
+            api_key: None,
+            headers: HashMap::new(),
+            retry: RetryPolicy::default(),
         };
         assert_eq!(config.get_url().unwrap(), "https://example.com:11434");
     }
 
+    #[test]
+    fn test_chat_messages() {
+        let messages = vec![
+            ChatMessage::system("Be brief."),
+            ChatMessage::user("What is Rust?"),
+            ChatMessage::assistant("A language."),
+        ];
+        let json = serde_json::to_value(&messages).unwrap();
+        assert_eq!(json[0]["role"], "system");
+        assert_eq!(json[1]["content"], "What is Rust?");
+        assert_eq!(json[2]["role"], "assistant");
+    }
+
+    #[tokio::test]
+    async fn test_generate_questions_uses_json_chat() {
+        let mut provider = MockModelProvider::new();
+        provider
+            .expect_chat()
+            .withf(|request| {
+                request.format == Some(serde_json::json!("json"))
+                    && request.messages[0].role == ChatRole::System
+                    && request.messages[1].content.starts_with("Generate exactly 2")
+            })
+            .times(1)
+            .returning(|_| {
+                Ok(r#"{"questions":[{"question":"Q1","answer":"A1"},{"question":"Q2","answer":"A2"}]}"#.to_string())
+            });
+
+        let engine = LLMEngine::from_provider(Box::new(provider));
+        let items = engine.generate_questions("# Guide", 2).await.unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[1].answer, "A2");
+    }
+
+    #[test]
+    fn test_credentials_are_not_serialized() {
+        let config = LLMConfig {
+            api_key: Some("secret".to_string()),
+            headers: HashMap::from([("X-Token".to_string(), "hidden".to_string())]),
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&config).unwrap();
+        assert!(!json.contains("secret"));
+        assert!(!json.contains("hidden"));
+    }
+
     #[tokio::test]
     async fn test_text_generation() {
         let mut mock = MockLLMClient::new();
@@ -0,0 +1,147 @@
+//! In-process sentence embeddings with candle, for running without an
+//! embedding server. Models are BERT-style sentence-transformers checkpoints
+//! fetched from the Hugging Face hub, e.g. `sentence-transformers/all-MiniLM-L6-v2`.
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use candle_core::{DType, Device, Tensor};
+use candle_nn::VarBuilder;
+use candle_transformers::models::bert::{BertModel, Config as BertConfig, DTYPE};
+use hf_hub::api::sync::ApiBuilder;
+use std::sync::Arc;
+use tokenizers::{PaddingParams, Tokenizer, TruncationParams};
+
+use crate::external::embedding::Embedder;
+use crate::external::error::ExternalError;
+
+/// Model suggested when the configured name is not a hub repository
+pub const DEFAULT_LOCAL_MODEL: &str = "sentence-transformers/all-MiniLM-L6-v2";
+
+/// A BERT sentence-embedding model with mean pooling and L2 normalisation
+pub struct LocalEmbedder {
+    model: Arc<BertModel>,
+    tokenizer: Arc<Tokenizer>,
+    name: String,
+}
+
+impl LocalEmbedder {
+    /// Download (or reuse the cached copy of) a model from the Hugging Face hub
+    ///
+    /// `model` must name a hub repository as `org/model`; an Ollama model
+    /// name such as `nomic-embed-text` is refused rather than replaced, since
+    /// another model would give vectors of another dimension.
+    pub fn load(model: &str) -> Result<Self> {
+        if !model.contains('/') {
+            return Err(ExternalError::ConfigError(format!(
+                "EMBEDDING_PROVIDER=local needs OLLAMA_EMBEDDING_MODEL to name a Hugging Face \
+                 model as org/model, e.g. {}, not {:?}",
+                DEFAULT_LOCAL_MODEL, model
+            ))
+            .into());
+        }
+        let name = model.to_string();
+        // HF_TOKEN (e.g. resolved from the keyring) takes precedence over the
+        // token saved by `huggingface-cli login`
+        let mut api = ApiBuilder::from_env();
+        if let Some(token) = std::env::var("HF_TOKEN").ok().filter(|t| !t.is_empty()) {
+            api = api.with_token(Some(token));
+        }
+        let repo = api.build()?.model(name.clone());
+        let config_path = repo.get("config.json")?;
+        let tokenizer_path = repo.get("tokenizer.json")?;
+        let weights_path = repo.get("model.safetensors")?;
+
+        let config: BertConfig = serde_json::from_str(&std::fs::read_to_string(config_path)?)?;
+        let mut tokenizer = Tokenizer::from_file(tokenizer_path).map_err(|e| anyhow!(e))?;
+        tokenizer.with_padding(Some(PaddingParams::default()));
+        // Longer passages are truncated to the model's context window
+        tokenizer
+            .with_truncation(Some(TruncationParams {
+                max_length: config.max_position_embeddings,
+                ..Default::default()
+            }))
+            .map_err(|e| anyhow!(e))?;
+
+        let vb =
+            unsafe { VarBuilder::from_mmaped_safetensors(&[weights_path], DTYPE, &Device::Cpu)? };
+        let model = BertModel::load(vb, &config)?;
+
+        Ok(Self {
+            model: Arc::new(model),
+            tokenizer: Arc::new(tokenizer),
+            name,
+        })
+    }
+
+    fn embed_sync(
+        model: &BertModel,
+        tokenizer: &Tokenizer,
+        texts: &[String],
+    ) -> Result<Vec<Vec<f32>>> {
+        let encodings = tokenizer
+            .encode_batch(texts.to_vec(), true)
+            .map_err(|e| anyhow!(e))?;
+        let device = Device::Cpu;
+        let ids = encodings
+            .iter()
+            .map(|e| Tensor::new(e.get_ids(), &device))
+            .collect::<candle_core::Result<Vec<_>>>()?;
+        let masks = encodings
+            .iter()
+            .map(|e| Tensor::new(e.get_attention_mask(), &device))
+            .collect::<candle_core::Result<Vec<_>>>()?;
+        let ids = Tensor::stack(&ids, 0)?;
+        let mask = Tensor::stack(&masks, 0)?;
+        let token_type_ids = ids.zeros_like()?;
+
+        let hidden = model.forward(&ids, &token_type_ids, Some(&mask))?;
+
+        // Mean pooling over real (non-padding) tokens
+        let mask = mask.to_dtype(DType::F32)?.unsqueeze(2)?;
+        let summed = hidden.broadcast_mul(&mask)?.sum(1)?;
+        let counts = mask.sum(1)?;
+        let pooled = summed.broadcast_div(&counts)?;
+        let norms = pooled.sqr()?.sum_keepdim(1)?.sqrt()?;
+        let normalized = pooled.broadcast_div(&norms)?;
+
+        Ok(normalized.to_vec2::<f32>()?)
+    }
+}
+
+#[async_trait]
+impl Embedder for LocalEmbedder {
+    async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        self.embed_batch(&[text.to_string()])
+            .await?
+            .pop()
+            .ok_or_else(|| anyhow!("No embedding returned"))
+    }
+
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        if texts.is_empty() {
+            return Ok(Vec::new());
+        }
+        let model = Arc::clone(&self.model);
+        let tokenizer = Arc::clone(&self.tokenizer);
+        let texts = texts.to_vec();
+        tokio::task::spawn_blocking(move || Self::embed_sync(&model, &tokenizer, &texts)).await?
+    }
+
+    fn model(&self) -> &str {
+        &self.name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_refuses_names_without_org() {
+        let error = LocalEmbedder::load("nomic-embed-text").err().unwrap();
+        assert!(matches!(
+            error.downcast_ref::<ExternalError>(),
+            Some(ExternalError::ConfigError(message)) if message.contains(DEFAULT_LOCAL_MODEL)
+        ));
+    }
+}
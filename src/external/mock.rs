@@ -0,0 +1,433 @@
+//! A generation backend that answers in-process, without a model or network,
+//! so the whole pipeline can run offline (`LLM_BACKEND=mock`): in tests, in
+//! CI and when trying out settings.
+//!
+//! Replies are made from templates and are the same for the same request.
+//! Question requests get questions about the headings of the content, with
+//! the first sentence under each heading as the answer; judges get a fixed
+//! verdict, and anything else gets the first sentences of its input.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::stream;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::external::llm::{ChatMessage, ChatRole};
+use crate::external::provider::{ChatRequest, ModelProvider, ProviderUsage};
+use crate::external::stream::TokenStream;
+use crate::sections::count_words;
+
+/// Model name reported when none is configured
+pub const MOCK_MODEL: &str = "mock";
+
+/// Endpoint reported for the backend, which sends no requests
+pub const MOCK_ENDPOINT: &str = "mock://";
+
+/// Questions asked about each heading, in order; `{}` is the heading
+const QUESTION_TEMPLATES: [&str; 3] = [
+    "What does the documentation say about {}?",
+    "What is the purpose of {}?",
+    "How is {} used?",
+];
+
+/// Difficulty of the questions of each template
+const DIFFICULTIES: [&str; 3] = ["easy", "medium", "hard"];
+
+/// Score the mock judge gives every criterion
+const JUDGE_SCORE: u8 = 4;
+
+/// Words of a line kept as the topic of content without headings
+const TOPIC_WORDS: usize = 6;
+
+/// Marker the question prompts use for the content
+const CONTENT_MARKER: &str = "Content: ";
+
+/// Marker of the questions later batches must not repeat
+const ASKED_MARKER: &str = "These questions were already asked";
+
+/// Deterministic in-process stand-in for a generation model
+#[derive(Debug, Default)]
+pub struct MockBackend {
+    model: String,
+    requests: AtomicU64,
+    prompt_tokens: AtomicU64,
+    completion_tokens: AtomicU64,
+}
+
+impl MockBackend {
+    pub fn new(model: impl Into<String>) -> Self {
+        Self {
+            model: model.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Count a request and its words as tokens
+    fn record(&self, prompt: &str, reply: String) -> String {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.prompt_tokens
+            .fetch_add(count_words(prompt) as u64, Ordering::Relaxed);
+        self.completion_tokens
+            .fetch_add(count_words(&reply) as u64, Ordering::Relaxed);
+        reply
+    }
+}
+
+/// Stream a reply word by word
+fn token_stream(reply: String) -> TokenStream {
+    let tokens: Vec<Result<String>> = reply
+        .split_inclusive(' ')
+        .map(|token| Ok(token.to_string()))
+        .collect();
+    Box::pin(stream::iter(tokens))
+}
+
+/// Number after `exactly` in a question prompt, e.g. "Generate exactly 5"
+fn requested_count(prompt: &str) -> Option<usize> {
+    let (_, rest) = prompt.split_once("exactly ")?;
+    let digits: String = rest.chars().take_while(char::is_ascii_digit).collect();
+    digits.parse().ok()
+}
+
+/// Sentences of some text, each on one line, leaving out headings
+fn sentences(text: &str) -> Vec<String> {
+    let text = text
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .flat_map(str::split_whitespace)
+        .collect::<Vec<_>>()
+        .join(" ");
+    text.split_inclusive(". ")
+        .map(|sentence| sentence.trim().to_string())
+        .filter(|sentence| !sentence.is_empty())
+        .collect()
+}
+
+fn first_sentence(text: &str) -> String {
+    sentences(text).into_iter().next().unwrap_or_default()
+}
+
+/// Headings of markdown content, each with the text under it; content
+/// without headings is one section named after its first words
+pub fn sections(content: &str) -> Vec<(String, String)> {
+    let mut sections: Vec<(String, String)> = Vec::new();
+    for line in content.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('#') {
+            let heading = trimmed.trim_start_matches('#').trim();
+            if !heading.is_empty() {
+                sections.push((heading.to_string(), String::new()));
+                continue;
+            }
+        }
+        if let Some((_, text)) = sections.last_mut() {
+            text.push_str(line);
+            text.push('\n');
+        }
+    }
+    if sections.is_empty() {
+        let topic: Vec<&str> = content.split_whitespace().take(TOPIC_WORDS).collect();
+        if !topic.is_empty() {
+            sections.push((topic.join(" "), content.to_string()));
+        }
+    }
+    sections
+}
+
+/// Questions about the headings of `content`, skipping those already
+/// asked
+///
+/// Each template is asked about every heading before the next template;
+/// after them come numbered points about the sentences under each heading,
+/// so there are always `count` questions, as a model asked for exactly
+/// that many would give.
+pub fn questions(content: &str, count: usize, asked: &[&str], rubric: bool) -> serde_json::Value {
+    let sections = sections(content);
+    let answer_about = |heading: &str, answer: String| match answer.is_empty() {
+        true => format!("{} is covered in the documentation.", heading),
+        false => answer,
+    };
+    let templated =
+        QUESTION_TEMPLATES
+            .iter()
+            .zip(DIFFICULTIES)
+            .flat_map(|(template, difficulty)| {
+                sections.iter().map(move |(heading, text)| {
+                    let answer = answer_about(heading, first_sentence(text));
+                    (template.replace("{}", heading), answer, difficulty)
+                })
+            });
+    let points: Vec<(&String, String)> = sections
+        .iter()
+        .flat_map(|(heading, text)| {
+            let sentences = match sentences(text) {
+                sentences if sentences.is_empty() => vec![String::new()],
+                sentences => sentences,
+            };
+            sentences
+                .into_iter()
+                .map(move |sentence| (heading, sentence))
+        })
+        .collect();
+    let numbered = points
+        .iter()
+        .cycle()
+        .enumerate()
+        .map(|(i, (heading, sentence))| {
+            let question = format!("What is point {} about {}?", i + 1, heading);
+            (question, answer_about(heading, sentence.clone()), "medium")
+        });
+
+    let questions: Vec<serde_json::Value> = templated
+        .chain(numbered)
+        .filter(|(question, _, _)| !asked.contains(&question.as_str()))
+        .take(count)
+        .map(|(question, answer, difficulty)| {
+            let mut record = serde_json::json!({
+                "question": question,
+                "answer": answer,
+                "difficulty": difficulty,
+            });
+            if rubric {
+                record["rubric"] = serde_json::json!([answer]);
+            }
+            record
+        })
+        .collect();
+    serde_json::json!({ "questions": questions })
+}
+
+/// Reply to a question prompt, if `prompt` is one
+fn question_reply(prompt: &str, rubric: bool) -> Option<String> {
+    let count = requested_count(prompt)?;
+    let (_, content) = prompt.split_once(CONTENT_MARKER)?;
+    let (content, asked) = match content.split_once(ASKED_MARKER) {
+        Some((content, asked)) => (content, asked),
+        None => (content, ""),
+    };
+    let asked: Vec<&str> = asked
+        .lines()
+        .filter_map(|line| line.strip_prefix("- "))
+        .collect();
+    Some(questions(content, count, &asked, rubric).to_string())
+}
+
+/// Whether a reply format requires the given field
+fn requires(format: Option<&serde_json::Value>, field: &str) -> bool {
+    format
+        .and_then(|format| format["required"].as_array())
+        .is_some_and(|required| required.iter().any(|name| name == field))
+}
+
+/// Question-answer pairs in the `Question:`/`Answer:` lines that raw
+/// prompts about a context ask for
+fn labelled_pairs(prompt: &str) -> String {
+    let context = prompt.split_once("Context:").map_or(prompt, |(_, c)| c);
+    let count = prompt
+        .split_once("generate ")
+        .and_then(|(_, rest)| rest.split_whitespace().next()?.parse().ok())
+        .unwrap_or(1);
+    let reply = questions(context, count, &[], false);
+    reply["questions"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|pair| {
+            format!(
+                "Question: {}\nAnswer: {}\n",
+                pair["question"].as_str().unwrap_or_default(),
+                pair["answer"].as_str().unwrap_or_default()
+            )
+        })
+        .collect()
+}
+
+/// Reply to a raw prompt
+fn completion(prompt: &str) -> String {
+    if prompt.contains("Question: <question>") {
+        return labelled_pairs(prompt);
+    }
+    question_reply(prompt, false).unwrap_or_else(|| first_sentence(prompt))
+}
+
+/// Reply to a conversation
+fn chat_reply(request: &ChatRequest) -> String {
+    let format = request.format.as_ref();
+    let user = request
+        .messages
+        .iter()
+        .rev()
+        .find(|message| message.role == ChatRole::User)
+        .map_or("", |message| message.content.as_str());
+
+    if requires(format, "correctness") {
+        return serde_json::json!({
+            "correctness": JUDGE_SCORE,
+            "relevance": JUDGE_SCORE,
+            "clarity": JUDGE_SCORE,
+            "reason": "Scored by the mock backend.",
+        })
+        .to_string();
+    }
+    if requires(format, "terms") {
+        return serde_json::json!({ "terms": [] }).to_string();
+    }
+    // Evaluation questions must come with the rubric their item schema requires
+    let items = format.map(|format| &format["properties"]["questions"]["items"]);
+    if let Some(reply) = question_reply(user, requires(items, "rubric")) {
+        return reply;
+    }
+    // Answers to a question about content are its first sentence
+    match user.split_once("Question: ") {
+        Some((content, _)) => first_sentence(content.trim_start_matches(CONTENT_MARKER)),
+        None => first_sentence(user),
+    }
+}
+
+/// All the text sent in a conversation, for usage accounting
+fn prompt_text(messages: &[ChatMessage]) -> String {
+    messages
+        .iter()
+        .map(|message| message.content.as_str())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[async_trait]
+impl ModelProvider for MockBackend {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn endpoint(&self) -> &str {
+        MOCK_ENDPOINT
+    }
+
+    async fn generate(&self, prompt: &str) -> Result<String> {
+        Ok(self.record(prompt, completion(prompt)))
+    }
+
+    async fn generate_stream(&self, prompt: &str) -> Result<TokenStream> {
+        Ok(token_stream(self.generate(prompt).await?))
+    }
+
+    async fn chat(&self, request: ChatRequest) -> Result<String> {
+        let reply = chat_reply(&request);
+        Ok(self.record(&prompt_text(&request.messages), reply))
+    }
+
+    async fn chat_stream(&self, request: ChatRequest) -> Result<TokenStream> {
+        Ok(token_stream(self.chat(request).await?))
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>> {
+        Ok(vec![self.model.clone()])
+    }
+
+    async fn pull_model(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn usage(&self) -> ProviderUsage {
+        ProviderUsage {
+            requests: self.requests.load(Ordering::Relaxed),
+            failures: 0,
+            prompt_tokens: self.prompt_tokens.load(Ordering::Relaxed),
+            completion_tokens: self.completion_tokens.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::judge::Judge;
+    use crate::processor::{question_prompt, DefaultOllamaClient, OllamaClient, ProcessedItem};
+    use futures::StreamExt;
+    use std::sync::Arc;
+
+    const GUIDE: &str = "# Installation\nRun the installer. It takes a minute.\n\n\
+                         ## Configuration\nSettings live in config.toml.\n";
+
+    #[test]
+    fn test_sections() {
+        assert_eq!(
+            sections(GUIDE),
+            [
+                (
+                    "Installation".to_string(),
+                    "Run the installer. It takes a minute.\n\n".to_string()
+                ),
+                (
+                    "Configuration".to_string(),
+                    "Settings live in config.toml.\n".to_string()
+                ),
+            ]
+        );
+        assert_eq!(
+            sections("Plain text about caching")[0].0,
+            "Plain text about caching"
+        );
+        assert!(sections("  ").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_questions_from_headings() {
+        let client = DefaultOllamaClient::from_provider(Arc::new(MockBackend::new(MOCK_MODEL)));
+        let items = client.generate_questions(GUIDE, 3).await.unwrap();
+        let questions: Vec<&str> = items.iter().map(|item| item.question.as_str()).collect();
+        assert_eq!(
+            questions,
+            [
+                "What does the documentation say about Installation?",
+                "What does the documentation say about Configuration?",
+                "What is the purpose of Installation?",
+            ]
+        );
+        assert_eq!(items[0].answer, "Run the installer.");
+        assert_eq!(items[1].answer, "Settings live in config.toml.");
+
+        // The same request gets the same reply, and past the templates come
+        // numbered points about each sentence
+        let again = client.generate_questions(GUIDE, 3).await.unwrap();
+        assert_eq!(again[2].question, items[2].question);
+        let many = client.generate_questions(GUIDE, 10).await.unwrap();
+        assert_eq!(many.len(), 10);
+        assert_eq!(many[7].question, "What is point 2 about Installation?");
+        assert_eq!(many[7].answer, "It takes a minute.");
+
+        let eval = client.generate_eval(GUIDE, 1).await.unwrap();
+        assert_eq!(
+            eval[0].rubric.as_deref(),
+            Some(&["Run the installer.".to_string()][..])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_other_requests() {
+        let backend = MockBackend::new("offline");
+        let item = ProcessedItem::new("Q", "A");
+        let judgement = Judge::new(&backend).score(&item).await.unwrap();
+        assert_eq!(judgement.overall, JUDGE_SCORE as f64);
+
+        let client = DefaultOllamaClient::from_provider(Arc::new(MockBackend::new(MOCK_MODEL)));
+        let answer = client.answer(GUIDE, "How do I install it?").await.unwrap();
+        assert_eq!(answer, "Run the installer.");
+        assert!(client.extract_terms(GUIDE).await.unwrap().is_empty());
+
+        let (_, prompt) = question_prompt(GUIDE, 1);
+        let streamed: Vec<String> = backend
+            .generate_stream(&prompt)
+            .await
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+            .await;
+        assert!(streamed.concat().contains("about Installation?"));
+
+        assert_eq!(backend.list_models().await.unwrap(), ["offline"]);
+        let usage = backend.usage();
+        assert_eq!(usage.requests, 2);
+        assert!(usage.prompt_tokens > 0 && usage.completion_tokens > 0);
+    }
+}
@@ -1,9 +1,31 @@
-mod embedding;
+pub(crate) mod embedding;
+mod embedding_cache;
 pub mod error;
+mod http;
 mod llm;
+#[cfg(feature = "local-embeddings")]
+mod local_embedding;
+pub mod mock;
+mod provider;
+mod retry;
+pub(crate) mod stream;
 pub mod vectordb;
 
-pub use embedding::{EmbeddingConfig, EmbeddingEngine};
+pub use embedding::{
+    embed_all, Embedder, EmbeddingConfig, EmbeddingEngine, EmbeddingProvider, OllamaEmbedder,
+    OpenAIEmbedder,
+};
+pub use embedding_cache::CachedEmbedder;
 pub use error::ExternalError;
-pub use llm::{LLMConfig, LLMEngine};
-pub use vectordb::{VectorDB, VectorDBConfig};
+pub(crate) use http::status_error;
+pub use http::{http_client, parse_headers};
+pub use llm::{ChatMessage, ChatRole, LLMBackend, LLMConfig, LLMEngine};
+#[cfg(feature = "local-embeddings")]
+pub use local_embedding::LocalEmbedder;
+pub use mock::MockBackend;
+pub use provider::{
+    model_matches, ChatRequest, ModelProvider, OllamaProvider, ProviderUsage, DEFAULT_KEEP_ALIVE,
+};
+pub use retry::RetryPolicy;
+pub use stream::TokenStream;
+pub use vectordb::{ScrollPage, SearchFilter, SearchHit, StoredPoint, VectorDB, VectorDBConfig};
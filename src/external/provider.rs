@@ -0,0 +1,550 @@
+//! The single client every generation request goes through, so the
+//! processor, RAG generation and `LLMEngine` share configuration, auth,
+//! timeouts, retries and usage accounting.
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use futures::{future::ready, StreamExt};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tracing::Instrument;
+
+use crate::external::error::ExternalError;
+use crate::external::http::{http_client, status_error};
+use crate::external::llm::{ChatMessage, LLMConfig};
+use crate::external::retry::RetryPolicy;
+use crate::external::stream::{ndjson_stream, TokenStream};
+
+#[cfg(test)]
+use mockall::automock;
+
+/// How long Ollama keeps the model loaded after a request unless configured otherwise
+pub const DEFAULT_KEEP_ALIVE: &str = "30m";
+
+/// A conversation to send to the model
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChatRequest {
+    pub messages: Vec<ChatMessage>,
+    /// `"json"` for any JSON reply, or a JSON schema the reply must follow
+    pub format: Option<serde_json::Value>,
+    /// Most tokens the reply may have (Ollama's `num_predict`); without it
+    /// the model's own limit applies
+    pub max_tokens: Option<usize>,
+}
+
+/// Requests and tokens used through a provider so far
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProviderUsage {
+    pub requests: u64,
+    pub failures: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+#[derive(Debug, Default)]
+struct UsageCounters {
+    requests: AtomicU64,
+    failures: AtomicU64,
+    prompt_tokens: AtomicU64,
+    completion_tokens: AtomicU64,
+}
+
+impl UsageCounters {
+    fn record_tokens(&self, prompt: u64, completion: u64) {
+        self.prompt_tokens.fetch_add(prompt, Ordering::Relaxed);
+        self.completion_tokens
+            .fetch_add(completion, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> ProviderUsage {
+        ProviderUsage {
+            requests: self.requests.load(Ordering::Relaxed),
+            failures: self.failures.load(Ordering::Relaxed),
+            prompt_tokens: self.prompt_tokens.load(Ordering::Relaxed),
+            completion_tokens: self.completion_tokens.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A server hosting generation models
+#[cfg_attr(test, automock)]
+#[async_trait]
+pub trait ModelProvider: Send + Sync {
+    /// Name of the generation model
+    fn model(&self) -> &str;
+
+    /// Base URL requests are sent to
+    fn endpoint(&self) -> &str;
+
+    /// Complete a raw prompt
+    async fn generate(&self, prompt: &str) -> Result<String>;
+
+    /// Complete a raw prompt, yielding tokens as they are produced
+    async fn generate_stream(&self, prompt: &str) -> Result<TokenStream>;
+
+    /// Send a conversation and return the assistant's reply
+    async fn chat(&self, request: ChatRequest) -> Result<String>;
+
+    /// Send a conversation, yielding the reply's tokens as they are produced;
+    /// dropping the stream aborts generation
+    async fn chat_stream(&self, request: ChatRequest) -> Result<TokenStream>;
+
+    /// Names of the models installed on the server
+    async fn list_models(&self) -> Result<Vec<String>>;
+
+    /// Download the generation model, printing progress as it arrives
+    async fn pull_model(&self) -> Result<()>;
+
+    /// Pull the generation model unless the server already has it
+    async fn ensure_model(&self) -> Result<()> {
+        let installed = self.list_models().await?;
+        if installed
+            .iter()
+            .any(|name| model_matches(name, self.model()))
+        {
+            return Ok(());
+        }
+        println!(
+            "Model {} not found, pulling it from the registry...",
+            self.model()
+        );
+        self.pull_model().await
+    }
+
+    /// Requests and tokens used so far
+    fn usage(&self) -> ProviderUsage;
+}
+
+/// Whether an installed model name satisfies the requested one
+///
+/// Ollama lists untagged models as `name:latest`, so `mistral` matches
+/// `mistral:latest`.
+pub fn model_matches(installed: &str, wanted: &str) -> bool {
+    let with_tag = |name: &str| {
+        if name.contains(':') {
+            name.to_string()
+        } else {
+            format!("{}:latest", name)
+        }
+    };
+    with_tag(installed) == with_tag(wanted)
+}
+
+/// Response of `/api/generate` and `/api/chat`, or one line of their streams
+#[derive(Debug, Deserialize)]
+struct ResponseChunk {
+    /// Text of `/api/generate` responses
+    #[serde(default)]
+    response: String,
+    /// Text of `/api/chat` responses
+    #[serde(default)]
+    message: Option<ChatMessage>,
+    #[serde(default)]
+    done: bool,
+    #[serde(default)]
+    prompt_eval_count: u64,
+    #[serde(default)]
+    eval_count: u64,
+}
+
+impl ResponseChunk {
+    fn into_text(self) -> String {
+        match self.message {
+            Some(message) => message.content,
+            None => self.response,
+        }
+    }
+}
+
+/// Response of `/api/tags`
+#[derive(Debug, Deserialize)]
+struct TagsResponse {
+    models: Vec<ModelTag>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelTag {
+    name: String,
+}
+
+/// One line of the streamed `/api/pull` response
+#[derive(Debug, Deserialize)]
+struct PullProgress {
+    #[serde(default)]
+    status: String,
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(default)]
+    total: Option<u64>,
+    #[serde(default)]
+    completed: Option<u64>,
+}
+
+/// Models served by Ollama's HTTP API
+pub struct OllamaProvider {
+    http: reqwest::Client,
+    endpoint: String,
+    model: String,
+    temperature: Option<f32>,
+    top_p: Option<f32>,
+    keep_alive: Option<String>,
+    retry: RetryPolicy,
+    usage: Arc<UsageCounters>,
+}
+
+impl OllamaProvider {
+    /// Provider for `model` at `endpoint` (e.g. `http://localhost:11434`) with
+    /// the model's own sampling defaults
+    pub fn new(endpoint: impl Into<String>, model: impl Into<String>) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            endpoint: endpoint.into().trim_end_matches('/').to_string(),
+            model: model.into(),
+            temperature: None,
+            top_p: None,
+            keep_alive: None,
+            retry: LLMConfig::default().retry,
+            usage: Arc::default(),
+        }
+    }
+
+    pub fn from_config(config: &LLMConfig) -> Result<Self> {
+        Self::new(config.get_url()?, config.model.clone())
+            .with_sampling(config.temperature, config.top_p)
+            .with_retry(config.retry)
+            .with_auth(config.api_key.as_deref(), &config.headers)
+    }
+
+    /// Send a bearer token and extra headers with every request, for Ollama
+    /// behind an authenticating reverse proxy
+    pub fn with_auth(
+        mut self,
+        api_key: Option<&str>,
+        headers: &HashMap<String, String>,
+    ) -> Result<Self> {
+        self.http = http_client(api_key, headers)?;
+        Ok(self)
+    }
+
+    pub fn with_sampling(mut self, temperature: f32, top_p: f32) -> Self {
+        self.temperature = Some(temperature);
+        self.top_p = Some(top_p);
+        self
+    }
+
+    /// Ask Ollama to keep the model loaded for this long after each request
+    /// (e.g. `30m`, or `-1` seconds for indefinitely) instead of its short default
+    pub fn with_keep_alive(mut self, keep_alive: Option<String>) -> Self {
+        self.keep_alive = keep_alive;
+        self
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Request body shared by generate and chat calls
+    fn request_body(&self, fields: serde_json::Value, stream: bool) -> serde_json::Value {
+        let mut body = fields;
+        body["model"] = serde_json::json!(&self.model);
+        body["stream"] = serde_json::json!(stream);
+        let mut options = serde_json::Map::new();
+        if let Some(temperature) = self.temperature {
+            options.insert("temperature".into(), serde_json::json!(temperature));
+        }
+        if let Some(top_p) = self.top_p {
+            options.insert("top_p".into(), serde_json::json!(top_p));
+        }
+        if !options.is_empty() {
+            body["options"] = options.into();
+        }
+        if let Some(keep_alive) = &self.keep_alive {
+            // Ollama reads a string as a duration with a unit, so bare
+            // numbers such as -1 have to be sent as numbers (seconds)
+            body["keep_alive"] = match keep_alive.trim().parse::<i64>() {
+                Ok(seconds) => serde_json::json!(seconds),
+                Err(_) => serde_json::json!(keep_alive),
+            };
+        }
+        body
+    }
+
+    fn chat_body(&self, request: ChatRequest, stream: bool) -> serde_json::Value {
+        let mut fields = serde_json::json!({ "messages": request.messages });
+        if let Some(format) = request.format {
+            fields["format"] = format;
+        }
+        let mut body = self.request_body(fields, stream);
+        if let Some(max_tokens) = request.max_tokens {
+            if !body["options"].is_object() {
+                body["options"] = serde_json::json!({});
+            }
+            body["options"]["num_predict"] = serde_json::json!(max_tokens);
+        }
+        body
+    }
+
+    /// Send a request, retrying transport failures; the timeout covers the
+    /// wait for the response headers
+    ///
+    /// Each request is traced as an `llm.request` span, which ends with the
+    /// headers: for a non-streamed reply, once the model is done.
+    async fn send(
+        &self,
+        what: &str,
+        path: &str,
+        body: &serde_json::Value,
+    ) -> Result<reqwest::Response> {
+        let span = tracing::info_span!(
+            "llm.request",
+            request = what,
+            model = %self.model,
+            endpoint = %self.endpoint
+        );
+        self.retry
+            .run(what, || async {
+                let response = self
+                    .http
+                    .post(format!("{}{}", self.endpoint, path))
+                    .json(body)
+                    .send()
+                    .await
+                    .map_err(|e| ExternalError::ConnectionError(e.to_string()))?;
+                if !response.status().is_success() {
+                    return Err(status_error(response).await.into());
+                }
+                Ok(response)
+            })
+            .instrument(span.clone())
+            .await
+            .inspect_err(|e| tracing::error!(parent: &span, "{:#}", e))
+    }
+
+    /// Send a non-streaming request and decode the reply
+    async fn post<T: DeserializeOwned>(
+        &self,
+        what: &str,
+        path: &str,
+        body: &serde_json::Value,
+    ) -> Result<T> {
+        let response = self.send(what, path, body).await?;
+        Ok(response
+            .json()
+            .await
+            .map_err(|e| ExternalError::OllamaError(format!("Invalid response: {}", e)))?)
+    }
+
+    /// Count a request and its outcome
+    fn track<T>(&self, result: Result<T>) -> Result<T> {
+        self.usage.requests.fetch_add(1, Ordering::Relaxed);
+        if result.is_err() {
+            self.usage.failures.fetch_add(1, Ordering::Relaxed);
+        }
+        result
+    }
+
+    async fn complete(&self, what: &str, path: &str, body: serde_json::Value) -> Result<String> {
+        let result = self.post::<ResponseChunk>(what, path, &body).await;
+        let chunk = self.track(result)?;
+        self.usage
+            .record_tokens(chunk.prompt_eval_count, chunk.eval_count);
+        Ok(chunk.into_text())
+    }
+
+    async fn stream(&self, what: &str, path: &str, body: serde_json::Value) -> Result<TokenStream> {
+        let result = self.send(what, path, &body).await;
+        let response = self.track(result)?;
+
+        // The final chunk (`done: true`) carries statistics, not text
+        let usage = Arc::clone(&self.usage);
+        let tokens = ndjson_stream::<ResponseChunk>(response, self.retry.timeout())
+            .inspect(move |chunk| {
+                if let Ok(chunk) = chunk {
+                    if chunk.done {
+                        usage.record_tokens(chunk.prompt_eval_count, chunk.eval_count);
+                    }
+                }
+            })
+            .take_while(|chunk| ready(!matches!(chunk, Ok(ResponseChunk { done: true, .. }))))
+            .map(|chunk| chunk.map(ResponseChunk::into_text))
+            .filter(|token| ready(!matches!(token, Ok(token) if token.is_empty())));
+        Ok(Box::pin(tokens))
+    }
+}
+
+#[async_trait]
+impl ModelProvider for OllamaProvider {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    async fn generate(&self, prompt: &str) -> Result<String> {
+        let body = self.request_body(serde_json::json!({ "prompt": prompt }), false);
+        self.complete("Generation", "/api/generate", body).await
+    }
+
+    async fn generate_stream(&self, prompt: &str) -> Result<TokenStream> {
+        let body = self.request_body(serde_json::json!({ "prompt": prompt }), true);
+        self.stream("Generation request", "/api/generate", body)
+            .await
+    }
+
+    async fn chat(&self, request: ChatRequest) -> Result<String> {
+        let body = self.chat_body(request, false);
+        self.complete("Chat", "/api/chat", body).await
+    }
+
+    async fn chat_stream(&self, request: ChatRequest) -> Result<TokenStream> {
+        let body = self.chat_body(request, true);
+        self.stream("Chat request", "/api/chat", body).await
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>> {
+        let tags: TagsResponse = self
+            .retry
+            .run("Listing models", || async {
+                let response = self
+                    .http
+                    .get(format!("{}/api/tags", self.endpoint))
+                    .send()
+                    .await
+                    .map_err(|e| ExternalError::ConnectionError(e.to_string()))?;
+                if !response.status().is_success() {
+                    return Err(status_error(response).await.into());
+                }
+                Ok(response.json().await?)
+            })
+            .await?;
+        Ok(tags.models.into_iter().map(|m| m.name).collect())
+    }
+
+    async fn pull_model(&self) -> Result<()> {
+        // Downloads can take far longer than any request timeout, so only
+        // the initial request is bounded
+        let body = serde_json::json!({ "model": &self.model, "stream": true });
+        let response = self.send("Model pull", "/api/pull", &body).await?;
+
+        let mut progress = ndjson_stream::<PullProgress>(response, None);
+        let mut stdout = std::io::stdout();
+        while let Some(update) = progress.next().await {
+            let update = update?;
+            if let Some(error) = update.error {
+                println!();
+                return Err(anyhow!("Failed to pull model {}: {}", self.model, error));
+            }
+            match (update.completed, update.total) {
+                (Some(completed), Some(total)) if total > 0 => {
+                    print!("\r{}: {}%", update.status, completed * 100 / total);
+                }
+                _ => print!("\r{}", update.status),
+            }
+            stdout.flush()?;
+        }
+        println!();
+        Ok(())
+    }
+
+    fn usage(&self) -> ProviderUsage {
+        self.usage.snapshot()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_model_matches() {
+        assert!(model_matches("mistral:latest", "mistral"));
+        assert!(model_matches("mistral", "mistral:latest"));
+        assert!(model_matches("m/qwen2514bmax:latest", "m/qwen2514bmax"));
+        assert!(!model_matches("mistral:7b", "mistral"));
+        assert!(!model_matches("mistral-nemo:latest", "mistral"));
+    }
+
+    #[test]
+    fn test_request_body() {
+        let provider = OllamaProvider::new("http://localhost:11434/", "m");
+        assert_eq!(provider.endpoint(), "http://localhost:11434");
+
+        let body = provider.chat_body(
+            ChatRequest {
+                messages: vec![ChatMessage::user("hi")],
+                format: Some(serde_json::json!("json")),
+                max_tokens: None,
+            },
+            false,
+        );
+        assert_eq!(body["model"], "m");
+        assert_eq!(body["messages"][0]["role"], "user");
+        assert_eq!(body["format"], "json");
+        assert_eq!(body["stream"], false);
+        // Without explicit settings the model's own defaults apply
+        assert!(body.get("options").is_none());
+        assert!(body.get("keep_alive").is_none());
+
+        let provider = provider
+            .with_sampling(0.5, 0.9)
+            .with_keep_alive(Some("30m".into()));
+        let body = provider.request_body(serde_json::json!({ "prompt": "p" }), true);
+        assert_eq!(body["options"]["temperature"], 0.5);
+        assert_eq!(body["keep_alive"], "30m");
+        assert_eq!(body["stream"], true);
+
+        let body = provider.chat_body(
+            ChatRequest {
+                messages: vec![ChatMessage::user("hi")],
+                format: None,
+                max_tokens: Some(512),
+            },
+            false,
+        );
+        assert_eq!(body["options"]["num_predict"], 512);
+        assert_eq!(body["options"]["temperature"], 0.5);
+
+        // A bare number is a number of seconds, not a duration string
+        let provider = provider.with_keep_alive(Some("-1".into()));
+        let body = provider.request_body(serde_json::json!({ "prompt": "p" }), false);
+        assert_eq!(body["keep_alive"], serde_json::json!(-1));
+    }
+
+    #[test]
+    fn test_response_chunk_text() {
+        let chat: ResponseChunk = serde_json::from_str(
+            r#"{"message":{"role":"assistant","content":"Hi"},"done":true,"prompt_eval_count":7,"eval_count":2}"#,
+        )
+        .unwrap();
+        assert_eq!((chat.prompt_eval_count, chat.eval_count), (7, 2));
+        assert_eq!(chat.into_text(), "Hi");
+
+        let generate: ResponseChunk =
+            serde_json::from_str(r#"{"response":"token","done":false}"#).unwrap();
+        assert_eq!(generate.into_text(), "token");
+    }
+
+    #[tokio::test]
+    async fn test_usage_counts_failures() {
+        // Nothing listens on port 1, so the request fails immediately
+        let provider = OllamaProvider::new("http://127.0.0.1:1", "m").with_retry(RetryPolicy {
+            max_retries: 0,
+            ..Default::default()
+        });
+        assert!(provider.generate("prompt").await.is_err());
+        assert_eq!(
+            provider.usage(),
+            ProviderUsage {
+                requests: 1,
+                failures: 1,
+                ..Default::default()
+            }
+        );
+    }
+}
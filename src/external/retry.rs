@@ -0,0 +1,232 @@
+use anyhow::Result;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::time::Duration;
+
+use crate::external::error::ExternalError;
+
+/// Longest wait between two attempts, however many retries came before
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Timeout and retry settings for calls to an external service
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// Seconds a single call may take before it is abandoned, and a streamed
+    /// reply may go without sending anything; 0 waits forever
+    pub timeout_secs: u64,
+    /// Further attempts after the first one fails
+    pub max_retries: usize,
+    /// Wait before the first retry in milliseconds, doubled for each later one
+    pub backoff_ms: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            timeout_secs: 60,
+            max_retries: 3,
+            backoff_ms: 1000,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn timeout(&self) -> Option<Duration> {
+        (self.timeout_secs > 0).then(|| Duration::from_secs(self.timeout_secs))
+    }
+
+    /// Wait before retry number `retry` (starting at 1)
+    pub fn backoff(&self, retry: usize) -> Duration {
+        let factor = 1u64 << (retry.saturating_sub(1)).min(16);
+        Duration::from_millis(self.backoff_ms.saturating_mul(factor)).min(MAX_BACKOFF)
+    }
+
+    /// Run `call` under the timeout, retrying transient failures with
+    /// exponential backoff
+    ///
+    /// `what` names the call in progress messages and the final error. Errors
+    /// a retry cannot fix, such as a 4xx status or an invalid reply, are
+    /// returned right away.
+    pub async fn run<T, F, Fut>(&self, what: &str, mut call: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let attempts = self.max_retries + 1;
+        let mut attempt = 1;
+        loop {
+            let result = match self.timeout() {
+                Some(timeout) => match tokio::time::timeout(timeout, call()).await {
+                    Ok(result) => result,
+                    Err(_) => Err(ExternalError::Timeout(format!(
+                        "{} did not finish within {}s",
+                        what, self.timeout_secs
+                    ))
+                    .into()),
+                },
+                None => call().await,
+            };
+
+            match result {
+                Ok(value) => return Ok(value),
+                Err(e) if attempt >= attempts || !is_transient(&e) => {
+                    return Err(if attempt > 1 {
+                        e.context(format!("{} failed after {} attempts", what, attempt))
+                    } else {
+                        e
+                    });
+                }
+                Err(e) => {
+                    let wait = self.backoff(attempt);
+                    println!(
+                        "{} failed (attempt {}/{}): {}; retrying in {:?}",
+                        what, attempt, attempts, e, wait
+                    );
+                    tokio::time::sleep(wait).await;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Whether a failed call may succeed when retried (see
+/// [`ExternalError::is_transient`]), including HTTP client errors that were
+/// passed on as they are
+fn is_transient(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        if let Some(error) = cause.downcast_ref::<ExternalError>() {
+            error.is_transient()
+        } else if let Some(error) = cause.downcast_ref::<reqwest::Error>() {
+            error.is_connect()
+                || error.is_timeout()
+                || error.status().is_some_and(|status| {
+                    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+                })
+        } else {
+            false
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn policy(max_retries: usize) -> RetryPolicy {
+        RetryPolicy {
+            timeout_secs: 1,
+            max_retries,
+            backoff_ms: 0,
+        }
+    }
+
+    #[test]
+    fn test_backoff_doubles_up_to_cap() {
+        let policy = RetryPolicy {
+            backoff_ms: 500,
+            ..Default::default()
+        };
+        assert_eq!(policy.backoff(1), Duration::from_millis(500));
+        assert_eq!(policy.backoff(3), Duration::from_millis(2000));
+        assert_eq!(policy.backoff(40), MAX_BACKOFF);
+    }
+
+    #[tokio::test]
+    async fn test_retries_until_success() {
+        let calls = AtomicUsize::new(0);
+        let value = policy(3)
+            .run("call", || async {
+                match calls.fetch_add(1, Ordering::SeqCst) {
+                    0 => Err(ExternalError::ConnectionError("refused".into()).into()),
+                    1 => Err(ExternalError::HttpStatus {
+                        status: StatusCode::SERVICE_UNAVAILABLE,
+                        message: "loading model".into(),
+                    }
+                    .into()),
+                    _ => Ok(42),
+                }
+            })
+            .await
+            .unwrap();
+        assert_eq!(value, 42);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_max_retries() {
+        let calls = AtomicUsize::new(0);
+        let err = policy(2)
+            .run("Search", || async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Err::<(), _>(ExternalError::ConnectionError("down".into()).into())
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert_eq!(err.to_string(), "Search failed after 3 attempts");
+        assert_eq!(err.root_cause().to_string(), "Connection error: down");
+    }
+
+    #[tokio::test]
+    async fn test_only_retries_transient_failures() {
+        let calls = AtomicUsize::new(0);
+        let err = policy(3)
+            .run("Generation", || async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Err::<(), _>(
+                    ExternalError::HttpStatus {
+                        status: StatusCode::NOT_FOUND,
+                        message: "model not found".into(),
+                    }
+                    .into(),
+                )
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert!(err.to_string().contains("404 Not Found"));
+
+        let calls = AtomicUsize::new(0);
+        policy(3)
+            .run("Parse", || async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Err::<(), _>(anyhow!("invalid reply"))
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        let calls = AtomicUsize::new(0);
+        policy(2)
+            .run("Generation", || async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Err::<(), _>(
+                    ExternalError::HttpStatus {
+                        status: StatusCode::TOO_MANY_REQUESTS,
+                        message: "slow down".into(),
+                    }
+                    .into(),
+                )
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_hung_call_times_out() {
+        let err = policy(0)
+            .run("Generation", std::future::pending::<Result<()>>)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ExternalError>(),
+            Some(ExternalError::Timeout(_))
+        ));
+    }
+}
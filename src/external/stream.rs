@@ -0,0 +1,168 @@
+use anyhow::Result;
+use futures::{stream, Stream, StreamExt};
+use serde::de::DeserializeOwned;
+use std::pin::Pin;
+use std::time::Duration;
+
+use crate::external::error::ExternalError;
+
+/// A stream of generated text fragments, in order
+pub type TokenStream = Pin<Box<dyn Stream<Item = Result<String>> + Send>>;
+
+/// Splits a byte stream into complete newline-terminated lines
+///
+/// Network chunks don't respect line boundaries, so partial lines are kept
+/// until the rest arrives.
+#[derive(Debug, Default)]
+pub(crate) struct LineBuffer {
+    pending: Vec<u8>,
+}
+
+impl LineBuffer {
+    /// Add a chunk, returning every line it completed
+    pub(crate) fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.pending.extend_from_slice(chunk);
+        let mut lines = Vec::new();
+        while let Some(newline) = self.pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=newline).collect();
+            let line = String::from_utf8_lossy(&line[..newline]).trim().to_string();
+            if !line.is_empty() {
+                lines.push(line);
+            }
+        }
+        lines
+    }
+
+    /// The unterminated last line, if any
+    pub(crate) fn finish(self) -> Option<String> {
+        let line = String::from_utf8_lossy(&self.pending).trim().to_string();
+        (!line.is_empty()).then_some(line)
+    }
+}
+
+/// Decode a newline-delimited JSON response body (as streamed by Ollama)
+/// into one item per line
+///
+/// With an `idle` limit, a body that sends nothing for that long ends the
+/// stream with a timeout, so a stalled server doesn't hang the reader.
+pub(crate) fn ndjson_stream<T>(
+    response: reqwest::Response,
+    idle: Option<Duration>,
+) -> Pin<Box<dyn Stream<Item = Result<T>> + Send>>
+where
+    T: DeserializeOwned + Send + 'static,
+{
+    let parse = |line: String| -> Result<T> {
+        serde_json::from_str(&line).map_err(|e| {
+            ExternalError::OllamaError(format!("Invalid stream chunk '{}': {}", line, e)).into()
+        })
+    };
+
+    let body = response.bytes_stream();
+    let lines = stream::unfold(
+        (body, Some(LineBuffer::default())),
+        move |(mut body, mut buffer)| async move {
+            loop {
+                let current = buffer.as_mut()?;
+                let next = match idle {
+                    Some(idle) => match tokio::time::timeout(idle, body.next()).await {
+                        Ok(next) => next,
+                        Err(_) => {
+                            let error = ExternalError::Timeout(format!(
+                                "no data received for {}s",
+                                idle.as_secs()
+                            ))
+                            .into();
+                            return Some((stream::iter(vec![Err(error)]), (body, None)));
+                        }
+                    },
+                    None => body.next().await,
+                };
+                match next {
+                    Some(Ok(chunk)) => {
+                        let lines = current.push(&chunk);
+                        if !lines.is_empty() {
+                            let items: Vec<Result<String>> = lines.into_iter().map(Ok).collect();
+                            return Some((stream::iter(items), (body, buffer)));
+                        }
+                    }
+                    Some(Err(e)) => {
+                        let error = ExternalError::ConnectionError(e.to_string()).into();
+                        return Some((stream::iter(vec![Err(error)]), (body, None)));
+                    }
+                    None => {
+                        let last: Option<Result<String>> = buffer.take()?.finish().map(Ok);
+                        return Some((
+                            stream::iter(last.into_iter().collect::<Vec<_>>()),
+                            (body, None),
+                        ));
+                    }
+                }
+            }
+        },
+    )
+    .flatten();
+
+    Box::pin(lines.map(move |line| line.and_then(parse)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_line_buffer_reassembles_split_lines() {
+        let mut buffer = LineBuffer::default();
+        assert!(buffer.push(b"{\"response\":\"He").is_empty());
+        assert_eq!(
+            buffer.push(b"llo\"}\n{\"response\":\" world\"}\n{\"done\""),
+            vec![
+                "{\"response\":\"Hello\"}".to_string(),
+                "{\"response\":\" world\"}".to_string()
+            ]
+        );
+        assert!(buffer.push(b"\n\n").len() == 1);
+        assert_eq!(buffer.finish(), None);
+    }
+
+    #[test]
+    fn test_line_buffer_keeps_unterminated_tail() {
+        let mut buffer = LineBuffer::default();
+        buffer.push(b"{\"done\":true}");
+        assert_eq!(buffer.finish().as_deref(), Some("{\"done\":true}"));
+    }
+
+    #[tokio::test]
+    async fn test_stalled_body_times_out() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        // A server that sends one line, then keeps the connection open without another
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = [0; 1024];
+            assert!(stream.read(&mut request).await.unwrap() > 0);
+            stream
+                .write_all(
+                    b"HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n\
+                      11\r\n{\"response\":\"a\"}\n\r\n",
+                )
+                .await
+                .unwrap();
+            tokio::time::sleep(Duration::from_secs(30)).await;
+        });
+
+        let response = reqwest::get(format!("http://{}", address)).await.unwrap();
+        let mut lines =
+            ndjson_stream::<serde_json::Value>(response, Some(Duration::from_millis(100)));
+        assert_eq!(lines.next().await.unwrap().unwrap()["response"], "a");
+        let error = lines.next().await.unwrap().unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ExternalError>(),
+            Some(ExternalError::Timeout(_))
+        ));
+        assert!(lines.next().await.is_none());
+        server.abort();
+    }
+}
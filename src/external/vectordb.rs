@@ -2,41 +2,91 @@ use anyhow::Result;
 use qdrant_client::{
     config::QdrantConfig,
     qdrant::{
-        point_id::PointIdOptions, points_selector::PointsSelectorOneOf, vectors_config::Config,
-        CreateCollection, DeletePoints, Distance, PointId, PointStruct, PointsIdsList,
-        PointsSelector, SearchPoints, UpsertPoints, Value, VectorParams, VectorsConfig,
+        point_id::PointIdOptions, points_selector::PointsSelectorOneOf,
+        vector_output::Vector as OutputVector, vectors_config::Config, Condition, CreateCollection,
+        DeletePoints, Distance, Filter, PointId, PointStruct, PointsIdsList, PointsSelector,
+        ScrollPoints, SearchPoints, UpsertPoints, Value, VectorParams, VectorsConfig,
         WithPayloadSelector, WithVectorsSelector, WriteOrdering,
     },
-    Qdrant,
+    Qdrant, QdrantError,
 };
+use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use tonic::Code;
 use url::Url;
+use uuid::Uuid;
 
 use crate::external::error::ExternalError;
+use crate::external::retry::RetryPolicy;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VectorDBConfig {
     pub collection_name: String,
     pub host: String,
     pub port: u16,
+    /// Dimension of stored vectors; 0 means "take it from the embedding model"
     pub vector_size: usize,
+    /// Drop and recreate the collection instead of reusing an existing one
+    #[serde(default)]
+    pub recreate: bool,
+    /// Maximum number of points sent in one upsert request
+    #[serde(default = "default_upsert_batch_size")]
+    pub upsert_batch_size: usize,
+    /// API key for Qdrant Cloud or secured deployments
+    #[serde(default, skip_serializing)]
+    pub api_key: Option<String>,
+    /// Connect over TLS (https) even when `host` has no scheme
+    #[serde(default)]
+    pub use_tls: bool,
+    /// Timeout and retries for reads and writes
+    #[serde(default)]
+    pub retry: RetryPolicy,
+}
+
+/// Points per upsert request unless configured otherwise
+pub const DEFAULT_UPSERT_BATCH_SIZE: usize = 256;
+
+fn default_upsert_batch_size() -> usize {
+    DEFAULT_UPSERT_BATCH_SIZE
 }
 
 impl VectorDBConfig {
     /// Get the full URL for the Qdrant service
+    ///
+    /// `use_tls` upgrades the scheme to https; the client enables TLS based on it.
     pub fn get_url(&self) -> Result<String> {
-        let url = if self.host.starts_with("http://") || self.host.starts_with("https://") {
-            format!("{}:{}", self.host.trim_end_matches('/'), self.port)
+        let host = self.host.trim_end_matches('/');
+        let (scheme, host) = if let Some(rest) = host.strip_prefix("https://") {
+            ("https", rest)
+        } else if let Some(rest) = host.strip_prefix("http://") {
+            (if self.use_tls { "https" } else { "http" }, rest)
         } else {
-            format!("http://{}:{}", self.host, self.port)
+            (if self.use_tls { "https" } else { "http" }, host)
         };
+        let url = format!("{}://{}:{}", scheme, host, self.port);
 
         // Validate the URL
         Url::parse(&url).map_err(|e| ExternalError::ConfigError(format!("Invalid URL: {}", e)))?;
 
         Ok(url)
     }
+
+    /// Reconcile the configured vector size with the embedding model's dimension
+    ///
+    /// An unset (zero) size adopts the model's dimension; an explicit size
+    /// that disagrees is rejected before anything is written to Qdrant.
+    pub fn resolve_vector_size(&mut self, dimension: usize) -> Result<(), ExternalError> {
+        if self.vector_size == 0 {
+            self.vector_size = dimension;
+        } else if self.vector_size != dimension {
+            return Err(ExternalError::DimensionMismatch {
+                expected: self.vector_size,
+                actual: dimension,
+            });
+        }
+        Ok(())
+    }
 }
 
 impl Default for VectorDBConfig {
@@ -46,7 +96,188 @@ impl Default for VectorDBConfig {
             host: "localhost".to_string(),
             port: 6334,
             vector_size: 384,
+            recreate: false,
+            upsert_batch_size: DEFAULT_UPSERT_BATCH_SIZE,
+            api_key: None,
+            use_tls: false,
+            retry: RetryPolicy::default(),
+        }
+    }
+}
+
+/// Distance metric used for every collection created by this crate
+const COLLECTION_DISTANCE: Distance = Distance::Cosine;
+
+/// Check an existing collection's vector parameters against the expected ones,
+/// describing the first difference found
+fn check_vector_params(
+    vectors_config: Option<&VectorsConfig>,
+    size: usize,
+    distance: Distance,
+) -> std::result::Result<(), String> {
+    let params = match vectors_config.and_then(|config| config.config.as_ref()) {
+        Some(Config::Params(params)) => params,
+        Some(Config::ParamsMap(_)) => {
+            return Err("it uses named vectors, expected a single unnamed vector".to_string())
         }
+        None => return Err("it has no vector configuration".to_string()),
+    };
+
+    // A size of 0 (not configured yet) accepts whatever the collection has
+    if size != 0 && params.size != size as u64 {
+        return Err(format!(
+            "its vectors have {} dimensions, expected {}",
+            params.size, size
+        ));
+    }
+    if params.distance != distance as i32 {
+        let found = Distance::try_from(params.distance)
+            .map(|d| d.as_str_name().to_string())
+            .unwrap_or_else(|_| params.distance.to_string());
+        return Err(format!(
+            "it uses {} distance, expected {}",
+            found,
+            distance.as_str_name()
+        ));
+    }
+    Ok(())
+}
+
+/// A search result with the payload stored alongside the vector
+///
+/// Payload values are flattened to text; lists such as `tags` are joined
+/// with ", ".
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchHit {
+    pub id: String,
+    pub score: f32,
+    pub payload: HashMap<String, String>,
+}
+
+/// A stored point as returned by `VectorDB::scroll`, payload flattened as in `SearchHit`
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredPoint {
+    pub id: String,
+    pub payload: HashMap<String, String>,
+}
+
+/// A point with its vector and unflattened payload, as kept in snapshots
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PointRecord {
+    pub id: String,
+    pub vector: Vec<f32>,
+    pub payload: serde_json::Map<String, serde_json::Value>,
+}
+
+/// One page of a scroll through the collection
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScrollPage<T = StoredPoint> {
+    pub points: Vec<T>,
+    /// Offset to pass to the next `scroll` call; `None` on the last page
+    pub next_offset: Option<String>,
+}
+
+/// Restricts a search to points whose payload matches every set field
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchFilter {
+    /// Document title or path
+    pub document: Option<String>,
+    /// Node type name as stored in the payload, e.g. `CodeBlock`
+    pub node_type: Option<String>,
+    /// A tag carried by the node or its document
+    pub tag: Option<String>,
+}
+
+impl SearchFilter {
+    pub fn is_empty(&self) -> bool {
+        self.document.is_none() && self.node_type.is_none() && self.tag.is_none()
+    }
+
+    /// Translate to a Qdrant filter; `None` when nothing is restricted
+    pub fn to_qdrant(&self) -> Option<Filter> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let mut conditions = Vec::new();
+        if let Some(document) = &self.document {
+            conditions.push(
+                Filter::should([
+                    Condition::matches("document", document.clone()),
+                    Condition::matches("document_path", document.clone()),
+                ])
+                .into(),
+            );
+        }
+        if let Some(node_type) = &self.node_type {
+            conditions.push(Condition::matches("node_type", node_type.clone()));
+        }
+        if let Some(tag) = &self.tag {
+            // Matches any element of the `tags` array
+            conditions.push(Condition::matches("tags", tag.clone()));
+        }
+        Some(Filter::must(conditions))
+    }
+}
+
+/// A Qdrant error as an external error: an unavailable server, a missed
+/// deadline and rate limiting become the errors retries are made for
+fn qdrant_error(error: QdrantError) -> ExternalError {
+    let code = match &error {
+        QdrantError::ResponseError { status } => status.code(),
+        QdrantError::ResourceExhaustedError { status, .. } => status.code(),
+        _ => return ExternalError::VectorDBError(error.to_string()),
+    };
+    match code {
+        Code::Unavailable => ExternalError::ConnectionError(error.to_string()),
+        Code::DeadlineExceeded => ExternalError::Timeout(error.to_string()),
+        Code::ResourceExhausted => ExternalError::HttpStatus {
+            status: StatusCode::TOO_MANY_REQUESTS,
+            message: error.to_string(),
+        },
+        _ => ExternalError::VectorDBError(error.to_string()),
+    }
+}
+
+/// Text form of a point ID, as returned by inserts and searches
+fn point_id_string(id: PointId) -> Option<String> {
+    match id.point_id_options? {
+        PointIdOptions::Num(num) => Some(num.to_string()),
+        PointIdOptions::Uuid(uuid) => Some(uuid),
+    }
+}
+
+/// Parse a point ID string: UUIDs for points written by this crate,
+/// integers for points from older collections
+fn parse_point_id(id: &str) -> Option<PointId> {
+    if let Ok(uuid) = Uuid::parse_str(id) {
+        Some(PointId::from(uuid.to_string()))
+    } else {
+        id.parse::<u64>().ok().map(PointId::from)
+    }
+}
+
+/// Parse a scroll offset returned by an earlier page
+fn parse_offset(offset: Option<String>) -> Result<Option<PointId>> {
+    offset
+        .map(|offset| {
+            parse_point_id(&offset).ok_or_else(|| {
+                ExternalError::VectorDBError(format!("Invalid scroll offset '{}'", offset)).into()
+            })
+        })
+        .transpose()
+}
+
+/// Flatten a payload value to the text form used by `SearchHit`
+fn payload_text(value: serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(text) => text,
+        serde_json::Value::Array(items) => items
+            .into_iter()
+            .map(payload_text)
+            .collect::<Vec<_>>()
+            .join(", "),
+        other => other.to_string(),
     }
 }
 
@@ -60,19 +291,84 @@ impl VectorDB {
     /// Create a new vector database client with the given configuration
     pub async fn new(config: VectorDBConfig) -> Result<Self> {
         let url = config.get_url()?;
-        let qdrant_config = QdrantConfig::from_url(&url);
+        let mut qdrant_config = QdrantConfig::from_url(&url).api_key(config.api_key.clone());
+        if let Some(timeout) = config.retry.timeout() {
+            qdrant_config = qdrant_config.timeout(timeout).connect_timeout(timeout);
+        }
         let client = Qdrant::new(qdrant_config)
             .map_err(|e| ExternalError::ConnectionError(e.to_string()))?;
 
         Ok(Self { client, config })
     }
 
-    /// Initialize the collection with the given configuration
+    /// Ping the server, returning its version
+    pub async fn health_check(&self) -> Result<String> {
+        let reply = self
+            .client
+            .health_check()
+            .await
+            .map_err(|e| ExternalError::ConnectionError(e.to_string()))?;
+        Ok(reply.version)
+    }
+
+    /// Make sure the collection exists with the configured parameters
+    ///
+    /// An existing collection is reused when its vector size and distance
+    /// match, and rejected with `ExternalError::CollectionMismatch` when they
+    /// don't. With `recreate` set, an existing collection is dropped first.
     pub async fn init_collection(&self) -> Result<()> {
+        let name = self.config.collection_name.clone();
+        let mut exists = self
+            .client
+            .collection_exists(name.clone())
+            .await
+            .map_err(qdrant_error)?;
+
+        if exists && self.config.recreate {
+            self.client
+                .delete_collection(name.clone())
+                .await
+                .map_err(qdrant_error)?;
+            exists = false;
+        }
+
+        if exists {
+            let info = self
+                .client
+                .collection_info(name.clone())
+                .await
+                .map_err(qdrant_error)?;
+            let vectors_config = info
+                .result
+                .and_then(|info| info.config)
+                .and_then(|config| config.params)
+                .and_then(|params| params.vectors_config);
+            return check_vector_params(
+                vectors_config.as_ref(),
+                self.config.vector_size,
+                COLLECTION_DISTANCE,
+            )
+            .map_err(|reason| {
+                ExternalError::CollectionMismatch {
+                    collection: name,
+                    reason,
+                }
+                .into()
+            });
+        }
+
+        if self.config.vector_size == 0 {
+            return Err(ExternalError::ConfigError(format!(
+                "Cannot create collection '{}' without a vector size; set QDRANT_VECTOR_SIZE",
+                name
+            ))
+            .into());
+        }
+
         let vectors_config = VectorsConfig {
             config: Some(Config::Params(VectorParams {
                 size: self.config.vector_size as u64,
-                distance: Distance::Cosine.into(),
+                distance: COLLECTION_DISTANCE.into(),
                 ..Default::default()
             })),
         };
@@ -86,88 +382,267 @@ impl VectorDB {
         self.client
             .create_collection(create_collection)
             .await
-            .map_err(|e| ExternalError::VectorDBError(e.to_string()))?;
+            .map_err(qdrant_error)?;
 
         Ok(())
     }
 
     /// Insert vectors with metadata into the database
+    ///
+    /// Each point is stored under the given UUID, so re-inserting a node
+    /// replaces its previous vector instead of adding another one.
+    /// Points are sent in chunks of `upsert_batch_size`, each retried on
+    /// failure; a chunk that keeps failing aborts the insert, leaving
+    /// earlier chunks stored. Returns the point IDs.
     pub async fn insert_vectors(
         &self,
+        ids: Vec<Uuid>,
         vectors: Vec<Vec<f32>>,
-        metadata: Vec<HashMap<String, String>>,
+        metadata: Vec<HashMap<String, serde_json::Value>>,
     ) -> Result<Vec<String>> {
-        let points: Vec<PointStruct> = vectors
-            .into_iter()
+        if ids.len() != vectors.len() || vectors.len() != metadata.len() {
+            return Err(ExternalError::VectorDBError(format!(
+                "Mismatched insert: {} ids, {} vectors, {} payloads",
+                ids.len(),
+                vectors.len(),
+                metadata.len()
+            ))
+            .into());
+        }
+
+        let points: Vec<PointStruct> = ids
+            .iter()
+            .zip(vectors)
             .zip(metadata)
-            .enumerate()
-            .map(|(i, (vector, meta))| {
+            .map(|((id, vector), meta)| {
                 let payload: HashMap<String, Value> =
                     meta.into_iter().map(|(k, v)| (k, Value::from(v))).collect();
 
                 PointStruct {
-                    id: Some(PointId {
-                        point_id_options: Some(PointIdOptions::Num(i as u64)),
-                    }),
+                    id: Some(PointId::from(id.to_string())),
                     payload,
                     vectors: Some(vector.into()),
                 }
             })
             .collect();
 
+        self.upsert_points(points).await?;
+        Ok(ids.iter().map(Uuid::to_string).collect())
+    }
+
+    /// Write previously exported points back, keeping their IDs
+    pub async fn import_points(&self, records: Vec<PointRecord>) -> Result<usize> {
+        let count = records.len();
+        let points = records
+            .into_iter()
+            .map(|record| {
+                let id = parse_point_id(&record.id).ok_or_else(|| {
+                    ExternalError::VectorDBError(format!("Invalid point ID '{}'", record.id))
+                })?;
+                let payload: HashMap<String, Value> = record
+                    .payload
+                    .into_iter()
+                    .map(|(k, v)| (k, Value::from(v)))
+                    .collect();
+                Ok(PointStruct {
+                    id: Some(id),
+                    payload,
+                    vectors: Some(record.vector.into()),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        self.upsert_points(points).await?;
+        Ok(count)
+    }
+
+    /// Upsert points in chunks of `upsert_batch_size`
+    async fn upsert_points(&self, points: Vec<PointStruct>) -> Result<()> {
+        let batch_size = self.config.upsert_batch_size.max(1);
+        let chunk_count = points.len().div_ceil(batch_size);
+        for (chunk_index, chunk) in points.chunks(batch_size).enumerate() {
+            self.upsert_chunk(chunk.to_vec()).await.map_err(|e| {
+                ExternalError::VectorDBError(format!(
+                    "Upsert of chunk {}/{} failed: {}",
+                    chunk_index + 1,
+                    chunk_count,
+                    e
+                ))
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Upsert one chunk of points, retrying transient failures
+    async fn upsert_chunk(&self, points: Vec<PointStruct>) -> Result<()> {
         let upsert_points = UpsertPoints {
             collection_name: self.config.collection_name.clone(),
-            points: points.clone(),
+            points,
             ordering: Some(WriteOrdering::default()),
             ..Default::default()
         };
-
-        self.client
-            .upsert_points(upsert_points)
+        let what = format!("Upsert of {} points", upsert_points.points.len());
+
+        self.config
+            .retry
+            .run(&what, || async {
+                self.client
+                    .upsert_points(upsert_points.clone())
+                    .await
+                    .map(|_| ())
+                    .map_err(|e| qdrant_error(e).into())
+            })
             .await
-            .map_err(|e| ExternalError::VectorDBError(e.to_string()))?;
+    }
 
-        Ok(points
-            .into_iter()
-            .filter_map(|p| {
-                p.id.map(|id| {
-                    if let Some(PointIdOptions::Num(num)) = id.point_id_options {
-                        num.to_string()
-                    } else {
-                        String::new()
-                    }
-                })
+    /// Read one page of stored points with their vectors, for snapshots
+    pub async fn export_points(
+        &self,
+        offset: Option<String>,
+        limit: u32,
+    ) -> Result<ScrollPage<PointRecord>> {
+        let request = ScrollPoints {
+            collection_name: self.config.collection_name.clone(),
+            offset: parse_offset(offset)?,
+            limit: Some(limit),
+            with_payload: Some(WithPayloadSelector::from(true)),
+            with_vectors: Some(WithVectorsSelector::from(true)),
+            ..Default::default()
+        };
+
+        let response = self
+            .config
+            .retry
+            .run("Scroll", || async {
+                self.client
+                    .scroll(request.clone())
+                    .await
+                    .map_err(|e| qdrant_error(e).into())
             })
-            .collect())
+            .await?;
+
+        let mut points = Vec::with_capacity(response.result.len());
+        for point in response.result {
+            let Some(id) = point.id.and_then(point_id_string) else {
+                continue;
+            };
+            let vector = match point.vectors.and_then(|vectors| vectors.get_vector()) {
+                Some(OutputVector::Dense(dense)) => dense.data,
+                _ => {
+                    return Err(ExternalError::VectorDBError(format!(
+                        "Point {} has no single dense vector",
+                        id
+                    ))
+                    .into())
+                }
+            };
+            points.push(PointRecord {
+                id,
+                vector,
+                payload: point
+                    .payload
+                    .into_iter()
+                    .map(|(key, value)| (key, value.into_json()))
+                    .collect(),
+            });
+        }
+
+        Ok(ScrollPage {
+            points,
+            next_offset: response.next_page_offset.and_then(point_id_string),
+        })
+    }
+
+    /// Read one page of stored points, optionally restricted by a payload filter
+    ///
+    /// Start with `offset: None` and pass each page's `next_offset` back in
+    /// until it is `None`.
+    pub async fn scroll(
+        &self,
+        filter: Option<SearchFilter>,
+        offset: Option<String>,
+        limit: u32,
+    ) -> Result<ScrollPage> {
+        let request = ScrollPoints {
+            collection_name: self.config.collection_name.clone(),
+            filter: filter.and_then(|filter| filter.to_qdrant()),
+            offset: parse_offset(offset)?,
+            limit: Some(limit),
+            with_payload: Some(WithPayloadSelector::from(true)),
+            ..Default::default()
+        };
+
+        let response = self
+            .config
+            .retry
+            .run("Scroll", || async {
+                self.client
+                    .scroll(request.clone())
+                    .await
+                    .map_err(|e| qdrant_error(e).into())
+            })
+            .await?;
+
+        Ok(ScrollPage {
+            points: response
+                .result
+                .into_iter()
+                .filter_map(|point| {
+                    Some(StoredPoint {
+                        id: point_id_string(point.id?)?,
+                        payload: point
+                            .payload
+                            .into_iter()
+                            .map(|(key, value)| (key, payload_text(value.into_json())))
+                            .collect(),
+                    })
+                })
+                .collect(),
+            next_offset: response.next_page_offset.and_then(point_id_string),
+        })
     }
 
-    /// Search for similar vectors
-    pub async fn search_vectors(&self, vector: Vec<f32>, limit: u64) -> Result<Vec<(String, f32)>> {
+    /// Search for similar vectors matching an optional payload filter
+    pub async fn search_vectors(
+        &self,
+        vector: Vec<f32>,
+        limit: u64,
+        filter: Option<SearchFilter>,
+    ) -> Result<Vec<SearchHit>> {
         let search_request = SearchPoints {
             collection_name: self.config.collection_name.clone(),
             vector,
             limit,
+            filter: filter.and_then(|filter| filter.to_qdrant()),
             with_payload: Some(WithPayloadSelector::from(true)),
-            with_vectors: Some(WithVectorsSelector::from(true)),
             ..Default::default()
         };
 
         let results = self
-            .client
-            .search_points(search_request)
-            .await
-            .map_err(|e| ExternalError::VectorDBError(e.to_string()))?;
+            .config
+            .retry
+            .run("Search", || async {
+                self.client
+                    .search_points(search_request.clone())
+                    .await
+                    .map_err(|e| qdrant_error(e).into())
+            })
+            .await?;
 
         Ok(results
             .result
             .into_iter()
             .filter_map(|r| {
-                r.id.and_then(|id| {
-                    if let Some(PointIdOptions::Num(num)) = id.point_id_options {
-                        Some((num.to_string(), r.score))
-                    } else {
-                        None
-                    }
+                let id = point_id_string(r.id?)?;
+                let payload = r
+                    .payload
+                    .into_iter()
+                    .map(|(key, value)| (key, payload_text(value.into_json())))
+                    .collect();
+                Some(SearchHit {
+                    id,
+                    score: r.score,
+                    payload,
                 })
             })
             .collect())
@@ -175,14 +650,7 @@ impl VectorDB {
 
     /// Delete vectors by their IDs
     pub async fn delete_vectors(&self, ids: Vec<String>) -> Result<()> {
-        let point_ids: Vec<PointId> = ids
-            .into_iter()
-            .filter_map(|id| {
-                id.parse::<u64>().ok().map(|num| PointId {
-                    point_id_options: Some(PointIdOptions::Num(num)),
-                })
-            })
-            .collect();
+        let point_ids: Vec<PointId> = ids.iter().filter_map(|id| parse_point_id(id)).collect();
 
         let points_selector = PointsSelector {
             points_selector_one_of: Some(PointsSelectorOneOf::Points(PointsIdsList {
@@ -197,12 +665,16 @@ impl VectorDB {
             ..Default::default()
         };
 
-        self.client
-            .delete_points(delete_points)
+        self.config
+            .retry
+            .run("Delete", || async {
+                self.client
+                    .delete_points(delete_points.clone())
+                    .await
+                    .map(|_| ())
+                    .map_err(|e| qdrant_error(e).into())
+            })
             .await
-            .map_err(|e| ExternalError::VectorDBError(e.to_string()))?;
-
-        Ok(())
     }
 }
 
@@ -216,10 +688,16 @@ mod tests {
         async fn init_collection(&self) -> Result<()>;
         async fn insert_vectors(
             &self,
+            ids: Vec<Uuid>,
             vectors: Vec<Vec<f32>>,
-            metadata: Vec<HashMap<String, String>>,
+            metadata: Vec<HashMap<String, serde_json::Value>>,
         ) -> Result<Vec<String>>;
-        async fn search_vectors(&self, vector: Vec<f32>, limit: u64) -> Result<Vec<(String, f32)>>;
+        async fn search_vectors(
+            &self,
+            vector: Vec<f32>,
+            limit: u64,
+            filter: Option<SearchFilter>,
+        ) -> Result<Vec<SearchHit>>;
         async fn delete_vectors(&self, ids: Vec<String>) -> Result<()>;
     }
 
@@ -231,6 +709,11 @@ mod tests {
             port: 6334,
             collection_name: "test".to_string(),
             vector_size: 384,
+            recreate: false,
+            upsert_batch_size: DEFAULT_UPSERT_BATCH_SIZE,
+            api_key: None,
+            use_tls: false,
+            retry: RetryPolicy::default(),
         };
         assert_eq!(config.get_url().unwrap(), "http://localhost:6334");
 
@@ -240,6 +723,11 @@ mod tests {
             port: 6334,
             collection_name: "test".to_string(),
             vector_size: 384,
+            recreate: false,
+            upsert_batch_size: DEFAULT_UPSERT_BATCH_SIZE,
+            api_key: None,
+            use_tls: false,
+            retry: RetryPolicy::default(),
         };
         assert_eq!(config.get_url().unwrap(), "http://example.com:6334");
 
@@ -249,10 +737,135 @@ mod tests {
             port: 6334,
             collection_name: "test".to_string(),
             vector_size: 384,
+            recreate: false,
+            upsert_batch_size: DEFAULT_UPSERT_BATCH_SIZE,
+            api_key: None,
+            use_tls: false,
+            retry: RetryPolicy::default(),
         };
         assert_eq!(config.get_url().unwrap(), "https://example.com:6334");
     }
 
+    #[test]
+    fn test_tls_url() {
+        let config = VectorDBConfig {
+            host: "xyz.cloud.qdrant.io".to_string(),
+            use_tls: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            config.get_url().unwrap(),
+            "https://xyz.cloud.qdrant.io:6334"
+        );
+
+        let config = VectorDBConfig {
+            host: "http://qdrant.internal".to_string(),
+            use_tls: true,
+            ..Default::default()
+        };
+        assert_eq!(config.get_url().unwrap(), "https://qdrant.internal:6334");
+    }
+
+    #[test]
+    fn test_api_key_is_not_serialized() {
+        let config = VectorDBConfig {
+            api_key: Some("secret".to_string()),
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&config).unwrap();
+        assert!(!json.contains("secret"));
+    }
+
+    #[test]
+    fn test_resolve_vector_size() {
+        let mut config = VectorDBConfig {
+            vector_size: 0,
+            ..Default::default()
+        };
+        config.resolve_vector_size(768).unwrap();
+        assert_eq!(config.vector_size, 768);
+
+        let mut config = VectorDBConfig::default();
+        let err = config.resolve_vector_size(768).unwrap_err();
+        assert!(matches!(
+            err,
+            ExternalError::DimensionMismatch {
+                expected: 384,
+                actual: 768
+            }
+        ));
+        assert!(err.to_string().contains("QDRANT_VECTOR_SIZE=768"));
+    }
+
+    #[test]
+    fn test_search_filter_translation() {
+        assert!(SearchFilter::default().to_qdrant().is_none());
+
+        let filter = SearchFilter {
+            document: Some("Guide".to_string()),
+            node_type: Some("CodeBlock".to_string()),
+            tag: Some("rust".to_string()),
+        }
+        .to_qdrant()
+        .unwrap();
+        assert_eq!(filter.must.len(), 3);
+        assert!(filter.should.is_empty());
+    }
+
+    #[test]
+    fn test_check_vector_params() {
+        let config = |size: u64, distance: Distance| VectorsConfig {
+            config: Some(Config::Params(VectorParams {
+                size,
+                distance: distance.into(),
+                ..Default::default()
+            })),
+        };
+
+        assert!(
+            check_vector_params(Some(&config(768, Distance::Cosine)), 768, Distance::Cosine)
+                .is_ok()
+        );
+
+        let err = check_vector_params(Some(&config(384, Distance::Cosine)), 768, Distance::Cosine)
+            .unwrap_err();
+        assert!(err.contains("384 dimensions, expected 768"));
+
+        let err = check_vector_params(Some(&config(768, Distance::Dot)), 768, Distance::Cosine)
+            .unwrap_err();
+        assert!(err.contains("Dot distance, expected Cosine"));
+
+        assert!(check_vector_params(None, 768, Distance::Cosine).is_err());
+
+        // An unconfigured size accepts the existing collection's
+        assert!(
+            check_vector_params(Some(&config(384, Distance::Cosine)), 0, Distance::Cosine).is_ok()
+        );
+    }
+
+    #[test]
+    fn test_point_ids() {
+        let uuid = Uuid::new_v4();
+        let id = parse_point_id(&uuid.to_string()).unwrap();
+        assert_eq!(point_id_string(id).unwrap(), uuid.to_string());
+
+        // Integer IDs from collections written before UUID point IDs
+        let id = parse_point_id("7").unwrap();
+        assert_eq!(id.point_id_options, Some(PointIdOptions::Num(7)));
+
+        assert!(parse_point_id("not-an-id").is_none());
+    }
+
+    #[test]
+    fn test_payload_text() {
+        assert_eq!(payload_text(serde_json::json!("TLS")), "TLS");
+        assert_eq!(
+            payload_text(serde_json::json!(["rust", "code"])),
+            "rust, code"
+        );
+        assert_eq!(payload_text(serde_json::json!(3)), "3");
+    }
+
     #[tokio::test]
     async fn test_vector_operations() {
         let mut mock = MockVectorDBClient::new();
@@ -262,17 +875,20 @@ mod tests {
 
         mock.expect_insert_vectors()
             .times(1)
-            .returning(|vectors, _| {
-                Ok(vectors
-                    .iter()
-                    .enumerate()
-                    .map(|(i, _)| i.to_string())
-                    .collect())
-            });
+            .returning(|ids, _, _| Ok(ids.iter().map(Uuid::to_string).collect()));
 
         mock.expect_search_vectors()
             .times(1)
-            .returning(|_, _limit| Ok(vec![("0".to_string(), 0.9), ("1".to_string(), 0.8)]));
+            .returning(|_, _limit, _filter| {
+                Ok(["0", "1"]
+                    .into_iter()
+                    .map(|id| SearchHit {
+                        id: id.to_string(),
+                        score: 0.9,
+                        payload: HashMap::new(),
+                    })
+                    .collect())
+            });
 
         mock.expect_delete_vectors().times(1).returning(|_| Ok(()));
 
@@ -282,22 +898,26 @@ mod tests {
         // Test vector insertion
         let vectors = vec![vec![1.0, 0.0], vec![0.0, 1.0]];
         let metadata = vec![
-            [("key".to_string(), "value1".to_string())]
+            [("key".to_string(), serde_json::json!("value1"))]
                 .into_iter()
                 .collect(),
-            [("key".to_string(), "value2".to_string())]
+            [("key".to_string(), serde_json::json!("value2"))]
                 .into_iter()
                 .collect(),
         ];
 
+        let point_ids = vec![Uuid::new_v4(), Uuid::new_v4()];
         let ids = mock
-            .insert_vectors(vectors.clone(), metadata)
+            .insert_vectors(point_ids.clone(), vectors.clone(), metadata)
             .await
             .unwrap();
-        assert_eq!(ids.len(), 2);
+        assert_eq!(
+            ids,
+            vec![point_ids[0].to_string(), point_ids[1].to_string()]
+        );
 
         // Test vector search
-        let results = mock.search_vectors(vec![1.0, 0.0], 2).await.unwrap();
+        let results = mock.search_vectors(vec![1.0, 0.0], 2, None).await.unwrap();
         assert_eq!(results.len(), 2);
 
         // Test vector deletion
@@ -0,0 +1,194 @@
+//! C ABI for the structural markdown parser, so ingestion services written
+//! in other languages can turn documents into graphs without running the
+//! pipeline. Built with the `ffi` feature as a shared or static library:
+//!
+//! ```text
+//! cargo rustc --release --lib --features ffi --crate-type cdylib
+//! ```
+//!
+//! The matching header is `include/llm_dataset_builder.h`. Strings passed in
+//! are borrowed; strings returned are owned by the caller and must be
+//! released with [`ldb_string_free`].
+
+use anyhow::{anyhow, Result};
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use crate::parser::{parse_markdown, parse_markdown_document};
+
+/// `{"nodes": [...], "edges": [...]}`, as [`DocumentGraph::to_json`](crate::graph::DocumentGraph::to_json)
+pub const LDB_FORMAT_JSON: c_int = 0;
+/// Graphviz DOT, as [`DocumentGraph::to_dot`](crate::graph::DocumentGraph::to_dot)
+pub const LDB_FORMAT_DOT: c_int = 1;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Parse markdown into a document graph and serialize it in `format`
+///
+/// With a `name` (usually the document's path), node IDs are stable: the
+/// same name and content always give the same IDs. With `name` NULL they are
+/// random. Returns NULL on failure; [`ldb_last_error`] tells why.
+///
+/// # Safety
+///
+/// `content` must be a valid NUL-terminated string, and `name` NULL or one.
+#[no_mangle]
+pub unsafe extern "C" fn ldb_parse_markdown(
+    content: *const c_char,
+    name: *const c_char,
+    format: c_int,
+) -> *mut c_char {
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let content = borrow_str(content, "content")?;
+        let graph = if name.is_null() {
+            parse_markdown(content)?
+        } else {
+            parse_markdown_document(content, borrow_str(name, "name")?)?
+        };
+        let serialized = match format {
+            LDB_FORMAT_JSON => graph.to_json(),
+            LDB_FORMAT_DOT => graph.to_dot(),
+            other => return Err(anyhow!("Unknown format {}", other)),
+        };
+        Ok(CString::new(serialized)?)
+    }))
+    .unwrap_or_else(|_| Err(anyhow!("The parser panicked")));
+
+    match result {
+        Ok(serialized) => {
+            set_last_error(None);
+            serialized.into_raw()
+        }
+        Err(e) => {
+            set_last_error(Some(format!("{:#}", e)));
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Why the last call on this thread failed, or NULL if it succeeded
+///
+/// The string stays valid until the next call on the same thread and must
+/// not be freed.
+#[no_mangle]
+pub extern "C" fn ldb_last_error() -> *const c_char {
+    LAST_ERROR.with(|error| {
+        error
+            .borrow()
+            .as_ref()
+            .map_or(ptr::null(), |error| error.as_ptr())
+    })
+}
+
+/// Release a string returned by this library; NULL is ignored
+///
+/// # Safety
+///
+/// `string` must be NULL or a string returned by this library that has not
+/// been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn ldb_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+/// Version of the library, e.g. `0.1.0`; must not be freed
+#[no_mangle]
+pub extern "C" fn ldb_version() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char
+}
+
+/// # Safety
+///
+/// `string` must be NULL or a valid NUL-terminated string.
+unsafe fn borrow_str<'a>(string: *const c_char, what: &str) -> Result<&'a str> {
+    if string.is_null() {
+        return Err(anyhow!("{} is NULL", what));
+    }
+    CStr::from_ptr(string)
+        .to_str()
+        .map_err(|e| anyhow!("{} is not valid UTF-8: {}", what, e))
+}
+
+fn set_last_error(error: Option<String>) {
+    let error =
+        error.map(|error| CString::new(error.replace('\0', " ")).expect("NUL bytes were replaced"));
+    LAST_ERROR.with(|last| *last.borrow_mut() = error);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn last_error() -> Option<String> {
+        let error = ldb_last_error();
+        (!error.is_null()).then(|| {
+            unsafe { CStr::from_ptr(error) }
+                .to_string_lossy()
+                .into_owned()
+        })
+    }
+
+    fn parse(content: &str, name: Option<&str>, format: c_int) -> Option<String> {
+        let content = CString::new(content).unwrap();
+        let name = name.map(|name| CString::new(name).unwrap());
+        let name_ptr = name.as_ref().map_or(ptr::null(), |name| name.as_ptr());
+        unsafe {
+            let serialized = ldb_parse_markdown(content.as_ptr(), name_ptr, format);
+            if serialized.is_null() {
+                return None;
+            }
+            let owned = CStr::from_ptr(serialized).to_str().unwrap().to_string();
+            ldb_string_free(serialized);
+            Some(owned)
+        }
+    }
+
+    #[test]
+    fn test_parse_markdown_over_the_c_abi() {
+        let markdown = "# Guide\n\nIntro text.\n\n## Install\n\nRun `make`.\n";
+        let json = parse(markdown, Some("docs/guide.md"), LDB_FORMAT_JSON).unwrap();
+        assert_eq!(last_error(), None);
+        let graph: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let nodes = graph["nodes"].as_array().unwrap();
+        assert_eq!(nodes[0]["node_type"], "Document");
+        assert_eq!(nodes[0]["metadata"]["attributes"]["path"], "docs/guide.md");
+        assert!(nodes.iter().any(|node| node["content"] == "Install"));
+        assert!(!graph["edges"].as_array().unwrap().is_empty());
+        // Named documents keep their node IDs
+        assert_eq!(
+            parse(markdown, Some("docs/guide.md"), LDB_FORMAT_JSON).unwrap(),
+            json
+        );
+
+        let dot = parse(markdown, None, LDB_FORMAT_DOT).unwrap();
+        assert!(dot.starts_with("digraph document {"));
+    }
+
+    #[test]
+    fn test_errors_over_the_c_abi() {
+        assert_eq!(parse("# Guide", None, 7), None);
+        assert_eq!(last_error().as_deref(), Some("Unknown format 7"));
+
+        let serialized = unsafe { ldb_parse_markdown(ptr::null(), ptr::null(), 0) };
+        assert!(serialized.is_null());
+        assert_eq!(last_error().as_deref(), Some("content is NULL"));
+
+        let invalid = b"# Caf\xe9\0";
+        let serialized =
+            unsafe { ldb_parse_markdown(invalid.as_ptr() as *const c_char, ptr::null(), 0) };
+        assert!(serialized.is_null());
+        assert!(last_error()
+            .unwrap()
+            .starts_with("content is not valid UTF-8"));
+
+        unsafe { ldb_string_free(ptr::null_mut()) };
+        let version = unsafe { CStr::from_ptr(ldb_version()) };
+        assert_eq!(version.to_str().unwrap(), env!("CARGO_PKG_VERSION"));
+    }
+}
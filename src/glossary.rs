@@ -0,0 +1,186 @@
+//! Glossaries of the domain terms of a document (`--glossary`).
+//!
+//! The model is asked for the terms a document defines before its sections
+//! are generated from. The glossary is kept next to the document's QA file,
+//! becomes definition questions of its own, and the terms a section mentions
+//! go with it into the question prompt, so answers use them as the document
+//! does.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use crate::difficulty::Difficulty;
+use crate::metadata::RecordMetadata;
+use crate::processor::ProcessedItem;
+
+/// Words of a document sent to the model in one request for terms
+pub const WORDS_PER_REQUEST: usize = 3000;
+
+/// Terms of a glossary passed with a section at most, so the prompt stays
+/// about the section
+const MAX_TERMS_PER_PROMPT: usize = 20;
+
+/// Starts the glossary block [`Glossary::attach`] adds to a section
+const GLOSSARY_MARKER: &str = "\n\nGlossary of this document:\n";
+
+/// Heading of the section the definition questions are generated from
+const GLOSSARY_HEADING: &str = "# Glossary";
+
+/// A domain term and how the document defines it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Term {
+    pub term: String,
+    pub definition: String,
+}
+
+/// The terms of one document, in the order they were found
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Glossary {
+    pub terms: Vec<Term>,
+}
+
+impl Glossary {
+    /// Glossary of `terms`, dropping empty ones and repeats of a term
+    /// (ignoring case), whose first definition is kept
+    pub fn new(terms: impl IntoIterator<Item = Term>) -> Self {
+        let mut seen = HashSet::new();
+        let terms = terms
+            .into_iter()
+            .map(|term| Term {
+                term: term.term.trim().to_string(),
+                definition: term.definition.trim().to_string(),
+            })
+            .filter(|term| !term.term.is_empty() && !term.definition.is_empty())
+            .filter(|term| seen.insert(term.term.to_lowercase()))
+            .collect();
+        Self { terms }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// The glossary as a markdown section, which its definition questions
+    /// are traced to
+    pub fn to_section(&self) -> String {
+        let mut text = format!("{}\n\n", GLOSSARY_HEADING);
+        for term in &self.terms {
+            text.push_str(&format!("- **{}**: {}\n", term.term, term.definition));
+        }
+        text
+    }
+
+    /// A question asking for the definition of each term, answered with it
+    pub fn definitions(&self) -> Vec<ProcessedItem> {
+        self.terms
+            .iter()
+            .map(|term| ProcessedItem {
+                question: format!("What does \"{}\" mean?", term.term),
+                answer: term.definition.clone(),
+                difficulty: Some(Difficulty::Easy),
+                metadata: RecordMetadata {
+                    prompt_template: Some("glossary".to_string()),
+                    ..RecordMetadata::default()
+                },
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    /// `text` followed by the definitions of the terms it mentions, for
+    /// [`detach`] to take off again when the prompt is written
+    pub fn attach(&self, text: &str) -> String {
+        let lowered = text.to_lowercase();
+        let mentioned: Vec<&Term> = self
+            .terms
+            .iter()
+            .filter(|term| lowered.contains(&term.term.to_lowercase()))
+            .take(MAX_TERMS_PER_PROMPT)
+            .collect();
+        if mentioned.is_empty() {
+            return text.to_string();
+        }
+        let mut attached = format!("{}{}", text.trim_end(), GLOSSARY_MARKER);
+        for term in mentioned {
+            attached.push_str(&format!("- {}: {}\n", term.term, term.definition));
+        }
+        attached
+    }
+}
+
+/// Split content into the section and the glossary block
+/// [`Glossary::attach`] added to it, if any
+pub fn detach(content: &str) -> (&str, Option<&str>) {
+    match content.rfind(GLOSSARY_MARKER) {
+        Some(at) => (
+            &content[..at],
+            Some(content[at + GLOSSARY_MARKER.len()..].trim_end()),
+        ),
+        None => (content, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn term(term: &str, definition: &str) -> Term {
+        Term {
+            term: term.to_string(),
+            definition: definition.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_new_drops_empty_and_repeated_terms() {
+        let glossary = Glossary::new([
+            term(" Shard ", "A partition of an index."),
+            term("shard", "Another definition."),
+            term("", "No term."),
+            term("Replica", " "),
+            term("Segment", "An immutable part of a shard."),
+        ]);
+        assert_eq!(
+            glossary.terms,
+            [
+                term("Shard", "A partition of an index."),
+                term("Segment", "An immutable part of a shard.")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_definitions_and_section() {
+        let glossary = Glossary::new([term("Shard", "A partition of an index.")]);
+        let definitions = glossary.definitions();
+        assert_eq!(definitions.len(), 1);
+        assert_eq!(definitions[0].question, "What does \"Shard\" mean?");
+        assert_eq!(definitions[0].answer, "A partition of an index.");
+        assert_eq!(definitions[0].difficulty, Some(Difficulty::Easy));
+        assert_eq!(
+            glossary.to_section(),
+            "# Glossary\n\n- **Shard**: A partition of an index.\n"
+        );
+    }
+
+    #[test]
+    fn test_attach_only_mentioned_terms_and_detach() {
+        let glossary = Glossary::new([
+            term("Shard", "A partition of an index."),
+            term("Replica", "A copy of a shard."),
+        ]);
+        let section = "## Scaling\n\nAdd a shard per node.\n";
+        let attached = glossary.attach(section);
+        assert_eq!(
+            detach(&attached),
+            (
+                "## Scaling\n\nAdd a shard per node.",
+                Some("- Shard: A partition of an index.")
+            )
+        );
+
+        let unrelated = "## Install\n\nRun the installer.\n";
+        assert_eq!(glossary.attach(unrelated), unrelated);
+        assert_eq!(detach(unrelated), (unrelated, None));
+    }
+}
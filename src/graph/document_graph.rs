@@ -1,23 +1,42 @@
 use anyhow::Result;
 use petgraph::{
+    algo,
     graph::{DiGraph, NodeIndex},
+    visit::{EdgeFiltered, EdgeRef},
     Direction,
 };
-use std::collections::HashMap;
+use regex::Regex;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::ops::RangeBounds;
+use std::sync::{Arc, OnceLock};
 use uuid::Uuid;
 
 use crate::graph::{
     edge::{DocumentEdge, RelationType},
     error::GraphError,
+    interner::Interner,
     node::{DocumentNode, NodeType},
+    query::NodeSet,
 };
 
+/// Maximum number of characters of node content shown in DOT labels
+const DOT_LABEL_MAX_CHARS: usize = 40;
+
 /// Represents a document as a directed graph
 pub struct DocumentGraph {
     /// The underlying graph structure
     graph: DiGraph<DocumentNode, DocumentEdge>,
     /// Mapping from UUID to node index for quick lookups
     node_map: HashMap<Uuid, NodeIndex>,
+    /// Normalized-content hash to node UUIDs, built on first use
+    ///
+    /// Kept up to date by `add_node`/`remove_node`; dropped by
+    /// `get_node_mut`, since the content may change through it.
+    content_index: OnceLock<HashMap<String, Vec<Uuid>>>,
+    /// Shared copies of the headings, titles, tags and attributes of the nodes
+    strings: Interner,
 }
 
 impl Default for DocumentGraph {
@@ -32,12 +51,19 @@ impl DocumentGraph {
         Self {
             graph: DiGraph::new(),
             node_map: HashMap::new(),
+            content_index: OnceLock::new(),
+            strings: Interner::new(),
         }
     }
 
-    /// Add a node to the graph
-    pub fn add_node(&mut self, node: DocumentNode) -> NodeIndex {
+    /// Add a node to the graph, sharing its repeated strings with the
+    /// nodes already in it
+    pub fn add_node(&mut self, mut node: DocumentNode) -> NodeIndex {
+        self.strings.intern_node(&mut node);
         let id = node.id;
+        if let Some(index) = self.content_index.get_mut() {
+            index.entry(node.content_hash()).or_default().push(id);
+        }
         let idx = self.graph.add_node(node);
         self.node_map.insert(id, idx);
         idx
@@ -58,14 +84,198 @@ impl DocumentGraph {
         Ok(())
     }
 
+    /// Remove a node and all edges touching it, returning the node
+    ///
+    /// If the node sat in a `Precedes` chain, its neighbours are linked to
+    /// each other so reading order is preserved. Nodes it contained are left
+    /// in place without a parent.
+    pub fn remove_node(&mut self, id: &Uuid) -> Result<DocumentNode> {
+        let idx = *self
+            .node_map
+            .get(id)
+            .ok_or_else(|| GraphError::NodeNotFound(id.to_string()))?;
+
+        let previous = self.precedes_neighbour(idx, Direction::Incoming);
+        let next = self.precedes_neighbour(idx, Direction::Outgoing);
+
+        // petgraph moves the last node into the freed index
+        let last = NodeIndex::new(self.graph.node_count() - 1);
+        let node = self
+            .graph
+            .remove_node(idx)
+            .ok_or_else(|| GraphError::NodeNotFound(id.to_string()))?;
+        self.node_map.remove(id);
+        if let Some(index) = self.content_index.get_mut() {
+            let hash = node.content_hash();
+            if let Some(ids) = index.get_mut(&hash) {
+                ids.retain(|other| other != id);
+                if ids.is_empty() {
+                    index.remove(&hash);
+                }
+            }
+        }
+        if idx != last {
+            let moved = self.graph[idx].id;
+            self.node_map.insert(moved, idx);
+        }
+
+        if let (Some(previous), Some(next)) = (previous, next) {
+            self.add_edge(DocumentEdge::new(previous, next, RelationType::Precedes))?;
+        }
+        Ok(node)
+    }
+
+    /// Remove the edge of the given relation between two nodes, returning it
+    pub fn remove_edge(
+        &mut self,
+        from: &Uuid,
+        to: &Uuid,
+        relation: RelationType,
+    ) -> Result<DocumentEdge> {
+        let edge_not_found = || GraphError::EdgeNotFound {
+            from: from.to_string(),
+            to: to.to_string(),
+        };
+        let (Some(from_idx), Some(to_idx)) = (self.node_map.get(from), self.node_map.get(to))
+        else {
+            return Err(edge_not_found().into());
+        };
+
+        let edge_idx = self
+            .graph
+            .edges_connecting(*from_idx, *to_idx)
+            .find(|edge| edge.weight().relation_type == relation)
+            .map(|edge| edge.id())
+            .ok_or_else(edge_not_found)?;
+        Ok(self
+            .graph
+            .remove_edge(edge_idx)
+            .expect("edge index was just found"))
+    }
+
+    /// Whether an edge of the given relation connects two nodes
+    pub fn has_edge(&self, from: &Uuid, to: &Uuid, relation: &RelationType) -> bool {
+        match (self.node_map.get(from), self.node_map.get(to)) {
+            (Some(from_idx), Some(to_idx)) => self
+                .graph
+                .edges_connecting(*from_idx, *to_idx)
+                .any(|edge| &edge.weight().relation_type == relation),
+            _ => false,
+        }
+    }
+
+    /// UUID of the node linked to this one by a Precedes edge in the given direction
+    fn precedes_neighbour(&self, idx: NodeIndex, direction: Direction) -> Option<Uuid> {
+        self.graph
+            .edges_directed(idx, direction)
+            .find(|edge| edge.weight().relation_type == RelationType::Precedes)
+            .map(|edge| match direction {
+                Direction::Incoming => self.graph[edge.source()].id,
+                Direction::Outgoing => self.graph[edge.target()].id,
+            })
+    }
+
     /// Get a reference to a node by its UUID
     pub fn get_node(&self, id: &Uuid) -> Option<&DocumentNode> {
         self.node_map.get(id).map(|idx| &self.graph[*idx])
     }
 
     /// Get a mutable reference to a node by its UUID
+    ///
+    /// Invalidates the content-hash index, which is rebuilt on next use.
     pub fn get_node_mut(&mut self, id: &Uuid) -> Option<&mut DocumentNode> {
-        self.node_map.get(id).map(|idx| &mut self.graph[*idx])
+        let idx = self.node_map.get(id)?;
+        self.content_index.take();
+        Some(&mut self.graph[*idx])
+    }
+
+    /// Set a node's embedding without invalidating the content-hash index
+    pub fn set_embedding(&mut self, id: &Uuid, embedding: Vec<f32>) -> Result<()> {
+        let idx = self
+            .node_map
+            .get(id)
+            .ok_or_else(|| GraphError::NodeNotFound(id.to_string()))?;
+        self.graph[*idx].set_embedding(embedding);
+        Ok(())
+    }
+
+    /// Set a node attribute without invalidating the content-hash index
+    pub fn set_attribute(&mut self, id: &Uuid, key: &str, value: impl AsRef<str>) -> Result<()> {
+        let idx = self
+            .node_map
+            .get(id)
+            .ok_or_else(|| GraphError::NodeNotFound(id.to_string()))?;
+        let (key, value) = (
+            self.strings.intern(key),
+            self.strings.intern(value.as_ref()),
+        );
+        self.graph[*idx].metadata.attributes.insert(key, value);
+        Ok(())
+    }
+
+    /// Tag a node without invalidating the content-hash index
+    pub fn add_tag(&mut self, id: &Uuid, tag: &str) -> Result<()> {
+        let idx = self
+            .node_map
+            .get(id)
+            .ok_or_else(|| GraphError::NodeNotFound(id.to_string()))?;
+        let tag = self.strings.intern(tag);
+        self.graph[*idx].metadata.tags.push(tag);
+        Ok(())
+    }
+
+    fn content_index(&self) -> &HashMap<String, Vec<Uuid>> {
+        self.content_index.get_or_init(|| {
+            let mut index: HashMap<String, Vec<Uuid>> = HashMap::new();
+            for node in self.graph.node_weights() {
+                index.entry(node.content_hash()).or_default().push(node.id);
+            }
+            index
+        })
+    }
+
+    /// Get all nodes whose normalized content has the given hash
+    pub fn find_by_content_hash(&self, hash: &str) -> Vec<&DocumentNode> {
+        self.content_index()
+            .get(hash)
+            .map(|ids| ids.iter().filter_map(|id| self.get_node(id)).collect())
+            .unwrap_or_default()
+    }
+
+    /// Get the other nodes with the same normalized content as this one
+    pub fn duplicates_of(&self, id: &Uuid) -> Result<Vec<&DocumentNode>> {
+        let node = self
+            .get_node(id)
+            .ok_or_else(|| GraphError::NodeNotFound(id.to_string()))?;
+        Ok(self
+            .find_by_content_hash(&node.content_hash())
+            .into_iter()
+            .filter(|other| other.id != *id)
+            .collect())
+    }
+
+    /// Groups of two or more content nodes sharing the same normalized content
+    ///
+    /// Nodes with empty content are ignored. Useful for spotting boilerplate
+    /// repeated across a corpus.
+    pub fn duplicate_groups(&self) -> Vec<Vec<&DocumentNode>> {
+        let mut groups: Vec<Vec<&DocumentNode>> = self
+            .content_index()
+            .values()
+            .filter(|ids| ids.len() > 1)
+            .map(|ids| {
+                ids.iter()
+                    .filter_map(|id| self.get_node(id))
+                    .collect::<Vec<_>>()
+            })
+            .filter(|nodes: &Vec<&DocumentNode>| {
+                nodes.first().is_some_and(|node| {
+                    node.node_type.is_content() && !node.content.trim().is_empty()
+                })
+            })
+            .collect();
+        groups.sort_by_key(|nodes| nodes[0].metadata.position);
+        groups
     }
 
     /// Get all nodes of a specific type
@@ -83,25 +293,102 @@ impl DocumentGraph {
             .collect()
     }
 
-    /// Get all children of a node (nodes connected by Contains edges)
-    pub fn get_children(&self, id: &Uuid) -> Result<Vec<&DocumentNode>> {
+    /// Get all nodes matching a predicate, in insertion order
+    pub fn filter_nodes<F>(&self, predicate: F) -> Vec<&DocumentNode>
+    where
+        F: Fn(&DocumentNode) -> bool,
+    {
+        self.graph
+            .node_weights()
+            .filter(|node| predicate(node))
+            .collect()
+    }
+
+    /// Get all nodes carrying a tag
+    pub fn find_by_tag(&self, tag: &str) -> Vec<&DocumentNode> {
+        self.filter_nodes(|node| node.metadata.tags.iter().any(|t| &**t == tag))
+    }
+
+    /// Get sections whose heading text matches, ignoring case and surrounding whitespace
+    pub fn find_by_heading(&self, heading: &str) -> Vec<&DocumentNode> {
+        let heading = heading.trim().to_lowercase();
+        self.filter_nodes(|node| {
+            node.node_type == NodeType::Section && node.content.trim().to_lowercase() == heading
+        })
+    }
+
+    /// Get all nodes whose content matches a regular expression
+    pub fn find_by_content(&self, pattern: &Regex) -> Vec<&DocumentNode> {
+        self.filter_nodes(|node| pattern.is_match(&node.content))
+    }
+
+    /// Get all nodes whose level falls within a range (e.g. `2..=3` for H2 and H3)
+    pub fn find_by_level(&self, levels: impl RangeBounds<i32>) -> Vec<&DocumentNode> {
+        self.filter_nodes(|node| node.metadata.level.is_some_and(|l| levels.contains(&l)))
+    }
+
+    /// Start a composable query over all nodes
+    pub fn query(&self) -> NodeSet<'_> {
+        NodeSet::new(self, self.graph.node_weights().collect())
+    }
+
+    /// Get a node and everything it contains, in reading order
+    pub fn descendants(&self, id: &Uuid) -> Result<Vec<&DocumentNode>> {
         let node_idx = self
             .node_map
             .get(id)
             .ok_or_else(|| GraphError::NodeNotFound(id.to_string()))?;
 
-        Ok(self
+        let mut nodes = Vec::new();
+        let mut visited = HashSet::new();
+        let mut stack = vec![*node_idx];
+        while let Some(idx) = stack.pop() {
+            if !visited.insert(idx) {
+                continue;
+            }
+            nodes.push(&self.graph[idx]);
+            stack.extend(self.ordered_children(idx).into_iter().rev());
+        }
+        Ok(nodes)
+    }
+
+    /// Copy the subtree rooted at a node into a new graph
+    ///
+    /// Node UUIDs are preserved. Edges are kept when both ends lie in the
+    /// subtree, so cross-references leaving it are dropped.
+    pub fn subgraph(&self, root_id: &Uuid) -> Result<DocumentGraph> {
+        let ids: HashSet<Uuid> = self
+            .descendants(root_id)?
+            .into_iter()
+            .map(|node| node.id)
+            .collect();
+        Ok(self.induced_subgraph(&ids))
+    }
+
+    /// Copy the given nodes and the edges between them into a new graph
+    pub(crate) fn induced_subgraph(&self, ids: &HashSet<Uuid>) -> DocumentGraph {
+        let mut subgraph = DocumentGraph::new();
+        for node in self
             .graph
-            .neighbors_directed(*node_idx, Direction::Outgoing)
-            .filter_map(|idx| {
-                let edge = self.graph.find_edge(*node_idx, idx)?;
-                if self.graph[edge].relation_type == RelationType::Contains {
-                    Some(&self.graph[idx])
-                } else {
-                    None
-                }
-            })
-            .collect())
+            .node_weights()
+            .filter(|node| ids.contains(&node.id))
+        {
+            subgraph.add_node(node.clone());
+        }
+        for edge in self
+            .graph
+            .edge_weights()
+            .filter(|edge| ids.contains(&edge.from) && ids.contains(&edge.to))
+        {
+            // Both ends were just added, so this cannot fail
+            let _ = subgraph.add_edge(edge.clone());
+        }
+        subgraph
+    }
+
+    /// Get all children of a node (nodes connected by Contains edges), in insertion order
+    pub fn get_children(&self, id: &Uuid) -> Result<Vec<&DocumentNode>> {
+        self.get_outgoing(id, RelationType::Contains)
     }
 
     /// Get the parent of a node (node connected by incoming Contains edge)
@@ -113,36 +400,37 @@ impl DocumentGraph {
 
         Ok(self
             .graph
-            .neighbors_directed(*node_idx, Direction::Incoming)
-            .find_map(|idx| {
-                let edge = self.graph.find_edge(idx, *node_idx)?;
-                if self.graph[edge].relation_type == RelationType::Contains {
-                    Some(&self.graph[idx])
-                } else {
-                    None
-                }
-            }))
+            .edges_directed(*node_idx, Direction::Incoming)
+            .find(|edge| edge.weight().relation_type == RelationType::Contains)
+            .map(|edge| &self.graph[edge.source()]))
+    }
+
+    /// Get all referenced nodes (nodes connected by References edges)
+    pub fn get_references(&self, id: &Uuid) -> Result<Vec<&DocumentNode>> {
+        self.get_outgoing(id, RelationType::References)
     }
 
     /// Get all related nodes (nodes connected by Related edges)
     pub fn get_related_nodes(&self, id: &Uuid) -> Result<Vec<&DocumentNode>> {
+        self.get_outgoing(id, RelationType::Related)
+    }
+
+    /// Get targets of outgoing edges with the given relation, in insertion order
+    fn get_outgoing(&self, id: &Uuid, relation: RelationType) -> Result<Vec<&DocumentNode>> {
         let node_idx = self
             .node_map
             .get(id)
             .ok_or_else(|| GraphError::NodeNotFound(id.to_string()))?;
 
-        Ok(self
+        // petgraph yields the most recently added edge first
+        let mut targets: Vec<&DocumentNode> = self
             .graph
-            .neighbors_directed(*node_idx, Direction::Outgoing)
-            .filter_map(|idx| {
-                let edge = self.graph.find_edge(*node_idx, idx)?;
-                if self.graph[edge].relation_type == RelationType::Related {
-                    Some(&self.graph[idx])
-                } else {
-                    None
-                }
-            })
-            .collect())
+            .edges_directed(*node_idx, Direction::Outgoing)
+            .filter(|edge| edge.weight().relation_type == relation)
+            .map(|edge| &self.graph[edge.target()])
+            .collect();
+        targets.reverse();
+        Ok(targets)
     }
 
     /// Get the path from root to this node
@@ -158,6 +446,386 @@ impl DocumentGraph {
         path.reverse();
         Ok(path)
     }
+
+    /// Get the node following this one among its siblings (outgoing Precedes edge)
+    pub fn get_next_sibling(&self, id: &Uuid) -> Result<Option<&DocumentNode>> {
+        Ok(self
+            .get_outgoing(id, RelationType::Precedes)?
+            .into_iter()
+            .next())
+    }
+
+    /// All nodes in linear document order
+    ///
+    /// Walks the `Contains` tree depth-first from each root (nodes without a
+    /// parent, in insertion order), visiting siblings along their `Precedes`
+    /// chain. Children not reachable through the chain, as in graphs built
+    /// without Precedes edges, follow in insertion order.
+    pub fn reading_order(&self) -> impl Iterator<Item = &DocumentNode> {
+        let mut ordered = Vec::with_capacity(self.graph.node_count());
+        let mut stack: Vec<NodeIndex> = self
+            .graph
+            .node_indices()
+            .filter(|idx| {
+                !self
+                    .graph
+                    .edges_directed(*idx, Direction::Incoming)
+                    .any(|edge| edge.weight().relation_type == RelationType::Contains)
+            })
+            .collect();
+        stack.reverse();
+
+        let mut visited = HashSet::new();
+        while let Some(idx) = stack.pop() {
+            if !visited.insert(idx) {
+                continue;
+            }
+            ordered.push(&self.graph[idx]);
+
+            let children = self.ordered_children(idx);
+            stack.extend(children.into_iter().rev());
+        }
+
+        ordered.into_iter()
+    }
+
+    /// Contains children of a node, ordered along their Precedes chain
+    fn ordered_children(&self, idx: NodeIndex) -> Vec<NodeIndex> {
+        let mut children: Vec<NodeIndex> = self
+            .graph
+            .edges_directed(idx, Direction::Outgoing)
+            .filter(|edge| edge.weight().relation_type == RelationType::Contains)
+            .map(|edge| edge.target())
+            .collect();
+        children.reverse();
+
+        let siblings: HashSet<NodeIndex> = children.iter().copied().collect();
+        let next_sibling = |child: NodeIndex| {
+            self.graph
+                .edges_directed(child, Direction::Outgoing)
+                .find(|edge| {
+                    edge.weight().relation_type == RelationType::Precedes
+                        && siblings.contains(&edge.target())
+                })
+                .map(|edge| edge.target())
+        };
+        let has_previous = |child: NodeIndex| {
+            self.graph
+                .edges_directed(child, Direction::Incoming)
+                .any(|edge| {
+                    edge.weight().relation_type == RelationType::Precedes
+                        && siblings.contains(&edge.source())
+                })
+        };
+
+        let mut ordered = Vec::with_capacity(children.len());
+        let mut seen = HashSet::new();
+        for head in children
+            .iter()
+            .copied()
+            .filter(|child| !has_previous(*child))
+        {
+            let mut current = Some(head);
+            while let Some(child) = current.filter(|child| seen.insert(*child)) {
+                ordered.push(child);
+                current = next_sibling(child);
+            }
+        }
+        ordered.extend(children.into_iter().filter(|child| seen.insert(*child)));
+        ordered
+    }
+
+    /// Get the ancestors of a node along Contains edges, nearest first
+    pub fn ancestors(&self, id: &Uuid) -> Result<Vec<&DocumentNode>> {
+        let mut ancestors = self.get_path_to_root(id)?;
+        ancestors.reverse();
+        Ok(ancestors)
+    }
+
+    /// Get the deepest node containing both nodes (a node counts as its own ancestor)
+    pub fn lowest_common_ancestor(&self, a: &Uuid, b: &Uuid) -> Result<Option<&DocumentNode>> {
+        let node_a = self
+            .get_node(a)
+            .ok_or_else(|| GraphError::NodeNotFound(a.to_string()))?;
+        let mut chain_a = self.get_path_to_root(a)?;
+        chain_a.push(node_a);
+
+        let node_b = self
+            .get_node(b)
+            .ok_or_else(|| GraphError::NodeNotFound(b.to_string()))?;
+        let mut chain_b = self.get_path_to_root(b)?;
+        chain_b.push(node_b);
+
+        Ok(chain_a
+            .iter()
+            .zip(chain_b.iter())
+            .take_while(|(x, y)| x.id == y.id)
+            .last()
+            .map(|(node, _)| *node))
+    }
+
+    /// Get the shortest directed path between two nodes over edges of any relation
+    ///
+    /// The path includes both ends; `None` if `to` is unreachable from `from`.
+    pub fn shortest_path(&self, from: &Uuid, to: &Uuid) -> Result<Option<Vec<&DocumentNode>>> {
+        let from_idx = self
+            .node_map
+            .get(from)
+            .ok_or_else(|| GraphError::NodeNotFound(from.to_string()))?;
+        let to_idx = self
+            .node_map
+            .get(to)
+            .ok_or_else(|| GraphError::NodeNotFound(to.to_string()))?;
+
+        Ok(
+            algo::astar(&self.graph, *from_idx, |idx| idx == *to_idx, |_| 1, |_| 0)
+                .map(|(_, path)| path.into_iter().map(|idx| &self.graph[idx]).collect()),
+        )
+    }
+
+    /// Whether edges of the given relation form a cycle
+    ///
+    /// Contains and Precedes edges should never be cyclic; References and
+    /// Related edges legitimately can be.
+    pub fn has_cycle(&self, relation: &RelationType) -> bool {
+        let filtered =
+            EdgeFiltered::from_fn(&self.graph, |edge| &edge.weight().relation_type == relation);
+        algo::is_cyclic_directed(&filtered)
+    }
+
+    /// Get all Document root nodes, one per parsed file
+    pub fn documents(&self) -> Vec<&DocumentNode> {
+        self.get_nodes_by_type(NodeType::Document)
+    }
+
+    /// Get the Document root a node belongs to
+    pub fn document_of(&self, id: &Uuid) -> Result<Option<&DocumentNode>> {
+        let node = self
+            .get_node(id)
+            .ok_or_else(|| GraphError::NodeNotFound(id.to_string()))?;
+        if node.node_type == NodeType::Document {
+            return Ok(Some(node));
+        }
+
+        Ok(self
+            .get_path_to_root(id)?
+            .into_iter()
+            .find(|n| n.node_type == NodeType::Document))
+    }
+
+    /// Move all nodes and edges of another graph into this one
+    ///
+    /// Node UUIDs are preserved, so edges between the merged graphs can be
+    /// added afterwards. Fails without modifying this graph if any node of
+    /// `other` already exists here.
+    pub fn merge(&mut self, other: DocumentGraph) -> Result<()> {
+        if let Some(duplicate) = other
+            .node_map
+            .keys()
+            .find(|id| self.node_map.contains_key(id))
+        {
+            return Err(GraphError::DuplicateNode(duplicate.to_string()).into());
+        }
+
+        let (nodes, edges) = other.graph.into_nodes_edges();
+        for node in nodes {
+            self.add_node(node.weight);
+        }
+        for edge in edges {
+            self.add_edge(edge.weight)?;
+        }
+        Ok(())
+    }
+
+    /// Combine per-file graphs into a single corpus graph
+    pub fn merge_all(graphs: impl IntoIterator<Item = DocumentGraph>) -> Result<Self> {
+        let mut corpus = Self::new();
+        for graph in graphs {
+            corpus.merge(graph)?;
+        }
+        Ok(corpus)
+    }
+
+    /// Merge small adjacent Text siblings into one node
+    ///
+    /// Two consecutive Text children of the same parent are merged when
+    /// either has fewer than `min_chars` characters. The later node's edges
+    /// are moved onto the earlier one. Returns the number of nodes removed.
+    pub fn compact(&mut self, min_chars: usize) -> Result<usize> {
+        let is_small = |node: &DocumentNode| node.content.trim().chars().count() < min_chars;
+        let parents: Vec<Uuid> = self.node_map.keys().copied().collect();
+        let mut removed = 0;
+
+        for parent in parents {
+            // Absorbed nodes are gone by the time their turn comes
+            let Some(parent_idx) = self.node_map.get(&parent) else {
+                continue;
+            };
+            let mut children: Vec<Uuid> = self
+                .ordered_children(*parent_idx)
+                .into_iter()
+                .map(|idx| self.graph[idx].id)
+                .collect();
+
+            let mut i = 0;
+            while i + 1 < children.len() {
+                let (keep, absorb) = (
+                    &self.node_map[&children[i]],
+                    &self.node_map[&children[i + 1]],
+                );
+                let (keep, absorb) = (&self.graph[*keep], &self.graph[*absorb]);
+                if keep.node_type == NodeType::Text
+                    && absorb.node_type == NodeType::Text
+                    && (is_small(keep) || is_small(absorb))
+                {
+                    self.merge_text_nodes(children[i], children[i + 1])?;
+                    children.remove(i + 1);
+                    removed += 1;
+                } else {
+                    i += 1;
+                }
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Fold `absorb` into `keep`, moving content, span and edges
+    fn merge_text_nodes(&mut self, keep: Uuid, absorb: Uuid) -> Result<()> {
+        let absorb_idx = self.node_map[&absorb];
+        let absorbed = &self.graph[absorb_idx];
+        let (content, span) = (absorbed.content.clone(), absorbed.metadata.span);
+
+        // Contains from the shared parent and the Precedes chain are handled
+        // by `remove_node`; everything else moves to `keep`
+        let moved: Vec<DocumentEdge> = self
+            .graph
+            .edges_directed(absorb_idx, Direction::Incoming)
+            .chain(self.graph.edges_directed(absorb_idx, Direction::Outgoing))
+            .map(|edge| edge.weight().clone())
+            .filter(|edge| match edge.relation_type {
+                RelationType::Precedes => false,
+                RelationType::Contains => edge.from == absorb,
+                _ => true,
+            })
+            .map(|mut edge| {
+                if edge.from == absorb {
+                    edge.from = keep;
+                }
+                if edge.to == absorb {
+                    edge.to = keep;
+                }
+                edge
+            })
+            .collect();
+
+        self.remove_node(&absorb)?;
+
+        let node = self
+            .get_node_mut(&keep)
+            .ok_or_else(|| GraphError::NodeNotFound(keep.to_string()))?;
+        node.content = Arc::from(format!("{}\n\n{}", node.content, content));
+        if let (Some(current), Some(span)) = (node.metadata.span.as_mut(), span) {
+            current.end_byte = current.end_byte.max(span.end_byte);
+            current.end_line = current.end_line.max(span.end_line);
+        }
+
+        for edge in moved {
+            if edge.from != edge.to && !self.has_edge(&edge.from, &edge.to, &edge.relation_type) {
+                self.add_edge(edge)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Render the graph in Graphviz DOT format
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph document {\n");
+        dot.push_str("    node [shape=box, style=filled, fontname=\"Helvetica\"];\n");
+
+        for idx in self.graph.node_indices() {
+            let node = &self.graph[idx];
+            let label = format!(
+                "{:?}\\n{}",
+                node.node_type,
+                escape_dot(&truncate_label(&node.content, DOT_LABEL_MAX_CHARS))
+            );
+            let _ = writeln!(
+                dot,
+                "    \"{}\" [label=\"{}\", fillcolor=\"{}\"];",
+                node.id,
+                label,
+                node.node_type.dot_color()
+            );
+        }
+
+        for edge in self.graph.edge_weights() {
+            let style = if edge.relation_type == RelationType::Contains {
+                "solid"
+            } else {
+                "dashed"
+            };
+            let _ = writeln!(
+                dot,
+                "    \"{}\" -> \"{}\" [label=\"{:?}\", style={}];",
+                edge.from, edge.to, edge.relation_type, style
+            );
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Serialize the graph as JSON, `{"nodes": [...], "edges": [...]}`, with
+    /// nodes in insertion order as stored by [`GraphStore`](crate::graph::GraphStore)
+    pub fn to_json(&self) -> String {
+        #[derive(Serialize)]
+        struct GraphJson<'a> {
+            nodes: Vec<&'a DocumentNode>,
+            edges: Vec<&'a DocumentEdge>,
+        }
+
+        let json = GraphJson {
+            nodes: self.nodes().collect(),
+            edges: self.edges().collect(),
+        };
+        serde_json::to_string(&json).expect("graph serializes to JSON")
+    }
+
+    /// Number of nodes in the graph
+    pub fn node_count(&self) -> usize {
+        self.graph.node_count()
+    }
+
+    /// Number of edges in the graph
+    pub fn edge_count(&self) -> usize {
+        self.graph.edge_count()
+    }
+
+    /// Iterate over all nodes in insertion order
+    pub fn nodes(&self) -> impl Iterator<Item = &DocumentNode> {
+        self.graph.node_weights()
+    }
+
+    /// Iterate over all edges
+    pub fn edges(&self) -> impl Iterator<Item = &DocumentEdge> {
+        self.graph.edge_weights()
+    }
+}
+
+/// Shorten text to a single line of at most `max_chars` characters
+fn truncate_label(text: &str, max_chars: usize) -> String {
+    let single_line = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if single_line.chars().count() <= max_chars {
+        single_line
+    } else {
+        let truncated: String = single_line.chars().take(max_chars).collect();
+        format!("{}...", truncated)
+    }
+}
+
+/// Escape characters with special meaning inside DOT string literals
+fn escape_dot(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
 }
 
 #[cfg(test)]
@@ -235,4 +903,384 @@ mod tests {
         let children = graph.get_children(&parent_id).unwrap();
         assert_eq!(children.len(), 2);
     }
+
+    #[test]
+    fn test_merge_graphs() {
+        let build = |title: &str| {
+            let mut graph = DocumentGraph::new();
+            let root = create_test_node(NodeType::Document, title);
+            let section = create_test_node(NodeType::Section, "Intro");
+            let (root_id, section_id) = (root.id, section.id);
+            graph.add_node(root);
+            graph.add_node(section);
+            graph
+                .add_edge(DocumentEdge::new(
+                    root_id,
+                    section_id,
+                    RelationType::Contains,
+                ))
+                .unwrap();
+            (graph, root_id, section_id)
+        };
+
+        let (first, first_root, first_section) = build("first.md");
+        let (second, second_root, second_section) = build("second.md");
+
+        let mut corpus = DocumentGraph::merge_all(vec![first, second]).unwrap();
+        assert_eq!(corpus.documents().len(), 2);
+        assert_eq!(corpus.node_count(), 4);
+        assert_eq!(corpus.edge_count(), 2);
+        assert_eq!(
+            corpus.document_of(&first_section).unwrap().unwrap().id,
+            first_root
+        );
+        assert_eq!(
+            corpus.document_of(&second_section).unwrap().unwrap().id,
+            second_root
+        );
+
+        // Cross-document edges can be added once merged
+        assert!(corpus
+            .add_edge(DocumentEdge::new(
+                first_section,
+                second_section,
+                RelationType::Related,
+            ))
+            .is_ok());
+
+        // Merging a graph with an existing node is rejected
+        let mut duplicate = DocumentGraph::new();
+        duplicate.add_node(corpus.get_node(&first_root).unwrap().clone());
+        assert!(corpus.merge(duplicate).is_err());
+        assert_eq!(corpus.node_count(), 4);
+    }
+
+    #[test]
+    fn test_reading_order() {
+        let mut graph = DocumentGraph::new();
+        let root = create_test_node(NodeType::Document, "Root");
+        let first = create_test_node(NodeType::Section, "First");
+        let nested = create_test_node(NodeType::Text, "Nested");
+        let second = create_test_node(NodeType::Section, "Second");
+        let ids = [root.id, first.id, nested.id, second.id];
+
+        // Insert out of document order; the Precedes chain decides
+        graph.add_node(root);
+        graph.add_node(second);
+        graph.add_node(nested);
+        graph.add_node(first);
+        graph
+            .add_edge(DocumentEdge::new(ids[0], ids[3], RelationType::Contains))
+            .unwrap();
+        graph
+            .add_edge(DocumentEdge::new(ids[0], ids[1], RelationType::Contains))
+            .unwrap();
+        graph
+            .add_edge(DocumentEdge::new(ids[1], ids[2], RelationType::Contains))
+            .unwrap();
+        graph
+            .add_edge(DocumentEdge::new(ids[1], ids[3], RelationType::Precedes))
+            .unwrap();
+
+        let order: Vec<Uuid> = graph.reading_order().map(|node| node.id).collect();
+        assert_eq!(order, ids);
+        assert_eq!(graph.get_next_sibling(&ids[1]).unwrap().unwrap().id, ids[3]);
+        assert!(graph.get_next_sibling(&ids[3]).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_subgraph() {
+        let mut graph = DocumentGraph::new();
+        let root = create_test_node(NodeType::Document, "Root");
+        let chapter = create_test_node(NodeType::Section, "Chapter");
+        let text = create_test_node(NodeType::Text, "Body");
+        let other = create_test_node(NodeType::Section, "Other");
+        let (root_id, chapter_id, text_id, other_id) = (root.id, chapter.id, text.id, other.id);
+        for node in [root, chapter, text, other] {
+            graph.add_node(node);
+        }
+        for (from, to, relation) in [
+            (root_id, chapter_id, RelationType::Contains),
+            (root_id, other_id, RelationType::Contains),
+            (chapter_id, text_id, RelationType::Contains),
+            (text_id, other_id, RelationType::References),
+        ] {
+            graph
+                .add_edge(DocumentEdge::new(from, to, relation))
+                .unwrap();
+        }
+
+        let chapter = graph.subgraph(&chapter_id).unwrap();
+        assert_eq!(chapter.node_count(), 2);
+        assert_eq!(chapter.edge_count(), 1);
+        assert!(chapter.get_node(&other_id).is_none());
+        assert!(chapter.get_parent(&chapter_id).unwrap().is_none());
+        assert_eq!(chapter.get_children(&chapter_id).unwrap()[0].id, text_id);
+
+        assert!(graph.subgraph(&Uuid::new_v4()).is_err());
+    }
+
+    #[test]
+    fn test_remove_node_keeps_lookup_consistent() {
+        let mut graph = DocumentGraph::new();
+        let nodes: Vec<DocumentNode> = ["a", "b", "c"]
+            .iter()
+            .map(|content| create_test_node(NodeType::Text, content))
+            .collect();
+        let ids: Vec<Uuid> = nodes.iter().map(|node| node.id).collect();
+        for node in nodes {
+            graph.add_node(node);
+        }
+        graph
+            .add_edge(DocumentEdge::new(ids[0], ids[1], RelationType::Precedes))
+            .unwrap();
+        graph
+            .add_edge(DocumentEdge::new(ids[1], ids[2], RelationType::Precedes))
+            .unwrap();
+
+        let removed = graph.remove_node(&ids[0]).unwrap();
+        assert_eq!(&*removed.content, "a");
+        assert!(graph.get_node(&ids[0]).is_none());
+        // The last node was moved into the freed slot and is still found
+        assert_eq!(&*graph.get_node(&ids[2]).unwrap().content, "c");
+
+        graph.remove_node(&ids[1]).unwrap();
+        assert_eq!(graph.node_count(), 1);
+        assert_eq!(graph.edge_count(), 0);
+        assert!(graph.remove_node(&ids[1]).is_err());
+    }
+
+    #[test]
+    fn test_remove_node_bridges_precedes_chain() {
+        let mut graph = DocumentGraph::new();
+        let nodes: Vec<DocumentNode> = ["a", "b", "c"]
+            .iter()
+            .map(|content| create_test_node(NodeType::Text, content))
+            .collect();
+        let ids: Vec<Uuid> = nodes.iter().map(|node| node.id).collect();
+        for node in nodes {
+            graph.add_node(node);
+        }
+        graph
+            .add_edge(DocumentEdge::new(ids[0], ids[1], RelationType::Precedes))
+            .unwrap();
+        graph
+            .add_edge(DocumentEdge::new(ids[1], ids[2], RelationType::Precedes))
+            .unwrap();
+
+        graph.remove_node(&ids[1]).unwrap();
+        assert_eq!(graph.get_next_sibling(&ids[0]).unwrap().unwrap().id, ids[2]);
+
+        let edge = graph
+            .remove_edge(&ids[0], &ids[2], RelationType::Precedes)
+            .unwrap();
+        assert_eq!(edge.to, ids[2]);
+        assert!(graph
+            .remove_edge(&ids[0], &ids[2], RelationType::Precedes)
+            .is_err());
+    }
+
+    #[test]
+    fn test_compact_merges_small_text_nodes() {
+        let markdown = "# Title\n\nHi.\n\nSee [docs](https://example.com).\n\n```\ncode\n```\n\nA paragraph that is long enough to stay on its own.\n";
+        let mut graph = crate::parser::parse_markdown(markdown).unwrap();
+        assert_eq!(graph.get_nodes_by_type(NodeType::Text).len(), 3);
+
+        let removed = graph.compact(10).unwrap();
+        assert_eq!(removed, 1);
+
+        let texts = graph.get_nodes_by_type(NodeType::Text);
+        assert_eq!(texts.len(), 2);
+        let merged = texts
+            .iter()
+            .find(|node| node.content.starts_with("Hi."))
+            .unwrap();
+        assert_eq!(&*merged.content, "Hi.\n\nSee docs.");
+        // The link reference moved to the merged node
+        let link = graph.get_nodes_by_type(NodeType::Link)[0];
+        assert!(graph.has_edge(&merged.id, &link.id, &RelationType::References));
+
+        let order: Vec<NodeType> = graph
+            .reading_order()
+            .map(|node| node.node_type.clone())
+            .filter(|node_type| *node_type != NodeType::Link)
+            .collect();
+        assert_eq!(
+            order,
+            [
+                NodeType::Document,
+                NodeType::Section,
+                NodeType::Text,
+                NodeType::Code,
+                NodeType::Text
+            ]
+        );
+    }
+
+    #[test]
+    fn test_graph_algorithms() {
+        let mut graph = DocumentGraph::new();
+        let nodes: Vec<DocumentNode> = ["root", "chapter", "intro", "details", "appendix"]
+            .iter()
+            .map(|content| create_test_node(NodeType::Section, content))
+            .collect();
+        let [root, chapter, intro, details, appendix] = [0, 1, 2, 3, 4].map(|i| nodes[i].id);
+        for node in nodes {
+            graph.add_node(node);
+        }
+        for (from, to, relation) in [
+            (root, chapter, RelationType::Contains),
+            (chapter, intro, RelationType::Contains),
+            (chapter, details, RelationType::Contains),
+            (root, appendix, RelationType::Contains),
+            (appendix, intro, RelationType::References),
+        ] {
+            graph
+                .add_edge(DocumentEdge::new(from, to, relation))
+                .unwrap();
+        }
+
+        let ancestors: Vec<Uuid> = graph
+            .ancestors(&details)
+            .unwrap()
+            .iter()
+            .map(|node| node.id)
+            .collect();
+        assert_eq!(ancestors, [chapter, root]);
+
+        let lca = |a, b| graph.lowest_common_ancestor(&a, &b).unwrap().unwrap().id;
+        assert_eq!(lca(intro, details), chapter);
+        assert_eq!(lca(details, appendix), root);
+        assert_eq!(lca(chapter, details), chapter);
+
+        let path: Vec<Uuid> = graph
+            .shortest_path(&appendix, &intro)
+            .unwrap()
+            .unwrap()
+            .iter()
+            .map(|node| node.id)
+            .collect();
+        assert_eq!(path, [appendix, intro]);
+        assert!(graph.shortest_path(&intro, &root).unwrap().is_none());
+
+        assert!(!graph.has_cycle(&RelationType::Contains));
+        graph
+            .add_edge(DocumentEdge::new(intro, appendix, RelationType::References))
+            .unwrap();
+        assert!(graph.has_cycle(&RelationType::References));
+        assert!(!graph.has_cycle(&RelationType::Contains));
+    }
+
+    #[test]
+    fn test_content_hash_index() {
+        let mut graph = DocumentGraph::new();
+        let first = create_test_node(NodeType::Text, "Licensed under MIT.");
+        let second = create_test_node(NodeType::Text, "  licensed under\nMIT. ");
+        let other = create_test_node(NodeType::Text, "Something else.");
+        let (first_id, second_id, other_id) = (first.id, second.id, other.id);
+        graph.add_node(first);
+        graph.add_node(other);
+
+        assert!(graph.duplicates_of(&first_id).unwrap().is_empty());
+
+        // Nodes added after the index was built are indexed too
+        graph.add_node(second);
+        assert_eq!(graph.duplicates_of(&first_id).unwrap()[0].id, second_id);
+        assert_eq!(graph.duplicate_groups().len(), 1);
+
+        // Editing content through get_node_mut is picked up
+        graph.get_node_mut(&other_id).unwrap().content = Arc::from("Licensed under MIT");
+        assert_eq!(graph.duplicates_of(&first_id).unwrap().len(), 1);
+        graph.get_node_mut(&other_id).unwrap().content = Arc::from("licensed under mit.");
+        assert_eq!(graph.duplicates_of(&first_id).unwrap().len(), 2);
+
+        graph.remove_node(&second_id).unwrap();
+        assert_eq!(graph.duplicates_of(&first_id).unwrap()[0].id, other_id);
+        let hash = graph.get_node(&first_id).unwrap().content_hash();
+        assert_eq!(graph.find_by_content_hash(&hash).len(), 2);
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let mut graph = DocumentGraph::new();
+
+        let parent = create_test_node(NodeType::Section, "A \"quoted\" heading");
+        let child = create_test_node(
+            NodeType::Text,
+            "A very long paragraph of text that should be truncated in the label",
+        );
+        let parent_id = parent.id;
+        let child_id = child.id;
+
+        graph.add_node(parent);
+        graph.add_node(child);
+        graph
+            .add_edge(DocumentEdge::new(
+                parent_id,
+                child_id,
+                RelationType::Contains,
+            ))
+            .unwrap();
+
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph document {"));
+        assert!(dot.contains("A \\\"quoted\\\" heading"));
+        assert!(dot.contains("fillcolor=\"lightblue\""));
+        assert!(dot.contains("..."));
+        assert!(dot.contains(&format!("\"{}\" -> \"{}\"", parent_id, child_id)));
+    }
+
+    #[test]
+    fn test_to_json() {
+        let mut graph = DocumentGraph::new();
+        let parent = create_test_node(NodeType::Section, "Usage");
+        let child = create_test_node(NodeType::Text, "Run it.");
+        let (parent_id, child_id) = (parent.id, child.id);
+        graph.add_node(parent);
+        graph.add_node(child);
+        graph
+            .add_edge(DocumentEdge::new(
+                parent_id,
+                child_id,
+                RelationType::Contains,
+            ))
+            .unwrap();
+
+        let json: serde_json::Value = serde_json::from_str(&graph.to_json()).unwrap();
+        let nodes = json["nodes"].as_array().unwrap();
+        assert_eq!(nodes.len(), 2);
+        assert_eq!(nodes[0]["content"], "Usage");
+        assert_eq!(nodes[1]["node_type"], "Text");
+        assert_eq!(json["edges"][0]["from"], parent_id.to_string());
+        assert_eq!(json["edges"][0]["relation_type"], "Contains");
+
+        let node: DocumentNode = serde_json::from_value(nodes[1].clone()).unwrap();
+        assert_eq!(node.id, child_id);
+    }
+
+    #[test]
+    fn test_repeated_strings_are_shared() {
+        let mut graph = DocumentGraph::new();
+        let ids: Vec<Uuid> = (0..2)
+            .map(|_| {
+                let node = create_test_node(NodeType::Section, "Usage");
+                let id = node.id;
+                graph.add_node(node);
+                id
+            })
+            .collect();
+        for id in &ids {
+            graph.add_tag(id, "lang:rust").unwrap();
+            graph.set_attribute(id, "source", "guide.md").unwrap();
+        }
+
+        let first = graph.get_node(&ids[0]).unwrap();
+        let second = graph.get_node(&ids[1]).unwrap();
+        assert!(Arc::ptr_eq(&first.content, &second.content));
+        assert!(Arc::ptr_eq(
+            &first.metadata.tags[0],
+            &second.metadata.tags[0]
+        ));
+        assert_eq!(second.attribute("source"), Some("guide.md"));
+    }
 }
@@ -8,6 +8,9 @@ pub enum GraphError {
     #[error("Edge not found between {from} and {to}")]
     EdgeNotFound { from: String, to: String },
 
+    #[error("Duplicate node: {0}")]
+    DuplicateNode(String),
+
     #[error("Invalid node type: {0}")]
     InvalidNodeType(String),
 
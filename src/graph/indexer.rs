@@ -0,0 +1,170 @@
+use anyhow::Result;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::external::Embedder;
+use crate::graph::{
+    document_graph::DocumentGraph,
+    error::GraphError,
+    node::{DocumentNode, NodeType},
+    store::VectorStore,
+};
+
+/// Node attribute holding the ID of the node's vector in the store
+pub const VECTOR_ID_ATTRIBUTE: &str = "vector_id";
+
+/// Embeds content nodes of a graph and stores them in a `VectorStore`
+pub struct GraphIndexer<'a> {
+    engine: &'a dyn Embedder,
+    store: &'a VectorStore,
+}
+
+impl<'a> GraphIndexer<'a> {
+    pub fn new(engine: &'a dyn Embedder, store: &'a VectorStore) -> Self {
+        Self { engine, store }
+    }
+
+    /// Embed and store every content node that has not been indexed yet
+    ///
+    /// Embeddings already present on a node (e.g. from similarity linking)
+    /// or on a node with the same normalized content are reused. All
+    /// vectors are stored in one batched insert, after which the embedding
+    /// and the vector ID are written back to each node. Returns the number
+    /// of nodes indexed.
+    pub async fn index(&self, graph: &mut DocumentGraph) -> Result<usize> {
+        let pending: Vec<Uuid> = graph
+            .filter_nodes(|node| {
+                is_indexable(node) && node.attribute(VECTOR_ID_ATTRIBUTE).is_none()
+            })
+            .into_iter()
+            .map(|node| node.id)
+            .collect();
+
+        let mut entries = Vec::with_capacity(pending.len());
+        for id in &pending {
+            let (embedding, payload) = {
+                let node = graph
+                    .get_node(id)
+                    .expect("pending nodes come from the graph");
+                // Identical content (e.g. repeated boilerplate) is embedded once
+                let existing = node.embedding().cloned().or_else(|| {
+                    graph
+                        .duplicates_of(id)
+                        .ok()?
+                        .into_iter()
+                        .find_map(|duplicate| duplicate.embedding().cloned())
+                });
+                let embedding = match existing {
+                    Some(embedding) => embedding,
+                    None => self.engine.embed(&node.content).await?,
+                };
+                (embedding, node_payload(graph, id)?)
+            };
+
+            // Later duplicates can reuse this embedding before it is stored
+            graph.set_embedding(id, embedding.clone())?;
+            entries.push((*id, embedding, payload));
+        }
+
+        let vector_ids = self.store.add_embeddings(entries).await?;
+        for (id, vector_id) in pending.iter().zip(vector_ids) {
+            graph.set_attribute(id, VECTOR_ID_ATTRIBUTE, vector_id)?;
+        }
+
+        Ok(pending.len())
+    }
+}
+
+/// Whether a node is embedded by the indexer
+pub(crate) fn is_indexable(node: &DocumentNode) -> bool {
+    node.node_type.is_content() && !node.content.trim().is_empty()
+}
+
+/// Payload stored with a node's vector
+///
+/// Identifies the node and carries enough context (document and nearest
+/// heading) to show a search hit without loading the graph. `node_type`,
+/// `document`, `document_path` and `tags` are what `SearchFilter` matches on.
+pub fn node_payload(graph: &DocumentGraph, id: &Uuid) -> Result<Value> {
+    let node = graph
+        .get_node(id)
+        .ok_or_else(|| GraphError::NodeNotFound(id.to_string()))?;
+    let path = graph.get_path_to_root(id)?;
+
+    let document = graph.document_of(id)?;
+    // Node tags plus document-level (front matter) tags, for filtering
+    let mut tags = node.metadata.tags.clone();
+    for tag in document.iter().flat_map(|d| &d.metadata.tags) {
+        if !tags.contains(tag) {
+            tags.push(tag.clone());
+        }
+    }
+    let heading = path
+        .iter()
+        .rev()
+        .find(|n| n.node_type == NodeType::Section)
+        .map(|n| &*n.content);
+
+    Ok(json!({
+        "node_id": node.id.to_string(),
+        "node_type": format!("{:?}", node.node_type),
+        "content": node.content,
+        "document": document
+            .and_then(|d| d.metadata.title.as_deref())
+            .unwrap_or_default(),
+        "document_path": document
+            .and_then(|d| d.attribute("path"))
+            .unwrap_or_default(),
+        "heading": heading.unwrap_or_default(),
+        "tags": tags,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_markdown;
+
+    #[test]
+    fn test_node_payload() {
+        let graph = parse_markdown("# Guide\n\n## TLS\n\nEnable TLS in the config.\n").unwrap();
+        let text = graph.get_nodes_by_type(NodeType::Text)[0];
+
+        let payload = node_payload(&graph, &text.id).unwrap();
+        assert_eq!(payload["node_id"], text.id.to_string());
+        assert_eq!(payload["node_type"], "Text");
+        assert_eq!(payload["content"], "Enable TLS in the config.");
+        assert_eq!(payload["document"], "Guide");
+        assert_eq!(payload["heading"], "TLS");
+
+        assert_eq!(payload["tags"], json!([]));
+    }
+
+    #[test]
+    fn test_node_payload_tags() {
+        let graph =
+            parse_markdown("---\ntags: [security]\n---\n# Guide\n\n```rust\nfn main() {}\n```\n")
+                .unwrap();
+        let code = graph.get_nodes_by_type(NodeType::Code)[0];
+
+        let payload = node_payload(&graph, &code.id).unwrap();
+        let tags = payload["tags"].as_array().unwrap();
+        assert!(tags.contains(&json!("security")));
+        assert!(tags.contains(&json!("language:rust")));
+    }
+
+    #[test]
+    fn test_indexable_nodes() {
+        let graph = parse_markdown("# Guide\n\n- item\n\n[link](https://example.com)\n").unwrap();
+        let indexable: Vec<NodeType> = graph
+            .filter_nodes(is_indexable)
+            .into_iter()
+            .map(|node| node.node_type.clone())
+            .collect();
+
+        assert!(indexable.contains(&NodeType::Section));
+        assert!(indexable.contains(&NodeType::ListItem));
+        assert!(!indexable.contains(&NodeType::Document));
+        assert!(!indexable.contains(&NodeType::Link));
+    }
+}
@@ -0,0 +1,97 @@
+//! Shared storage for the strings that repeat across a corpus graph.
+//!
+//! Tags, headings, titles and attributes such as code block languages take
+//! a handful of distinct values across hundreds of thousands of nodes, so
+//! each distinct value is kept once and nodes hold cheap `Arc<str>` handles.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use crate::graph::node::{DocumentNode, NodeType};
+
+/// Set of distinct strings handed out as shared `Arc<str>`s
+#[derive(Debug, Default, Clone)]
+pub struct Interner {
+    strings: HashSet<Arc<str>>,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The shared copy of `text`, added on first use
+    pub fn intern(&mut self, text: &str) -> Arc<str> {
+        if let Some(existing) = self.strings.get(text) {
+            return Arc::clone(existing);
+        }
+        let interned: Arc<str> = Arc::from(text);
+        self.strings.insert(Arc::clone(&interned));
+        interned
+    }
+
+    /// Replace the node's repeated strings with their shared copies
+    ///
+    /// Headings (the content of section nodes), titles, tags and attributes
+    /// are interned; other content is mostly unique and is left as it is.
+    pub fn intern_node(&mut self, node: &mut DocumentNode) {
+        if matches!(node.node_type, NodeType::Section | NodeType::Subsection) {
+            node.content = self.intern(&node.content);
+        }
+        let metadata = &mut node.metadata;
+        if let Some(title) = metadata.title.as_mut() {
+            *title = self.intern(title);
+        }
+        for tag in &mut metadata.tags {
+            *tag = self.intern(tag);
+        }
+        metadata.attributes = std::mem::take(&mut metadata.attributes)
+            .into_iter()
+            .map(|(key, value)| (self.intern(&key), self.intern(&value)))
+            .collect();
+    }
+
+    /// Number of distinct strings
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intern_shares_one_copy() {
+        let mut interner = Interner::new();
+        let first = interner.intern("rust");
+        let second = interner.intern(&String::from("rust"));
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(interner.len(), 1);
+
+        let mut a = DocumentNode::new(
+            NodeType::Section,
+            "Installation",
+            Some("Guide".to_string()),
+            Some(2),
+            0,
+            vec!["docs".to_string()],
+        );
+        a.set_attribute("language", "rust");
+        let mut b = a.clone();
+        b.content = Arc::from("Installation");
+        b.metadata.tags = vec![Arc::from("docs")];
+
+        interner.intern_node(&mut a);
+        interner.intern_node(&mut b);
+        assert!(Arc::ptr_eq(&a.content, &b.content));
+        assert!(Arc::ptr_eq(&a.metadata.tags[0], &b.metadata.tags[0]));
+        assert_eq!(b.attribute("language"), Some("rust"));
+        // "rust", "Installation", "Guide", "docs", "language"
+        assert_eq!(interner.len(), 5);
+    }
+}
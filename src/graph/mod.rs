@@ -1,11 +1,31 @@
 pub mod document_graph;
 pub mod edge;
 pub mod error;
+#[cfg(feature = "native")]
+pub mod indexer;
+pub mod interner;
 pub mod node;
+#[cfg(feature = "native")]
+pub mod persistence;
+pub mod query;
+#[cfg(feature = "native")]
+pub mod similarity;
+#[cfg(feature = "native")]
 pub mod store;
+pub mod tagging;
 
 pub use document_graph::DocumentGraph;
 pub use edge::DocumentEdge;
 pub use error::GraphError;
-pub use node::DocumentNode;
-pub use store::VectorStore;
+#[cfg(feature = "native")]
+pub use indexer::GraphIndexer;
+pub use interner::Interner;
+pub use node::{DocumentNode, SourceSpan};
+#[cfg(feature = "native")]
+pub use persistence::GraphStore;
+pub use query::NodeSet;
+#[cfg(feature = "native")]
+pub use similarity::{add_similarity_edges, SimilarityConfig};
+#[cfg(feature = "native")]
+pub use store::{SnapshotHeader, VectorStore};
+pub use tagging::{tag_nodes, TaggingConfig};
@@ -1,8 +1,12 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
 use uuid::Uuid;
 
+use crate::hash::hash_text;
+
 /// Type of document node
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum NodeType {
     Document,
     Section,
@@ -22,13 +26,71 @@ pub enum NodeType {
     CodeBlock,
 }
 
+impl NodeType {
+    /// Fill color used when rendering nodes of this type with Graphviz
+    pub fn dot_color(&self) -> &'static str {
+        match self {
+            NodeType::Document => "lightgoldenrod",
+            NodeType::Section | NodeType::Subsection => "lightblue",
+            NodeType::Text | NodeType::Paragraph => "white",
+            NodeType::Code | NodeType::CodeBlock => "lightgrey",
+            NodeType::List | NodeType::ListItem => "palegreen",
+            NodeType::Table | NodeType::TableRow | NodeType::TableCell => "wheat",
+            NodeType::Link | NodeType::Image => "plum",
+            NodeType::Quote | NodeType::Footnote => "mistyrose",
+        }
+    }
+
+    /// Whether nodes of this type carry text worth embedding on their own
+    ///
+    /// Containers (documents, lists, tables), single cells and link targets
+    /// are excluded; their text is covered by the nodes around them.
+    pub fn is_content(&self) -> bool {
+        matches!(
+            self,
+            NodeType::Section
+                | NodeType::Subsection
+                | NodeType::Text
+                | NodeType::Paragraph
+                | NodeType::Code
+                | NodeType::CodeBlock
+                | NodeType::ListItem
+                | NodeType::TableRow
+                | NodeType::Quote
+                | NodeType::Footnote
+        )
+    }
+}
+
+/// Location of a node in its source file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceSpan {
+    /// Byte offset of the first character
+    pub start_byte: usize,
+    /// Byte offset just past the last character
+    pub end_byte: usize,
+    /// First line of the node (1-based)
+    pub start_line: usize,
+    /// Last line of the node (1-based, inclusive)
+    pub end_line: usize,
+}
+
 /// Metadata associated with a document node
+///
+/// Strings are shared handles; a [`DocumentGraph`](crate::graph::DocumentGraph)
+/// interns them so repeated values are stored once.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NodeMetadata {
-    pub title: Option<String>,
+    pub title: Option<Arc<str>>,
     pub level: Option<i32>,
     pub position: usize,
-    pub tags: Vec<String>,
+    pub tags: Vec<Arc<str>>,
+    /// Additional key/value attributes such as link URLs
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub attributes: BTreeMap<Arc<str>, Arc<str>>,
+    /// Where the node was found in its source file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub span: Option<SourceSpan>,
 }
 
 /// Represents a node in the document graph
@@ -39,7 +101,7 @@ pub struct DocumentNode {
     /// Type of the node
     pub node_type: NodeType,
     /// Actual content of the node
-    pub content: String,
+    pub content: Arc<str>,
     /// Node metadata
     pub metadata: NodeMetadata,
     /// Vector embedding of the node content
@@ -51,7 +113,7 @@ impl DocumentNode {
     /// Create a new document node
     pub fn new(
         node_type: NodeType,
-        content: String,
+        content: impl Into<Arc<str>>,
         title: Option<String>,
         level: Option<i32>,
         position: usize,
@@ -60,12 +122,14 @@ impl DocumentNode {
         Self {
             id: Uuid::new_v4(),
             node_type,
-            content,
+            content: content.into(),
             metadata: NodeMetadata {
-                title,
+                title: title.map(Arc::from),
                 level,
                 position,
-                tags,
+                tags: tags.into_iter().map(Arc::from).collect(),
+                attributes: BTreeMap::new(),
+                span: None,
             },
             embedding: None,
         }
@@ -80,6 +144,32 @@ impl DocumentNode {
     pub fn embedding(&self) -> Option<&Vec<f32>> {
         self.embedding.as_ref()
     }
+
+    /// Set a metadata attribute
+    pub fn set_attribute(&mut self, key: &str, value: impl Into<Arc<str>>) {
+        self.metadata
+            .attributes
+            .insert(Arc::from(key), value.into());
+    }
+
+    /// Get a metadata attribute if it exists
+    pub fn attribute(&self, key: &str) -> Option<&str> {
+        self.metadata.attributes.get(key).map(|value| &**value)
+    }
+
+    /// Hash of the node's content after normalization
+    ///
+    /// Whitespace runs are collapsed and case is ignored, so reflowed or
+    /// re-indented copies of the same text hash alike.
+    pub fn content_hash(&self) -> String {
+        let normalized = self
+            .content
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase();
+        hash_text(&normalized)
+    }
 }
 
 #[cfg(test)]
@@ -98,11 +188,11 @@ mod tests {
         );
 
         assert_eq!(node.node_type, NodeType::Section);
-        assert_eq!(node.content, "Test content");
-        assert_eq!(node.metadata.title, Some("Test title".to_string()));
+        assert_eq!(&*node.content, "Test content");
+        assert_eq!(node.metadata.title.as_deref(), Some("Test title"));
         assert_eq!(node.metadata.level, Some(1));
         assert_eq!(node.metadata.position, 0);
-        assert_eq!(node.metadata.tags, vec!["test"]);
+        assert_eq!(node.metadata.tags, vec![Arc::from("test")]);
         assert!(node.embedding.is_none());
     }
 
@@ -124,4 +214,17 @@ mod tests {
 
         assert_eq!(node.embedding(), Some(&embedding));
     }
+
+    #[test]
+    fn test_attribute_operations() {
+        let mut node = DocumentNode::new(NodeType::Link, "docs".to_string(), None, None, 0, vec![]);
+
+        assert!(node.attribute("url").is_none());
+        node.set_attribute("url", "https://example.com");
+        assert_eq!(node.attribute("url"), Some("https://example.com"));
+
+        let json = serde_json::to_string(&node).unwrap();
+        let restored: DocumentNode = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.attribute("url"), Some("https://example.com"));
+    }
 }
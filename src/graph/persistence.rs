@@ -0,0 +1,306 @@
+use anyhow::{Context, Result};
+use sled::{Db, Tree};
+use std::path::Path;
+use uuid::Uuid;
+
+use crate::graph::{
+    document_graph::DocumentGraph,
+    edge::DocumentEdge,
+    node::{DocumentNode, NodeType},
+};
+
+/// Persistent storage for document graphs in an embedded sled database
+///
+/// Nodes and edges are written individually, so a corpus graph can be
+/// updated one document at a time and loaded again without re-parsing.
+/// Call `flush` once a batch of changes is complete.
+/// Records are kept in insertion order, which preserves child ordering
+/// when the graph is loaded.
+pub struct GraphStore {
+    db: Db,
+    /// sequence number -> node JSON
+    nodes: Tree,
+    /// node UUID -> sequence number
+    node_ids: Tree,
+    /// sequence number -> edge JSON
+    edges: Tree,
+    /// node UUID + edge sequence number -> (), for both ends of each edge
+    edges_by_node: Tree,
+    /// document key -> JSON list of the node UUIDs stored for that document
+    documents: Tree,
+}
+
+impl GraphStore {
+    /// Open (or create) a store at the given directory
+    pub fn open(path: &Path) -> Result<Self> {
+        // No background flusher: it would keep the database locked after
+        // the store is dropped. Changes are flushed by `flush` and on drop.
+        let db = sled::Config::new()
+            .path(path)
+            .flush_every_ms(None)
+            .open()
+            .with_context(|| format!("Failed to open graph store at {:?}", path))?;
+        Ok(Self {
+            nodes: db.open_tree("nodes")?,
+            node_ids: db.open_tree("node_ids")?,
+            edges: db.open_tree("edges")?,
+            edges_by_node: db.open_tree("edges_by_node")?,
+            documents: db.open_tree("documents")?,
+            db,
+        })
+    }
+
+    /// Insert or update a node, keeping its original position
+    pub fn put_node(&self, node: &DocumentNode) -> Result<()> {
+        let seq = match self.node_ids.get(node.id.as_bytes())? {
+            Some(seq) => seq.to_vec(),
+            None => {
+                let seq = self.db.generate_id()?.to_be_bytes().to_vec();
+                self.node_ids.insert(node.id.as_bytes(), seq.as_slice())?;
+                seq
+            }
+        };
+        self.nodes.insert(seq, serde_json::to_vec(node)?)?;
+        Ok(())
+    }
+
+    /// Append an edge
+    pub fn put_edge(&self, edge: &DocumentEdge) -> Result<()> {
+        let seq = self.db.generate_id()?.to_be_bytes();
+        self.edges.insert(seq, serde_json::to_vec(edge)?)?;
+        self.edges_by_node
+            .insert(node_edge_key(&edge.from, &seq), &[])?;
+        self.edges_by_node
+            .insert(node_edge_key(&edge.to, &seq), &[])?;
+        Ok(())
+    }
+
+    /// Get a stored node by UUID
+    pub fn get_node(&self, id: &Uuid) -> Result<Option<DocumentNode>> {
+        let Some(seq) = self.node_ids.get(id.as_bytes())? else {
+            return Ok(None);
+        };
+        match self.nodes.get(seq)? {
+            Some(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Remove a node and every edge touching it
+    pub fn remove_node(&self, id: &Uuid) -> Result<()> {
+        let prefix = id.as_bytes();
+        let edge_seqs: Vec<Vec<u8>> = self
+            .edges_by_node
+            .scan_prefix(prefix)
+            .keys()
+            .map(|key| key.map(|key| key[prefix.len()..].to_vec()))
+            .collect::<Result<_, _>>()?;
+
+        for seq in edge_seqs {
+            if let Some(bytes) = self.edges.remove(&seq)? {
+                let edge: DocumentEdge = serde_json::from_slice(&bytes)?;
+                self.edges_by_node.remove(node_edge_key(&edge.from, &seq))?;
+                self.edges_by_node.remove(node_edge_key(&edge.to, &seq))?;
+            }
+        }
+
+        if let Some(seq) = self.node_ids.remove(prefix)? {
+            self.nodes.remove(seq)?;
+        }
+        Ok(())
+    }
+
+    /// Store every node and edge of a graph
+    ///
+    /// Nodes already present are updated in place; edges are appended, so
+    /// saving the same graph twice duplicates its edges. Use
+    /// `replace_document` to update documents that are already stored.
+    pub fn save_graph(&self, graph: &DocumentGraph) -> Result<()> {
+        for node in graph.nodes() {
+            self.put_node(node)?;
+        }
+        for edge in graph.edges() {
+            self.put_edge(edge)?;
+        }
+        self.record_documents(graph)?;
+        Ok(())
+    }
+
+    /// Replace the stored version of each document in the graph
+    ///
+    /// Documents are identified by their `path` attribute (falling back to
+    /// the node UUID); nodes previously stored for the same document are
+    /// removed before the new ones are written.
+    pub fn replace_document(&self, graph: &DocumentGraph) -> Result<()> {
+        for document in graph.documents() {
+            if let Some(stored) = self.documents.get(document_key(document))? {
+                let ids: Vec<Uuid> = serde_json::from_slice(&stored)?;
+                for id in ids {
+                    self.remove_node(&id)?;
+                }
+            }
+        }
+        self.save_graph(graph)
+    }
+
+    /// Remove a stored document and all of its nodes
+    pub fn remove_document(&self, key: &str) -> Result<bool> {
+        let Some(stored) = self.documents.remove(key)? else {
+            return Ok(false);
+        };
+        let ids: Vec<Uuid> = serde_json::from_slice(&stored)?;
+        for id in ids {
+            self.remove_node(&id)?;
+        }
+        Ok(true)
+    }
+
+    /// Keys of all stored documents
+    pub fn document_keys(&self) -> Result<Vec<String>> {
+        self.documents
+            .iter()
+            .keys()
+            .map(|key| Ok(String::from_utf8_lossy(&key?).into_owned()))
+            .collect()
+    }
+
+    /// Load the whole stored corpus as one graph
+    pub fn load_graph(&self) -> Result<DocumentGraph> {
+        let mut graph = DocumentGraph::new();
+        for bytes in self.nodes.iter().values() {
+            graph.add_node(serde_json::from_slice(&bytes?)?);
+        }
+        for bytes in self.edges.iter().values() {
+            graph.add_edge(serde_json::from_slice(&bytes?)?)?;
+        }
+        Ok(graph)
+    }
+
+    /// Number of stored nodes
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Number of stored edges
+    pub fn edge_count(&self) -> usize {
+        self.edges.len()
+    }
+
+    /// Write all pending changes to disk
+    pub fn flush(&self) -> Result<()> {
+        self.db.flush()?;
+        Ok(())
+    }
+
+    /// Remember which nodes belong to each document in the graph
+    fn record_documents(&self, graph: &DocumentGraph) -> Result<()> {
+        for document in graph.documents() {
+            let ids: Vec<Uuid> = graph
+                .descendants(&document.id)?
+                .into_iter()
+                .map(|node| node.id)
+                .collect();
+            self.documents
+                .insert(document_key(document), serde_json::to_vec(&ids)?)?;
+        }
+        Ok(())
+    }
+}
+
+/// Key identifying a document across runs
+fn document_key(document: &DocumentNode) -> String {
+    debug_assert_eq!(document.node_type, NodeType::Document);
+    document
+        .attribute("path")
+        .map(str::to_string)
+        .unwrap_or_else(|| document.id.to_string())
+}
+
+fn node_edge_key(id: &Uuid, seq: &[u8]) -> Vec<u8> {
+    let mut key = id.as_bytes().to_vec();
+    key.extend_from_slice(seq);
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_markdown_file;
+    use std::fs;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_save_and_load_graph() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let doc = temp_dir.path().join("guide.md");
+        fs::write(
+            &doc,
+            "# Guide\n\n## Install\n\nRun it.\n\n## Usage\n\nUse it.\n",
+        )
+        .unwrap();
+        let graph = parse_markdown_file(&doc).unwrap();
+        let root = graph.documents()[0].id;
+        let expected: Vec<Arc<str>> = graph
+            .get_children(&root)
+            .unwrap()
+            .iter()
+            .map(|node| node.content.clone())
+            .collect();
+
+        let store_path = temp_dir.path().join("graph.db");
+        {
+            let store = GraphStore::open(&store_path).unwrap();
+            store.save_graph(&graph).unwrap();
+            store.flush().unwrap();
+        }
+
+        // Reopen to make sure the data survives
+        let store = GraphStore::open(&store_path).unwrap();
+        let loaded = store.load_graph().unwrap();
+        assert_eq!(loaded.node_count(), graph.node_count());
+        assert_eq!(loaded.edge_count(), graph.edge_count());
+        let children: Vec<Arc<str>> = loaded
+            .get_children(&root)
+            .unwrap()
+            .iter()
+            .map(|node| node.content.clone())
+            .collect();
+        assert_eq!(children, expected);
+        assert_eq!(store.document_keys().unwrap(), [doc.display().to_string()]);
+    }
+
+    #[test]
+    fn test_replace_document() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let doc = temp_dir.path().join("guide.md");
+        let other = temp_dir.path().join("other.md");
+        fs::write(&other, "# Other\n\nUnchanged.\n").unwrap();
+        fs::write(&doc, "# Guide\n\nOld text.\n").unwrap();
+
+        let store = GraphStore::open(&temp_dir.path().join("graph.db")).unwrap();
+        store
+            .save_graph(&parse_markdown_file(&other).unwrap())
+            .unwrap();
+        store
+            .save_graph(&parse_markdown_file(&doc).unwrap())
+            .unwrap();
+        let before = store.node_count();
+
+        fs::write(&doc, "# Guide\n\nNew text.\n").unwrap();
+        store
+            .replace_document(&parse_markdown_file(&doc).unwrap())
+            .unwrap();
+
+        let loaded = store.load_graph().unwrap();
+        assert_eq!(store.node_count(), before);
+        let texts: Vec<&str> = loaded
+            .get_nodes_by_type(NodeType::Text)
+            .into_iter()
+            .map(|node| &*node.content)
+            .collect();
+        assert_eq!(texts, ["Unchanged.", "New text."]);
+
+        assert!(store.remove_document(&doc.display().to_string()).unwrap());
+        assert_eq!(store.load_graph().unwrap().documents().len(), 1);
+    }
+}
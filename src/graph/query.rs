@@ -0,0 +1,207 @@
+use regex::Regex;
+use std::collections::HashSet;
+use std::ops::RangeBounds;
+use uuid::Uuid;
+
+use crate::graph::{
+    document_graph::DocumentGraph,
+    node::{DocumentNode, NodeType},
+    tagging::tag_matches,
+};
+
+/// A borrowed selection of nodes from a `DocumentGraph`
+///
+/// Created with `DocumentGraph::query()` and narrowed or combined with the
+/// methods below. Nodes keep the order in which they were selected and each
+/// appears at most once.
+#[derive(Clone)]
+pub struct NodeSet<'a> {
+    graph: &'a DocumentGraph,
+    nodes: Vec<&'a DocumentNode>,
+}
+
+impl<'a> NodeSet<'a> {
+    pub(crate) fn new(graph: &'a DocumentGraph, nodes: Vec<&'a DocumentNode>) -> Self {
+        let mut seen = HashSet::new();
+        let nodes = nodes
+            .into_iter()
+            .filter(|node| seen.insert(node.id))
+            .collect();
+        Self { graph, nodes }
+    }
+
+    /// Keep only nodes matching a predicate
+    pub fn filter<F>(self, predicate: F) -> Self
+    where
+        F: Fn(&DocumentNode) -> bool,
+    {
+        let nodes = self
+            .nodes
+            .into_iter()
+            .filter(|node| predicate(node))
+            .collect();
+        Self { nodes, ..self }
+    }
+
+    /// Keep only nodes of a type
+    pub fn of_type(self, node_type: NodeType) -> Self {
+        self.filter(|node| node.node_type == node_type)
+    }
+
+    /// Keep only nodes carrying a tag
+    pub fn with_tag(self, tag: &str) -> Self {
+        self.filter(|node| node.metadata.tags.iter().any(|t| &**t == tag))
+    }
+
+    /// Keep only nodes carrying any of the tags; a keyword or entity may be
+    /// given without its prefix, and case is ignored
+    pub fn tagged<T: AsRef<str>>(self, tags: &[T]) -> Self {
+        self.filter(|node| {
+            node.metadata
+                .tags
+                .iter()
+                .any(|tag| tags.iter().any(|wanted| tag_matches(tag, wanted.as_ref())))
+        })
+    }
+
+    /// Keep only nodes whose content matches a regular expression
+    pub fn matching(self, pattern: &Regex) -> Self {
+        self.filter(|node| pattern.is_match(&node.content))
+    }
+
+    /// Keep only nodes whose level falls within a range
+    pub fn with_level(self, levels: impl RangeBounds<i32>) -> Self {
+        self.filter(|node| node.metadata.level.is_some_and(|l| levels.contains(&l)))
+    }
+
+    /// Expand each node to itself and everything it contains
+    ///
+    /// Turns a selection of sections into the content of those sections.
+    pub fn with_descendants(self) -> Self {
+        let graph = self.graph;
+        let nodes = self
+            .nodes
+            .iter()
+            .flat_map(|node| graph.descendants(&node.id).unwrap_or_default())
+            .collect();
+        Self::new(graph, nodes)
+    }
+
+    /// Nodes in either set
+    pub fn union(self, other: NodeSet<'a>) -> Self {
+        let mut nodes = self.nodes;
+        nodes.extend(other.nodes);
+        Self::new(self.graph, nodes)
+    }
+
+    /// Nodes in both sets
+    pub fn intersect(self, other: &NodeSet<'a>) -> Self {
+        let ids = other.ids();
+        self.filter(|node| ids.contains(&node.id))
+    }
+
+    /// Nodes in this set but not in the other
+    pub fn difference(self, other: &NodeSet<'a>) -> Self {
+        let ids = other.ids();
+        self.filter(|node| !ids.contains(&node.id))
+    }
+
+    /// The selected nodes
+    pub fn nodes(&self) -> &[&'a DocumentNode] {
+        &self.nodes
+    }
+
+    /// UUIDs of the selected nodes
+    pub fn ids(&self) -> HashSet<Uuid> {
+        self.nodes.iter().map(|node| node.id).collect()
+    }
+
+    /// Iterate over the selected nodes
+    pub fn iter(&self) -> impl Iterator<Item = &'a DocumentNode> + '_ {
+        self.nodes.iter().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Copy the selected nodes and the edges between them into a new graph
+    pub fn to_graph(&self) -> DocumentGraph {
+        self.graph.induced_subgraph(&self.ids())
+    }
+
+    /// The graph the selection was made from
+    pub fn graph(&self) -> &'a DocumentGraph {
+        self.graph
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_markdown;
+
+    const MARKDOWN: &str = r#"# Guide
+
+## Install
+
+Run `cargo install`.
+
+## Usage
+
+### Flags
+
+Pass `--verbose` for more output.
+
+## FAQ
+
+Ask on the forum.
+"#;
+
+    #[test]
+    fn test_graph_finders() {
+        let graph = parse_markdown(MARKDOWN).unwrap();
+
+        assert_eq!(graph.find_by_heading("  usage ").len(), 1);
+        assert_eq!(graph.find_by_level(2..=2).len(), 3);
+        assert_eq!(graph.find_by_level(3..).len(), 1);
+        assert_eq!(
+            graph.find_by_content(&Regex::new(r"--\w+").unwrap()).len(),
+            1
+        );
+        assert!(graph.find_by_tag("missing").is_empty());
+    }
+
+    #[test]
+    fn test_node_set_combinators() {
+        let graph = parse_markdown(MARKDOWN).unwrap();
+
+        let usage = graph
+            .query()
+            .of_type(NodeType::Section)
+            .filter(|node| &*node.content == "Usage")
+            .with_descendants();
+        let contents: Vec<&str> = usage.iter().map(|node| &*node.content).collect();
+        assert_eq!(
+            contents,
+            ["Usage", "Flags", "Pass `--verbose` for more output."]
+        );
+
+        let text = graph.query().of_type(NodeType::Text);
+        assert_eq!(text.len(), 3);
+        assert_eq!(text.clone().intersect(&usage).len(), 1);
+        assert_eq!(text.clone().difference(&usage).len(), 2);
+        assert_eq!(usage.clone().union(text).len(), 5);
+
+        let slice = usage.to_graph();
+        assert_eq!(slice.node_count(), 3);
+        assert_eq!(
+            slice.reading_order().next().map(|node| &*node.content),
+            Some("Usage")
+        );
+    }
+}
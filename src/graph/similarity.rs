@@ -0,0 +1,213 @@
+use anyhow::Result;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::external::Embedder;
+use crate::graph::{
+    document_graph::DocumentGraph,
+    edge::{DocumentEdge, RelationType},
+    node::{DocumentNode, NodeType},
+};
+
+/// Settings for linking semantically similar nodes
+#[derive(Debug, Clone)]
+pub struct SimilarityConfig {
+    /// Minimum cosine similarity for two nodes to be linked
+    pub threshold: f32,
+    /// Maximum number of Related edges added from each node
+    pub max_neighbors: usize,
+}
+
+impl Default for SimilarityConfig {
+    fn default() -> Self {
+        Self {
+            threshold: 0.8,
+            max_neighbors: 5,
+        }
+    }
+}
+
+/// Whether a node takes part in similarity linking
+fn is_linkable(node: &DocumentNode) -> bool {
+    matches!(node.node_type, NodeType::Section | NodeType::Text) && !node.content.trim().is_empty()
+}
+
+/// Cosine similarity of two vectors (0.0 if either is zero or they differ in length)
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a * norm_b)
+}
+
+/// Embed every Section and Text node that has no embedding yet
+///
+/// Returns the number of nodes embedded.
+pub async fn embed_nodes(graph: &mut DocumentGraph, engine: &dyn Embedder) -> Result<usize> {
+    let pending: Vec<(Uuid, Arc<str>)> = graph
+        .filter_nodes(|node| is_linkable(node) && node.embedding.is_none())
+        .into_iter()
+        .map(|node| (node.id, node.content.clone()))
+        .collect();
+
+    for (id, content) in &pending {
+        let duplicate = graph
+            .duplicates_of(id)?
+            .into_iter()
+            .find_map(|node| node.embedding().cloned());
+        let embedding = match duplicate {
+            Some(embedding) => embedding,
+            None => engine.embed(content).await?,
+        };
+        graph.set_embedding(id, embedding)?;
+    }
+    Ok(pending.len())
+}
+
+/// Add weighted Related edges between embedded nodes that are similar enough
+///
+/// Each node is linked to at most `max_neighbors` of its nearest neighbours
+/// scoring at least `threshold`. Edges are added in both directions with the
+/// similarity as weight, so `get_related_nodes` works from either end.
+/// Nodes within the same Contains chain (a section and its own text) are
+/// not linked. Returns the number of edges added.
+pub fn link_similar_nodes(graph: &mut DocumentGraph, config: &SimilarityConfig) -> Result<usize> {
+    let embedded: Vec<(Uuid, &[f32])> = graph
+        .filter_nodes(is_linkable)
+        .into_iter()
+        .filter_map(|node| node.embedding().map(|e| (node.id, e.as_slice())))
+        .collect();
+
+    let mut links = Vec::new();
+    for (i, (id, embedding)) in embedded.iter().enumerate() {
+        let mut neighbours: Vec<(Uuid, f32)> = embedded
+            .iter()
+            .enumerate()
+            .filter(|(j, _)| *j != i)
+            .map(|(_, (other, other_embedding))| {
+                (*other, cosine_similarity(embedding, other_embedding))
+            })
+            .filter(|(_, score)| *score >= config.threshold)
+            .collect();
+        neighbours.sort_by(|a, b| b.1.total_cmp(&a.1));
+        neighbours.truncate(config.max_neighbors);
+        links.extend(
+            neighbours
+                .into_iter()
+                .map(|(other, score)| (*id, other, score)),
+        );
+    }
+
+    let mut added = 0;
+    for (from, to, score) in links {
+        if is_contained_in(graph, &from, &to)? || is_contained_in(graph, &to, &from)? {
+            continue;
+        }
+        for (from, to) in [(from, to), (to, from)] {
+            if !graph.has_edge(&from, &to, &RelationType::Related) {
+                graph.add_edge(DocumentEdge::with_weight(
+                    from,
+                    to,
+                    RelationType::Related,
+                    score,
+                ))?;
+                added += 1;
+            }
+        }
+    }
+    Ok(added)
+}
+
+/// Embed content nodes and link similar ones, turning the graph into a semantic graph
+pub async fn add_similarity_edges(
+    graph: &mut DocumentGraph,
+    engine: &dyn Embedder,
+    config: &SimilarityConfig,
+) -> Result<usize> {
+    embed_nodes(graph, engine).await?;
+    link_similar_nodes(graph, config)
+}
+
+/// Whether `ancestor` lies on the Contains path above `id`
+fn is_contained_in(graph: &DocumentGraph, id: &Uuid, ancestor: &Uuid) -> Result<bool> {
+    Ok(graph
+        .get_path_to_root(id)?
+        .iter()
+        .any(|node| node.id == *ancestor))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn embedded_node(node_type: NodeType, content: &str, embedding: Vec<f32>) -> DocumentNode {
+        let mut node = DocumentNode::new(node_type, content.to_string(), None, None, 0, vec![]);
+        node.set_embedding(embedding);
+        node
+    }
+
+    #[test]
+    fn test_cosine_similarity() {
+        assert!((cosine_similarity(&[1.0, 0.0], &[2.0, 0.0]) - 1.0).abs() < 1e-6);
+        assert!(cosine_similarity(&[1.0, 0.0], &[0.0, 1.0]).abs() < 1e-6);
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 0.0]), 0.0);
+        assert_eq!(cosine_similarity(&[1.0], &[1.0, 0.0]), 0.0);
+    }
+
+    #[test]
+    fn test_link_similar_nodes() {
+        let mut graph = DocumentGraph::new();
+        let tls = embedded_node(NodeType::Text, "Configure TLS", vec![1.0, 0.1, 0.0]);
+        let certs = embedded_node(NodeType::Text, "Install certificates", vec![0.9, 0.2, 0.0]);
+        let install = embedded_node(NodeType::Section, "Installing", vec![0.0, 0.0, 1.0]);
+        let code = embedded_node(NodeType::Code, "tls = true", vec![1.0, 0.1, 0.0]);
+        let (tls_id, certs_id, install_id, code_id) = (tls.id, certs.id, install.id, code.id);
+        for node in [tls, certs, install, code] {
+            graph.add_node(node);
+        }
+
+        let added = link_similar_nodes(&mut graph, &SimilarityConfig::default()).unwrap();
+        assert_eq!(added, 2);
+
+        let related = graph.get_related_nodes(&tls_id).unwrap();
+        assert_eq!(related.len(), 1);
+        assert_eq!(related[0].id, certs_id);
+        assert_eq!(graph.get_related_nodes(&certs_id).unwrap()[0].id, tls_id);
+        assert!(graph.get_related_nodes(&install_id).unwrap().is_empty());
+        assert!(graph.get_related_nodes(&code_id).unwrap().is_empty());
+
+        // Running again does not duplicate edges
+        assert_eq!(
+            link_similar_nodes(&mut graph, &SimilarityConfig::default()).unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_link_skips_own_section() {
+        let mut graph = DocumentGraph::new();
+        let section = embedded_node(NodeType::Section, "TLS", vec![1.0, 0.0]);
+        let text = embedded_node(NodeType::Text, "TLS setup", vec![1.0, 0.0]);
+        let (section_id, text_id) = (section.id, text.id);
+        graph.add_node(section);
+        graph.add_node(text);
+        graph
+            .add_edge(DocumentEdge::new(
+                section_id,
+                text_id,
+                RelationType::Contains,
+            ))
+            .unwrap();
+
+        assert_eq!(
+            link_similar_nodes(&mut graph, &SimilarityConfig::default()).unwrap(),
+            0
+        );
+    }
+}
@@ -1,9 +1,14 @@
 #[cfg(not(test))]
 use crate::external::vectordb::VectorDB;
+use crate::external::vectordb::{PointRecord, ScrollPage, SearchFilter, SearchHit, StoredPoint};
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
 use uuid::Uuid;
 
 #[cfg(test)]
@@ -15,13 +20,67 @@ pub trait VectorDBTrait {
     async fn init_collection(&self) -> Result<()>;
     async fn insert_vectors(
         &self,
+        ids: Vec<Uuid>,
         vectors: Vec<Vec<f32>>,
-        metadata: Vec<HashMap<String, String>>,
+        metadata: Vec<HashMap<String, Value>>,
     ) -> Result<Vec<String>>;
-    async fn search_vectors(&self, vector: Vec<f32>, limit: u64) -> Result<Vec<(String, f32)>>;
+    async fn search_vectors(
+        &self,
+        vector: Vec<f32>,
+        limit: u64,
+        filter: Option<SearchFilter>,
+    ) -> Result<Vec<SearchHit>>;
+    async fn scroll(
+        &self,
+        filter: Option<SearchFilter>,
+        offset: Option<String>,
+        limit: u32,
+    ) -> Result<ScrollPage>;
+    async fn export_points(
+        &self,
+        offset: Option<String>,
+        limit: u32,
+    ) -> Result<ScrollPage<PointRecord>>;
+    async fn import_points(&self, records: Vec<PointRecord>) -> Result<usize>;
     async fn delete_vectors(&self, ids: Vec<String>) -> Result<()>;
 }
 
+/// Points fetched per scroll request by `VectorStore::list_points` and
+/// `VectorStore::snapshot`, and points per import batch in `restore`
+const SCROLL_PAGE_SIZE: u32 = 256;
+
+/// Schema name written in the first line of a snapshot file
+pub const SNAPSHOT_SCHEMA: &str = "llm_dataset_builder.vector_snapshot";
+
+/// First line of a snapshot file; every further line is a `PointRecord`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotHeader {
+    pub schema: String,
+    /// Dimension of the stored vectors, 0 for an empty snapshot
+    pub vector_size: usize,
+}
+
+impl SnapshotHeader {
+    /// Read and check the header of a snapshot file
+    pub fn read(path: &Path) -> Result<Self> {
+        let mut line = String::new();
+        BufReader::new(File::open(path)?).read_line(&mut line)?;
+        Self::parse(&line)
+    }
+
+    fn parse(line: &str) -> Result<Self> {
+        let header: SnapshotHeader = serde_json::from_str(line)
+            .map_err(|e| anyhow!("Not a vector snapshot (bad header: {})", e))?;
+        if header.schema != SNAPSHOT_SCHEMA {
+            return Err(anyhow!(
+                "Not a vector snapshot (schema '{}')",
+                header.schema
+            ));
+        }
+        Ok(header)
+    }
+}
+
 #[cfg(not(test))]
 #[async_trait]
 impl VectorDBTrait for VectorDB {
@@ -31,14 +90,41 @@ impl VectorDBTrait for VectorDB {
 
     async fn insert_vectors(
         &self,
+        ids: Vec<Uuid>,
         vectors: Vec<Vec<f32>>,
-        metadata: Vec<HashMap<String, String>>,
+        metadata: Vec<HashMap<String, Value>>,
     ) -> Result<Vec<String>> {
-        self.insert_vectors(vectors, metadata).await
+        self.insert_vectors(ids, vectors, metadata).await
     }
 
-    async fn search_vectors(&self, vector: Vec<f32>, limit: u64) -> Result<Vec<(String, f32)>> {
-        self.search_vectors(vector, limit).await
+    async fn search_vectors(
+        &self,
+        vector: Vec<f32>,
+        limit: u64,
+        filter: Option<SearchFilter>,
+    ) -> Result<Vec<SearchHit>> {
+        self.search_vectors(vector, limit, filter).await
+    }
+
+    async fn scroll(
+        &self,
+        filter: Option<SearchFilter>,
+        offset: Option<String>,
+        limit: u32,
+    ) -> Result<ScrollPage> {
+        self.scroll(filter, offset, limit).await
+    }
+
+    async fn export_points(
+        &self,
+        offset: Option<String>,
+        limit: u32,
+    ) -> Result<ScrollPage<PointRecord>> {
+        self.export_points(offset, limit).await
+    }
+
+    async fn import_points(&self, records: Vec<PointRecord>) -> Result<usize> {
+        self.import_points(records).await
     }
 
     async fn delete_vectors(&self, ids: Vec<String>) -> Result<()> {
@@ -79,26 +165,146 @@ impl VectorStore {
         store
     }
 
+    /// Store an embedding under the node's UUID, replacing any earlier
+    /// vector for the same node. Returns the point ID.
     pub async fn add_embedding(
         &self,
-        _id: &Uuid,
+        id: &Uuid,
         embedding: Vec<f32>,
         metadata: Value,
-    ) -> Result<()> {
-        let metadata_map: HashMap<String, String> = serde_json::from_value(metadata)
+    ) -> Result<String> {
+        let metadata_map: HashMap<String, Value> = serde_json::from_value(metadata)
             .map_err(|e| anyhow!("Failed to parse metadata: {}", e))?;
 
         let ids = self
             .db
-            .insert_vectors(vec![embedding], vec![metadata_map])
+            .insert_vectors(vec![*id], vec![embedding], vec![metadata_map])
             .await
             .map_err(|e| anyhow!("Failed to insert embedding: {}", e))?;
 
-        if ids.is_empty() {
-            anyhow::bail!("No IDs returned from vector insertion");
+        ids.into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("No IDs returned from vector insertion"))
+    }
+
+    /// Store many embeddings at once, each under its node's UUID
+    ///
+    /// The vector database splits large batches into chunked upserts.
+    /// Returns the point IDs in input order.
+    pub async fn add_embeddings(
+        &self,
+        entries: Vec<(Uuid, Vec<f32>, Value)>,
+    ) -> Result<Vec<String>> {
+        if entries.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut ids = Vec::with_capacity(entries.len());
+        let mut vectors = Vec::with_capacity(entries.len());
+        let mut metadata = Vec::with_capacity(entries.len());
+        for (id, embedding, payload) in entries {
+            let payload: HashMap<String, Value> = serde_json::from_value(payload)
+                .map_err(|e| anyhow!("Failed to parse metadata: {}", e))?;
+            ids.push(id);
+            vectors.push(embedding);
+            metadata.push(payload);
+        }
+
+        self.db
+            .insert_vectors(ids, vectors, metadata)
+            .await
+            .map_err(|e| anyhow!("Failed to insert embeddings: {}", e))
+    }
+
+    /// Enumerate every stored point matching `filter`, following scroll pages
+    pub async fn list_points(&self, filter: &SearchFilter) -> Result<Vec<StoredPoint>> {
+        let filter = (!filter.is_empty()).then(|| filter.clone());
+        let mut points = Vec::new();
+        let mut offset = None;
+        loop {
+            let page = self
+                .db
+                .scroll(filter.clone(), offset, SCROLL_PAGE_SIZE)
+                .await?;
+            points.extend(page.points);
+            match page.next_offset {
+                Some(next) => offset = Some(next),
+                None => return Ok(points),
+            }
         }
+    }
+
+    /// Dump every point (ID, vector and payload) to a JSONL snapshot file
+    ///
+    /// The file can be restored into any Qdrant instance with `restore`,
+    /// so an indexed corpus moves between machines without re-embedding.
+    /// Returns the number of points written.
+    pub async fn snapshot(&self, path: &Path) -> Result<usize> {
+        let mut page = self.db.export_points(None, SCROLL_PAGE_SIZE).await?;
+        let header = SnapshotHeader {
+            schema: SNAPSHOT_SCHEMA.to_string(),
+            vector_size: page.points.first().map_or(0, |p| p.vector.len()),
+        };
 
-        Ok(())
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(&mut writer, &header)?;
+        writeln!(writer)?;
+
+        let mut written = 0;
+        loop {
+            for point in &page.points {
+                serde_json::to_writer(&mut writer, point)?;
+                writeln!(writer)?;
+            }
+            written += page.points.len();
+            match page.next_offset {
+                Some(offset) => {
+                    page = self
+                        .db
+                        .export_points(Some(offset), SCROLL_PAGE_SIZE)
+                        .await?
+                }
+                None => break,
+            }
+        }
+        writer.flush()?;
+        Ok(written)
+    }
+
+    /// Load a snapshot written by `snapshot`, keeping point IDs
+    ///
+    /// Points already in the collection with the same IDs are overwritten.
+    /// Returns the number of points restored.
+    pub async fn restore(&self, path: &Path) -> Result<usize> {
+        let mut lines = BufReader::new(File::open(path)?).lines();
+        let header = SnapshotHeader::parse(&lines.next().transpose()?.unwrap_or_default())?;
+
+        let mut restored = 0;
+        let mut batch = Vec::with_capacity(SCROLL_PAGE_SIZE as usize);
+        for (number, line) in lines.enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record: PointRecord = serde_json::from_str(&line)
+                .map_err(|e| anyhow!("Invalid snapshot record on line {}: {}", number + 2, e))?;
+            if record.vector.len() != header.vector_size {
+                return Err(anyhow!(
+                    "Snapshot record {} has {} dimensions, expected {}",
+                    record.id,
+                    record.vector.len(),
+                    header.vector_size
+                ));
+            }
+            batch.push(record);
+            if batch.len() == SCROLL_PAGE_SIZE as usize {
+                restored += self.db.import_points(std::mem::take(&mut batch)).await?;
+            }
+        }
+        if !batch.is_empty() {
+            restored += self.db.import_points(batch).await?;
+        }
+        Ok(restored)
     }
 
     pub async fn search_similar(
@@ -106,7 +312,25 @@ impl VectorStore {
         embedding: &[f32],
         limit: u64,
     ) -> Result<Vec<(String, f32)>> {
-        self.db.search_vectors(embedding.to_vec(), limit).await
+        let hits = self
+            .db
+            .search_vectors(embedding.to_vec(), limit, None)
+            .await?;
+        Ok(hits.into_iter().map(|hit| (hit.id, hit.score)).collect())
+    }
+
+    /// Search for similar embeddings whose payload matches `filter`,
+    /// including the payload stored with each
+    pub async fn search_hits(
+        &self,
+        embedding: &[f32],
+        limit: u64,
+        filter: &SearchFilter,
+    ) -> Result<Vec<SearchHit>> {
+        let filter = (!filter.is_empty()).then(|| filter.clone());
+        self.db
+            .search_vectors(embedding.to_vec(), limit, filter)
+            .await
     }
 
     pub async fn delete_embedding(&self, id: &Uuid) -> Result<()> {
@@ -131,44 +355,222 @@ mod tests {
     #[tokio::test]
     async fn test_embedding_operations() {
         let mut mock = MockVectorDBTrait::new();
+        let id = Uuid::new_v4();
 
         // Setup expectations
         mock.expect_init_collection().times(1).returning(|| Ok(()));
         mock.expect_insert_vectors()
-            .with(predicate::always(), predicate::always())
+            .with(
+                predicate::eq(vec![id]),
+                predicate::always(),
+                predicate::always(),
+            )
             .times(1)
-            .returning(|vectors, _| {
-                let result = vectors
-                    .iter()
-                    .enumerate()
-                    .map(|(i, _)| i.to_string())
-                    .collect();
-                Ok(result)
-            });
+            .returning(|ids, _, _| Ok(ids.iter().map(Uuid::to_string).collect()));
+        mock.expect_delete_vectors()
+            .with(predicate::eq(vec![id.to_string()]))
+            .times(1)
+            .returning(|_| Ok(()));
 
         mock.expect_search_vectors()
-            .with(predicate::always(), predicate::eq(2u64))
+            .with(
+                predicate::always(),
+                predicate::eq(2u64),
+                predicate::eq(None),
+            )
             .times(1)
-            .returning(|_, _| Ok(vec![("0".to_string(), 0.9), ("1".to_string(), 0.8)]));
+            .returning(|_, _, _| {
+                Ok([("0", 0.9), ("1", 0.8)]
+                    .into_iter()
+                    .map(|(id, score)| SearchHit {
+                        id: id.to_string(),
+                        score,
+                        payload: HashMap::new(),
+                    })
+                    .collect())
+            });
 
         let store = VectorStore::new_with_mock(mock, "test_collection").await;
 
         // Test storing embeddings
-        let id = Uuid::new_v4();
         let embedding = vec![1.0, 0.0];
         let metadata = serde_json::json!({
             "key": "value1"
         });
 
-        assert!(store
+        let vector_id = store
             .add_embedding(&id, embedding.clone(), metadata)
             .await
-            .is_ok());
+            .unwrap();
+        assert_eq!(vector_id, id.to_string());
 
         // Test querying similar embeddings
         let results = store.search_similar(&embedding, 2).await.unwrap();
         assert_eq!(results.len(), 2);
         assert_eq!(results[0].0, "0");
         assert_eq!(results[1].0, "1");
+
+        // The node's UUID addresses its point
+        store.delete_embedding(&id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_add_embeddings_in_one_insert() {
+        let mut mock = MockVectorDBTrait::new();
+        mock.expect_init_collection().times(1).returning(|| Ok(()));
+        mock.expect_insert_vectors()
+            .times(1)
+            .returning(|ids, vectors, metadata| {
+                assert_eq!(vectors.len(), 3);
+                assert_eq!(metadata[2]["key"], "c");
+                Ok(ids.iter().map(Uuid::to_string).collect())
+            });
+
+        let store = VectorStore::new_with_mock(mock, "test_collection").await;
+        let entries: Vec<_> = ["a", "b", "c"]
+            .into_iter()
+            .map(|key| (Uuid::new_v4(), vec![1.0], serde_json::json!({ "key": key })))
+            .collect();
+        let expected: Vec<String> = entries.iter().map(|(id, _, _)| id.to_string()).collect();
+
+        assert_eq!(store.add_embeddings(entries).await.unwrap(), expected);
+        assert!(store.add_embeddings(Vec::new()).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_list_points_follows_pages() {
+        let mut mock = MockVectorDBTrait::new();
+        mock.expect_init_collection().times(1).returning(|| Ok(()));
+        mock.expect_scroll()
+            .with(
+                predicate::eq(None),
+                predicate::eq(None),
+                predicate::always(),
+            )
+            .times(1)
+            .returning(|_, _, _| {
+                Ok(ScrollPage {
+                    points: vec![StoredPoint {
+                        id: "a".to_string(),
+                        payload: HashMap::new(),
+                    }],
+                    next_offset: Some("b".to_string()),
+                })
+            });
+        mock.expect_scroll()
+            .with(
+                predicate::eq(None),
+                predicate::eq(Some("b".to_string())),
+                predicate::always(),
+            )
+            .times(1)
+            .returning(|_, _, _| {
+                Ok(ScrollPage {
+                    points: vec![StoredPoint {
+                        id: "b".to_string(),
+                        payload: HashMap::new(),
+                    }],
+                    next_offset: None,
+                })
+            });
+
+        let store = VectorStore::new_with_mock(mock, "test_collection").await;
+        let points = store.list_points(&SearchFilter::default()).await.unwrap();
+        let ids: Vec<&str> = points.iter().map(|p| p.id.as_str()).collect();
+        assert_eq!(ids, vec!["a", "b"]);
+    }
+
+    fn record(id: &str) -> PointRecord {
+        PointRecord {
+            id: id.to_string(),
+            vector: vec![1.0, 0.0],
+            payload: serde_json::json!({ "tags": ["rust"] })
+                .as_object()
+                .unwrap()
+                .clone(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("corpus.snapshot.jsonl");
+
+        let mut source = MockVectorDBTrait::new();
+        source.expect_init_collection().returning(|| Ok(()));
+        source
+            .expect_export_points()
+            .with(predicate::eq(None), predicate::always())
+            .returning(|_, _| {
+                Ok(ScrollPage {
+                    points: vec![record("a")],
+                    next_offset: Some("b".to_string()),
+                })
+            });
+        source
+            .expect_export_points()
+            .with(predicate::eq(Some("b".to_string())), predicate::always())
+            .returning(|_, _| {
+                Ok(ScrollPage {
+                    points: vec![record("b")],
+                    next_offset: None,
+                })
+            });
+        let store = VectorStore::new_with_mock(source, "source").await;
+        assert_eq!(store.snapshot(&path).await.unwrap(), 2);
+
+        let header = SnapshotHeader::read(&path).unwrap();
+        assert_eq!(header.vector_size, 2);
+
+        let mut target = MockVectorDBTrait::new();
+        target.expect_init_collection().returning(|| Ok(()));
+        target.expect_import_points().times(1).returning(|records| {
+            assert_eq!(records, vec![record("a"), record("b")]);
+            Ok(records.len())
+        });
+        let store = VectorStore::new_with_mock(target, "target").await;
+        assert_eq!(store.restore(&path).await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_restore_rejects_other_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("not_a_snapshot.jsonl");
+        std::fs::write(&path, "{\"question\":\"q\",\"answer\":\"a\"}\n").unwrap();
+
+        let mut mock = MockVectorDBTrait::new();
+        mock.expect_init_collection().returning(|| Ok(()));
+        let store = VectorStore::new_with_mock(mock, "target").await;
+        assert!(store.restore(&path).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_search_hits() {
+        let mut mock = MockVectorDBTrait::new();
+        mock.expect_init_collection().times(1).returning(|| Ok(()));
+        let filter = SearchFilter {
+            tag: Some("security".to_string()),
+            ..Default::default()
+        };
+        mock.expect_search_vectors()
+            .with(
+                predicate::always(),
+                predicate::eq(1u64),
+                predicate::eq(Some(filter.clone())),
+            )
+            .times(1)
+            .returning(|_, _, _| {
+                Ok(vec![SearchHit {
+                    id: "0".to_string(),
+                    score: 0.9,
+                    payload: [("heading".to_string(), "TLS".to_string())]
+                        .into_iter()
+                        .collect(),
+                }])
+            });
+
+        let store = VectorStore::new_with_mock(mock, "test_collection").await;
+        let hits = store.search_hits(&[1.0, 0.0], 1, &filter).await.unwrap();
+        assert_eq!(hits[0].payload["heading"], "TLS");
     }
 }
@@ -0,0 +1,355 @@
+//! Keyword and entity tags for the nodes of a graph.
+//!
+//! Keywords are found with RAKE (Rapid Automatic Keyword Extraction):
+//! text is cut into candidate phrases at stopwords and punctuation, each
+//! word scores its degree (the length of the phrases it appears in) over
+//! its frequency across the graph, and a phrase scores the sum of its
+//! words. Entities are names that stand out by their spelling: acronyms,
+//! CamelCase identifiers and runs of capitalized words.
+//!
+//! Tags are stored in `NodeMetadata.tags` as `keyword:<phrase>` and
+//! `entity:<name>`, next to the tags the parser adds, such as `language:rust`.
+
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
+use uuid::Uuid;
+
+use crate::graph::{
+    document_graph::DocumentGraph,
+    node::{DocumentNode, NodeType},
+};
+
+/// Prefix of keyword tags
+pub const KEYWORD_PREFIX: &str = "keyword:";
+
+/// Prefix of entity tags
+pub const ENTITY_PREFIX: &str = "entity:";
+
+/// Settings for tagging the nodes of a graph
+#[derive(Debug, Clone)]
+pub struct TaggingConfig {
+    /// Keyword tags added to a node at most
+    pub max_keywords: usize,
+    /// Entity tags added to a node at most
+    pub max_entities: usize,
+    /// Words a keyword phrase has at most
+    pub max_phrase_words: usize,
+}
+
+impl Default for TaggingConfig {
+    fn default() -> Self {
+        Self {
+            max_keywords: 5,
+            max_entities: 5,
+            max_phrase_words: 3,
+        }
+    }
+}
+
+/// Words that end a candidate phrase
+const STOPWORDS: &[&str] = &[
+    "a", "about", "above", "after", "again", "all", "also", "am", "an", "and", "any", "are", "as",
+    "at", "be", "because", "been", "before", "being", "below", "between", "both", "but", "by",
+    "can", "could", "did", "do", "does", "doing", "done", "down", "during", "each", "either",
+    "else", "etc", "even", "every", "few", "for", "from", "further", "get", "gets", "given", "had",
+    "has", "have", "having", "he", "her", "here", "hers", "him", "his", "how", "however", "i",
+    "if", "in", "into", "is", "it", "its", "itself", "just", "let", "like", "may", "me", "might",
+    "more", "most", "much", "must", "my", "need", "needs", "no", "nor", "not", "now", "of", "off",
+    "often", "on", "once", "one", "only", "or", "other", "our", "out", "over", "own", "per",
+    "same", "see", "set", "she", "should", "so", "some", "such", "than", "that", "the", "their",
+    "them", "then", "there", "these", "they", "this", "those", "through", "thus", "to", "too",
+    "two", "under", "until", "up", "us", "use", "used", "uses", "using", "very", "via", "was",
+    "we", "well", "were", "what", "when", "where", "whether", "which", "while", "who", "whom",
+    "why", "will", "with", "within", "without", "would", "yet", "you", "your",
+];
+
+pub(crate) fn is_stopword(word: &str) -> bool {
+    STOPWORDS.binary_search(&word).is_ok()
+}
+
+/// Whether a node's own text is tagged; code is left out
+fn is_taggable(node: &DocumentNode) -> bool {
+    node.node_type.is_content()
+        && !matches!(node.node_type, NodeType::Code | NodeType::CodeBlock)
+        && !node.content.trim().is_empty()
+}
+
+/// Whether a tag matches what was asked for: the same tag, or the same
+/// keyword or entity without its prefix, ignoring case
+pub fn tag_matches(tag: &str, wanted: &str) -> bool {
+    let wanted = wanted.trim();
+    tag.eq_ignore_ascii_case(wanted)
+        || [KEYWORD_PREFIX, ENTITY_PREFIX]
+            .iter()
+            .filter_map(|prefix| tag.strip_prefix(prefix))
+            .any(|value| value.eq_ignore_ascii_case(wanted))
+}
+
+/// Candidate keyword phrases of a text, lowercased, in order
+fn candidate_phrases(text: &str, max_words: usize) -> Vec<Vec<String>> {
+    static SPLIT: OnceLock<Regex> = OnceLock::new();
+    // Phrases end at punctuation, inline code and line breaks
+    let split = SPLIT.get_or_init(|| Regex::new(r#"[.,;:!?()\[\]{}"`|<>*=/\n]+"#).unwrap());
+
+    let mut phrases = Vec::new();
+    for fragment in split.split(text) {
+        let mut phrase: Vec<String> = Vec::new();
+        for word in fragment.split_whitespace() {
+            let word = word
+                .trim_matches(|c: char| !c.is_alphanumeric())
+                .to_lowercase();
+            let is_word = word.chars().count() > 1
+                && word.chars().any(char::is_alphabetic)
+                && !is_stopword(&word);
+            if is_word {
+                phrase.push(word);
+            } else if !phrase.is_empty() {
+                phrases.push(std::mem::take(&mut phrase));
+            }
+        }
+        if !phrase.is_empty() {
+            phrases.push(phrase);
+        }
+    }
+    // Overlong runs are seldom real phrases
+    phrases.retain(|phrase| phrase.len() <= max_words);
+    phrases
+}
+
+/// Entities named in a text, in order of first mention
+pub fn entities(text: &str) -> Vec<String> {
+    static ENTITY: OnceLock<Regex> = OnceLock::new();
+    let entity = ENTITY.get_or_init(|| {
+        Regex::new(concat!(
+            // Acronyms such as TLS or HTTP2
+            r"\b[A-Z][A-Z0-9]+s?\b",
+            // CamelCase identifiers such as DocumentGraph or iOS
+            r"|\b[A-Za-z][a-z0-9]+[A-Z][A-Za-z0-9]*\b",
+            // Runs of capitalized words such as Label Studio
+            r"|\b[A-Z][a-z0-9]+(?:[ \-][A-Z][a-z0-9]+)*\b",
+        ))
+        .unwrap()
+    });
+
+    let mut seen = HashSet::new();
+    let mut found = Vec::new();
+    for capture in entity.find_iter(text) {
+        let name = capture.as_str();
+        let words = name.split([' ', '-']).count();
+        let single_capitalized = words == 1
+            && name
+                .chars()
+                .skip(1)
+                .all(|c| c.is_lowercase() || c.is_numeric());
+        if single_capitalized && starts_sentence(text, capture.start()) {
+            continue;
+        }
+        if is_stopword(&name.to_lowercase()) || name.len() < 2 {
+            continue;
+        }
+        if seen.insert(name.to_string()) {
+            found.push(name.to_string());
+        }
+    }
+    found
+}
+
+/// Whether the word at `at` opens a sentence or line, where any word is
+/// capitalized
+fn starts_sentence(text: &str, at: usize) -> bool {
+    let before = text[..at].trim_end_matches([' ', '\t', '#', '-', '*', '>', '_']);
+    before.is_empty()
+        || before.ends_with(['.', '!', '?', ':', '\n'])
+        || before.ends_with(|c: char| c.is_numeric())
+}
+
+/// Whether a node opens a section of its own
+fn is_section(node: &DocumentNode) -> bool {
+    matches!(node.node_type, NodeType::Section | NodeType::Subsection)
+}
+
+/// Text a node is tagged from: its own content, or for a section its
+/// heading and its own text, without the sections nested in it
+///
+/// A section's span covers only its heading, so a tag taken from a nested
+/// section would target the chunk of the heading rather than its own.
+fn node_text(graph: &DocumentGraph, node: &DocumentNode) -> String {
+    if !is_section(node) {
+        return node.content.to_string();
+    }
+    let mut parts = Vec::new();
+    let mut stack = vec![node];
+    while let Some(current) = stack.pop() {
+        if is_taggable(current) {
+            parts.push(&*current.content);
+        }
+        let children = graph.get_children(&current.id).unwrap_or_default();
+        stack.extend(
+            children
+                .into_iter()
+                .rev()
+                .filter(|child| !is_section(child)),
+        );
+    }
+    parts.join("\n")
+}
+
+/// Add keyword and entity tags to every content node of the graph
+///
+/// Word scores are computed over the whole graph, so a phrase that is
+/// specific to the corpus outranks one made of common words. Tags a node
+/// already carries are not added again. Returns the number of tags added.
+pub fn tag_nodes(graph: &mut DocumentGraph, config: &TaggingConfig) -> usize {
+    let texts: Vec<(Uuid, String)> = graph
+        .filter_nodes(is_taggable)
+        .into_iter()
+        .map(|node| (node.id, node_text(graph, node)))
+        .collect();
+
+    // Word scores of RAKE, from the nodes' own text only so sections don't
+    // count their content twice
+    let mut frequency: HashMap<String, f64> = HashMap::new();
+    let mut degree: HashMap<String, f64> = HashMap::new();
+    for node in graph.filter_nodes(is_taggable) {
+        if is_section(node) {
+            continue;
+        }
+        for phrase in candidate_phrases(&node.content, config.max_phrase_words) {
+            for word in &phrase {
+                *frequency.entry(word.clone()).or_default() += 1.0;
+                *degree.entry(word.clone()).or_default() += phrase.len() as f64;
+            }
+        }
+    }
+    let score = |phrase: &[String]| -> f64 {
+        phrase
+            .iter()
+            .map(|word| match frequency.get(word) {
+                Some(frequency) => degree[word] / frequency,
+                None => phrase.len() as f64,
+            })
+            .sum()
+    };
+
+    let mut added = 0;
+    for (id, text) in texts {
+        let mut ranked: Vec<(f64, String)> = Vec::new();
+        for phrase in candidate_phrases(&text, config.max_phrase_words) {
+            let joined = phrase.join(" ");
+            if !ranked.iter().any(|(_, seen)| *seen == joined) {
+                ranked.push((score(&phrase), joined));
+            }
+        }
+        // RAKE keeps the best third of the candidates; ties keep text order
+        let keep = ranked.len().div_ceil(3).min(config.max_keywords);
+        ranked.sort_by(|a, b| b.0.total_cmp(&a.0));
+        let keywords = ranked
+            .into_iter()
+            .take(keep)
+            .map(|(_, phrase)| format!("{}{}", KEYWORD_PREFIX, phrase));
+        let names = entities(&text)
+            .into_iter()
+            .take(config.max_entities)
+            .map(|name| format!("{}{}", ENTITY_PREFIX, name));
+
+        let existing: HashSet<String> = graph
+            .get_node(&id)
+            .map(|node| node.metadata.tags.iter().map(|t| t.to_string()).collect())
+            .unwrap_or_default();
+        for tag in keywords.chain(names) {
+            if !existing.contains(&tag) && graph.add_tag(&id, &tag).is_ok() {
+                added += 1;
+            }
+        }
+    }
+    added
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_markdown;
+
+    #[test]
+    fn test_stopwords_are_sorted() {
+        assert!(STOPWORDS.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn test_candidate_phrases_split_at_stopwords_and_punctuation() {
+        let phrases = candidate_phrases(
+            "The vector store is built from embeddings of every node, using `cosine` \
+             distance and approximate nearest neighbour search indexes.",
+            3,
+        );
+        let phrases: Vec<String> = phrases.iter().map(|p| p.join(" ")).collect();
+        // The five-word run is left out
+        assert_eq!(
+            phrases,
+            [
+                "vector store",
+                "built",
+                "embeddings",
+                "node",
+                "cosine",
+                "distance"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_entities() {
+        let text = "Records can be sent to Label Studio or Argilla over HTTPS. \
+                    The DocumentGraph keeps them.\nThese are kept.";
+        // Capitalized words opening a sentence or line are not names
+        assert_eq!(
+            entities(text),
+            ["Label Studio", "Argilla", "HTTPS", "DocumentGraph"]
+        );
+    }
+
+    #[test]
+    fn test_tag_nodes_and_match_tags() {
+        let mut graph = parse_markdown(
+            "# Search\n\nThe vector store indexes embeddings with Qdrant.\n\n\
+             ## Ranking\n\nVector store results are ranked by cosine similarity.\n\n\
+             ```rust\nlet store = VectorStore::new();\n```\n",
+        )
+        .unwrap();
+        assert!(tag_nodes(&mut graph, &TaggingConfig::default()) > 0);
+
+        let ranking = &graph.find_by_heading("Ranking")[0];
+        let tags: Vec<&str> = ranking.metadata.tags.iter().map(|t| &**t).collect();
+        assert!(tags.contains(&"keyword:vector store results"), "{:?}", tags);
+        assert!(!tags.iter().any(|tag| tag.contains("VectorStore")));
+
+        let search = graph.query().tagged(&["qdrant"]);
+        assert!(search
+            .iter()
+            .any(|node| node.node_type == NodeType::Section && &*node.content == "Search"));
+        assert!(tag_matches("language:rust", "LANGUAGE:rust"));
+        assert!(!tag_matches("keyword:vector store", "vector"));
+
+        // Tagging again adds nothing new
+        assert_eq!(tag_nodes(&mut graph, &TaggingConfig::default()), 0);
+    }
+
+    #[test]
+    fn test_sections_are_not_tagged_from_nested_sections() {
+        let mut graph = parse_markdown(
+            "# Client\n\nThe client sends requests.\n\n\
+             ### Advanced\n\nSwitching endpoints needs a restart.\n",
+        )
+        .unwrap();
+        tag_nodes(&mut graph, &TaggingConfig::default());
+
+        let tagged = graph.query().tagged(&["switching endpoints"]);
+        let headings: Vec<&str> = tagged
+            .iter()
+            .filter(|node| is_section(node))
+            .map(|node| &*node.content)
+            .collect();
+        assert_eq!(headings, ["Advanced"]);
+    }
+}
@@ -0,0 +1,47 @@
+//! Content hashes, shared by the parser, records, the run manifest and the
+//! stable choices of sampling and splitting.
+
+use sha2::{Digest, Sha256};
+
+/// Hex-encoded SHA-256 of a piece of text
+pub fn hash_text(text: &str) -> String {
+    format!("{:x}", Sha256::digest(text.as_bytes()))
+}
+
+/// Stable 64-bit hash of `text` in `domain`, for choices that look random
+/// across inputs but are the same on every run
+pub fn stable_u64(domain: &[u8], text: &str) -> u64 {
+    let mut hasher = Sha256::new();
+    hasher.update(domain);
+    hasher.update(text.as_bytes());
+    let digest = hasher.finalize();
+    u64::from_le_bytes(digest[..8].try_into().expect("SHA-256 digest is 32 bytes"))
+}
+
+/// [`stable_u64`] scaled to a fraction between 0 and 1
+pub fn stable_fraction(domain: &[u8], text: &str) -> f64 {
+    stable_u64(domain, text) as f64 / u64::MAX as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_text_is_stable() {
+        assert_eq!(hash_text("prompt"), hash_text("prompt"));
+        assert_ne!(hash_text("prompt"), hash_text("prompt "));
+        assert_eq!(hash_text("").len(), 64);
+    }
+
+    #[test]
+    fn test_stable_hashes_depend_on_domain() {
+        assert_eq!(stable_u64(b"eval\n", "text"), stable_u64(b"eval\n", "text"));
+        assert_ne!(
+            stable_u64(b"eval\n", "text"),
+            stable_u64(b"split\n", "text")
+        );
+        let fraction = stable_fraction(b"eval\n", "text");
+        assert!((0.0..=1.0).contains(&fraction));
+    }
+}
@@ -0,0 +1,399 @@
+//! Conversion of external QA datasets into records, the reverse of
+//! `export`: SQuAD, Alpaca and ShareGPT files become a dataset that can be
+//! merged, deduplicated, filtered and exported with the generated ones.
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::hash::hash_text;
+use crate::processor::ProcessedItem;
+use crate::schema;
+
+/// Format of a dataset read by the `import` command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    /// SQuAD JSON: `data` of titled articles, whose `paragraphs` hold a
+    /// `context` and the `qas` about it
+    Squad,
+    /// `instruction`/`input`/`output` objects, as a JSON array or JSONL
+    Alpaca,
+    /// `conversations` of `human`/`gpt` turns, or OpenAI chat `messages`,
+    /// as a JSON array or JSONL
+    ShareGpt,
+}
+
+impl ImportFormat {
+    pub const ALL: [ImportFormat; 3] = [
+        ImportFormat::Squad,
+        ImportFormat::Alpaca,
+        ImportFormat::ShareGpt,
+    ];
+
+    /// Name used on the command line
+    pub fn name(&self) -> &'static str {
+        match self {
+            ImportFormat::Squad => "squad",
+            ImportFormat::Alpaca => "alpaca",
+            ImportFormat::ShareGpt => "sharegpt",
+        }
+    }
+
+    /// The format of a dataset whose first record looks like `record`
+    fn of_record(record: &Value) -> Option<Self> {
+        if record.get("data").is_some_and(Value::is_array) {
+            Some(ImportFormat::Squad)
+        } else if record.get("instruction").is_some() && record.get("output").is_some() {
+            Some(ImportFormat::Alpaca)
+        } else if record.get("conversations").is_some() || record.get("messages").is_some() {
+            Some(ImportFormat::ShareGpt)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for ImportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for ImportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "squad" => Ok(ImportFormat::Squad),
+            "alpaca" => Ok(ImportFormat::Alpaca),
+            "sharegpt" => Ok(ImportFormat::ShareGpt),
+            other => Err(anyhow!(
+                "Unknown import format '{}' (expected squad, alpaca or sharegpt)",
+                other
+            )),
+        }
+    }
+}
+
+/// What an import kept and what it left out
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    pub imported: usize,
+    /// SQuAD questions marked impossible, or without an answer
+    pub unanswerable: usize,
+    /// Records missing a question or answer, or breaking the schema
+    pub invalid: usize,
+    /// Questions already imported, compared like `validate` does
+    pub duplicates: usize,
+}
+
+impl fmt::Display for ImportReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} records imported", self.imported)?;
+        let left_out = [
+            (self.duplicates, "duplicate"),
+            (self.unanswerable, "unanswerable"),
+            (self.invalid, "invalid"),
+        ];
+        for (count, kind) in left_out.into_iter().filter(|(count, _)| *count > 0) {
+            write!(f, ", {} {} left out", count, kind)?;
+        }
+        Ok(())
+    }
+}
+
+/// Records read from external datasets
+#[derive(Debug, Default)]
+pub struct Imported {
+    pub items: Vec<ProcessedItem>,
+    pub report: ImportReport,
+}
+
+impl Imported {
+    /// Keep a record unless it breaks the schema or repeats a question
+    fn push(&mut self, item: ProcessedItem, questions: &mut HashSet<String>) {
+        if schema::validate_item(&item).is_err() {
+            self.report.invalid += 1;
+        } else if !questions.insert(item.question.trim().to_lowercase()) {
+            self.report.duplicates += 1;
+        } else {
+            self.report.imported += 1;
+            self.items.push(item);
+        }
+    }
+}
+
+/// Read every file into records, in the given format or the one each file
+/// looks like
+pub fn import_files(paths: &[PathBuf], format: Option<ImportFormat>) -> Result<Imported> {
+    let mut imported = Imported::default();
+    let mut questions = HashSet::new();
+    for path in paths {
+        let content =
+            fs::read_to_string(path).with_context(|| format!("Could not read {:?}", path))?;
+        let source = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        import_records(&content, &source, format, &mut imported, &mut questions)
+            .with_context(|| format!("Could not import {:?}", path))?;
+    }
+    Ok(imported)
+}
+
+/// Read one file's records into `imported`
+///
+/// Records get IDs over `source` (the file's name), their context and
+/// question, so importing the same file again gives the same IDs.
+fn import_records(
+    content: &str,
+    source: &str,
+    format: Option<ImportFormat>,
+    imported: &mut Imported,
+    questions: &mut HashSet<String>,
+) -> Result<()> {
+    let (records, malformed) = json_records(content)?;
+    imported.report.invalid += malformed;
+    let Some(format) = format.or_else(|| records.first().and_then(ImportFormat::of_record)) else {
+        if records.is_empty() {
+            return Ok(());
+        }
+        bail!("Unrecognized dataset format; give it with --format");
+    };
+
+    let record_item = |question: &str, answer: &str, context: Option<String>| {
+        let mut item = ProcessedItem {
+            question: question.trim().to_string(),
+            answer: answer.trim().to_string(),
+            context: context.filter(|context| !context.trim().is_empty()),
+            ..Default::default()
+        };
+        item.assign_id(
+            source,
+            &hash_text(item.context.as_deref().unwrap_or_default()),
+        );
+        item
+    };
+    for record in &records {
+        match format {
+            ImportFormat::Squad => {
+                let articles = record["data"].as_array().into_iter().flatten();
+                for paragraph in articles.flat_map(|article| array(&article["paragraphs"])) {
+                    let context = paragraph["context"].as_str().map(str::to_string);
+                    for qa in array(&paragraph["qas"]) {
+                        let answer =
+                            array(&qa["answers"]).find_map(|answer| answer["text"].as_str());
+                        match (qa["question"].as_str(), answer) {
+                            (_, None)
+                                if qa["is_impossible"] == true || qa["answers"].is_array() =>
+                            {
+                                imported.report.unanswerable += 1
+                            }
+                            (Some(question), Some(answer)) => imported
+                                .push(record_item(question, answer, context.clone()), questions),
+                            _ => imported.report.invalid += 1,
+                        }
+                    }
+                }
+            }
+            ImportFormat::Alpaca => {
+                match (record["instruction"].as_str(), record["output"].as_str()) {
+                    (Some(question), Some(answer)) => {
+                        let context = record["input"].as_str().map(str::to_string);
+                        imported.push(record_item(question, answer, context), questions)
+                    }
+                    _ => imported.report.invalid += 1,
+                }
+            }
+            ImportFormat::ShareGpt => {
+                let pairs = conversation_pairs(record);
+                if pairs.is_empty() {
+                    imported.report.invalid += 1;
+                }
+                for (question, answer, context) in pairs {
+                    imported.push(record_item(&question, &answer, context), questions);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The elements of a JSON array, or none
+fn array(value: &Value) -> impl Iterator<Item = &Value> {
+    value.as_array().into_iter().flatten()
+}
+
+/// The records of a JSON array, a single JSON document or JSONL, with the
+/// number of JSONL lines that aren't JSON
+fn json_records(content: &str) -> Result<(Vec<Value>, usize)> {
+    let trimmed = content.trim_start();
+    if trimmed.starts_with('[') {
+        let records: Vec<Value> = serde_json::from_str(trimmed).context("Invalid JSON array")?;
+        return Ok((records, 0));
+    }
+    if let Ok(document) = serde_json::from_str::<Value>(trimmed) {
+        return Ok((vec![document], 0));
+    }
+    let mut records = Vec::new();
+    let mut malformed = 0;
+    for line in content.lines().filter(|line| !line.trim().is_empty()) {
+        match serde_json::from_str::<Value>(line) {
+            // A schema header of an exported or generated file
+            Ok(record) if record.get("schema").is_some() => {}
+            Ok(record) => records.push(record),
+            Err(_) => malformed += 1,
+        }
+    }
+    Ok((records, malformed))
+}
+
+/// The question and answer of every user turn answered by the assistant,
+/// each with the conversation before it as context
+///
+/// Turns are `{"from", "value"}` (ShareGPT) or `{"role", "content"}`
+/// (OpenAI); system turns are left out.
+fn conversation_pairs(record: &Value) -> Vec<(String, String, Option<String>)> {
+    let turns: Vec<(&str, &str)> = array(&record["conversations"])
+        .chain(array(&record["messages"]))
+        .filter_map(|turn| {
+            let speaker = turn["from"].as_str().or_else(|| turn["role"].as_str())?;
+            let text = turn["value"]
+                .as_str()
+                .or_else(|| turn["content"].as_str())?;
+            let speaker = match speaker {
+                "human" | "user" => "Human",
+                "gpt" | "assistant" | "chatgpt" | "bard" | "bing" => "Assistant",
+                _ => return None,
+            };
+            Some((speaker, text))
+        })
+        .collect();
+
+    let mut pairs = Vec::new();
+    for (i, window) in turns.windows(2).enumerate() {
+        let [(asker, question), (answerer, answer)] = window else {
+            continue;
+        };
+        if *asker != "Human" || *answerer != "Assistant" {
+            continue;
+        }
+        let earlier: Vec<String> = turns[..i]
+            .iter()
+            .map(|(speaker, text)| format!("{}: {}", speaker, text))
+            .collect();
+        let context = (!earlier.is_empty()).then(|| earlier.join("\n\n"));
+        pairs.push((question.to_string(), answer.to_string(), context));
+    }
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::{export_dataset, ExportFormat};
+
+    fn import(content: &str, format: Option<ImportFormat>) -> Imported {
+        let mut imported = Imported::default();
+        import_records(
+            content,
+            "data.json",
+            format,
+            &mut imported,
+            &mut HashSet::new(),
+        )
+        .unwrap();
+        imported
+    }
+
+    #[test]
+    fn test_import_squad() {
+        let squad = r#"{"version": "v2.0", "data": [{"title": "TLS", "paragraphs": [{
+            "context": "TLS is enabled with use_tls.",
+            "qas": [
+                {"id": "1", "question": "How is TLS enabled?", "answers": [{"text": "With use_tls", "answer_start": 16}]},
+                {"id": "2", "question": "Who wrote TLS?", "answers": [], "is_impossible": true},
+                {"id": "3", "question": "How is TLS enabled? ", "answers": [{"text": "use_tls", "answer_start": 16}]}
+            ]}]}]}"#;
+        let imported = import(squad, None);
+        assert_eq!(
+            imported.report,
+            ImportReport {
+                imported: 1,
+                unanswerable: 1,
+                invalid: 0,
+                duplicates: 1,
+            }
+        );
+        let item = &imported.items[0];
+        assert_eq!(item.answer, "With use_tls");
+        assert_eq!(
+            item.context.as_deref(),
+            Some("TLS is enabled with use_tls.")
+        );
+        assert!(item.id.is_some());
+        assert_eq!(import(squad, None).items[0].id, item.id);
+    }
+
+    #[test]
+    fn test_import_round_trips_exports() {
+        let dir = tempfile::tempdir().unwrap();
+        let item = ProcessedItem {
+            question: "How do I enable TLS?".to_string(),
+            answer: "Set use_tls to true.".to_string(),
+            context: Some("TLS docs".to_string()),
+            ..Default::default()
+        };
+
+        let alpaca = dir.path().join("export_alpaca.json");
+        export_dataset(&alpaca, std::slice::from_ref(&item), ExportFormat::Alpaca).unwrap();
+        let imported = import_files(&[alpaca], None).unwrap();
+        assert_eq!(imported.items[0].question, item.question);
+        assert_eq!(imported.items[0].answer, item.answer);
+        assert_eq!(imported.items[0].context, item.context);
+
+        let sharegpt = dir.path().join("export_sharegpt.jsonl");
+        export_dataset(
+            &sharegpt,
+            std::slice::from_ref(&item),
+            ExportFormat::ShareGpt,
+        )
+        .unwrap();
+        let imported = import_files(&[sharegpt], Some(ImportFormat::ShareGpt)).unwrap();
+        assert_eq!(imported.items[0].answer, item.answer);
+    }
+
+    #[test]
+    fn test_import_conversations() {
+        let jsonl = [
+            r#"{"conversations": [{"from": "system", "value": "Be brief."}, {"from": "human", "value": "What is Qdrant?"}, {"from": "gpt", "value": "A vector database."}, {"from": "human", "value": "Is it open source?"}, {"from": "gpt", "value": "Yes."}]}"#,
+            r#"{"messages": [{"role": "user", "content": "What is Ollama?"}, {"role": "assistant", "content": "A model server."}]}"#,
+            "not json",
+            r#"{"conversations": [{"from": "human", "value": "Unanswered?"}]}"#,
+        ]
+        .join("\n");
+        let imported = import(&jsonl, None);
+        assert_eq!(imported.report.imported, 3);
+        assert_eq!(imported.report.invalid, 2);
+        let follow_up = &imported.items[1];
+        assert_eq!(follow_up.question, "Is it open source?");
+        assert_eq!(
+            follow_up.context.as_deref(),
+            Some("Human: What is Qdrant?\n\nAssistant: A vector database.")
+        );
+        assert_eq!(imported.items[2].answer, "A model server.");
+
+        let unknown = import_records(
+            r#"[{"prompt": "Q", "completion": "A"}]"#,
+            "data.json",
+            None,
+            &mut Imported::default(),
+            &mut HashSet::new(),
+        );
+        assert!(unknown.is_err());
+    }
+}
@@ -0,0 +1,129 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::hash::hash_text;
+use crate::processor::ProcessedItem;
+
+/// Content hash of a section, ignoring surrounding whitespace
+pub fn section_hash(content: &str) -> String {
+    hash_text(content.trim())
+}
+
+/// One section of a source file and the number of records generated from it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SectionEntry {
+    pub hash: String,
+    pub items: usize,
+}
+
+/// Sidecar written next to a QA file mapping its records back to sections
+///
+/// Records are written section by section, so the entries (in order) split
+/// the QA file into per-section groups. This lets a later run keep the
+/// records of unchanged sections and regenerate only the ones whose content
+/// hash changed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SectionIndex {
+    pub sections: Vec<SectionEntry>,
+}
+
+impl SectionIndex {
+    /// Load an index, returning `None` if it does not exist or cannot be read
+    pub fn load(path: &Path) -> Option<Self> {
+        let content = fs::read_to_string(path).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Record a section and how many records were written for it
+    pub fn push(&mut self, content: &str, items: usize) {
+        self.sections.push(SectionEntry {
+            hash: section_hash(content),
+            items,
+        });
+    }
+
+    /// Split records into per-section groups keyed by content hash
+    ///
+    /// Returns `None` if the index does not account for exactly these
+    /// records (e.g. the QA file was edited by hand). Sections with the same
+    /// content keep one group each, in file order.
+    pub fn group_items(
+        &self,
+        items: Vec<ProcessedItem>,
+    ) -> Option<HashMap<String, Vec<Vec<ProcessedItem>>>> {
+        let total: usize = self.sections.iter().map(|entry| entry.items).sum();
+        if total != items.len() {
+            return None;
+        }
+
+        let mut groups: HashMap<String, Vec<Vec<ProcessedItem>>> = HashMap::new();
+        let mut items = items.into_iter();
+        for entry in &self.sections {
+            let group = items.by_ref().take(entry.items).collect();
+            groups.entry(entry.hash.clone()).or_default().push(group);
+        }
+        Some(groups)
+    }
+}
+
+/// Content hash of a file, read in chunks so large files aren't held in memory
+pub fn file_hash(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(question: &str) -> ProcessedItem {
+        ProcessedItem::new(question, "A")
+    }
+
+    #[test]
+    fn test_group_items() {
+        let mut index = SectionIndex::default();
+        index.push("# One\n", 2);
+        index.push("# Two\n", 1);
+
+        let groups = index
+            .group_items(vec![item("Q1"), item("Q2"), item("Q3")])
+            .unwrap();
+        assert_eq!(groups[&section_hash("# One")][0].len(), 2);
+        assert_eq!(groups[&section_hash("  # Two  ")][0][0].question, "Q3");
+
+        // Counts that do not match the records are rejected
+        assert!(index.group_items(vec![item("Q1")]).is_none());
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("doc_qa.sections.json");
+        assert!(SectionIndex::load(&path).is_none());
+
+        let mut index = SectionIndex::default();
+        index.push("content", 3);
+        index.save(&path).unwrap();
+        assert_eq!(SectionIndex::load(&path), Some(index));
+    }
+
+    #[test]
+    fn test_file_hash() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let source = temp_dir.path().join("doc.md");
+        fs::write(&source, "# Doc\nText\n").unwrap();
+        assert_eq!(file_hash(&source).unwrap(), hash_text("# Doc\nText\n"));
+    }
+}
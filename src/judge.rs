@@ -0,0 +1,298 @@
+//! LLM-as-judge scoring of generated records, and quality reports over the
+//! scores so datasets generated with different settings can be compared.
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+use crate::external::{ChatMessage, ChatRequest, ModelProvider};
+use crate::processor::{DefaultOllamaClient, ProcessedItem};
+
+/// Instructions for the judge model
+pub const JUDGE_PROMPT: &str = "You are a strict reviewer of question-answer pairs used to train language models. \
+     Rate the pair on each criterion from 1 (poor) to 5 (excellent). \
+     correctness: the answer is accurate, and supported by the context or rubric when one is given. \
+     relevance: the answer addresses exactly what was asked. \
+     clarity: the question is self-contained and unambiguous, and the answer is well written. \
+     Reply with JSON holding the three scores and a one-sentence 'reason'.";
+
+/// Records whose overall score is below this are counted as low quality
+pub const LOW_QUALITY_SCORE: f64 = 3.0;
+
+/// Scores from 1 to 5 given to one record
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Scores {
+    pub correctness: u8,
+    pub relevance: u8,
+    pub clarity: u8,
+}
+
+impl Scores {
+    /// Mean of the criteria
+    pub fn overall(&self) -> f64 {
+        (self.correctness + self.relevance + self.clarity) as f64 / 3.0
+    }
+}
+
+/// The judge's verdict on one record
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Judgement {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<Uuid>,
+    pub question: String,
+    #[serde(flatten)]
+    pub scores: Scores,
+    pub overall: f64,
+    #[serde(default)]
+    pub reason: String,
+}
+
+/// Parse the judge's reply, clamping scores to 1..=5
+pub fn parse_verdict(reply: &str) -> Result<(Scores, String)> {
+    #[derive(Deserialize)]
+    struct Verdict {
+        correctness: f64,
+        relevance: f64,
+        clarity: f64,
+        #[serde(default)]
+        reason: String,
+    }
+
+    let sanitized = DefaultOllamaClient::sanitize_json(reply.trim());
+    let verdict: Verdict = serde_json::from_str(&sanitized)
+        .map_err(|e| anyhow!("Unreadable verdict ({}): {}", e, reply.trim()))?;
+    let score = |value: f64| value.round().clamp(1.0, 5.0) as u8;
+    Ok((
+        Scores {
+            correctness: score(verdict.correctness),
+            relevance: score(verdict.relevance),
+            clarity: score(verdict.clarity),
+        },
+        verdict.reason,
+    ))
+}
+
+/// Scores records with a judge model
+pub struct Judge<'a> {
+    provider: &'a dyn ModelProvider,
+}
+
+impl<'a> Judge<'a> {
+    pub fn new(provider: &'a dyn ModelProvider) -> Self {
+        Self { provider }
+    }
+
+    /// Name of the judge model
+    pub fn model(&self) -> &str {
+        self.provider.model()
+    }
+
+    fn request(item: &ProcessedItem) -> ChatRequest {
+        let mut pair = format!("Question: {}\nAnswer: {}", item.question, item.answer);
+        if let Some(context) = &item.context {
+            pair = format!("Context: {}\n{}", context, pair);
+        }
+        if let Some(rubric) = item.rubric.as_ref().filter(|rubric| !rubric.is_empty()) {
+            pair.push_str("\nRubric:");
+            for point in rubric {
+                pair.push_str(&format!("\n- {}", point));
+            }
+        }
+        let score = serde_json::json!({ "type": "integer", "minimum": 1, "maximum": 5 });
+        ChatRequest {
+            messages: vec![ChatMessage::system(JUDGE_PROMPT), ChatMessage::user(pair)],
+            format: Some(serde_json::json!({
+                "type": "object",
+                "required": ["correctness", "relevance", "clarity", "reason"],
+                "properties": {
+                    "correctness": score,
+                    "relevance": score,
+                    "clarity": score,
+                    "reason": { "type": "string" }
+                }
+            })),
+            max_tokens: None,
+        }
+    }
+
+    /// Score one record
+    pub async fn score(&self, item: &ProcessedItem) -> Result<Judgement> {
+        let reply = self.provider.chat(Self::request(item)).await?;
+        let (scores, reason) = parse_verdict(&reply)?;
+        Ok(Judgement {
+            id: item.id,
+            question: item.question.clone(),
+            scores,
+            overall: scores.overall(),
+            reason,
+        })
+    }
+}
+
+/// Aggregate quality of a dataset as judged
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct QualityReport {
+    /// Judge model that gave the scores
+    pub judge: String,
+    pub records: usize,
+    pub scored: usize,
+    /// Records the judge gave no readable verdict for
+    pub failed: usize,
+    pub correctness: f64,
+    pub relevance: f64,
+    pub clarity: f64,
+    pub overall: f64,
+    /// Records by overall score rounded to 1..=5
+    pub distribution: [usize; 5],
+    /// Records with an overall score below [`LOW_QUALITY_SCORE`]
+    pub low_quality: usize,
+}
+
+impl QualityReport {
+    pub fn from_judgements(judge: &str, judgements: &[Judgement], failed: usize) -> Self {
+        let mut report = Self {
+            judge: judge.to_string(),
+            records: judgements.len() + failed,
+            scored: judgements.len(),
+            failed,
+            ..Self::default()
+        };
+        if judgements.is_empty() {
+            return report;
+        }
+        let average = |criterion: fn(&Scores) -> u8| {
+            judgements
+                .iter()
+                .map(|j| criterion(&j.scores) as f64)
+                .sum::<f64>()
+                / judgements.len() as f64
+        };
+        report.correctness = average(|s| s.correctness);
+        report.relevance = average(|s| s.relevance);
+        report.clarity = average(|s| s.clarity);
+        report.overall = (report.correctness + report.relevance + report.clarity) / 3.0;
+        for judgement in judgements {
+            let bucket = judgement.overall.round().clamp(1.0, 5.0) as usize - 1;
+            report.distribution[bucket] += 1;
+            if judgement.overall < LOW_QUALITY_SCORE {
+                report.low_quality += 1;
+            }
+        }
+        report
+    }
+}
+
+impl fmt::Display for QualityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Judge:        {}", self.judge)?;
+        writeln!(
+            f,
+            "Scored:       {} of {} ({} failed)",
+            self.scored, self.records, self.failed
+        )?;
+        writeln!(f, "Correctness:  {:.2}", self.correctness)?;
+        writeln!(f, "Relevance:    {:.2}", self.relevance)?;
+        writeln!(f, "Clarity:      {:.2}", self.clarity)?;
+        writeln!(f, "Overall:      {:.2}", self.overall)?;
+        writeln!(
+            f,
+            "Low quality:  {} (overall below {})",
+            self.low_quality, LOW_QUALITY_SCORE
+        )?;
+        write!(f, "Distribution:")?;
+        for (score, count) in self.distribution.iter().enumerate() {
+            write!(f, " {}:{}", score + 1, count)?;
+        }
+        Ok(())
+    }
+}
+
+/// Where the per-record scores of a dataset are written: `NAME.scores.jsonl`
+pub fn scores_path(path: &Path) -> PathBuf {
+    sibling(path, "scores.jsonl")
+}
+
+/// Where the quality report of a dataset is written: `NAME.quality.json`
+pub fn report_path(path: &Path) -> PathBuf {
+    sibling(path, "quality.json")
+}
+
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("dataset");
+    path.with_file_name(format!("{}.{}", stem, suffix))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn judgement(correctness: u8, relevance: u8, clarity: u8) -> Judgement {
+        let scores = Scores {
+            correctness,
+            relevance,
+            clarity,
+        };
+        Judgement {
+            id: None,
+            question: "Q?".to_string(),
+            scores,
+            overall: scores.overall(),
+            reason: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_parse_verdict() {
+        let (scores, reason) = parse_verdict(
+            "```json\n{\"correctness\": 5, \"relevance\": 4.4, \"clarity\": 9, \"reason\": \"Fine.\"}\n```",
+        )
+        .unwrap();
+        assert_eq!(
+            scores,
+            Scores {
+                correctness: 5,
+                relevance: 4,
+                clarity: 5
+            }
+        );
+        assert_eq!(reason, "Fine.");
+        assert!(parse_verdict("Looks good to me").is_err());
+    }
+
+    #[test]
+    fn test_request_includes_context_and_rubric() {
+        let item = ProcessedItem {
+            question: "How is TLS enabled?".to_string(),
+            answer: "Set tls = true.".to_string(),
+            context: Some("TLS is off by default.".to_string()),
+            rubric: Some(vec!["Names the tls setting".to_string()]),
+            ..Default::default()
+        };
+        let request = Judge::request(&item);
+        let ChatMessage { content, .. } = &request.messages[1];
+        assert!(content.starts_with("Context: TLS is off by default.\nQuestion:"));
+        assert!(content.ends_with("Rubric:\n- Names the tls setting"));
+    }
+
+    #[test]
+    fn test_quality_report() {
+        let judgements = [judgement(5, 5, 5), judgement(4, 4, 1), judgement(1, 2, 2)];
+        let report = QualityReport::from_judgements("judge", &judgements, 1);
+        assert_eq!((report.records, report.scored, report.failed), (4, 3, 1));
+        assert!((report.correctness - 10.0 / 3.0).abs() < 1e-9);
+        assert!((report.overall - 29.0 / 9.0).abs() < 1e-9);
+        assert_eq!(report.distribution, [0, 1, 1, 0, 1]);
+        assert_eq!(report.low_quality, 1);
+
+        assert_eq!(QualityReport::from_judgements("judge", &[], 2).overall, 0.0);
+        assert_eq!(
+            scores_path(Path::new("out/all_qa.jsonl")),
+            Path::new("out/all_qa.scores.jsonl")
+        );
+    }
+}
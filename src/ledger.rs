@@ -0,0 +1,160 @@
+//! Ledger of what was generated from each input file, and with what: the
+//! file's content hash, a hash of the prompt templates and the model. A file
+//! is only skipped when all three match a completed entry, so changing the
+//! prompts or the model regenerates it.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::hash::hash_text;
+use crate::processor::prompt_templates;
+
+/// File in the output directory with the ledger of every input file
+/// questions were generated from
+pub const LEDGER_FILE: &str = "ledger.json";
+
+/// Hash of every prompt template the records of a file can be generated with
+pub fn prompt_hash() -> String {
+    let templates: Vec<String> = prompt_templates()
+        .into_iter()
+        .map(|(name, template)| format!("{}\n{}", name, template))
+        .collect();
+    hash_text(&templates.join("\n\n"))
+}
+
+/// What records were generated from: the key of a ledger entry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GenerationKey {
+    pub content_hash: String,
+    pub prompt_hash: String,
+    pub model: String,
+}
+
+impl GenerationKey {
+    /// Key of generating from content with this hash using the current
+    /// prompt templates and `model`
+    pub fn new(content_hash: String, model: &str) -> Self {
+        Self {
+            content_hash,
+            prompt_hash: prompt_hash(),
+            model: model.to_string(),
+        }
+    }
+
+    /// Whether records of `other` were generated the same way, whatever the
+    /// content
+    pub fn same_generation(&self, other: &GenerationKey) -> bool {
+        self.prompt_hash == other.prompt_hash && self.model == other.model
+    }
+}
+
+/// The last generation from one input file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    #[serde(flatten)]
+    pub key: GenerationKey,
+    /// Records written to the file's QA file
+    pub records: usize,
+    /// Every section was generated from; files that were interrupted,
+    /// sampled or cut short by a limit are generated from again
+    pub complete: bool,
+    pub generated_at: DateTime<Utc>,
+}
+
+/// Ledger entries keyed by path relative to the output directory
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunLedger {
+    pub files: BTreeMap<String, LedgerEntry>,
+}
+
+impl RunLedger {
+    /// Load a ledger, starting an empty one if it does not exist or cannot be read
+    pub fn load(path: &Path) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Whether the file's records are complete for exactly this content,
+    /// these prompts and this model
+    pub fn is_generated(&self, file: &str, key: &GenerationKey) -> bool {
+        self.files
+            .get(file)
+            .is_some_and(|entry| entry.complete && entry.key == *key)
+    }
+
+    /// Whether the records of the file's sections can be reused for
+    /// generating with `key`: they were generated with the same prompts and
+    /// model, from this content or an earlier version of it
+    pub fn can_reuse_sections(&self, file: &str, key: &GenerationKey) -> bool {
+        self.files
+            .get(file)
+            .is_some_and(|entry| entry.key.same_generation(key))
+    }
+
+    /// Record a generation from a file, replacing the last one
+    pub fn record(&mut self, file: String, key: GenerationKey, records: usize, complete: bool) {
+        let entry = LedgerEntry {
+            key,
+            records,
+            complete,
+            generated_at: Utc::now(),
+        };
+        self.files.insert(file, entry);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ledger_keys() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join(LEDGER_FILE);
+        assert_eq!(RunLedger::load(&path), RunLedger::default());
+
+        let key = GenerationKey::new(hash_text("# Doc\n"), "llama3");
+        let mut ledger = RunLedger::default();
+        ledger.record("doc.md".to_string(), key.clone(), 4, true);
+        ledger.record(
+            "partial.md".to_string(),
+            GenerationKey::new(hash_text("# Partial\n"), "llama3"),
+            1,
+            false,
+        );
+        ledger.save(&path).unwrap();
+
+        let loaded = RunLedger::load(&path);
+        assert!(loaded.is_generated("doc.md", &key));
+        assert!(!loaded.is_generated("other.md", &key));
+        let edited = GenerationKey::new(hash_text("# Edited\n"), "llama3");
+        assert!(!loaded.is_generated("doc.md", &edited));
+        assert!(loaded.can_reuse_sections("doc.md", &edited));
+
+        // Another model or other prompts generate everything again
+        let other_model = GenerationKey::new(key.content_hash.clone(), "mistral");
+        assert!(!loaded.is_generated("doc.md", &other_model));
+        assert!(!loaded.can_reuse_sections("doc.md", &other_model));
+        let other_prompts = GenerationKey {
+            prompt_hash: hash_text("older prompts"),
+            ..key
+        };
+        assert!(!loaded.is_generated("doc.md", &other_prompts));
+
+        // An interrupted file is picked up again, keeping its sections
+        let partial = GenerationKey::new(hash_text("# Partial\n"), "llama3");
+        assert!(!loaded.is_generated("partial.md", &partial));
+        assert!(loaded.can_reuse_sections("partial.md", &partial));
+    }
+}
@@ -1,13 +1,118 @@
+#[cfg(feature = "native")]
+pub mod annotation;
+#[cfg(feature = "native")]
+pub mod augment;
+#[cfg(feature = "native")]
+pub mod balance;
+#[cfg(feature = "native")]
+pub mod benchmark;
+#[cfg(feature = "native")]
+pub mod boilerplate;
+#[cfg(feature = "native")]
+pub mod budget;
+#[cfg(feature = "native")]
+pub mod builder;
+#[cfg(feature = "native")]
+pub mod changelog;
+#[cfg(feature = "native")]
+pub mod code_docs;
+#[cfg(feature = "native")]
 pub mod config;
+#[cfg(feature = "native")]
+pub mod contamination;
+#[cfg(feature = "native")]
+pub mod coverage;
+#[cfg(feature = "native")]
 pub mod datasource;
+pub mod density;
+#[cfg(feature = "native")]
+pub mod difficulty;
+#[cfg(feature = "native")]
+pub mod distill;
+#[cfg(feature = "native")]
+pub mod eval;
+#[cfg(feature = "native")]
+pub mod exit;
+#[cfg(feature = "native")]
+pub mod experiments;
+#[cfg(feature = "native")]
+pub mod export;
+#[cfg(feature = "native")]
 pub mod external;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "native")]
+pub mod glossary;
 pub mod graph;
+pub mod hash;
+#[cfg(feature = "native")]
+pub mod import;
+#[cfg(feature = "native")]
+pub mod incremental;
+#[cfg(feature = "native")]
+pub mod judge;
+#[cfg(feature = "native")]
+pub mod ledger;
+#[cfg(feature = "native")]
+pub mod limits;
+#[cfg(feature = "native")]
+pub mod lineage;
+#[cfg(feature = "native")]
+pub mod manifest;
+#[cfg(feature = "native")]
+pub mod metadata;
+#[cfg(feature = "native")]
+pub mod migrate;
+#[cfg(feature = "native")]
+pub mod notify;
+#[cfg(feature = "native")]
+pub mod observer;
+#[cfg(feature = "native")]
+pub mod oversize;
 pub mod parser;
+#[cfg(feature = "native")]
+pub mod pipeline;
+#[cfg(feature = "native")]
+pub mod preflight;
+#[cfg(feature = "native")]
 pub mod processor;
+#[cfg(feature = "native")]
+pub mod publish;
+#[cfg(feature = "native")]
+pub mod queue;
+#[cfg(feature = "native")]
+pub mod rag;
+#[cfg(feature = "native")]
+pub mod rejection;
+#[cfg(feature = "native")]
+pub mod review;
+#[cfg(feature = "native")]
+pub mod schema;
+pub mod sections;
+#[cfg(feature = "native")]
+pub mod shutdown;
+#[cfg(feature = "native")]
+pub mod split;
+#[cfg(feature = "native")]
+pub mod stats;
+#[cfg(feature = "native")]
+pub mod telemetry;
+#[cfg(feature = "native")]
+pub mod validate;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "native")]
+pub mod watch;
 
+#[cfg(feature = "native")]
 pub use config::Config;
+#[cfg(feature = "native")]
 pub use datasource::DataSource;
+#[cfg(feature = "native")]
 pub use external::{EmbeddingEngine, ExternalError, LLMEngine, VectorDB};
-pub use graph::{error::GraphError, DocumentEdge, DocumentGraph, DocumentNode};
-pub use parser::{parse_markdown, parse_markdown_file};
+pub use graph::{error::GraphError, DocumentEdge, DocumentGraph, DocumentNode, NodeSet};
+pub use parser::{
+    parse_markdown, parse_markdown_dir, parse_markdown_document, parse_markdown_file,
+};
+#[cfg(feature = "native")]
 pub use processor::OllamaProcessor;
@@ -0,0 +1,185 @@
+//! Caps for pilot runs over part of a corpus: how many files are processed,
+//! how many questions are generated, and what share of sections is used.
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::hash::stable_fraction;
+
+/// Limits of one run; clones share the question count, so a cap spans all files
+#[derive(Debug, Clone, Default)]
+pub struct RunLimits {
+    pub max_files: Option<usize>,
+    /// Questions generated by this run (sections kept by `--incremental` don't count)
+    pub max_questions: Option<usize>,
+    /// Percentage of sections to generate questions from
+    pub sample_percent: Option<f64>,
+    /// Picks a different sample; the same seed picks the same sections
+    pub sample_seed: u64,
+    generated: Arc<AtomicUsize>,
+    /// Set once the question cap dropped questions or skipped a section
+    capped: Arc<AtomicBool>,
+}
+
+impl RunLimits {
+    pub fn new(
+        max_files: Option<usize>,
+        max_questions: Option<usize>,
+        sample_percent: Option<f64>,
+        sample_seed: u64,
+    ) -> Self {
+        Self {
+            max_files,
+            max_questions,
+            sample_percent,
+            sample_seed,
+            ..Default::default()
+        }
+    }
+
+    /// The files to process and how many were left out by `max_files`
+    pub fn limit_files(&self, mut files: Vec<PathBuf>) -> (Vec<PathBuf>, usize) {
+        let held_back = match self.max_files {
+            Some(max) if files.len() > max => files.len() - max,
+            _ => 0,
+        };
+        files.truncate(files.len() - held_back);
+        (files, held_back)
+    }
+
+    /// Whether a section is part of the sample
+    ///
+    /// The choice is a hash of the seed and the section's content, so it is
+    /// random across sections but stable across runs, and an `--incremental`
+    /// rerun keeps the same sections.
+    pub fn sampled(&self, section: &str) -> bool {
+        let Some(percent) = self.sample_percent else {
+            return true;
+        };
+        stable_fraction(&self.sample_seed.to_le_bytes(), section.trim()) * 100.0 < percent
+    }
+
+    /// Questions that may still be generated, if capped
+    pub fn remaining_questions(&self) -> Option<usize> {
+        self.max_questions
+            .map(|max| max.saturating_sub(self.generated()))
+    }
+
+    /// Record that a section was skipped because the cap was reached
+    pub fn skip_capped(&self) {
+        self.capped.store(true, Ordering::SeqCst);
+    }
+
+    /// Keep as many of `count` new questions as the cap allows, counting them
+    /// as generated; returns how many to keep
+    pub fn take_questions(&self, count: usize) -> usize {
+        let Some(max) = self.max_questions else {
+            self.generated.fetch_add(count, Ordering::SeqCst);
+            return count;
+        };
+        let mut kept = 0;
+        let _ = self
+            .generated
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |generated| {
+                kept = count.min(max.saturating_sub(generated));
+                Some(generated + kept)
+            });
+        if kept < count {
+            self.skip_capped();
+        }
+        kept
+    }
+
+    /// Questions generated so far
+    pub fn generated(&self) -> usize {
+        self.generated.load(Ordering::SeqCst)
+    }
+
+    /// Why the run stopped short of its input, if a cap did that; sampling is
+    /// a deliberate choice and doesn't count
+    pub fn exceeded(&self, files_held_back: usize) -> Option<String> {
+        if self.capped.load(Ordering::SeqCst) {
+            Some(format!(
+                "Question limit of {} reached before all sections were generated",
+                self.max_questions.unwrap_or_default()
+            ))
+        } else if files_held_back > 0 {
+            Some(format!(
+                "File limit of {} left {} files unprocessed",
+                self.max_files.unwrap_or_default(),
+                files_held_back
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limit_files() {
+        let files: Vec<PathBuf> = ["a.md", "b.md", "c.md"].map(PathBuf::from).into();
+        let limits = RunLimits {
+            max_files: Some(2),
+            ..Default::default()
+        };
+        let (kept, held_back) = limits.limit_files(files.clone());
+        assert_eq!(kept, files[..2]);
+        assert_eq!(held_back, 1);
+        assert_eq!(
+            limits.exceeded(held_back).unwrap(),
+            "File limit of 2 left 1 files unprocessed"
+        );
+
+        assert_eq!(RunLimits::default().limit_files(files.clone()), (files, 0));
+    }
+
+    #[test]
+    fn test_question_cap_is_shared() {
+        let limits = RunLimits {
+            max_questions: Some(5),
+            ..Default::default()
+        };
+        let other_file = limits.clone();
+        assert_eq!(limits.take_questions(3), 3);
+        assert_eq!(limits.exceeded(0), None);
+        assert_eq!(other_file.remaining_questions(), Some(2));
+        assert_eq!(other_file.take_questions(4), 2);
+        assert_eq!(limits.remaining_questions(), Some(0));
+        assert!(limits
+            .exceeded(0)
+            .unwrap()
+            .starts_with("Question limit of 5"));
+
+        let unlimited = RunLimits::default();
+        assert_eq!(unlimited.take_questions(7), 7);
+        assert_eq!(unlimited.remaining_questions(), None);
+    }
+
+    #[test]
+    fn test_sample_is_stable_and_proportional() {
+        let sections: Vec<String> = (0..1000).map(|i| format!("## Section {}", i)).collect();
+        let limits = RunLimits {
+            sample_percent: Some(20.0),
+            ..Default::default()
+        };
+        let picked = sections.iter().filter(|s| limits.sampled(s)).count();
+        assert!((150..250).contains(&picked), "picked {}", picked);
+        assert!(sections
+            .iter()
+            .all(|s| limits.sampled(s) == limits.clone().sampled(s)));
+
+        let reseeded = RunLimits {
+            sample_seed: 7,
+            ..limits.clone()
+        };
+        assert!(sections
+            .iter()
+            .any(|s| limits.sampled(s) != reseeded.sampled(s)));
+        assert!(sections.iter().all(|s| RunLimits::default().sampled(s)));
+    }
+}
@@ -0,0 +1,283 @@
+//! Lineage of generated records: the document span and graph nodes a record
+//! was generated from, and resolving that back to the nodes, their document
+//! and the source it was collected from, as W3C PROV.
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::ops::Range;
+use uuid::Uuid;
+
+use crate::datasource::SourceProvenance;
+use crate::graph::{DocumentGraph, DocumentNode};
+use crate::processor::ProcessedItem;
+
+/// Where a record was generated from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lineage {
+    /// Input file, named as in record IDs: its path within the output directory
+    pub document: String,
+    /// Byte range of the document the record was generated from
+    pub span: Range<usize>,
+    /// Graph nodes the span is made of, as parsed by
+    /// [`parse_markdown_document`](crate::parser::parse_markdown_document)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub nodes: Vec<Uuid>,
+}
+
+impl Lineage {
+    /// Lineage of a span of `document`, made of the nodes of `graph` it covers
+    pub fn of_span(document: &str, span: Range<usize>, graph: Option<&DocumentGraph>) -> Self {
+        let nodes = graph
+            .map(|graph| nodes_within(graph, &span))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|node| node.id)
+            .collect();
+        Self {
+            document: document.to_string(),
+            span,
+            nodes,
+        }
+    }
+}
+
+/// Outermost content nodes lying entirely within `span`, in reading order
+///
+/// Nodes whose parent also lies within the span are left out, so a section
+/// is represented by its heading node rather than every paragraph under it.
+pub fn nodes_within<'a>(graph: &'a DocumentGraph, span: &Range<usize>) -> Vec<&'a DocumentNode> {
+    let within = |node: &DocumentNode| {
+        node.node_type.is_content()
+            && node
+                .metadata
+                .span
+                .is_some_and(|s| span.start <= s.start_byte && s.end_byte <= span.end)
+    };
+    graph
+        .reading_order()
+        .filter(|node| within(node))
+        .filter(|node| !matches!(graph.get_parent(&node.id), Ok(Some(parent)) if within(parent)))
+        .collect()
+}
+
+/// A record's lineage resolved against a parsed document and the recorded sources
+#[derive(Debug)]
+pub struct Resolved<'a> {
+    pub nodes: Vec<&'a DocumentNode>,
+    pub document: Option<&'a DocumentNode>,
+    pub source: Option<&'a SourceProvenance>,
+}
+
+/// Resolve `lineage` to its nodes in `graph`, their document, and the source
+/// the document was collected from
+///
+/// Nodes are looked up by ID. If none are found, e.g. because the document
+/// changed since the record was generated, the nodes now within the record's
+/// span are used instead.
+pub fn resolve<'a>(
+    graph: &'a DocumentGraph,
+    sources: &'a [SourceProvenance],
+    lineage: &Lineage,
+) -> Result<Resolved<'a>> {
+    let mut nodes: Vec<&DocumentNode> = lineage
+        .nodes
+        .iter()
+        .filter_map(|id| graph.get_node(id))
+        .collect();
+    if nodes.is_empty() {
+        nodes = nodes_within(graph, &lineage.span);
+    }
+    let document = match nodes.first() {
+        Some(node) => graph.document_of(&node.id)?,
+        None => graph.documents().into_iter().next(),
+    };
+    let source = sources
+        .iter()
+        .find(|source| source.files.contains(&lineage.document));
+    Ok(Resolved {
+        nodes,
+        document,
+        source,
+    })
+}
+
+/// Lineage of a record, or an error naming the record if it has none
+pub fn lineage_of(item: &ProcessedItem) -> Result<&Lineage> {
+    item.lineage.as_ref().ok_or_else(|| {
+        anyhow!(
+            "Record {} has no lineage; regenerate the dataset to record it",
+            item.id.map(|id| id.to_string()).unwrap_or_default()
+        )
+    })
+}
+
+impl Resolved<'_> {
+    /// The record's lineage as a PROV-JSON document
+    ///
+    /// The record was derived from each node, each node from the document,
+    /// and the document had the collected source as its primary source.
+    pub fn to_prov(&self, item: &ProcessedItem, lineage: &Lineage) -> Value {
+        let mut entities = Map::new();
+        let mut derivations = Map::new();
+        let mut derive = |generated: &str, used: &str, primary: bool| {
+            let mut derivation = json!({
+                "prov:generatedEntity": generated,
+                "prov:usedEntity": used,
+            });
+            if primary {
+                derivation["prov:type"] = json!("prov:PrimarySource");
+            }
+            derivations.insert(format!("_:d{}", derivations.len() + 1), derivation);
+        };
+
+        let record = format!(
+            "record:{}",
+            item.id.map(|id| id.to_string()).unwrap_or_default()
+        );
+        entities.insert(
+            record.clone(),
+            json!({ "prov:type": "record", "prov:label": item.question }),
+        );
+
+        let document = format!("document:{}", lineage.document);
+        let mut document_entity = json!({
+            "prov:type": "document",
+            "span": format!("{}..{}", lineage.span.start, lineage.span.end),
+        });
+        if let Some(title) = self.document.and_then(|d| d.metadata.title.as_deref()) {
+            document_entity["prov:label"] = json!(title);
+        }
+        entities.insert(document.clone(), document_entity);
+
+        if self.nodes.is_empty() {
+            derive(&record, &document, false);
+        }
+        for node in &self.nodes {
+            let id = format!("node:{}", node.id);
+            let mut entity = json!({ "prov:type": format!("{:?}", node.node_type) });
+            if let Some(span) = node.metadata.span {
+                entity["span"] = json!(format!("{}..{}", span.start_byte, span.end_byte));
+            }
+            if let Some(title) = node.metadata.title.as_deref() {
+                entity["prov:label"] = json!(title);
+            }
+            entities.insert(id.clone(), entity);
+            derive(&record, &id, false);
+            derive(&id, &document, false);
+        }
+
+        if let Some(source) = self.source {
+            let id = format!("source:{}", source.location);
+            let mut entity = json!({
+                "prov:type": source.kind,
+                "prov:location": source.location,
+            });
+            if let Some(revision) = &source.revision {
+                entity["revision"] = json!(revision);
+            }
+            entities.insert(id.clone(), entity);
+            derive(&document, &id, true);
+        }
+
+        json!({
+            "prefix": {
+                "record": "urn:uuid:",
+                "node": "urn:uuid:",
+                "document": "file:",
+                "source": "",
+            },
+            "entity": entities,
+            "wasDerivedFrom": derivations,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_markdown_document;
+
+    const MARKDOWN: &str = "Intro text.\n\n# Setup\nInstall it.\n\n## Usage\nRun it.\n";
+
+    fn sources() -> Vec<SourceProvenance> {
+        vec![SourceProvenance {
+            kind: "github".to_string(),
+            location: "https://github.com/owner/repo".to_string(),
+            revision: Some("abc123".to_string()),
+            files: vec!["docs/guide.md".to_string()],
+        }]
+    }
+
+    #[test]
+    fn test_nodes_within_keep_outermost() {
+        let graph = parse_markdown_document(MARKDOWN, "docs/guide.md").unwrap();
+        let setup = MARKDOWN.find("# Setup").unwrap();
+        let usage = MARKDOWN.find("## Usage").unwrap();
+
+        let titles: Vec<&str> = nodes_within(&graph, &(setup..usage))
+            .into_iter()
+            .map(|node| &*node.content)
+            .collect();
+        assert_eq!(titles, ["Setup"]);
+        let intro: Vec<&str> = nodes_within(&graph, &(0..setup))
+            .into_iter()
+            .map(|node| &*node.content)
+            .collect();
+        assert_eq!(intro, ["Intro text."]);
+    }
+
+    #[test]
+    fn test_resolve_record_to_node_document_and_source() {
+        let graph = parse_markdown_document(MARKDOWN, "docs/guide.md").unwrap();
+        let setup = MARKDOWN.find("# Setup").unwrap();
+        let usage = MARKDOWN.find("## Usage").unwrap();
+        let lineage = Lineage::of_span("docs/guide.md", setup..usage, Some(&graph));
+        assert_eq!(lineage.nodes.len(), 1);
+
+        // A fresh parse of the same document has the same node IDs
+        let reparsed = parse_markdown_document(MARKDOWN, "docs/guide.md").unwrap();
+        let sources = sources();
+        let resolved = resolve(&reparsed, &sources, &lineage).unwrap();
+        assert_eq!(resolved.nodes[0].id, lineage.nodes[0]);
+        assert_eq!(
+            resolved.document.unwrap().attribute("path"),
+            Some("docs/guide.md")
+        );
+        assert_eq!(resolved.source.unwrap().revision.as_deref(), Some("abc123"));
+
+        // Once the document changes, the nodes now in the span are used
+        let edited = MARKDOWN.replace("Intro text.", "Intro!");
+        let changed = parse_markdown_document(&edited, "docs/guide.md").unwrap();
+        let resolved = resolve(&changed, &sources, &lineage).unwrap();
+        assert!(changed.get_node(&lineage.nodes[0]).is_none());
+        assert_eq!(resolved.nodes.len(), 1);
+    }
+
+    #[test]
+    fn test_to_prov() {
+        let graph = parse_markdown_document(MARKDOWN, "docs/guide.md").unwrap();
+        let lineage = Lineage::of_span("docs/guide.md", 13..MARKDOWN.len(), Some(&graph));
+        let mut item = ProcessedItem {
+            question: "How is it installed?".to_string(),
+            answer: "Install it.".to_string(),
+            lineage: Some(lineage.clone()),
+            ..Default::default()
+        };
+        item.assign_id("docs/guide.md", "span");
+        let sources = sources();
+        let prov = resolve(&graph, &sources, &lineage)
+            .unwrap()
+            .to_prov(&item, &lineage);
+
+        let source = "source:https://github.com/owner/repo";
+        assert_eq!(prov["entity"][source]["revision"], "abc123");
+        let derivations = prov["wasDerivedFrom"].as_object().unwrap();
+        // Record from the Setup section (Usage is nested in it), the section
+        // from the document, and the document from its source
+        assert_eq!(derivations.len(), 3);
+        assert!(derivations
+            .values()
+            .any(|d| d["prov:usedEntity"] == source && d["prov:type"] == "prov:PrimarySource"));
+    }
+}
@@ -1,234 +1,3375 @@
-use clap::Parser;
+use anyhow::Context;
+use clap::builder::{PossibleValuesParser, TypedValueParser};
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand, ValueHint};
+use clap_complete::Shell;
+use console::Term;
 use dotenv::dotenv;
+use regex::Regex;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::env;
 use std::fs;
-use std::io::Write;
+use std::io::{IsTerminal, Write};
+use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use uuid::Uuid;
 use walkdir::WalkDir;
 
+use llm_dataset_builder::annotation::{self, AnnotationClient, AnnotationTool};
+use llm_dataset_builder::augment::{self, Augmenter, BackTranslator, DEFAULT_MIN_SIMILARITY};
+use llm_dataset_builder::balance::{self, Balance, BalanceBy};
+use llm_dataset_builder::benchmark;
+use llm_dataset_builder::boilerplate;
+use llm_dataset_builder::budget::{QuestionBudget, TokenBudget, DEFAULT_MAX_OUTPUT_TOKENS};
+use llm_dataset_builder::config::{
+    config_template, effective_settings, ConfigFile, DEFAULT_CONFIG_FILE,
+};
+use llm_dataset_builder::contamination::{
+    self, ContaminationCheck, ContaminationReport, DEFAULT_NGRAM, DEFAULT_SIMILARITY,
+};
+use llm_dataset_builder::coverage::{CoverageReport, DocumentSections, COVERAGE_FILE};
 use llm_dataset_builder::datasource::{
-    DataSource, GitHubReleaseSource, GitHubSource, LocalSource, UrlSource,
+    open_source, recorded_sources, DataSource, LocalSource, SourceProvenance, SourceType,
+};
+use llm_dataset_builder::density::QuestionDensity;
+use llm_dataset_builder::distill::{Student, DEFAULT_MIN_AGREEMENT, STUDENT_REPORT_FILE};
+use llm_dataset_builder::eval::{EvalSplit, EVAL_FILE};
+use llm_dataset_builder::exit::{ExitReason, RunReport, ERRORS_FILE};
+use llm_dataset_builder::experiments::{self, Comparison, Registry};
+use llm_dataset_builder::export::{export_curriculum, export_dataset, Curriculum, ExportFormat};
+use llm_dataset_builder::external::{
+    Embedder, EmbeddingEngine, LLMBackend, MockBackend, ModelProvider, OllamaProvider,
+    SearchFilter, DEFAULT_KEEP_ALIVE,
+};
+use llm_dataset_builder::graph::{
+    tag_nodes, DocumentGraph, GraphIndexer, SnapshotHeader, TaggingConfig, VectorStore,
 };
+use llm_dataset_builder::import::{import_files, ImportFormat};
+use llm_dataset_builder::judge::{self, Judge, QualityReport};
+use llm_dataset_builder::limits::RunLimits;
+use llm_dataset_builder::lineage::{self, Lineage};
+use llm_dataset_builder::manifest::RunManifest;
+use llm_dataset_builder::migrate;
+use llm_dataset_builder::notify::{RunNotification, WEBHOOK_URL_VAR};
+use llm_dataset_builder::observer::PipelineObserver;
+use llm_dataset_builder::oversize::{parse_size, OversizePolicy, SizeLimit, PARTS_DIR};
+use llm_dataset_builder::parser::{
+    parse_markdown_dir, parse_markdown_document, parse_markdown_file,
+};
+use llm_dataset_builder::pipeline::{self, Pipeline};
+use llm_dataset_builder::preflight::{preflight, PreflightTargets};
 use llm_dataset_builder::processor::{
-    DefaultOllamaClient, DefaultOllamaProcessor, OllamaProcessor,
+    prompt_templates, DefaultOllamaClient, DefaultOllamaProcessor, OllamaProcessor, ProcessedItem,
+    ProcessorError,
+};
+use llm_dataset_builder::publish::{StreamSink, StreamTarget, PUBLISH_URL_VAR};
+use llm_dataset_builder::queue::{self, QueueClient, WorkQueue, DEFAULT_LEASE, QUEUE_TOKEN_VAR};
+use llm_dataset_builder::rag::{RagConfig, RagGenerator};
+use llm_dataset_builder::rejection::{CandidateScoring, RejectionSampling};
+use llm_dataset_builder::review;
+use llm_dataset_builder::schema::{self, DatasetWriter};
+use llm_dataset_builder::shutdown::Shutdown;
+use llm_dataset_builder::split::{self, DEFAULT_CLUSTER_SIMILARITY, DEFAULT_TEST_PERCENT};
+use llm_dataset_builder::stats::DatasetStats;
+use llm_dataset_builder::telemetry::Telemetry;
+use llm_dataset_builder::validate;
+use llm_dataset_builder::watch::{
+    is_input_file, ChangeWatcher, ContentTracker, DatasetAppender, WatchPlan,
 };
+use llm_dataset_builder::Config;
+
+/// File the `rag` command writes its records to
+const RAG_OUTPUT_FILE: &str = "rag_qa.jsonl";
+
+/// File `rag --negatives` writes its retrieval records to
+const RAG_NEGATIVES_FILE: &str = "rag_negatives.jsonl";
+
+/// File the `import` command writes its records to
+const IMPORTED_FILE: &str = "imported.jsonl";
+
+/// Characters of matched content shown per search result
+const SEARCH_SNIPPET_CHARS: usize = 160;
+
+/// Quiet period that ends a burst of file system events in --watch mode
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(500);
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
     /// Output directory for collected data
-    #[arg(short = 'd', long)]
+    #[arg(short = 'd', long, global = true, value_hint = ValueHint::DirPath)]
     output_dir: Option<String>,
 
+    /// Config file with settings and named profiles [default: llm_dataset_builder.toml if present]
+    #[arg(long, global = true, value_hint = ValueHint::FilePath)]
+    config: Option<PathBuf>,
+
+    /// Profile from the config file to use, e.g. gpu-server
+    #[arg(long, global = true)]
+    profile: Option<String>,
+
     /// Ollama API endpoint
-    #[arg(short = 'e', long)]
+    #[arg(short = 'e', long, global = true)]
     ollama_endpoint: Option<String>,
 
     /// Ollama model to use
-    #[arg(short = 'm', long)]
+    #[arg(short = 'm', long, global = true)]
     model: Option<String>,
 
     /// Test mode (skips interactive input)
-    #[arg(long, hide = true)]
+    #[arg(long, global = true, hide = true)]
     test_mode: bool,
+
+    /// Options of the pipeline run without a subcommand
+    #[command(flatten)]
+    pipeline: PipelineArgs,
+
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-async fn collect_sources() -> Result<Vec<Box<dyn DataSource>>, Box<dyn std::error::Error>> {
-    let mut sources: Vec<Box<dyn DataSource>> = Vec::new();
-    let mut buffer = String::new();
+#[derive(clap::Args, Debug, Clone)]
+struct ServiceArgs {
+    /// Don't check that Ollama, the embedding model and Qdrant are reachable before starting
+    #[arg(long)]
+    skip_preflight: bool,
+}
 
-    loop {
-        println!("\nEnter a data source (press Enter to finish):");
-        println!("- URL (e.g., https://example.com/file.txt)");
-        println!("- Local path (e.g., /path/to/file)");
-        println!("- GitHub URL (e.g., https://github.com/user/repo/tree/branch/path)");
-        println!("- GitHub releases URL (e.g., https://github.com/user/repo/releases)");
-        print!("> ");
-        std::io::stdout().flush()?;
+#[derive(clap::Args, Debug, Clone)]
+struct ModelArgs {
+    /// How long Ollama keeps the model loaded between requests, e.g. 30m or -1 for always
+    #[arg(long)]
+    keep_alive: Option<String>,
 
-        buffer.clear();
-        std::io::stdin().read_line(&mut buffer)?;
-        let input = buffer.trim();
+    /// Skip the startup check that pulls the model if the Ollama server lacks it
+    #[arg(long)]
+    no_pull: bool,
 
-        if input.is_empty() {
-            break;
-        }
+    #[command(flatten)]
+    services: ServiceArgs,
+}
 
-        // Check if it's a GitHub releases URL
-        if input.contains("/releases") {
-            println!("Processing GitHub releases: {}", input);
-            match GitHubReleaseSource::new(input) {
-                Ok(source) => {
-                    sources.push(Box::new(source) as Box<dyn DataSource>);
-                    println!("Successfully added GitHub releases source: {}", input);
-                }
-                Err(e) => println!("Error adding GitHub releases source: {}", e),
-            }
-            continue;
+#[derive(clap::Args, Debug, Clone)]
+struct DensityArgs {
+    /// How many questions a file is worth: words, tokens, section, file or
+    /// information, each optionally with its amount, e.g. words:10 or file:20
+    #[arg(long, value_name = "STRATEGY")]
+    question_density: Option<String>,
+}
+
+impl DensityArgs {
+    /// --question-density, else QUESTION_DENSITY, else words:10
+    fn density(&self) -> anyhow::Result<QuestionDensity> {
+        setting(
+            self.question_density.clone(),
+            "QUESTION_DENSITY",
+            "words:10",
+        )
+        .parse()
+        .context(ExitReason::Config)
+    }
+}
+
+#[derive(clap::Args, Debug, Clone)]
+struct BalanceArgs {
+    /// Cap each source document's share of all_qa.jsonl at PERCENT (e.g. 20), dropping
+    /// records of larger ones once generation finishes
+    #[arg(long, value_name = "PERCENT", value_parser = parse_percent)]
+    max_source_share: Option<f64>,
+
+    /// Whether --max-source-share caps documents or whole directories of them
+    #[arg(
+        long,
+        default_value_t = BalanceBy::default(),
+        value_parser = names::<BalanceBy>(BalanceBy::ALL.map(|by| by.name())),
+        ignore_case = true
+    )]
+    balance_by: BalanceBy,
+}
+
+impl BalanceArgs {
+    fn balance(&self) -> Option<Balance> {
+        self.max_source_share.map(|max_share| Balance {
+            max_share,
+            by: self.balance_by,
+        })
+    }
+}
+
+#[derive(clap::Args, Debug, Clone)]
+struct SourceArgs {
+    /// Source to collect: a URL, GitHub URL or local path (repeatable); without it,
+    /// sources are prompted for when running in a terminal
+    #[arg(long = "source", value_name = "URL_OR_PATH", value_hint = ValueHint::AnyPath)]
+    sources: Vec<String>,
+
+    /// Type of the sources; give it once for all sources or once per --source
+    #[arg(
+        long = "source-type",
+        value_name = "TYPE",
+        value_parser = names::<SourceType>(SourceType::ALL.map(|kind| kind.name())),
+        ignore_case = true
+    )]
+    source_types: Vec<SourceType>,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+struct GenerateArgs {
+    #[command(flatten)]
+    model: ModelArgs,
+
+    /// Only regenerate questions for sections that changed since the last run
+    #[arg(long)]
+    incremental: bool,
+
+    /// Generate questions for every file, including files unchanged since the last run
+    #[arg(long)]
+    force: bool,
+
+    /// Size question targets by document structure (node types and heading levels)
+    #[arg(long)]
+    structured_budget: bool,
+
+    #[command(flatten)]
+    density: DensityArgs,
+
+    /// Show generation output live and abort responses that are clearly not valid JSON
+    #[arg(long)]
+    stream: bool,
+
+    /// Most tokens one model reply may have; bigger question targets are asked
+    /// for in batches that fit, so replies aren't cut off mid-JSON
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_MAX_OUTPUT_TOKENS as u64, value_parser = clap::value_parser!(u64).range(1..))]
+    max_output_tokens: u64,
+
+    /// Keep running after the first pass, generating questions for files added to or
+    /// changed in local sources and polling remote sources for new content (implies
+    /// --incremental)
+    #[arg(long)]
+    watch: bool,
+
+    /// Seconds between polls of remote sources in --watch mode
+    #[arg(long, value_name = "SECS", default_value_t = 300)]
+    watch_interval: u64,
+
+    /// Stop after processing this many input files
+    #[arg(long, value_name = "N")]
+    max_files: Option<usize>,
+
+    /// Stop once this many questions have been generated in this run
+    #[arg(long, value_name = "N")]
+    max_questions: Option<usize>,
+
+    /// Only generate questions from a random PERCENT of the sections (e.g. 10)
+    #[arg(long, value_name = "PERCENT", value_parser = parse_percent)]
+    sample: Option<f64>,
+
+    /// Seed choosing the --sample; the same seed picks the same sections
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    sample_seed: u64,
+
+    /// Hold out PERCENT of the sections (e.g. 10) as an evaluation set: their questions
+    /// get reference answers and grading rubrics and are written to eval.jsonl instead
+    /// of all_qa.jsonl
+    #[arg(long, value_name = "PERCENT", value_parser = parse_percent)]
+    eval: Option<f64>,
+
+    /// Generate N candidate answers per question (the first included) and keep the
+    /// best, as scored by --candidate-scoring; 1 turns rejection sampling off
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+    candidates: u64,
+
+    /// How candidate answers are scored: grounding in the section and length, or
+    /// an LLM judge using the generation model
+    #[arg(
+        long,
+        default_value_t = CandidateScoring::default(),
+        value_parser = names::<CandidateScoring>(CandidateScoring::ALL.map(|scoring| scoring.name())),
+        ignore_case = true
+    )]
+    candidate_scoring: CandidateScoring,
+
+    /// Keep the candidate answers that lost on each record as `rejected`, for
+    /// preference data (`export --format preference`)
+    #[arg(long)]
+    keep_rejected: bool,
+
+    #[command(flatten)]
+    balance: BalanceArgs,
+
+    /// Distill into this smaller model: it answers every generated question without
+    /// the section, and sections it gets wrong get more questions from --model
+    #[arg(long, value_name = "MODEL")]
+    student_model: Option<String>,
+
+    /// Student answers whose word overlap (F1) with the teacher's is below this
+    /// count as wrong
+    #[arg(long, value_name = "F1", default_value_t = DEFAULT_MIN_AGREEMENT, value_parser = parse_agreement)]
+    student_agreement: f64,
+
+    /// Files larger than this are handled by --oversized, e.g. 500KB or 20MB
+    #[arg(long, value_name = "SIZE", default_value = "10MB", value_parser = parse_file_size)]
+    max_file_size: u64,
+
+    /// What to do with files over --max-file-size: leave them out, split them into
+    /// sub-documents, or generate from a map-reduce summary
+    #[arg(
+        long,
+        default_value_t = OversizePolicy::default(),
+        value_parser = names::<OversizePolicy>(OversizePolicy::ALL.map(|policy| policy.name())),
+        ignore_case = true
+    )]
+    oversized: OversizePolicy,
+
+    /// Strip boilerplate (license headers, badge walls, "Edit this page" links and
+    /// blocks repeated across many documents) from collected files before generating
+    #[arg(long)]
+    strip_boilerplate: bool,
+
+    /// Extract a glossary of each file's domain terms first: it is written to
+    /// NAME_qa.glossary.json, adds a definition question per term, and keeps the
+    /// terms consistent in the generated answers
+    #[arg(long)]
+    glossary: bool,
+
+    /// Only generate from sections with a node tagged TAG: a keyword or entity found
+    /// in the text (with or without its keyword:/entity: prefix), or a parser tag such
+    /// as language:rust. Repeat for any of several tags
+    #[arg(long = "target-tag", value_name = "TAG")]
+    target_tags: Vec<String>,
+
+    /// Only generate from the sections under these headings, of any level and
+    /// ignoring case, e.g. "Installation,Configuration"
+    #[arg(long, value_name = "HEADINGS", value_delimiter = ',')]
+    only_sections: Vec<String>,
+
+    /// Only generate from sections with a node whose text matches this regular
+    /// expression, e.g. "(?i)error|fail"
+    #[arg(long, value_name = "REGEX", value_parser = Regex::new)]
+    only_matching: Option<Regex>,
+
+    /// Once generation finishes, generate questions for the sections of the processed
+    /// files that got none, asking for each section's share of its file's questions
+    #[arg(long, conflicts_with_all = ["target_tags", "only_sections", "only_matching"])]
+    top_up: bool,
+
+    /// Write metadata columns with every record: source, model, prompt_template,
+    /// created_at, lang, the tags of its source nodes and a grounding score
+    #[arg(long)]
+    rich_schema: bool,
+
+    /// POST a run summary (counts, duration, failures, output paths) to this webhook,
+    /// e.g. a Slack incoming webhook, when the run finishes or aborts
+    #[arg(long, value_name = "URL")]
+    notify_webhook: Option<String>,
+
+    /// Publish each accepted record to a Kafka topic or NATS subject as it is generated:
+    /// nats://[user:password@]host[:port]/subject, or kafka://host[:port]/topic for a Kafka
+    /// REST Proxy (kafka+https:// over HTTPS)
+    #[arg(long, value_name = "URL")]
+    publish: Option<String>,
+
+    /// Record the run as a named experiment in OUTPUT_DIR/experiments, with its
+    /// settings, dataset statistics and a snapshot of the dataset, for `compare`
+    #[arg(long, value_name = "NAME", value_parser = parse_run_name)]
+    run_name: Option<String>,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+struct PipelineArgs {
+    #[command(flatten)]
+    sources: SourceArgs,
+
+    #[command(flatten)]
+    generate: GenerateArgs,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Collect sources, then generate questions from them (the default without a subcommand)
+    Pipeline(PipelineArgs),
+
+    /// Download or copy sources into the output directory without generating anything
+    Collect(SourceArgs),
+
+    /// Generate questions for the markdown and text files in the output directory
+    Generate(GenerateArgs),
+
+    /// Strip boilerplate from the markdown and text files in the output directory,
+    /// as --strip-boilerplate does before generating
+    Clean {
+        /// Only report what would be removed
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Collect sources and serve their files to `work` instances on other machines,
+    /// merging the records they post back into one dataset
+    Coordinate {
+        /// Address workers connect to
+        #[arg(long, value_name = "ADDR", default_value = "0.0.0.0:7878")]
+        listen: SocketAddr,
+
+        /// Minutes a worker has to finish a claimed file before it is handed out again
+        #[arg(long, value_name = "MINUTES", default_value_t = DEFAULT_LEASE.as_secs() / 60, value_parser = clap::value_parser!(u64).range(1..))]
+        lease: u64,
+
+        #[command(flatten)]
+        sources: SourceArgs,
+
+        #[command(flatten)]
+        generate: GenerateArgs,
+    },
+
+    /// Generate questions for files claimed from a coordinator, with this machine's Ollama
+    Work {
+        /// URL of the coordinator, e.g. http://gpu-0:7878
+        #[arg(long, value_name = "URL")]
+        coordinator: String,
+
+        /// Name reported with the records [default: the host name]
+        #[arg(long)]
+        name: Option<String>,
+
+        #[command(flatten)]
+        generate: GenerateArgs,
+    },
+
+    /// Convert a generated dataset into a fine-tuning format
+    Export {
+        /// Dataset files to export [default: OUTPUT_DIR/all_qa.jsonl]
+        inputs: Vec<PathBuf>,
+
+        /// Output format
+        #[arg(
+            short = 'f',
+            long,
+            default_value_t = ExportFormat::default(),
+            value_parser = names::<ExportFormat>(ExportFormat::ALL.map(|format| format.name())),
+            ignore_case = true
+        )]
+        format: ExportFormat,
+
+        /// File to write [default: OUTPUT_DIR/export_FORMAT.jsonl]
+        #[arg(short = 'o', long)]
+        output: Option<PathBuf>,
+
+        /// Arrange records by difficulty for curriculum learning: one file per
+        /// difficulty (NAME.easy.jsonl, ...), or one file sorted from easy to hard
+        #[arg(
+            long,
+            value_parser = names::<Curriculum>(Curriculum::ALL.map(|curriculum| curriculum.name())),
+            ignore_case = true
+        )]
+        curriculum: Option<Curriculum>,
+    },
+
+    /// Read SQuAD, Alpaca or ShareGPT datasets into a dataset of records, to merge,
+    /// deduplicate, filter and export with generated ones
+    Import {
+        /// Dataset files to import
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+
+        /// Format of the files [default: told from each file's first record]
+        #[arg(
+            short = 'f',
+            long,
+            value_parser = names::<ImportFormat>(ImportFormat::ALL.map(|format| format.name())),
+            ignore_case = true
+        )]
+        format: Option<ImportFormat>,
+
+        /// File to write [default: OUTPUT_DIR/imported.jsonl]
+        #[arg(short = 'o', long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Print record counts, duplicates and lengths for generated datasets
+    Stats {
+        /// Dataset files to summarize [default: OUTPUT_DIR/all_qa.jsonl]
+        paths: Vec<PathBuf>,
+    },
+
+    /// Check datasets for malformed lines, schema errors, empty answers,
+    /// encoding problems and duplicates
+    Validate {
+        /// JSONL or Parquet dataset files to check [default: OUTPUT_DIR/all_qa.jsonl]
+        paths: Vec<PathBuf>,
+
+        /// Write the records that pass, cleaned up, to NAME.repaired.jsonl next to each file
+        #[arg(long)]
+        repair: bool,
+    },
+
+    /// Upgrade datasets written by older versions to the current schema, keeping their IDs
+    Migrate {
+        /// JSONL or JSON array dataset files to upgrade [default: OUTPUT_DIR/all_qa.jsonl]
+        paths: Vec<PathBuf>,
+
+        /// Replace each file instead of writing NAME.migrated.jsonl next to it
+        #[arg(long)]
+        in_place: bool,
+    },
+
+    /// Score records with an LLM judge, writing per-record scores and a quality report
+    Evaluate {
+        /// Dataset files to score [default: OUTPUT_DIR/all_qa.jsonl]
+        paths: Vec<PathBuf>,
+
+        /// Model judging the records [default: the generation model]
+        #[arg(long)]
+        judge_model: Option<String>,
+
+        /// Only score the first N records of each file
+        #[arg(short = 'n', long)]
+        limit: Option<usize>,
+
+        #[command(flatten)]
+        services: ServiceArgs,
+    },
+
+    /// Find records whose questions overlap benchmark questions (MMLU, GSM8K, ...),
+    /// by shared n-grams and optionally by embedding similarity
+    Contamination {
+        /// Dataset files to check [default: OUTPUT_DIR/all_qa.jsonl]
+        paths: Vec<PathBuf>,
+
+        /// Benchmark JSONL file; each line's question is read from its `question`,
+        /// `prompt`, `problem`, `input`, `instruction` or `text` field (repeatable)
+        #[arg(short = 'b', long = "benchmark", required = true, value_hint = ValueHint::FilePath)]
+        benchmarks: Vec<PathBuf>,
+
+        /// Words per n-gram; sharing one with a benchmark question is an overlap
+        #[arg(long, value_name = "N", default_value_t = DEFAULT_NGRAM)]
+        ngram: usize,
+
+        /// Also compare question embeddings from the configured embedding model
+        #[arg(long)]
+        embeddings: bool,
+
+        /// Cosine similarity from which embeddings overlap
+        #[arg(long, default_value_t = DEFAULT_SIMILARITY, requires = "embeddings")]
+        similarity: f32,
+
+        /// Write each dataset without its overlapping records to NAME.decontaminated.jsonl
+        #[arg(long)]
+        remove: bool,
+
+        #[command(flatten)]
+        services: ServiceArgs,
+    },
+
+    /// Add paraphrased questions by translating them to pivot languages and back,
+    /// or noisy ones as users type them, writing each dataset with its variants to
+    /// NAME.augmented.jsonl
+    Augment {
+        /// Dataset files to augment [default: OUTPUT_DIR/all_qa.jsonl]
+        paths: Vec<PathBuf>,
+
+        /// Language to translate through, by name or ISO 639-1 code (repeatable)
+        #[arg(
+            long = "pivot",
+            value_name = "LANGUAGE",
+            required_unless_present = "noise"
+        )]
+        pivots: Vec<String>,
+
+        /// Cosine similarity to the original question a paraphrase needs to be kept
+        #[arg(long, default_value_t = DEFAULT_MIN_SIMILARITY)]
+        min_similarity: f32,
+
+        /// Noisy variants to add per record, with typos, missing punctuation or
+        /// casual phrasing
+        #[arg(long, value_name = "N")]
+        noise: Option<usize>,
+
+        /// Only augment the first N records of each file
+        #[arg(short = 'n', long)]
+        limit: Option<usize>,
+
+        #[command(flatten)]
+        services: ServiceArgs,
+    },
+
+    /// Split datasets into train and test sets by semantic cluster, so no near-duplicate
+    /// questions end up on both sides; writes NAME.train.jsonl and NAME.test.jsonl
+    Split {
+        /// Dataset files to split [default: OUTPUT_DIR/all_qa.jsonl]
+        paths: Vec<PathBuf>,
+
+        /// Percentage of records to put in the test set
+        #[arg(long, value_name = "PERCENT", default_value_t = DEFAULT_TEST_PERCENT)]
+        test_percent: f64,
+
+        /// Cosine similarity of question embeddings from which records share a cluster
+        #[arg(long, default_value_t = DEFAULT_CLUSTER_SIMILARITY)]
+        similarity: f32,
+
+        #[command(flatten)]
+        services: ServiceArgs,
+    },
+
+    /// Compare two runs recorded with --run-name: their settings, record counts,
+    /// quality scores (once `evaluate` has scored their snapshots) and shared questions
+    Compare {
+        /// Name of the first run
+        #[arg(value_parser = parse_run_name)]
+        a: String,
+
+        /// Name of the second run
+        #[arg(value_parser = parse_run_name)]
+        b: String,
+    },
+
+    /// Report which sections of the files in the output directory datasets have no
+    /// questions from, and which have far more than their share
+    Coverage {
+        /// Dataset files to analyze together [default: OUTPUT_DIR/all_qa.jsonl]
+        paths: Vec<PathBuf>,
+
+        #[command(flatten)]
+        density: DensityArgs,
+    },
+
+    /// Write a copy of datasets in which no source document makes up more than
+    /// --max-source-share, as NAME.balanced.jsonl
+    Balance {
+        /// Dataset files to balance [default: OUTPUT_DIR/all_qa.jsonl]
+        paths: Vec<PathBuf>,
+
+        #[command(flatten)]
+        balance: BalanceArgs,
+    },
+
+    /// Step through records in the terminal, accepting, editing or rejecting
+    /// each with a keystroke; decisions are saved as the records' review_status
+    Review {
+        /// Dataset files to review [default: OUTPUT_DIR/all_qa.jsonl]
+        paths: Vec<PathBuf>,
+    },
+
+    /// Send records to a Label Studio project or Argilla dataset for human
+    /// review, or pull the annotators' verdicts back into the dataset
+    Annotate {
+        #[command(subcommand)]
+        action: AnnotateAction,
+    },
+
+    /// Trace a record back to the graph nodes, document and source it was
+    /// generated from, printed as W3C PROV-JSON
+    Lineage {
+        /// ID of the record
+        id: Uuid,
+
+        /// Dataset files to look for the record in [default: OUTPUT_DIR/all_qa.jsonl]
+        paths: Vec<PathBuf>,
+    },
+
+    /// Parse markdown and inspect the resulting document graph
+    Graph {
+        /// Markdown file, or a directory to parse into one corpus graph
+        path: PathBuf,
+
+        /// Print the graph in Graphviz DOT format
+        #[arg(long)]
+        dot: bool,
+
+        /// Print the graph's nodes and edges as JSON
+        #[arg(long, conflicts_with = "dot")]
+        json: bool,
+
+        /// Only inspect the section with this heading (and its contents)
+        #[arg(long)]
+        section: Option<String>,
+
+        /// Tag the nodes with the keywords and entities in their text and list
+        /// the most common tags
+        #[arg(long)]
+        keywords: bool,
+
+        /// Only inspect nodes with this tag (and their contents); a keyword or
+        /// entity may be given without its prefix. Repeat for any of several tags
+        #[arg(long = "tag", value_name = "TAG")]
+        tags: Vec<String>,
+    },
+
+    /// Embed the content of markdown files into the vector store
+    Index {
+        /// Markdown file, or a directory to index as one corpus
+        path: PathBuf,
+
+        /// Drop and recreate the collection before indexing
+        #[arg(long)]
+        recreate: bool,
+
+        #[command(flatten)]
+        services: ServiceArgs,
+    },
+
+    /// Search indexed content for passages similar to a query
+    Search {
+        /// Natural language query, e.g. "how do I configure TLS"
+        query: String,
+
+        /// Number of results to show
+        #[arg(short = 'n', long, default_value_t = 5)]
+        limit: u64,
+
+        /// Only search nodes of this document (title or path)
+        #[arg(long)]
+        document: Option<String>,
+
+        /// Only search nodes of this type, e.g. CodeBlock
+        #[arg(long)]
+        node_type: Option<String>,
+
+        /// Only search nodes carrying this tag
+        #[arg(long)]
+        tag: Option<String>,
+
+        #[command(flatten)]
+        services: ServiceArgs,
+    },
+
+    /// Generate question/context/answer records grounded in retrieved passages
+    Rag {
+        /// Markdown file, or a directory to use as the retrieval corpus
+        path: PathBuf,
+
+        /// Number of passages retrieved as context for each question
+        #[arg(short = 'k', long, default_value_t = 3)]
+        top_k: u64,
+
+        /// Minimum words for a passage to generate questions from
+        #[arg(long, default_value_t = 20)]
+        min_words: usize,
+
+        /// Also write a retrieval record per question to rag_negatives.jsonl, with its
+        /// passage and up to N hard-negative passages from the index
+        #[arg(long, value_name = "N")]
+        negatives: Option<usize>,
+
+        #[command(flatten)]
+        density: DensityArgs,
+
+        #[command(flatten)]
+        model: ModelArgs,
+    },
+
+    /// Dump the vector collection (IDs, vectors and payloads) to a file
+    Snapshot {
+        /// Snapshot file to write
+        path: PathBuf,
+
+        #[command(flatten)]
+        services: ServiceArgs,
+    },
+
+    /// Load a snapshot file into the vector collection
+    Restore {
+        /// Snapshot file written by the snapshot command
+        path: PathBuf,
+
+        /// Drop and recreate the collection before restoring
+        #[arg(long)]
+        recreate: bool,
+
+        #[command(flatten)]
+        services: ServiceArgs,
+    },
+
+    /// Measure throughput, latency and failures of the generation and embedding backends
+    Benchmark {
+        /// Requests sent at each concurrency level
+        #[arg(short = 'n', long, default_value_t = 12)]
+        requests: usize,
+
+        /// Concurrency levels to try, e.g. 1,2,4
+        #[arg(short = 'c', long, value_delimiter = ',', default_value = "1,4")]
+        concurrency: Vec<usize>,
+
+        /// Only benchmark the generation model
+        #[arg(long, conflicts_with = "skip_generation")]
+        skip_embeddings: bool,
+
+        /// Only benchmark the embedding model
+        #[arg(long)]
+        skip_generation: bool,
+
+        #[command(flatten)]
+        model: ModelArgs,
+    },
+
+    /// Write a default config file or show the settings in effect
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+
+    /// Print a shell completion script covering all subcommands, formats and profiles
+    Completions {
+        /// Shell to complete for
+        shell: Shell,
+    },
+}
+
+impl Command {
+    /// Generation options of the commands that generate questions
+    fn generate_args(&self) -> Option<&GenerateArgs> {
+        match self {
+            Command::Pipeline(pipeline) => Some(&pipeline.generate),
+            Command::Generate(generate)
+            | Command::Coordinate { generate, .. }
+            | Command::Work { generate, .. } => Some(generate),
+            _ => None,
         }
+    }
 
-        // Check if it's a GitHub URL
-        if input.starts_with("https://github.com/")
-            && (input.contains("/tree/") || input.contains("/blob/"))
-        {
-            println!("Processing GitHub source: {}", input);
-            sources.push(Box::new(GitHubSource::new(input, None, None)) as Box<dyn DataSource>);
-            println!("Successfully added GitHub source: {}", input);
-            continue;
+    /// Model options of the commands that generate with the Ollama model
+    fn model_args(&self) -> Option<&ModelArgs> {
+        match self {
+            Command::Rag { model, .. } | Command::Benchmark { model, .. } => Some(model),
+            _ => self.generate_args().map(|generate| &generate.model),
         }
+    }
 
-        // Check if it's a regular URL
-        if input.starts_with("http://") || input.starts_with("https://") {
-            println!("Processing URL source: {}", input);
-            match UrlSource::new(input) {
-                Ok(source) => {
-                    sources.push(Box::new(source) as Box<dyn DataSource>);
-                    println!("Successfully added URL source: {}", input);
-                }
-                Err(e) => println!("Error adding URL source: {}", e),
-            }
-            continue;
+    /// Service check options of the commands that use external services
+    fn service_args(&self) -> Option<&ServiceArgs> {
+        match self {
+            Command::Evaluate { services, .. }
+            | Command::Contamination { services, .. }
+            | Command::Augment { services, .. }
+            | Command::Split { services, .. }
+            | Command::Index { services, .. }
+            | Command::Search { services, .. }
+            | Command::Snapshot { services, .. }
+            | Command::Restore { services, .. } => Some(services),
+            _ => self.model_args().map(|model| &model.services),
         }
+    }
+}
 
-        // Assume it's a local path if it doesn't match the above
-        if Path::new(input).exists() {
-            println!("Processing local source: {}", input);
-            sources.push(Box::new(LocalSource::new(input)) as Box<dyn DataSource>);
-            println!("Successfully added local source: {}", input);
-        } else {
-            println!("Invalid input. Please enter:");
-            println!("- A GitHub URL (https://github.com/user/repo/tree/branch/path)");
-            println!("- A GitHub releases URL (https://github.com/user/repo/releases)");
-            println!("- A regular URL (http:// or https://)");
-            println!("- A valid local file or directory path");
+/// Refuse the options of the default pipeline run when they come before a
+/// subcommand, which would otherwise ignore them
+fn check_pipeline_args(matches: &ArgMatches) -> Result<(), clap::Error> {
+    let Some(subcommand) = matches.subcommand_name() else {
+        return Ok(());
+    };
+    let mut command = Args::command();
+    let given = command
+        .get_arguments()
+        .find(|arg| {
+            !arg.is_global_set()
+                && matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine)
+        })
+        .and_then(|arg| arg.get_long())
+        .map(str::to_string);
+    match given {
+        Some(long) => {
+            let message = format!(
+                "the argument '--{}' cannot be used before '{}'",
+                long, subcommand
+            );
+            Err(command.error(ErrorKind::ArgumentConflict, message))
         }
+        None => Ok(()),
     }
+}
 
-    Ok(sources)
+/// Name of an experiment, which names its directory
+fn parse_run_name(value: &str) -> Result<String, String> {
+    experiments::check_name(value).map(|_| value.to_string())
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Load environment variables from .env file
-    dotenv().ok();
+/// Percentage in (0, 100]
+fn parse_percent(value: &str) -> Result<f64, String> {
+    match value.trim_end_matches('%').parse::<f64>() {
+        Ok(percent) if percent > 0.0 && percent <= 100.0 => Ok(percent),
+        _ => Err(format!("'{}' is not a percentage between 0 and 100", value)),
+    }
+}
 
-    let args = Args::parse();
+/// Share of overlapping words between 0 and 1
+fn parse_agreement(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(agreement) if (0.0..=1.0).contains(&agreement) => Ok(agreement),
+        _ => Err(format!("'{}' is not a number between 0 and 1", value)),
+    }
+}
 
-    // Use command line args if provided, otherwise fall back to env vars, then defaults
-    let output_dir = args
-        .output_dir
-        .or_else(|| env::var("OUTPUT_DIR").ok())
-        .unwrap_or_else(|| "output".to_string());
+/// Size in bytes, with an optional KB, MB or GB unit
+fn parse_file_size(value: &str) -> Result<u64, String> {
+    match parse_size(value) {
+        Ok(0) => Err("the size limit must be above 0".to_string()),
+        Ok(bytes) => Ok(bytes),
+        Err(e) => Err(e.to_string()),
+    }
+}
 
-    let ollama_endpoint = args
-        .ollama_endpoint
-        .or_else(|| env::var("OLLAMA_ENDPOINT").ok())
-        .unwrap_or_else(|| "http://localhost:11434".to_string());
+/// Parser accepting one of `names`, listed in help and completions, and
+/// converting it with `FromStr`
+fn names<T>(names: impl IntoIterator<Item = &'static str>) -> impl TypedValueParser<Value = T>
+where
+    T: FromStr<Err = anyhow::Error> + Clone + Send + Sync + 'static,
+{
+    PossibleValuesParser::new(names).try_map(|name| name.parse::<T>())
+}
 
-    let model = args
-        .model
-        .or_else(|| env::var("OLLAMA_MODEL").ok())
-        .unwrap_or_else(|| "m/qwen2514bmax".to_string());
+#[derive(Subcommand, Debug)]
+enum AnnotateAction {
+    /// Send the records not reviewed yet as tasks
+    Push {
+        #[command(flatten)]
+        target: AnnotationTarget,
 
-    // Create output directory if it doesn't exist
-    fs::create_dir_all(&output_dir)?;
+        /// Dataset files to send [default: OUTPUT_DIR/all_qa.jsonl]
+        paths: Vec<PathBuf>,
+    },
 
-    // Initialize processor
-    let processor = DefaultOllamaProcessor::new_with_client(
-        ollama_endpoint.clone(),
-        model.clone(),
-        Box::new(DefaultOllamaClient::new(ollama_endpoint, model)),
-        Some(PathBuf::from(&output_dir)),
-    );
+    /// Save submitted verdicts as the records' review_status, applying corrections
+    Pull {
+        #[command(flatten)]
+        target: AnnotationTarget,
 
-    // Collect data sources
-    let sources = if args.test_mode {
-        Vec::new()
-    } else {
-        collect_sources().await?
-    };
-
-    // Process each source
-    let mut all_items = Vec::new();
-
-    // If no sources added, check existing files
-    if sources.is_empty() {
-        println!("No new sources added. Processing existing files in output directory...");
-        let mut existing_files = Vec::new();
-        for entry in WalkDir::new(Path::new(&output_dir))
-            .into_iter()
-            .filter_map(|e| e.ok())
-            .filter(|e| e.file_type().is_file())
-            .filter(|e| {
-                e.path()
-                    .extension()
-                    .and_then(|ext| ext.to_str())
-                    .map(|ext| ext == "md" || ext == "txt")
-                    .unwrap_or(false)
-            })
-        {
-            existing_files.push(entry.path().to_path_buf());
-        }
-
-        if existing_files.is_empty() {
-            println!("No markdown or text files found in output directory to process.");
-            return Ok(());
-        }
+        /// Dataset files to update [default: OUTPUT_DIR/all_qa.jsonl]
+        paths: Vec<PathBuf>,
+    },
+}
 
-        println!(
-            "Found {} markdown/text files to process.",
-            existing_files.len()
-        );
-        for file_path in existing_files {
-            println!("Processing file: {:?}", file_path);
-            match processor.process_file(&file_path).await {
-                Ok(items) => {
-                    all_items.extend(items);
-                }
-                Err(e) => {
-                    eprintln!("Error processing file {:?}: {}", file_path, e);
-                }
-            }
-        }
+#[derive(clap::Args, Debug)]
+struct AnnotationTarget {
+    /// Annotation tool
+    #[arg(
+        long,
+        value_parser = names::<AnnotationTool>(AnnotationTool::ALL.map(|tool| tool.name())),
+        ignore_case = true
+    )]
+    tool: AnnotationTool,
+
+    /// Label Studio project ID or Argilla dataset ID
+    #[arg(long)]
+    project: String,
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigAction {
+    /// Write a config file listing every setting, commented out at its default
+    Init {
+        /// Where to write the file
+        #[arg(default_value = DEFAULT_CONFIG_FILE)]
+        path: PathBuf,
+
+        /// Overwrite an existing file
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Print each setting's effective value and where it comes from, secrets masked
+    Show,
+}
+
+fn parse_graph(path: &Path) -> anyhow::Result<DocumentGraph> {
+    if path.is_dir() {
+        parse_markdown_dir(path)
     } else {
-        // Process new sources
-        for source in sources {
-            println!("\nProcessing source...");
+        parse_markdown_file(path)
+    }
+}
 
-            // Collect files from source
-            let files = source.collect(Path::new(&output_dir)).await?;
-            println!("Found {} files", files.len());
+/// Startup checks requested on the command line
+#[derive(Debug, Clone, Copy)]
+struct Startup {
+    /// Check the external services before doing any work
+    preflight: bool,
+    /// Pull the generation model if the server doesn't have it
+    pull_model: bool,
+}
 
-            for file_path in files {
-                println!("Processing file: {:?}", file_path);
-                match processor.process_file(&file_path).await {
-                    Ok(items) => {
-                        all_items.extend(items);
-                    }
-                    Err(e) => {
-                        eprintln!("Error processing file {:?}: {}", file_path, e);
-                    }
-                }
-            }
+impl Startup {
+    async fn check(&self, targets: PreflightTargets<'_>) -> anyhow::Result<()> {
+        if self.preflight {
+            preflight(targets).await?;
         }
+        Ok(())
     }
+}
 
-    // Save combined results
-    let output_file = Path::new(&output_dir).join("all_qa.jsonl");
-    let mut output = String::new();
-    for item in &all_items {
-        if let Ok(json_line) = serde_json::to_string(item) {
-            output.push_str(&json_line);
-            output.push('\n');
-        }
+/// Open the configured collection after checking that the embedding model's
+/// dimension matches it, so a mismatch fails here rather than on the first upsert
+async fn open_vector_store(
+    config: &mut Config,
+    engine: &EmbeddingEngine,
+) -> anyhow::Result<VectorStore> {
+    let dimension = engine.dimension().await?;
+    config.vector_db.resolve_vector_size(dimension)?;
+    VectorStore::new(config.vector_db.clone(), &config.vector_db.collection_name).await
+}
+
+async fn run_index_command(path: &Path, recreate: bool, startup: Startup) -> anyhow::Result<()> {
+    let mut config = load_config()?;
+    config.vector_db.recreate |= recreate;
+    let engine = EmbeddingEngine::new(config.embedding.clone()).await?;
+    startup
+        .check(PreflightTargets {
+            embedding: Some((&engine, &config.embedding)),
+            vector_db: Some(&config.vector_db),
+            ..Default::default()
+        })
+        .await?;
+    let mut graph = parse_graph(path)?;
+    let store = open_vector_store(&mut config, &engine).await?;
+
+    let indexed = GraphIndexer::new(&engine, &store).index(&mut graph).await?;
+    println!(
+        "Indexed {} nodes from {:?} into collection '{}'",
+        indexed, path, config.vector_db.collection_name
+    );
+    Ok(())
+}
+
+async fn run_search_command(
+    query: &str,
+    limit: u64,
+    filter: &SearchFilter,
+    startup: Startup,
+) -> anyhow::Result<()> {
+    let mut config = load_config()?;
+    // Searching must never drop the collection it is about to query
+    config.vector_db.recreate = false;
+    let engine = EmbeddingEngine::new(config.embedding.clone()).await?;
+    startup
+        .check(PreflightTargets {
+            embedding: Some((&engine, &config.embedding)),
+            vector_db: Some(&config.vector_db),
+            ..Default::default()
+        })
+        .await?;
+    let store = open_vector_store(&mut config, &engine).await?;
+
+    let embedding = engine.generate_embeddings(query).await?;
+    let hits = store.search_hits(&embedding, limit, filter).await?;
+    if hits.is_empty() {
+        println!("No matches found.");
+        return Ok(());
+    }
+
+    for (rank, hit) in hits.iter().enumerate() {
+        let field = |key: &str| hit.payload.get(key).map(String::as_str).unwrap_or_default();
+        let document = match field("document_path") {
+            "" => field("document"),
+            path => path,
+        };
+        let snippet: String = field("content")
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .chars()
+            .take(SEARCH_SNIPPET_CHARS)
+            .collect();
+
+        println!(
+            "{}. [{:.3}] {} > {}",
+            rank + 1,
+            hit.score,
+            document,
+            field("heading")
+        );
+        println!("   {}", snippet);
     }
-    fs::write(&output_file, output)?;
+    Ok(())
+}
+
+async fn run_snapshot_command(path: &Path, startup: Startup) -> anyhow::Result<()> {
+    let mut config = load_config()?;
+    // Snapshotting reads the collection; it must never drop it
+    config.vector_db.recreate = false;
+    startup
+        .check(PreflightTargets {
+            vector_db: Some(&config.vector_db),
+            ..Default::default()
+        })
+        .await?;
+    let store =
+        VectorStore::new(config.vector_db.clone(), &config.vector_db.collection_name).await?;
+
+    let written = store.snapshot(path).await?;
     println!(
-        "Saved {} question-answer pairs to {:?}",
-        all_items.len(),
-        output_file
+        "Wrote {} points from collection '{}' to {:?}",
+        written, config.vector_db.collection_name, path
     );
-    println!("Individual file results saved as [filename]_qa.jsonl in the output directory");
+    Ok(())
+}
+
+async fn run_restore_command(path: &Path, recreate: bool, startup: Startup) -> anyhow::Result<()> {
+    let mut config = load_config()?;
+    config.vector_db.recreate |= recreate;
+    startup
+        .check(PreflightTargets {
+            vector_db: Some(&config.vector_db),
+            ..Default::default()
+        })
+        .await?;
+
+    // The snapshot decides the collection's vector size, not the embedding model
+    let header = SnapshotHeader::read(path)?;
+    if header.vector_size > 0 {
+        config.vector_db.resolve_vector_size(header.vector_size)?;
+    }
+    let store =
+        VectorStore::new(config.vector_db.clone(), &config.vector_db.collection_name).await?;
 
+    let restored = store.restore(path).await?;
+    println!(
+        "Restored {} points from {:?} into collection '{}'",
+        restored, path, config.vector_db.collection_name
+    );
     Ok(())
 }
+
+async fn run_rag_command(
+    path: &Path,
+    rag_config: RagConfig,
+    output_dir: &str,
+    client: &DefaultOllamaClient,
+    model: &str,
+    startup: Startup,
+) -> anyhow::Result<()> {
+    let mut config = load_config()?;
+    let engine = EmbeddingEngine::new(config.embedding.clone()).await?;
+    startup
+        .check(PreflightTargets {
+            llm: Some((client.provider().as_ref(), startup.pull_model)),
+            embedding: Some((&engine, &config.embedding)),
+            vector_db: Some(&config.vector_db),
+        })
+        .await?;
+    if startup.pull_model {
+        client.provider().ensure_model().await?;
+    }
+    fs::create_dir_all(output_dir)?;
+
+    let mut manifest = RunManifest::new(serde_json::json!({
+        "mode": "rag",
+        "output_dir": output_dir,
+        "model": model,
+        "top_k": rag_config.top_k,
+        "min_words": rag_config.min_words,
+        "question_density": rag_config.density.to_string(),
+        "negatives": rag_config.negatives,
+    }));
+    manifest.add_model("generation", model);
+    manifest.add_model("embedding", &config.embedding.model);
+    for (name, template) in prompt_templates() {
+        manifest.add_prompt_template(name, template);
+    }
+    manifest.add_source(LocalSource::new(path).provenance().await);
+
+    // Retrieval runs against the corpus itself, so index it first
+    let mut graph = parse_graph(path)?;
+    let store = open_vector_store(&mut config, &engine).await?;
+    let indexed = GraphIndexer::new(&engine, &store).index(&mut graph).await?;
+    println!("Indexed {} nodes for retrieval", indexed);
+
+    let negatives = rag_config.negatives;
+    let (items, retrieval) = RagGenerator::new(client, &engine, &store, rag_config)
+        .generate_with_negatives(&graph)
+        .await?;
+
+    let output_path = Path::new(output_dir).join(RAG_OUTPUT_FILE);
+    let written = schema::write_dataset(&output_path, &items)?;
+    println!("Saved {} RAG records to {:?}", written, output_path);
+    manifest.add_output(&output_path);
+
+    if negatives > 0 {
+        let negatives_path = Path::new(output_dir).join(RAG_NEGATIVES_FILE);
+        let mut file = std::io::BufWriter::new(fs::File::create(&negatives_path)?);
+        for record in &retrieval {
+            writeln!(file, "{}", serde_json::to_string(record)?)?;
+        }
+        file.flush()?;
+        println!(
+            "Saved {} retrieval records with hard negatives to {:?}",
+            retrieval.len(),
+            negatives_path
+        );
+        manifest.add_output(&negatives_path);
+    }
+
+    manifest.add_usage("generation", client.provider().usage());
+    manifest.finish(written);
+    manifest.write(Path::new(output_dir))?;
+    Ok(())
+}
+
+/// What the `graph` command shows
+struct GraphView<'a> {
+    dot: bool,
+    json: bool,
+    /// Only the section with this heading
+    section: Option<&'a str>,
+    /// Tag keywords and entities and list the most common tags
+    keywords: bool,
+    /// Only nodes with any of these tags, and their contents (--tag)
+    tags: &'a [String],
+}
+
+/// Most common tags listed by `graph --keywords`
+const TOP_TAGS: usize = 20;
+
+fn run_graph_command(path: &Path, view: GraphView) -> anyhow::Result<()> {
+    let GraphView { dot, json, .. } = view;
+    let mut graph = parse_graph(path)?;
+
+    if let Some(heading) = view.section {
+        let section_id = graph
+            .find_by_heading(heading)
+            .first()
+            .map(|node| node.id)
+            .ok_or_else(|| anyhow::anyhow!("No section with heading {:?}", heading))?;
+        graph = graph.subgraph(&section_id)?;
+    }
+    if view.keywords || !view.tags.is_empty() {
+        tag_nodes(&mut graph, &TaggingConfig::default());
+    }
+    if !view.tags.is_empty() {
+        let tagged = graph.query().tagged(view.tags).with_descendants();
+        if tagged.is_empty() {
+            anyhow::bail!("No nodes tagged {}", view.tags.join(" or "));
+        }
+        graph = tagged.to_graph();
+    }
+
+    if dot {
+        print!("{}", graph.to_dot());
+        return Ok(());
+    }
+    if json {
+        println!("{}", graph.to_json());
+        return Ok(());
+    }
+
+    println!(
+        "Parsed {:?}: {} nodes, {} edges",
+        path,
+        graph.node_count(),
+        graph.edge_count()
+    );
+    let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+    for node in graph.nodes() {
+        *counts.entry(format!("{:?}", node.node_type)).or_default() += 1;
+    }
+    for (node_type, count) in counts {
+        println!("  {}: {}", node_type, count);
+    }
+    if view.keywords {
+        let mut tags: HashMap<&str, usize> = HashMap::new();
+        for tag in graph.nodes().flat_map(|node| &node.metadata.tags) {
+            *tags.entry(tag).or_default() += 1;
+        }
+        let mut tags: Vec<(&str, usize)> = tags.into_iter().collect();
+        tags.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        println!("Most common tags:");
+        for (tag, count) in tags.into_iter().take(TOP_TAGS) {
+            println!("  {}: {} nodes", tag, count);
+        }
+    }
+    Ok(())
+}
+
+async fn collect_sources() -> anyhow::Result<Vec<Box<dyn DataSource>>> {
+    let mut sources: Vec<Box<dyn DataSource>> = Vec::new();
+    let mut buffer = String::new();
+
+    loop {
+        println!("\nEnter a data source (press Enter to finish):");
+        println!("- URL (e.g., https://example.com/file.txt)");
+        println!("- Local path (e.g., /path/to/file)");
+        println!("- GitHub URL (e.g., https://github.com/user/repo/tree/branch/path)");
+        println!("- GitHub releases URL (e.g., https://github.com/user/repo/releases)");
+        print!("> ");
+        std::io::stdout().flush()?;
+
+        buffer.clear();
+        std::io::stdin().read_line(&mut buffer)?;
+        let input = buffer.trim();
+
+        if input.is_empty() {
+            break;
+        }
+
+        match SourceType::detect(input).and_then(|kind| Ok((kind, open_source(input, kind)?))) {
+            Ok((kind, source)) => {
+                sources.push(source);
+                println!("Successfully added {} source: {}", kind, input);
+            }
+            Err(e) => {
+                println!("Error adding source: {}", e);
+                println!("Please enter:");
+                println!("- A GitHub URL (https://github.com/user/repo/tree/branch/path)");
+                println!("- A GitHub releases URL (https://github.com/user/repo/releases)");
+                println!("- A regular URL (http:// or https://)");
+                println!("- A valid local file or directory path");
+            }
+        }
+    }
+
+    Ok(sources)
+}
+
+/// Config file that was applied to the environment
+struct LoadedConfigFile {
+    path: PathBuf,
+    profile: Option<String>,
+    /// Variables the file set, i.e. that weren't already in the environment
+    applied: Vec<String>,
+}
+
+/// Export the config file's settings (and the selected profile) to the
+/// environment, below variables that are already set
+fn load_config_file(
+    path: Option<&Path>,
+    profile: Option<&str>,
+) -> anyhow::Result<Option<LoadedConfigFile>> {
+    let default_path = Path::new(DEFAULT_CONFIG_FILE);
+    let path = match path {
+        Some(path) => path,
+        None if default_path.exists() => default_path,
+        None if profile.is_some() => anyhow::bail!(
+            "--profile needs a config file; create {} or pass --config",
+            DEFAULT_CONFIG_FILE
+        ),
+        None => return Ok(None),
+    };
+
+    let file = ConfigFile::load(path)?;
+    let applied = file.apply_to_env(profile)?;
+    let profile = file.selected_profile(profile).map(str::to_string);
+    if let Some(profile) = &profile {
+        println!("Using profile '{}' from {}", profile, path.display());
+    }
+    Ok(Some(LoadedConfigFile {
+        path: path.to_path_buf(),
+        profile,
+        applied,
+    }))
+}
+
+fn run_config_command(
+    action: &ConfigAction,
+    cli: &[(&str, String)],
+    loaded: Option<&LoadedConfigFile>,
+) -> anyhow::Result<()> {
+    match action {
+        ConfigAction::Init { path, force } => {
+            if path.exists() && !force {
+                anyhow::bail!(
+                    "{} already exists; pass --force to overwrite it",
+                    path.display()
+                );
+            }
+            fs::write(path, config_template())?;
+            println!("Wrote {}", path.display());
+        }
+        ConfigAction::Show => {
+            match loaded {
+                Some(loaded) => println!(
+                    "Config file: {}{}",
+                    loaded.path.display(),
+                    loaded
+                        .profile
+                        .as_ref()
+                        .map(|profile| format!(" (profile '{}')", profile))
+                        .unwrap_or_default()
+                ),
+                None => println!("Config file: none"),
+            }
+            let from_file = loaded.map(|l| l.applied.as_slice()).unwrap_or_default();
+            for setting in effective_settings(cli, from_file) {
+                println!(
+                    "{:<28} {:<32} ({})",
+                    setting.name,
+                    setting.value.as_deref().unwrap_or("(unset)"),
+                    setting.source
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Print a completion script for `shell`, offering the profiles of the
+/// config file (if any) for --profile
+fn run_completions_command(shell: Shell, loaded: Option<&LoadedConfigFile>) -> anyhow::Result<()> {
+    let mut command = Args::command();
+    if let Some(loaded) = loaded {
+        let profiles: Vec<String> = ConfigFile::load(&loaded.path)?
+            .profile_names()
+            .map(str::to_string)
+            .collect();
+        if !profiles.is_empty() {
+            command = command.mut_arg("profile", |arg| {
+                arg.value_parser(PossibleValuesParser::new(profiles))
+            });
+        }
+    }
+    let name = command.get_name().to_string();
+    clap_complete::generate(shell, &mut command, name, &mut std::io::stdout());
+    Ok(())
+}
+
+/// Question generation settings shared by `generate` and `pipeline`
+struct Generation {
+    output_dir: String,
+    /// Ollama, or the in-process mock (LLM_BACKEND)
+    backend: LLMBackend,
+    ollama_endpoint: String,
+    model: String,
+    keep_alive: String,
+    incremental: bool,
+    force: bool,
+    structured_budget: bool,
+    /// Questions each file is worth (--question-density)
+    density: QuestionDensity,
+    stream: bool,
+    max_output_tokens: usize,
+    /// Stops generation on Ctrl-C or SIGTERM
+    shutdown: Shutdown,
+    /// --max-files, --max-questions and --sample
+    limits: RunLimits,
+    /// --max-file-size and --oversized
+    size_limit: SizeLimit,
+    /// Sections held out for the evaluation set (--eval)
+    eval_split: Option<EvalSplit>,
+    /// --candidates, --candidate-scoring and --keep-rejected
+    rejection: Option<RejectionSampling>,
+    /// Answers below this agreement with the teacher's fail the student
+    student_agreement: f64,
+    /// --max-source-share and --balance-by
+    balance: Option<Balance>,
+    /// Strip boilerplate from the inputs before generating
+    strip_boilerplate: bool,
+    /// Extract a glossary per file and generate with it
+    glossary: bool,
+    /// Only generate from sections with nodes carrying these tags (--target-tag)
+    target_tags: Vec<String>,
+    /// Only generate from the sections under these headings (--only-sections)
+    only_sections: Vec<String>,
+    /// Only generate from sections with a node matching this (--only-matching)
+    only_matching: Option<Regex>,
+    /// Generate for the sections left without questions once the run finishes
+    top_up: bool,
+    /// Write the metadata columns of every record
+    rich_schema: bool,
+    /// Topic or subject accepted records are published to (--publish)
+    publish: Option<StreamTarget>,
+    /// Experiment the run is recorded as (--run-name)
+    run_name: Option<String>,
+}
+
+/// Record the run in the experiments registry when it was given a --run-name
+fn record_experiment(
+    generation: &Generation,
+    manifest: &RunManifest,
+    dataset: &Path,
+) -> anyhow::Result<()> {
+    let Some(name) = &generation.run_name else {
+        return Ok(());
+    };
+    let registry = Registry::new(Path::new(&generation.output_dir));
+    let experiment = registry.record(name, manifest, dataset)?;
+    println!(
+        "Recorded experiment '{}' with {} records; compare runs with `compare {} OTHER`",
+        name, experiment.stats.records, name
+    );
+    Ok(())
+}
+
+/// Check the services and make sure the generation model is available
+async fn prepare_generation(provider: &dyn ModelProvider, startup: Startup) -> anyhow::Result<()> {
+    startup
+        .check(PreflightTargets {
+            llm: Some((provider, startup.pull_model)),
+            ..Default::default()
+        })
+        .await?;
+    if startup.pull_model {
+        provider.ensure_model().await?;
+    }
+    Ok(())
+}
+
+/// Open the `--source` arguments, pairing each with its `--source-type`
+fn sources_from_args(
+    inputs: &[String],
+    types: &[SourceType],
+) -> anyhow::Result<Vec<Box<dyn DataSource>>> {
+    let type_of = |index: usize| match types.len() {
+        0 => Ok(SourceType::Auto),
+        1 => Ok(types[0]),
+        n if n == inputs.len() => Ok(types[index]),
+        n => Err(anyhow::anyhow!(
+            "Got {} --source-type values for {} sources; give one for all or one per source",
+            n,
+            inputs.len()
+        )),
+    };
+    inputs
+        .iter()
+        .enumerate()
+        .map(|(index, input)| {
+            open_source(input, type_of(index)?)
+                .map_err(|e| e.context(format!("Invalid source '{}'", input)))
+        })
+        .collect()
+}
+
+/// Sources from the command line, else prompted for when stdin is a terminal
+async fn select_sources(
+    inputs: &[String],
+    types: &[SourceType],
+    test_mode: bool,
+) -> anyhow::Result<Vec<Box<dyn DataSource>>> {
+    if !inputs.is_empty() {
+        sources_from_args(inputs, types).context(ExitReason::Config)
+    } else if !types.is_empty() {
+        Err(anyhow::anyhow!("--source-type needs at least one --source")
+            .context(ExitReason::Config))
+    } else if !test_mode && std::io::stdin().is_terminal() {
+        collect_sources().await
+    } else {
+        Ok(Vec::new())
+    }
+}
+
+/// Collect sources into the output directory, returning the files written
+/// and where they came from
+async fn run_collect_command(
+    output_dir: &Path,
+    sources: Vec<Box<dyn DataSource>>,
+) -> anyhow::Result<(Vec<PathBuf>, Vec<SourceProvenance>)> {
+    let (sender, mut received) = mpsc::channel(pipeline::CHANNEL_CAPACITY);
+    let mut files = Vec::new();
+    let drain = async {
+        while let Some(file) = received.recv().await {
+            files.push(file);
+        }
+    };
+    let (provenance, _) = tokio::join!(
+        pipeline::collect_sources(sources, output_dir, sender),
+        drain
+    );
+    Ok((files, provenance?))
+}
+
+/// Markdown and text files in the output directory, leaving out the parts
+/// and summaries made from oversized files
+fn existing_inputs(output_dir: &Path) -> Vec<PathBuf> {
+    let parts_dir = output_dir.join(PARTS_DIR);
+    WalkDir::new(output_dir)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|e| e.path() != parts_dir)
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter(|e| is_input_file(e.path()))
+        .map(|e| e.path().to_path_buf())
+        .collect()
+}
+
+/// Files to generate questions for
+enum Inputs {
+    /// Sources to collect first; their files are generated from as each
+    /// source finishes
+    Collect(Vec<Box<dyn DataSource>>),
+    /// Files already in the output directory, and the sources they came from
+    Existing(Vec<PathBuf>, Vec<SourceProvenance>),
+}
+
+/// Strip boilerplate from the inputs' files; sources are collected in full
+/// first, since repeated blocks are only known once every file is in
+async fn strip_boilerplate(output_dir: &Path, inputs: Inputs) -> anyhow::Result<Inputs> {
+    let (files, sources) = match inputs {
+        Inputs::Collect(sources) => run_collect_command(output_dir, sources).await?,
+        Inputs::Existing(files, sources) => (files, sources),
+    };
+    let report = boilerplate::clean_files(&files, boilerplate::DEFAULT_MIN_SHARE, false)?;
+    print!("{}", report);
+    Ok(Inputs::Existing(files, sources))
+}
+
+fn run_clean_command(output_dir: &Path, dry_run: bool) -> anyhow::Result<()> {
+    let files = existing_inputs(output_dir);
+    let report = boilerplate::clean_files(&files, boilerplate::DEFAULT_MIN_SHARE, dry_run)?;
+    print!("{}", report);
+    if dry_run && report.files_changed > 0 {
+        println!("Run clean without --dry-run to remove it");
+    }
+    Ok(())
+}
+
+/// Generate questions for the inputs, writing per-file datasets, the
+/// combined `all_qa.jsonl` and the run manifest
+async fn run_generate_command(
+    generation: &Generation,
+    provider: &dyn ModelProvider,
+    client: DefaultOllamaClient,
+    student: Option<&Arc<dyn ModelProvider>>,
+    inputs: Inputs,
+    watch_interval: Option<Duration>,
+    report: &mut RunReport,
+) -> anyhow::Result<()> {
+    let (output_dir, shutdown) = (&generation.output_dir, &generation.shutdown);
+    // This run's outcome replaces the summary of an earlier failed one
+    let _ = fs::remove_file(Path::new(output_dir).join(ERRORS_FILE));
+    let inputs = match generation.strip_boilerplate {
+        true => strip_boilerplate(Path::new(output_dir), inputs).await?,
+        false => inputs,
+    };
+    if let Inputs::Existing(files, _) = &inputs {
+        if files.is_empty() && watch_interval.is_none() {
+            println!("No markdown or text files found in output directory to process.");
+            return Ok(());
+        }
+        println!("Found {} markdown/text files to process.", files.len());
+    }
+    let limits = &generation.limits;
+    fs::create_dir_all(output_dir)?;
+
+    // Start the provenance manifest for this run
+    let mut manifest = RunManifest::new(serde_json::json!({
+        "output_dir": output_dir,
+        "llm_backend": generation.backend.to_string(),
+        "ollama_endpoint": &generation.ollama_endpoint,
+        "model": &generation.model,
+        "incremental": generation.incremental,
+        "force": generation.force,
+        "structured_budget": generation.structured_budget,
+        "question_density": generation.density.to_string(),
+        "stream": generation.stream,
+        "max_output_tokens": generation.max_output_tokens,
+        "keep_alive": &generation.keep_alive,
+        "max_files": generation.limits.max_files,
+        "max_questions": generation.limits.max_questions,
+        "sample": generation.limits.sample_percent,
+        "sample_seed": generation.limits.sample_seed,
+        "max_file_size": generation.size_limit.max_bytes,
+        "oversized": generation.size_limit.policy.name(),
+        "eval": generation.eval_split.map(|split| split.percent),
+        "candidates": generation.rejection.map_or(1, |r| r.candidates),
+        "candidate_scoring": generation.rejection.map(|r| r.scoring.name()),
+        "keep_rejected": generation.rejection.is_some_and(|r| r.keep_rejected),
+        "student_model": student.map(|student| student.model()),
+        "student_agreement": student.map(|_| generation.student_agreement),
+        "max_source_share": generation.balance.map(|b| b.max_share),
+        "balance_by": generation.balance.map(|b| b.by.name()),
+        "strip_boilerplate": generation.strip_boilerplate,
+        "glossary": generation.glossary,
+        "target_tags": &generation.target_tags,
+        "only_sections": &generation.only_sections,
+        "only_matching": generation.only_matching.as_ref().map(Regex::as_str),
+        "top_up": generation.top_up,
+        "rich_schema": generation.rich_schema,
+        "publish": generation.publish.as_ref().map(ToString::to_string),
+        "run_name": &generation.run_name,
+    }));
+    manifest.add_model("generation", &generation.model);
+    if let Some(student) = student {
+        manifest.add_model("student", student.model());
+    }
+    for (name, template) in prompt_templates() {
+        manifest.add_prompt_template(name, template);
+    }
+
+    let publisher = connect_publisher(generation).await?;
+    let processor = generation_processor(generation, client, student, publisher.as_ref());
+    handle_signals(shutdown.clone());
+
+    // Files are generated from while later sources are still downloading, and
+    // records are streamed into the combined dataset as they are generated
+    let output_file = Path::new(output_dir).join("all_qa.jsonl");
+    let mut dataset = schema::DatasetWriter::create(&output_file)?;
+    // Held-out records never go into the training set; they are only
+    // collected into eval.jsonl when --eval is given
+    let eval_file = Path::new(output_dir).join(EVAL_FILE);
+    let mut eval_dataset = match generation.eval_split {
+        Some(_) => Some(schema::DatasetWriter::create(&eval_file)?),
+        None => None,
+    };
+    let (files, received) = mpsc::channel(pipeline::CHANNEL_CAPACITY);
+    let feed = async {
+        match inputs {
+            Inputs::Collect(sources) => {
+                pipeline::collect_sources(sources, Path::new(output_dir), files).await
+            }
+            Inputs::Existing(existing, sources) => {
+                pipeline::send_files(existing, files).await;
+                Ok(sources)
+            }
+        }
+    };
+    // Where every record came from, to map the run's coverage of its files
+    let mut lineages = Vec::new();
+    let mut append = |items: &[ProcessedItem]| {
+        lineages.extend(items.iter().map(|item| item.lineage.clone()));
+        let (eval, train): (Vec<_>, Vec<_>) =
+            items.iter().cloned().partition(ProcessedItem::is_eval);
+        if let Some(eval_dataset) = eval_dataset.as_mut() {
+            eval_dataset.append(&eval)?;
+        }
+        dataset.append(&train).map(drop)
+    };
+    let mut processed = Vec::new();
+    let mut record = |file_path: &Path, result: anyhow::Result<usize>| match result {
+        Ok(_) => {
+            report.file_succeeded();
+            processed.push(file_path.to_path_buf());
+        }
+        Err(e) => {
+            eprintln!("Error processing file {:?}: {:#}", file_path, e);
+            report.file_failed(file_path, &e);
+        }
+    };
+    let (sources, held_back) = tokio::join!(
+        feed,
+        Pipeline::new(&processor).run(received, &mut append, &mut record)
+    );
+    let sources = sources?;
+
+    let watch = watch_plan(watch_interval, Path::new(output_dir), &sources)?;
+    if report.files_processed == 0 && held_back == 0 && watch.is_none() {
+        println!("No markdown or text files found to process.");
+        return Ok(());
+    }
+    for source in sources {
+        manifest.add_source(source);
+    }
+
+    if !shutdown.is_requested() {
+        let coverage_file = check_coverage(
+            generation,
+            &processor,
+            &processed,
+            &lineages,
+            &mut dataset,
+            publisher.as_deref(),
+        )
+        .await?;
+        manifest.add_output(&coverage_file);
+    }
+
+    // Save combined results
+    let mut written = dataset.finish()?;
+    if let Some(balance) = generation.balance {
+        let balanced = balance.apply(schema::read_dataset(&output_file)?.items);
+        written = schema::write_dataset(&output_file, &balanced.items)?;
+        println!("Balanced by {}: {}", balance.by, balanced);
+    }
+    println!(
+        "Saved {} question-answer pairs to {:?}",
+        written, output_file
+    );
+    println!("Individual file results saved as [filename]_qa.jsonl in the output directory");
+    manifest.add_output(&output_file);
+    if let Some(eval_dataset) = eval_dataset {
+        let held_out = eval_dataset.finish()?;
+        println!(
+            "Saved {} evaluation questions with reference answers to {:?}",
+            held_out, eval_file
+        );
+        manifest.add_output(&eval_file);
+    }
+
+    if let Some(student_report) = processor.student_report() {
+        let report_file = Path::new(output_dir).join(STUDENT_REPORT_FILE);
+        student_report.write(&report_file)?;
+        println!("{}", student_report);
+        println!("Wrote the student's weak areas to {:?}", report_file);
+        manifest.add_output(&report_file);
+    }
+
+    manifest.add_usage("generation", provider.usage());
+    if let Some(student) = student {
+        manifest.add_usage("student", student.usage());
+    }
+    manifest.interrupted = shutdown.is_requested();
+    manifest.finish(written);
+    let manifest_path = manifest.write(Path::new(output_dir))?;
+    println!("Wrote run manifest to {:?}", manifest_path);
+    record_experiment(generation, &manifest, &output_file)?;
+
+    let result = if shutdown.is_requested() {
+        println!("Interrupted; run again with --incremental to generate the remaining sections");
+        Ok(())
+    } else if let Some(exceeded) = limits.exceeded(held_back) {
+        Err(ProcessorError::BudgetExceeded(exceeded).into())
+    } else if let Some(plan) = watch {
+        let eval_file = generation.eval_split.map(|_| eval_file);
+        watch_for_changes(
+            &processor,
+            &output_file,
+            eval_file.as_deref(),
+            plan,
+            shutdown,
+            report,
+        )
+        .await
+    } else {
+        Ok(())
+    };
+    finish_publishing(publisher).await;
+    result
+}
+
+/// The sections of input files, named by their path within the output
+/// directory; files over the size limit are left out, as their records come
+/// from their parts or summary
+fn document_sections(
+    output_dir: &Path,
+    files: &[PathBuf],
+    size_limit: Option<SizeLimit>,
+) -> anyhow::Result<Vec<DocumentSections>> {
+    let mut documents = Vec::new();
+    for path in files {
+        if let Some(limit) = size_limit {
+            if limit.exceeded_by(fs::metadata(path)?.len()) {
+                continue;
+            }
+        }
+        let name = path.strip_prefix(output_dir).unwrap_or(path);
+        documents.push(DocumentSections::read(
+            path,
+            name.to_string_lossy().into_owned(),
+        )?);
+    }
+    Ok(documents)
+}
+
+/// Report how the run's records cover the sections of the files it
+/// processed, written to `coverage.json`, and with `--top-up` generate
+/// questions for the sections without any into `dataset`, publishing them
+/// with the run's other records
+///
+/// Returns where the report was written.
+async fn check_coverage(
+    generation: &Generation,
+    processor: &DefaultOllamaProcessor,
+    processed: &[PathBuf],
+    lineages: &[Option<Lineage>],
+    dataset: &mut DatasetWriter,
+    publisher: Option<&StreamSink>,
+) -> anyhow::Result<PathBuf> {
+    let output_dir = Path::new(&generation.output_dir);
+    let documents = document_sections(output_dir, processed, Some(generation.size_limit))?;
+    let mut coverage = CoverageReport::new(
+        &documents,
+        generation.density,
+        lineages.iter().map(Option::as_ref),
+    );
+    println!("\nCoverage: {}", coverage);
+
+    if generation.top_up && coverage.uncovered().next().is_some() {
+        let mut topped_up = 0;
+        for document in &documents {
+            let graph = fs::read_to_string(output_dir.join(&document.document))
+                .map_err(anyhow::Error::from)
+                .and_then(|content| parse_markdown_document(&content, &document.document))
+                .map(|mut graph| {
+                    if generation.rich_schema {
+                        tag_nodes(&mut graph, &TaggingConfig::default());
+                    }
+                    graph
+                })
+                .ok();
+            let uncovered = coverage
+                .sections
+                .iter_mut()
+                .filter(|section| section.document == document.document && section.is_uncovered());
+            for entry in uncovered {
+                if generation.shutdown.is_requested() {
+                    break;
+                }
+                let section = &document.sections[entry.number - 1];
+                println!("\nTopping up {} ({} questions)", entry, entry.expected);
+                match processor
+                    .top_up(&document.document, section, entry.expected, graph.as_ref())
+                    .await
+                {
+                    Ok(items) => {
+                        dataset.append(&items)?;
+                        if let Some(publisher) = publisher {
+                            items
+                                .iter()
+                                .for_each(|item| publisher.on_item_accepted(item));
+                        }
+                        entry.questions = items.len();
+                        topped_up += items.len();
+                    }
+                    Err(e) => eprintln!("Could not top up {}: {:#}", entry, e),
+                }
+            }
+        }
+        println!(
+            "Topped up uncovered sections with {} questions (in all_qa.jsonl only)",
+            topped_up
+        );
+    }
+
+    let coverage_file = output_dir.join(COVERAGE_FILE);
+    coverage.write(&coverage_file)?;
+    println!("Wrote section coverage to {:?}", coverage_file);
+    Ok(coverage_file)
+}
+
+fn run_coverage_command(
+    output_dir: &Path,
+    paths: &[PathBuf],
+    density: QuestionDensity,
+) -> anyhow::Result<()> {
+    let items = schema::merge_datasets(&dataset_paths(output_dir, paths))?;
+    let documents = document_sections(output_dir, &existing_inputs(output_dir), None)?;
+    let coverage = CoverageReport::new(
+        &documents,
+        density,
+        items.iter().map(|item| item.lineage.as_ref()),
+    );
+    println!("{}", coverage);
+    let coverage_file = output_dir.join(COVERAGE_FILE);
+    coverage.write(&coverage_file)?;
+    println!("Wrote section coverage to {}", coverage_file.display());
+    Ok(())
+}
+
+/// A processor generating with the settings of `generation`
+fn generation_processor(
+    generation: &Generation,
+    client: DefaultOllamaClient,
+    student: Option<&Arc<dyn ModelProvider>>,
+    publisher: Option<&Arc<StreamSink>>,
+) -> DefaultOllamaProcessor {
+    let processor = DefaultOllamaProcessor::new_with_client(
+        generation.ollama_endpoint.clone(),
+        generation.model.clone(),
+        Box::new(client),
+        Some(PathBuf::from(&generation.output_dir)),
+    )
+    .with_incremental(generation.incremental)
+    .with_force(generation.force)
+    .with_budget(generation.structured_budget.then(QuestionBudget::default))
+    .with_density(generation.density)
+    .with_shutdown(generation.shutdown.clone())
+    .with_limits(generation.limits.clone())
+    .with_size_limit(generation.size_limit)
+    .with_eval_split(generation.eval_split)
+    .with_rejection_sampling(generation.rejection)
+    .with_glossary(generation.glossary)
+    .with_target_tags(generation.target_tags.clone())
+    .with_target_sections(generation.only_sections.clone())
+    .with_target_pattern(generation.only_matching.clone())
+    .with_rich_schema(generation.rich_schema)
+    .with_student(student.map(|student| {
+        Student::new(
+            Box::new(DefaultOllamaClient::from_provider(Arc::clone(student))),
+            student.model(),
+            generation.student_agreement,
+        )
+    }));
+    match publisher {
+        Some(publisher) => processor.with_observer(Arc::clone(publisher) as _),
+        None => processor,
+    }
+}
+
+/// Connect to the topic or subject accepted records are published to
+/// (`--publish`), if one was given
+async fn connect_publisher(generation: &Generation) -> anyhow::Result<Option<Arc<StreamSink>>> {
+    let Some(target) = &generation.publish else {
+        return Ok(None);
+    };
+    let sink = StreamSink::connect(target.clone())
+        .await
+        .context(ExitReason::BackendUnreachable)?;
+    println!("Publishing accepted records to {}", target);
+    Ok(Some(Arc::new(sink)))
+}
+
+/// Wait until the records still queued for publishing are published
+async fn finish_publishing(publisher: Option<Arc<StreamSink>>) {
+    if let Some(publisher) = publisher {
+        let report = publisher.finish().await;
+        println!("Published to {}: {}", publisher.target(), report);
+    }
+}
+
+/// Queue the inputs' files for workers and merge the records they post back
+/// into `all_qa.jsonl`, `eval.jsonl` and the run manifest
+async fn run_coordinate_command(
+    generation: &Generation,
+    inputs: Inputs,
+    listen: SocketAddr,
+    lease: Duration,
+    report: &mut RunReport,
+) -> anyhow::Result<()> {
+    let (output_dir, shutdown) = (Path::new(&generation.output_dir), &generation.shutdown);
+    let _ = fs::remove_file(output_dir.join(ERRORS_FILE));
+    fs::create_dir_all(output_dir)?;
+    let inputs = match generation.strip_boilerplate {
+        true => strip_boilerplate(output_dir, inputs).await?,
+        false => inputs,
+    };
+    let mut manifest = RunManifest::new(serde_json::json!({
+        "output_dir": output_dir,
+        "listen": listen,
+        "lease_minutes": lease.as_secs() / 60,
+        "max_files": generation.limits.max_files,
+        "max_source_share": generation.balance.map(|b| b.max_share),
+        "balance_by": generation.balance.map(|b| b.by.name()),
+        "strip_boilerplate": generation.strip_boilerplate,
+        "run_name": &generation.run_name,
+    }));
+
+    let (queue, mut results) = WorkQueue::new(lease);
+    let queue = queue.with_token(env::var(QUEUE_TOKEN_VAR).ok());
+    let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+    let (addr, server) = queue
+        .serve(listen, async {
+            let _ = stopped.await;
+        })
+        .context(ExitReason::Config)?;
+    let server = tokio::spawn(server);
+    println!(
+        "Coordinating on {}; start workers with `work --coordinator http://<this host>:{}`",
+        addr,
+        addr.port()
+    );
+    handle_signals(shutdown.clone());
+
+    let (files, mut received) = mpsc::channel(pipeline::CHANNEL_CAPACITY);
+    let feed = async {
+        match inputs {
+            Inputs::Collect(sources) => pipeline::collect_sources(sources, output_dir, files).await,
+            Inputs::Existing(existing, sources) => {
+                pipeline::send_files(existing, files).await;
+                Ok(sources)
+            }
+        }
+    };
+    let mut held_back = 0;
+    let enqueue = async {
+        let (mut queued, mut unreadable) = (0, Vec::new());
+        while let Some(path) = received.recv().await {
+            if shutdown.is_requested() {
+                break;
+            }
+            if generation.limits.max_files.is_some_and(|max| queued >= max) {
+                held_back += 1;
+                continue;
+            }
+            match fs::read_to_string(&path) {
+                Ok(content) => {
+                    let relative = path.strip_prefix(output_dir).unwrap_or(&path);
+                    queue.push(relative.to_string_lossy().into_owned(), content);
+                    queued += 1;
+                }
+                Err(e) => unreadable.push((path, e)),
+            }
+        }
+        queue.close();
+        println!("Queued {} files", queued);
+        unreadable
+    };
+
+    let output_file = output_dir.join("all_qa.jsonl");
+    let mut dataset = schema::DatasetWriter::create(&output_file)?;
+    let eval_file = output_dir.join(EVAL_FILE);
+    let mut eval_dataset = None;
+    let mut failures = Vec::new();
+    let merge = async {
+        loop {
+            let completed = tokio::select! {
+                completed = results.recv() => completed,
+                _ = shutdown.requested() => None,
+            };
+            let Some(completed) = completed else {
+                break;
+            };
+            let result = completed.result;
+            manifest.add_model(&format!("generation ({})", result.worker), &result.model);
+            match result.error {
+                Some(error) => {
+                    eprintln!("{} failed on {}: {}", completed.path, result.worker, error);
+                    failures.push((completed.path, error));
+                }
+                None => {
+                    println!(
+                        "{}: {} records from {} ({} files left)",
+                        completed.path,
+                        result.items.len(),
+                        result.worker,
+                        queue.remaining()
+                    );
+                    let (eval, train): (Vec<_>, Vec<_>) =
+                        result.items.into_iter().partition(ProcessedItem::is_eval);
+                    if !eval.is_empty() {
+                        if eval_dataset.is_none() {
+                            eval_dataset = Some(schema::DatasetWriter::create(&eval_file)?);
+                        }
+                        eval_dataset.as_mut().unwrap().append(&eval)?;
+                    }
+                    dataset.append(&train)?;
+                    report.file_succeeded();
+                }
+            }
+        }
+        anyhow::Ok(())
+    };
+    let (sources, unreadable, merged) = tokio::join!(feed, enqueue, merge);
+    merged?;
+    for (path, e) in unreadable {
+        report.file_failed(&path, &e.into());
+    }
+    for (path, error) in failures {
+        report.file_failed(&output_dir.join(path), &anyhow::anyhow!(error));
+    }
+    for source in sources? {
+        manifest.add_source(source);
+    }
+
+    // Workers still polling learn that the run is over before the server stops
+    if !shutdown.is_requested() {
+        tokio::time::sleep(queue::DRAIN_PERIOD).await;
+    }
+    let _ = stop.send(());
+    server.await??;
+
+    let mut written = dataset.finish()?;
+    if let Some(balance) = generation.balance {
+        let balanced = balance.apply(schema::read_dataset(&output_file)?.items);
+        written = schema::write_dataset(&output_file, &balanced.items)?;
+        println!("Balanced by {}: {}", balance.by, balanced);
+    }
+    println!(
+        "Saved {} question-answer pairs to {:?}",
+        written, output_file
+    );
+    manifest.add_output(&output_file);
+    if let Some(eval_dataset) = eval_dataset {
+        let held_out = eval_dataset.finish()?;
+        println!(
+            "Saved {} evaluation questions with reference answers to {:?}",
+            held_out, eval_file
+        );
+        manifest.add_output(&eval_file);
+    }
+    manifest.interrupted = shutdown.is_requested();
+    manifest.finish(written);
+    let manifest_path = manifest.write(output_dir)?;
+    println!("Wrote run manifest to {:?}", manifest_path);
+    record_experiment(generation, &manifest, &output_file)?;
+
+    match generation.limits.exceeded(held_back) {
+        Some(exceeded) if !shutdown.is_requested() => {
+            Err(ProcessorError::BudgetExceeded(exceeded).into())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Generate questions for files claimed from the coordinator at `url` until
+/// it has none left
+async fn run_work_command(
+    generation: &Generation,
+    client: DefaultOllamaClient,
+    student: Option<&Arc<dyn ModelProvider>>,
+    url: &str,
+    name: Option<&str>,
+) -> anyhow::Result<()> {
+    let name = name
+        .map(str::to_string)
+        .or_else(|| env::var("HOSTNAME").ok())
+        .unwrap_or_else(|| format!("worker-{}", std::process::id()));
+    let output_dir = Path::new(&generation.output_dir);
+    fs::create_dir_all(output_dir)?;
+    let publisher = connect_publisher(generation).await?;
+    let processor = generation_processor(generation, client, student, publisher.as_ref());
+    handle_signals(generation.shutdown.clone());
+
+    println!("Working for {} as {}", url, name);
+    let coordinator = QueueClient::new(url, env::var(QUEUE_TOKEN_VAR).ok());
+    let done = queue::work(
+        &processor,
+        &coordinator,
+        output_dir,
+        &name,
+        &generation.model,
+    )
+    .await;
+    finish_publishing(publisher).await;
+    println!("Generated questions for {} files", done?);
+    Ok(())
+}
+
+/// Request a graceful shutdown on the first Ctrl-C or SIGTERM and exit
+/// immediately on the second
+fn handle_signals(shutdown: Shutdown) {
+    tokio::spawn(async move {
+        termination_signal().await;
+        eprintln!(
+            "\nStopping: saving what was generated so far (press Ctrl-C again to exit immediately)..."
+        );
+        shutdown.request();
+        termination_signal().await;
+        std::process::exit(ExitReason::Interrupted.code());
+    });
+}
+
+/// Complete on Ctrl-C, or on SIGTERM where there is one
+async fn termination_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+            }
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+/// What `--watch` should follow for the given sources, if watching
+fn watch_plan(
+    interval: Option<Duration>,
+    output_dir: &Path,
+    sources: &[SourceProvenance],
+) -> anyhow::Result<Option<WatchPlan>> {
+    let Some(interval) = interval else {
+        return Ok(None);
+    };
+    fs::create_dir_all(output_dir)?;
+    WatchPlan::from_sources(sources, output_dir, interval).map(Some)
+}
+
+/// Generate questions for files as local sources change and remote sources
+/// gain content, appending the new records to `output_file`, and those held
+/// out for evaluation to `eval_file`, until shutdown
+async fn watch_for_changes(
+    processor: &DefaultOllamaProcessor,
+    output_file: &Path,
+    eval_file: Option<&Path>,
+    plan: WatchPlan,
+    shutdown: &Shutdown,
+    report: &mut RunReport,
+) -> anyhow::Result<()> {
+    let watched: Vec<PathBuf> = plan.mirrors.iter().map(|m| m.source.clone()).collect();
+    let mut watcher = ChangeWatcher::new(&watched)?;
+    let mut tracker = ContentTracker::default();
+    tracker.changed(existing_inputs(&plan.output_dir));
+    let mut appender = DatasetAppender::open(output_file)?;
+    let mut eval_appender = eval_file.map(DatasetAppender::open).transpose()?;
+
+    let mut poll = tokio::time::interval(plan.interval);
+    // The first tick completes immediately; the feeds were just collected
+    poll.tick().await;
+    println!(
+        "Watching {} local and {} remote sources for changes (Ctrl-C to stop)...",
+        plan.mirrors.len(),
+        plan.feeds.len()
+    );
+
+    loop {
+        let candidates = tokio::select! {
+            _ = shutdown.requested() => break,
+            Some(batch) = watcher.next_batch(WATCH_DEBOUNCE) => {
+                let mut copied = Vec::new();
+                for changed in batch {
+                    for mirror in &plan.mirrors {
+                        let Some(dest) = mirror.destination(&changed) else {
+                            continue;
+                        };
+                        if dest != changed {
+                            if let Some(parent) = dest.parent() {
+                                fs::create_dir_all(parent)?;
+                            }
+                            fs::copy(&changed, &dest)?;
+                        }
+                        copied.push(dest);
+                    }
+                }
+                copied
+            }
+            _ = poll.tick(), if !plan.feeds.is_empty() => {
+                let mut collected = Vec::new();
+                for feed in &plan.feeds {
+                    match feed.collect(&plan.output_dir).await {
+                        Ok(files) => collected.extend(files),
+                        Err(e) => eprintln!("Error polling source: {}", e),
+                    }
+                }
+                collected.retain(|path| is_input_file(path));
+                collected
+            }
+        };
+
+        for file_path in tracker.changed(candidates) {
+            if shutdown.is_requested() {
+                break;
+            }
+            println!("Processing file: {:?}", file_path);
+            match processor.process_file(&file_path).await {
+                Ok(items) => {
+                    report.file_succeeded();
+                    let (eval, items): (Vec<_>, Vec<_>) =
+                        items.into_iter().partition(ProcessedItem::is_eval);
+                    if let Some(eval_appender) = eval_appender.as_mut() {
+                        eval_appender.append(&eval)?;
+                    }
+                    let appended = appender.append(&items)?;
+                    println!(
+                        "Appended {} question-answer pairs to {:?}",
+                        appended, output_file
+                    );
+                }
+                Err(e) => {
+                    eprintln!("Error processing file {:?}: {:#}", file_path, e);
+                    report.file_failed(&file_path, &e);
+                }
+            }
+        }
+    }
+    println!("Stopped watching.");
+    Ok(())
+}
+
+/// Dataset files named on the command line, or the combined dataset
+fn dataset_paths(output_dir: &Path, paths: &[PathBuf]) -> Vec<PathBuf> {
+    if paths.is_empty() {
+        vec![output_dir.join("all_qa.jsonl")]
+    } else {
+        paths.to_vec()
+    }
+}
+
+fn run_export_command(
+    output_dir: &Path,
+    inputs: &[PathBuf],
+    format: ExportFormat,
+    output: Option<&Path>,
+    curriculum: Option<Curriculum>,
+) -> anyhow::Result<()> {
+    let mut items = schema::merge_datasets(&dataset_paths(output_dir, inputs))?;
+    let reviewed = items.len();
+    items.retain(review::is_kept);
+    if items.len() < reviewed {
+        println!(
+            "Leaving out {} records rejected in review",
+            reviewed - items.len()
+        );
+    }
+    let output = output
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| output_dir.join(format!("export_{}.{}", format, format.extension())));
+    let Some(curriculum) = curriculum else {
+        let written = export_dataset(&output, &items, format)?;
+        println!("Exported {} records as {} to {:?}", written, format, output);
+        return Ok(());
+    };
+    for (path, written) in export_curriculum(&output, &items, format, curriculum)? {
+        println!("Exported {} records as {} to {:?}", written, format, path);
+    }
+    Ok(())
+}
+
+fn run_import_command(
+    output_dir: &Path,
+    inputs: &[PathBuf],
+    format: Option<ImportFormat>,
+    output: Option<&Path>,
+) -> anyhow::Result<()> {
+    let imported = import_files(inputs, format)?;
+    let output = output
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| output_dir.join(IMPORTED_FILE));
+    if let Some(dir) = output.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    schema::write_dataset(&output, &imported.items)?;
+    println!("Wrote {:?}: {}", output, imported.report);
+    Ok(())
+}
+
+fn run_stats_command(output_dir: &Path, paths: &[PathBuf]) -> anyhow::Result<()> {
+    for path in dataset_paths(output_dir, paths) {
+        let dataset = schema::read_dataset(&path)?;
+        println!(
+            "{} (schema {})",
+            path.display(),
+            dataset.header.schema_version
+        );
+        println!("{}\n", DatasetStats::from_items(&dataset.items));
+    }
+    Ok(())
+}
+
+fn run_compare_command(output_dir: &Path, a: &str, b: &str) -> anyhow::Result<()> {
+    let comparison = Comparison::of(&Registry::new(output_dir), a, b)?;
+    println!("{}", comparison);
+    Ok(())
+}
+
+async fn run_evaluate_command(
+    output_dir: &Path,
+    paths: &[PathBuf],
+    provider: &dyn ModelProvider,
+    limit: Option<usize>,
+    startup: Startup,
+) -> anyhow::Result<()> {
+    prepare_generation(provider, startup).await?;
+    let judge = Judge::new(provider);
+    for path in dataset_paths(output_dir, paths) {
+        let dataset = schema::read_dataset(&path)?;
+        let items = &dataset.items[..limit.unwrap_or(usize::MAX).min(dataset.items.len())];
+        println!("Scoring {} records of {}", items.len(), path.display());
+
+        let scores_path = judge::scores_path(&path);
+        let mut scores = std::io::BufWriter::new(fs::File::create(&scores_path)?);
+        let (mut judgements, mut failed) = (Vec::new(), 0);
+        for (i, item) in items.iter().enumerate() {
+            match judge.score(item).await {
+                Ok(judgement) => {
+                    writeln!(scores, "{}", serde_json::to_string(&judgement)?)?;
+                    judgements.push(judgement);
+                }
+                Err(e) => {
+                    println!("Could not score record {}: {}", i + 1, e);
+                    failed += 1;
+                }
+            }
+        }
+        scores.flush()?;
+
+        let report = QualityReport::from_judgements(judge.model(), &judgements, failed);
+        let report_path = judge::report_path(&path);
+        fs::write(&report_path, serde_json::to_string_pretty(&report)?)?;
+        println!("{}\n", report);
+        println!(
+            "Wrote scores to {} and the report to {}\n",
+            scores_path.display(),
+            report_path.display()
+        );
+    }
+    Ok(())
+}
+
+fn run_lineage_command(output_dir: &Path, id: &Uuid, paths: &[PathBuf]) -> anyhow::Result<()> {
+    let mut found = None;
+    'datasets: for path in dataset_paths(output_dir, paths) {
+        for item in schema::DatasetReader::open(&path)? {
+            let item = item?;
+            if item.id.as_ref() == Some(id) {
+                found = Some(item);
+                break 'datasets;
+            }
+        }
+    }
+    let item = found.ok_or_else(|| anyhow::anyhow!("No record with ID {}", id))?;
+    let lineage = lineage::lineage_of(&item)?;
+
+    // Documents are named by their path within the output directory, or as
+    // given to the rag command
+    let document = [
+        output_dir.join(&lineage.document),
+        PathBuf::from(&lineage.document),
+    ]
+    .into_iter()
+    .find(|path| path.is_file())
+    .ok_or_else(|| anyhow::anyhow!("Document {:?} of record {} not found", lineage.document, id))?;
+    let graph = parse_markdown_document(&fs::read_to_string(document)?, &lineage.document)?;
+    let sources = recorded_sources(output_dir)?;
+    let resolved = lineage::resolve(&graph, &sources, lineage)?;
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&resolved.to_prov(&item, lineage))?
+    );
+    Ok(())
+}
+
+async fn run_benchmark_command(
+    provider: &dyn ModelProvider,
+    options: BenchmarkOptions<'_>,
+    startup: Startup,
+) -> anyhow::Result<()> {
+    let mut config = load_config()?;
+    // Cached embeddings would measure the cache instead of the model
+    config.embedding.cache_dir = None;
+    let engine = if options.skip_embeddings {
+        None
+    } else {
+        Some(EmbeddingEngine::new(config.embedding.clone()).await?)
+    };
+    startup
+        .check(PreflightTargets {
+            llm: (!options.skip_generation).then_some((provider, startup.pull_model)),
+            embedding: engine.as_ref().map(|engine| (engine, &config.embedding)),
+            ..Default::default()
+        })
+        .await?;
+    if startup.pull_model && !options.skip_generation {
+        provider.ensure_model().await?;
+    }
+
+    let mut results = Vec::new();
+    println!("{}", benchmark::RESULT_HEADER);
+    for &concurrency in options.concurrency {
+        if !options.skip_generation {
+            let result =
+                benchmark::benchmark_generation(provider, options.requests, concurrency).await;
+            println!("{}", result);
+            results.push(result);
+        }
+        if let Some(engine) = &engine {
+            let result = benchmark::benchmark_embeddings(
+                engine,
+                &config.embedding.model,
+                options.requests,
+                concurrency,
+            )
+            .await;
+            println!("{}", result);
+            results.push(result);
+        }
+    }
+
+    for result in &results {
+        if let Some(error) = &result.first_error {
+            println!(
+                "{} at concurrency {}: {} of {} requests failed, first error: {}",
+                result.backend, result.concurrency, result.failures, result.requests, error
+            );
+        }
+    }
+    if let Some(failed) = results
+        .iter()
+        .find(|result| result.requests > 0 && result.failures == result.requests)
+    {
+        return Err(anyhow::anyhow!(
+            "Every {} request failed at concurrency {}",
+            failed.backend,
+            failed.concurrency
+        ))
+        .context(ExitReason::BackendUnreachable);
+    }
+    Ok(())
+}
+
+/// What the benchmark command measures
+#[derive(Debug, Clone, Copy)]
+struct BenchmarkOptions<'a> {
+    requests: usize,
+    concurrency: &'a [usize],
+    skip_embeddings: bool,
+    skip_generation: bool,
+}
+
+/// Settings of the `contamination` command
+struct ContaminationOptions<'a> {
+    benchmarks: &'a [PathBuf],
+    ngram: usize,
+    /// Embedding similarity threshold, when embeddings are compared
+    similarity: Option<f32>,
+    remove: bool,
+}
+
+async fn run_contamination_command(
+    output_dir: &Path,
+    paths: &[PathBuf],
+    options: ContaminationOptions<'_>,
+    startup: Startup,
+) -> anyhow::Result<()> {
+    let mut benchmarks = Vec::new();
+    for path in options.benchmarks {
+        let items = contamination::load_benchmark(path)?;
+        println!("Loaded {} questions from {}", items.len(), path.display());
+        benchmarks.extend(items);
+    }
+
+    let engine = match options.similarity {
+        Some(_) => {
+            let config = load_config()?;
+            let engine = EmbeddingEngine::new(config.embedding.clone()).await?;
+            startup
+                .check(PreflightTargets {
+                    embedding: Some((&engine, &config.embedding)),
+                    ..Default::default()
+                })
+                .await?;
+            Some(engine)
+        }
+        None => None,
+    };
+    let mut check = ContaminationCheck::new(&benchmarks, options.ngram);
+    if let (Some(engine), Some(similarity)) = (&engine, options.similarity) {
+        println!("Embedding {} benchmark questions", benchmarks.len());
+        check = check.with_embeddings(engine, similarity).await?;
+    }
+
+    for path in dataset_paths(output_dir, paths) {
+        let dataset = schema::read_dataset(&path)?;
+        let found = check.check(&dataset.items).await?;
+        let contaminated: HashSet<usize> = found.iter().map(|(index, _)| *index).collect();
+        let report = ContaminationReport::new(
+            dataset.items.len(),
+            found.into_iter().map(|(_, overlap)| overlap).collect(),
+        );
+        let report_path = contamination::report_path(&path);
+        fs::write(&report_path, serde_json::to_string_pretty(&report)?)?;
+        println!("{}\n{}", path.display(), report);
+        println!("Wrote the overlapping records to {}", report_path.display());
+
+        if options.remove {
+            let clean: Vec<ProcessedItem> = dataset
+                .items
+                .into_iter()
+                .enumerate()
+                .filter(|(index, _)| !contaminated.contains(index))
+                .map(|(_, item)| item)
+                .collect();
+            let clean_path = contamination::decontaminated_path(&path);
+            schema::write_dataset(&clean_path, &clean)?;
+            println!(
+                "Wrote {} records without overlaps to {}",
+                clean.len(),
+                clean_path.display()
+            );
+        }
+        println!();
+    }
+    Ok(())
+}
+
+/// Settings of the `augment` command
+struct AugmentOptions<'a> {
+    pivots: &'a [String],
+    min_similarity: f32,
+    noise: usize,
+    limit: Option<usize>,
+}
+
+async fn run_augment_command(
+    output_dir: &Path,
+    paths: &[PathBuf],
+    provider: &dyn ModelProvider,
+    options: AugmentOptions<'_>,
+    startup: Startup,
+) -> anyhow::Result<()> {
+    // Noise needs neither the model nor embeddings
+    let engine = match options.pivots.is_empty() {
+        true => None,
+        false => {
+            prepare_generation(provider, startup).await?;
+            let config = load_config()?;
+            let engine = EmbeddingEngine::new(config.embedding.clone()).await?;
+            startup
+                .check(PreflightTargets {
+                    embedding: Some((&engine, &config.embedding)),
+                    ..Default::default()
+                })
+                .await?;
+            Some(engine)
+        }
+    };
+    let mut augmenter = Augmenter::new().with_noise(options.noise);
+    if let Some(engine) = &engine {
+        let translator =
+            BackTranslator::new(provider, engine).with_min_similarity(options.min_similarity);
+        augmenter = augmenter.with_back_translation(translator, options.pivots.to_vec());
+    }
+
+    for path in dataset_paths(output_dir, paths) {
+        let dataset = schema::read_dataset(&path)?;
+        println!(
+            "Augmenting {} records of {}",
+            dataset.items.len(),
+            path.display()
+        );
+        let (items, report) = augmenter.augment(dataset.items, options.limit).await?;
+        let augmented_path = augment::augmented_path(&path);
+        schema::write_dataset(&augmented_path, &items)?;
+        println!("{}", report);
+        println!(
+            "Wrote {} records to {}\n",
+            items.len(),
+            augmented_path.display()
+        );
+    }
+    Ok(())
+}
+
+async fn run_split_command(
+    output_dir: &Path,
+    paths: &[PathBuf],
+    test_percent: f64,
+    similarity: f32,
+    startup: Startup,
+) -> anyhow::Result<()> {
+    if !(0.0..=100.0).contains(&test_percent) {
+        return Err(anyhow::anyhow!("--test-percent must be between 0 and 100"))
+            .context(ExitReason::Config);
+    }
+    let config = load_config()?;
+    let engine = EmbeddingEngine::new(config.embedding.clone()).await?;
+    startup
+        .check(PreflightTargets {
+            embedding: Some((&engine, &config.embedding)),
+            ..Default::default()
+        })
+        .await?;
+
+    for path in dataset_paths(output_dir, paths) {
+        let dataset = schema::read_dataset(&path)?;
+        println!(
+            "Embedding and clustering {} questions of {}",
+            dataset.items.len(),
+            path.display()
+        );
+        let split =
+            split::split_by_cluster(dataset.items, &engine, similarity, test_percent).await?;
+        let (train_path, test_path) = split::split_paths(&path);
+        schema::write_dataset(&train_path, &split.train)?;
+        schema::write_dataset(&test_path, &split.test)?;
+        println!("{}", split.report);
+        println!(
+            "Wrote {} and {}\n",
+            train_path.display(),
+            test_path.display()
+        );
+    }
+    Ok(())
+}
+
+fn run_balance_command(
+    output_dir: &Path,
+    paths: &[PathBuf],
+    balance: Option<Balance>,
+) -> anyhow::Result<()> {
+    let balance = balance
+        .ok_or_else(|| anyhow::anyhow!("balance needs --max-source-share"))
+        .context(ExitReason::Config)?;
+    for path in dataset_paths(output_dir, paths) {
+        let balanced = balance.apply(schema::read_dataset(&path)?.items);
+        let balanced_path = balance::balanced_path(&path);
+        schema::write_dataset(&balanced_path, &balanced.items)?;
+        println!(
+            "{} balanced by {}: {}",
+            path.display(),
+            balance.by,
+            balanced
+        );
+        println!("Wrote {}\n", balanced_path.display());
+    }
+    Ok(())
+}
+
+async fn run_annotate_command(output_dir: &Path, action: &AnnotateAction) -> anyhow::Result<()> {
+    match action {
+        AnnotateAction::Push { target, paths } => {
+            let client = AnnotationClient::from_env(target.tool, &target.project)
+                .context(ExitReason::Config)?;
+            let mut items = schema::merge_datasets(&dataset_paths(output_dir, paths))?;
+            items.retain(|item| item.review_status.is_none());
+            let pushed = client.push(&items).await?;
+            println!(
+                "Sent {} records to {} project {}",
+                pushed, target.tool, target.project
+            );
+            if pushed < items.len() {
+                println!(
+                    "Left out {} records without an ID (datasets from before schema 1.2.0)",
+                    items.len() - pushed
+                );
+            }
+        }
+        AnnotateAction::Pull { target, paths } => {
+            let client = AnnotationClient::from_env(target.tool, &target.project)
+                .context(ExitReason::Config)?;
+            let annotations = client.pull().await?;
+            println!(
+                "{} annotated records in {} project {}",
+                annotations.len(),
+                target.tool,
+                target.project
+            );
+            for path in dataset_paths(output_dir, paths) {
+                let mut items = schema::read_dataset(&path)?.items;
+                let updated = annotation::apply_annotations(&mut items, &annotations);
+                let mut writer = DatasetWriter::create(&path)?;
+                writer.append(&items)?;
+                writer.finish()?;
+                println!("Updated {} records of {}", updated, path.display());
+            }
+        }
+    }
+    Ok(())
+}
+
+fn run_review_command(output_dir: &Path, paths: &[PathBuf]) -> anyhow::Result<()> {
+    let term = Term::stdout();
+    if !term.is_term() {
+        return Err(anyhow::anyhow!("review needs an interactive terminal"))
+            .context(ExitReason::Config);
+    }
+    for path in dataset_paths(output_dir, paths) {
+        let summary = review::review_file(&path, &term)?;
+        term.clear_screen()?;
+        println!("{}: {}", path.display(), summary);
+        if summary.stopped {
+            println!("Run review again to pick up where you left off");
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn run_validate_command(output_dir: &Path, paths: &[PathBuf], repair: bool) -> anyhow::Result<()> {
+    let mut invalid = 0;
+    for path in dataset_paths(output_dir, paths) {
+        let report = validate::validate_file(&path)
+            .with_context(|| format!("Failed to validate {}", path.display()))?;
+        println!(
+            "{} (schema {})",
+            path.display(),
+            report.header.schema_version
+        );
+        println!("{}", report);
+        if !report.is_valid() {
+            invalid += 1;
+        }
+        if repair {
+            let repaired = validate::repaired_path(&path);
+            schema::write_dataset(&repaired, &report.repaired)?;
+            println!(
+                "Wrote {} records to {}\n",
+                report.repaired.len(),
+                repaired.display()
+            );
+        }
+    }
+    if invalid > 0 {
+        return Err(anyhow::anyhow!("{} dataset(s) failed validation", invalid))
+            .context(ExitReason::InvalidDataset);
+    }
+    Ok(())
+}
+
+/// Upgrade dataset files to the current schema, each one entirely or not at all
+fn run_migrate_command(output_dir: &Path, paths: &[PathBuf], in_place: bool) -> anyhow::Result<()> {
+    for path in dataset_paths(output_dir, paths) {
+        let migration = migrate::migrate_file(&path)
+            .with_context(|| format!("Failed to migrate {}", path.display()))
+            .context(ExitReason::InvalidDataset)?;
+        let target = migrate::migrated_path(&path, in_place);
+        let mut writer = DatasetWriter::create(&target)?;
+        writer.append(&migration.items)?;
+        writer.finish()?;
+        println!("{}: {}", path.display(), migration.report);
+        println!("Wrote {}", target.display());
+    }
+    Ok(())
+}
+
+/// Command line value, else environment variable, else default
+fn setting(cli: Option<String>, name: &str, default: &str) -> String {
+    cli.or_else(|| env::var(name).ok())
+        .unwrap_or_else(|| default.to_string())
+}
+
+/// Settings from the environment, failing as a configuration error
+fn load_config() -> anyhow::Result<Config> {
+    Config::from_env().context(ExitReason::Config)
+}
+
+#[tokio::main]
+async fn main() {
+    // Load environment variables from .env file
+    dotenv().ok();
+
+    let matches = Args::command().get_matches();
+    let args = check_pipeline_args(&matches)
+        .and_then(|()| Args::from_arg_matches(&matches))
+        .unwrap_or_else(|e| e.exit());
+    // Runs are worth a notification; printing settings or completions is not
+    let webhook = match &args.command {
+        Some(Command::Config { .. } | Command::Completions { .. }) => None,
+        command => command
+            .as_ref()
+            .map_or(Some(&args.pipeline.generate), Command::generate_args)
+            .and_then(|generate| generate.notify_webhook.clone())
+            .or_else(|| env::var(WEBHOOK_URL_VAR).ok()),
+    }
+    .filter(|url| !url.is_empty());
+    let started_at = chrono::Utc::now();
+    let shutdown = Shutdown::new();
+    let mut report = RunReport::default();
+    let result = run(args, &shutdown, &mut report).await;
+    if let Err(e) = &result {
+        eprintln!("Error: {:#}", e);
+    }
+
+    let output_dir = report.output_dir.take();
+    let summary = report.finish(result.as_ref().err(), shutdown.is_requested());
+    if summary.reason != ExitReason::Success {
+        if let Some(dir) = &output_dir {
+            if let Err(e) = summary.write(dir) {
+                eprintln!("Could not write {}: {:#}", ERRORS_FILE, e);
+            }
+        }
+    }
+    if let Some(url) = webhook {
+        let notification = RunNotification::new(&summary, started_at, output_dir.as_deref());
+        if let Err(e) = notification.send(&url).await {
+            eprintln!("{:#}", e);
+        }
+    }
+    std::process::exit(summary.exit_code);
+}
+
+async fn run(args: Args, shutdown: &Shutdown, report: &mut RunReport) -> anyhow::Result<()> {
+    // Known before the config file is read, so its errors are reported too
+    report.output_dir = Some(setting(args.output_dir.clone(), "OUTPUT_DIR", "output").into());
+    let loaded = load_config_file(args.config.as_deref(), args.profile.as_deref())
+        .context(ExitReason::Config)?;
+    let command = args.command.unwrap_or(Command::Pipeline(args.pipeline));
+
+    if let Command::Config { action } = &command {
+        let cli: Vec<(&str, String)> = [
+            ("OUTPUT_DIR", &args.output_dir),
+            ("OLLAMA_ENDPOINT", &args.ollama_endpoint),
+            ("OLLAMA_MODEL", &args.model),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| value.clone().map(|value| (name, value)))
+        .collect();
+        return run_config_command(action, &cli, loaded.as_ref()).context(ExitReason::Config);
+    }
+    if let Command::Completions { shell } = &command {
+        return run_completions_command(*shell, loaded.as_ref());
+    }
+    // Flushes the remaining spans when the run ends
+    let _telemetry = Telemetry::init().context(ExitReason::Config)?;
+
+    // Use command line args if provided, otherwise fall back to env vars, then defaults
+    let output_dir = setting(args.output_dir, "OUTPUT_DIR", "output");
+    report.output_dir = Some(PathBuf::from(&output_dir));
+    let ollama_endpoint = setting(
+        args.ollama_endpoint,
+        "OLLAMA_ENDPOINT",
+        "http://localhost:11434",
+    );
+    let model = setting(args.model, "OLLAMA_MODEL", "m/qwen2514bmax");
+    let model_args = command.model_args();
+    let keep_alive = setting(
+        model_args.and_then(|model| model.keep_alive.clone()),
+        "OLLAMA_KEEP_ALIVE",
+        DEFAULT_KEEP_ALIVE,
+    );
+
+    // The backend, auth and retry settings come from the variables shared with the library
+    let llm_config = load_config()?.llm;
+    let model_provider = |model: &str| -> anyhow::Result<Arc<dyn ModelProvider>> {
+        if llm_config.backend == LLMBackend::Mock {
+            return Ok(Arc::new(MockBackend::new(model)));
+        }
+        let provider = OllamaProvider::new(ollama_endpoint.clone(), model.to_string())
+            .with_keep_alive(Some(keep_alive.clone()))
+            .with_retry(llm_config.retry)
+            .with_auth(llm_config.api_key.as_deref(), &llm_config.headers)
+            .context(ExitReason::Config)?;
+        Ok(Arc::new(provider))
+    };
+    let provider = model_provider(&model)?;
+    let client = |stream: bool, max_output_tokens: usize| {
+        DefaultOllamaClient::from_provider(Arc::clone(&provider))
+            .with_streaming(stream)
+            .with_token_budget(TokenBudget { max_output_tokens })
+    };
+    let startup = Startup {
+        preflight: !command
+            .service_args()
+            .is_some_and(|services| services.skip_preflight)
+            && !args.test_mode,
+        pull_model: !model_args.is_some_and(|model| model.no_pull) && !args.test_mode,
+    };
+
+    let generation = |generate: &GenerateArgs| -> anyhow::Result<Generation> {
+        Ok(Generation {
+            output_dir: output_dir.clone(),
+            backend: llm_config.backend,
+            ollama_endpoint: ollama_endpoint.clone(),
+            model: model.clone(),
+            keep_alive: keep_alive.clone(),
+            incremental: generate.incremental || generate.watch,
+            force: generate.force,
+            structured_budget: generate.structured_budget,
+            density: generate.density.density()?,
+            stream: generate.stream,
+            max_output_tokens: generate.max_output_tokens as usize,
+            shutdown: shutdown.clone(),
+            limits: RunLimits::new(
+                generate.max_files,
+                generate.max_questions,
+                generate.sample,
+                generate.sample_seed,
+            ),
+            size_limit: SizeLimit {
+                max_bytes: generate.max_file_size,
+                policy: generate.oversized,
+            },
+            eval_split: generate.eval.map(EvalSplit::new),
+            rejection: (generate.candidates > 1).then_some(RejectionSampling {
+                candidates: generate.candidates as usize,
+                scoring: generate.candidate_scoring,
+                keep_rejected: generate.keep_rejected,
+            }),
+            student_agreement: generate.student_agreement,
+            balance: generate.balance.balance(),
+            strip_boilerplate: generate.strip_boilerplate,
+            glossary: generate.glossary,
+            target_tags: generate.target_tags.clone(),
+            only_sections: generate
+                .only_sections
+                .iter()
+                .map(|heading| heading.trim().to_string())
+                .filter(|heading| !heading.is_empty())
+                .collect(),
+            only_matching: generate.only_matching.clone(),
+            top_up: generate.top_up,
+            rich_schema: generate.rich_schema,
+            publish: generate
+                .publish
+                .clone()
+                .or_else(|| env::var(PUBLISH_URL_VAR).ok())
+                .filter(|url| !url.is_empty())
+                .map(|url| url.parse())
+                .transpose()
+                .context(ExitReason::Config)?,
+            run_name: generate.run_name.clone(),
+        })
+    };
+    let student = |generate: &GenerateArgs| -> anyhow::Result<Option<Arc<dyn ModelProvider>>> {
+        generate
+            .student_model
+            .as_deref()
+            .map(model_provider)
+            .transpose()
+    };
+    // Remote sources are polled at this interval in --watch mode
+    let watch_interval = |generate: &GenerateArgs| {
+        generate
+            .watch
+            .then(|| Duration::from_secs(generate.watch_interval.max(1)))
+    };
+
+    match &command {
+        Command::Pipeline(pipeline) => {
+            let generate = &pipeline.generate;
+            prepare_generation(provider.as_ref(), startup).await?;
+            let student = student(generate)?;
+            if let Some(student) = &student {
+                prepare_generation(student.as_ref(), startup).await?;
+            }
+            let sources = select_sources(
+                &pipeline.sources.sources,
+                &pipeline.sources.source_types,
+                args.test_mode,
+            )
+            .await?;
+            let inputs = if sources.is_empty() {
+                println!("No new sources added. Processing existing files in output directory...");
+                Inputs::Existing(
+                    existing_inputs(Path::new(&output_dir)),
+                    recorded_sources(Path::new(&output_dir))?,
+                )
+            } else {
+                Inputs::Collect(sources)
+            };
+            run_generate_command(
+                &generation(generate)?,
+                provider.as_ref(),
+                client(generate.stream, generate.max_output_tokens as usize),
+                student.as_ref(),
+                inputs,
+                watch_interval(generate),
+                report,
+            )
+            .await
+        }
+        Command::Collect(sources) => {
+            let sources =
+                select_sources(&sources.sources, &sources.source_types, args.test_mode).await?;
+            let (files, _) = run_collect_command(Path::new(&output_dir), sources).await?;
+            println!("Collected {} files into {}", files.len(), output_dir);
+            Ok(())
+        }
+        Command::Generate(generate) => {
+            prepare_generation(provider.as_ref(), startup).await?;
+            let student = student(generate)?;
+            if let Some(student) = &student {
+                prepare_generation(student.as_ref(), startup).await?;
+            }
+            let inputs = Inputs::Existing(
+                existing_inputs(Path::new(&output_dir)),
+                recorded_sources(Path::new(&output_dir))?,
+            );
+            run_generate_command(
+                &generation(generate)?,
+                provider.as_ref(),
+                client(generate.stream, generate.max_output_tokens as usize),
+                student.as_ref(),
+                inputs,
+                watch_interval(generate),
+                report,
+            )
+            .await
+        }
+        Command::Coordinate {
+            listen,
+            lease,
+            sources,
+            generate,
+        } => {
+            let sources =
+                select_sources(&sources.sources, &sources.source_types, args.test_mode).await?;
+            let inputs = if sources.is_empty() {
+                Inputs::Existing(
+                    existing_inputs(Path::new(&output_dir)),
+                    recorded_sources(Path::new(&output_dir))?,
+                )
+            } else {
+                Inputs::Collect(sources)
+            };
+            let lease = Duration::from_secs(lease * 60);
+            run_coordinate_command(&generation(generate)?, inputs, *listen, lease, report).await
+        }
+        Command::Work {
+            coordinator,
+            name,
+            generate,
+        } => {
+            prepare_generation(provider.as_ref(), startup).await?;
+            let student = student(generate)?;
+            if let Some(student) = &student {
+                prepare_generation(student.as_ref(), startup).await?;
+            }
+            run_work_command(
+                &generation(generate)?,
+                client(generate.stream, generate.max_output_tokens as usize),
+                student.as_ref(),
+                coordinator,
+                name.as_deref(),
+            )
+            .await
+        }
+        Command::Export {
+            inputs,
+            format,
+            output,
+            curriculum,
+        } => run_export_command(
+            Path::new(&output_dir),
+            inputs,
+            *format,
+            output.as_deref(),
+            *curriculum,
+        ),
+        Command::Import {
+            inputs,
+            format,
+            output,
+        } => run_import_command(Path::new(&output_dir), inputs, *format, output.as_deref()),
+        Command::Stats { paths } => run_stats_command(Path::new(&output_dir), paths),
+        Command::Validate { paths, repair } => {
+            run_validate_command(Path::new(&output_dir), paths, *repair)
+        }
+        Command::Migrate { paths, in_place } => {
+            run_migrate_command(Path::new(&output_dir), paths, *in_place)
+        }
+        Command::Evaluate {
+            paths,
+            judge_model,
+            limit,
+            ..
+        } => {
+            let judge = match judge_model {
+                Some(judge_model) => model_provider(judge_model)?,
+                None => Arc::clone(&provider),
+            };
+            run_evaluate_command(
+                Path::new(&output_dir),
+                paths,
+                judge.as_ref(),
+                *limit,
+                startup,
+            )
+            .await
+        }
+        Command::Contamination {
+            paths,
+            benchmarks,
+            ngram,
+            embeddings,
+            similarity,
+            remove,
+            ..
+        } => {
+            let options = ContaminationOptions {
+                benchmarks,
+                ngram: *ngram,
+                similarity: embeddings.then_some(*similarity),
+                remove: *remove,
+            };
+            run_contamination_command(Path::new(&output_dir), paths, options, startup).await
+        }
+        Command::Augment {
+            paths,
+            pivots,
+            min_similarity,
+            noise,
+            limit,
+            ..
+        } => {
+            let options = AugmentOptions {
+                pivots,
+                min_similarity: *min_similarity,
+                noise: noise.unwrap_or(0),
+                limit: *limit,
+            };
+            run_augment_command(
+                Path::new(&output_dir),
+                paths,
+                provider.as_ref(),
+                options,
+                startup,
+            )
+            .await
+        }
+        Command::Split {
+            paths,
+            test_percent,
+            similarity,
+            ..
+        } => {
+            run_split_command(
+                Path::new(&output_dir),
+                paths,
+                *test_percent,
+                *similarity,
+                startup,
+            )
+            .await
+        }
+        Command::Compare { a, b } => run_compare_command(Path::new(&output_dir), a, b),
+        Command::Coverage { paths, density } => {
+            run_coverage_command(Path::new(&output_dir), paths, density.density()?)
+        }
+        Command::Balance { paths, balance } => {
+            run_balance_command(Path::new(&output_dir), paths, balance.balance())
+        }
+        Command::Annotate { action } => run_annotate_command(Path::new(&output_dir), action).await,
+        Command::Clean { dry_run } => run_clean_command(Path::new(&output_dir), *dry_run),
+        Command::Review { paths } => run_review_command(Path::new(&output_dir), paths),
+        Command::Lineage { id, paths } => run_lineage_command(Path::new(&output_dir), id, paths),
+        Command::Graph {
+            path,
+            dot,
+            json,
+            section,
+            keywords,
+            tags,
+        } => run_graph_command(
+            path,
+            GraphView {
+                dot: *dot,
+                json: *json,
+                section: section.as_deref(),
+                keywords: *keywords,
+                tags,
+            },
+        ),
+        Command::Index { path, recreate, .. } => run_index_command(path, *recreate, startup).await,
+        Command::Search {
+            query,
+            limit,
+            document,
+            node_type,
+            tag,
+            ..
+        } => {
+            let filter = SearchFilter {
+                document: document.clone(),
+                node_type: node_type.clone(),
+                tag: tag.clone(),
+            };
+            run_search_command(query, *limit, &filter, startup).await
+        }
+        Command::Rag {
+            path,
+            top_k,
+            min_words,
+            negatives,
+            density,
+            ..
+        } => {
+            run_rag_command(
+                path,
+                RagConfig {
+                    top_k: *top_k,
+                    min_words: *min_words,
+                    density: density.density()?,
+                    negatives: negatives.unwrap_or(0),
+                    ..RagConfig::default()
+                },
+                &output_dir,
+                &client(false, DEFAULT_MAX_OUTPUT_TOKENS),
+                &model,
+                startup,
+            )
+            .await
+        }
+        Command::Snapshot { path, .. } => run_snapshot_command(path, startup).await,
+        Command::Restore { path, recreate, .. } => {
+            run_restore_command(path, *recreate, startup).await
+        }
+        Command::Benchmark {
+            requests,
+            concurrency,
+            skip_embeddings,
+            skip_generation,
+            ..
+        } => {
+            let options = BenchmarkOptions {
+                requests: *requests,
+                concurrency,
+                skip_embeddings: *skip_embeddings,
+                skip_generation: *skip_generation,
+            };
+            run_benchmark_command(provider.as_ref(), options, startup).await
+        }
+        Command::Config { .. } | Command::Completions { .. } => {
+            unreachable!("handled before the clients are built")
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::datasource::SourceProvenance;
+use crate::external::ProviderUsage;
+use crate::hash::hash_text;
+use crate::schema::SCHEMA_VERSION;
+
+/// File name of the manifest written next to the dataset
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// Name and version of the tool that produced a dataset
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToolInfo {
+    pub name: String,
+    pub version: String,
+    pub schema_version: String,
+}
+
+impl Default for ToolInfo {
+    fn default() -> Self {
+        Self {
+            name: env!("CARGO_PKG_NAME").to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            schema_version: SCHEMA_VERSION.to_string(),
+        }
+    }
+}
+
+/// Provenance record describing how a dataset was produced
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunManifest {
+    pub tool: ToolInfo,
+    pub started_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<DateTime<Utc>>,
+    /// Snapshot of the effective configuration for the run
+    pub config: Value,
+    /// Models used for each role (e.g. "generation", "embedding")
+    pub models: BTreeMap<String, String>,
+    /// SHA-256 of each prompt template, keyed by template name
+    pub prompt_templates: BTreeMap<String, String>,
+    pub sources: Vec<SourceProvenance>,
+    pub outputs: Vec<PathBuf>,
+    pub item_count: usize,
+    /// Whether the run was stopped early by Ctrl-C or SIGTERM
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub interrupted: bool,
+    /// Requests and tokens spent per model role
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub usage: BTreeMap<String, ProviderUsage>,
+}
+
+impl RunManifest {
+    /// Start a new manifest for a run beginning now
+    pub fn new(config: Value) -> Self {
+        Self {
+            tool: ToolInfo::default(),
+            started_at: Utc::now(),
+            finished_at: None,
+            config,
+            models: BTreeMap::new(),
+            prompt_templates: BTreeMap::new(),
+            sources: Vec::new(),
+            outputs: Vec::new(),
+            item_count: 0,
+            interrupted: false,
+            usage: BTreeMap::new(),
+        }
+    }
+
+    /// Record the model used for a given role
+    pub fn add_model(&mut self, role: &str, model: &str) {
+        self.models.insert(role.to_string(), model.to_string());
+    }
+
+    /// Record the hash of a prompt template
+    pub fn add_prompt_template(&mut self, name: &str, template: &str) {
+        self.prompt_templates
+            .insert(name.to_string(), hash_text(template));
+    }
+
+    /// Record a data source that contributed to the run
+    pub fn add_source(&mut self, source: SourceProvenance) {
+        self.sources.push(source);
+    }
+
+    /// Record an output file produced by the run
+    pub fn add_output(&mut self, path: &Path) {
+        self.outputs.push(path.to_path_buf());
+    }
+
+    /// Record the requests and tokens a model role used
+    pub fn add_usage(&mut self, role: &str, usage: ProviderUsage) {
+        self.usage.insert(role.to_string(), usage);
+    }
+
+    /// Mark the run as finished with the given number of records
+    pub fn finish(&mut self, item_count: usize) {
+        self.item_count = item_count;
+        self.finished_at = Some(Utc::now());
+    }
+
+    /// Write the manifest into the given directory
+    pub fn write(&self, dir: &Path) -> Result<PathBuf> {
+        let path = dir.join(MANIFEST_FILE);
+        fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(path)
+    }
+
+    /// Load a previously written manifest
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_manifest_round_trip() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let mut manifest = RunManifest::new(json!({"model": "mistral"}));
+        manifest.add_model("generation", "mistral");
+        manifest.add_prompt_template("documentation", "Generate {count} questions");
+        manifest.add_source(SourceProvenance {
+            kind: "url".to_string(),
+            location: "https://example.com/doc.md".to_string(),
+            revision: None,
+            files: Vec::new(),
+        });
+        manifest.finish(42);
+
+        let path = manifest.write(temp_dir.path()).unwrap();
+        let loaded = RunManifest::load(&path).unwrap();
+
+        assert_eq!(loaded.tool, ToolInfo::default());
+        assert_eq!(loaded.models["generation"], "mistral");
+        assert_eq!(
+            loaded.prompt_templates["documentation"],
+            hash_text("Generate {count} questions")
+        );
+        assert_eq!(loaded.sources.len(), 1);
+        assert_eq!(loaded.item_count, 42);
+        assert!(loaded.finished_at.is_some());
+    }
+}
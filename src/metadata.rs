@@ -0,0 +1,220 @@
+//! Optional metadata columns of a record, written with `--rich-schema`:
+//! where it came from, which model and prompt template produced it, when,
+//! in what language, with the tags of its source nodes and a quality score.
+//! Without the flag records keep the minimal question/answer format.
+//!
+//! Records made by `augment` are marked as such whether or not the flag was
+//! given, so they can be told apart from the records they were made from.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
+use uuid::Uuid;
+
+/// Metadata columns of a record; every one is left out when unset
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RecordMetadata {
+    /// Input file the record was generated from, named as in its lineage
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Model that wrote the question and answer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Name of the prompt template the model was asked with, as listed in
+    /// the run manifest
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_template: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<DateTime<Utc>>,
+    /// ISO 639-1 code of the question's language, when it could be told
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lang: Option<String>,
+    /// Keyword, entity and parser tags of the graph nodes the record was
+    /// generated from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+    /// How well the answer is grounded in its source, from 0 to 1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub score: Option<f64>,
+    /// How the record was derived from another one, e.g.
+    /// `back_translation:de`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub augmentation: Option<String>,
+    /// ID of the record this one was derived from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub augmented_from: Option<Uuid>,
+    /// Set on variants written with typos, missing punctuation or casual
+    /// phrasing, as users type
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub noisy: Option<bool>,
+}
+
+impl RecordMetadata {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Fill the columns that are unset from `other`, so records kept from an
+    /// earlier run keep when and how they were generated
+    pub fn fill_from(&mut self, other: RecordMetadata) {
+        self.source = self.source.take().or(other.source);
+        self.model = self.model.take().or(other.model);
+        self.prompt_template = self.prompt_template.take().or(other.prompt_template);
+        self.created_at = self.created_at.or(other.created_at);
+        self.lang = self.lang.take().or(other.lang);
+        self.tags = self.tags.take().or(other.tags);
+        self.score = self.score.or(other.score);
+        self.augmentation = self.augmentation.take().or(other.augmentation);
+        self.augmented_from = self.augmented_from.or(other.augmented_from);
+        self.noisy = self.noisy.or(other.noisy);
+    }
+}
+
+/// Languages told apart in Latin script by their most common words
+const LATIN_PROFILES: &[(&str, &[&str])] = &[
+    (
+        "en",
+        &[
+            "the", "is", "are", "and", "of", "to", "what", "how", "which", "does", "do", "in",
+            "with", "for",
+        ],
+    ),
+    (
+        "de",
+        &[
+            "der", "die", "das", "ist", "und", "wie", "was", "welche", "mit", "für", "nicht",
+            "ein", "eine", "wird",
+        ],
+    ),
+    (
+        "fr",
+        &[
+            "le", "la", "les", "est", "et", "des", "que", "quel", "quelle", "comment", "une",
+            "pour", "dans", "avec",
+        ],
+    ),
+    (
+        "es",
+        &[
+            "el", "los", "las", "es", "y", "qué", "cómo", "cuál", "una", "para", "con", "del",
+            "por", "se",
+        ],
+    ),
+    (
+        "it",
+        &[
+            "il", "lo", "gli", "è", "e", "che", "come", "quale", "una", "per", "con", "del",
+            "della", "non",
+        ],
+    ),
+    (
+        "pt",
+        &[
+            "o", "os", "as", "é", "e", "que", "como", "qual", "uma", "para", "com", "do", "da",
+            "não",
+        ],
+    ),
+    (
+        "nl",
+        &[
+            "de", "het", "een", "is", "en", "wat", "hoe", "welke", "van", "voor", "met", "niet",
+            "wordt", "zijn",
+        ],
+    ),
+];
+
+/// Language written in a script used for one language only
+fn script_language(c: char) -> Option<&'static str> {
+    match c {
+        '\u{3040}'..='\u{30FF}' => Some("ja"),
+        '\u{AC00}'..='\u{D7AF}' | '\u{1100}'..='\u{11FF}' => Some("ko"),
+        '\u{4E00}'..='\u{9FFF}' | '\u{3400}'..='\u{4DBF}' => Some("zh"),
+        '\u{0400}'..='\u{04FF}' => Some("ru"),
+        '\u{0370}'..='\u{03FF}' => Some("el"),
+        '\u{0590}'..='\u{05FF}' => Some("he"),
+        '\u{0600}'..='\u{06FF}' => Some("ar"),
+        '\u{0900}'..='\u{097F}' => Some("hi"),
+        '\u{0E00}'..='\u{0E7F}' => Some("th"),
+        _ => None,
+    }
+}
+
+/// ISO 639-1 code of the language a text is most likely written in
+///
+/// Non-Latin scripts decide the language by themselves: any kana makes a
+/// text Japanese, otherwise the most frequent script wins. Latin text is
+/// matched against the common words of a few European languages. Returns
+/// `None` when nothing matches, e.g. for a text of code or numbers.
+pub fn detect_lang(text: &str) -> Option<&'static str> {
+    let mut scripts: Vec<(&str, usize)> = Vec::new();
+    for lang in text.chars().filter_map(script_language) {
+        match scripts.iter_mut().find(|(seen, _)| *seen == lang) {
+            Some((_, count)) => *count += 1,
+            None => scripts.push((lang, 1)),
+        }
+    }
+    // Japanese mixes kana with Han ideographs
+    if scripts.iter().any(|(lang, _)| *lang == "ja") {
+        return Some("ja");
+    }
+    if let Some((lang, _)) = scripts.iter().max_by_key(|(_, count)| *count) {
+        return Some(lang);
+    }
+
+    let words: Vec<String> = text.unicode_words().map(str::to_lowercase).collect();
+    LATIN_PROFILES
+        .iter()
+        .map(|(lang, common)| {
+            let hits = words
+                .iter()
+                .filter(|word| common.contains(&word.as_str()))
+                .count();
+            (*lang, hits)
+        })
+        .filter(|(_, hits)| *hits > 0)
+        // The first of equally likely languages wins
+        .rev()
+        .max_by_key(|(_, hits)| *hits)
+        .map(|(lang, _)| lang)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_lang() {
+        assert_eq!(detect_lang("What does the --force flag do?"), Some("en"));
+        assert_eq!(
+            detect_lang("Wie wird der Cache für die Embeddings konfiguriert?"),
+            Some("de")
+        );
+        assert_eq!(
+            detect_lang("Comment configurer le cache pour les embeddings ?"),
+            Some("fr")
+        );
+        assert_eq!(detect_lang("如何配置缓存？"), Some("zh"));
+        assert_eq!(detect_lang("キャッシュの設定方法は？"), Some("ja"));
+        assert_eq!(detect_lang("Как настроить кэш?"), Some("ru"));
+        assert_eq!(detect_lang("cargo build --release"), None);
+    }
+
+    #[test]
+    fn test_metadata_is_left_out_when_unset() {
+        let metadata = RecordMetadata::default();
+        assert!(metadata.is_empty());
+        assert_eq!(serde_json::to_string(&metadata).unwrap(), "{}");
+
+        let mut kept = RecordMetadata {
+            model: Some("llama3".to_string()),
+            ..RecordMetadata::default()
+        };
+        kept.fill_from(RecordMetadata {
+            model: Some("mistral".to_string()),
+            lang: Some("en".to_string()),
+            ..RecordMetadata::default()
+        });
+        assert_eq!(kept.model.as_deref(), Some("llama3"));
+        assert_eq!(kept.lang.as_deref(), Some("en"));
+    }
+}
@@ -0,0 +1,320 @@
+//! Upgrades of datasets written by older versions of the tool, from plain
+//! question/answer records (headerless JSONL, or the JSON arrays the first
+//! versions wrote) to the current schema.
+//!
+//! Records keep every field they have, IDs included. Those without an ID get
+//! a deterministic one, so migrating the same file twice gives the same IDs,
+//! and unset columns that can be told from the record itself are filled: its
+//! difficulty, the language of its question and the document of its lineage.
+
+use anyhow::{bail, Result};
+use serde_json::Value;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::difficulty;
+use crate::hash::hash_text;
+use crate::metadata::detect_lang;
+use crate::processor::ProcessedItem;
+use crate::schema::{self, SchemaError, SchemaHeader, SCHEMA_VERSION};
+
+/// Span that IDs of migrated records are made with, with a hash of the answer
+pub const MIGRATED: &str = "migrated";
+
+/// Records that could not be migrated listed before the rest are only counted
+const LISTED_FAILURES: usize = 10;
+
+/// What migrating a dataset changed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MigrationReport {
+    /// Schema version the dataset was written with
+    pub from_version: String,
+    pub records: usize,
+    pub ids_assigned: usize,
+    pub difficulties: usize,
+    pub languages: usize,
+    pub sources: usize,
+}
+
+impl fmt::Display for MigrationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} records from schema {} to {}: {} IDs assigned, {} difficulties, \
+             {} languages and {} sources filled",
+            self.records,
+            self.from_version,
+            SCHEMA_VERSION,
+            self.ids_assigned,
+            self.difficulties,
+            self.languages,
+            self.sources
+        )
+    }
+}
+
+/// A dataset upgraded to the current schema
+#[derive(Debug, Clone)]
+pub struct Migration {
+    pub items: Vec<ProcessedItem>,
+    pub report: MigrationReport,
+}
+
+/// Numeric components of a version, e.g. `[1, 10, 0]`
+fn version_parts(version: &str) -> Result<Vec<u64>, SchemaError> {
+    version
+        .split('.')
+        .map(|part| part.parse())
+        .collect::<Result<_, _>>()
+        .map_err(|_| SchemaError::InvalidVersion(version.to_string()))
+}
+
+/// Refuse datasets written by a newer version of the tool, whose fields
+/// this one would drop
+fn check_not_newer(header: &SchemaHeader) -> Result<(), SchemaError> {
+    if version_parts(&header.schema_version)? > version_parts(SCHEMA_VERSION)? {
+        return Err(SchemaError::IncompatibleVersion {
+            expected: SCHEMA_VERSION.to_string(),
+            found: header.schema_version.clone(),
+        });
+    }
+    Ok(())
+}
+
+/// Numbered records of a dataset, or why a line is not one
+type Records = Vec<(usize, serde_json::Result<Value>)>;
+
+/// The header and the numbered records of a JSONL dataset or a JSON array
+fn read_records(content: &str) -> Result<(SchemaHeader, Records)> {
+    if content.trim_start().starts_with('[') {
+        let records: Vec<Value> = serde_json::from_str(content)?;
+        let numbered = records.into_iter().enumerate();
+        return Ok((
+            SchemaHeader::legacy(),
+            numbered
+                .map(|(index, record)| (index + 1, Ok(record)))
+                .collect(),
+        ));
+    }
+
+    let mut header = None;
+    let mut records = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if header.is_none() && records.is_empty() {
+            if let Some(found) = SchemaHeader::from_line(line) {
+                header = Some(found);
+                continue;
+            }
+        }
+        records.push((index + 1, serde_json::from_str(line)));
+    }
+    Ok((header.unwrap_or_else(SchemaHeader::legacy), records))
+}
+
+/// Fill the unset columns of a record from the record itself; `source`
+/// names the dataset, for IDs of records without a lineage
+pub fn upgrade(item: &mut ProcessedItem, source: &str, report: &mut MigrationReport) {
+    if item.metadata.source.is_none() {
+        if let Some(lineage) = &item.lineage {
+            item.metadata.source = Some(lineage.document.clone());
+            report.sources += 1;
+        }
+    }
+    if item.id.is_none() {
+        let source = item
+            .metadata
+            .source
+            .clone()
+            .unwrap_or_else(|| source.to_string());
+        let span = format!("{}:{}", MIGRATED, hash_text(&item.answer));
+        item.assign_id(&source, &span);
+        report.ids_assigned += 1;
+    }
+    if item.difficulty.is_none() {
+        item.difficulty = Some(difficulty::estimate(item));
+        report.difficulties += 1;
+    }
+    if item.metadata.lang.is_none() {
+        if let Some(lang) = detect_lang(&item.question) {
+            item.metadata.lang = Some(lang.to_string());
+            report.languages += 1;
+        }
+    }
+}
+
+/// Upgrade the records of a dataset file
+///
+/// Nothing is migrated unless every record is: lines that are not records
+/// or break the schema fail the migration, naming them, so no record is
+/// lost on the way (`validate --repair` drops them first).
+pub fn migrate_file(path: &Path) -> Result<Migration> {
+    let (header, records) = read_records(&fs::read_to_string(path)?)?;
+    check_not_newer(&header)?;
+    let source = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let mut report = MigrationReport {
+        from_version: header.schema_version,
+        ..MigrationReport::default()
+    };
+    let mut items = Vec::with_capacity(records.len());
+    let mut failures = Vec::new();
+    for (line, record) in records {
+        let parsed = record.and_then(serde_json::from_value::<ProcessedItem>);
+        let mut item = match parsed {
+            Ok(item) => item,
+            Err(e) => {
+                failures.push(format!("line {}: {}", line, e));
+                continue;
+            }
+        };
+        upgrade(&mut item, &source, &mut report);
+        match schema::validate_item(&item) {
+            Ok(()) => items.push(item),
+            Err(e) => failures.push(format!("line {}: {}", line, e)),
+        }
+    }
+    if !failures.is_empty() {
+        let mut listed = failures[..failures.len().min(LISTED_FAILURES)].join("\n  ");
+        if failures.len() > LISTED_FAILURES {
+            listed.push_str(&format!(
+                "\n  ... and {} more",
+                failures.len() - LISTED_FAILURES
+            ));
+        }
+        bail!(
+            "{} records of {} cannot be migrated; run `validate --repair` on it first:\n  {}",
+            failures.len(),
+            path.display(),
+            listed
+        );
+    }
+    report.records = items.len();
+    Ok(Migration { items, report })
+}
+
+/// Where the migrated copy of a dataset goes: next to it, as
+/// `NAME.migrated.jsonl`, or as `NAME.jsonl` when replacing it in place
+pub fn migrated_path(path: &Path, in_place: bool) -> PathBuf {
+    if in_place {
+        return path.with_extension("jsonl");
+    }
+    let stem = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("dataset");
+    path.with_file_name(format!("{}.migrated.jsonl", stem))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::difficulty::Difficulty;
+    use crate::lineage::Lineage;
+
+    #[test]
+    fn test_migrates_legacy_records() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("all_qa.jsonl");
+        fs::write(
+            &path,
+            "{\"question\":\"What is the default port of the server?\",\"answer\":\"8080.\"}\n\
+             \n\
+             {\"question\":\"Why is TLS required?\",\"answer\":\"To encrypt traffic.\"}\n",
+        )
+        .unwrap();
+
+        let migration = migrate_file(&path).unwrap();
+        let report = &migration.report;
+        assert_eq!(report.from_version, schema::LEGACY_SCHEMA_VERSION);
+        assert_eq!((report.records, report.ids_assigned), (2, 2));
+        assert_eq!(report.difficulties, 2);
+        assert_eq!(report.languages, 2);
+        let item = &migration.items[0];
+        assert_eq!(item.difficulty, Some(Difficulty::Easy));
+        assert_eq!(item.metadata.lang.as_deref(), Some("en"));
+
+        // The same file always gets the same IDs
+        let again = migrate_file(&path).unwrap();
+        assert_eq!(again.items[0].id, item.id);
+        assert_ne!(again.items[1].id, item.id);
+    }
+
+    #[test]
+    fn test_keeps_ids_and_set_columns() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("all_qa.jsonl");
+        let mut item = ProcessedItem {
+            difficulty: Some(Difficulty::Hard),
+            ..ProcessedItem::new("Q?", "A")
+        };
+        item.assign_id("guide.md", "abc");
+        item.lineage = Some(Lineage::of_span("docs/guide.md", 0..10, None));
+        let id = item.id;
+        fs::write(
+            &path,
+            format!(
+                "{{\"schema\":\"{}\",\"schema_version\":\"1.3.0\"}}\n{}\n",
+                schema::SCHEMA_NAME,
+                serde_json::to_string(&item).unwrap()
+            ),
+        )
+        .unwrap();
+
+        let migration = migrate_file(&path).unwrap();
+        assert_eq!(migration.report.from_version, "1.3.0");
+        assert_eq!(migration.report.ids_assigned, 0);
+        assert_eq!(migration.report.sources, 1);
+        let migrated = &migration.items[0];
+        assert_eq!(migrated.id, id);
+        assert_eq!(migrated.difficulty, Some(Difficulty::Hard));
+        assert_eq!(migrated.metadata.source.as_deref(), Some("docs/guide.md"));
+    }
+
+    #[test]
+    fn test_reads_json_arrays() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("guide_qa.json");
+        fs::write(&path, r#"[{"question": "Q1?", "answer": "A1"}]"#).unwrap();
+        let migration = migrate_file(&path).unwrap();
+        assert_eq!(migration.items[0].question, "Q1?");
+        assert_eq!(
+            migrated_path(&path, true),
+            temp_dir.path().join("guide_qa.jsonl")
+        );
+        assert_eq!(
+            migrated_path(&path, false),
+            temp_dir.path().join("guide_qa.migrated.jsonl")
+        );
+    }
+
+    #[test]
+    fn test_refuses_unreadable_and_newer_datasets() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("all_qa.jsonl");
+        fs::write(
+            &path,
+            "{\"question\":\"Q?\",\"answer\":\"A\"}\nnot json\n{\"question\":\"\",\"answer\":\"A\"}\n",
+        )
+        .unwrap();
+        let error = migrate_file(&path).unwrap_err().to_string();
+        assert!(error.contains("2 records"));
+        assert!(error.contains("line 2") && error.contains("line 3"));
+
+        fs::write(
+            &path,
+            format!(
+                "{{\"schema\":\"{}\",\"schema_version\":\"1.99.0\"}}\n",
+                schema::SCHEMA_NAME
+            ),
+        )
+        .unwrap();
+        assert!(migrate_file(&path).is_err());
+    }
+}
@@ -0,0 +1,186 @@
+//! Notification posted to a webhook when a run finishes or aborts, so teams
+//! running multi-hour builds unattended learn how they ended.
+//!
+//! The summary is sent as JSON. Its `text` field holds a one-line summary
+//! that Slack incoming webhooks show as the message; other receivers can
+//! read the structured fields.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::exit::{ErrorSummary, ExitReason, FileFailure};
+use crate::manifest::{RunManifest, MANIFEST_FILE};
+
+/// Webhook to notify when a run ends
+pub const WEBHOOK_URL_VAR: &str = "NOTIFY_WEBHOOK_URL";
+
+/// Failed files listed in a notification; the rest are only counted
+const MAX_LISTED_FAILURES: usize = 10;
+
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Summary of a run as posted to the webhook
+#[derive(Debug, Clone, Serialize)]
+pub struct RunNotification {
+    /// One-line summary, shown by Slack as the message
+    pub text: String,
+    pub reason: ExitReason,
+    pub exit_code: i32,
+    pub started_at: DateTime<Utc>,
+    pub duration_secs: u64,
+    pub files_processed: usize,
+    pub files_failed: usize,
+    /// The first failed files with their errors
+    pub failures: Vec<FileFailure>,
+    /// Records written, when the run got as far as writing its manifest
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub records: Option<usize>,
+    pub output_dir: Option<PathBuf>,
+    pub outputs: Vec<PathBuf>,
+    /// The error that ended the run, if it did not run to completion
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl RunNotification {
+    /// Summarize a run that began at `started_at`; record counts and output
+    /// paths come from the run manifest in `output_dir` if this run wrote one
+    pub fn new(
+        summary: &ErrorSummary,
+        started_at: DateTime<Utc>,
+        output_dir: Option<&Path>,
+    ) -> Self {
+        let manifest = output_dir
+            .and_then(|dir| RunManifest::load(&dir.join(MANIFEST_FILE)).ok())
+            .filter(|manifest| manifest.started_at >= started_at);
+        let duration_secs = (Utc::now() - started_at).num_seconds().max(0) as u64;
+        let records = manifest.as_ref().map(|manifest| manifest.item_count);
+
+        let mut text = format!(
+            "{} run {} after {}: {} files processed",
+            env!("CARGO_PKG_NAME"),
+            match summary.reason {
+                ExitReason::Success => "succeeded".to_string(),
+                reason => format!("ended with {} (exit code {})", reason, summary.exit_code),
+            },
+            format_duration(duration_secs),
+            summary.files_processed,
+        );
+        if !summary.failed_files.is_empty() {
+            text.push_str(&format!(", {} failed", summary.failed_files.len()));
+        }
+        if let Some(records) = records {
+            text.push_str(&format!(", {} records", records));
+        }
+        if let Some(dir) = output_dir {
+            text.push_str(&format!(" in {}", dir.display()));
+        }
+        if let Some(error) = &summary.error {
+            text.push_str(&format!("\nError: {}", error));
+        }
+
+        Self {
+            text,
+            reason: summary.reason,
+            exit_code: summary.exit_code,
+            started_at,
+            duration_secs,
+            files_processed: summary.files_processed,
+            files_failed: summary.failed_files.len(),
+            failures: summary
+                .failed_files
+                .iter()
+                .take(MAX_LISTED_FAILURES)
+                .cloned()
+                .collect(),
+            records,
+            output_dir: output_dir.map(Path::to_path_buf),
+            outputs: manifest
+                .map(|manifest| manifest.outputs)
+                .unwrap_or_default(),
+            error: summary.error.clone(),
+        }
+    }
+
+    /// POST the summary to `url`
+    pub async fn send(&self, url: &str) -> Result<()> {
+        reqwest::Client::new()
+            .post(url)
+            .timeout(SEND_TIMEOUT)
+            .json(self)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .with_context(|| format!("Could not notify {}", url))?;
+        Ok(())
+    }
+}
+
+/// Duration as e.g. `2h 5m`, `5m 12s` or `40s`
+fn format_duration(secs: u64) -> String {
+    let (hours, minutes, seconds) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else if minutes > 0 {
+        format!("{}m {}s", minutes, seconds)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exit::RunReport;
+    use anyhow::anyhow;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_run_notification() {
+        let dir = tempdir().unwrap();
+        let started_at = Utc::now() - chrono::Duration::seconds(3725);
+        let mut manifest = RunManifest::new(serde_json::json!({}));
+        manifest.add_output(&dir.path().join("all_qa.jsonl"));
+        manifest.finish(42);
+        manifest.write(dir.path()).unwrap();
+
+        let mut report = RunReport::default();
+        report.file_succeeded();
+        report.file_failed(Path::new("guide.md"), &anyhow!("bad reply"));
+        let notification =
+            RunNotification::new(&report.finish(None, false), started_at, Some(dir.path()));
+        assert_eq!(notification.reason, ExitReason::PartialFailure);
+        assert_eq!(notification.records, Some(42));
+        assert_eq!(notification.outputs, vec![dir.path().join("all_qa.jsonl")]);
+        assert_eq!(notification.failures[0].path, Path::new("guide.md"));
+        assert_eq!(
+            notification.text,
+            format!(
+                "llm_dataset_builder run ended with some files failed (exit code 3) after 1h 2m: \
+                 2 files processed, 1 failed, 42 records in {}",
+                dir.path().display()
+            )
+        );
+
+        // A manifest left by an earlier run is not reported as this run's
+        let error = anyhow!("Ollama is not reachable");
+        let later = RunNotification::new(
+            &RunReport::default().finish(Some(&error), false),
+            Utc::now() + chrono::Duration::seconds(1),
+            Some(dir.path()),
+        );
+        assert_eq!(later.records, None);
+        assert!(later.outputs.is_empty());
+        assert!(later.text.ends_with("\nError: Ollama is not reachable"));
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(40), "40s");
+        assert_eq!(format_duration(312), "5m 12s");
+        assert_eq!(format_duration(7500), "2h 5m");
+    }
+}
@@ -0,0 +1,35 @@
+//! Hooks into a generation run for library users: metrics, progress UIs, or
+//! filtering records as they are generated, without changing the processor.
+
+use std::ops::Range;
+use std::path::Path;
+
+use crate::processor::ProcessedItem;
+
+/// Receives the events of a run as the pipeline stages reach them
+///
+/// Register one with `DefaultOllamaProcessor::with_observer`. Every method
+/// does nothing by default. They are called from the pipeline's stages
+/// while the run goes on, so they should return quickly.
+pub trait PipelineObserver: Send + Sync {
+    /// A file was taken up for generation
+    fn on_file_start(&self, _path: &Path) {}
+
+    /// Questions were generated for a section of `document` (named as in
+    /// record IDs) and passed schema validation
+    ///
+    /// Records removed from `items` are neither written nor passed on.
+    fn on_chunk_generated(
+        &self,
+        _document: &str,
+        _span: &Range<usize>,
+        _items: &mut Vec<ProcessedItem>,
+    ) {
+    }
+
+    /// A record was written to its file's QA file and passed on
+    fn on_item_accepted(&self, _item: &ProcessedItem) {}
+
+    /// A section or a whole file of `path` failed
+    fn on_error(&self, _path: &Path, _error: &anyhow::Error) {}
+}
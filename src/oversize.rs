@@ -0,0 +1,330 @@
+//! Files larger than `--max-file-size`: left out, split into sub-documents,
+//! or condensed by map-reduce summaries before questions are generated.
+
+use anyhow::{anyhow, Result};
+use std::fmt;
+use std::fs;
+use std::future::Future;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::sections::{read_sections, SectionReader, MAX_SECTION_BYTES};
+
+/// Largest file generated from as it is, unless `--max-file-size` says otherwise
+pub const DEFAULT_MAX_FILE_BYTES: u64 = 10 * 1024 * 1024;
+
+/// Directory in the output directory holding the parts and summaries of
+/// oversized files
+pub const PARTS_DIR: &str = "parts";
+
+/// Rounds of summarizing summaries before giving up on reaching the limit
+const MAX_REDUCE_ROUNDS: usize = 4;
+
+/// What happens to a file over the size limit, given with `--oversized`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OversizePolicy {
+    /// Leave the file out with a warning
+    #[default]
+    Skip,
+    /// Generate from sub-documents of at most the limit, each with its own QA file
+    Split,
+    /// Generate from a summary of the file, condensed section by section
+    Summarize,
+}
+
+impl OversizePolicy {
+    pub const ALL: [OversizePolicy; 3] = [
+        OversizePolicy::Skip,
+        OversizePolicy::Split,
+        OversizePolicy::Summarize,
+    ];
+
+    /// Name used on the command line
+    pub fn name(&self) -> &'static str {
+        match self {
+            OversizePolicy::Skip => "skip",
+            OversizePolicy::Split => "split",
+            OversizePolicy::Summarize => "summarize",
+        }
+    }
+}
+
+impl fmt::Display for OversizePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for OversizePolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "skip" => Ok(OversizePolicy::Skip),
+            "split" => Ok(OversizePolicy::Split),
+            "summarize" => Ok(OversizePolicy::Summarize),
+            other => Err(anyhow!(
+                "Unknown oversized file policy '{}' (expected skip, split or summarize)",
+                other
+            )),
+        }
+    }
+}
+
+/// Size limit on input files and what to do with files over it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeLimit {
+    pub max_bytes: u64,
+    pub policy: OversizePolicy,
+}
+
+impl Default for SizeLimit {
+    fn default() -> Self {
+        Self {
+            max_bytes: DEFAULT_MAX_FILE_BYTES,
+            policy: OversizePolicy::default(),
+        }
+    }
+}
+
+impl SizeLimit {
+    pub fn exceeded_by(&self, bytes: u64) -> bool {
+        bytes > self.max_bytes
+    }
+}
+
+/// Parse a size such as `10MB`, `512KiB` or `1048576` into bytes; units are
+/// powers of 1024
+pub fn parse_size(value: &str) -> Result<u64> {
+    let value = value.trim();
+    let unit_start = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(unit_start);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| anyhow!("Invalid size '{}'", value))?;
+    let multiplier: u64 = match unit.trim().to_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1024,
+        "m" | "mb" | "mib" => 1024 * 1024,
+        "g" | "gb" | "gib" => 1024 * 1024 * 1024,
+        _ => {
+            return Err(anyhow!(
+                "Unknown unit in size '{}' (expected B, KB, MB or GB)",
+                value
+            ))
+        }
+    };
+    Ok((number * multiplier as f64) as u64)
+}
+
+/// A size for messages, e.g. `12.5 MB`
+pub fn format_size(bytes: u64) -> String {
+    const MB: f64 = 1024.0 * 1024.0;
+    if bytes as f64 >= MB {
+        format!("{:.1} MB", bytes as f64 / MB)
+    } else {
+        format!("{:.1} KB", bytes as f64 / 1024.0)
+    }
+}
+
+fn stem_and_extension(path: &Path) -> (&str, &str) {
+    let stem = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("document");
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("md");
+    (stem, extension)
+}
+
+/// Split a file at section boundaries into parts of at most `max_bytes`,
+/// written to `parts_dir` as `STEM.partNNN.EXT`
+///
+/// Sections are streamed, so only one is held in memory; a section larger
+/// than `max_bytes` becomes a part of its own.
+pub fn split_file(path: &Path, parts_dir: &Path, max_bytes: u64) -> Result<Vec<PathBuf>> {
+    fs::create_dir_all(parts_dir)?;
+    let (stem, extension) = stem_and_extension(path);
+    let mut parts = Vec::new();
+    let mut current: Option<(BufWriter<fs::File>, u64)> = None;
+
+    for section in SectionReader::open(path)? {
+        let section = section?;
+        let len = section.len() as u64;
+        if let Some((writer, written)) = current.take() {
+            if written + len > max_bytes {
+                writer.into_inner().map_err(|e| e.into_error())?;
+            } else {
+                current = Some((writer, written));
+            }
+        }
+        let (writer, written) = match current.as_mut() {
+            Some(current) => current,
+            None => {
+                let part =
+                    parts_dir.join(format!("{}.part{:03}.{}", stem, parts.len() + 1, extension));
+                let writer = BufWriter::new(fs::File::create(&part)?);
+                parts.push(part);
+                current.insert((writer, 0))
+            }
+        };
+        writer.write_all(section.as_bytes())?;
+        *written += len;
+    }
+    if let Some((writer, _)) = current {
+        writer.into_inner().map_err(|e| e.into_error())?;
+    }
+    Ok(parts)
+}
+
+/// Where [`summarize_file`] writes the summary of `path`
+pub fn summary_path(path: &Path, parts_dir: &Path) -> PathBuf {
+    parts_dir.join(format!("{}.summary.md", stem_and_extension(path).0))
+}
+
+/// Join consecutive texts into groups of at most `max_bytes`
+fn group_by_size(texts: Vec<String>, max_bytes: usize) -> Vec<String> {
+    let mut groups: Vec<String> = Vec::new();
+    for text in texts {
+        match groups.last_mut() {
+            Some(group) if group.len() + text.len() + 2 <= max_bytes => {
+                group.push_str("\n\n");
+                group.push_str(&text);
+            }
+            _ => groups.push(text),
+        }
+    }
+    groups
+}
+
+/// Condense a file into a summary of at most `max_bytes`, written to
+/// `parts_dir` as `STEM.summary.md`
+///
+/// Each section is summarized on its own (map); while the joined summaries
+/// are still over the limit, runs of them are summarized again (reduce).
+pub async fn summarize_file<F, Fut>(
+    path: &Path,
+    parts_dir: &Path,
+    max_bytes: u64,
+    summarize: F,
+) -> Result<PathBuf>
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Result<String>>,
+{
+    let mut summaries = Vec::new();
+    let mut sections = read_sections(path.to_path_buf());
+    while let Some(section) = sections.recv().await {
+        let summary = summarize(section?.text).await?;
+        if !summary.trim().is_empty() {
+            summaries.push(summary.trim().to_string());
+        }
+    }
+
+    let total = |summaries: &[String]| summaries.iter().map(|s| s.len() as u64 + 2).sum::<u64>();
+    let mut rounds = 0;
+    while total(&summaries) > max_bytes {
+        rounds += 1;
+        if rounds > MAX_REDUCE_ROUNDS {
+            return Err(anyhow!(
+                "Summary of {:?} is still {} after {} rounds, over the {} limit",
+                path,
+                format_size(total(&summaries)),
+                MAX_REDUCE_ROUNDS,
+                format_size(max_bytes)
+            ));
+        }
+        println!(
+            "Condensing {} summaries of {:?} (round {})",
+            summaries.len(),
+            path,
+            rounds
+        );
+        let mut reduced = Vec::new();
+        for group in group_by_size(summaries, MAX_SECTION_BYTES) {
+            reduced.push(summarize(group).await?.trim().to_string());
+        }
+        summaries = reduced;
+    }
+
+    let mut document = format!("# Summary of {}\n\n", stem_and_extension(path).0);
+    for (i, summary) in summaries.iter().enumerate() {
+        document.push_str(&format!("## Part {}\n{}\n\n", i + 1, summary));
+    }
+    tokio::fs::create_dir_all(parts_dir).await?;
+    let summary_path = summary_path(path, parts_dir);
+    tokio::fs::write(&summary_path, document).await?;
+    Ok(summary_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("1048576").unwrap(), 1024 * 1024);
+        assert_eq!(parse_size("10MB").unwrap(), 10 * 1024 * 1024);
+        assert_eq!(parse_size("512 KiB").unwrap(), 512 * 1024);
+        assert_eq!(parse_size("1.5k").unwrap(), 1536);
+        assert!(parse_size("ten").is_err());
+        assert!(parse_size("5 TB").is_err());
+
+        for policy in OversizePolicy::ALL {
+            assert_eq!(policy.name().parse::<OversizePolicy>().unwrap(), policy);
+        }
+    }
+
+    #[test]
+    fn test_split_file_at_sections() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("log.md");
+        let section = |n| format!("# Entry {}\n{}\n", n, "line ".repeat(20));
+        let text: String = (0..10).map(section).collect();
+        fs::write(&path, &text).unwrap();
+
+        let parts_dir = temp_dir.path().join(PARTS_DIR);
+        let parts = split_file(&path, &parts_dir, 3 * section(0).len() as u64).unwrap();
+        assert_eq!(parts.len(), 4);
+        assert_eq!(parts[0], parts_dir.join("log.part001.md"));
+        let rejoined: String = parts
+            .iter()
+            .map(|part| fs::read_to_string(part).unwrap())
+            .collect();
+        assert_eq!(rejoined, text);
+        assert!(fs::read_to_string(&parts[3])
+            .unwrap()
+            .starts_with("# Entry 9"));
+    }
+
+    #[tokio::test]
+    async fn test_summarize_file_reduces_until_under_limit() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("dump.txt");
+        let text: String = (0..40)
+            .map(|n| format!("# Part {}\n{}\n", n, "detail ".repeat(50)))
+            .collect();
+        fs::write(&path, text).unwrap();
+
+        // Keeps the first 100 bytes of whatever it is given
+        let calls = std::sync::atomic::AtomicUsize::new(0);
+        let summary = summarize_file(&path, temp_dir.path(), 1000, |text| {
+            calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            async move { Ok(text.chars().take(100).collect()) }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(summary, temp_dir.path().join("dump.summary.md"));
+        let content = fs::read_to_string(summary).unwrap();
+        assert!(content.starts_with("# Summary of dump\n\n## Part 1\n# Part 0"));
+        assert!(content.len() <= 1200);
+        // One summary per section, then at least one reduce round
+        assert!(calls.into_inner() > 40);
+    }
+}
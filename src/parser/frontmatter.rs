@@ -0,0 +1,114 @@
+use serde_yaml::Value;
+use std::collections::BTreeMap;
+
+/// Document-level metadata declared in a leading `---` YAML block
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Frontmatter {
+    pub title: Option<String>,
+    /// Values of the `tags` and `keywords` fields
+    pub tags: Vec<String>,
+    /// All fields rendered as strings (lists are comma separated)
+    pub attributes: BTreeMap<String, String>,
+}
+
+/// Split leading YAML frontmatter from the markdown body
+///
+/// Returns the parsed frontmatter (if the block is a YAML mapping), the body
+/// after the closing delimiter, and the byte offset at which the body starts.
+/// Anything that is not valid frontmatter is left in the body untouched.
+pub fn split_frontmatter(content: &str) -> (Option<Frontmatter>, &str, usize) {
+    let Some(rest) = content
+        .strip_prefix("---\n")
+        .or_else(|| content.strip_prefix("---\r\n"))
+    else {
+        return (None, content, 0);
+    };
+    let opening_len = content.len() - rest.len();
+
+    // Find a closing `---` or `...` line
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        let trimmed = line.trim_end();
+        if trimmed == "---" || trimmed == "..." {
+            let yaml = &rest[..offset];
+            let body_start = opening_len + offset + line.len();
+            return match parse_yaml(yaml) {
+                Some(frontmatter) => (Some(frontmatter), &content[body_start..], body_start),
+                None => (None, content, 0),
+            };
+        }
+        offset += line.len();
+    }
+
+    (None, content, 0)
+}
+
+fn parse_yaml(yaml: &str) -> Option<Frontmatter> {
+    let Ok(Value::Mapping(mapping)) = serde_yaml::from_str::<Value>(yaml) else {
+        return None;
+    };
+
+    let mut frontmatter = Frontmatter::default();
+    for (key, value) in mapping {
+        let Some(key) = scalar_to_string(&key) else {
+            continue;
+        };
+
+        let values: Vec<String> = match &value {
+            Value::Sequence(items) => items.iter().filter_map(scalar_to_string).collect(),
+            other => scalar_to_string(other).into_iter().collect(),
+        };
+
+        match key.as_str() {
+            "title" => frontmatter.title = values.first().cloned(),
+            "tags" | "keywords" => frontmatter.tags.extend(values.iter().cloned()),
+            _ => {}
+        }
+        frontmatter.attributes.insert(key, values.join(", "));
+    }
+
+    Some(frontmatter)
+}
+
+fn scalar_to_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Bool(b) => Some(b.to_string()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_frontmatter() {
+        let content =
+            "---\ntitle: Guide\ntags: [rust, cli]\ndate: 2024-01-01\ndraft: false\n---\n# Body\n";
+        let (frontmatter, body, offset) = split_frontmatter(content);
+        let frontmatter = frontmatter.unwrap();
+
+        assert_eq!(frontmatter.title.as_deref(), Some("Guide"));
+        assert_eq!(frontmatter.tags, vec!["rust", "cli"]);
+        assert_eq!(frontmatter.attributes["date"], "2024-01-01");
+        assert_eq!(frontmatter.attributes["draft"], "false");
+        assert_eq!(frontmatter.attributes["tags"], "rust, cli");
+        assert_eq!(body, "# Body\n");
+        assert_eq!(&content[offset..], body);
+    }
+
+    #[test]
+    fn test_no_frontmatter() {
+        let content = "# Title\n---\nText\n";
+        let (frontmatter, body, offset) = split_frontmatter(content);
+        assert!(frontmatter.is_none());
+        assert_eq!(body, content);
+        assert_eq!(offset, 0);
+
+        // A thematic break pair around plain text is not frontmatter
+        let content = "---\njust text\n---\n";
+        assert!(split_frontmatter(content).0.is_none());
+    }
+}
@@ -1,21 +1,622 @@
 use anyhow::Result;
-use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag};
+use pulldown_cmark::{Alignment, CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag};
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
 use std::path::Path;
+use std::sync::Arc;
+use uuid::Uuid;
+use walkdir::WalkDir;
 
-use crate::graph::{edge::RelationType, node::NodeType, DocumentEdge, DocumentGraph, DocumentNode};
+use crate::graph::{
+    edge::RelationType, node::NodeType, DocumentEdge, DocumentGraph, DocumentNode, SourceSpan,
+};
+use crate::hash::hash_text;
+
+pub mod frontmatter;
+
+pub use frontmatter::{split_frontmatter, Frontmatter};
+
+/// Namespace of node IDs, so they never equal v5 UUIDs made for anything else
+const NODE_ID_NAMESPACE: Uuid = Uuid::from_u128(0x3d5b_91e6_07a2_4c8f_b5d0_2e94_61f8_ac37);
 
 /// Parse a markdown file into a document graph
+///
+/// The Document root records the file path and falls back to the file stem
+/// as its title. Node IDs are derived from the path as given and the file's
+/// content, as in [`parse_markdown_document`].
 pub fn parse_markdown_file(path: &Path) -> Result<DocumentGraph> {
     let content = std::fs::read_to_string(path)?;
-    parse_markdown(&content)
+    let mut graph = parse_markdown_document(&content, &path.display().to_string())?;
+
+    if let Some(root_id) = graph.documents().first().map(|root| root.id) {
+        if let Some(root) = graph.get_node_mut(&root_id) {
+            if root.metadata.title.is_none() {
+                let stem = path
+                    .file_stem()
+                    .and_then(|s| s.to_str())
+                    .unwrap_or("unknown")
+                    .to_string();
+                root.content = Arc::from(stem.as_str());
+                root.metadata.title = Some(Arc::from(stem));
+            }
+        }
+    }
+
+    Ok(graph)
+}
+
+/// Parse markdown content named `name`, usually its path, into a document graph
+///
+/// Unlike [`parse_markdown`], node IDs are v5 UUIDs of the name, the content
+/// and the node's position, so parsing the same unchanged document again
+/// gives every node the same ID and records can refer to nodes by ID. The
+/// name is recorded as the Document root's `path` attribute.
+pub fn parse_markdown_document(content: &str, name: &str) -> Result<DocumentGraph> {
+    let ids = Uuid::new_v5(
+        &NODE_ID_NAMESPACE,
+        format!("{}\n{}", name, hash_text(content)).as_bytes(),
+    );
+    let mut graph = parse(content, Some(ids))?;
+    if let Some(root_id) = graph.documents().first().map(|root| root.id) {
+        graph.set_attribute(&root_id, "path", name)?;
+    }
+    Ok(graph)
+}
+
+/// A link or image whose closing tag has not been reached yet
+struct OpenReference {
+    node_type: NodeType,
+    url: String,
+    title: String,
+    /// Offset into the text buffer where the reference text starts
+    text_start: usize,
+}
+
+/// A completed link or image waiting for its containing node to exist
+struct PendingReference {
+    node_type: NodeType,
+    content: String,
+    attributes: BTreeMap<String, String>,
+    range: Range<usize>,
+}
+
+/// Parse every markdown file under a directory into one corpus graph
+pub fn parse_markdown_dir(dir: &Path) -> Result<DocumentGraph> {
+    let mut graphs = Vec::new();
+    for entry in WalkDir::new(dir)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter(|e| {
+            e.path()
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .map(|ext| ext == "md" || ext == "markdown")
+                .unwrap_or(false)
+        })
+    {
+        graphs.push(parse_markdown_file(entry.path())?);
+    }
+    DocumentGraph::merge_all(graphs)
+}
+
+/// Incrementally builds a document tree while walking parser events
+struct GraphBuilder {
+    graph: DocumentGraph,
+    /// Document root that top-level content hangs off, if any
+    root: Option<Uuid>,
+    /// Currently open sections as (heading level, node id), outermost first
+    section_stack: Vec<(i32, Uuid)>,
+    /// Open container nodes (lists, list items) inside the current section
+    container_stack: Vec<Uuid>,
+    /// Text accumulated since the last flush
+    current_text: String,
+    /// Source range covered by the accumulated text
+    text_range: Option<Range<usize>>,
+    /// Source range of the event being handled (relative to the body)
+    current_range: Range<usize>,
+    /// Byte offset of the markdown body within the source (after frontmatter)
+    offset_base: usize,
+    /// Byte offsets at which each source line starts
+    line_starts: Vec<usize>,
+    /// Most recently attached child of each parent, for Precedes edges
+    last_child: HashMap<Uuid, Uuid>,
+    /// Section anchors seen so far, for resolving `#anchor` links
+    anchors: HashMap<String, Uuid>,
+    /// Running position of nodes in document order
+    position: usize,
+    /// Header cell texts of the table being parsed
+    table_headers: Vec<String>,
+    /// Column of the next cell in the current table row
+    table_column: usize,
+    /// Whether the parser is inside a table header row
+    in_table_head: bool,
+    /// Links and images currently open
+    open_references: Vec<OpenReference>,
+    /// Links and images seen since the last flush
+    pending_references: Vec<PendingReference>,
+    /// Footnote labels referenced since the last flush
+    pending_footnotes: Vec<String>,
+    /// Footnote references as (referencing node, label), resolved at the end
+    footnote_references: Vec<(Uuid, String)>,
+    /// Footnote definition nodes by label
+    footnotes: HashMap<String, Uuid>,
+    /// Namespace node IDs are derived from, or `None` for random IDs
+    ids: Option<Uuid>,
+}
+
+impl GraphBuilder {
+    fn new(source: &str, offset_base: usize, ids: Option<Uuid>) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+
+        Self {
+            graph: DocumentGraph::new(),
+            root: None,
+            section_stack: Vec::new(),
+            container_stack: Vec::new(),
+            current_text: String::new(),
+            text_range: None,
+            current_range: 0..0,
+            offset_base,
+            line_starts,
+            last_child: HashMap::new(),
+            anchors: HashMap::new(),
+            position: 0,
+            table_headers: Vec::new(),
+            table_column: 0,
+            in_table_head: false,
+            open_references: Vec::new(),
+            pending_references: Vec::new(),
+            pending_footnotes: Vec::new(),
+            footnote_references: Vec::new(),
+            footnotes: HashMap::new(),
+            ids,
+        }
+    }
+
+    /// The node new content should be attached to
+    fn parent_id(&self) -> Option<Uuid> {
+        self.container_stack
+            .last()
+            .copied()
+            .or_else(|| self.section_stack.last().map(|(_, id)| *id))
+            .or(self.root)
+    }
+
+    /// Convert a body-relative byte range into a source span
+    fn span_for(&self, range: &Range<usize>) -> SourceSpan {
+        let start_byte = self.offset_base + range.start;
+        let end_byte = self.offset_base + range.end;
+        let line_of = |offset: usize| self.line_starts.partition_point(|&start| start <= offset);
+        SourceSpan {
+            start_byte,
+            end_byte,
+            start_line: line_of(start_byte),
+            end_line: line_of(end_byte.saturating_sub(1).max(start_byte)),
+        }
+    }
+
+    /// Record the source location of a node
+    fn set_span(&mut self, id: &Uuid, range: &Range<usize>) {
+        let span = self.span_for(range);
+        if let Some(node) = self.graph.get_node_mut(id) {
+            node.metadata.span = Some(span);
+        }
+    }
+
+    /// Append text to the buffer, extending the range it covers
+    fn push_text(&mut self, text: &str) {
+        self.current_text.push_str(text);
+        let range = self.current_range.clone();
+        self.text_range = Some(match self.text_range.take() {
+            Some(existing) => existing.start.min(range.start)..existing.end.max(range.end),
+            None => range,
+        });
+    }
+
+    /// Create a node and connect it to the current parent with a Contains edge
+    ///
+    /// The node's span is the range of the event being handled. The previous
+    /// child of the same parent, if any, gets a Precedes edge to the new node.
+    fn attach(
+        &mut self,
+        node_type: NodeType,
+        content: String,
+        title: Option<String>,
+        level: Option<i32>,
+        tags: Vec<String>,
+    ) -> Result<Uuid> {
+        let parent = self.parent_id();
+        let mut node = DocumentNode::new(node_type, content, title, level, self.position, tags);
+        if let Some(ids) = &self.ids {
+            node.id = Uuid::new_v5(ids, self.position.to_string().as_bytes());
+        }
+        let id = node.id;
+        self.position += 1;
+        self.graph.add_node(node);
+        let range = self.current_range.clone();
+        self.set_span(&id, &range);
+
+        if let Some(parent) = parent {
+            self.graph
+                .add_edge(DocumentEdge::new(parent, id, RelationType::Contains))?;
+            if let Some(previous) = self.last_child.insert(parent, id) {
+                self.graph
+                    .add_edge(DocumentEdge::new(previous, id, RelationType::Precedes))?;
+            }
+        }
+
+        Ok(id)
+    }
+
+    /// Take the accumulated text, leaving the buffer empty
+    fn take_text(&mut self) -> String {
+        self.text_range = None;
+        std::mem::take(&mut self.current_text)
+    }
+
+    /// Turn accumulated text into content for the current parent
+    ///
+    /// A list item without content yet absorbs the text directly, as do
+    /// quotes and footnote definitions; otherwise a Text node is created under
+    /// the current parent. Links and images seen in the text are attached
+    /// afterwards.
+    fn flush_text(&mut self) -> Result<()> {
+        let text_range = self.text_range.clone();
+        let text = self.take_text();
+        let text = text.trim();
+        let mut source = None;
+
+        if !text.is_empty() {
+            if let Some(parent) = self.container_stack.last().copied() {
+                if let Some(node) = self.graph.get_node_mut(&parent) {
+                    match node.node_type {
+                        NodeType::ListItem if node.content.is_empty() => {
+                            node.content = Arc::from(text);
+                            source = Some(parent);
+                        }
+                        NodeType::Quote | NodeType::Footnote => {
+                            node.content = if node.content.is_empty() {
+                                Arc::from(text)
+                            } else {
+                                Arc::from(format!("{}\n\n{}", node.content, text))
+                            };
+                            source = Some(parent);
+                        }
+                        _ => {}
+                    }
+                }
+            }
+
+            if source.is_none() {
+                let id = self.attach(NodeType::Text, text.to_string(), None, None, vec![])?;
+                if let Some(range) = text_range {
+                    self.set_span(&id, &range);
+                }
+                source = Some(id);
+            }
+        }
+
+        self.attach_references(source.or_else(|| self.parent_id()))
+    }
+
+    /// Start tracking a link or image tag
+    fn open_reference(&mut self, node_type: NodeType, url: &str, title: &str) {
+        self.open_references.push(OpenReference {
+            node_type,
+            url: url.to_string(),
+            title: title.to_string(),
+            text_start: self.current_text.len(),
+        });
+    }
+
+    /// Finish a link or image tag, capturing the text it wrapped
+    fn close_reference(&mut self) {
+        let Some(open) = self.open_references.pop() else {
+            return;
+        };
+        let text = self
+            .current_text
+            .get(open.text_start..)
+            .unwrap_or("")
+            .trim()
+            .to_string();
+
+        let mut attributes = BTreeMap::new();
+        attributes.insert("url".to_string(), open.url);
+        if !open.title.is_empty() {
+            attributes.insert("title".to_string(), open.title);
+        }
+        if open.node_type == NodeType::Image {
+            attributes.insert("alt".to_string(), text.clone());
+        }
+
+        self.pending_references.push(PendingReference {
+            node_type: open.node_type,
+            content: text,
+            attributes,
+            range: self.current_range.clone(),
+        });
+    }
+
+    /// Attach pending links and images, referenced from `source`
+    fn attach_references(&mut self, source: Option<Uuid>) -> Result<()> {
+        let labels = std::mem::take(&mut self.pending_footnotes);
+        if let Some(source) = source {
+            self.footnote_references
+                .extend(labels.into_iter().map(|label| (source, label)));
+        }
+
+        for reference in std::mem::take(&mut self.pending_references) {
+            let id = self.attach(reference.node_type, reference.content, None, None, vec![])?;
+            for (key, value) in reference.attributes {
+                self.graph.set_attribute(&id, &key, value)?;
+            }
+            self.set_span(&id, &reference.range);
+            if let Some(source) = source {
+                self.graph
+                    .add_edge(DocumentEdge::new(source, id, RelationType::References))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Close sections at the same or deeper level than a new heading
+    fn close_sections(&mut self, level: i32) {
+        while matches!(self.section_stack.last(), Some((open, _)) if *open >= level) {
+            self.section_stack.pop();
+        }
+        self.container_stack.clear();
+    }
+
+    /// Open a table row (header or body) under the current table
+    fn start_table_row(&mut self, header: bool) -> Result<()> {
+        let tags = if header {
+            vec!["header".to_string()]
+        } else {
+            vec![]
+        };
+        let id = self.attach(NodeType::TableRow, String::new(), None, None, tags)?;
+        self.container_stack.push(id);
+        self.table_column = 0;
+        self.in_table_head = header;
+        Ok(())
+    }
+
+    /// Close a table row, summarising its cells as the row content
+    fn end_table_row(&mut self) {
+        if let Some(row_id) = self.container_stack.pop() {
+            let cells: Vec<Arc<str>> = self
+                .graph
+                .get_children(&row_id)
+                .map(|cells| cells.iter().map(|c| c.content.clone()).collect())
+                .unwrap_or_default();
+            if let Some(row) = self.graph.get_node_mut(&row_id) {
+                row.content = Arc::from(cells.join(" | "));
+            }
+        }
+
+        if self.in_table_head {
+            // The table node shows its column headers
+            let headers = self.table_headers.join(" | ");
+            if let Some(table_id) = self.container_stack.last().copied() {
+                if let Some(table) = self.graph.get_node_mut(&table_id) {
+                    table.content = Arc::from(headers);
+                }
+            }
+        }
+        self.in_table_head = false;
+    }
+
+    /// Emit a table cell, titled with its column header
+    fn end_table_cell(&mut self) -> Result<()> {
+        let text = self.take_text().trim().to_string();
+        let id = if self.in_table_head {
+            self.table_headers.push(text.clone());
+            self.attach(
+                NodeType::TableCell,
+                text.clone(),
+                Some(text),
+                None,
+                vec!["header".to_string()],
+            )?
+        } else {
+            let header = self.table_headers.get(self.table_column).cloned();
+            self.attach(NodeType::TableCell, text, header, None, vec![])?
+        };
+        self.table_column += 1;
+
+        // Links inside the cell belong to the cell
+        self.container_stack.push(id);
+        self.attach_references(Some(id))?;
+        self.container_stack.pop();
+        Ok(())
+    }
+
+    /// Create the Document root node, carrying frontmatter metadata if present
+    fn add_document_root(&mut self, frontmatter: Frontmatter) -> Result<()> {
+        let id = self.attach(
+            NodeType::Document,
+            frontmatter.title.clone().unwrap_or_default(),
+            frontmatter.title,
+            None,
+            frontmatter.tags,
+        )?;
+        for (key, value) in frontmatter.attributes {
+            self.graph.set_attribute(&id, &key, value)?;
+        }
+        self.root = Some(id);
+        Ok(())
+    }
+
+    /// Open a blockquote or footnote definition that absorbs its paragraphs
+    fn open_block(&mut self, node_type: NodeType, tags: Vec<String>) -> Result<Uuid> {
+        self.flush_text()?;
+        let id = self.attach(node_type, String::new(), None, None, tags)?;
+        self.container_stack.push(id);
+        Ok(id)
+    }
+
+    /// Close a blockquote, tagging GitHub-style admonitions (`> [!NOTE]`)
+    fn close_quote(&mut self) -> Result<()> {
+        self.flush_text()?;
+        let Some(id) = self.container_stack.pop() else {
+            return Ok(());
+        };
+        let admonition = self.graph.get_node(&id).and_then(|quote| {
+            quote
+                .content
+                .strip_prefix("[!")
+                .and_then(|rest| rest.split_once(']'))
+                .map(|(kind, body)| (kind.to_lowercase(), Arc::from(body.trim())))
+        });
+        if let Some((kind, body)) = admonition {
+            self.graph.add_tag(&id, &format!("admonition:{}", kind))?;
+            self.graph.set_attribute(&id, "admonition", kind)?;
+            if let Some(quote) = self.graph.get_node_mut(&id) {
+                quote.content = body;
+            }
+        }
+        Ok(())
+    }
+
+    /// Connect footnote references to their definitions
+    fn resolve_footnotes(&mut self) -> Result<()> {
+        for (source, label) in std::mem::take(&mut self.footnote_references) {
+            if let Some(footnote) = self.footnotes.get(&label) {
+                self.graph.add_edge(DocumentEdge::new(
+                    source,
+                    *footnote,
+                    RelationType::References,
+                ))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Title an untitled Document root after the first top-level heading
+    fn title_document_root(&mut self) {
+        let Some(root_id) = self.root else {
+            return;
+        };
+        let first_heading = self.graph.get_children(&root_id).ok().and_then(|children| {
+            children
+                .into_iter()
+                .find(|n| n.node_type == NodeType::Section && n.metadata.level == Some(1))
+                .map(|n| n.content.clone())
+        });
+
+        if let (Some(heading), Some(root)) = (first_heading, self.graph.get_node_mut(&root_id)) {
+            if root.metadata.title.is_none() {
+                root.content = heading.clone();
+                root.metadata.title = Some(heading);
+            }
+        }
+    }
+
+    /// Give a section a unique GitHub-style anchor
+    ///
+    /// Repeated headings get `-1`, `-2`, ... suffixes in document order.
+    fn assign_anchor(&mut self, id: Uuid, heading: &str) {
+        let slug = slugify(heading);
+        let mut anchor = slug.clone();
+        let mut suffix = 0;
+        while self.anchors.contains_key(&anchor) {
+            suffix += 1;
+            anchor = format!("{}-{}", slug, suffix);
+        }
+
+        if let Some(section) = self.graph.get_node_mut(&id) {
+            section.set_attribute("anchor", anchor.clone());
+        }
+        self.anchors.insert(anchor, id);
+    }
+
+    /// Connect `[text](#anchor)` links to the sections they point at
+    fn resolve_anchors(&mut self) -> Result<()> {
+        let links: Vec<(Uuid, Uuid)> = self
+            .graph
+            .get_nodes_by_type(NodeType::Link)
+            .into_iter()
+            .filter_map(|link| {
+                let anchor = link.attribute("url")?.strip_prefix('#')?;
+                let section = self.anchors.get(&anchor.to_lowercase())?;
+                Some((link.id, *section))
+            })
+            .collect();
+
+        for (link, section) in links {
+            self.graph
+                .add_edge(DocumentEdge::new(link, section, RelationType::References))?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<DocumentGraph> {
+        self.flush_text()?;
+        self.resolve_footnotes()?;
+        self.resolve_anchors()?;
+        self.title_document_root();
+        Ok(self.graph)
+    }
+}
+
+/// GitHub-style anchor for a heading
+///
+/// Lowercases the text, drops punctuation other than `-` and `_`, and turns
+/// spaces into hyphens, so `## Getting Started!` becomes `getting-started`.
+pub fn slugify(heading: &str) -> String {
+    heading
+        .trim()
+        .chars()
+        .filter(|c| c.is_alphanumeric() || matches!(c, ' ' | '-' | '_'))
+        .flat_map(char::to_lowercase)
+        .map(|c| if c == ' ' { '-' } else { c })
+        .collect()
+}
+
+fn heading_level(level: HeadingLevel) -> i32 {
+    match level {
+        HeadingLevel::H1 => 1,
+        HeadingLevel::H2 => 2,
+        HeadingLevel::H3 => 3,
+        HeadingLevel::H4 => 4,
+        HeadingLevel::H5 => 5,
+        HeadingLevel::H6 => 6,
+    }
 }
 
 /// Parse markdown content into a document graph
+///
+/// Headings open sections that contain everything up to the next heading of
+/// the same or higher level, so the result is a tree of `Contains` edges.
+/// Everything hangs off a single `Document` root node, which carries any
+/// leading YAML frontmatter as metadata.
 pub fn parse_markdown(content: &str) -> Result<DocumentGraph> {
-    let mut graph = DocumentGraph::new();
-    let mut current_section: Option<DocumentNode> = None;
-    let mut current_code_block: Option<DocumentNode> = None;
-    let mut list_stack: Vec<DocumentNode> = Vec::new();
+    parse(content, None)
+}
+
+fn parse(content: &str, ids: Option<Uuid>) -> Result<DocumentGraph> {
+    let source = content;
+    let (frontmatter, content, offset_base) = split_frontmatter(source);
+    let mut builder = GraphBuilder::new(source, offset_base, ids);
+
+    // The Document root spans the whole source, frontmatter included
+    builder.current_range = 0..content.len();
+    builder.add_document_root(frontmatter.unwrap_or_default())?;
+    if let Some(root) = builder.root {
+        builder.graph.get_node_mut(&root).unwrap().metadata.span = Some(SourceSpan {
+            start_byte: 0,
+            end_byte: source.len(),
+            start_line: 1,
+            end_line: builder.line_starts.len(),
+        });
+    }
+    let mut code_block_tags: Option<Vec<String>> = None;
+
     // Initialize parser with all extensions enabled
     let mut options = Options::empty();
     options.insert(Options::ENABLE_TABLES);
@@ -24,162 +625,155 @@ pub fn parse_markdown(content: &str) -> Result<DocumentGraph> {
     options.insert(Options::ENABLE_TASKLISTS);
 
     let parser = Parser::new_ext(content, options);
-    let mut current_text = String::new();
 
-    for event in parser {
+    for (event, range) in parser.into_offset_iter() {
+        builder.current_range = range;
         match event {
             Event::Start(Tag::Heading(level, ..)) => {
-                // Create a new section node
-                if !current_text.is_empty() {
-                    let text_node = DocumentNode::new(
-                        NodeType::Text,
-                        current_text.clone(),
-                        None,
-                        None,
-                        0,
-                        vec![],
-                    );
-                    graph.add_node(text_node);
-                    current_text.clear();
-                }
-
-                let level = match level {
-                    HeadingLevel::H1 => 1,
-                    HeadingLevel::H2 => 2,
-                    HeadingLevel::H3 => 3,
-                    HeadingLevel::H4 => 4,
-                    HeadingLevel::H5 => 5,
-                    HeadingLevel::H6 => 6,
-                };
-
-                current_section = Some(DocumentNode::new(
+                builder.flush_text()?;
+                builder.close_sections(heading_level(level));
+            }
+            Event::End(Tag::Heading(level, ..)) => {
+                let level = heading_level(level);
+                let title = builder.take_text().trim().to_string();
+                let id = builder.attach(
                     NodeType::Section,
-                    String::new(),
-                    None,
+                    title.clone(),
+                    Some(title.clone()),
                     Some(level),
-                    0,
                     vec![],
-                ));
-            }
-            Event::End(Tag::Heading(..)) => {
-                if let Some(mut section) = current_section.take() {
-                    section.content = current_text.clone();
-                    graph.add_node(section);
-                    current_text.clear();
-                }
+                )?;
+                builder.section_stack.push((level, id));
+                builder.assign_anchor(id, &title);
+                builder.attach_references(Some(id))?;
             }
             Event::Start(Tag::CodeBlock(kind)) => {
-                current_code_block = Some(DocumentNode::new(
-                    NodeType::Code,
-                    String::new(),
-                    None,
-                    None,
-                    0,
-                    match kind {
-                        CodeBlockKind::Fenced(lang) => {
-                            let lang_str = lang.to_string();
-                            if !lang_str.is_empty() {
-                                vec![format!("language:{}", lang_str)]
-                            } else {
-                                vec![]
-                            }
+                builder.flush_text()?;
+                code_block_tags = Some(match kind {
+                    CodeBlockKind::Fenced(lang) => {
+                        let lang_str = lang.to_string();
+                        if !lang_str.is_empty() {
+                            vec![format!("language:{}", lang_str)]
+                        } else {
+                            vec![]
                         }
-                        CodeBlockKind::Indented => vec!["indented".to_string()],
-                    },
-                ));
+                    }
+                    CodeBlockKind::Indented => vec!["indented".to_string()],
+                });
             }
             Event::End(Tag::CodeBlock(_)) => {
-                if let Some(mut code_block) = current_code_block.take() {
-                    code_block.content = current_text.trim().to_string();
-                    graph.add_node(code_block);
-                    current_text.clear();
+                if let Some(tags) = code_block_tags.take() {
+                    let code = builder.take_text().trim().to_string();
+                    builder.attach(NodeType::Code, code, None, None, tags)?;
                 }
             }
             Event::Start(Tag::List(ordered)) => {
-                let list_node = DocumentNode::new(
-                    NodeType::List,
-                    String::new(),
-                    None,
-                    None,
-                    0,
-                    if ordered.is_some() {
-                        vec!["ordered".to_string()]
-                    } else {
-                        vec!["unordered".to_string()]
-                    },
-                );
-                graph.add_node(list_node.clone());
-                list_stack.push(list_node);
+                builder.flush_text()?;
+                let tags = if ordered.is_some() {
+                    vec!["ordered".to_string()]
+                } else {
+                    vec!["unordered".to_string()]
+                };
+                let id = builder.attach(NodeType::List, String::new(), None, None, tags)?;
+                builder.container_stack.push(id);
             }
-
             Event::Start(Tag::Item) => {
-                if let Some(list_node) = list_stack.last() {
-                    let item_node = DocumentNode::new(
-                        NodeType::ListItem,
-                        String::new(),
-                        Some(list_node.id.to_string()),
-                        None,
-                        0,
-                        vec![],
-                    );
-                    list_stack.push(item_node);
-                }
+                builder.flush_text()?;
+                let id = builder.attach(NodeType::ListItem, String::new(), None, None, vec![])?;
+                builder.container_stack.push(id);
             }
-
-            Event::End(Tag::Item) => {
-                if let Some(mut item_node) = list_stack.pop() {
-                    if let Some(parent_node) = list_stack.last() {
-                        item_node.content = current_text.trim().to_string();
-                        graph.add_node(item_node.clone());
-                        graph.add_edge(DocumentEdge::new(
-                            parent_node.id,
-                            item_node.id,
-                            RelationType::Contains,
-                        ))?;
-                        current_text.clear();
-                    }
-                }
+            Event::End(Tag::Item) | Event::End(Tag::List(_)) => {
+                builder.flush_text()?;
+                builder.container_stack.pop();
             }
-
-            Event::End(Tag::List(_)) => {
-                if let Some(_list_node) = list_stack.pop() {
-                    // List node is already added to the graph
+            Event::Start(Tag::Table(alignments)) => {
+                builder.flush_text()?;
+                builder.table_headers.clear();
+                let tags = alignments
+                    .iter()
+                    .map(|alignment| {
+                        let name = match alignment {
+                            Alignment::None => "none",
+                            Alignment::Left => "left",
+                            Alignment::Center => "center",
+                            Alignment::Right => "right",
+                        };
+                        format!("align:{}", name)
+                    })
+                    .collect();
+                let id = builder.attach(NodeType::Table, String::new(), None, None, tags)?;
+                builder.container_stack.push(id);
+            }
+            Event::Start(Tag::TableHead) => builder.start_table_row(true)?,
+            Event::Start(Tag::TableRow) => builder.start_table_row(false)?,
+            Event::Start(Tag::TableCell) => {
+                builder.take_text();
+            }
+            Event::End(Tag::TableCell) => builder.end_table_cell()?,
+            Event::End(Tag::TableHead) | Event::End(Tag::TableRow) => builder.end_table_row(),
+            Event::End(Tag::Table(_)) => {
+                builder.container_stack.pop();
+                builder.table_headers.clear();
+            }
+            Event::Start(Tag::Link(_, url, title)) => {
+                builder.open_reference(NodeType::Link, &url, &title);
+            }
+            Event::Start(Tag::Image(_, url, title)) => {
+                builder.open_reference(NodeType::Image, &url, &title);
+            }
+            Event::End(Tag::Link(..)) | Event::End(Tag::Image(..)) => {
+                builder.close_reference();
+            }
+            Event::Start(Tag::BlockQuote) => {
+                builder.open_block(NodeType::Quote, vec![])?;
+            }
+            Event::End(Tag::BlockQuote) => builder.close_quote()?,
+            Event::Start(Tag::FootnoteDefinition(label)) => {
+                let id = builder.open_block(NodeType::Footnote, vec![])?;
+                if let Some(node) = builder.graph.get_node_mut(&id) {
+                    node.set_attribute("label", label.to_string());
                 }
+                builder.footnotes.insert(label.to_string(), id);
+            }
+            Event::End(Tag::FootnoteDefinition(_)) => {
+                builder.flush_text()?;
+                builder.container_stack.pop();
+            }
+            Event::FootnoteReference(label) => {
+                builder.push_text(&format!("[^{}]", label));
+                builder.pending_footnotes.push(label.to_string());
+            }
+            Event::End(Tag::Paragraph) => {
+                builder.flush_text()?;
             }
             Event::Text(text) => {
                 // Accumulate text content
-                current_text.push_str(&text);
+                builder.push_text(&text);
             }
             Event::Code(code) => {
                 // Handle inline code blocks
-                current_text.push('`');
-                current_text.push_str(&code);
-                current_text.push('`');
+                builder.push_text(&format!("`{}`", code));
             }
             Event::SoftBreak | Event::HardBreak => {
                 // Handle line breaks
-                current_text.push('\n');
+                builder.push_text("\n");
             }
             _ => {}
         }
     }
 
-    // Handle any remaining text
-    if !current_text.is_empty() {
-        let text_node = DocumentNode::new(NodeType::Text, current_text, None, None, 0, vec![]);
-        graph.add_node(text_node);
-    }
-
-    Ok(graph)
+    builder.finish()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    #[test]
-    fn test_parse_markdown_basic() {
-        let markdown = r#"# Title
+    fn tags(node: &DocumentNode) -> Vec<&str> {
+        node.metadata.tags.iter().map(|tag| &**tag).collect()
+    }
+
+    const MARKDOWN: &str = r#"# Title
 This is a paragraph.
 
 ## Section 1
@@ -194,9 +788,23 @@ fn main() {
 ### Subsection
 - List item 1
 - List item 2
+  - Nested item
+
+## Section 2
+Closing text.
 "#;
 
-        let graph = parse_markdown(markdown).unwrap();
+    fn find_section<'a>(graph: &'a DocumentGraph, title: &str) -> &'a DocumentNode {
+        graph
+            .get_nodes_by_type(NodeType::Section)
+            .into_iter()
+            .find(|node| &*node.content == title)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_parse_markdown_basic() {
+        let graph = parse_markdown(MARKDOWN).unwrap();
 
         // Check if we have the correct number of nodes
         let sections = graph.get_nodes_by_type(NodeType::Section);
@@ -204,9 +812,336 @@ fn main() {
         let lists = graph.get_nodes_by_type(NodeType::List);
         let texts = graph.get_nodes_by_type(NodeType::Text);
 
-        assert_eq!(sections.len(), 3); // Title, Section 1, Subsection
+        assert_eq!(sections.len(), 4); // Title, Section 1, Subsection, Section 2
         assert_eq!(code_blocks.len(), 1); // Rust code block
-        assert_eq!(lists.len(), 1); // One list
-        assert!(texts.len() > 0); // At least one text node
+        assert_eq!(lists.len(), 2); // Outer list and nested list
+        assert!(!texts.is_empty()); // At least one text node
+    }
+
+    #[test]
+    fn test_heading_hierarchy() {
+        let graph = parse_markdown(MARKDOWN).unwrap();
+
+        let title = find_section(&graph, "Title");
+        let section1 = find_section(&graph, "Section 1");
+        let subsection = find_section(&graph, "Subsection");
+        let section2 = find_section(&graph, "Section 2");
+
+        let document = graph.get_nodes_by_type(NodeType::Document)[0];
+        assert_eq!(document.metadata.title.as_deref(), Some("Title"));
+        assert!(graph.get_parent(&document.id).unwrap().is_none());
+        assert_eq!(
+            graph.get_parent(&title.id).unwrap().unwrap().id,
+            document.id
+        );
+        assert_eq!(
+            graph.get_parent(&section1.id).unwrap().unwrap().id,
+            title.id
+        );
+        assert_eq!(
+            graph.get_parent(&subsection.id).unwrap().unwrap().id,
+            section1.id
+        );
+        assert_eq!(
+            graph.get_parent(&section2.id).unwrap().unwrap().id,
+            title.id
+        );
+    }
+
+    #[test]
+    fn test_sibling_reading_order() {
+        let graph = parse_markdown(MARKDOWN).unwrap();
+
+        let section1 = find_section(&graph, "Section 1");
+        let section2 = find_section(&graph, "Section 2");
+        assert_eq!(
+            graph.get_next_sibling(&section1.id).unwrap().unwrap().id,
+            section2.id
+        );
+        assert!(graph.get_next_sibling(&section2.id).unwrap().is_none());
+
+        let sections: Vec<&str> = graph
+            .reading_order()
+            .filter(|node| node.node_type == NodeType::Section)
+            .map(|node| &*node.content)
+            .collect();
+        assert_eq!(sections, ["Title", "Section 1", "Subsection", "Section 2"]);
+        assert_eq!(graph.reading_order().count(), graph.node_count());
+    }
+
+    #[test]
+    fn test_slugify() {
+        assert_eq!(slugify("Getting Started!"), "getting-started");
+        assert_eq!(slugify("`cargo run` -- options"), "cargo-run----options");
+        assert_eq!(slugify("Über_Config (v2.0)"), "über_config-v20");
+    }
+
+    #[test]
+    fn test_section_anchors() {
+        let markdown = "# Guide\n\nSee [setup](#setup) and [the other one](#setup-1).\n\n## Setup\n\nA.\n\n## Setup\n\nB.\n";
+        let graph = parse_markdown(markdown).unwrap();
+
+        let anchors: Vec<&str> = graph
+            .get_nodes_by_type(NodeType::Section)
+            .into_iter()
+            .filter_map(|node| node.attribute("anchor"))
+            .collect();
+        assert_eq!(anchors, ["guide", "setup", "setup-1"]);
+
+        for link in graph.get_nodes_by_type(NodeType::Link) {
+            let anchor = link.attribute("url").unwrap().trim_start_matches('#');
+            let targets = graph.get_references(&link.id).unwrap();
+            assert_eq!(targets.len(), 1);
+            assert_eq!(targets[0].attribute("anchor"), Some(anchor));
+        }
+    }
+
+    #[test]
+    fn test_content_attached_to_sections() {
+        let graph = parse_markdown(MARKDOWN).unwrap();
+
+        let section1 = find_section(&graph, "Section 1");
+        let children = graph.get_children(&section1.id).unwrap();
+        assert!(children.iter().any(|n| n.node_type == NodeType::Code));
+        assert!(children
+            .iter()
+            .any(|n| n.node_type == NodeType::Text && &*n.content == "Some text."));
+
+        let code = graph.get_nodes_by_type(NodeType::Code)[0];
+        assert_eq!(tags(code), ["language:rust"]);
+    }
+
+    #[test]
+    fn test_tables() {
+        let markdown = r#"## Options
+| Name | Default |
+|:-----|--------:|
+| `port` | 8080 |
+| host | localhost |
+"#;
+        let graph = parse_markdown(markdown).unwrap();
+
+        let tables = graph.get_nodes_by_type(NodeType::Table);
+        assert_eq!(tables.len(), 1);
+        let table = tables[0];
+        assert_eq!(&*table.content, "Name | Default");
+        assert_eq!(tags(table), ["align:left", "align:right"]);
+        assert_eq!(
+            &*graph.get_parent(&table.id).unwrap().unwrap().content,
+            "Options"
+        );
+
+        let rows = graph.get_children(&table.id).unwrap();
+        assert_eq!(rows.len(), 3);
+        assert!(rows.iter().any(|r| tags(r) == ["header"]));
+        assert!(rows.iter().any(|r| &*r.content == "`port` | 8080"));
+
+        let cells = graph.get_nodes_by_type(NodeType::TableCell);
+        assert_eq!(cells.len(), 6);
+        let value = cells.iter().find(|c| &*c.content == "localhost").unwrap();
+        assert_eq!(value.metadata.title.as_deref(), Some("Default"));
+    }
+
+    #[test]
+    fn test_links_and_images() {
+        let markdown = r#"## Links
+See the [guide](https://example.com/guide "Guide") for details.
+
+![Architecture diagram](img/arch.png)
+
+- [Item link](#links)
+"#;
+        let graph = parse_markdown(markdown).unwrap();
+
+        let links = graph.get_nodes_by_type(NodeType::Link);
+        assert_eq!(links.len(), 2);
+        let guide = links.iter().find(|l| &*l.content == "guide").unwrap();
+        assert_eq!(guide.attribute("url"), Some("https://example.com/guide"));
+        assert_eq!(guide.attribute("title"), Some("Guide"));
+
+        let images = graph.get_nodes_by_type(NodeType::Image);
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].attribute("url"), Some("img/arch.png"));
+        assert_eq!(images[0].attribute("alt"), Some("Architecture diagram"));
+
+        // The paragraph containing the link references it
+        let text = graph
+            .get_nodes_by_type(NodeType::Text)
+            .into_iter()
+            .find(|t| t.content.starts_with("See the guide"))
+            .unwrap();
+        let referencing: Vec<_> = graph
+            .edges()
+            .filter(|e| e.relation_type == RelationType::References && e.to == guide.id)
+            .collect();
+        assert_eq!(referencing.len(), 1);
+        assert_eq!(referencing[0].from, text.id);
+
+        // Links in list items are referenced by the item
+        let item = graph.get_nodes_by_type(NodeType::ListItem)[0];
+        assert!(graph
+            .edges()
+            .any(|e| e.relation_type == RelationType::References && e.from == item.id));
+    }
+
+    #[test]
+    fn test_quotes_and_footnotes() {
+        let markdown = r#"## Notes
+Rust is fast[^perf].
+
+> [!WARNING]
+> Unsafe code voids the warranty.
+
+> Plain quote.
+
+[^perf]: See the benchmarks.
+"#;
+        let graph = parse_markdown(markdown).unwrap();
+
+        let quotes = graph.get_nodes_by_type(NodeType::Quote);
+        assert_eq!(quotes.len(), 2);
+        let warning = quotes
+            .iter()
+            .find(|q| q.attribute("admonition") == Some("warning"))
+            .unwrap();
+        assert_eq!(&*warning.content, "Unsafe code voids the warranty.");
+        assert!(tags(warning).contains(&"admonition:warning"));
+        assert!(quotes.iter().any(|q| &*q.content == "Plain quote."));
+
+        let footnotes = graph.get_nodes_by_type(NodeType::Footnote);
+        assert_eq!(footnotes.len(), 1);
+        assert_eq!(&*footnotes[0].content, "See the benchmarks.");
+        assert_eq!(footnotes[0].attribute("label"), Some("perf"));
+
+        let text = graph
+            .get_nodes_by_type(NodeType::Text)
+            .into_iter()
+            .find(|t| &*t.content == "Rust is fast[^perf].")
+            .unwrap();
+        assert!(graph
+            .edges()
+            .any(|e| e.relation_type == RelationType::References
+                && e.from == text.id
+                && e.to == footnotes[0].id));
+    }
+
+    #[test]
+    fn test_frontmatter_document_root() {
+        let markdown = "---\ntitle: Install Guide\ntags:\n  - setup\n  - linux\nauthor: Jane\n---\n# Installing\nRun the installer.\n";
+        let graph = parse_markdown(markdown).unwrap();
+
+        let documents = graph.get_nodes_by_type(NodeType::Document);
+        assert_eq!(documents.len(), 1);
+        let document = documents[0];
+        assert_eq!(document.metadata.title.as_deref(), Some("Install Guide"));
+        assert_eq!(tags(document), ["setup", "linux"]);
+        assert_eq!(document.attribute("author"), Some("Jane"));
+
+        let section = find_section(&graph, "Installing");
+        assert_eq!(
+            graph.get_parent(&section.id).unwrap().unwrap().id,
+            document.id
+        );
+        // Frontmatter must not leak into the body as text or headings
+        assert_eq!(graph.get_nodes_by_type(NodeType::Section).len(), 1);
+    }
+
+    #[test]
+    fn test_parse_markdown_file_root() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("notes.md");
+        std::fs::write(&path, "Just some text.\n").unwrap();
+
+        let graph = parse_markdown_file(&path).unwrap();
+        let documents = graph.documents();
+        assert_eq!(documents.len(), 1);
+        assert_eq!(documents[0].metadata.title.as_deref(), Some("notes"));
+        assert_eq!(
+            documents[0].attribute("path"),
+            Some(path.display().to_string().as_str())
+        );
+    }
+
+    #[test]
+    fn test_document_node_ids_are_stable() {
+        let markdown = "# Guide\nSome text.\n";
+        let ids = |content, name| {
+            let graph = parse_markdown_document(content, name).unwrap();
+            graph
+                .reading_order()
+                .map(|node| node.id)
+                .collect::<Vec<_>>()
+        };
+        let first = ids(markdown, "docs/guide.md");
+        assert_eq!(first, ids(markdown, "docs/guide.md"));
+        assert_ne!(first, ids(markdown, "docs/other.md"));
+        assert_ne!(first[1], ids("# Guide\nOther text.\n", "docs/guide.md")[1]);
+    }
+
+    #[test]
+    fn test_parse_markdown_dir() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::fs::write(temp_dir.path().join("a.md"), "# A\nText.\n").unwrap();
+        std::fs::create_dir(temp_dir.path().join("nested")).unwrap();
+        std::fs::write(temp_dir.path().join("nested/b.md"), "# B\n").unwrap();
+        std::fs::write(temp_dir.path().join("ignored.txt"), "# C\n").unwrap();
+
+        let corpus = parse_markdown_dir(temp_dir.path()).unwrap();
+        assert_eq!(corpus.documents().len(), 2);
+    }
+
+    #[test]
+    fn test_source_spans() {
+        let markdown =
+            "---\ntitle: Spans\n---\n# Heading\n\nFirst paragraph\nwraps here.\n\n```sh\nls\n```\n";
+        let graph = parse_markdown(markdown).unwrap();
+
+        let heading = find_section(&graph, "Heading");
+        let span = heading.metadata.span.unwrap();
+        assert_eq!((span.start_line, span.end_line), (4, 4));
+        assert_eq!(&markdown[span.start_byte..span.end_byte], "# Heading\n");
+
+        let text = graph.get_nodes_by_type(NodeType::Text)[0];
+        let span = text.metadata.span.unwrap();
+        assert_eq!((span.start_line, span.end_line), (6, 7));
+
+        let code = graph.get_nodes_by_type(NodeType::Code)[0];
+        let span = code.metadata.span.unwrap();
+        assert_eq!((span.start_line, span.end_line), (9, 11));
+
+        let document = graph.documents()[0];
+        let span = document.metadata.span.unwrap();
+        assert_eq!((span.start_byte, span.end_byte), (0, markdown.len()));
+    }
+
+    #[test]
+    fn test_nested_lists() {
+        let graph = parse_markdown(MARKDOWN).unwrap();
+
+        let items = graph.get_nodes_by_type(NodeType::ListItem);
+        assert_eq!(items.len(), 3);
+
+        let nested = items.iter().find(|n| &*n.content == "Nested item").unwrap();
+        let path: Vec<NodeType> = graph
+            .get_path_to_root(&nested.id)
+            .unwrap()
+            .into_iter()
+            .map(|n| n.node_type.clone())
+            .collect();
+        assert_eq!(
+            path,
+            vec![
+                NodeType::Document,
+                NodeType::Section,
+                NodeType::Section,
+                NodeType::Section,
+                NodeType::List,
+                NodeType::ListItem,
+                NodeType::List,
+            ]
+        );
+
+        let item2 = items.iter().find(|n| &*n.content == "List item 2").unwrap();
+        assert_eq!(graph.get_children(&item2.id).unwrap().len(), 1);
     }
 }
@@ -0,0 +1,983 @@
+//! The generation run as a chain of stages connected by bounded channels:
+//! collection → chunking → generation → filtering → writing.
+//!
+//! Each stage works on one file or section at a time and hands it on, so
+//! downloads, LLM requests and disk writes overlap instead of waiting for
+//! each other, while the channel capacity bounds how much is held in memory.
+
+use anyhow::{anyhow, Result};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tracing::Instrument;
+
+use crate::changelog::{self, Release};
+use crate::datasource::{record_sources, DataSource, SourceProvenance};
+use crate::density::DocumentSize;
+use crate::glossary::Glossary;
+use crate::graph::{node::NodeType, tag_nodes, DocumentGraph, TaggingConfig};
+use crate::incremental::{section_hash, SectionIndex};
+use crate::oversize::{format_size, split_file, summarize_file, summary_path, OversizePolicy};
+use crate::parser::parse_markdown_document;
+use crate::processor::{
+    DefaultOllamaProcessor, ItemSink, PreviousSections, ProcessedItem, ProcessorError,
+};
+use crate::schema;
+use crate::sections::{read_sections, SpannedSection};
+
+/// Files or sections waiting between two stages
+pub const CHANNEL_CAPACITY: usize = 8;
+
+/// Called with the outcome of each file: the number of records passed to
+/// the sink, or why the file failed
+pub type FileCallback<'a> = dyn FnMut(&Path, Result<usize>) + Send + 'a;
+
+/// What happens to a section on its way through the stages
+enum Work {
+    /// Unchanged since the previous run; its records are kept (`--incremental`)
+    Reuse(Vec<ProcessedItem>),
+    /// Questions are still to be generated
+    Generate,
+    /// Questions were generated, or generating them failed
+    Generated(Result<Vec<ProcessedItem>>),
+    /// Not part of the sample (`--sample`)
+    SampledOut,
+    /// Has no node targeted by `--target-tag`, `--only-sections` or
+    /// `--only-matching`
+    Untargeted,
+    /// Not generated because of shutdown or the question cap
+    Skipped,
+}
+
+struct Section {
+    /// 1-based position in the file
+    number: usize,
+    text: String,
+    /// Byte range of the section in the file
+    span: Range<usize>,
+    /// Held out for the evaluation set
+    held_out: bool,
+    work: Work,
+}
+
+/// Messages passed between the stages, in file order
+enum Event {
+    /// A file's sections follow
+    Start {
+        path: PathBuf,
+        hash: String,
+        size: DocumentSize,
+        sections: usize,
+        questions: usize,
+        /// Terms of the file passed with its sections (`--glossary`)
+        glossary: Option<Glossary>,
+    },
+    Section(Section),
+    /// The end of a file's sections; an error if reading it failed midway
+    End(Result<()>),
+    /// The QA file of an earlier run is kept as it is
+    Existing {
+        path: PathBuf,
+        hash: String,
+    },
+    /// The file could not be read
+    Failed(PathBuf, anyhow::Error),
+}
+
+/// Runs files through the chunking, generation, filtering and writing stages
+/// of a processor
+pub struct Pipeline<'a> {
+    processor: &'a DefaultOllamaProcessor,
+}
+
+impl<'a> Pipeline<'a> {
+    pub fn new(processor: &'a DefaultOllamaProcessor) -> Self {
+        Self { processor }
+    }
+
+    /// Process the files received until the channel closes or shutdown is
+    /// requested, handing records to `sink` and each file's outcome to
+    /// `on_file`
+    ///
+    /// Returns the number of files left out because of `--max-files`.
+    pub async fn run(
+        self,
+        files: mpsc::Receiver<PathBuf>,
+        sink: &mut ItemSink<'_>,
+        on_file: &mut FileCallback<'_>,
+    ) -> usize {
+        let (chunked, to_generate) = mpsc::channel(CHANNEL_CAPACITY);
+        let (generated, to_filter) = mpsc::channel(CHANNEL_CAPACITY);
+        let (filtered, to_write) = mpsc::channel(CHANNEL_CAPACITY);
+
+        let (held_back, _, _, _) = tokio::join!(
+            self.chunk(files, chunked),
+            self.generate(to_generate, generated),
+            self.filter(to_filter, filtered),
+            self.write(to_write, sink, on_file),
+        );
+        if let Err(e) = self.processor.save_ledger() {
+            println!("Could not save the ledger: {}", e);
+        }
+        held_back
+    }
+
+    /// Split each file into sections and decide which need generating
+    async fn chunk(&self, mut files: mpsc::Receiver<PathBuf>, out: mpsc::Sender<Event>) -> usize {
+        let limits = self.processor.limits();
+        let mut taken = 0;
+        let mut held_back = 0;
+
+        while let Some(path) = files.recv().await {
+            if self.processor.shutdown().is_requested() {
+                break;
+            }
+            if let Some(max) = limits.max_files.filter(|&max| taken >= max) {
+                if held_back == 0 {
+                    println!("Processing the first {} files (--max-files)", max);
+                }
+                held_back += 1;
+                continue;
+            }
+            taken += 1;
+            println!("Processing file: {:?}", path);
+            for observer in self.processor.observers() {
+                observer.on_file_start(&path);
+            }
+
+            let documents = match self.documents(&path).await {
+                Ok(documents) => documents,
+                Err(e) => {
+                    if out.send(Event::Failed(path, e)).await.is_err() {
+                        break;
+                    }
+                    continue;
+                }
+            };
+            for document in documents {
+                let span = tracing::info_span!("chunk", file = %document.display());
+                if !self.chunk_file(document, &out).instrument(span).await {
+                    return held_back;
+                }
+            }
+        }
+        held_back
+    }
+
+    /// The documents to generate from for a file: the file itself, or for a
+    /// file over the size limit its parts or its summary, or none if it is
+    /// skipped
+    async fn documents(&self, path: &Path) -> Result<Vec<PathBuf>> {
+        let limit = self.processor.size_limit();
+        let size = fs::metadata(path)
+            .await
+            .map_err(ProcessorError::io(path))?
+            .len();
+        if !limit.exceeded_by(size) {
+            return Ok(vec![path.to_path_buf()]);
+        }
+        let parts_dir = self.processor.parts_dir();
+        match limit.policy {
+            OversizePolicy::Skip => {
+                println!(
+                    "Skipping {:?}: {} is over the {} file size limit (--oversized split or summarize processes it)",
+                    path,
+                    format_size(size),
+                    format_size(limit.max_bytes)
+                );
+                Ok(Vec::new())
+            }
+            OversizePolicy::Split => {
+                let (file, dir) = (path.to_path_buf(), parts_dir);
+                let parts =
+                    tokio::task::spawn_blocking(move || split_file(&file, &dir, limit.max_bytes))
+                        .await??;
+                println!(
+                    "Split {:?} ({}) into {} parts",
+                    path,
+                    format_size(size),
+                    parts.len()
+                );
+                Ok(parts)
+            }
+            OversizePolicy::Summarize => {
+                let summary_path = summary_path(path, &parts_dir);
+                if !self.processor.is_forced() && is_newer(&summary_path, path).await {
+                    println!("Using the existing summary {:?}", summary_path);
+                    return Ok(vec![summary_path]);
+                }
+                println!(
+                    "Summarizing {:?} ({}) section by section...",
+                    path,
+                    format_size(size)
+                );
+                let processor = self.processor;
+                let summary =
+                    summarize_file(path, &parts_dir, limit.max_bytes, |text| async move {
+                        if processor.shutdown().is_requested() {
+                            return Err(anyhow!("Summarizing stopped by shutdown"));
+                        }
+                        processor.summarize(&text).await
+                    })
+                    .await?;
+                Ok(vec![summary])
+            }
+        }
+    }
+
+    /// Plan a file and send its sections on; `false` if the next stage is gone
+    async fn chunk_file(&self, path: PathBuf, out: &mpsc::Sender<Event>) -> bool {
+        let plan = match self.processor.plan_file(&path).await {
+            Ok(plan) => plan,
+            Err(e) => return out.send(Event::Failed(path, e)).await.is_ok(),
+        };
+        if plan.existing {
+            let existing = Event::Existing {
+                path,
+                hash: plan.hash,
+            };
+            return out.send(existing).await.is_ok();
+        }
+
+        let glossary = match self.processor.glossary_enabled() {
+            true => self.glossary(&path).await,
+            false => None,
+        };
+        let start = Event::Start {
+            path: path.clone(),
+            hash: plan.hash,
+            size: plan.size,
+            // The glossary's definitions follow as a section of their own
+            sections: plan.size.sections + usize::from(glossary.is_some()),
+            questions: plan.questions,
+            glossary: glossary.clone(),
+        };
+        if out.send(start).await.is_err() {
+            return false;
+        }
+        let targeted = match self.targeted_spans(&path).await {
+            Ok(targeted) => targeted,
+            Err(e) => return out.send(Event::End(Err(e))).await.is_ok(),
+        };
+        let mut previous = plan.previous;
+        let sections = match plan.releases {
+            Some(releases) => match self.write_releases(&path, &releases).await {
+                Ok(()) => release_sections(&releases),
+                Err(e) => return out.send(Event::End(Err(e))).await.is_ok(),
+            },
+            None => read_sections(path.clone()),
+        };
+        let end = match self
+            .chunk_sections(sections, previous.as_mut(), targeted.as_deref(), out)
+            .await
+        {
+            Ok(true) => Ok(()),
+            Ok(false) => return false,
+            Err(e) => Err(e),
+        };
+        if let (Ok(()), Some(glossary)) = (&end, glossary) {
+            let section = self
+                .glossary_section(&path, glossary, plan.size.sections + 1, previous.as_mut())
+                .await;
+            if out.send(Event::Section(section)).await.is_err() {
+                return false;
+            }
+        }
+        out.send(Event::End(end)).await.is_ok()
+    }
+
+    /// The glossary of a file (`--glossary`), kept next to its QA file as
+    /// `NAME_qa.glossary.json`
+    ///
+    /// A glossary newer than the file is used as it is. Without one the
+    /// model is asked for the terms; if that fails, or finds no terms, the
+    /// file is generated from without a glossary.
+    async fn glossary(&self, path: &Path) -> Option<Glossary> {
+        let glossary_path = self.processor.get_qa_path(path, "glossary.json");
+        if !self.processor.is_forced() && is_newer(&glossary_path, path).await {
+            match fs::read(&glossary_path).await.map_err(anyhow::Error::from) {
+                Ok(bytes) => match serde_json::from_slice::<Glossary>(&bytes) {
+                    Ok(glossary) if glossary.is_empty() => return None,
+                    Ok(glossary) => {
+                        println!("Using the existing glossary {:?}", glossary_path);
+                        return Some(glossary);
+                    }
+                    Err(e) => println!("Extracting the glossary again: {}", e),
+                },
+                Err(e) => println!("Extracting the glossary again: {}", e),
+            }
+        }
+        let extracted = async {
+            let document = fs::read_to_string(path)
+                .await
+                .map_err(ProcessorError::io(path))?;
+            let glossary = self.processor.extract_glossary(&document).await?;
+            fs::write(&glossary_path, serde_json::to_vec_pretty(&glossary)?).await?;
+            anyhow::Ok(glossary)
+        };
+        match extracted.await {
+            Ok(glossary) if glossary.is_empty() => {
+                println!("No glossary terms found in {:?}", path);
+                None
+            }
+            Ok(glossary) => {
+                println!(
+                    "Wrote {} glossary terms to {:?}",
+                    glossary.terms.len(),
+                    glossary_path
+                );
+                Some(glossary)
+            }
+            Err(e) => {
+                println!("Continuing without a glossary for {:?}: {:#}", path, e);
+                None
+            }
+        }
+    }
+
+    /// The section holding a file's definition questions, one per glossary
+    /// term, traced to the whole file
+    async fn glossary_section(
+        &self,
+        path: &Path,
+        glossary: Glossary,
+        number: usize,
+        previous: Option<&mut PreviousSections>,
+    ) -> Section {
+        let text = glossary.to_section();
+        let length = fs::metadata(path)
+            .await
+            .map_or(0, |meta| meta.len() as usize);
+        let reused = previous
+            .and_then(|groups| groups.get_mut(&section_hash(&text)))
+            .filter(|groups| !groups.is_empty())
+            .map(|groups| groups.remove(0));
+        let limits = self.processor.limits();
+        let work = match reused {
+            Some(items) => Work::Reuse(items),
+            None if self.processor.shutdown().is_requested() => Work::Skipped,
+            None => {
+                let mut items = glossary.definitions();
+                items.truncate(limits.take_questions(items.len()));
+                Work::Generated(Ok(items))
+            }
+        };
+        Section {
+            number,
+            text,
+            span: 0..length,
+            held_out: false,
+            work,
+        }
+    }
+
+    /// Spans of the file's targeted nodes: those carrying a tag asked for
+    /// (`--target-tag`), once its nodes are tagged with their keywords and
+    /// entities, under a heading asked for (`--only-sections`) and matching
+    /// the pattern (`--only-matching`); `None` when nothing was targeted
+    ///
+    /// A node has to meet every kind of target given.
+    async fn targeted_spans(&self, path: &Path) -> Result<Option<Vec<Range<usize>>>> {
+        if !self.processor.is_targeted() {
+            return Ok(None);
+        }
+        let tags = self.processor.target_tags().to_vec();
+        let headings: Vec<String> = self
+            .processor
+            .target_sections()
+            .iter()
+            .map(|heading| heading.trim().to_lowercase())
+            .collect();
+        let pattern = self.processor.target_pattern().cloned();
+        let name = self.processor.document_key(path);
+        let path = path.to_path_buf();
+        let spans = tokio::task::spawn_blocking(move || {
+            let content = std::fs::read_to_string(&path).map_err(ProcessorError::io(&path))?;
+            let mut graph = parse_markdown_document(&content, &name)?;
+            if !tags.is_empty() {
+                tag_nodes(&mut graph, &TaggingConfig::default());
+            }
+            let mut targeted = graph.query();
+            if !tags.is_empty() {
+                targeted = targeted.tagged(&tags);
+            }
+            if !headings.is_empty() {
+                let under = graph
+                    .query()
+                    .of_type(NodeType::Section)
+                    .filter(|node| headings.contains(&node.content.trim().to_lowercase()))
+                    .with_descendants();
+                targeted = targeted.intersect(&under);
+            }
+            if let Some(pattern) = &pattern {
+                targeted = targeted.matching(pattern);
+            }
+            let spans = targeted
+                .iter()
+                .filter_map(|node| node.metadata.span)
+                .map(|span| span.start_byte..span.end_byte)
+                .collect();
+            anyhow::Ok(spans)
+        })
+        .await??;
+        Ok(Some(spans))
+    }
+
+    /// Keep the parsed versions of a changelog next to its QA file, as
+    /// `NAME_qa.changelog.json`
+    async fn write_releases(&self, path: &Path, releases: &[Release]) -> Result<()> {
+        let releases_path = self.processor.get_qa_path(path, "changelog.json");
+        fs::write(&releases_path, serde_json::to_vec_pretty(releases)?).await?;
+        Ok(())
+    }
+
+    /// Send the sections of a file on; `false` if the next stage is gone
+    async fn chunk_sections(
+        &self,
+        mut sections: mpsc::Receiver<Result<SpannedSection>>,
+        mut previous: Option<&mut PreviousSections>,
+        targeted: Option<&[Range<usize>]>,
+        out: &mpsc::Sender<Event>,
+    ) -> Result<bool> {
+        let mut number = 0;
+        while let Some(section) = sections.recv().await {
+            let SpannedSection { text, span } = section?;
+            number += 1;
+            let held_out = self.processor.held_out(&text);
+            // Records from before the evaluation split changed are of the
+            // wrong kind and are generated again
+            let reused = previous
+                .as_mut()
+                .and_then(|groups| groups.get_mut(&section_hash(&text)))
+                .filter(|groups| !groups.is_empty())
+                .map(|groups| groups.remove(0))
+                .filter(|items| items.iter().all(|item| item.is_eval() == held_out));
+            // A section is targeted by a targeted node lying within it
+            let untargeted = targeted.is_some_and(|targeted| {
+                !targeted
+                    .iter()
+                    .any(|node| span.start <= node.start && node.end <= span.end)
+            });
+            let work = match reused {
+                Some(items) => Work::Reuse(items),
+                None if untargeted => Work::Untargeted,
+                None if !self.processor.limits().sampled(&text) => Work::SampledOut,
+                None => Work::Generate,
+            };
+            let section = Section {
+                number,
+                text,
+                span,
+                held_out,
+                work,
+            };
+            if out.send(Event::Section(section)).await.is_err() {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Generate questions for the sections that need them, one at a time
+    async fn generate(&self, mut events: mpsc::Receiver<Event>, out: mpsc::Sender<Event>) {
+        let (mut size, mut sections, mut questions) = (DocumentSize::default(), 0, 0);
+        let mut document = String::new();
+        let mut glossary = None;
+
+        while let Some(mut event) = events.recv().await {
+            match &mut event {
+                Event::Start {
+                    path,
+                    size: z,
+                    sections: s,
+                    questions: q,
+                    glossary: g,
+                    ..
+                } => {
+                    (size, sections, questions) = (*z, *s, *q);
+                    document = self.processor.document_key(path);
+                    glossary = g.take();
+                }
+                Event::Section(section) if matches!(section.work, Work::Generate) => {
+                    let span = tracing::info_span!(
+                        "generate",
+                        document = %document,
+                        section = section.number
+                    );
+                    section.work = self
+                        .generate_section(
+                            &document,
+                            section,
+                            glossary.as_ref(),
+                            (&size, sections, questions),
+                        )
+                        .instrument(span.clone())
+                        .await;
+                    if let Work::Generated(Err(e)) = &section.work {
+                        tracing::error!(parent: &span, "{:#}", e);
+                    }
+                }
+                _ => {}
+            }
+            if out.send(event).await.is_err() {
+                break;
+            }
+        }
+    }
+
+    /// Generate a section's questions; the totals are the size, sections
+    /// and questions of its file
+    async fn generate_section(
+        &self,
+        document: &str,
+        section: &Section,
+        glossary: Option<&Glossary>,
+        (total_size, total_sections, total_questions): (&DocumentSize, usize, usize),
+    ) -> Work {
+        let (shutdown, limits) = (self.processor.shutdown(), self.processor.limits());
+        // Unchanged sections are still passed on, so an interrupted
+        // incremental run loses none of the earlier records
+        if shutdown.is_requested() {
+            return Work::Skipped;
+        }
+        let mut target = self
+            .processor
+            .section_target(&section.text, total_size, total_questions);
+        match limits.remaining_questions() {
+            Some(0) => {
+                limits.skip_capped();
+                return Work::Skipped;
+            }
+            Some(remaining) => target = target.min(remaining),
+            None => {}
+        }
+
+        println!(
+            "\nProcessing section {}/{} ({} words, target {} questions)",
+            section.number,
+            total_sections,
+            DefaultOllamaProcessor::count_words(&section.text),
+            target
+        );
+        let generate = async {
+            if section.held_out {
+                self.processor
+                    .process_eval_section(&section.text, target, glossary)
+                    .await
+            } else {
+                self.processor
+                    .process_section_recursive(&section.text, target, glossary)
+                    .await
+            }
+        };
+        let generated = tokio::select! {
+            result = generate => result,
+            _ = shutdown.requested() => return Work::Skipped,
+        };
+        // Counted here rather than when written, so the next section's
+        // target already sees this one's questions
+        let items = match generated {
+            Ok(mut items) => {
+                items.truncate(limits.take_questions(items.len()));
+                items
+            }
+            Err(e) => return Work::Generated(Err(e)),
+        };
+        if section.held_out {
+            return Work::Generated(Ok(items));
+        }
+        // Reference answers of the evaluation set aren't resampled or
+        // distilled. Without --candidates or --student-model this finishes
+        // at once, and then wins over a shutdown requested during generation.
+        let refine = async {
+            let items = self.processor.best_of(&section.text, items).await;
+            self.processor
+                .distill(document, &section.text, section.span.clone(), items)
+                .await
+        };
+        tokio::select! {
+            biased;
+            items = refine => Work::Generated(Ok(items)),
+            _ = shutdown.requested() => Work::Skipped,
+        }
+    }
+
+    /// Drop generated records that don't pass schema validation, then let
+    /// the observers see (and filter) the rest
+    async fn filter(&self, mut events: mpsc::Receiver<Event>, out: mpsc::Sender<Event>) {
+        let mut document = String::new();
+        while let Some(mut event) = events.recv().await {
+            match &mut event {
+                Event::Start { path, .. } => document = self.processor.document_key(path),
+                Event::Section(Section {
+                    number,
+                    span,
+                    work: Work::Generated(Ok(items)),
+                    ..
+                }) => {
+                    let before = items.len();
+                    items.retain(|item| schema::validate_item(item).is_ok());
+                    if items.len() < before {
+                        println!(
+                            "Dropped {} invalid questions from section {}",
+                            before - items.len(),
+                            number
+                        );
+                    }
+                    for observer in self.processor.observers() {
+                        observer.on_chunk_generated(&document, span, items);
+                    }
+                }
+                _ => {}
+            }
+            if out.send(event).await.is_err() {
+                break;
+            }
+        }
+    }
+
+    /// Write each file's QA file and section index and pass its records on
+    async fn write(
+        &self,
+        mut events: mpsc::Receiver<Event>,
+        sink: &mut ItemSink<'_>,
+        on_file: &mut FileCallback<'_>,
+    ) {
+        let mut current: Option<FileState> = None;
+        let mut on_file = |path: &Path, result: Result<usize>| {
+            if let Err(e) = &result {
+                for observer in self.processor.observers() {
+                    observer.on_error(path, e);
+                }
+            }
+            on_file(path, result);
+        };
+
+        while let Some(event) = events.recv().await {
+            match event {
+                Event::Start {
+                    path,
+                    hash,
+                    sections,
+                    ..
+                } => {
+                    current = Some(FileState::create(self.processor, path, hash, sections).await);
+                }
+                Event::Section(section) => {
+                    if let Some(state) = current.as_mut() {
+                        state.section(self.processor, section, sink).await;
+                    }
+                }
+                Event::End(end) => {
+                    if let Some(state) = current.take() {
+                        let path = state.path.clone();
+                        on_file(&path, state.finish(self.processor, end).await);
+                    }
+                }
+                Event::Existing { path, hash } => {
+                    let result = self.processor.replay_existing_qa(&path, sink);
+                    if let Ok(records) = result {
+                        self.processor.file_generated(&path, hash, records, true);
+                    }
+                    on_file(&path, result);
+                }
+                Event::Failed(path, e) => on_file(&path, Err(e)),
+            }
+        }
+    }
+}
+
+/// The writing stage's progress through one file
+struct FileState {
+    path: PathBuf,
+    /// The file as named in record IDs
+    source: String,
+    hash: String,
+    qa_file: Result<fs::File>,
+    index: SectionIndex,
+    sections: usize,
+    passed: usize,
+    skipped: usize,
+    sampled_out: usize,
+    untargeted: usize,
+    last_error: Option<anyhow::Error>,
+    /// The file parsed into a graph, for tracing records to its nodes
+    graph: Option<DocumentGraph>,
+}
+
+impl FileState {
+    /// Create or truncate the file's QA file
+    async fn create(
+        processor: &DefaultOllamaProcessor,
+        path: PathBuf,
+        hash: String,
+        sections: usize,
+    ) -> Self {
+        let qa_path = processor.get_qa_path(&path, "jsonl");
+        println!("Creating output file at {:?}", qa_path);
+        let qa_file = create_qa_file(&qa_path).await;
+        let source = processor.document_key(&path);
+        let graph = parse_document(path.clone(), source.clone(), processor.rich_schema()).await;
+        Self {
+            source,
+            graph,
+            path,
+            hash,
+            qa_file,
+            index: SectionIndex::default(),
+            sections,
+            passed: 0,
+            skipped: 0,
+            sampled_out: 0,
+            untargeted: 0,
+            last_error: None,
+        }
+    }
+
+    async fn section(
+        &mut self,
+        processor: &DefaultOllamaProcessor,
+        section: Section,
+        sink: &mut ItemSink<'_>,
+    ) {
+        let mut items = match section.work {
+            Work::Reuse(items) => {
+                println!(
+                    "\nSection {}/{} unchanged, keeping {} existing questions",
+                    section.number,
+                    self.sections,
+                    items.len()
+                );
+                items
+            }
+            Work::Generated(Ok(items)) => items,
+            Work::Generated(Err(e)) => {
+                println!("Error processing section: {}", e);
+                for observer in processor.observers() {
+                    observer.on_error(&self.path, &e);
+                }
+                self.last_error = Some(e);
+                return;
+            }
+            Work::SampledOut => {
+                self.sampled_out += 1;
+                return;
+            }
+            Work::Untargeted => {
+                self.untargeted += 1;
+                return;
+            }
+            Work::Generate | Work::Skipped => {
+                self.skipped += 1;
+                return;
+            }
+        };
+
+        processor.trace_records(
+            &mut items,
+            &self.source,
+            &section.text,
+            section.span,
+            self.graph.as_ref(),
+        );
+
+        // Write questions from this section immediately
+        let Ok(file) = self.qa_file.as_mut() else {
+            return;
+        };
+        let span = tracing::info_span!("write", document = %self.source, section = section.number);
+        let written = match write_records(file, &items)
+            .instrument(span)
+            .await
+            .and_then(|written| {
+                sink(&items)?;
+                Ok(written)
+            }) {
+            Ok(written) => written,
+            Err(e) => {
+                self.qa_file = Err(e);
+                return;
+            }
+        };
+        println!("Added {} questions (written to file)", written);
+        for observer in processor.observers() {
+            items
+                .iter()
+                .for_each(|item| observer.on_item_accepted(item));
+        }
+        self.index.push(&section.text, written);
+        self.passed += items.len();
+    }
+
+    async fn finish(self, processor: &DefaultOllamaProcessor, end: Result<()>) -> Result<usize> {
+        if self.sampled_out > 0 {
+            println!(
+                "Sampled {} of {} sections",
+                self.sections - self.sampled_out,
+                self.sections
+            );
+        }
+        if self.untargeted > 0 {
+            println!(
+                "{} of {} sections have no node targeted by --target-tag, --only-sections or --only-matching",
+                self.untargeted, self.sections
+            );
+        }
+        if self.skipped > 0 {
+            println!(
+                "Stopped early, {} of {} sections were not generated",
+                self.skipped, self.sections
+            );
+        }
+        // Writes to a tokio file complete in the background until flushed
+        self.qa_file?.flush().await?;
+        end?;
+        self.index.save(&processor.section_index_path(&self.path))?;
+        match self.last_error {
+            // Every section that was attempted failed, so the file failed
+            Some(e) if self.passed == 0 => {
+                Err(e.context(format!("No questions generated for {:?}", self.path)))
+            }
+            Some(_) => {
+                processor.file_generated(&self.path, self.hash, self.passed, false);
+                Ok(self.passed)
+            }
+            None => {
+                // A file cut short is not skipped next time
+                let complete = self.skipped == 0
+                    && self.sampled_out == 0
+                    && self.untargeted == 0
+                    && processor.limits().exceeded(0).is_none();
+                processor.file_generated(&self.path, self.hash, self.passed, complete);
+                Ok(self.passed)
+            }
+        }
+    }
+}
+
+/// The sections of a changelog's versions, received like those of a file
+fn release_sections(releases: &[Release]) -> mpsc::Receiver<Result<SpannedSection>> {
+    let sections = changelog::sections(releases);
+    let (sender, receiver) = mpsc::channel(sections.len().max(1));
+    for section in sections {
+        let _ = sender.try_send(Ok(section));
+    }
+    receiver
+}
+
+/// Whether `path` exists and was modified after `than`
+async fn is_newer(path: &Path, than: &Path) -> bool {
+    let modified = |path: &Path| {
+        let path = path.to_path_buf();
+        async move {
+            fs::metadata(path)
+                .await
+                .and_then(|meta| meta.modified())
+                .ok()
+        }
+    };
+    match (modified(path).await, modified(than).await) {
+        (Some(modified), Some(source)) => modified >= source,
+        _ => false,
+    }
+}
+
+/// Create a QA file holding only the schema header
+async fn create_qa_file(path: &Path) -> Result<fs::File> {
+    let mut header = Vec::new();
+    schema::write_header(&mut header)?;
+    let mut file = fs::File::create(path)
+        .await
+        .map_err(ProcessorError::io(path))?;
+    file.write_all(&header)
+        .await
+        .map_err(ProcessorError::io(path))?;
+    Ok(file)
+}
+
+/// Append the records that pass validation to a QA file, returning how many
+/// were written
+async fn write_records(file: &mut fs::File, items: &[ProcessedItem]) -> Result<usize> {
+    let mut records = Vec::new();
+    let written = schema::write_items(&mut records, items)?;
+    file.write_all(&records).await?;
+    Ok(written)
+}
+
+/// Parse a file into a graph named `name`, or `None` with a note if it
+/// cannot be, in which case its records carry no graph nodes
+///
+/// With `tagged`, nodes are tagged with their keywords and entities for the
+/// `tags` column of the records.
+async fn parse_document(path: PathBuf, name: String, tagged: bool) -> Option<DocumentGraph> {
+    let parsed = tokio::task::spawn_blocking(move || {
+        let content = std::fs::read_to_string(&path)?;
+        let mut graph = parse_markdown_document(&content, &name)?;
+        if tagged {
+            tag_nodes(&mut graph, &TaggingConfig::default());
+        }
+        Ok(graph)
+    })
+    .await
+    .map_err(anyhow::Error::from)
+    .and_then(|parsed| parsed);
+    parsed
+        .map_err(|e| println!("Records will not name graph nodes: {}", e))
+        .ok()
+}
+
+/// Collect each source into `output_dir`, sending the files on as soon as
+/// the source is done, and record where they came from
+///
+/// Stops early, without an error, once the receiving end is closed.
+pub async fn collect_sources(
+    sources: Vec<Box<dyn DataSource>>,
+    output_dir: &Path,
+    files: mpsc::Sender<PathBuf>,
+) -> Result<Vec<SourceProvenance>> {
+    fs::create_dir_all(output_dir).await?;
+
+    let mut provenance = Vec::new();
+    'sources: for source in sources {
+        println!("\nProcessing source...");
+        let mut origin = source.provenance().await;
+
+        let span = tracing::info_span!("collect", source = %origin.location);
+        let collected = source
+            .collect(output_dir)
+            .instrument(span.clone())
+            .await
+            .inspect_err(|e| tracing::error!(parent: &span, "{:#}", e))?;
+        println!("Found {} files", collected.len());
+        origin.files = collected
+            .iter()
+            .map(|file| {
+                file.strip_prefix(output_dir)
+                    .unwrap_or(file)
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect();
+        provenance.push(origin);
+        for file in collected {
+            if files.send(file).await.is_err() {
+                break 'sources;
+            }
+        }
+    }
+    if !provenance.is_empty() {
+        record_sources(output_dir, &provenance)?;
+    }
+    Ok(provenance)
+}
+
+/// Send `files` into a pipeline, stopping once the receiving end is closed
+pub async fn send_files(files: Vec<PathBuf>, to: mpsc::Sender<PathBuf>) {
+    for file in files {
+        if to.send(file).await.is_err() {
+            break;
+        }
+    }
+}
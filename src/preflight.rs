@@ -0,0 +1,261 @@
+//! Health checks for the external services a command depends on, run before
+//! any files are collected so misconfiguration surfaces as one clear report
+//! instead of a failure halfway through a run.
+
+use anyhow::Result;
+use thiserror::Error;
+
+use crate::external::{
+    model_matches, Embedder, EmbeddingConfig, EmbeddingEngine, ModelProvider, VectorDB,
+    VectorDBConfig,
+};
+
+/// Outcome of checking one service
+#[derive(Debug, Clone)]
+pub struct CheckOutcome {
+    /// Service name shown in the report, e.g. "LLM"
+    pub service: &'static str,
+    /// What was checked on success, or what to fix on failure
+    pub result: std::result::Result<String, String>,
+}
+
+impl CheckOutcome {
+    fn ok(service: &'static str, detail: String) -> Self {
+        Self {
+            service,
+            result: Ok(detail),
+        }
+    }
+
+    fn failed(service: &'static str, hint: String) -> Self {
+        Self {
+            service,
+            result: Err(hint),
+        }
+    }
+
+    pub fn passed(&self) -> bool {
+        self.result.is_ok()
+    }
+}
+
+/// Every check that failed, each with what to fix
+#[derive(Debug, Error)]
+#[error("Preflight failed:\n  - {}", .failures.join("\n  - "))]
+pub struct PreflightError {
+    pub failures: Vec<String>,
+}
+
+/// The services a command needs; `None` entries are not checked
+#[derive(Default)]
+pub struct PreflightTargets<'a> {
+    /// Generation provider, and whether a missing model may be pulled
+    pub llm: Option<(&'a dyn ModelProvider, bool)>,
+    pub embedding: Option<(&'a EmbeddingEngine, &'a EmbeddingConfig)>,
+    pub vector_db: Option<&'a VectorDBConfig>,
+}
+
+/// Check every configured service, print a report, and fail with all
+/// problems at once if any check failed
+pub async fn preflight(targets: PreflightTargets<'_>) -> Result<Vec<CheckOutcome>> {
+    let mut outcomes = Vec::new();
+    if let Some((provider, allow_pull)) = targets.llm {
+        outcomes.push(check_llm(provider, allow_pull).await);
+    }
+    if let Some((engine, config)) = targets.embedding {
+        outcomes.push(check_embedding(engine, config).await);
+    }
+    if let Some(config) = targets.vector_db {
+        outcomes.push(check_vector_db(config).await);
+    }
+
+    for outcome in &outcomes {
+        match &outcome.result {
+            Ok(detail) => println!("[ok]   {}: {}", outcome.service, detail),
+            Err(hint) => println!("[fail] {}: {}", outcome.service, hint),
+        }
+    }
+
+    let failures: Vec<String> = outcomes
+        .iter()
+        .filter_map(|o| {
+            o.result
+                .as_ref()
+                .err()
+                .map(|e| format!("{}: {}", o.service, e))
+        })
+        .collect();
+    if failures.is_empty() {
+        Ok(outcomes)
+    } else {
+        Err(PreflightError { failures }.into())
+    }
+}
+
+/// Ping the Ollama server and make sure the generation model is installed
+pub async fn check_llm(provider: &dyn ModelProvider, allow_pull: bool) -> CheckOutcome {
+    let (endpoint, model) = (provider.endpoint(), provider.model());
+    let installed = match provider.list_models().await {
+        Ok(installed) => installed,
+        Err(e) => {
+            return CheckOutcome::failed(
+                "LLM",
+                format!(
+                    "cannot reach Ollama at {} ({}); start it with `ollama serve` or set \
+                     OLLAMA_ENDPOINT / --ollama-endpoint",
+                    endpoint, e
+                ),
+            )
+        }
+    };
+
+    if installed.iter().any(|name| model_matches(name, model)) {
+        CheckOutcome::ok("LLM", format!("{} at {}", model, endpoint))
+    } else if allow_pull {
+        CheckOutcome::ok(
+            "LLM",
+            format!("{} at {} (not installed, will be pulled)", model, endpoint),
+        )
+    } else {
+        CheckOutcome::failed(
+            "LLM",
+            format!(
+                "model '{}' is not installed on {}; run `ollama pull {}`, drop --no-pull, or \
+                 choose one of: {}",
+                model,
+                endpoint,
+                model,
+                if installed.is_empty() {
+                    "(none installed)".to_string()
+                } else {
+                    installed.join(", ")
+                }
+            ),
+        )
+    }
+}
+
+/// Embed a probe text, which both reaches the endpoint and proves the model exists
+pub async fn check_embedding(engine: &EmbeddingEngine, config: &EmbeddingConfig) -> CheckOutcome {
+    match engine.dimension().await {
+        Ok(dimension) => CheckOutcome::ok(
+            "Embedding",
+            format!(
+                "{} via {} ({} dimensions)",
+                engine.model(),
+                config.provider,
+                dimension
+            ),
+        ),
+        Err(e) => CheckOutcome::failed(
+            "Embedding",
+            format!(
+                "model '{}' via {} failed ({}); check EMBEDDING_PROVIDER, OLLAMA_HOST/OLLAMA_PORT \
+                 and OLLAMA_EMBEDDING_MODEL, and that the model is pulled",
+                engine.model(),
+                config.provider,
+                e
+            ),
+        ),
+    }
+}
+
+/// Ask Qdrant for its health status
+pub async fn check_vector_db(config: &VectorDBConfig) -> CheckOutcome {
+    let url = config.get_url().unwrap_or_default();
+    let version = match VectorDB::new(config.clone()).await {
+        Ok(db) => db.health_check().await,
+        Err(e) => Err(e),
+    };
+    match version {
+        Ok(version) => CheckOutcome::ok("Vector DB", format!("Qdrant {} at {}", version, url)),
+        Err(e) => CheckOutcome::failed(
+            "Vector DB",
+            format!(
+                "cannot reach Qdrant at {} ({}); start it or set QDRANT_HOST/QDRANT_PORT, \
+                 plus QDRANT_API_KEY and QDRANT_USE_TLS for secured servers",
+                url, e
+            ),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::external::embedding::MockEmbedder;
+    use crate::external::{OllamaProvider, RetryPolicy};
+    use anyhow::anyhow;
+
+    // Nothing listens on port 1, so connections are refused immediately
+    const UNREACHABLE: &str = "http://127.0.0.1:1";
+
+    fn no_retries() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 0,
+            ..Default::default()
+        }
+    }
+
+    fn unreachable_provider() -> OllamaProvider {
+        OllamaProvider::new(UNREACHABLE, "mistral").with_retry(no_retries())
+    }
+
+    fn embedding_engine(result: Result<Vec<f32>, &'static str>) -> EmbeddingEngine {
+        let mut mock = MockEmbedder::new();
+        mock.expect_model()
+            .return_const("nomic-embed-text".to_string());
+        mock.expect_dimension()
+            .returning(move || result.clone().map(|v| v.len()).map_err(|e| anyhow!(e)));
+        EmbeddingEngine::from_embedder(Box::new(mock)).with_retry(no_retries())
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_llm_is_reported() {
+        let provider = unreachable_provider();
+        let outcome = check_llm(&provider, true).await;
+        let hint = outcome.result.unwrap_err();
+        assert!(hint.contains("cannot reach Ollama at http://127.0.0.1:1"));
+        assert!(hint.contains("OLLAMA_ENDPOINT"));
+    }
+
+    #[tokio::test]
+    async fn test_embedding_check() {
+        let config = EmbeddingConfig::default();
+
+        let outcome = check_embedding(&embedding_engine(Ok(vec![0.0; 768])), &config).await;
+        assert_eq!(
+            outcome.result.unwrap(),
+            "nomic-embed-text via ollama (768 dimensions)"
+        );
+
+        let outcome = check_embedding(&embedding_engine(Err("model not found")), &config).await;
+        let hint = outcome.result.unwrap_err();
+        assert!(hint.contains("model not found"));
+        assert!(hint.contains("OLLAMA_EMBEDDING_MODEL"));
+    }
+
+    #[tokio::test]
+    async fn test_preflight_collects_all_failures() {
+        let provider = unreachable_provider();
+        let engine = embedding_engine(Err("connection refused"));
+        let config = EmbeddingConfig::default();
+
+        let err = preflight(PreflightTargets {
+            llm: Some((&provider, false)),
+            embedding: Some((&engine, &config)),
+            vector_db: None,
+        })
+        .await
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("LLM: cannot reach Ollama"));
+        assert!(err.contains("Embedding: model 'nomic-embed-text'"));
+    }
+
+    #[tokio::test]
+    async fn test_preflight_without_targets_passes() {
+        let outcomes = preflight(PreflightTargets::default()).await.unwrap();
+        assert!(outcomes.is_empty());
+    }
+}
@@ -1,16 +1,365 @@
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
+use chrono::Utc;
+use futures::StreamExt;
 use regex::Regex;
-use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use std::fs;
+use std::collections::{BTreeSet, HashMap};
 use std::io::Write;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use thiserror::Error;
+use tokio::sync::mpsc;
+use tokio::task::spawn_blocking;
+use uuid::Uuid;
+
+use crate::budget::{QuestionBudget, TokenBudget};
+use crate::changelog::{self, Release};
+use crate::density::{DocumentSize, QuestionDensity};
+use crate::difficulty::{self, Difficulty};
+use crate::distill::{new_questions, Student, StudentReport, WeakArea};
+use crate::eval::EvalSplit;
+use crate::external::{ChatMessage, ChatRequest, ModelProvider, OllamaProvider};
+use crate::glossary::{self, Glossary, Term};
+use crate::graph::{node::NodeType, DocumentGraph};
+use crate::incremental::{file_hash, section_hash, SectionIndex};
+use crate::judge::Judge;
+use crate::ledger::{GenerationKey, RunLedger, LEDGER_FILE};
+use crate::limits::RunLimits;
+use crate::lineage::Lineage;
+use crate::metadata::{detect_lang, RecordMetadata};
+use crate::observer::PipelineObserver;
+use crate::oversize::{SizeLimit, PARTS_DIR};
+use crate::pipeline::{send_files, Pipeline};
+use crate::rejection::{heuristic_score, rank, CandidateScoring, RejectionSampling};
+use crate::review::ReviewStatus;
+use crate::schema::{self, DatasetReader, SchemaHeader};
+use crate::sections::{self, measure_file, SpannedSection};
+use crate::shutdown::Shutdown;
+
+/// Extension of the section index written next to each QA file
+const SECTION_INDEX_EXTENSION: &str = "sections.json";
+
+/// Records per batch when passing on an existing QA file
+const REPLAY_BATCH: usize = 256;
+
+/// Records as the model wrote them, without metadata columns it made up:
+/// those are the processor's to fill in
+fn from_model(mut items: Vec<ProcessedItem>) -> Vec<ProcessedItem> {
+    for item in &mut items {
+        item.metadata = RecordMetadata::default();
+    }
+    items
+}
+
+/// Tags of the graph nodes lying within `span`, sorted and without repeats
+///
+/// Sections are left out: their span is only the heading, but their tags
+/// come from text that may lie in other chunks.
+fn span_tags(graph: &DocumentGraph, span: &Range<usize>) -> Vec<String> {
+    let tags: BTreeSet<String> = graph
+        .nodes()
+        .filter(|node| !matches!(node.node_type, NodeType::Section | NodeType::Subsection))
+        .filter(|node| {
+            node.metadata
+                .span
+                .is_some_and(|s| span.start <= s.start_byte && s.end_byte <= span.end)
+        })
+        .flat_map(|node| node.metadata.tags.iter().map(|tag| tag.to_string()))
+        .collect();
+    tags.into_iter().collect()
+}
+
+/// Why generating questions failed
+///
+/// Returned inside `anyhow::Error`s, so callers branch on it with
+/// [`ProcessorError::of`]: whether to retry, wait for the server, or give up.
+#[derive(Error, Debug)]
+pub enum ProcessorError {
+    /// The model server failed the request: unreachable, timed out or
+    /// answering with an error
+    #[error("Model backend unavailable")]
+    BackendUnavailable(#[source] anyhow::Error),
+
+    /// Replies that aren't the requested JSON, on every attempt
+    #[error("Unusable model response after {attempts} attempts: {reason}")]
+    MalformedResponse { attempts: usize, reason: String },
+
+    /// Replies cut off at the output token limit even for one question
+    #[error("Model response cut off at {max_tokens} tokens; raise --max-output-tokens")]
+    Truncated { max_tokens: usize },
+
+    /// A run limit (`--max-files`, `--max-questions`) left input unprocessed
+    #[error("Budget exceeded: {0}")]
+    BudgetExceeded(String),
+
+    #[error("Could not access {path:?}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// The client doesn't implement a step, e.g. a test double
+    #[error("This client cannot {0}")]
+    Unsupported(&'static str),
+}
+
+impl ProcessorError {
+    /// The first processor error among an error and its causes
+    pub fn of(error: &anyhow::Error) -> Option<&ProcessorError> {
+        error.chain().find_map(|cause| cause.downcast_ref())
+    }
+
+    /// Whether trying the same request again may succeed
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            ProcessorError::BackendUnavailable(_)
+                | ProcessorError::MalformedResponse { .. }
+                | ProcessorError::Truncated { .. }
+        )
+    }
+
+    pub(crate) fn io(path: &Path) -> impl FnOnce(std::io::Error) -> ProcessorError + '_ {
+        move |source| ProcessorError::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+}
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+fn backend_unavailable(error: &anyhow::Error) -> bool {
+    matches!(
+        ProcessorError::of(error),
+        Some(ProcessorError::BackendUnavailable(_))
+    )
+}
+
+/// Receives the records of a file as they are generated
+pub type ItemSink<'a> = dyn FnMut(&[ProcessedItem]) -> Result<()> + Send + 'a;
+
+/// Records of the previous run grouped by section hash
+pub(crate) type PreviousSections = HashMap<String, Vec<Vec<ProcessedItem>>>;
+
+/// What is known about a file before its sections are read
+pub(crate) struct FilePlan {
+    pub size: DocumentSize,
+    /// Questions to generate for the whole file
+    pub questions: usize,
+    /// Records of unchanged sections to keep (`--incremental`)
+    pub previous: Option<PreviousSections>,
+    /// The QA file of an earlier run has enough records to keep as it is
+    pub existing: bool,
+    /// Content hash of the file, recorded once its questions are complete
+    pub hash: String,
+    /// Versions of a changelog, each generated from as one section
+    pub releases: Option<Vec<Release>>,
+}
+
+/// Namespace of the record IDs, so they never equal v5 UUIDs made for
+/// anything else
+const ITEM_ID_NAMESPACE: Uuid = Uuid::from_u128(0x8f3c_2a71_5d4e_4b09_a6e1_73c2_9d05_b418);
+
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
 pub struct ProcessedItem {
+    /// Derived from the record's source, span and question, so the same
+    /// record gets the same ID on every run (absent in older datasets)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<Uuid>,
     pub question: String,
     pub answer: String,
+    /// Retrieved passages the answer is grounded in (RAG datasets only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<String>,
+    /// Document span and graph nodes the record was generated from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lineage: Option<Lineage>,
+    /// Points a good answer must cover; only evaluation records, whose
+    /// answer is the reference answer, have one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rubric: Option<Vec<String>>,
+    /// Candidate answers that scored lower than `answer` under rejection
+    /// sampling (`--keep-rejected`), best first
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rejected: Option<Vec<String>>,
+    /// How hard the question is, as labelled by the model that wrote it
+    #[serde(
+        default,
+        deserialize_with = "difficulty::deserialize_label",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub difficulty: Option<Difficulty>,
+    /// Verdict of a human reviewer (`review`), if the record was reviewed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub review_status: Option<ReviewStatus>,
+    /// Source, model, prompt template, time, language, tags and score of
+    /// the record, as columns of their own (`--rich-schema`)
+    #[serde(flatten)]
+    pub metadata: RecordMetadata,
+}
+
+impl ProcessedItem {
+    /// Record of just a question and its answer, with every other column unset
+    pub fn new(question: impl Into<String>, answer: impl Into<String>) -> Self {
+        Self {
+            question: question.into(),
+            answer: answer.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Deterministic ID of a record generated from `span` of `source`
+    ///
+    /// `source` names the input file and `span` the part of it the question
+    /// was generated from, e.g. a section's content hash.
+    pub fn item_id(source: &str, span: &str, question: &str) -> Uuid {
+        let name = format!("{}\n{}\n{}", source, span, question.trim());
+        Uuid::new_v5(&ITEM_ID_NAMESPACE, name.as_bytes())
+    }
+
+    /// Set the record's ID from where it was generated
+    pub fn assign_id(&mut self, source: &str, span: &str) {
+        self.id = Some(Self::item_id(source, span, &self.question));
+    }
+
+    /// Whether the record belongs to the evaluation set rather than the
+    /// training set
+    pub fn is_eval(&self) -> bool {
+        self.rubric.is_some()
+    }
+}
+
+/// Prompt asking for questions about release notes; `{count}` is the target
+pub const RELEASE_NOTES_PROMPT: &str =
+    "Generate exactly {count} unique questions and answers from these release notes. \
+     Focus on specific changes, features, and improvements. \
+     Format as JSON array with 'question', 'answer' and 'difficulty' (easy, medium or hard) fields. \
+     Questions should be detailed and specific to the version mentioned in the notes.";
+
+/// Prompt asking for questions about documentation; `{count}` is the target
+pub const DOCUMENTATION_PROMPT: &str =
+    "Generate exactly {count} unique questions and answers from this documentation. \
+     Focus on key concepts, features, and usage. \
+     Format as JSON array with 'question', 'answer' and 'difficulty' (easy, medium or hard) fields. \
+     Easy questions recall one stated fact, medium ones combine or apply facts, hard ones need reasoning across the content.";
+
+/// Prompt asking for questions about one version of a changelog; `{count}`
+/// is the target and `{version}` the version
+pub const CHANGELOG_PROMPT: &str =
+    "Generate exactly {count} unique questions and answers about the changes in version {version}. \
+     Every question must name version {version}, and every answer must be about that version only. \
+     Format as JSON array with 'question', 'answer' and 'difficulty' (easy, medium or hard) fields.";
+
+pub const RELEASE_NOTES_SYSTEM_PROMPT: &str = "You are a helpful assistant that generates questions and answers about software release notes. \
+     Format your response as JSON. Keep answers concise and factual. \
+     Focus on the specific changes and improvements in this version.";
+
+pub const DOCUMENTATION_SYSTEM_PROMPT: &str = "You are a helpful assistant that generates questions and answers about technical documentation. \
+     Format your response as JSON. Keep answers concise and factual. \
+     Focus on the technical details and functionality being described.";
+
+/// Prompt asking for a summary of part of a file too large to generate from
+/// as it is (`--oversized summarize`)
+pub const SUMMARY_PROMPT: &str = "Summarize the following text, keeping the facts, names, numbers and steps a reader could be asked about. \
+     Reply with the summary only, without headings.";
+
+/// Prompt asking for evaluation questions with reference answers and
+/// grading rubrics (`--eval`); `{count}` is the target
+pub const EVAL_PROMPT: &str =
+    "Generate exactly {count} unique questions that test understanding of this content. \
+     For each, give a complete reference answer and a rubric: a list of the specific points a correct answer must contain. \
+     Format as JSON array with 'question', 'answer' and 'rubric' fields.";
+
+/// Prompt asking for one more answer to a generated question, a candidate
+/// for rejection sampling (`--candidates`)
+pub const ANSWER_PROMPT: &str = "Answer the question using only the content provided. \
+     Keep the answer concise and factual. Reply with the answer only.";
+
+/// Prompt for the student model, which answers without the content
+/// (`--student-model`)
+pub const STUDENT_PROMPT: &str = "Answer the question from what you know. \
+     Keep the answer concise and factual. Reply with the answer only.";
+
+/// Prompt asking for the domain terms of a document and their definitions
+/// (`--glossary`)
+pub const GLOSSARY_PROMPT: &str =
+    "List the domain-specific terms this content defines or relies on, each with a one-sentence definition taken from the content. \
+     Leave out common words and terms the content doesn't explain. \
+     Format as JSON object with a 'terms' array of objects with 'term' and 'definition' fields.";
+
+/// Instruction added to a question prompt with the glossary terms the
+/// content mentions
+pub const GLOSSARY_INSTRUCTION: &str =
+    "Use these terms with the spelling and meaning the document's glossary gives them:";
+
+pub const EVAL_SYSTEM_PROMPT: &str =
+    "You are a helpful assistant that writes evaluation questions about technical documentation. \
+     Format your response as JSON. Reference answers must be fully supported by the content. \
+     Rubric points should be short, checkable facts.";
+
+/// All prompt templates used for generation, keyed by name
+pub fn prompt_templates() -> Vec<(&'static str, &'static str)> {
+    vec![
+        ("release_notes", RELEASE_NOTES_PROMPT),
+        ("changelog", CHANGELOG_PROMPT),
+        ("documentation", DOCUMENTATION_PROMPT),
+        ("release_notes_system", RELEASE_NOTES_SYSTEM_PROMPT),
+        ("documentation_system", DOCUMENTATION_SYSTEM_PROMPT),
+        ("summary", SUMMARY_PROMPT),
+        ("eval", EVAL_PROMPT),
+        ("eval_system", EVAL_SYSTEM_PROMPT),
+        ("answer", ANSWER_PROMPT),
+        ("student", STUDENT_PROMPT),
+        ("glossary", GLOSSARY_PROMPT),
+        ("glossary_instruction", GLOSSARY_INSTRUCTION),
+    ]
+}
+
+/// System and user messages asking for `target_count` questions about `content`
+///
+/// Release notes and changelogs get the release-notes prompts, everything
+/// else the documentation prompts; a version of a changelog gets questions
+/// naming that version. Glossary terms attached to the content
+/// (`--glossary`) become an instruction rather than content.
+pub fn question_prompt(content: &str, target_count: usize) -> (&'static str, String) {
+    let (content, terms) = glossary::detach(content);
+    let version = changelog::section_version(content);
+    let (system_msg, user_template) = match question_template(content) {
+        "changelog" => (RELEASE_NOTES_SYSTEM_PROMPT, CHANGELOG_PROMPT),
+        "release_notes" => (RELEASE_NOTES_SYSTEM_PROMPT, RELEASE_NOTES_PROMPT),
+        _ => (DOCUMENTATION_SYSTEM_PROMPT, DOCUMENTATION_PROMPT),
+    };
+    let user_msg = format!(
+        "{}{}\nContent: {}",
+        user_template
+            .replace("{count}", &target_count.to_string())
+            .replace("{version}", version.unwrap_or_default()),
+        glossary_instruction(terms),
+        content
+    );
+    (system_msg, user_msg)
+}
+
+/// Name of the template, as [`prompt_templates`] lists it, that questions
+/// about `content` are asked with
+pub fn question_template(content: &str) -> &'static str {
+    let (content, _) = glossary::detach(content);
+    if changelog::section_version(content).is_some() {
+        "changelog"
+    } else if content.contains("# Release Notes") || content.contains("# Changelog") {
+        "release_notes"
+    } else {
+        "documentation"
+    }
+}
+
+/// The glossary instruction for terms detached from the content, if any
+fn glossary_instruction(terms: Option<&str>) -> String {
+    terms
+        .map(|terms| format!("\n{}\n{}", GLOSSARY_INSTRUCTION, terms))
+        .unwrap_or_default()
 }
 
 #[async_trait]
@@ -20,24 +369,198 @@ pub trait OllamaClient: Send + Sync {
         content: &str,
         target_count: usize,
     ) -> Result<Vec<ProcessedItem>>;
+
+    /// Condense part of an oversized file (`--oversized summarize`)
+    async fn summarize(&self, _content: &str) -> Result<String> {
+        Err(ProcessorError::Unsupported("summarize").into())
+    }
+
+    /// Domain terms of part of a document, with their definitions
+    /// (`--glossary`)
+    async fn extract_terms(&self, _content: &str) -> Result<Vec<Term>> {
+        Err(ProcessorError::Unsupported("extract glossary terms").into())
+    }
+
+    /// Generate evaluation questions with reference answers and rubrics
+    /// for a held-out section (`--eval`)
+    async fn generate_eval(
+        &self,
+        _content: &str,
+        _target_count: usize,
+    ) -> Result<Vec<ProcessedItem>> {
+        Err(ProcessorError::Unsupported("generate evaluation questions").into())
+    }
+
+    /// Answer a question about `content` again, giving another candidate
+    /// for rejection sampling (`--candidates`)
+    async fn answer(&self, _content: &str, _question: &str) -> Result<String> {
+        Err(ProcessorError::Unsupported("generate candidate answers").into())
+    }
+
+    /// Answer a question without the content it was generated from, as the
+    /// student model of distillation (`--student-model`)
+    async fn answer_unaided(&self, _question: &str) -> Result<String> {
+        Err(ProcessorError::Unsupported("answer without content").into())
+    }
+
+    /// Overall judge score of a record from 1 to 5
+    /// (`--candidate-scoring judge`)
+    async fn judge(&self, _item: &ProcessedItem) -> Result<f64> {
+        Err(ProcessorError::Unsupported("judge answers").into())
+    }
 }
 
 pub struct DefaultOllamaClient {
-    endpoint: String,
-    model: String,
-    client: Client,
+    provider: Arc<dyn ModelProvider>,
+    stream: bool,
+    token_budget: TokenBudget,
+}
+
+/// A question prompt for a number of questions: system and user message
+type QuestionPrompt<'a> = dyn Fn(usize) -> (&'static str, String) + Send + Sync + 'a;
+
+/// How much streamed output may arrive before the `questions` key must have appeared
+const MALFORMED_PREFIX_CHARS: usize = 80;
+
+/// Whether the beginning of a streamed response already shows it can't be
+/// the requested `{"questions": [...]}` object
+///
+/// Only clear-cut cases count: output that starts with something other
+/// than a JSON object (after an optional code fence), or a long prefix
+/// without the `questions` key.
+pub fn is_obviously_malformed(partial: &str) -> bool {
+    let trimmed = partial.trim_start();
+    // A code fence may still be arriving
+    if "```json".starts_with(trimmed) {
+        return false;
+    }
+    let body = trimmed
+        .strip_prefix("```json")
+        .or_else(|| trimmed.strip_prefix("```"))
+        .unwrap_or(trimmed)
+        .trim_start();
+
+    match body.chars().next() {
+        None => false,
+        Some('{') => {
+            body.chars().count() > MALFORMED_PREFIX_CHARS && !body.contains("\"questions\"")
+        }
+        Some(_) => true,
+    }
 }
 
 impl DefaultOllamaClient {
     pub fn new(endpoint: String, model: String) -> Self {
+        Self::from_provider(Arc::new(OllamaProvider::new(endpoint, model)))
+    }
+
+    /// Generate through an already configured provider
+    pub fn from_provider(provider: Arc<dyn ModelProvider>) -> Self {
         Self {
-            endpoint,
-            model,
-            client: Client::new(),
+            provider,
+            stream: false,
+            token_budget: TokenBudget::default(),
+        }
+    }
+
+    pub fn provider(&self) -> &Arc<dyn ModelProvider> {
+        &self.provider
+    }
+
+    /// Stream responses, echoing tokens as they arrive and aborting
+    /// generation as soon as the output is obviously malformed
+    pub fn with_streaming(mut self, stream: bool) -> Self {
+        self.stream = stream;
+        self
+    }
+
+    /// Limit the output tokens of question requests (`--max-output-tokens`)
+    pub fn with_token_budget(mut self, token_budget: TokenBudget) -> Self {
+        self.token_budget = token_budget;
+        self
+    }
+
+    /// Request for questions; with `rubric`, each must come with a rubric
+    fn question_request(
+        system_msg: &str,
+        user_msg: &str,
+        rubric: bool,
+        max_tokens: usize,
+    ) -> ChatRequest {
+        let mut item = serde_json::json!({
+            "type": "object",
+            "required": ["question", "answer"],
+            "properties": {
+                "question": {
+                    "type": "string"
+                },
+                "answer": {
+                    "type": "string"
+                },
+                "difficulty": {
+                    "type": "string",
+                    "enum": ["easy", "medium", "hard"]
+                }
+            }
+        });
+        if rubric {
+            item["required"] = serde_json::json!(["question", "answer", "rubric"]);
+            item["properties"]["rubric"] = serde_json::json!({
+                "type": "array",
+                "items": { "type": "string" }
+            });
+        }
+        ChatRequest {
+            messages: vec![ChatMessage::system(system_msg), ChatMessage::user(user_msg)],
+            format: Some(serde_json::json!({
+                "type": "object",
+                "required": ["questions"],
+                "properties": {
+                    "questions": {
+                        "type": "array",
+                        "items": item
+                    }
+                }
+            })),
+            max_tokens: Some(max_tokens),
         }
     }
 
-    fn sanitize_json(json: &str) -> String {
+    async fn chat(&self, request: ChatRequest) -> Result<String> {
+        Ok(self
+            .provider
+            .chat(request)
+            .await
+            .map_err(ProcessorError::BackendUnavailable)?)
+    }
+
+    /// Stream a chat reply, printing tokens live
+    ///
+    /// Returns the full message, or `None` if it was abandoned as malformed;
+    /// dropping the token stream closes the connection and stops generation.
+    async fn stream_chat(&self, request: ChatRequest) -> Result<Option<String>> {
+        let mut tokens = self
+            .provider
+            .chat_stream(request)
+            .await
+            .map_err(ProcessorError::BackendUnavailable)?;
+        let mut content = String::new();
+        let mut stdout = std::io::stdout();
+        while let Some(token) = tokens.next().await {
+            let token = token.map_err(ProcessorError::BackendUnavailable)?;
+            print!("{}", token);
+            stdout.flush()?;
+            content.push_str(&token);
+            if is_obviously_malformed(&content) {
+                println!();
+                return Ok(None);
+            }
+        }
+        println!();
+        Ok(Some(content))
+    }
+
+    pub(crate) fn sanitize_json(json: &str) -> String {
         // First strip any markdown code blocks
         let json = if let Some(content) = json.strip_prefix("```json") {
             if let Some(content) = content.strip_suffix("```") {
@@ -116,170 +639,274 @@ impl OllamaClient for DefaultOllamaClient {
         content: &str,
         target_count: usize,
     ) -> Result<Vec<ProcessedItem>> {
-        const MAX_RETRIES: usize = 3;
-        let mut retries = 0;
+        self.request_in_batches(
+            &|count| question_prompt(content, count),
+            target_count,
+            false,
+        )
+        .await
+    }
 
-        while retries < MAX_RETRIES {
-            let prompt_text = if content.contains("# Release Notes")
-                || content.contains("# Changelog")
-            {
-                format!(
-                    "Generate exactly {} unique questions and answers from these release notes. \
-                     Focus on specific changes, features, and improvements. \
-                     Format as JSON array with 'question' and 'answer' fields. \
-                     Questions should be detailed and specific to the version mentioned in the notes.",
-                    target_count
-                )
-            } else {
-                format!(
-                    "Generate exactly {} unique questions and answers from this documentation. \
-                     Focus on key concepts, features, and usage. \
-                     Format as JSON array with 'question' and 'answer' fields.",
-                    target_count
-                )
-            };
+    async fn summarize(&self, content: &str) -> Result<String> {
+        let request = ChatRequest {
+            messages: vec![
+                ChatMessage::system(SUMMARY_PROMPT),
+                ChatMessage::user(content),
+            ],
+            format: None,
+            max_tokens: None,
+        };
+        self.chat(request).await
+    }
 
-            let (system_msg, user_msg) = if content.contains("# Release Notes")
-                || content.contains("# Changelog")
-            {
-                (
-                    "You are a helpful assistant that generates questions and answers about software release notes. \
-                     Format your response as JSON. Keep answers concise and factual. \
-                     Focus on the specific changes and improvements in this version.",
-                    format!("{}\nContent: {}", prompt_text, content)
-                )
-            } else {
-                (
-                    "You are a helpful assistant that generates questions and answers about technical documentation. \
-                     Format your response as JSON. Keep answers concise and factual. \
-                     Focus on the technical details and functionality being described.",
-                    format!("{}\nContent: {}", prompt_text, content)
-                )
-            };
+    async fn generate_eval(
+        &self,
+        content: &str,
+        target_count: usize,
+    ) -> Result<Vec<ProcessedItem>> {
+        let (content, terms) = glossary::detach(content);
+        let prompt = |count: usize| {
+            let user_msg = format!(
+                "{}{}\nContent: {}",
+                EVAL_PROMPT.replace("{count}", &count.to_string()),
+                glossary_instruction(terms),
+                content
+            );
+            (EVAL_SYSTEM_PROMPT, user_msg)
+        };
+        self.request_in_batches(&prompt, target_count, true).await
+    }
 
-            println!("Requesting {} questions from Ollama...", target_count);
-            let response = self
-                .client
-                .post(format!("{}/api/chat", self.endpoint))
-                .json(&serde_json::json!({
-                    "model": &self.model,
-                    "messages": [
-                        {
-                            "role": "system",
-                            "content": system_msg
-                        },
-                        {
-                            "role": "user",
-                            "content": user_msg
-                        }
-                    ],
-                    "stream": false,
-                    "format": {
-                        "type": "object",
-                        "required": ["questions"],
-                        "properties": {
-                            "questions": {
-                                "type": "array",
-                                "items": {
-                                    "type": "object",
-                                    "required": ["question", "answer"],
-                                    "properties": {
-                                        "question": {
-                                            "type": "string"
-                                        },
-                                        "answer": {
-                                            "type": "string"
-                                        }
-                                    }
-                                }
+    async fn extract_terms(&self, content: &str) -> Result<Vec<Term>> {
+        let request = ChatRequest {
+            messages: vec![
+                ChatMessage::system(GLOSSARY_PROMPT),
+                ChatMessage::user(content),
+            ],
+            format: Some(serde_json::json!({
+                "type": "object",
+                "required": ["terms"],
+                "properties": {
+                    "terms": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "required": ["term", "definition"],
+                            "properties": {
+                                "term": { "type": "string" },
+                                "definition": { "type": "string" }
                             }
                         }
                     }
-                }))
-                .send()
-                .await?;
-
-            if !response.status().is_success() {
-                let error_text = response.text().await?;
-                println!("Ollama API error: {}", error_text);
-                return Err(anyhow!("Ollama API error: {}", error_text));
-            }
+                }
+            })),
+            max_tokens: None,
+        };
+        let reply = self.chat(request).await?;
 
-            let response_text = response.text().await?;
-            println!("Received response from Ollama");
+        #[derive(Deserialize)]
+        struct TermsResponse {
+            terms: Vec<Term>,
+        }
 
-            #[derive(Debug, Deserialize)]
-            struct ChatMessage {
-                content: String,
-            }
+        let parsed: TermsResponse =
+            serde_json::from_str(&Self::sanitize_json(&reply)).map_err(|e| {
+                ProcessorError::MalformedResponse {
+                    attempts: 1,
+                    reason: e.to_string(),
+                }
+            })?;
+        Ok(parsed.terms)
+    }
 
-            #[derive(Debug, Deserialize)]
-            struct ChatResponse {
-                message: ChatMessage,
-            }
+    async fn answer(&self, content: &str, question: &str) -> Result<String> {
+        let request = ChatRequest {
+            messages: vec![
+                ChatMessage::system(ANSWER_PROMPT),
+                ChatMessage::user(format!("Content: {}\nQuestion: {}", content, question)),
+            ],
+            format: None,
+            max_tokens: None,
+        };
+        Ok(self.chat(request).await?.trim().to_string())
+    }
 
-            match serde_json::from_str::<ChatResponse>(&response_text) {
-                Ok(chat_response) => {
-                    let sanitized = Self::sanitize_json(&chat_response.message.content);
+    async fn answer_unaided(&self, question: &str) -> Result<String> {
+        let request = ChatRequest {
+            messages: vec![
+                ChatMessage::system(STUDENT_PROMPT),
+                ChatMessage::user(question),
+            ],
+            format: None,
+            max_tokens: None,
+        };
+        Ok(self.chat(request).await?.trim().to_string())
+    }
+
+    async fn judge(&self, item: &ProcessedItem) -> Result<f64> {
+        let judgement = Judge::new(self.provider.as_ref()).score(item).await?;
+        Ok(judgement.overall)
+    }
+}
 
-                    #[derive(Debug, Deserialize)]
-                    struct QuestionResponse {
-                        questions: Vec<ProcessedItem>,
+impl DefaultOllamaClient {
+    /// Ask for questions in batches that fit the output token budget
+    ///
+    /// Later batches are told which questions were already asked, and
+    /// repeats are dropped. Asking stops early once a batch comes back
+    /// short, as the section has likely run out of material.
+    async fn request_in_batches(
+        &self,
+        prompt: &QuestionPrompt<'_>,
+        target_count: usize,
+        rubric: bool,
+    ) -> Result<Vec<ProcessedItem>> {
+        let mut batch = self.token_budget.questions_per_request(rubric);
+        let mut items: Vec<ProcessedItem> = Vec::new();
+        while items.len() < target_count {
+            let wanted = (target_count - items.len()).min(batch);
+            let asked = &items;
+            let batch_prompt = move |count: usize| {
+                let (system_msg, mut user_msg) = prompt(count);
+                if !asked.is_empty() {
+                    user_msg
+                        .push_str("\n\nThese questions were already asked; do not repeat them:");
+                    for item in asked {
+                        user_msg.push_str(&format!("\n- {}", item.question));
+                    }
+                }
+                (system_msg, user_msg)
+            };
+            let (generated, requested) =
+                match self.request_questions(&batch_prompt, wanted, rubric).await {
+                    Ok(reply) => reply,
+                    Err(e) if !items.is_empty() => {
+                        println!(
+                            "Keeping {} questions after a failed batch: {}",
+                            items.len(),
+                            e
+                        );
+                        break;
                     }
+                    Err(e) => return Err(e),
+                };
+            // A cut-off reply shrank the batch; later batches stay that size
+            batch = batch.min(requested);
+            let generated = new_questions(&items, generated);
+            let short = generated.len() < requested;
+            items.extend(generated);
+            if short {
+                break;
+            }
+        }
+        Ok(items)
+    }
 
-                    match serde_json::from_str::<QuestionResponse>(&sanitized) {
-                        Ok(parsed) => {
-                            println!(
-                                "Received {} questions (requested {})",
-                                parsed.questions.len(),
-                                target_count
-                            );
-                            return Ok(parsed.questions);
-                        }
-                        Err(e) => {
-                            println!(
-                                "Failed to parse as JSON (attempt {}/{}): {}",
-                                retries + 1,
-                                MAX_RETRIES,
-                                e
-                            );
-                            println!("Raw response: {}", response_text);
-                            println!("Sanitized response: {}", sanitized);
-                            retries += 1;
-                            if retries == MAX_RETRIES {
-                                return Err(anyhow!(
-                                    "Failed to parse Ollama response after {} attempts",
-                                    MAX_RETRIES
-                                ));
-                            }
-                            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
-                        }
+    /// Whether a reply is JSON that ends before its closing brace, as when
+    /// it reached the output token limit
+    fn looks_truncated(reply: &str) -> bool {
+        let reply = reply.trim();
+        let body = reply
+            .strip_prefix("```json")
+            .or_else(|| reply.strip_prefix("```"))
+            .unwrap_or(reply)
+            .trim_start();
+        body.starts_with('{') && !(reply.ends_with('}') || reply.ends_with("```"))
+    }
+
+    /// Ask for questions, retrying replies that can't be parsed
+    ///
+    /// A reply cut off mid-JSON is retried asking for half as many
+    /// questions. Returns the questions and how many were last asked for.
+    async fn request_questions(
+        &self,
+        prompt: &QuestionPrompt<'_>,
+        target_count: usize,
+        rubric: bool,
+    ) -> Result<(Vec<ProcessedItem>, usize)> {
+        const MAX_RETRIES: usize = 3;
+        let mut retries = 0;
+        let mut target_count = target_count;
+
+        while retries < MAX_RETRIES {
+            println!("Requesting {} questions from Ollama...", target_count);
+            let (system_msg, user_msg) = prompt(target_count);
+            let max_tokens = self.token_budget.max_tokens(target_count, rubric);
+            let request = Self::question_request(system_msg, &user_msg, rubric, max_tokens);
+
+            let content = if self.stream {
+                match self.stream_chat(request).await? {
+                    Some(content) => content,
+                    None => {
+                        retries += 1;
+                        println!(
+                            "Aborted malformed response (attempt {}/{})",
+                            retries, MAX_RETRIES
+                        );
+                        continue;
                     }
                 }
+            } else {
+                let content = self.chat(request).await?;
+                println!("Received response from Ollama");
+                content
+            };
+
+            let sanitized = Self::sanitize_json(&content);
+
+            #[derive(Debug, Deserialize)]
+            struct QuestionResponse {
+                questions: Vec<ProcessedItem>,
+            }
+
+            match serde_json::from_str::<QuestionResponse>(&sanitized) {
+                Ok(parsed) => {
+                    println!(
+                        "Received {} questions (requested {})",
+                        parsed.questions.len(),
+                        target_count
+                    );
+                    return Ok((parsed.questions, target_count));
+                }
                 Err(e) => {
                     println!(
-                        "Failed to parse chat response (attempt {}/{}): {}",
+                        "Failed to parse as JSON (attempt {}/{}): {}",
                         retries + 1,
                         MAX_RETRIES,
                         e
                     );
-                    println!("Raw response: {}", response_text);
+                    println!("Raw response: {}", content);
+                    println!("Sanitized response: {}", sanitized);
+                    let truncated = Self::looks_truncated(&content);
                     retries += 1;
                     if retries == MAX_RETRIES {
-                        return Err(anyhow!(
-                            "Failed to parse chat response after {} attempts",
-                            MAX_RETRIES
-                        ));
+                        return Err(if truncated && target_count == 1 {
+                            ProcessorError::Truncated { max_tokens }
+                        } else {
+                            ProcessorError::MalformedResponse {
+                                attempts: MAX_RETRIES,
+                                reason: e.to_string(),
+                            }
+                        }
+                        .into());
+                    }
+                    if truncated && target_count > 1 {
+                        target_count /= 2;
+                        println!(
+                            "Response was cut off at the output limit; asking for {} questions",
+                            target_count
+                        );
                     }
                     tokio::time::sleep(std::time::Duration::from_secs(1)).await;
                 }
             }
         }
 
-        Err(anyhow!(
-            "Failed to process section after {} attempts",
-            MAX_RETRIES
-        ))
+        Err(ProcessorError::MalformedResponse {
+            attempts: MAX_RETRIES,
+            reason: "every streamed reply was abandoned as malformed".to_string(),
+        }
+        .into())
     }
 }
 
@@ -290,81 +917,244 @@ pub trait OllamaProcessor {
 
 pub struct DefaultOllamaProcessor {
     client: Box<dyn OllamaClient>,
+    /// Model the client generates with, as the ledger records it
+    model: String,
     output_dir: PathBuf,
+    incremental: bool,
+    force: bool,
+    budget: Option<QuestionBudget>,
+    density: QuestionDensity,
+    shutdown: Shutdown,
+    limits: RunLimits,
+    size_limit: SizeLimit,
+    eval_split: Option<EvalSplit>,
+    rejection: Option<RejectionSampling>,
+    student: Option<Student>,
+    glossary: bool,
+    target_tags: Vec<String>,
+    target_sections: Vec<String>,
+    target_pattern: Option<Regex>,
+    rich_schema: bool,
+    observers: Vec<Arc<dyn PipelineObserver>>,
+    /// Loaded on first use, so the output directory can still change
+    ledger: Mutex<Option<RunLedger>>,
 }
 
 impl DefaultOllamaProcessor {
     pub fn new(endpoint: String, model: String) -> Self {
         Self {
-            client: Box::new(DefaultOllamaClient::new(endpoint, model)),
+            client: Box::new(DefaultOllamaClient::new(endpoint, model.clone())),
+            model,
             output_dir: PathBuf::from("output"),
+            incremental: false,
+            force: false,
+            budget: None,
+            density: QuestionDensity::default(),
+            shutdown: Shutdown::new(),
+            limits: RunLimits::default(),
+            size_limit: SizeLimit::default(),
+            eval_split: None,
+            rejection: None,
+            student: None,
+            glossary: false,
+            target_tags: Vec::new(),
+            target_sections: Vec::new(),
+            target_pattern: None,
+            rich_schema: false,
+            observers: Vec::new(),
+            ledger: Mutex::new(None),
         }
     }
 
     pub fn new_with_client(
         _endpoint: String,
-        _model: String,
+        model: String,
         client: Box<dyn OllamaClient>,
         output_dir: Option<PathBuf>,
     ) -> Self {
         Self {
             client,
+            model,
             output_dir: output_dir.unwrap_or_else(|| PathBuf::from("output")),
+            incremental: false,
+            force: false,
+            budget: None,
+            density: QuestionDensity::default(),
+            shutdown: Shutdown::new(),
+            limits: RunLimits::default(),
+            size_limit: SizeLimit::default(),
+            eval_split: None,
+            rejection: None,
+            student: None,
+            glossary: false,
+            target_tags: Vec::new(),
+            target_sections: Vec::new(),
+            target_pattern: None,
+            rich_schema: false,
+            observers: Vec::new(),
+            ledger: Mutex::new(None),
         }
     }
 
-    pub fn count_words(text: &str) -> usize {
-        text.split_whitespace().count()
+    /// Only regenerate questions for sections whose content changed since the last run
+    pub fn with_incremental(mut self, incremental: bool) -> Self {
+        self.incremental = incremental;
+        self
     }
 
-    pub fn calculate_question_targets(word_count: usize) -> (usize, usize, usize) {
-        let base_goal = (word_count as f64 / 10.0).ceil() as usize;
-        let base_goal = base_goal.max(2);
-        let extra_questions = (base_goal as f64 * 0.25).ceil() as usize;
-        let extra_questions = extra_questions.max(2);
-        let generation_target = base_goal + extra_questions;
-        let min_acceptable = ((base_goal as f64 * 0.8).ceil() as usize).max(2);
+    /// Generate questions for every file, even ones unchanged since the last
+    /// run or with enough records in their QA file
+    pub fn with_force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
 
-        println!("Question targets for {} words:", word_count);
-        println!("  Base goal: {} questions", base_goal);
-        println!(
-            "  Generating: {} questions (+{} extra)",
-            generation_target, extra_questions
-        );
-        println!("  Minimum acceptable: {} questions", min_acceptable);
+    /// Size section targets by document structure instead of words/10
+    pub fn with_budget(mut self, budget: Option<QuestionBudget>) -> Self {
+        self.budget = budget;
+        self
+    }
 
-        (base_goal, generation_target, min_acceptable)
+    /// How many questions each file is worth, instead of one per 10 words
+    pub fn with_density(mut self, density: QuestionDensity) -> Self {
+        self.density = density;
+        self
     }
 
-    fn split_into_sections(&self, content: &str) -> Vec<String> {
-        let mut sections = Vec::new();
-        let mut current_section = String::new();
-        let header_regex = Regex::new(r"(?m)^#\s|^##\s").unwrap();
+    /// Stop generating once `shutdown` is requested; sections generated so
+    /// far stay in the QA file and its section index
+    pub fn with_shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
 
-        if !header_regex.is_match(content.lines().next().unwrap_or("")) {
-            current_section = String::new();
-        }
+    /// Only generate from the sampled sections, and stop once the question
+    /// cap is reached; clones of `limits` share one count across processors
+    pub fn with_limits(mut self, limits: RunLimits) -> Self {
+        self.limits = limits;
+        self
+    }
 
-        for line in content.lines() {
-            if header_regex.is_match(line) {
-                if !current_section.trim().is_empty() {
-                    sections.push(current_section);
-                }
-                current_section = String::new();
-            }
-            current_section.push_str(line);
-            current_section.push('\n');
-        }
+    /// Size above which files are skipped, split or summarized
+    pub fn with_size_limit(mut self, size_limit: SizeLimit) -> Self {
+        self.size_limit = size_limit;
+        self
+    }
 
-        if !current_section.trim().is_empty() {
-            sections.push(current_section);
-        }
+    /// Hold out a share of sections for the evaluation set; their records
+    /// get reference answers and rubrics instead of training answers
+    pub fn with_eval_split(mut self, eval_split: Option<EvalSplit>) -> Self {
+        self.eval_split = eval_split;
+        self
+    }
 
-        if sections.is_empty() {
-            sections.push(content.to_string());
+    /// Generate several candidate answers per question and keep the best
+    pub fn with_rejection_sampling(mut self, rejection: Option<RejectionSampling>) -> Self {
+        self.rejection = rejection;
+        self
+    }
+
+    /// Have a student model answer every generated question, and generate
+    /// more questions for sections where it disagrees with the teacher
+    pub fn with_student(mut self, student: Option<Student>) -> Self {
+        self.student = student;
+        self
+    }
+
+    /// Extract a glossary of each file's domain terms before generating,
+    /// add a definition question per term, and pass the terms a section
+    /// mentions along with it
+    pub fn with_glossary(mut self, glossary: bool) -> Self {
+        self.glossary = glossary;
+        self
+    }
+
+    /// Only generate from sections with a node carrying any of these tags,
+    /// after the nodes are tagged with their keywords and entities
+    pub fn with_target_tags(mut self, tags: Vec<String>) -> Self {
+        self.target_tags = tags;
+        self
+    }
+
+    /// Only generate from the sections under any of these headings, of any
+    /// level and ignoring case
+    pub fn with_target_sections(mut self, headings: Vec<String>) -> Self {
+        self.target_sections = headings;
+        self
+    }
+
+    /// Only generate from sections with a node whose text matches `pattern`
+    pub fn with_target_pattern(mut self, pattern: Option<Regex>) -> Self {
+        self.target_pattern = pattern;
+        self
+    }
+
+    /// Write the metadata columns of every record: source, model, prompt
+    /// template, creation time, language, tags and score
+    pub fn with_rich_schema(mut self, rich_schema: bool) -> Self {
+        self.rich_schema = rich_schema;
+        self
+    }
+
+    /// Tell `observer` about the run's files, sections and records as they
+    /// go through the pipeline
+    pub fn with_observer(mut self, observer: Arc<dyn PipelineObserver>) -> Self {
+        self.observers.push(observer);
+        self
+    }
+
+    /// Where the student was weak so far, if there is one
+    pub fn student_report(&self) -> Option<StudentReport> {
+        self.student.as_ref().map(Student::report)
+    }
+
+    /// Questions to request for one section of a file
+    pub(crate) fn section_target(
+        &self,
+        section: &str,
+        total: &DocumentSize,
+        total_questions: usize,
+    ) -> usize {
+        let proportional =
+            self.density
+                .section_share(&DocumentSize::of_section(section), total, total_questions);
+
+        match &self.budget {
+            Some(budget) => budget.for_markdown(section, 1).unwrap_or_else(|e| {
+                println!("Falling back to word-based target: {}", e);
+                proportional
+            }),
+            None => proportional,
         }
+    }
 
-        sections
+    /// Words in a text, as [`sections::count_words`] counts them
+    pub fn count_words(text: &str) -> usize {
+        sections::count_words(text)
+    }
+
+    /// The strategy question targets are set by
+    pub fn density(&self) -> QuestionDensity {
+        self.density
+    }
+
+    /// Question targets of a file of this size under the density strategy
+    pub fn calculate_question_targets(&self, size: &DocumentSize) -> (usize, usize, usize) {
+        let (base_goal, generation_target, min_acceptable) = self.density.targets(size);
+        let extra_questions = generation_target - base_goal;
+
+        println!(
+            "Question targets for {} words in {} sections ({}):",
+            size.words, size.sections, self.density
+        );
+        println!("  Base goal: {} questions", base_goal);
+        println!(
+            "  Generating: {} questions (+{} extra)",
+            generation_target, extra_questions
+        );
+        println!("  Minimum acceptable: {} questions", min_acceptable);
+
+        (base_goal, generation_target, min_acceptable)
     }
 
     fn split_by_headings(&self, content: &str) -> Vec<String> {
@@ -422,17 +1212,27 @@ impl DefaultOllamaProcessor {
         sections
     }
 
-    async fn process_section_recursive(
+    /// Generate questions for a section, splitting it by headings and then
+    /// by paragraphs while the whole gives too few
+    ///
+    /// With a `glossary`, each request carries the terms its part mentions.
+    pub(crate) async fn process_section_recursive(
         &self,
         section: &str,
         target_questions: usize,
+        glossary: Option<&Glossary>,
     ) -> Result<Vec<ProcessedItem>> {
         let mut all_items = Vec::new();
+        let with_terms = |part: &str| match glossary {
+            Some(glossary) => glossary.attach(part),
+            None => part.to_string(),
+        };
 
         let items = self
             .client
-            .generate_questions(section, target_questions)
-            .await?;
+            .generate_questions(&with_terms(section), target_questions)
+            .await
+            .map(from_model)?;
         println!(
             "Got {} questions from full section (target: {})",
             items.len(),
@@ -444,7 +1244,12 @@ impl DefaultOllamaProcessor {
         }
 
         println!("Splitting section by headings...");
-        let heading_sections = self.split_by_headings(section);
+        // Parts of a changelog version keep the version they belong to
+        let heading_sections: Vec<String> = self
+            .split_by_headings(section)
+            .iter()
+            .map(|part| changelog::with_release_header(section, part))
+            .collect();
         if heading_sections.len() > 1 {
             for (i, subsection) in heading_sections.iter().enumerate() {
                 println!(
@@ -463,14 +1268,17 @@ impl DefaultOllamaProcessor {
 
                 match self
                     .client
-                    .generate_questions(subsection, subsection_target)
+                    .generate_questions(&with_terms(subsection), subsection_target)
                     .await
+                    .map(from_model)
                 {
                     Ok(mut items) => {
                         println!("  Got {} questions", items.len());
                         all_items.append(&mut items);
                     }
-                    Err(e) => println!("Error processing heading section: {}", e),
+                    // Every other subsection would fail the same way
+                    Err(e) if backend_unavailable(&e) => return Err(e),
+                    Err(e) => println!("Error processing heading section: {:#}", e),
                 }
             }
 
@@ -485,7 +1293,11 @@ impl DefaultOllamaProcessor {
 
         println!("Splitting section by paragraphs...");
         all_items.clear();
-        let paragraph_sections = self.split_by_paragraphs(section);
+        let paragraph_sections: Vec<String> = self
+            .split_by_paragraphs(section)
+            .iter()
+            .map(|part| changelog::with_release_header(section, part))
+            .collect();
         if paragraph_sections.len() > 1 {
             for (i, subsection) in paragraph_sections.iter().enumerate() {
                 println!(
@@ -504,14 +1316,16 @@ impl DefaultOllamaProcessor {
 
                 match self
                     .client
-                    .generate_questions(subsection, subsection_target)
+                    .generate_questions(&with_terms(subsection), subsection_target)
                     .await
+                    .map(from_model)
                 {
                     Ok(mut items) => {
                         println!("  Got {} questions", items.len());
                         all_items.append(&mut items);
                     }
-                    Err(e) => println!("Error processing paragraph section: {}", e),
+                    Err(e) if backend_unavailable(&e) => return Err(e),
+                    Err(e) => println!("Error processing paragraph section: {:#}", e),
                 }
             }
 
@@ -532,7 +1346,7 @@ impl DefaultOllamaProcessor {
         Ok(all_items)
     }
 
-    fn get_qa_path(&self, file_path: &Path, extension: &str) -> PathBuf {
+    pub(crate) fn get_qa_path(&self, file_path: &Path, extension: &str) -> PathBuf {
         let file_stem = file_path
             .file_stem()
             .and_then(|s| s.to_str())
@@ -541,159 +1355,510 @@ impl DefaultOllamaProcessor {
             .join(format!("{}_qa.{}", file_stem, extension))
     }
 
-    fn convert_json_to_jsonl(
-        &self,
-        json_path: &Path,
-        jsonl_path: &Path,
-    ) -> Result<Vec<ProcessedItem>> {
-        println!(
-            "Converting {:?} to JSONL format at {:?}",
-            json_path, jsonl_path
-        );
-        let content = fs::read_to_string(json_path)?;
-        let items: Vec<ProcessedItem> = serde_json::from_str(&content)?;
-
-        let mut output = String::new();
-        for item in &items {
-            if let Ok(json_line) = serde_json::to_string(item) {
-                output.push_str(&json_line);
-                output.push('\n');
-            }
+    /// Records of the previous run grouped by section hash, if they can be trusted
+    fn load_previous_sections(index_path: &Path, qa_path: &Path) -> Option<PreviousSections> {
+        let index = SectionIndex::load(index_path)?;
+        let dataset = schema::read_dataset(qa_path).ok()?;
+        SchemaHeader::default()
+            .check_compatible(&dataset.header)
+            .ok()?;
+
+        let groups = index.group_items(dataset.items);
+        if groups.is_none() {
+            println!(
+                "Section index does not match existing questions, regenerating all sections..."
+            );
         }
-        fs::write(jsonl_path, output)?;
-        Ok(items)
+        groups
     }
 
-    fn check_existing_qa(
+    /// Pass the records of a file's existing QA file on in batches
+    pub(crate) fn replay_existing_qa(
         &self,
         file_path: &Path,
-        _required_questions: usize,
-    ) -> Result<Option<Vec<ProcessedItem>>> {
-        let jsonl_path = self.get_qa_path(file_path, "jsonl");
-
-        if jsonl_path.exists() {
-            println!("Found existing JSONL file: {:?}", jsonl_path);
-            if let Ok(content) = fs::read_to_string(&jsonl_path) {
-                let mut items = Vec::new();
-                for line in content.lines() {
-                    if let Ok(item) = serde_json::from_str::<ProcessedItem>(line) {
-                        items.push(item);
-                    }
-                }
-                if !items.is_empty() {
-                    let content = fs::read_to_string(file_path)?;
-                    let word_count = Self::count_words(&content);
-                    let (_, _, min_acceptable) = Self::calculate_question_targets(word_count);
-
-                    if items.len() >= min_acceptable {
-                        println!("Found existing JSONL file with {} questions (minimum acceptable: {}), skipping...",
-                            items.len(), min_acceptable);
-                        return Ok(Some(items));
-                    } else {
-                        println!("Found existing JSONL file but only has {} questions (minimum needed: {}), regenerating with extra buffer...",
-                            items.len(), min_acceptable);
-                    }
-                } else {
-                    println!("No valid items found in existing JSONL file");
-                }
-            }
-        } else {
-            let json_path = self.get_qa_path(file_path, "json");
-            if json_path.exists() {
-                println!("Found existing JSON file: {:?}", json_path);
-                if let Ok(content) = fs::read_to_string(&json_path) {
-                    if let Ok(items) = serde_json::from_str::<Vec<ProcessedItem>>(&content) {
-                        let content = fs::read_to_string(file_path)?;
-                        let word_count = Self::count_words(&content);
-                        let (_, _, min_acceptable) = Self::calculate_question_targets(word_count);
-
-                        if items.len() >= min_acceptable {
-                            println!("Found existing JSON file with {} questions (minimum acceptable: {}), converting to JSONL...",
-                                items.len(), min_acceptable);
-                            match self.convert_json_to_jsonl(&json_path, &jsonl_path) {
-                                Ok(items) => {
-                                    println!("Successfully converted to JSONL format");
-                                    return Ok(Some(items));
-                                }
-                                Err(e) => {
-                                    println!("Failed to convert to JSONL format: {}", e);
-                                }
-                            }
-                        } else {
-                            println!("Found existing JSON file but only has {} questions (minimum needed: {}), regenerating with extra buffer...",
-                                items.len(), min_acceptable);
-                        }
-                    }
-                }
-            } else {
-                println!("No existing QA file found");
+        sink: &mut ItemSink<'_>,
+    ) -> Result<usize> {
+        let mut passed = 0;
+        let mut batch = Vec::with_capacity(REPLAY_BATCH);
+        for item in DatasetReader::open(&self.get_qa_path(file_path, "jsonl"))? {
+            batch.push(item?);
+            if batch.len() == REPLAY_BATCH {
+                sink(&batch)?;
+                passed += batch.len();
+                batch.clear();
             }
         }
-        Ok(None)
+        if !batch.is_empty() {
+            sink(&batch)?;
+            passed += batch.len();
+        }
+        Ok(passed)
     }
-}
-
-#[async_trait]
-impl OllamaProcessor for DefaultOllamaProcessor {
-    async fn process_file(&self, file_path: &Path) -> Result<Vec<ProcessedItem>> {
-        let content = fs::read_to_string(file_path)?;
-        let total_words = Self::count_words(&content);
-        let (_, total_questions_needed, _) = Self::calculate_question_targets(total_words);
 
-        if let Some(existing_items) = self.check_existing_qa(file_path, total_questions_needed)? {
-            return Ok(existing_items);
+    /// Measure a file's size and decide, by the ledger, whether earlier
+    /// records of it can be kept
+    ///
+    /// The file and its earlier QA file are read on blocking threads, so
+    /// planning a large file doesn't hold up the requests of other sections.
+    pub(crate) async fn plan_file(&self, file_path: &Path) -> Result<FilePlan> {
+        let path = file_path.to_path_buf();
+        let hash = spawn_blocking(move || file_hash(&path)).await??;
+        let key = self.generation_key(hash.clone());
+        if !self.force && self.is_generated(file_path, &key).await {
+            println!(
+                "{:?} is unchanged since its questions were generated with these prompts and model, skipping...",
+                file_path
+            );
+            return Ok(FilePlan {
+                size: DocumentSize::default(),
+                questions: 0,
+                previous: None,
+                existing: true,
+                hash,
+                releases: None,
+            });
         }
 
-        let mut all_items = Vec::new();
-        let sections = self.split_into_sections(&content);
+        let path = file_path.to_path_buf();
+        let releases = spawn_blocking(move || changelog::read_releases(&path)).await??;
+        let size = match &releases {
+            Some(releases) => {
+                println!(
+                    "{:?} is a changelog, generating from its {} versions",
+                    file_path,
+                    releases.len()
+                );
+                changelog::measure(releases)
+            }
+            None => {
+                let path = file_path.to_path_buf();
+                spawn_blocking(move || measure_file(&path)).await??
+            }
+        };
+        let (_, questions, _) = self.calculate_question_targets(&size);
+        let document = self.document_key(file_path);
+        let reusable = self.ledger(|ledger| ledger.can_reuse_sections(&document, &key));
+        let previous = if self.incremental && !self.force && reusable {
+            let index_path = self.section_index_path(file_path);
+            let qa_path = self.get_qa_path(file_path, "jsonl");
+            spawn_blocking(move || Self::load_previous_sections(&index_path, &qa_path)).await?
+        } else {
+            None
+        };
+        Ok(FilePlan {
+            size,
+            questions,
+            previous,
+            existing: false,
+            hash,
+            releases,
+        })
+    }
+
+    fn ledger<T>(&self, f: impl FnOnce(&mut RunLedger) -> T) -> T {
+        let mut ledger = self.ledger.lock().unwrap_or_else(PoisonError::into_inner);
+        f(ledger.get_or_insert_with(|| RunLedger::load(&self.output_dir.join(LEDGER_FILE))))
+    }
 
-        // Create or truncate the output file at the start
+    /// What generating from content with this hash is recorded under
+    fn generation_key(&self, hash: String) -> GenerationKey {
+        GenerationKey::new(hash, &self.model)
+    }
+
+    /// How records and the ledger name a file: its path within the output
+    /// directory
+    pub(crate) fn document_key(&self, file_path: &Path) -> String {
+        file_path
+            .strip_prefix(&self.output_dir)
+            .unwrap_or(file_path)
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// Whether the ledger has the file's records complete for this key, and
+    /// its QA file can still be used
+    async fn is_generated(&self, file_path: &Path, key: &GenerationKey) -> bool {
+        let document = self.document_key(file_path);
+        if !self.ledger(|ledger| ledger.is_generated(&document, key)) {
+            return false;
+        }
         let qa_path = self.get_qa_path(file_path, "jsonl");
-        println!("Creating output file at {:?}", qa_path);
-        fs::File::create(&qa_path)?;
+        spawn_blocking(move || {
+            DatasetReader::open(&qa_path).is_ok_and(|reader| {
+                SchemaHeader::default()
+                    .check_compatible(&reader.header)
+                    .is_ok()
+            })
+        })
+        .await
+        .unwrap_or(false)
+    }
 
-        for (i, section) in sections.iter().enumerate() {
-            if section.trim().is_empty() {
-                continue;
+    /// Record in the ledger that the file's QA file holds `records` generated
+    /// from content with this hash; only a `complete` file is skipped later
+    pub(crate) fn file_generated(
+        &self,
+        file_path: &Path,
+        hash: String,
+        records: usize,
+        complete: bool,
+    ) {
+        let document = self.document_key(file_path);
+        let key = self.generation_key(hash);
+        self.ledger(|ledger| ledger.record(document, key, records, complete));
+    }
+
+    /// Write the ledger, if any file was recorded
+    pub(crate) fn save_ledger(&self) -> Result<()> {
+        let ledger = self.ledger.lock().unwrap_or_else(PoisonError::into_inner);
+        match ledger.as_ref() {
+            Some(ledger) if !ledger.files.is_empty() => {
+                ledger.save(&self.output_dir.join(LEDGER_FILE))
             }
+            _ => Ok(()),
+        }
+    }
 
-            let section_words = Self::count_words(section);
-            let section_target = (total_questions_needed as f64
-                * (section_words as f64 / total_words as f64))
-                .ceil() as usize;
+    /// Where the section index of a file's QA file is kept
+    pub(crate) fn section_index_path(&self, file_path: &Path) -> PathBuf {
+        self.get_qa_path(file_path, SECTION_INDEX_EXTENSION)
+    }
 
-            println!(
-                "\nProcessing section {}/{} ({} words, target {} questions)",
-                i + 1,
-                sections.len(),
-                section_words,
-                section_target
-            );
+    pub(crate) fn shutdown(&self) -> &Shutdown {
+        &self.shutdown
+    }
 
-            match self
-                .process_section_recursive(section, section_target)
-                .await
-            {
-                Ok(questions) => {
-                    // Write questions from this section immediately
-                    let mut file = fs::OpenOptions::new().append(true).open(&qa_path)?;
+    pub(crate) fn limits(&self) -> &RunLimits {
+        &self.limits
+    }
 
-                    for item in &questions {
-                        writeln!(file, "{}", serde_json::to_string(item)?)?;
-                    }
+    pub(crate) fn observers(&self) -> &[Arc<dyn PipelineObserver>] {
+        &self.observers
+    }
+
+    pub(crate) fn size_limit(&self) -> SizeLimit {
+        self.size_limit
+    }
+
+    pub(crate) fn is_forced(&self) -> bool {
+        self.force
+    }
+
+    /// Where the parts and summaries of oversized files are written
+    pub(crate) fn parts_dir(&self) -> PathBuf {
+        self.output_dir.join(PARTS_DIR)
+    }
 
-                    println!("Added {} questions (written to file)", questions.len());
+    pub(crate) async fn summarize(&self, content: &str) -> Result<String> {
+        self.client.summarize(content).await
+    }
 
-                    let mut questions_copy = questions.clone();
-                    all_items.append(&mut questions_copy);
+    pub(crate) fn glossary_enabled(&self) -> bool {
+        self.glossary
+    }
+
+    pub(crate) fn target_tags(&self) -> &[String] {
+        &self.target_tags
+    }
+
+    pub(crate) fn target_sections(&self) -> &[String] {
+        &self.target_sections
+    }
+
+    pub(crate) fn target_pattern(&self) -> Option<&Regex> {
+        self.target_pattern.as_ref()
+    }
+
+    /// Whether generation is restricted to some of the graph's nodes
+    pub(crate) fn is_targeted(&self) -> bool {
+        !self.target_tags.is_empty()
+            || !self.target_sections.is_empty()
+            || self.target_pattern.is_some()
+    }
+
+    pub fn rich_schema(&self) -> bool {
+        self.rich_schema
+    }
+
+    /// Ask for the terms of a document, a few thousand words at a time
+    ///
+    /// The document is split at headings, so a request only cuts a section
+    /// when the section alone is over the limit. A term found in several
+    /// parts keeps its first definition.
+    pub(crate) async fn extract_glossary(&self, document: &str) -> Result<Glossary> {
+        let mut parts: Vec<String> = Vec::new();
+        for section in self.split_by_headings(document) {
+            match parts.last_mut() {
+                Some(part)
+                    if Self::count_words(part) + Self::count_words(&section)
+                        <= glossary::WORDS_PER_REQUEST =>
+                {
+                    part.push_str(&section)
                 }
-                Err(e) => {
-                    println!("Error processing section: {}", e);
+                _ => parts.push(section),
+            }
+        }
+        let mut terms = Vec::new();
+        for (i, part) in parts.iter().enumerate() {
+            if self.shutdown.is_requested() {
+                return Err(anyhow!("Glossary extraction stopped by shutdown"));
+            }
+            println!("Extracting glossary terms ({}/{})", i + 1, parts.len());
+            terms.extend(self.client.extract_terms(part).await?);
+        }
+        Ok(Glossary::new(terms))
+    }
+
+    /// Whether a section is held out for the evaluation set
+    pub(crate) fn held_out(&self, section: &str) -> bool {
+        self.eval_split
+            .as_ref()
+            .is_some_and(|split| split.held_out(section))
+    }
+
+    /// Generate evaluation records for a held-out section
+    pub(crate) async fn process_eval_section(
+        &self,
+        section: &str,
+        target_questions: usize,
+        glossary: Option<&Glossary>,
+    ) -> Result<Vec<ProcessedItem>> {
+        let section = match glossary {
+            Some(glossary) => glossary.attach(section),
+            None => section.to_string(),
+        };
+        let mut items = self
+            .client
+            .generate_eval(&section, target_questions)
+            .await
+            .map(from_model)?;
+        // A record without a rubric would be taken for a training record
+        for item in &mut items {
+            item.rubric.get_or_insert_with(Vec::new);
+        }
+        println!(
+            "Got {} evaluation questions (target: {})",
+            items.len(),
+            target_questions
+        );
+        Ok(items)
+    }
+
+    /// Replace each record's answer with the best of several candidates
+    /// (`--candidates`)
+    ///
+    /// The generated answer is the first candidate; the others are asked
+    /// for one at a time. Candidates that fail to generate are left out,
+    /// and ones that fail to be judged score 0, so a record always keeps
+    /// an answer.
+    pub(crate) async fn best_of(
+        &self,
+        section: &str,
+        mut items: Vec<ProcessedItem>,
+    ) -> Vec<ProcessedItem> {
+        let Some(rejection) = self.rejection.filter(|r| r.candidates > 1) else {
+            return items;
+        };
+        for item in &mut items {
+            let mut answers = vec![item.answer.clone()];
+            for _ in 1..rejection.candidates {
+                match self.client.answer(section, &item.question).await {
+                    Ok(answer) if !answer.trim().is_empty() => answers.push(answer),
+                    Ok(_) => {}
+                    Err(e) => println!("Failed to generate a candidate answer: {}", e),
                 }
             }
+            let mut scored = Vec::with_capacity(answers.len());
+            for answer in answers {
+                let score = match rejection.scoring {
+                    CandidateScoring::Heuristic => heuristic_score(section, &answer),
+                    CandidateScoring::Judge => {
+                        let candidate = ProcessedItem {
+                            answer: answer.clone(),
+                            ..item.clone()
+                        };
+                        self.client.judge(&candidate).await.unwrap_or_else(|e| {
+                            println!("Failed to judge a candidate answer: {}", e);
+                            0.0
+                        })
+                    }
+                };
+                scored.push((score, answer));
+            }
+            let mut ranked = rank(scored).into_iter();
+            if let Some(best) = ranked.next() {
+                item.answer = best;
+            }
+            let rejected: Vec<String> = ranked.collect();
+            if rejection.keep_rejected && !rejected.is_empty() {
+                item.rejected = Some(rejected);
+            }
         }
+        items
+    }
 
-        Ok(all_items)
+    /// Quiz the student on a section's records (`--student-model`)
+    ///
+    /// Every question the student fails earns the section one follow-up
+    /// question from the teacher, asked once; follow-ups repeating a
+    /// question of the section are dropped.
+    pub(crate) async fn distill(
+        &self,
+        document: &str,
+        section: &str,
+        span: Range<usize>,
+        mut items: Vec<ProcessedItem>,
+    ) -> Vec<ProcessedItem> {
+        let Some(student) = &self.student else {
+            return items;
+        };
+        let failed = student.failures(&items).await;
+        let mut follow_up = Vec::new();
+        if !failed.is_empty() {
+            println!(
+                "Student failed {} of {} questions, asking for {} more",
+                failed.len(),
+                items.len(),
+                failed.len()
+            );
+            match self.client.generate_questions(section, failed.len()).await {
+                Ok(generated) => {
+                    follow_up = new_questions(&items, from_model(generated));
+                    follow_up.truncate(failed.len());
+                    follow_up.truncate(self.limits.take_questions(follow_up.len()));
+                }
+                Err(e) => println!("Failed to generate follow-up questions: {}", e),
+            }
+        }
+        student.record(WeakArea {
+            document: document.to_string(),
+            span,
+            heading: section
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_string(),
+            asked: items.len(),
+            failed: failed.len(),
+            follow_up: follow_up.len(),
+        });
+        items.append(&mut follow_up);
+        items
+    }
+
+    /// Generate questions for a section of `document` no record was
+    /// generated from (`--top-up`), asking for `target` of them
+    ///
+    /// The records are traced to the section as the pipeline traces its
+    /// records. Sections held out for evaluation or left out of the sample
+    /// get none, and the question cap applies.
+    pub async fn top_up(
+        &self,
+        document: &str,
+        section: &SpannedSection,
+        target: usize,
+        graph: Option<&DocumentGraph>,
+    ) -> Result<Vec<ProcessedItem>> {
+        if self.held_out(&section.text) || !self.limits.sampled(&section.text) {
+            return Ok(Vec::new());
+        }
+        let target = match self.limits.remaining_questions() {
+            Some(remaining) => target.min(remaining),
+            None => target,
+        };
+        if target == 0 || self.shutdown.is_requested() {
+            return Ok(Vec::new());
+        }
+        let mut items = self
+            .process_section_recursive(&section.text, target, None)
+            .await?;
+        items.truncate(self.limits.take_questions(items.len()));
+        items.retain(|item| schema::validate_item(item).is_ok());
+        self.trace_records(
+            &mut items,
+            document,
+            &section.text,
+            section.span.clone(),
+            graph,
+        );
+        Ok(items)
+    }
+
+    /// Give records generated from a section of `document` their IDs and
+    /// lineage, and with `--rich-schema` their metadata columns
+    ///
+    /// Columns a record already has, e.g. one kept from an earlier run, are
+    /// left as they are; without `--rich-schema` they are dropped.
+    pub(crate) fn trace_records(
+        &self,
+        items: &mut [ProcessedItem],
+        document: &str,
+        text: &str,
+        span: Range<usize>,
+        graph: Option<&DocumentGraph>,
+    ) {
+        let tags = graph.map(|graph| span_tags(graph, &span));
+        let lineage = Lineage::of_span(document, span, graph);
+        let span = section_hash(text);
+        let created_at = Utc::now();
+        for item in items {
+            item.assign_id(document, &span);
+            item.lineage = Some(lineage.clone());
+            if !self.rich_schema {
+                item.metadata = RecordMetadata::default();
+                continue;
+            }
+            let template = if item.is_eval() {
+                "eval"
+            } else {
+                question_template(text)
+            };
+            let score = heuristic_score(text, &item.answer);
+            item.metadata.fill_from(RecordMetadata {
+                source: Some(document.to_string()),
+                model: Some(self.model.clone()).filter(|model| !model.is_empty()),
+                prompt_template: Some(template.to_string()),
+                created_at: Some(created_at),
+                lang: detect_lang(&item.question).map(str::to_string),
+                tags: tags.clone().filter(|tags| !tags.is_empty()),
+                score: Some((score * 1000.0).round() / 1000.0),
+                ..RecordMetadata::default()
+            });
+        }
+    }
+
+    /// Generate questions for a file, handing each section's records to
+    /// `sink` once they are written to the file's QA file
+    ///
+    /// The file runs through the same [`Pipeline`] stages as a whole run:
+    /// it is read one section at a time and no records are kept, so memory
+    /// use doesn't grow with the size of the file. Returns the number of
+    /// records passed to `sink`.
+    pub async fn process_file_with(
+        &self,
+        file_path: &Path,
+        sink: &mut ItemSink<'_>,
+    ) -> Result<usize> {
+        let (files, received) = mpsc::channel(1);
+        let mut outcome = None;
+        let feed = send_files(vec![file_path.to_path_buf()], files);
+        let mut record = |_: &Path, result| outcome = Some(result);
+        tokio::join!(feed, Pipeline::new(self).run(received, sink, &mut record));
+        outcome.unwrap_or_else(|| Err(anyhow!("{:?} was not processed", file_path)))
+    }
+}
+
+#[async_trait]
+impl OllamaProcessor for DefaultOllamaProcessor {
+    async fn process_file(&self, file_path: &Path) -> Result<Vec<ProcessedItem>> {
+        let mut items = Vec::new();
+        self.process_file_with(file_path, &mut |batch| {
+            items.extend_from_slice(batch);
+            Ok(())
+        })
+        .await?;
+        Ok(items)
     }
 }
 
@@ -702,6 +1867,7 @@ mod tests {
     use super::*;
     use mockall::mock;
     use mockall::predicate;
+    use std::fs;
 
     mock! {
         pub OllamaClient {}
@@ -712,9 +1878,252 @@ mod tests {
         }
     }
 
-    // Mock OllamaProcessor to override check_existing_qa
+    #[test]
+    fn test_span_tags_come_from_text_in_the_span() {
+        let content =
+            "# Client\n\nThe client sends requests.\n\n## Advanced\n\nSwitch endpoints.\n";
+        let mut graph = crate::parser::parse_markdown_document(content, "guide.md").unwrap();
+        crate::graph::tag_nodes(&mut graph, &crate::graph::TaggingConfig::default());
+        // A section tagged from text outside the span, e.g. a later part of
+        // a section split into several chunks
+        let client = graph.find_by_heading("Client")[0].id;
+        graph.add_tag(&client, "keyword:elsewhere").unwrap();
+
+        let intro = 0..content.find("## Advanced").unwrap();
+        let tags = span_tags(&graph, &intro);
+        assert!(
+            tags.contains(&"keyword:client sends requests".to_string()),
+            "{:?}",
+            tags
+        );
+        assert!(!tags
+            .iter()
+            .any(|tag| tag.contains("elsewhere") || tag.contains("endpoints")));
+    }
+
+    #[test]
+    fn test_count_words_without_spaces() {
+        assert_eq!(
+            DefaultOllamaProcessor::count_words("## Setup: run `make`, then test."),
+            5
+        );
+        // Nine ideographs and a full stop, with no whitespace at all
+        assert_eq!(
+            DefaultOllamaProcessor::count_words("数据集生成工具说明。"),
+            9
+        );
+        assert_eq!(DefaultOllamaProcessor::count_words("Rust で データ"), 3);
+
+        let processor = DefaultOllamaProcessor::new(String::new(), String::new());
+        let size = DocumentSize::of_section(&"文档内容".repeat(50));
+        let (base, _, _) = processor.calculate_question_targets(&size);
+        assert_eq!(base, 20);
+
+        // A fixed count per file ignores the length
+        let processor = processor.with_density(QuestionDensity::PerFile(5));
+        assert_eq!(processor.calculate_question_targets(&size), (5, 7, 4));
+    }
+
+    #[test]
+    fn test_item_id_is_deterministic() {
+        let id = ProcessedItem::item_id("docs/guide.md", "abc123", "What is TLS?");
+        assert_eq!(id.get_version_num(), 5);
+        assert_eq!(
+            id,
+            ProcessedItem::item_id("docs/guide.md", "abc123", " What is TLS? ")
+        );
+        assert_ne!(
+            id,
+            ProcessedItem::item_id("docs/other.md", "abc123", "What is TLS?")
+        );
+        assert_ne!(
+            id,
+            ProcessedItem::item_id("docs/guide.md", "def456", "What is TLS?")
+        );
+    }
+
+    #[test]
+    fn test_question_prompt() {
+        let (system, user) = question_prompt("# Release Notes\n\nv2 adds TLS", 3);
+        assert_eq!(system, RELEASE_NOTES_SYSTEM_PROMPT);
+        assert!(user.starts_with("Generate exactly 3 unique questions"));
+        assert!(user.ends_with("Content: # Release Notes\n\nv2 adds TLS"));
+
+        let (system, _) = question_prompt("# Guide", 1);
+        assert_eq!(system, DOCUMENTATION_SYSTEM_PROMPT);
+
+        let (system, user) = question_prompt("# Changelog: version 1.2.0\n\n- Adds TLS", 2);
+        assert_eq!(system, RELEASE_NOTES_SYSTEM_PROMPT);
+        assert!(user.starts_with(
+            "Generate exactly 2 unique questions and answers about the changes in version 1.2.0."
+        ));
+
+        let glossary = Glossary::new([Term {
+            term: "TLS".to_string(),
+            definition: "Transport Layer Security.".to_string(),
+        }]);
+        let (_, user) = question_prompt(&glossary.attach("# Guide\n\nEnable TLS."), 1);
+        assert!(user.contains(&format!(
+            "{}\n- TLS: Transport Layer Security.\nContent: # Guide\n\nEnable TLS.",
+            GLOSSARY_INSTRUCTION
+        )));
+        assert!(user.ends_with("Enable TLS."));
+    }
+
+    #[test]
+    fn test_question_request_constrains_output() {
+        let request = DefaultOllamaClient::question_request("system", "user", false, 512);
+        assert_eq!(request.messages[0], ChatMessage::system("system"));
+        assert_eq!(request.messages[1], ChatMessage::user("user"));
+        let format = request.format.unwrap();
+        assert_eq!(format["required"], serde_json::json!(["questions"]));
+        assert!(format["properties"]["questions"]["items"]["properties"]["rubric"].is_null());
+        assert_eq!(request.max_tokens, Some(512));
+
+        let request = DefaultOllamaClient::question_request("system", "user", true, 512);
+        let item = &request.format.unwrap()["properties"]["questions"]["items"];
+        assert_eq!(
+            item["required"],
+            serde_json::json!(["question", "answer", "rubric"])
+        );
+        assert_eq!(item["properties"]["rubric"]["type"], "array");
+    }
+
+    /// Replies from a script, recording each request
+    struct ScriptedProvider {
+        replies: Mutex<Vec<String>>,
+        requests: Mutex<Vec<ChatRequest>>,
+    }
+
+    #[async_trait]
+    impl ModelProvider for ScriptedProvider {
+        fn model(&self) -> &str {
+            "scripted"
+        }
+
+        fn endpoint(&self) -> &str {
+            "http://scripted"
+        }
+
+        async fn generate(&self, _prompt: &str) -> Result<String> {
+            unimplemented!()
+        }
+
+        async fn generate_stream(&self, _prompt: &str) -> Result<crate::external::TokenStream> {
+            unimplemented!()
+        }
+
+        async fn chat(&self, request: ChatRequest) -> Result<String> {
+            self.requests.lock().unwrap().push(request);
+            Ok(self.replies.lock().unwrap().remove(0))
+        }
+
+        async fn chat_stream(&self, _request: ChatRequest) -> Result<crate::external::TokenStream> {
+            unimplemented!()
+        }
+
+        async fn list_models(&self) -> Result<Vec<String>> {
+            Ok(Vec::new())
+        }
+
+        async fn pull_model(&self) -> Result<()> {
+            Ok(())
+        }
+
+        fn usage(&self) -> crate::external::ProviderUsage {
+            crate::external::ProviderUsage::default()
+        }
+    }
+
+    fn questions_reply(questions: &[&str]) -> String {
+        let questions: Vec<_> = questions
+            .iter()
+            .map(|q| serde_json::json!({ "question": q, "answer": "A" }))
+            .collect();
+        serde_json::json!({ "questions": questions }).to_string()
+    }
+
+    #[tokio::test]
+    async fn test_questions_are_requested_in_batches_within_the_token_budget() {
+        let provider = Arc::new(ScriptedProvider {
+            replies: Mutex::new(vec![
+                questions_reply(&["Q1", "Q2"]),
+                // Cut off at the output limit
+                r#"{"questions": [{"question": "Q3", "answer": "#.to_string(),
+                questions_reply(&["Q3"]),
+                questions_reply(&["Q4"]),
+                questions_reply(&["Q4"]),
+            ]),
+            requests: Mutex::new(Vec::new()),
+        });
+        let client =
+            DefaultOllamaClient::from_provider(provider.clone()).with_token_budget(TokenBudget {
+                max_output_tokens: 400,
+            });
+
+        let items = client.generate_questions("# Guide", 5).await.unwrap();
+        let questions: Vec<&str> = items.iter().map(|item| &*item.question).collect();
+        // The repeated Q4 came back short, which ends the batches
+        assert_eq!(questions, ["Q1", "Q2", "Q3", "Q4"]);
+
+        let requests = provider.requests.lock().unwrap();
+        let max_tokens: Vec<_> = requests.iter().map(|r| r.max_tokens).collect();
+        assert_eq!(
+            max_tokens,
+            [Some(352), Some(352), Some(192), Some(192), Some(192)]
+        );
+        let asked = |i: usize| requests[i].messages[1].content.clone();
+        assert!(asked(0).starts_with("Generate exactly 2 unique questions"));
+        assert!(asked(2).starts_with("Generate exactly 1 unique questions"));
+        assert!(asked(2).contains("do not repeat them:\n- Q1\n- Q2"));
+    }
+
+    #[tokio::test]
+    async fn test_unusable_replies_are_told_apart() {
+        let client = |replies: Vec<String>| {
+            DefaultOllamaClient::from_provider(Arc::new(ScriptedProvider {
+                replies: Mutex::new(replies),
+                requests: Mutex::new(Vec::new()),
+            }))
+        };
+
+        let prose = client(vec!["Sure! Here are some questions.".to_string(); 3]);
+        let error = prose.generate_questions("# Guide", 1).await.unwrap_err();
+        assert!(matches!(
+            ProcessorError::of(&error),
+            Some(ProcessorError::MalformedResponse { attempts: 3, .. })
+        ));
+
+        let cut_off = client(vec![r#"{"questions": [{"question": "Q"#.to_string(); 3]);
+        let error = cut_off.generate_questions("# Guide", 1).await.unwrap_err();
+        assert!(matches!(
+            ProcessorError::of(&error),
+            Some(ProcessorError::Truncated { max_tokens: 192 })
+        ));
+    }
+
+    #[test]
+    fn test_obviously_malformed_stream() {
+        // Still plausible
+        assert!(!is_obviously_malformed(""));
+        assert!(!is_obviously_malformed("  ``"));
+        assert!(!is_obviously_malformed("```json\n{\"ques"));
+        assert!(!is_obviously_malformed(
+            "{\"questions\": [{\"question\": \"What"
+        ));
+
+        // Prose instead of JSON
+        assert!(is_obviously_malformed("Sure! Here are"));
+        assert!(is_obviously_malformed("[{\"question\""));
+        // An object that isn't the requested one
+        let wrong_object = format!("{{\"answer\": \"{}\"", "x".repeat(100));
+        assert!(is_obviously_malformed(&wrong_object));
+    }
+
+    // Stand-in for OllamaProcessor that generates each file as one section
     struct TestOllamaProcessor {
         client: Box<dyn OllamaClient>,
+        #[allow(dead_code)]
         output_dir: PathBuf,
     }
 
@@ -757,10 +2166,8 @@ mod tests {
         async fn process_file(&self, file_path: &Path) -> Result<Vec<ProcessedItem>> {
             let content = fs::read_to_string(file_path)?;
             let total_words = DefaultOllamaProcessor::count_words(&content);
-            let (_, total_questions_needed, _) =
-                DefaultOllamaProcessor::calculate_question_targets(total_words);
+            let (_, total_questions_needed, _) = sections::question_targets(total_words);
 
-            // Skip checking existing QA files in tests
             let sections = self.split_into_sections(&content);
             let mut all_items = Vec::new();
 
@@ -809,14 +2216,8 @@ mod tests {
             .times(1)
             .returning(|_, _| {
                 Ok(vec![
-                    ProcessedItem {
-                        question: "Q1".to_string(),
-                        answer: "A1".to_string(),
-                    },
-                    ProcessedItem {
-                        question: "Q2".to_string(),
-                        answer: "A2".to_string(),
-                    },
+                    ProcessedItem::new("Q1", "A1"),
+                    ProcessedItem::new("Q2", "A2"),
                 ])
             });
 
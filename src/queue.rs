@@ -0,0 +1,535 @@
+//! Work queue for spreading a generation run over several machines: a
+//! coordinator collects the sources and queues their files, and workers,
+//! each with its own Ollama server, claim files over HTTP, generate
+//! questions from them and post the records back to be merged into one
+//! dataset.
+//!
+//! The coordinator serves two endpoints:
+//! - `POST /claim` answers with the next [`Task`], `204 No Content` while
+//!   every file is claimed but not all are done, or `410 Gone` once the run
+//!   is over
+//! - `POST /results` takes a [`TaskResult`]
+//!
+//! A claimed file not reported within the lease is queued again, so a worker
+//! that dies doesn't lose it; a late result for a file already done is
+//! ignored.
+
+use anyhow::{anyhow, Context, Result};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+use crate::pipeline::Pipeline;
+use crate::processor::{DefaultOllamaProcessor, ProcessedItem};
+
+/// Shared secret the coordinator requires as a bearer token, if set
+pub const QUEUE_TOKEN_VAR: &str = "QUEUE_TOKEN";
+
+/// How long a worker has to report a claimed file before it is queued again
+pub const DEFAULT_LEASE: Duration = Duration::from_secs(30 * 60);
+
+/// Pause before a worker asks again while every file is claimed
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long the coordinator keeps answering claims once the run is over, so
+/// workers waiting between polls learn that it is
+pub const DRAIN_PERIOD: Duration = Duration::from_secs(2 * POLL_INTERVAL.as_secs());
+
+/// How long a worker keeps trying to reach a coordinator that doesn't answer
+const COORDINATOR_PATIENCE: Duration = Duration::from_secs(60);
+
+/// A file for a worker to generate questions from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Task {
+    pub id: u64,
+    /// Path of the file relative to the output directory, which names it in
+    /// record IDs and lineage on every machine alike
+    pub path: String,
+    pub content: String,
+}
+
+/// What a worker generated from a [`Task`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskResult {
+    pub id: u64,
+    pub worker: String,
+    pub model: String,
+    pub items: Vec<ProcessedItem>,
+    /// Why the file produced no records, if it failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A finished task as the coordinator receives it
+#[derive(Debug)]
+pub struct Completed {
+    pub path: String,
+    pub result: TaskResult,
+}
+
+/// The coordinator's answer to a claim
+#[derive(Debug, PartialEq, Eq)]
+pub enum Claim {
+    Task(Task),
+    /// Every file is claimed, but more may be queued again or added
+    Wait,
+    /// The run is over
+    Done,
+}
+
+#[derive(Default)]
+struct State {
+    next_id: u64,
+    pending: VecDeque<Task>,
+    leased: HashMap<u64, (Task, Instant)>,
+    /// No more files will be added
+    closed: bool,
+    results: Option<mpsc::UnboundedSender<Completed>>,
+}
+
+impl State {
+    fn is_finished(&self) -> bool {
+        self.closed && self.pending.is_empty() && self.leased.is_empty()
+    }
+
+    /// Stop passing on results once the run is over, ending the receiver
+    fn end_if_finished(&mut self) {
+        if self.is_finished() {
+            self.results = None;
+        }
+    }
+}
+
+/// The coordinator's queue of files and their leases
+#[derive(Clone)]
+pub struct WorkQueue {
+    state: Arc<Mutex<State>>,
+    lease: Duration,
+    token: Option<String>,
+}
+
+impl WorkQueue {
+    /// Create a queue and the receiver of the results workers post to it,
+    /// which ends once the queue is closed and every file is done
+    pub fn new(lease: Duration) -> (Self, mpsc::UnboundedReceiver<Completed>) {
+        let (results, received) = mpsc::unbounded_channel();
+        let state = State {
+            results: Some(results),
+            ..State::default()
+        };
+        let queue = Self {
+            state: Arc::new(Mutex::new(state)),
+            lease,
+            token: None,
+        };
+        (queue, received)
+    }
+
+    /// Only serve workers presenting `token` as a bearer token
+    pub fn with_token(mut self, token: Option<String>) -> Self {
+        self.token = token.filter(|token| !token.is_empty());
+        self
+    }
+
+    /// Queue a file for the workers
+    pub fn push(&self, path: String, content: String) {
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.pending.push_back(Task { id, path, content });
+    }
+
+    /// No more files will be queued
+    pub fn close(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.closed = true;
+        state.end_if_finished();
+    }
+
+    /// Files queued or claimed but not yet done
+    pub fn remaining(&self) -> usize {
+        let state = self.state.lock().unwrap();
+        state.pending.len() + state.leased.len()
+    }
+
+    /// Hand out the next file, queueing again those whose lease expired
+    pub fn claim(&self) -> Claim {
+        let mut state = self.state.lock().unwrap();
+        let lease = self.lease;
+        let expired: Vec<u64> = state
+            .leased
+            .iter()
+            .filter(|(_, (_, claimed))| claimed.elapsed() >= lease)
+            .map(|(&id, _)| id)
+            .collect();
+        for id in expired {
+            if let Some((task, _)) = state.leased.remove(&id) {
+                println!("Lease on {} expired; queueing it again", task.path);
+                state.pending.push_back(task);
+            }
+        }
+
+        match state.pending.pop_front() {
+            Some(task) => {
+                state.leased.insert(task.id, (task.clone(), Instant::now()));
+                Claim::Task(task)
+            }
+            None if state.is_finished() => Claim::Done,
+            None => Claim::Wait,
+        }
+    }
+
+    /// Take a worker's result; `false` if the file was already done
+    pub fn complete(&self, result: TaskResult) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let task = match state.leased.remove(&result.id) {
+            Some((task, _)) => task,
+            None => match state.pending.iter().position(|task| task.id == result.id) {
+                // Queued again after its lease expired, but reported after all
+                Some(position) => state.pending.remove(position).unwrap(),
+                None => return false,
+            },
+        };
+        if let Some(results) = &state.results {
+            let _ = results.send(Completed {
+                path: task.path,
+                result,
+            });
+        }
+        state.end_if_finished();
+        true
+    }
+
+    /// Serve the queue to workers on `addr` until `until` completes
+    ///
+    /// Returns the address bound, e.g. for port 0, and the server to await.
+    pub fn serve(
+        &self,
+        addr: SocketAddr,
+        until: impl Future<Output = ()> + Send + 'static,
+    ) -> Result<(SocketAddr, impl Future<Output = Result<()>>)> {
+        let queue = self.clone();
+        let make_service = make_service_fn(move |_| {
+            let queue = queue.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let queue = queue.clone();
+                    async move { Ok::<_, Infallible>(queue.handle(request).await) }
+                }))
+            }
+        });
+        let server = Server::try_bind(&addr)
+            .with_context(|| format!("Could not listen on {}", addr))?
+            .serve(make_service);
+        let bound = server.local_addr();
+        let server = server.with_graceful_shutdown(until);
+        Ok((bound, async move { Ok(server.await?) }))
+    }
+
+    async fn handle(&self, request: Request<Body>) -> Response<Body> {
+        if let Some(token) = &self.token {
+            let expected = format!("Bearer {}", token);
+            let authorization = request.headers().get(hyper::header::AUTHORIZATION);
+            if authorization.and_then(|value| value.to_str().ok()) != Some(expected.as_str()) {
+                return status(StatusCode::UNAUTHORIZED);
+            }
+        }
+        match (request.method(), request.uri().path()) {
+            (&Method::POST, "/claim") => match self.claim() {
+                Claim::Task(task) => match serde_json::to_vec(&task) {
+                    Ok(body) => Response::new(Body::from(body)),
+                    Err(_) => status(StatusCode::INTERNAL_SERVER_ERROR),
+                },
+                Claim::Wait => status(StatusCode::NO_CONTENT),
+                Claim::Done => status(StatusCode::GONE),
+            },
+            (&Method::POST, "/results") => {
+                let result = match hyper::body::to_bytes(request.into_body()).await {
+                    Ok(body) => serde_json::from_slice::<TaskResult>(&body),
+                    Err(_) => return status(StatusCode::BAD_REQUEST),
+                };
+                match result {
+                    Ok(result) => {
+                        self.complete(result);
+                        status(StatusCode::NO_CONTENT)
+                    }
+                    Err(_) => status(StatusCode::BAD_REQUEST),
+                }
+            }
+            _ => status(StatusCode::NOT_FOUND),
+        }
+    }
+}
+
+fn status(code: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = code;
+    response
+}
+
+/// A worker's connection to the coordinator
+pub struct QueueClient {
+    client: reqwest::Client,
+    url: String,
+    token: Option<String>,
+}
+
+impl QueueClient {
+    /// Talk to the coordinator at `url`, e.g. `http://gpu-0:7878`
+    pub fn new(url: &str, token: Option<String>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: url.trim_end_matches('/').to_string(),
+            token: token.filter(|token| !token.is_empty()),
+        }
+    }
+
+    fn post(&self, endpoint: &str) -> reqwest::RequestBuilder {
+        let request = self.client.post(format!("{}{}", self.url, endpoint));
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    pub async fn claim(&self) -> Result<Claim> {
+        let response = self.post("/claim").send().await?;
+        if response.status() == StatusCode::GONE {
+            return Ok(Claim::Done);
+        }
+        let response = response
+            .error_for_status()
+            .context("The coordinator refused the claim")?;
+        Ok(match response.status() {
+            StatusCode::NO_CONTENT => Claim::Wait,
+            _ => Claim::Task(response.json().await?),
+        })
+    }
+
+    pub async fn submit(&self, result: &TaskResult) -> Result<()> {
+        self.post("/results")
+            .json(result)
+            .send()
+            .await?
+            .error_for_status()
+            .context("The coordinator refused the result")?;
+        Ok(())
+    }
+}
+
+/// Claim files from the coordinator and generate questions from them with
+/// `processor` until the run is over or shutdown is requested
+///
+/// Claimed files are written under `output_dir` at the path the coordinator
+/// gives, so per-file QA files and `--incremental` work as in a local run.
+/// Returns the number of files done.
+pub async fn work(
+    processor: &DefaultOllamaProcessor,
+    queue: &QueueClient,
+    output_dir: &Path,
+    worker: &str,
+    model: &str,
+) -> Result<usize> {
+    let shutdown = processor.shutdown();
+    let mut done = 0;
+    let mut last_contact = Instant::now();
+    while !shutdown.is_requested() {
+        let task = match queue.claim().await {
+            Ok(Claim::Task(task)) => task,
+            Ok(Claim::Wait) => {
+                last_contact = Instant::now();
+                sleep_unless_shutdown(processor, POLL_INTERVAL).await;
+                continue;
+            }
+            Ok(Claim::Done) => break,
+            Err(e) if last_contact.elapsed() < COORDINATOR_PATIENCE => {
+                println!("Coordinator not reachable, retrying: {:#}", e);
+                sleep_unless_shutdown(processor, POLL_INTERVAL).await;
+                continue;
+            }
+            Err(e) => return Err(e.context(format!("Could not reach {}", queue.url))),
+        };
+        last_contact = Instant::now();
+
+        let (items, error) = generate_task(processor, output_dir, &task).await?;
+        if shutdown.is_requested() {
+            // Left unfinished; the lease runs out and another worker takes it
+            break;
+        }
+        let result = TaskResult {
+            id: task.id,
+            worker: worker.to_string(),
+            model: model.to_string(),
+            items,
+            error,
+        };
+        queue.submit(&result).await?;
+        done += 1;
+    }
+    Ok(done)
+}
+
+/// Write a claimed file into `output_dir` and run it through the pipeline
+async fn generate_task(
+    processor: &DefaultOllamaProcessor,
+    output_dir: &Path,
+    task: &Task,
+) -> Result<(Vec<ProcessedItem>, Option<String>)> {
+    let path = output_dir.join(task_path(&task.path)?);
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    tokio::fs::write(&path, &task.content).await?;
+
+    let (file, received) = mpsc::channel(1);
+    file.send(path).await?;
+    drop(file);
+    let mut items = Vec::new();
+    let mut error = None;
+    let mut sink = |generated: &[ProcessedItem]| {
+        items.extend_from_slice(generated);
+        Ok(())
+    };
+    let mut on_file = |path: &Path, result: Result<usize>| {
+        if let Err(e) = result {
+            eprintln!("Error processing file {:?}: {:#}", path, e);
+            error.get_or_insert(format!("{:#}", e));
+        }
+    };
+    Pipeline::new(processor)
+        .run(received, &mut sink, &mut on_file)
+        .await;
+    // A file counts as failed only when it produced nothing
+    let error = error.filter(|_| items.is_empty());
+    Ok((items, error))
+}
+
+/// A task's path, refusing any that would write outside the output directory
+fn task_path(path: &str) -> Result<PathBuf> {
+    let relative = Path::new(path);
+    if relative.as_os_str().is_empty()
+        || !relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return Err(anyhow!(
+            "Refusing task path {:?} outside the output directory",
+            path
+        ));
+    }
+    Ok(relative.to_path_buf())
+}
+
+async fn sleep_unless_shutdown(processor: &DefaultOllamaProcessor, duration: Duration) {
+    tokio::select! {
+        _ = tokio::time::sleep(duration) => {}
+        _ = processor.shutdown().requested() => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(id: u64) -> TaskResult {
+        TaskResult {
+            id,
+            worker: "gpu-1".to_string(),
+            model: "qwen".to_string(),
+            items: Vec::new(),
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_work_queue_leases() {
+        let (queue, mut results) = WorkQueue::new(Duration::ZERO);
+        queue.push("a.md".to_string(), "# A".to_string());
+        queue.push("b.md".to_string(), "# B".to_string());
+        queue.close();
+
+        let Claim::Task(a) = queue.claim() else {
+            panic!("expected a task");
+        };
+        assert_eq!(a.path, "a.md");
+        // With a zero lease, a.md is queued again behind b.md
+        let Claim::Task(b) = queue.claim() else {
+            panic!("expected a task");
+        };
+        assert_eq!(b.path, "b.md");
+        assert!(queue.complete(result(a.id)));
+        assert!(!queue.complete(result(a.id)), "a.md reported twice");
+        assert_eq!(queue.remaining(), 1);
+        assert!(queue.complete(result(b.id)));
+        assert_eq!(queue.claim(), Claim::Done);
+
+        assert_eq!(results.try_recv().unwrap().path, "a.md");
+        assert_eq!(results.try_recv().unwrap().path, "b.md");
+        assert!(matches!(
+            results.try_recv(),
+            Err(mpsc::error::TryRecvError::Disconnected)
+        ));
+    }
+
+    #[test]
+    fn test_work_queue_waits_for_claimed_files() {
+        let (queue, _results) = WorkQueue::new(DEFAULT_LEASE);
+        assert_eq!(queue.claim(), Claim::Wait);
+        queue.push("a.md".to_string(), String::new());
+        queue.close();
+        let Claim::Task(a) = queue.claim() else {
+            panic!("expected a task");
+        };
+        assert_eq!(queue.claim(), Claim::Wait);
+        queue.complete(result(a.id));
+        assert_eq!(queue.claim(), Claim::Done);
+    }
+
+    #[test]
+    fn test_task_path() {
+        assert_eq!(
+            task_path("docs/guide.md").unwrap(),
+            PathBuf::from("docs/guide.md")
+        );
+        assert!(task_path("../guide.md").is_err());
+        assert!(task_path("/etc/passwd").is_err());
+        assert!(task_path("").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_queue_over_http() {
+        let (queue, _results) = WorkQueue::new(DEFAULT_LEASE);
+        let queue = queue.with_token(Some("secret".to_string()));
+        queue.push("a.md".to_string(), "# A".to_string());
+        queue.close();
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let (addr, server) = queue
+            .serve("127.0.0.1:0".parse().unwrap(), async {
+                let _ = stopped.await;
+            })
+            .unwrap();
+        let server = tokio::spawn(server);
+        let url = format!("http://{}", addr);
+
+        assert!(QueueClient::new(&url, None).claim().await.is_err());
+        let client = QueueClient::new(&url, Some("secret".to_string()));
+        let Claim::Task(task) = client.claim().await.unwrap() else {
+            panic!("expected a task");
+        };
+        assert_eq!(task.content, "# A");
+        assert_eq!(client.claim().await.unwrap(), Claim::Wait);
+        client.submit(&result(task.id)).await.unwrap();
+        assert_eq!(client.claim().await.unwrap(), Claim::Done);
+
+        stop.send(()).unwrap();
+        server.await.unwrap().unwrap();
+    }
+}
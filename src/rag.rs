@@ -0,0 +1,345 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::density::{DocumentSize, QuestionDensity};
+use crate::external::{Embedder, SearchFilter, SearchHit};
+use crate::graph::{indexer::is_indexable, node::NodeType, DocumentGraph, VectorStore};
+use crate::lineage::Lineage;
+use crate::processor::{DefaultOllamaProcessor, OllamaClient, ProcessedItem};
+
+/// Separator placed between retrieved passages in a record's context
+pub const CONTEXT_SEPARATOR: &str = "\n\n";
+
+/// Settings for retrieval-augmented dataset generation
+#[derive(Debug, Clone)]
+pub struct RagConfig {
+    /// Number of passages retrieved for each question
+    pub top_k: u64,
+    /// Always include the passage the question was generated from
+    pub include_source: bool,
+    /// Chunks with fewer words are not used to generate questions
+    pub min_words: usize,
+    /// How many questions each chunk is worth
+    pub density: QuestionDensity,
+    /// Hard-negative passages sampled for each question's retrieval record;
+    /// none are sampled when 0
+    pub negatives: usize,
+}
+
+impl Default for RagConfig {
+    fn default() -> Self {
+        Self {
+            top_k: 3,
+            include_source: true,
+            min_words: 20,
+            density: QuestionDensity::default(),
+            negatives: 0,
+        }
+    }
+}
+
+/// A question with the passage it was generated from and passages that are
+/// similar to it but don't answer it, for training retrievers and rerankers
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RetrievalRecord {
+    pub question: String,
+    pub positive: String,
+    pub negatives: Vec<String>,
+}
+
+/// Generates question/context/answer records from an indexed graph
+///
+/// Questions are generated from each content chunk, then the question is
+/// embedded and the vector store is queried; the retrieved passages become
+/// the record's context. The graph is expected to be indexed into `store`
+/// already (see `GraphIndexer`).
+pub struct RagGenerator<'a> {
+    client: &'a dyn OllamaClient,
+    engine: &'a dyn Embedder,
+    store: &'a VectorStore,
+    config: RagConfig,
+}
+
+impl<'a> RagGenerator<'a> {
+    pub fn new(
+        client: &'a dyn OllamaClient,
+        engine: &'a dyn Embedder,
+        store: &'a VectorStore,
+        config: RagConfig,
+    ) -> Self {
+        Self {
+            client,
+            engine,
+            store,
+            config,
+        }
+    }
+
+    /// Generate RAG records for every sufficiently long chunk of the graph
+    pub async fn generate(&self, graph: &DocumentGraph) -> Result<Vec<ProcessedItem>> {
+        Ok(self.generate_with_negatives(graph).await?.0)
+    }
+
+    /// Generate RAG records, and a retrieval record with hard negatives for
+    /// each question that has any (`negatives` in the config)
+    ///
+    /// Hard negatives are the passages ranked highest for the question
+    /// other than its own, so they are retrieved with more candidates than
+    /// the context needs.
+    pub async fn generate_with_negatives(
+        &self,
+        graph: &DocumentGraph,
+    ) -> Result<(Vec<ProcessedItem>, Vec<RetrievalRecord>)> {
+        let mut items = Vec::new();
+        let mut retrieval = Vec::new();
+        let top_k = self.config.top_k as usize;
+        let limit = match self.config.negatives {
+            0 => top_k,
+            negatives => top_k.max(negatives * NEGATIVE_POOL + 1),
+        };
+
+        for chunk in rag_chunks(graph, self.config.min_words) {
+            let size = DocumentSize::of_section(&chunk.content);
+            let (target, _, _) = self.config.density.targets(&size);
+
+            let questions = match self.client.generate_questions(&chunk.content, target).await {
+                Ok(questions) => questions,
+                Err(e) => {
+                    println!("Skipping chunk after generation error: {}", e);
+                    continue;
+                }
+            };
+
+            let source = graph
+                .document_of(&chunk.id)?
+                .and_then(|document| document.attribute("path"))
+                .unwrap_or_default()
+                .to_string();
+            let span = chunk.content_hash();
+            let lineage = Lineage {
+                document: source.clone(),
+                span: chunk
+                    .metadata
+                    .span
+                    .map(|span| span.start_byte..span.end_byte)
+                    .unwrap_or_default(),
+                nodes: vec![chunk.id],
+            };
+            for mut item in questions {
+                item.assign_id(&source, &span);
+                item.lineage = Some(lineage.clone());
+                let embedding = self.engine.embed(&item.question).await?;
+                let hits = self
+                    .store
+                    .search_hits(&embedding, limit as u64, &SearchFilter::default())
+                    .await?;
+                let source = self.config.include_source.then_some(&*chunk.content);
+                item.context = Some(build_context(source, &hits[..top_k.min(hits.len())]));
+                if self.config.negatives > 0 {
+                    let negatives = hard_negatives(
+                        &chunk.id.to_string(),
+                        &chunk.content,
+                        &item.answer,
+                        &hits,
+                        self.config.negatives,
+                        self.config.min_words,
+                    );
+                    if !negatives.is_empty() {
+                        retrieval.push(RetrievalRecord {
+                            question: item.question.clone(),
+                            positive: chunk.content.trim().to_string(),
+                            negatives,
+                        });
+                    }
+                }
+                items.push(item);
+            }
+        }
+
+        Ok((items, retrieval))
+    }
+}
+
+/// Content nodes long enough to generate questions from, in reading order
+///
+/// Headings are skipped since they carry too little text on their own.
+fn rag_chunks(graph: &DocumentGraph, min_words: usize) -> Vec<&crate::graph::DocumentNode> {
+    graph
+        .reading_order()
+        .filter(|node| is_indexable(node))
+        .filter(|node| !matches!(node.node_type, NodeType::Section | NodeType::Subsection))
+        .filter(|node| DefaultOllamaProcessor::count_words(&node.content) >= min_words)
+        .collect()
+}
+
+/// Candidates retrieved per hard negative asked for, so enough are left
+/// once the positive and likely false negatives are dropped
+const NEGATIVE_POOL: usize = 3;
+
+/// Words an answer needs before a passage containing it is taken to answer
+/// the question too; shorter answers are found in too many passages
+const MIN_ANSWER_WORDS: usize = 3;
+
+/// Lowercased words of a text, for telling whether a passage contains an
+/// answer
+fn words(text: &str) -> Vec<String> {
+    text.split_whitespace()
+        .map(|word| {
+            word.trim_matches(|c: char| !c.is_alphanumeric())
+                .to_lowercase()
+        })
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+/// Up to `count` hard negatives for a question from its retrieved passages,
+/// best ranked first
+///
+/// The positive passage (by node ID or content) is not a negative, nor are
+/// passages containing the question's answer word for word, which likely
+/// answer it too, nor passages shorter than `min_words`, such as headings,
+/// which are too easy to tell apart. Duplicate passages are dropped.
+pub fn hard_negatives(
+    positive_id: &str,
+    positive: &str,
+    answer: &str,
+    hits: &[SearchHit],
+    count: usize,
+    min_words: usize,
+) -> Vec<String> {
+    let positive = positive.trim();
+    let answer = words(answer);
+    let answers = |content: &str| {
+        answer.len() >= MIN_ANSWER_WORDS
+            && words(content)
+                .windows(answer.len())
+                .any(|window| window == answer.as_slice())
+    };
+    let mut negatives: Vec<String> = Vec::new();
+    for hit in hits {
+        if negatives.len() == count {
+            break;
+        }
+        let Some(content) = hit.payload.get("content").map(|c| c.trim()) else {
+            continue;
+        };
+        let is_positive = hit.id == positive_id
+            || hit.payload.get("node_id").map(String::as_str) == Some(positive_id)
+            || content == positive;
+        if is_positive
+            || DefaultOllamaProcessor::count_words(content) < min_words
+            || answers(content)
+            || negatives.iter().any(|negative| negative == content)
+        {
+            continue;
+        }
+        negatives.push(content.to_string());
+    }
+    negatives
+}
+
+/// Join retrieved passages into a context, optionally ensuring the source passage is present
+///
+/// The source passage comes first when it was not retrieved; duplicate
+/// passages are dropped.
+pub fn build_context(source: Option<&str>, hits: &[SearchHit]) -> String {
+    let mut passages: Vec<&str> = Vec::new();
+    let retrieved = hits
+        .iter()
+        .filter_map(|hit| hit.payload.get("content"))
+        .map(|content| content.trim())
+        .filter(|content| !content.is_empty());
+
+    if let Some(source) = source.map(str::trim) {
+        if !hits
+            .iter()
+            .any(|hit| hit.payload.get("content").map(|c| c.trim()) == Some(source))
+        {
+            passages.push(source);
+        }
+    }
+    for passage in retrieved {
+        if !passages.contains(&passage) {
+            passages.push(passage);
+        }
+    }
+
+    passages.join(CONTEXT_SEPARATOR)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_markdown;
+
+    fn hit(content: &str) -> SearchHit {
+        SearchHit {
+            id: "0".to_string(),
+            score: 0.9,
+            payload: [("content".to_string(), content.to_string())]
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_build_context() {
+        let hits = [
+            hit("TLS is enabled by default."),
+            hit("Certificates live in /etc."),
+        ];
+
+        assert_eq!(
+            build_context(None, &hits),
+            "TLS is enabled by default.\n\nCertificates live in /etc."
+        );
+        // Source not retrieved: prepended
+        assert_eq!(
+            build_context(Some("Set tls = true."), &hits[..1]),
+            "Set tls = true.\n\nTLS is enabled by default."
+        );
+        // Source retrieved: kept in retrieval order, not duplicated
+        assert_eq!(
+            build_context(Some("Certificates live in /etc."), &hits),
+            "TLS is enabled by default.\n\nCertificates live in /etc."
+        );
+    }
+
+    #[test]
+    fn test_hard_negatives() {
+        let positive = "Set tls = true in config.toml to enable TLS.";
+        let mut own = hit(positive);
+        own.id = "positive".to_string();
+        let hits = [
+            own,
+            hit("TLS certificates are read from the certs directory at startup."),
+            hit("TLS"),
+            hit("To turn it on, set tls = true in config.toml and restart."),
+            hit("TLS certificates are read from the certs directory at startup."),
+            hit("Plain HTTP is served on port 8080 unless configured otherwise."),
+            hit("Logs are rotated daily and kept for a week by default."),
+        ];
+        let negatives = hard_negatives("positive", positive, "Set tls = true.", &hits, 2, 3);
+        assert_eq!(
+            negatives,
+            [
+                "TLS certificates are read from the certs directory at startup.",
+                "Plain HTTP is served on port 8080 unless configured otherwise."
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rag_chunks() {
+        let graph = parse_markdown(
+            "# A long heading with quite a few words in it\n\nShort text.\n\nThis paragraph has enough words to be used.\n",
+        )
+        .unwrap();
+
+        let chunks: Vec<&str> = rag_chunks(&graph, 5)
+            .into_iter()
+            .map(|node| &*node.content)
+            .collect();
+        assert_eq!(chunks, ["This paragraph has enough words to be used."]);
+    }
+}
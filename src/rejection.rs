@@ -0,0 +1,145 @@
+//! Rejection sampling: several candidate answers are generated for each
+//! question, scored, and only the best is kept. The others can be kept on
+//! the record as rejected answers for preference (DPO-style) training data.
+
+use anyhow::{anyhow, Result};
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::processor::DefaultOllamaProcessor;
+
+/// How candidate answers are scored (`--candidate-scoring`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CandidateScoring {
+    /// Grounding in the section and a sensible length, without model calls
+    #[default]
+    Heuristic,
+    /// The overall score of an LLM judge, one request per candidate
+    Judge,
+}
+
+impl CandidateScoring {
+    pub const ALL: [CandidateScoring; 2] = [CandidateScoring::Heuristic, CandidateScoring::Judge];
+
+    /// Name used on the command line
+    pub fn name(&self) -> &'static str {
+        match self {
+            CandidateScoring::Heuristic => "heuristic",
+            CandidateScoring::Judge => "judge",
+        }
+    }
+}
+
+impl fmt::Display for CandidateScoring {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for CandidateScoring {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "heuristic" => Ok(CandidateScoring::Heuristic),
+            "judge" => Ok(CandidateScoring::Judge),
+            other => Err(anyhow!(
+                "Unknown candidate scoring '{}' (expected heuristic or judge)",
+                other
+            )),
+        }
+    }
+}
+
+/// Settings of rejection sampling (`--candidates`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RejectionSampling {
+    /// Candidate answers per question, the generated answer included
+    pub candidates: usize,
+    pub scoring: CandidateScoring,
+    /// Keep the answers that lost on the record as `rejected`
+    pub keep_rejected: bool,
+}
+
+/// Answers shorter than this many words score lower
+const MIN_ANSWER_WORDS: usize = 5;
+
+/// Answers longer than this many words score lower
+const MAX_ANSWER_WORDS: usize = 200;
+
+/// Score of an answer from 0 to 1 without asking a model
+///
+/// The share of the answer's words (of four or more letters) that occur in
+/// the section it should be grounded in, scaled down for answers too short
+/// to be useful or long enough to ramble.
+pub fn heuristic_score(section: &str, answer: &str) -> f64 {
+    let normalize = |word: &str| {
+        word.trim_matches(|c: char| !c.is_alphanumeric())
+            .to_lowercase()
+    };
+    let source: HashSet<String> = section.split_whitespace().map(normalize).collect();
+    let words: Vec<String> = answer
+        .split_whitespace()
+        .map(normalize)
+        .filter(|word| word.chars().count() >= 4)
+        .collect();
+    if words.is_empty() {
+        return 0.0;
+    }
+    let grounded = words.iter().filter(|word| source.contains(*word)).count() as f64;
+    let grounding = grounded / words.len() as f64;
+
+    let length = DefaultOllamaProcessor::count_words(answer);
+    let length_factor = if length < MIN_ANSWER_WORDS {
+        length as f64 / MIN_ANSWER_WORDS as f64
+    } else if length > MAX_ANSWER_WORDS {
+        MAX_ANSWER_WORDS as f64 / length as f64
+    } else {
+        1.0
+    };
+    grounding * length_factor
+}
+
+/// Order scored candidates best first; ties keep the earlier candidate first
+pub fn rank(mut scored: Vec<(f64, String)>) -> Vec<String> {
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    let mut seen = HashSet::new();
+    scored
+        .into_iter()
+        .map(|(_, answer)| answer)
+        .filter(|answer| seen.insert(answer.trim().to_string()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECTION: &str =
+        "Enable TLS by setting use_tls to true in config.toml and restarting the server.";
+
+    #[test]
+    fn test_heuristic_prefers_grounded_answers() {
+        let grounded = "Set use_tls to true in config.toml, then restart the server.";
+        let invented = "Install the certificate bundle through the admin dashboard wizard.";
+        assert!(heuristic_score(SECTION, grounded) > heuristic_score(SECTION, invented));
+        assert!(heuristic_score(SECTION, "Restart.") < heuristic_score(SECTION, grounded));
+        assert_eq!(heuristic_score(SECTION, ""), 0.0);
+    }
+
+    #[test]
+    fn test_rank_orders_and_drops_repeats() {
+        let ranked = rank(vec![
+            (0.2, "B".to_string()),
+            (0.9, "A".to_string()),
+            (0.2, "C".to_string()),
+            (0.5, " A ".to_string()),
+        ]);
+        assert_eq!(ranked, ["A", "B", "C"]);
+
+        for scoring in CandidateScoring::ALL {
+            assert_eq!(scoring.name().parse::<CandidateScoring>().unwrap(), scoring);
+        }
+    }
+}
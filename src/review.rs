@@ -0,0 +1,440 @@
+//! Human review of generated records in the terminal: each record is
+//! accepted, edited or rejected with a keystroke, and the decision is
+//! written back into the dataset as the record's `review_status`.
+//!
+//! Decisions are appended to `NAME.review.jsonl` next to the dataset as they
+//! are made, so a session that is interrupted loses nothing: the next review
+//! of the same file replays them. Quitting writes the decisions into the
+//! dataset and removes the log; records already reviewed are skipped when
+//! the review is picked up again.
+
+use anyhow::{anyhow, Context, Result};
+use console::{style, Key, Term};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use uuid::Uuid;
+
+use crate::processor::ProcessedItem;
+use crate::schema::{self, DatasetWriter};
+
+/// A reviewer's verdict on a record
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReviewStatus {
+    /// Kept as generated
+    Accepted,
+    /// Kept with the question or answer corrected by the reviewer
+    Edited,
+    /// Not to be trained on; left in the dataset, but not exported
+    Rejected,
+}
+
+impl ReviewStatus {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ReviewStatus::Accepted => "accepted",
+            ReviewStatus::Edited => "edited",
+            ReviewStatus::Rejected => "rejected",
+        }
+    }
+}
+
+impl fmt::Display for ReviewStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Whether a record may be trained on: it was not rejected in review
+pub fn is_kept(item: &ProcessedItem) -> bool {
+    item.review_status != Some(ReviewStatus::Rejected)
+}
+
+/// One decision of a session, as logged to `NAME.review.jsonl`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Decision {
+    /// Position of the record in the dataset
+    pub index: usize,
+    /// ID of the record, to notice a dataset that changed under the log
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<Uuid>,
+    pub status: ReviewStatus,
+    /// The corrected question and answer of an edited record
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub question: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub answer: Option<String>,
+}
+
+/// Where the decisions of an unfinished review of `path` are logged:
+/// `NAME.review.jsonl`
+pub fn session_path(path: &Path) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("dataset");
+    path.with_file_name(format!("{}.review.jsonl", stem))
+}
+
+/// Review counts of a dataset
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReviewSummary {
+    pub accepted: usize,
+    pub edited: usize,
+    pub rejected: usize,
+    /// Records not reviewed yet
+    pub remaining: usize,
+    /// Whether the reviewer quit before reaching the last record
+    pub stopped: bool,
+}
+
+impl ReviewSummary {
+    fn of(items: &[ProcessedItem]) -> Self {
+        let mut summary = Self::default();
+        for item in items {
+            match item.review_status {
+                Some(ReviewStatus::Accepted) => summary.accepted += 1,
+                Some(ReviewStatus::Edited) => summary.edited += 1,
+                Some(ReviewStatus::Rejected) => summary.rejected += 1,
+                None => summary.remaining += 1,
+            }
+        }
+        summary
+    }
+}
+
+impl fmt::Display for ReviewSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} accepted, {} edited, {} rejected, {} left to review",
+            self.accepted, self.edited, self.rejected, self.remaining
+        )
+    }
+}
+
+/// A dataset under review, with the log of decisions not yet written back
+pub struct ReviewSession {
+    path: PathBuf,
+    items: Vec<ProcessedItem>,
+    log: fs::File,
+}
+
+impl ReviewSession {
+    /// Open `path` for review, replaying the decisions of an interrupted
+    /// session
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut items = schema::read_dataset(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?
+            .items;
+        let log_path = session_path(path);
+        if log_path.exists() {
+            for line in BufReader::new(fs::File::open(&log_path)?).lines() {
+                let line = line?;
+                // A line cut short by the interruption is the one decision lost
+                let Ok(decision) = serde_json::from_str::<Decision>(&line) else {
+                    continue;
+                };
+                apply(&mut items, decision).with_context(|| {
+                    format!(
+                        "{} changed since its review began; delete {} to start over",
+                        path.display(),
+                        log_path.display()
+                    )
+                })?;
+            }
+        }
+        let log = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&log_path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            items,
+            log,
+        })
+    }
+
+    pub fn items(&self) -> &[ProcessedItem] {
+        &self.items
+    }
+
+    /// Positions of the records not reviewed yet, in dataset order
+    pub fn pending(&self) -> Vec<usize> {
+        (0..self.items.len())
+            .filter(|&index| self.items[index].review_status.is_none())
+            .collect()
+    }
+
+    /// Record a decision on the record at `index`
+    pub fn decide(
+        &mut self,
+        index: usize,
+        status: ReviewStatus,
+        edit: Option<(String, String)>,
+    ) -> Result<()> {
+        let (question, answer) = edit.unzip();
+        let decision = Decision {
+            index,
+            id: self.items.get(index).and_then(|item| item.id),
+            status,
+            question,
+            answer,
+        };
+        writeln!(self.log, "{}", serde_json::to_string(&decision)?)?;
+        self.log.sync_data()?;
+        apply(&mut self.items, decision)
+    }
+
+    /// Write the decisions into the dataset and remove the log
+    pub fn finish(self) -> Result<ReviewSummary> {
+        let mut writer = DatasetWriter::create(&self.path)?;
+        writer.append(&self.items)?;
+        writer.finish()?;
+        fs::remove_file(session_path(&self.path))?;
+        Ok(ReviewSummary::of(&self.items))
+    }
+}
+
+fn apply(items: &mut [ProcessedItem], decision: Decision) -> Result<()> {
+    let item = items
+        .get_mut(decision.index)
+        .filter(|item| item.id == decision.id)
+        .ok_or_else(|| anyhow!("No record {} to review", decision.index + 1))?;
+    item.review_status = Some(decision.status);
+    if let Some(question) = decision.question {
+        item.question = question;
+    }
+    if let Some(answer) = decision.answer {
+        item.answer = answer;
+    }
+    Ok(())
+}
+
+/// Line separating the question from the answer in the editor
+const EDIT_SEPARATOR: &str = "---";
+
+/// Review the records of `path` not reviewed yet, one at a time
+pub fn review_file(path: &Path, term: &Term) -> Result<ReviewSummary> {
+    let mut session = ReviewSession::open(path)?;
+    let pending = session.pending();
+    for (position, &index) in pending.iter().enumerate() {
+        show(
+            term,
+            path,
+            &session.items()[index],
+            index,
+            pending.len() - position,
+        )?;
+        loop {
+            let key = match term.read_key() {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => Key::CtrlC,
+                key => key?,
+            };
+            match key {
+                Key::Char('a') => session.decide(index, ReviewStatus::Accepted, None)?,
+                Key::Char('r') => session.decide(index, ReviewStatus::Rejected, None)?,
+                Key::Char('e') => match edit(&session.items()[index])? {
+                    Some(edit) => session.decide(index, ReviewStatus::Edited, Some(edit))?,
+                    None => {
+                        term.write_line("Unchanged; choose again.")?;
+                        continue;
+                    }
+                },
+                Key::Char('s') => {}
+                Key::Char('q') | Key::Escape | Key::CtrlC => {
+                    let mut summary = session.finish()?;
+                    summary.stopped = true;
+                    return Ok(summary);
+                }
+                _ => continue,
+            }
+            break;
+        }
+    }
+    session.finish()
+}
+
+fn show(
+    term: &Term,
+    path: &Path,
+    item: &ProcessedItem,
+    index: usize,
+    remaining: usize,
+) -> Result<()> {
+    term.clear_screen()?;
+    let mut header = format!(
+        "{} record {} ({} left)",
+        path.display(),
+        index + 1,
+        remaining
+    );
+    if let Some(lineage) = &item.lineage {
+        header.push_str(&format!(" from {}", lineage.document));
+    }
+    if let Some(difficulty) = item.difficulty {
+        header.push_str(&format!(", {}", difficulty));
+    }
+    term.write_line(&style(header).dim().to_string())?;
+    if let Some(context) = &item.context {
+        term.write_line(&format!("\n{}\n{}", style("Context").bold(), context))?;
+    }
+    term.write_line(&format!(
+        "\n{}\n{}",
+        style("Question").bold(),
+        item.question
+    ))?;
+    term.write_line(&format!("\n{}\n{}\n", style("Answer").bold(), item.answer))?;
+    term.write_line(&format!(
+        "{}ccept  {}dit  {}eject  {}kip  {}uit",
+        style("[a]").cyan(),
+        style("[e]").cyan(),
+        style("[r]").cyan(),
+        style("[s]").cyan(),
+        style("[q]").cyan()
+    ))?;
+    Ok(())
+}
+
+/// Let the reviewer correct a record in `$VISUAL` or `$EDITOR`, returning
+/// the new question and answer, or None if nothing was changed
+fn edit(item: &ProcessedItem) -> Result<Option<(String, String)>> {
+    let path = std::env::temp_dir().join(format!("review-{}.md", process::id()));
+    fs::write(&path, edit_text(item))?;
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or("vi");
+    let status = process::Command::new(program)
+        .args(words)
+        .arg(&path)
+        .status()
+        .with_context(|| format!("Could not start the editor '{}'", editor));
+    let edited = fs::read_to_string(&path);
+    let _ = fs::remove_file(&path);
+    if !status?.success() {
+        return Ok(None);
+    }
+    parse_edit(&edited?, item)
+}
+
+fn edit_text(item: &ProcessedItem) -> String {
+    format!(
+        "{}\n{}\n{}\n",
+        item.question.trim(),
+        EDIT_SEPARATOR,
+        item.answer.trim()
+    )
+}
+
+/// The question and answer of an edited file, or None if it is unchanged
+fn parse_edit(text: &str, item: &ProcessedItem) -> Result<Option<(String, String)>> {
+    let mut question = Vec::new();
+    let mut lines = text.lines();
+    for line in lines.by_ref() {
+        if line.trim() == EDIT_SEPARATOR {
+            break;
+        }
+        question.push(line);
+    }
+    let question = question.join("\n").trim().to_string();
+    let answer = lines.collect::<Vec<_>>().join("\n").trim().to_string();
+    if question.is_empty() || answer.is_empty() {
+        return Err(anyhow!(
+            "An edited record needs a question, a '{}' line and an answer",
+            EDIT_SEPARATOR
+        ));
+    }
+    if question == item.question.trim() && answer == item.answer.trim() {
+        return Ok(None);
+    }
+    Ok(Some((question, answer)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn item(question: &str) -> ProcessedItem {
+        let mut item = ProcessedItem::new(question, "An answer.");
+        item.assign_id("guide.md", "span");
+        item
+    }
+
+    #[test]
+    fn test_review_session_resumes() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("all_qa.jsonl");
+        schema::write_dataset(&path, &[item("One?"), item("Two?"), item("Three?")]).unwrap();
+
+        let mut session = ReviewSession::open(&path).unwrap();
+        assert_eq!(session.pending(), [0, 1, 2]);
+        session.decide(0, ReviewStatus::Accepted, None).unwrap();
+        let edit = Some(("Second?".to_string(), "Fixed.".to_string()));
+        session.decide(1, ReviewStatus::Edited, edit).unwrap();
+        // Interrupted before finishing: the log is replayed
+        drop(session);
+        let session = ReviewSession::open(&path).unwrap();
+        assert_eq!(session.pending(), [2]);
+        assert_eq!(session.items()[1].question, "Second?");
+
+        let summary = session.finish().unwrap();
+        assert_eq!(
+            (summary.accepted, summary.edited, summary.remaining),
+            (1, 1, 1)
+        );
+        assert!(!session_path(&path).exists());
+        let items = schema::read_dataset(&path).unwrap().items;
+        assert_eq!(items[1].review_status, Some(ReviewStatus::Edited));
+        assert_eq!(items[1].answer, "Fixed.");
+        assert_eq!(items[2].review_status, None);
+
+        let mut session = ReviewSession::open(&path).unwrap();
+        assert_eq!(session.pending(), [2]);
+        session.decide(2, ReviewStatus::Rejected, None).unwrap();
+        let summary = session.finish().unwrap();
+        assert_eq!(
+            summary.to_string(),
+            "1 accepted, 1 edited, 1 rejected, 0 left to review"
+        );
+        assert!(!is_kept(&schema::read_dataset(&path).unwrap().items[2]));
+    }
+
+    #[test]
+    fn test_review_log_must_match_the_dataset() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("all_qa.jsonl");
+        schema::write_dataset(&path, &[item("One?")]).unwrap();
+        let mut session = ReviewSession::open(&path).unwrap();
+        session.decide(0, ReviewStatus::Accepted, None).unwrap();
+        drop(session);
+
+        schema::write_dataset(&path, &[item("Another?")]).unwrap();
+        let error = ReviewSession::open(&path).err().unwrap();
+        assert!(error.to_string().contains("changed since its review began"));
+    }
+
+    #[test]
+    fn test_parse_edit() {
+        let original = item("What is it?");
+        assert_eq!(parse_edit(&edit_text(&original), &original).unwrap(), None);
+        assert_eq!(
+            parse_edit(
+                "What is it exactly?\n---\nA tool.\n\n---\nMore.\n",
+                &original
+            )
+            .unwrap(),
+            Some((
+                "What is it exactly?".to_string(),
+                "A tool.\n\n---\nMore.".to_string()
+            ))
+        );
+        assert!(parse_edit("No separator\n", &original).is_err());
+    }
+}
@@ -0,0 +1,562 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fs;
+use std::io::{BufRead, BufReader, BufWriter, Lines, Write};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+use crate::processor::ProcessedItem;
+
+/// Identifier written into every dataset header record
+pub const SCHEMA_NAME: &str = "llm_dataset_builder.processed_item";
+/// Current version of the record schema (semver, major bumps are breaking)
+pub const SCHEMA_VERSION: &str = "1.10.0";
+/// Version assumed for files written before headers were introduced
+pub const LEGACY_SCHEMA_VERSION: &str = "1.0.0";
+
+#[derive(Error, Debug)]
+pub enum SchemaError {
+    #[error("Invalid record: {0}")]
+    InvalidRecord(String),
+
+    #[error("Incompatible schema versions: {expected} and {found}")]
+    IncompatibleVersion { expected: String, found: String },
+
+    #[error("Invalid schema version: {0}")]
+    InvalidVersion(String),
+}
+
+/// Header record written as the first line of every JSONL dataset file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SchemaHeader {
+    pub schema: String,
+    pub schema_version: String,
+}
+
+impl Default for SchemaHeader {
+    fn default() -> Self {
+        Self {
+            schema: SCHEMA_NAME.to_string(),
+            schema_version: SCHEMA_VERSION.to_string(),
+        }
+    }
+}
+
+impl SchemaHeader {
+    /// Header assumed for files that have no header record
+    pub fn legacy() -> Self {
+        Self {
+            schema: SCHEMA_NAME.to_string(),
+            schema_version: LEGACY_SCHEMA_VERSION.to_string(),
+        }
+    }
+
+    /// Try to interpret a JSONL line as a header record
+    pub fn from_line(line: &str) -> Option<Self> {
+        serde_json::from_str::<Self>(line)
+            .ok()
+            .filter(|header| header.schema == SCHEMA_NAME)
+    }
+
+    /// Major component of the schema version
+    pub fn major_version(&self) -> Result<u64, SchemaError> {
+        self.schema_version
+            .split('.')
+            .next()
+            .and_then(|major| major.parse().ok())
+            .ok_or_else(|| SchemaError::InvalidVersion(self.schema_version.clone()))
+    }
+
+    /// Check that records under both headers can be mixed in one dataset
+    pub fn check_compatible(&self, other: &SchemaHeader) -> Result<(), SchemaError> {
+        if self.major_version()? != other.major_version()? {
+            return Err(SchemaError::IncompatibleVersion {
+                expected: self.schema_version.clone(),
+                found: other.schema_version.clone(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// JSON Schema describing a single dataset record
+pub fn json_schema() -> Value {
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "$id": format!("{}/{}", SCHEMA_NAME, SCHEMA_VERSION),
+        "title": "ProcessedItem",
+        "type": "object",
+        "required": ["question", "answer"],
+        "properties": {
+            "id": { "type": "string", "format": "uuid", "minLength": 1 },
+            "question": { "type": "string", "minLength": 1 },
+            "answer": { "type": "string", "minLength": 1 },
+            "context": { "type": "string", "minLength": 1 },
+            "lineage": { "type": "object" },
+            "rubric": { "type": "array" },
+            "rejected": { "type": "array" },
+            "difficulty": { "type": "string", "enum": ["easy", "medium", "hard"] },
+            "review_status": { "type": "string", "enum": ["accepted", "edited", "rejected"] },
+            "source": { "type": "string", "minLength": 1 },
+            "model": { "type": "string", "minLength": 1 },
+            "prompt_template": { "type": "string", "minLength": 1 },
+            "created_at": { "type": "string", "format": "date-time" },
+            "lang": { "type": "string", "minLength": 1 },
+            "tags": { "type": "array" },
+            "score": { "type": "number" },
+            "augmentation": { "type": "string", "minLength": 1 },
+            "augmented_from": { "type": "string", "format": "uuid", "minLength": 1 },
+            "noisy": { "type": "boolean" }
+        },
+        "additionalProperties": false
+    })
+}
+
+/// Validate a JSON value against the record schema
+pub fn validate_value(value: &Value) -> Result<(), SchemaError> {
+    let schema = json_schema();
+    let object = value
+        .as_object()
+        .ok_or_else(|| SchemaError::InvalidRecord("record is not an object".to_string()))?;
+
+    let properties = schema["properties"]
+        .as_object()
+        .cloned()
+        .unwrap_or_default();
+
+    if let Some(required) = schema["required"].as_array() {
+        for field in required.iter().filter_map(|f| f.as_str()) {
+            if !object.contains_key(field) {
+                return Err(SchemaError::InvalidRecord(format!(
+                    "missing required field '{}'",
+                    field
+                )));
+            }
+        }
+    }
+
+    for (key, field_value) in object {
+        let Some(rules) = properties.get(key) else {
+            if schema["additionalProperties"] == Value::Bool(false) {
+                return Err(SchemaError::InvalidRecord(format!(
+                    "unexpected field '{}'",
+                    key
+                )));
+            }
+            continue;
+        };
+        validate_field(key, field_value, rules)?;
+    }
+
+    Ok(())
+}
+
+fn validate_field(key: &str, value: &Value, rules: &Value) -> Result<(), SchemaError> {
+    let type_matches = match rules["type"].as_str() {
+        Some("string") => value.is_string(),
+        Some("number") => value.is_number(),
+        Some("integer") => value.is_i64() || value.is_u64(),
+        Some("boolean") => value.is_boolean(),
+        Some("array") => value.is_array(),
+        Some("object") => value.is_object(),
+        _ => true,
+    };
+    if !type_matches {
+        return Err(SchemaError::InvalidRecord(format!(
+            "field '{}' must be of type {}",
+            key, rules["type"]
+        )));
+    }
+
+    if let Some(allowed) = rules["enum"].as_array() {
+        if !allowed.contains(value) {
+            return Err(SchemaError::InvalidRecord(format!(
+                "field '{}' must be one of {}",
+                key, rules["enum"]
+            )));
+        }
+    }
+
+    if let (Some("uuid"), Some(text)) = (rules["format"].as_str(), value.as_str()) {
+        if uuid::Uuid::parse_str(text).is_err() {
+            return Err(SchemaError::InvalidRecord(format!(
+                "field '{}' must be a UUID",
+                key
+            )));
+        }
+    }
+
+    if let (Some("date-time"), Some(text)) = (rules["format"].as_str(), value.as_str()) {
+        if chrono::DateTime::parse_from_rfc3339(text).is_err() {
+            return Err(SchemaError::InvalidRecord(format!(
+                "field '{}' must be an RFC 3339 date and time",
+                key
+            )));
+        }
+    }
+
+    if let (Some(min), Some(text)) = (rules["minLength"].as_u64(), value.as_str()) {
+        if (text.trim().chars().count() as u64) < min {
+            return Err(SchemaError::InvalidRecord(format!(
+                "field '{}' must not be empty",
+                key
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Validate a record against the schema before it is written
+pub fn validate_item(item: &ProcessedItem) -> Result<(), SchemaError> {
+    let value =
+        serde_json::to_value(item).map_err(|e| SchemaError::InvalidRecord(e.to_string()))?;
+    validate_value(&value)
+}
+
+/// Write the header record for a new dataset file
+pub fn write_header<W: Write>(writer: &mut W) -> Result<()> {
+    writeln!(
+        writer,
+        "{}",
+        serde_json::to_string(&SchemaHeader::default())?
+    )?;
+    Ok(())
+}
+
+/// Write records that pass validation, returning how many were written
+pub fn write_items<W: Write>(writer: &mut W, items: &[ProcessedItem]) -> Result<usize> {
+    let mut written = 0;
+    for item in items {
+        if let Err(e) = validate_item(item) {
+            println!("Skipping record that failed schema validation: {}", e);
+            continue;
+        }
+        writeln!(writer, "{}", serde_json::to_string(item)?)?;
+        written += 1;
+    }
+    Ok(written)
+}
+
+/// Write a complete dataset file (header plus validated records)
+pub fn write_dataset(path: &Path, items: &[ProcessedItem]) -> Result<usize> {
+    let mut file = fs::File::create(path)?;
+    write_header(&mut file)?;
+    write_items(&mut file, items)
+}
+
+/// Records read back from a dataset file along with its header
+#[derive(Debug, Clone)]
+pub struct Dataset {
+    pub header: SchemaHeader,
+    pub items: Vec<ProcessedItem>,
+}
+
+/// Read a JSONL dataset file, tolerating legacy files without a header
+pub fn read_dataset(path: &Path) -> Result<Dataset> {
+    let reader = DatasetReader::open(path)?;
+    let header = reader.header.clone();
+    let items = reader.collect::<Result<_>>()?;
+    Ok(Dataset { header, items })
+}
+
+/// Reads the records of a JSONL dataset one line at a time
+///
+/// Like [`read_dataset`], lines that are not records are skipped.
+pub struct DatasetReader {
+    pub header: SchemaHeader,
+    lines: Lines<BufReader<fs::File>>,
+    /// First record line, read while looking for the header
+    pending: Option<String>,
+}
+
+impl DatasetReader {
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut lines = BufReader::new(fs::File::open(path)?).lines();
+        let mut first = None;
+        for line in lines.by_ref() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                first = Some(line);
+                break;
+            }
+        }
+        let (header, pending) = match first.as_deref().and_then(SchemaHeader::from_line) {
+            Some(header) => (header, None),
+            None => (SchemaHeader::legacy(), first),
+        };
+        Ok(Self {
+            header,
+            lines,
+            pending,
+        })
+    }
+}
+
+impl Iterator for DatasetReader {
+    type Item = Result<ProcessedItem>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.pending.take() {
+                Some(line) => line,
+                None => match self.lines.next()? {
+                    Ok(line) => line,
+                    Err(e) => return Some(Err(e.into())),
+                },
+            };
+            if let Ok(item) = serde_json::from_str::<ProcessedItem>(&line) {
+                return Some(Ok(item));
+            }
+        }
+    }
+}
+
+/// Writes a dataset file batch by batch
+///
+/// Records go to a temporary file next to the target, which replaces the
+/// target on [`DatasetWriter::finish`], so an existing dataset stays intact
+/// until the new one is complete. A writer dropped without finishing removes
+/// the temporary file.
+pub struct DatasetWriter {
+    path: PathBuf,
+    partial: PathBuf,
+    /// Taken once the file is finished
+    writer: Option<BufWriter<fs::File>>,
+    written: usize,
+}
+
+impl DatasetWriter {
+    pub fn create(path: &Path) -> Result<Self> {
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".partial");
+        let partial = PathBuf::from(partial);
+        let mut writer = BufWriter::new(fs::File::create(&partial)?);
+        write_header(&mut writer)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            partial,
+            writer: Some(writer),
+            written: 0,
+        })
+    }
+
+    /// Write the records that pass validation, returning how many were written
+    pub fn append(&mut self, items: &[ProcessedItem]) -> Result<usize> {
+        let writer = self
+            .writer
+            .as_mut()
+            .expect("writer is only taken by finish");
+        let written = write_items(writer, items)?;
+        self.written += written;
+        Ok(written)
+    }
+
+    /// Move the finished file into place, returning the number of records
+    pub fn finish(mut self) -> Result<usize> {
+        if let Some(mut writer) = self.writer.take() {
+            writer.flush()?;
+        }
+        fs::rename(&self.partial, &self.path)?;
+        Ok(self.written)
+    }
+}
+
+impl Drop for DatasetWriter {
+    fn drop(&mut self) {
+        if self.writer.take().is_some() {
+            let _ = fs::remove_file(&self.partial);
+        }
+    }
+}
+
+/// Merge several dataset files, refusing to mix incompatible schema versions
+pub fn merge_datasets(paths: &[PathBuf]) -> Result<Vec<ProcessedItem>> {
+    let current = SchemaHeader::default();
+    let mut merged = Vec::new();
+
+    for path in paths {
+        let dataset = read_dataset(path)?;
+        current.check_compatible(&dataset.header)?;
+        merged.extend(dataset.items);
+    }
+
+    Ok(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(question: &str, answer: &str) -> ProcessedItem {
+        ProcessedItem::new(question, answer)
+    }
+
+    #[test]
+    fn test_validate_item() {
+        assert!(validate_item(&item("What is Rust?", "A language.")).is_ok());
+        assert!(validate_item(&item("", "A language.")).is_err());
+        assert!(validate_item(&item("What is Rust?", "   ")).is_err());
+    }
+
+    #[test]
+    fn test_validate_item_with_context() {
+        let mut rag = item("What is Rust?", "A language.");
+        rag.context = Some("Rust is a systems programming language.".to_string());
+        assert!(validate_item(&rag).is_ok());
+
+        rag.context = Some(" ".to_string());
+        assert!(validate_item(&rag).is_err());
+    }
+
+    #[test]
+    fn test_validate_value_rejects_unknown_fields() {
+        let value = json!({"question": "Q", "answer": "A", "extra": 1});
+        assert!(validate_value(&value).is_err());
+
+        let value = json!({"question": "Q"});
+        assert!(validate_value(&value).is_err());
+
+        let id = "2ed6657d-e927-568b-95e1-2665a8aea6a2";
+        assert!(validate_value(&json!({"id": id, "question": "Q", "answer": "A"})).is_ok());
+        let value = json!({"id": "item-1", "question": "Q", "answer": "A"});
+        assert!(validate_value(&value).is_err());
+
+        let lineage = json!({"document": "guide.md", "span": {"start": 0, "end": 9}});
+        let value = json!({"question": "Q", "answer": "A", "lineage": lineage});
+        assert!(validate_value(&value).is_ok());
+        let value = json!({"question": "Q", "answer": "A", "lineage": "guide.md"});
+        assert!(validate_value(&value).is_err());
+
+        let value = json!({"question": "Q", "answer": "A", "rubric": ["Names the flag"]});
+        assert!(validate_value(&value).is_ok());
+        let value = json!({"question": "Q", "answer": "A", "rejected": ["Worse answer"]});
+        assert!(validate_value(&value).is_ok());
+        let value = json!({"question": "Q", "answer": "A", "difficulty": "hard"});
+        assert!(validate_value(&value).is_ok());
+        let value = json!({"question": "Q", "answer": "A", "difficulty": "trivial"});
+        assert!(validate_value(&value).is_err());
+    }
+
+    #[test]
+    fn test_rich_schema_columns() {
+        let mut rich = item("What is Rust?", "A language.");
+        rich.metadata.model = Some("llama3".to_string());
+        rich.metadata.created_at = Some(chrono::Utc::now());
+        rich.metadata.tags = Some(vec!["keyword:rust".to_string()]);
+        rich.metadata.score = Some(0.8);
+        assert!(validate_item(&rich).is_ok());
+
+        // The columns sit next to question and answer, and read back
+        let value = serde_json::to_value(&rich).unwrap();
+        assert_eq!(value["model"], "llama3");
+        assert!(value.get("lang").is_none());
+        let read: ProcessedItem = serde_json::from_value(value).unwrap();
+        assert_eq!(read.metadata, rich.metadata);
+
+        let plain = serde_json::to_value(item("What is Rust?", "A language.")).unwrap();
+        assert_eq!(plain.as_object().unwrap().len(), 2);
+
+        let value = json!({"question": "Q", "answer": "A", "created_at": "yesterday"});
+        assert!(validate_value(&value).is_err());
+        let value = json!({"question": "Q", "answer": "A", "score": "high"});
+        assert!(validate_value(&value).is_err());
+        let value = json!({"question": "Q", "answer": "A", "augmented_from": "original"});
+        assert!(validate_value(&value).is_err());
+        let value = json!({"question": "Q", "answer": "A", "noisy": "yes"});
+        assert!(validate_value(&value).is_err());
+    }
+
+    #[test]
+    fn test_header_compatibility() {
+        let current = SchemaHeader::default();
+        let minor = SchemaHeader {
+            schema_version: "1.9.0".to_string(),
+            ..SchemaHeader::default()
+        };
+        let major = SchemaHeader {
+            schema_version: "2.0.0".to_string(),
+            ..SchemaHeader::default()
+        };
+
+        assert!(current.check_compatible(&minor).is_ok());
+        assert!(current.check_compatible(&major).is_err());
+    }
+
+    #[test]
+    fn test_write_and_read_dataset() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("data.jsonl");
+
+        let written =
+            write_dataset(&path, &[item("Q1", "A1"), item("", "A2"), item("Q3", "A3")]).unwrap();
+        assert_eq!(written, 2);
+
+        let dataset = read_dataset(&path).unwrap();
+        assert_eq!(dataset.header, SchemaHeader::default());
+        assert_eq!(dataset.items.len(), 2);
+    }
+
+    #[test]
+    fn test_dataset_writer_replaces_file_when_finished() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("all_qa.jsonl");
+        write_dataset(&path, &[item("Old", "A")]).unwrap();
+
+        let mut writer = DatasetWriter::create(&path).unwrap();
+        assert_eq!(
+            writer.append(&[item("Q1", "A1"), item("", "A")]).unwrap(),
+            1
+        );
+        assert_eq!(writer.append(&[item("Q2", "A2")]).unwrap(), 1);
+        // Until finished, readers still see the previous dataset
+        assert_eq!(read_dataset(&path).unwrap().items[0].question, "Old");
+
+        assert_eq!(writer.finish().unwrap(), 2);
+        let questions: Vec<String> = DatasetReader::open(&path)
+            .unwrap()
+            .map(|item| item.unwrap().question)
+            .collect();
+        assert_eq!(questions, ["Q1", "Q2"]);
+        assert!(!temp_dir.path().join("all_qa.jsonl.partial").exists());
+    }
+
+    #[test]
+    fn test_dataset_reader_reads_legacy_files() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("legacy.jsonl");
+        fs::write(
+            &path,
+            "\n{\"question\":\"Q1\",\"answer\":\"A1\"}\nnot a record\n{\"question\":\"Q2\",\"answer\":\"A2\"}\n",
+        )
+        .unwrap();
+
+        let reader = DatasetReader::open(&path).unwrap();
+        assert_eq!(reader.header, SchemaHeader::legacy());
+        assert_eq!(reader.count(), 2);
+    }
+
+    #[test]
+    fn test_merge_rejects_incompatible_versions() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let legacy = temp_dir.path().join("legacy.jsonl");
+        let future = temp_dir.path().join("future.jsonl");
+
+        fs::write(&legacy, "{\"question\":\"Q1\",\"answer\":\"A1\"}\n").unwrap();
+        fs::write(
+            &future,
+            format!(
+                "{{\"schema\":\"{}\",\"schema_version\":\"2.0.0\"}}\n{{\"question\":\"Q2\",\"answer\":\"A2\"}}\n",
+                SCHEMA_NAME
+            ),
+        )
+        .unwrap();
+
+        assert_eq!(
+            merge_datasets(std::slice::from_ref(&legacy)).unwrap().len(),
+            1
+        );
+        assert!(merge_datasets(&[legacy, future]).is_err());
+    }
+}
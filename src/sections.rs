@@ -0,0 +1,382 @@
+//! Streaming split of source files into the sections questions are generated
+//! from, so files far larger than memory can be processed.
+
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::ops::Range;
+use std::path::Path;
+#[cfg(feature = "native")]
+use std::path::PathBuf;
+#[cfg(feature = "native")]
+use tokio::sync::mpsc;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::density::{targets_for_goal, DocumentSize};
+use crate::hash::hash_text;
+
+/// Size at which a section without headings is split at the next blank line
+pub const MAX_SECTION_BYTES: usize = 64 * 1024;
+
+/// Size at which a section is split at the next line, blank or not
+const HARD_SECTION_BYTES: usize = 4 * MAX_SECTION_BYTES;
+
+/// Whether a line starts a new section (an H1 or H2 heading)
+fn is_section_heading(line: &str) -> bool {
+    line.strip_prefix("##")
+        .or_else(|| line.strip_prefix('#'))
+        .and_then(|rest| rest.chars().next())
+        .is_some_and(char::is_whitespace)
+}
+
+/// A section and the byte range of the source it was read from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpannedSection {
+    pub text: String,
+    pub span: Range<usize>,
+}
+
+/// Yields the sections of a text one at a time
+///
+/// A section starts at each H1 or H2 heading. Sections that grow past
+/// [`MAX_SECTION_BYTES`] are split at the next blank line, and past four
+/// times that at the next line, so only one bounded section is held in
+/// memory at a time. Whitespace-only sections are skipped. Line endings
+/// are normalized to `\n`; [`SectionReader::spanned`] also yields where
+/// each section lies in the original bytes.
+pub struct SectionReader<R> {
+    reader: R,
+    current: String,
+    /// Bytes read so far, and where the current section started
+    offset: usize,
+    start: usize,
+    done: bool,
+}
+
+impl<R: BufRead> SectionReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            current: String::new(),
+            offset: 0,
+            start: 0,
+            done: false,
+        }
+    }
+
+    /// Yield each section with its byte range
+    pub fn spanned(self) -> impl Iterator<Item = Result<SpannedSection>> {
+        let mut reader = self;
+        std::iter::from_fn(move || reader.next_section())
+    }
+
+    fn take(&mut self, end: usize) -> Option<SpannedSection> {
+        let text = std::mem::take(&mut self.current);
+        let span = std::mem::replace(&mut self.start, end)..end;
+        (!text.trim().is_empty()).then_some(SpannedSection { text, span })
+    }
+
+    fn next_section(&mut self) -> Option<Result<SpannedSection>> {
+        let mut raw = String::new();
+        while !self.done {
+            raw.clear();
+            let line_start = self.offset;
+            match self.reader.read_line(&mut raw) {
+                Ok(0) => {
+                    self.done = true;
+                    return self.take(line_start).map(Ok);
+                }
+                Ok(read) => self.offset += read,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e.into()));
+                }
+            }
+            let line = raw.strip_suffix('\n').unwrap_or(&raw);
+            let line = line.strip_suffix('\r').unwrap_or(line);
+
+            let split = is_section_heading(line)
+                || (self.current.len() >= MAX_SECTION_BYTES && line.trim().is_empty())
+                || self.current.len() >= HARD_SECTION_BYTES;
+            let finished = if split { self.take(line_start) } else { None };
+            self.current.push_str(line);
+            self.current.push('\n');
+            if finished.is_some() {
+                return finished.map(Ok);
+            }
+        }
+        None
+    }
+}
+
+impl SectionReader<BufReader<fs::File>> {
+    pub fn open(path: &Path) -> Result<Self> {
+        Ok(Self::new(BufReader::new(fs::File::open(path)?)))
+    }
+}
+
+impl<R: BufRead> Iterator for SectionReader<R> {
+    type Item = Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_section()
+            .map(|section| section.map(|section| section.text))
+    }
+}
+
+#[cfg(feature = "native")]
+/// Read the sections of a file on a blocking thread, receiving them one at
+/// a time with their byte ranges; reading stops once the receiver is dropped
+pub fn read_sections(path: PathBuf) -> mpsc::Receiver<Result<SpannedSection>> {
+    let (sender, receiver) = mpsc::channel(1);
+    tokio::task::spawn_blocking(move || {
+        let reader = match SectionReader::open(&path) {
+            Ok(reader) => reader,
+            Err(e) => {
+                let _ = sender.blocking_send(Err(e));
+                return;
+            }
+        };
+        for section in reader.spanned() {
+            if sender.blocking_send(section).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+/// Size of a file, measured without holding it in memory
+pub fn measure_file(path: &Path) -> Result<DocumentSize> {
+    let mut size = DocumentSize::default();
+    for section in SectionReader::open(path)? {
+        size += DocumentSize::of_section(&section?);
+    }
+    Ok(size)
+}
+
+/// Words in a text, split at Unicode word boundaries rather than whitespace
+///
+/// Scripts written without spaces count per word as segmented by UAX #29:
+/// each Han ideograph or hiragana counts once, a katakana run counts once.
+/// Punctuation and markup such as `#` don't count.
+pub fn count_words(text: &str) -> usize {
+    text.unicode_words().count()
+}
+
+/// Question targets for a document of `word_count` words: the base goal,
+/// how many to generate (the goal plus a quarter extra), and the minimum
+/// accepted
+///
+/// These are the targets of the default `words:10`
+/// [`QuestionDensity`](crate::density::QuestionDensity).
+pub fn question_targets(word_count: usize) -> (usize, usize, usize) {
+    targets_for_goal(((word_count as f64 / 10.0).ceil() as usize).max(2))
+}
+
+/// A section's share of a document's questions, in proportion to its words
+pub fn proportional_target(
+    section_words: usize,
+    total_words: usize,
+    total_questions: usize,
+) -> usize {
+    (total_questions as f64 * (section_words as f64 / total_words as f64)).ceil() as usize
+}
+
+/// How a document will be split into sections before generation
+#[derive(Debug, Clone, Serialize)]
+pub struct ChunkPreview {
+    pub words: usize,
+    /// Questions that would be generated for the whole document
+    pub questions: usize,
+    pub sections: Vec<SectionPreview>,
+}
+
+/// One section of a [`ChunkPreview`]
+#[derive(Debug, Clone, Serialize)]
+pub struct SectionPreview {
+    /// Position of the section, from 1
+    pub number: usize,
+    /// Byte range of the section in the document
+    pub span: Range<usize>,
+    /// First line of the section
+    pub heading: String,
+    pub words: usize,
+    /// The section's share of the document's questions
+    pub questions: usize,
+    /// Number of an earlier section with the same text, ignoring whitespace
+    /// and case
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duplicate_of: Option<usize>,
+}
+
+/// Split a document into sections as generation would, with the words and
+/// word-based question target of each, without calling a model
+///
+/// Token budgets set with `QUESTION_BUDGET` are not applied; the targets are
+/// the word-based defaults.
+pub fn preview(content: &str) -> ChunkPreview {
+    let spanned: Vec<SpannedSection> = SectionReader::new(content.as_bytes())
+        .spanned()
+        .collect::<Result<_>>()
+        .expect("reading from memory cannot fail");
+    let words: usize = spanned
+        .iter()
+        .map(|section| count_words(&section.text))
+        .sum();
+    let (_, questions, _) = question_targets(words);
+
+    let mut seen = HashMap::new();
+    let sections = spanned
+        .into_iter()
+        .enumerate()
+        .map(|(index, section)| {
+            let number = index + 1;
+            let normalized = section
+                .text
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+                .to_lowercase();
+            let section_words = count_words(&section.text);
+            SectionPreview {
+                number,
+                heading: section
+                    .text
+                    .lines()
+                    .next()
+                    .unwrap_or_default()
+                    .trim()
+                    .to_string(),
+                words: section_words,
+                questions: proportional_target(section_words, words, questions),
+                duplicate_of: match seen.get(&hash_text(&normalized)) {
+                    Some(&first) => Some(first),
+                    None => {
+                        seen.insert(hash_text(&normalized), number);
+                        None
+                    }
+                },
+                span: section.span,
+            }
+        })
+        .collect();
+
+    ChunkPreview {
+        words,
+        questions,
+        sections,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sections(text: &str) -> Vec<String> {
+        SectionReader::new(text.as_bytes())
+            .collect::<Result<_>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_splits_at_top_level_headings() {
+        assert_eq!(
+            sections("Intro\n# One\nText\n### Detail\nMore\n## Two\nEnd"),
+            [
+                "Intro\n",
+                "# One\nText\n### Detail\nMore\n",
+                "## Two\nEnd\n"
+            ]
+        );
+        assert!(sections(" \n\n").is_empty());
+    }
+
+    #[test]
+    fn test_spans_cover_the_original_bytes() {
+        let text = "Intro\r\n\r\n# One\r\nText\n  \n## Two\nEnd";
+        let spanned: Vec<SpannedSection> = SectionReader::new(text.as_bytes())
+            .spanned()
+            .collect::<Result<_>>()
+            .unwrap();
+        let spans: Vec<_> = spanned.iter().map(|s| s.span.clone()).collect();
+        assert_eq!(spans, [0..9, 9..24, 24..text.len()]);
+        assert_eq!(spanned[1].text, "# One\nText\n  \n");
+        assert_eq!(&text[spanned[2].span.clone()], "## Two\nEnd");
+    }
+
+    #[test]
+    fn test_splits_oversized_sections() {
+        let paragraph = format!("{}\n", "word ".repeat(1000));
+        let text = format!("# Dump\n{}", paragraph.repeat(30) + "\n" + &paragraph);
+        let parts = sections(&text);
+        assert_eq!(parts.len(), 2);
+        assert!(parts[0].len() >= MAX_SECTION_BYTES);
+        assert!(parts[1].starts_with('\n'));
+
+        let unbroken = paragraph.repeat(60);
+        assert!(sections(&unbroken)
+            .iter()
+            .all(|part| part.len() <= HARD_SECTION_BYTES + paragraph.len()));
+    }
+
+    #[cfg(feature = "native")]
+    #[tokio::test]
+    async fn test_read_sections() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("doc.md");
+        fs::write(&path, "# One\nText\n## Two\nMore\n").unwrap();
+        let mut receiver = read_sections(path);
+        assert_eq!(
+            receiver.recv().await.unwrap().unwrap().text,
+            "# One\nText\n"
+        );
+        let second = receiver.recv().await.unwrap().unwrap();
+        assert_eq!(second.text, "## Two\nMore\n");
+        assert_eq!(second.span, 11..23);
+        assert!(receiver.recv().await.is_none());
+
+        let mut missing = read_sections(temp_dir.path().join("missing.md"));
+        assert!(missing.recv().await.unwrap().is_err());
+    }
+
+    #[test]
+    fn test_measure_file() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("doc.md");
+        fs::write(&path, "# One\nTwo words\n## Two\nThree more words\n").unwrap();
+        let size = measure_file(&path).unwrap();
+        assert_eq!((size.words, size.sections), (7, 2));
+    }
+
+    #[test]
+    fn test_preview() {
+        let text = "Intro words\n# One\nSome text here\n## Two\nsome   TEXT here\n";
+        let preview = preview(text);
+        assert_eq!(preview.words, 10);
+        assert_eq!(preview.questions, question_targets(10).1);
+        let headings: Vec<_> = preview
+            .sections
+            .iter()
+            .map(|s| s.heading.as_str())
+            .collect();
+        assert_eq!(headings, ["Intro words", "# One", "## Two"]);
+        assert_eq!(preview.sections[1].span, 12..33);
+        assert_eq!(preview.sections[1].words, 4);
+        assert_eq!(preview.sections[2].duplicate_of, None);
+        assert!(preview.sections.iter().map(|s| s.questions).sum::<usize>() >= preview.questions);
+
+        let repeated = super::preview("# Note\nSame.\n# Note\nsame.\n");
+        assert_eq!(repeated.sections[1].duplicate_of, Some(1));
+        assert!(super::preview("").sections.is_empty());
+    }
+
+    #[test]
+    fn test_question_targets() {
+        assert_eq!(question_targets(0), (2, 4, 2));
+        assert_eq!(question_targets(400), (40, 50, 32));
+        assert_eq!(proportional_target(100, 400, 50), 13);
+    }
+}
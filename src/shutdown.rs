@@ -0,0 +1,75 @@
+//! Cooperative cancellation for long runs.
+//!
+//! A [`Shutdown`] is shared between the signal handler and the code doing the
+//! work. Once it is requested no new LLM requests are started, in-flight ones
+//! are abandoned, and whatever was generated so far is written out normally.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::Notify;
+
+#[derive(Debug, Default)]
+struct State {
+    requested: AtomicBool,
+    notify: Notify,
+}
+
+/// Cloneable handle to a shutdown request
+#[derive(Debug, Clone, Default)]
+pub struct Shutdown {
+    state: Arc<State>,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask everything holding this handle to stop
+    pub fn request(&self) {
+        self.state.requested.store(true, Ordering::SeqCst);
+        self.state.notify.notify_waiters();
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.state.requested.load(Ordering::SeqCst)
+    }
+
+    /// Complete once shutdown has been requested
+    pub async fn requested(&self) {
+        loop {
+            let notified = self.state.notify.notified();
+            if self.is_requested() {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_request_wakes_waiters() {
+        let shutdown = Shutdown::new();
+        assert!(!shutdown.is_requested());
+
+        let waiter = tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move { shutdown.requested().await }
+        });
+        tokio::task::yield_now().await;
+        shutdown.request();
+        tokio::time::timeout(Duration::from_secs(5), waiter)
+            .await
+            .expect("waiter was not woken")
+            .unwrap();
+
+        // Already requested: completes right away
+        shutdown.requested().await;
+        assert!(shutdown.is_requested());
+    }
+}
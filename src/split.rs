@@ -0,0 +1,327 @@
+//! Train/test splits by semantic cluster: records whose questions embed
+//! close together are grouped, and each group goes to one side as a whole,
+//! so no near-duplicate of a test question is trained on.
+
+use anyhow::Result;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+use crate::external::{embed_all, Embedder};
+use crate::hash::stable_u64;
+use crate::processor::ProcessedItem;
+
+/// Percentage of records put in the test set
+pub const DEFAULT_TEST_PERCENT: f64 = 10.0;
+
+/// Cosine similarity from which two questions are in the same cluster
+pub const DEFAULT_CLUSTER_SIMILARITY: f32 = 0.85;
+
+/// Scale a vector to unit length, so the cosine similarity of two vectors
+/// is their dot product
+fn normalize(mut vector: Vec<f32>) -> Vec<f32> {
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|x| *x /= norm);
+    }
+    vector
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+/// Root of `i` in a union-find forest, halving the path on the way
+fn root(parents: &mut [usize], mut i: usize) -> usize {
+    while parents[i] != i {
+        parents[i] = parents[parents[i]];
+        i = parents[i];
+    }
+    i
+}
+
+/// Cluster of each embedding, numbered in order of first appearance
+///
+/// Embeddings at least `similarity` close are in the same cluster, and so
+/// are the embeddings close to any of its members (single linkage), so two
+/// near-duplicates always share a cluster. Every pair is compared, which
+/// takes a few seconds for tens of thousands of records.
+pub fn cluster(embeddings: &[Vec<f32>], similarity: f32) -> Vec<usize> {
+    let vectors: Vec<Vec<f32>> = embeddings.iter().cloned().map(normalize).collect();
+    let mut parents: Vec<usize> = (0..vectors.len()).collect();
+    for i in 0..vectors.len() {
+        for j in i + 1..vectors.len() {
+            if dot(&vectors[i], &vectors[j]) >= similarity {
+                let (a, b) = (root(&mut parents, i), root(&mut parents, j));
+                parents[a.max(b)] = a.min(b);
+            }
+        }
+    }
+    numbered(&mut parents)
+}
+
+/// The trees of a union-find forest numbered in order of first appearance
+fn numbered(parents: &mut [usize]) -> Vec<usize> {
+    let mut numbers = vec![usize::MAX; parents.len()];
+    let mut next = 0;
+    (0..parents.len())
+        .map(|i| {
+            let root = root(parents, i);
+            if numbers[root] == usize::MAX {
+                numbers[root] = next;
+                next += 1;
+            }
+            numbers[root]
+        })
+        .collect()
+}
+
+/// Merge the cluster of each variant made by `augment` with its original's,
+/// since noisy variants can embed further from the original than the
+/// similarity asked for
+pub fn join_variants(items: &[ProcessedItem], clusters: &[usize]) -> Vec<usize> {
+    let originals: HashMap<Uuid, usize> = items
+        .iter()
+        .zip(clusters)
+        .filter_map(|(item, &cluster)| item.id.map(|id| (id, cluster)))
+        .collect();
+    let count = clusters.iter().max().map_or(0, |max| max + 1);
+    let mut parents: Vec<usize> = (0..count).collect();
+    for (item, &cluster) in items.iter().zip(clusters) {
+        let original = item
+            .metadata
+            .augmented_from
+            .and_then(|id| originals.get(&id));
+        if let Some(&original) = original {
+            let (a, b) = (root(&mut parents, cluster), root(&mut parents, original));
+            parents[a.max(b)] = a.min(b);
+        }
+    }
+    let numbers = numbered(&mut parents);
+    clusters.iter().map(|&cluster| numbers[cluster]).collect()
+}
+
+/// Order of a cluster when filling the test set: a hash of its questions,
+/// so the same dataset is always split the same way
+fn cluster_hash(questions: &[&str]) -> u64 {
+    let mut questions = questions.to_vec();
+    questions.sort_unstable();
+    let text: String = questions
+        .iter()
+        .map(|question| format!("{}\n", question.trim()))
+        .collect();
+    stable_u64(b"split\n", &text)
+}
+
+/// Records of a dataset split into train and test sets
+#[derive(Debug, Clone, Default)]
+pub struct ClusterSplit {
+    pub train: Vec<ProcessedItem>,
+    pub test: Vec<ProcessedItem>,
+    pub report: SplitReport,
+}
+
+/// How a dataset was split
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SplitReport {
+    pub records: usize,
+    pub clusters: usize,
+    /// Records in the largest cluster
+    pub largest: usize,
+    pub train: usize,
+    pub test: usize,
+}
+
+impl fmt::Display for SplitReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} records in {} clusters (largest: {}): {} train, {} test",
+            self.records, self.clusters, self.largest, self.train, self.test
+        )
+    }
+}
+
+/// Split records by their clusters (see [`cluster`])
+///
+/// Clusters are taken into the test set in the order of their hash, each
+/// one only if it still fits within `test_percent` of the records, so a
+/// large cluster is skipped for smaller ones rather than overfilling the
+/// test set. Records keep their dataset order on both sides.
+pub fn split_clusters(
+    items: Vec<ProcessedItem>,
+    clusters: &[usize],
+    test_percent: f64,
+) -> ClusterSplit {
+    let count = clusters.iter().max().map_or(0, |max| max + 1);
+    let mut members: Vec<Vec<usize>> = vec![Vec::new(); count];
+    for (index, &cluster) in clusters.iter().enumerate() {
+        members[cluster].push(index);
+    }
+    let mut order: Vec<usize> = (0..count).collect();
+    order.sort_by_cached_key(|&cluster| {
+        let questions: Vec<&str> = members[cluster]
+            .iter()
+            .map(|&index| items[index].question.as_str())
+            .collect();
+        cluster_hash(&questions)
+    });
+
+    let target = (items.len() as f64 * test_percent / 100.0).round() as usize;
+    let mut in_test = vec![false; count];
+    let mut test = 0;
+    for cluster in order {
+        let size = members[cluster].len();
+        if test + size <= target {
+            in_test[cluster] = true;
+            test += size;
+        }
+    }
+
+    let mut split = ClusterSplit {
+        report: SplitReport {
+            records: items.len(),
+            clusters: count,
+            largest: members.iter().map(Vec::len).max().unwrap_or(0),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    for (item, &cluster) in items.into_iter().zip(clusters) {
+        match in_test[cluster] {
+            true => split.test.push(item),
+            false => split.train.push(item),
+        }
+    }
+    split.report.train = split.train.len();
+    split.report.test = split.test.len();
+    split
+}
+
+/// Embed the questions of a dataset, cluster them and split it, keeping
+/// variants with their originals
+pub async fn split_by_cluster(
+    items: Vec<ProcessedItem>,
+    embedder: &dyn Embedder,
+    similarity: f32,
+    test_percent: f64,
+) -> Result<ClusterSplit> {
+    let questions: Vec<String> = items.iter().map(|item| item.question.clone()).collect();
+    let embeddings = embed_all(embedder, &questions).await?;
+    let clusters = join_variants(&items, &cluster(&embeddings, similarity));
+    Ok(split_clusters(items, &clusters, test_percent))
+}
+
+/// Where the train and test sets of a dataset are written:
+/// `NAME.train.jsonl` and `NAME.test.jsonl`
+pub fn split_paths(path: &Path) -> (PathBuf, PathBuf) {
+    let stem = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("dataset");
+    (
+        path.with_file_name(format!("{}.train.jsonl", stem)),
+        path.with_file_name(format!("{}.test.jsonl", stem)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::external::embedding::MockEmbedder;
+
+    fn item(question: &str) -> ProcessedItem {
+        ProcessedItem::new(question, "A")
+    }
+
+    #[test]
+    fn test_cluster_links_near_duplicates_transitively() {
+        let embeddings = [
+            vec![1.0, 0.0, 0.0],
+            vec![0.0, 1.0, 0.0],
+            vec![0.95, 0.3, 0.0],
+            vec![0.0, 0.0, 2.0],
+            // Close to the third, though not to the first
+            vec![0.8, 0.6, 0.0],
+        ];
+        assert_eq!(cluster(&embeddings, 0.9), [0, 1, 0, 2, 0]);
+        assert_eq!(cluster(&embeddings, 1.1), [0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_variants_join_their_originals() {
+        let mut original = item("How is the cache configured?");
+        original.assign_id("guide.md", "section");
+        let mut noisy = item("how is teh cache configured");
+        noisy.metadata.augmented_from = original.id;
+        let items = [item("Which port?"), noisy, item("What is TLS?"), original];
+        assert_eq!(join_variants(&items, &[0, 1, 2, 3]), [0, 1, 2, 1]);
+    }
+
+    #[test]
+    fn test_clusters_stay_on_one_side() {
+        let items: Vec<ProcessedItem> = (0..100).map(|i| item(&format!("Q{}", i))).collect();
+        let clusters: Vec<usize> = (0..100).map(|i| i / 4).collect();
+        let split = split_clusters(items.clone(), &clusters, 20.0);
+        assert_eq!((split.report.train, split.report.test), (80, 20));
+        assert_eq!((split.report.clusters, split.report.largest), (25, 4));
+        for group in items.chunks(4) {
+            let tested = group
+                .iter()
+                .filter(|item| split.test.iter().any(|t| t.question == item.question))
+                .count();
+            assert!(tested == 0 || tested == 4);
+        }
+
+        // The same dataset is split the same way
+        let again = split_clusters(items, &clusters, 20.0);
+        let questions = |items: &[ProcessedItem]| {
+            items
+                .iter()
+                .map(|item| item.question.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(questions(&again.test), questions(&split.test));
+
+        // A cluster larger than the test share stays in training
+        let items: Vec<ProcessedItem> = (0..10).map(|i| item(&format!("Q{}", i))).collect();
+        let split = split_clusters(items, &[0, 0, 0, 0, 0, 0, 0, 0, 1, 2], 20.0);
+        assert_eq!(split.report.test, 2);
+        assert_eq!(split.train.len(), 8);
+    }
+
+    #[tokio::test]
+    async fn test_split_by_cluster() {
+        let mut embedder = MockEmbedder::new();
+        embedder.expect_embed_batch().returning(|texts| {
+            Ok(texts
+                .iter()
+                .map(|text| match text.contains("cache") {
+                    true => vec![1.0, 0.1],
+                    false => vec![0.1, 1.0],
+                })
+                .collect())
+        });
+        let items = vec![
+            item("How is the cache configured?"),
+            item("How do you configure the cache?"),
+            item("Which port does the server use?"),
+            item("What port is the server on?"),
+        ];
+        let split = split_by_cluster(items, &embedder, 0.9, 50.0).await.unwrap();
+        assert_eq!(split.report.clusters, 2);
+        assert_eq!(split.test.len(), 2);
+        assert_eq!(
+            split.test[0].question.contains("cache"),
+            split.test[1].question.contains("cache")
+        );
+    }
+
+    #[test]
+    fn test_split_paths() {
+        let (train, test) = split_paths(Path::new("out/all_qa.jsonl"));
+        assert_eq!(train, Path::new("out/all_qa.train.jsonl"));
+        assert_eq!(test, Path::new("out/all_qa.test.jsonl"));
+    }
+}
@@ -0,0 +1,88 @@
+//! Summary statistics for generated datasets.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::processor::{DefaultOllamaProcessor, ProcessedItem};
+
+/// Counts and averages over the records of a dataset
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DatasetStats {
+    pub records: usize,
+    /// Distinct questions, compared case-insensitively after trimming
+    pub unique_questions: usize,
+    /// Records that carry retrieved context (RAG records)
+    pub with_context: usize,
+    pub empty_answers: usize,
+    pub avg_question_words: f64,
+    pub avg_answer_words: f64,
+}
+
+impl DatasetStats {
+    pub fn from_items(items: &[ProcessedItem]) -> Self {
+        if items.is_empty() {
+            return Self::default();
+        }
+        let words = DefaultOllamaProcessor::count_words;
+        let average = |total: usize| total as f64 / items.len() as f64;
+
+        let unique: HashSet<String> = items
+            .iter()
+            .map(|item| item.question.trim().to_lowercase())
+            .collect();
+        Self {
+            records: items.len(),
+            unique_questions: unique.len(),
+            with_context: items.iter().filter(|item| item.context.is_some()).count(),
+            empty_answers: items
+                .iter()
+                .filter(|item| item.answer.trim().is_empty())
+                .count(),
+            avg_question_words: average(items.iter().map(|item| words(&item.question)).sum()),
+            avg_answer_words: average(items.iter().map(|item| words(&item.answer)).sum()),
+        }
+    }
+
+    /// Records whose question already appeared earlier in the dataset
+    pub fn duplicate_questions(&self) -> usize {
+        self.records - self.unique_questions
+    }
+}
+
+impl fmt::Display for DatasetStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Records:             {}", self.records)?;
+        writeln!(f, "Unique questions:    {}", self.unique_questions)?;
+        writeln!(f, "Duplicate questions: {}", self.duplicate_questions())?;
+        writeln!(f, "With context:        {}", self.with_context)?;
+        writeln!(f, "Empty answers:       {}", self.empty_answers)?;
+        writeln!(f, "Avg question words:  {:.1}", self.avg_question_words)?;
+        write!(f, "Avg answer words:    {:.1}", self.avg_answer_words)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(question: &str, answer: &str) -> ProcessedItem {
+        ProcessedItem::new(question, answer)
+    }
+
+    #[test]
+    fn test_dataset_stats() {
+        let stats = DatasetStats::from_items(&[
+            item("What is Qdrant?", "A vector database."),
+            item("what is qdrant? ", "A vector search engine."),
+            item("How to index?", " "),
+        ]);
+        assert_eq!(stats.records, 3);
+        assert_eq!(stats.unique_questions, 2);
+        assert_eq!(stats.duplicate_questions(), 1);
+        assert_eq!(stats.empty_answers, 1);
+        assert_eq!(stats.avg_question_words, 3.0);
+
+        assert_eq!(DatasetStats::from_items(&[]), DatasetStats::default());
+    }
+}
@@ -0,0 +1,102 @@
+//! Export of the run's tracing spans to an OpenTelemetry collector over
+//! OTLP/HTTP, so slow stages and failing backends of long runs show up in
+//! Jaeger or Tempo.
+//!
+//! Collection, chunking, generation, model and embedding requests, and
+//! writing each open a `tracing` span. Spans are only exported when the
+//! crate is built with the `otel` feature and `OTEL_EXPORTER_OTLP_ENDPOINT`
+//! is set; otherwise they cost next to nothing.
+
+use anyhow::Result;
+use std::env;
+
+/// Collector base URL, e.g. `http://localhost:4318`; spans go to `/v1/traces`
+pub const OTLP_ENDPOINT_VAR: &str = "OTEL_EXPORTER_OTLP_ENDPOINT";
+
+/// Service the spans are reported under
+pub const SERVICE_NAME_VAR: &str = "OTEL_SERVICE_NAME";
+
+/// Keeps span export running; dropping it flushes the spans not yet sent
+pub struct Telemetry {
+    #[cfg(feature = "otel")]
+    provider: Option<opentelemetry_sdk::trace::SdkTracerProvider>,
+}
+
+impl Telemetry {
+    /// Start exporting spans if a collector is configured
+    pub fn init() -> Result<Self> {
+        let endpoint = env::var(OTLP_ENDPOINT_VAR)
+            .ok()
+            .filter(|endpoint| !endpoint.is_empty());
+        #[cfg(feature = "otel")]
+        {
+            let provider = match endpoint {
+                Some(endpoint) => Some(otel::install(&endpoint)?),
+                None => None,
+            };
+            Ok(Self { provider })
+        }
+        #[cfg(not(feature = "otel"))]
+        {
+            if endpoint.is_some() {
+                println!(
+                    "{} is set, but spans are only exported when built with --features otel",
+                    OTLP_ENDPOINT_VAR
+                );
+            }
+            Ok(Self {})
+        }
+    }
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        #[cfg(feature = "otel")]
+        if let Some(provider) = self.provider.take() {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("Could not export the last spans: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "otel")]
+mod otel {
+    use super::*;
+    use opentelemetry::trace::TracerProvider;
+    use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+    use opentelemetry_sdk::Resource;
+    use tracing_subscriber::filter::LevelFilter;
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::util::SubscriberInitExt;
+    use tracing_subscriber::Layer;
+
+    /// Install a global subscriber sending spans at `LOG_LEVEL` and above
+    /// to the collector at `endpoint`
+    pub(super) fn install(endpoint: &str) -> Result<SdkTracerProvider> {
+        let exporter = SpanExporter::builder()
+            .with_http()
+            .with_endpoint(format!("{}/v1/traces", endpoint.trim_end_matches('/')))
+            .build()?;
+        let mut resource = Resource::builder();
+        if env::var(SERVICE_NAME_VAR).is_err() {
+            resource = resource.with_service_name(env!("CARGO_PKG_NAME"));
+        }
+        let provider = SdkTracerProvider::builder()
+            .with_batch_exporter(exporter)
+            .with_resource(resource.build())
+            .build();
+
+        let level = env::var("LOG_LEVEL")
+            .ok()
+            .and_then(|level| level.parse().ok())
+            .unwrap_or(LevelFilter::INFO);
+        let layer = tracing_opentelemetry::layer()
+            .with_tracer(provider.tracer(env!("CARGO_PKG_NAME")))
+            .with_filter(level);
+        tracing_subscriber::registry().with(layer).try_init()?;
+        println!("Exporting traces to {}", endpoint);
+        Ok(provider)
+    }
+}
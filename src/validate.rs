@@ -0,0 +1,346 @@
+//! Checks of existing dataset files: malformed lines, records that break the
+//! schema, empty answers, encoding problems and duplicate questions, with a
+//! repaired copy holding only the records that pass.
+
+use anyhow::Result;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::processor::ProcessedItem;
+use crate::schema::{self, SchemaHeader};
+
+/// Issues listed per file before the rest are only counted
+const LISTED_ISSUES: usize = 20;
+
+/// Kind of problem found in a dataset
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IssueKind {
+    /// Not valid JSON; dropped when repairing
+    Malformed,
+    /// Breaks the record schema or has an incompatible header; dropped when repairing
+    Schema,
+    /// Blank answer; dropped when repairing
+    EmptyAnswer,
+    /// Invalid UTF-8, replacement or control characters; cleaned when repairing
+    Encoding,
+    /// Question already asked earlier in the file; dropped when repairing
+    Duplicate,
+}
+
+impl IssueKind {
+    const ALL: [IssueKind; 5] = [
+        IssueKind::Malformed,
+        IssueKind::Schema,
+        IssueKind::EmptyAnswer,
+        IssueKind::Encoding,
+        IssueKind::Duplicate,
+    ];
+
+    fn label(&self) -> &'static str {
+        match self {
+            IssueKind::Malformed => "Malformed lines",
+            IssueKind::Schema => "Schema errors",
+            IssueKind::EmptyAnswer => "Empty answers",
+            IssueKind::Encoding => "Encoding issues",
+            IssueKind::Duplicate => "Duplicates",
+        }
+    }
+}
+
+/// One problem, at a 1-based line (or row, for Parquet)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    pub line: usize,
+    pub kind: IssueKind,
+    pub message: String,
+}
+
+/// Result of validating one dataset file
+#[derive(Debug, Clone)]
+pub struct ValidationReport {
+    pub header: SchemaHeader,
+    /// Lines holding records, i.e. all non-blank lines but the header
+    pub records: usize,
+    pub issues: Vec<Issue>,
+    /// The records that passed, with encoding problems cleaned up
+    pub repaired: Vec<ProcessedItem>,
+}
+
+impl ValidationReport {
+    pub fn count(&self, kind: IssueKind) -> usize {
+        self.issues
+            .iter()
+            .filter(|issue| issue.kind == kind)
+            .count()
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Records:          {}", self.records)?;
+        writeln!(f, "Valid:            {}", self.repaired.len())?;
+        for kind in IssueKind::ALL {
+            writeln!(
+                f,
+                "{:<18}{}",
+                format!("{}:", kind.label()),
+                self.count(kind)
+            )?;
+        }
+        for issue in self.issues.iter().take(LISTED_ISSUES) {
+            writeln!(f, "  line {}: {}", issue.line, issue.message)?;
+        }
+        if self.issues.len() > LISTED_ISSUES {
+            writeln!(f, "  ... and {} more", self.issues.len() - LISTED_ISSUES)?;
+        }
+        Ok(())
+    }
+}
+
+/// Validate a JSONL dataset, or a Parquet one (with the `parquet` feature)
+pub fn validate_file(path: &Path) -> Result<ValidationReport> {
+    if path.extension().and_then(|ext| ext.to_str()) == Some("parquet") {
+        let rows = read_parquet_rows(path)?;
+        let mut validator = Validator::default();
+        for (index, row) in rows.into_iter().enumerate() {
+            validator.record(index + 1, row);
+        }
+        Ok(validator.finish(SchemaHeader::default()))
+    } else {
+        Ok(validate_jsonl(&fs::read(path)?))
+    }
+}
+
+/// Validate the content of a JSONL dataset
+pub fn validate_jsonl(content: &[u8]) -> ValidationReport {
+    let mut validator = Validator::default();
+    let mut header = None;
+
+    for (index, raw) in content.split(|&byte| byte == b'\n').enumerate() {
+        let line = index + 1;
+        let mut text = match std::str::from_utf8(raw) {
+            Ok(text) => text.to_string(),
+            Err(e) => {
+                validator.issue(line, IssueKind::Encoding, format!("invalid UTF-8 ({})", e));
+                String::from_utf8_lossy(raw).into_owned()
+            }
+        };
+        if let Some(stripped) = text.strip_prefix('\u{feff}') {
+            validator.issue(line, IssueKind::Encoding, "byte order mark".to_string());
+            text = stripped.to_string();
+        }
+        if text.trim().is_empty() {
+            continue;
+        }
+
+        if header.is_none() && validator.records == 0 {
+            if let Some(found) = SchemaHeader::from_line(&text) {
+                if let Err(e) = SchemaHeader::default().check_compatible(&found) {
+                    validator.issue(line, IssueKind::Schema, e.to_string());
+                }
+                header = Some(found);
+                continue;
+            }
+        }
+
+        match serde_json::from_str::<Value>(&text) {
+            Ok(value) => validator.record(line, value),
+            Err(e) => {
+                validator.records += 1;
+                validator.issue(line, IssueKind::Malformed, format!("malformed JSON: {}", e));
+            }
+        }
+    }
+    validator.finish(header.unwrap_or_else(SchemaHeader::legacy))
+}
+
+/// Text with replacement characters and control characters (other than
+/// line breaks and tabs) removed
+fn clean_text(text: &str) -> String {
+    text.chars()
+        .filter(|&c| c != '\u{fffd}' && (!c.is_control() || matches!(c, '\n' | '\r' | '\t')))
+        .collect()
+}
+
+#[derive(Default)]
+struct Validator {
+    records: usize,
+    issues: Vec<Issue>,
+    questions: HashSet<String>,
+    repaired: Vec<ProcessedItem>,
+}
+
+impl Validator {
+    fn issue(&mut self, line: usize, kind: IssueKind, message: String) {
+        self.issues.push(Issue {
+            line,
+            kind,
+            message,
+        });
+    }
+
+    fn record(&mut self, line: usize, mut value: Value) {
+        self.records += 1;
+
+        if let Some(object) = value.as_object_mut() {
+            // Parquet rows carry missing optional columns as nulls
+            object.retain(|_, field| !field.is_null());
+            let mut dirty = false;
+            for field in object.values_mut() {
+                if let Some(text) = field.as_str() {
+                    let cleaned = clean_text(text);
+                    if cleaned != text {
+                        dirty = true;
+                        *field = Value::String(cleaned);
+                    }
+                }
+            }
+            if dirty {
+                self.issue(
+                    line,
+                    IssueKind::Encoding,
+                    "replacement or control characters".to_string(),
+                );
+            }
+            if object
+                .get("answer")
+                .and_then(Value::as_str)
+                .is_some_and(|answer| answer.trim().is_empty())
+            {
+                self.issue(line, IssueKind::EmptyAnswer, "empty answer".to_string());
+                return;
+            }
+        }
+
+        if let Err(e) = schema::validate_value(&value) {
+            self.issue(line, IssueKind::Schema, e.to_string());
+            return;
+        }
+        let item: ProcessedItem = match serde_json::from_value(value) {
+            Ok(item) => item,
+            Err(e) => {
+                self.issue(line, IssueKind::Schema, e.to_string());
+                return;
+            }
+        };
+        if !self.questions.insert(item.question.trim().to_lowercase()) {
+            self.issue(
+                line,
+                IssueKind::Duplicate,
+                format!("duplicate question: {}", item.question),
+            );
+            return;
+        }
+        self.repaired.push(item);
+    }
+
+    fn finish(self, header: SchemaHeader) -> ValidationReport {
+        let mut issues = self.issues;
+        issues.sort_by_key(|issue| issue.line);
+        ValidationReport {
+            header,
+            records: self.records,
+            issues,
+            repaired: self.repaired,
+        }
+    }
+}
+
+/// Where the repaired copy of a dataset goes: next to it, as `NAME.repaired.jsonl`
+pub fn repaired_path(path: &Path) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("dataset");
+    path.with_file_name(format!("{}.repaired.jsonl", stem))
+}
+
+#[cfg(feature = "parquet")]
+fn read_parquet_rows(path: &Path) -> Result<Vec<Value>> {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    let reader = SerializedFileReader::new(fs::File::open(path)?)?;
+    reader
+        .get_row_iter(None)?
+        .map(|row| Ok(row?.to_json_value()))
+        .collect()
+}
+
+#[cfg(not(feature = "parquet"))]
+fn read_parquet_rows(path: &Path) -> Result<Vec<Value>> {
+    Err(anyhow::anyhow!(
+        "Cannot read {}: Parquet support needs the `parquet` feature (cargo build --features parquet)",
+        path.display()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_jsonl_reports_each_problem() {
+        let mut content = Vec::new();
+        content.extend_from_slice(
+            b"{\"schema\":\"llm_dataset_builder.processed_item\",\"schema_version\":\"1.1.0\"}\n",
+        );
+        content.extend_from_slice(b"{\"question\":\"What is TLS?\",\"answer\":\"Encryption.\"}\n");
+        content.extend_from_slice(b"{\"question\":\"Broken\",\n");
+        content.extend_from_slice(b"{\"question\":\"What is mTLS?\",\"answer\":\" \"}\n");
+        content.extend_from_slice(b"{\"question\":\"what is tls?\",\"answer\":\"Again.\"}\n");
+        content
+            .extend_from_slice(b"{\"question\":\"Why \xff?\",\"answer\":\"Bad\\u0007 bytes.\"}\n");
+        content.extend_from_slice(b"{\"question\":\"Extra?\",\"answer\":\"A\",\"rating\":1}\n");
+
+        let report = validate_jsonl(&content);
+        assert_eq!(report.header.schema_version, "1.1.0");
+        assert_eq!(report.records, 6);
+        assert_eq!(report.count(IssueKind::Malformed), 1);
+        assert_eq!(report.count(IssueKind::EmptyAnswer), 1);
+        assert_eq!(report.count(IssueKind::Duplicate), 1);
+        assert_eq!(report.count(IssueKind::Schema), 1);
+        // Invalid UTF-8 on the line, then the characters cleaned from the record
+        assert_eq!(report.count(IssueKind::Encoding), 2);
+
+        let questions: Vec<&str> = report
+            .repaired
+            .iter()
+            .map(|item| item.question.as_str())
+            .collect();
+        assert_eq!(questions, ["What is TLS?", "Why ?"]);
+        assert_eq!(report.repaired[1].answer, "Bad bytes.");
+        assert!(!report.is_valid());
+    }
+
+    #[test]
+    fn test_validate_clean_legacy_file() {
+        let report = validate_jsonl(b"{\"question\":\"Q?\",\"answer\":\"A.\"}\n\n");
+        assert!(report.is_valid());
+        assert_eq!(report.header, SchemaHeader::legacy());
+        assert_eq!(report.repaired.len(), 1);
+    }
+
+    #[test]
+    fn test_incompatible_header_is_reported() {
+        let report = validate_jsonl(
+            b"{\"schema\":\"llm_dataset_builder.processed_item\",\"schema_version\":\"2.0.0\"}\n",
+        );
+        assert_eq!(report.count(IssueKind::Schema), 1);
+        assert_eq!(report.records, 0);
+    }
+
+    #[test]
+    fn test_repaired_path() {
+        assert_eq!(
+            repaired_path(Path::new("out/all_qa.jsonl")),
+            PathBuf::from("out/all_qa.repaired.jsonl")
+        );
+    }
+}
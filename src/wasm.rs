@@ -0,0 +1,61 @@
+//! JavaScript bindings for the parser and chunker, so a browser page can
+//! show how a document will be split and how many questions each section
+//! gets before any generation runs. Built with the `wasm` feature and
+//! without the default `native` one:
+//!
+//! ```text
+//! cargo build --release --lib --target wasm32-unknown-unknown \
+//!     --no-default-features --features wasm
+//! wasm-bindgen --target web --out-dir web/pkg \
+//!     target/wasm32-unknown-unknown/release/llm_dataset_builder.wasm
+//! ```
+//!
+//! Results are returned as JSON strings; errors are thrown as JavaScript
+//! exceptions.
+
+use wasm_bindgen::prelude::*;
+
+use crate::parser::{parse_markdown, parse_markdown_document};
+use crate::sections;
+
+/// Sections, words and question targets of a document as JSON, as
+/// [`sections::preview`] computes them
+#[wasm_bindgen(js_name = previewChunks)]
+pub fn preview_chunks(markdown: &str) -> Result<String, JsError> {
+    Ok(serde_json::to_string(&sections::preview(markdown))?)
+}
+
+/// The document graph of a document as JSON, as
+/// [`DocumentGraph::to_json`](crate::graph::DocumentGraph::to_json)
+/// serializes it; with a `name`, node IDs are stable
+#[wasm_bindgen(js_name = parseMarkdown)]
+pub fn parse_markdown_json(markdown: &str, name: Option<String>) -> Result<String, JsError> {
+    let graph = match name {
+        Some(name) => parse_markdown_document(markdown, &name),
+        None => parse_markdown(markdown),
+    }
+    .map_err(|e| JsError::new(&format!("{:#}", e)))?;
+    Ok(graph.to_json())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bindings_return_json() {
+        let markdown = "# Guide\n\nIntro text.\n\n## Install\n\nRun `make`.\n";
+        let preview: serde_json::Value =
+            serde_json::from_str(&preview_chunks(markdown).unwrap()).unwrap();
+        assert_eq!(preview["words"], 6);
+        assert_eq!(preview["sections"][1]["heading"], "## Install");
+
+        let graph = parse_markdown_json(markdown, Some("guide.md".to_string())).unwrap();
+        assert_eq!(
+            parse_markdown_json(markdown, Some("guide.md".to_string())).unwrap(),
+            graph
+        );
+        let graph: serde_json::Value = serde_json::from_str(&graph).unwrap();
+        assert_eq!(graph["nodes"][0]["node_type"], "Document");
+    }
+}
@@ -0,0 +1,303 @@
+//! Building blocks for `--watch`: file system notifications for local
+//! sources, change detection for re-polled remote sources, and appending new
+//! records to an existing dataset.
+
+use anyhow::Result;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+use crate::datasource::{open_source, DataSource, SourceProvenance, SourceType};
+use crate::incremental::file_hash;
+use crate::processor::ProcessedItem;
+use crate::schema;
+
+/// Whether a file is one the generator reads (markdown or plain text)
+pub fn is_input_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext == "md" || ext == "txt")
+        .unwrap_or(false)
+}
+
+/// A local source whose files are copied into the output directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalMirror {
+    /// Watched file or directory
+    pub source: PathBuf,
+    pub output_dir: PathBuf,
+}
+
+impl LocalMirror {
+    /// Where a changed file under the source lives in the output directory,
+    /// laid out the way [`crate::datasource::LocalSource`] copies it
+    pub fn destination(&self, changed: &Path) -> Option<PathBuf> {
+        if self.source.is_file() {
+            (changed == self.source)
+                .then(|| changed.file_name().map(|name| self.output_dir.join(name)))
+                .flatten()
+        } else {
+            let relative = changed.strip_prefix(&self.source).ok()?;
+            Some(self.output_dir.join(relative))
+        }
+    }
+}
+
+/// What to watch after the initial run
+pub struct WatchPlan {
+    pub mirrors: Vec<LocalMirror>,
+    /// Remote sources that are collected again on every poll
+    pub feeds: Vec<Box<dyn DataSource>>,
+    /// How often the feeds are polled
+    pub interval: Duration,
+    /// Canonical output directory the mirrors and feeds write into
+    pub output_dir: PathBuf,
+}
+
+impl WatchPlan {
+    /// Watch the local sources and poll the remote ones among `sources`;
+    /// without any sources the output directory itself is watched
+    pub fn from_sources(
+        sources: &[SourceProvenance],
+        output_dir: &Path,
+        interval: Duration,
+    ) -> Result<Self> {
+        let output_dir = output_dir.canonicalize()?;
+        let mut plan = Self {
+            mirrors: Vec::new(),
+            feeds: Vec::new(),
+            interval,
+            output_dir: output_dir.clone(),
+        };
+        for source in sources {
+            let kind = match source.kind.as_str() {
+                "local" => {
+                    plan.mirrors.push(LocalMirror {
+                        source: PathBuf::from(&source.location),
+                        output_dir: output_dir.clone(),
+                    });
+                    continue;
+                }
+                "github" => SourceType::GitHub,
+                "github_releases" => SourceType::GitHubReleases,
+                _ => SourceType::Url,
+            };
+            plan.feeds.push(open_source(&source.location, kind)?);
+        }
+        if plan.mirrors.is_empty() && plan.feeds.is_empty() {
+            plan.mirrors.push(LocalMirror {
+                source: output_dir.clone(),
+                output_dir,
+            });
+        }
+        Ok(plan)
+    }
+}
+
+/// File system watcher reporting created or modified input files
+pub struct ChangeWatcher {
+    _watcher: RecommendedWatcher,
+    events: mpsc::UnboundedReceiver<PathBuf>,
+}
+
+impl ChangeWatcher {
+    pub fn new(paths: &[PathBuf]) -> Result<Self> {
+        let (sender, events) = mpsc::unbounded_channel();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                if let Ok(event) = event {
+                    if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                        for path in event.paths {
+                            let _ = sender.send(path);
+                        }
+                    }
+                }
+            })?;
+        for path in paths {
+            watcher.watch(path, RecursiveMode::Recursive)?;
+        }
+        Ok(Self {
+            _watcher: watcher,
+            events,
+        })
+    }
+
+    /// Wait for changes, then gather everything that changes until nothing
+    /// has for `debounce`, so a burst of writes yields each file once
+    pub async fn next_batch(&mut self, debounce: Duration) -> Option<BTreeSet<PathBuf>> {
+        loop {
+            let mut batch = BTreeSet::from([self.events.recv().await?]);
+            while let Ok(Some(path)) = tokio::time::timeout(debounce, self.events.recv()).await {
+                batch.insert(path);
+            }
+            batch.retain(|path| path.is_file() && is_input_file(path));
+            if !batch.is_empty() {
+                return Some(batch);
+            }
+        }
+    }
+}
+
+/// Content hashes of files seen so far, to tell real changes from rewrites
+/// of identical content; they are the hashes incremental runs and the ledger
+/// key files by
+#[derive(Debug, Default)]
+pub struct ContentTracker {
+    hashes: HashMap<PathBuf, String>,
+}
+
+impl ContentTracker {
+    /// The files among `paths` that are new or whose content changed
+    pub fn changed(&mut self, paths: impl IntoIterator<Item = PathBuf>) -> Vec<PathBuf> {
+        paths
+            .into_iter()
+            .filter(|path| {
+                let Ok(hash) = file_hash(path) else {
+                    return false;
+                };
+                self.hashes.insert(path.clone(), hash.clone()) != Some(hash)
+            })
+            .collect()
+    }
+}
+
+/// Appends records to a dataset file, skipping those it already holds
+pub struct DatasetAppender {
+    path: PathBuf,
+    seen: HashSet<(String, String)>,
+}
+
+impl DatasetAppender {
+    pub fn open(path: &Path) -> Result<Self> {
+        let seen = if path.exists() {
+            schema::read_dataset(path)?
+                .items
+                .into_iter()
+                .map(|item| (item.question, item.answer))
+                .collect()
+        } else {
+            HashSet::new()
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            seen,
+        })
+    }
+
+    /// Append the records not yet in the dataset, returning how many were written
+    pub fn append(&mut self, items: &[ProcessedItem]) -> Result<usize> {
+        let new: Vec<ProcessedItem> = items
+            .iter()
+            .filter(|item| {
+                self.seen
+                    .insert((item.question.clone(), item.answer.clone()))
+            })
+            .cloned()
+            .collect();
+        if new.is_empty() {
+            return Ok(0);
+        }
+
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        if file.metadata()?.len() == 0 {
+            schema::write_header(&mut file)?;
+        }
+        schema::write_items(&mut file, &new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(question: &str) -> ProcessedItem {
+        ProcessedItem::new(question, "Answer.")
+    }
+
+    #[test]
+    fn test_mirror_destination() {
+        let dir = tempfile::tempdir().unwrap();
+        let docs = dir.path().join("docs");
+        fs::create_dir_all(&docs).unwrap();
+        let mirror = LocalMirror {
+            source: docs.clone(),
+            output_dir: PathBuf::from("/out"),
+        };
+        assert_eq!(
+            mirror.destination(&docs.join("guide/tls.md")),
+            Some(PathBuf::from("/out/guide/tls.md"))
+        );
+        assert_eq!(mirror.destination(&dir.path().join("other.md")), None);
+
+        let file = docs.join("readme.md");
+        fs::write(&file, "# Readme").unwrap();
+        let mirror = LocalMirror {
+            source: file.clone(),
+            output_dir: PathBuf::from("/out"),
+        };
+        assert_eq!(
+            mirror.destination(&file),
+            Some(PathBuf::from("/out/readme.md"))
+        );
+    }
+
+    #[test]
+    fn test_content_tracker_reports_changes_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.md");
+        let mut tracker = ContentTracker::default();
+
+        fs::write(&path, "v1").unwrap();
+        assert_eq!(tracker.changed([path.clone()]), vec![path.clone()]);
+        assert!(tracker.changed([path.clone()]).is_empty());
+        assert_eq!(tracker.hashes[&path], file_hash(&path).unwrap());
+
+        fs::write(&path, "v2").unwrap();
+        assert_eq!(tracker.changed([path.clone()]), vec![path.clone()]);
+        assert!(tracker.changed([dir.path().join("missing.md")]).is_empty());
+    }
+
+    #[test]
+    fn test_appender_skips_known_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("all_qa.jsonl");
+        schema::write_dataset(&path, &[item("What is TLS?")]).unwrap();
+
+        let mut appender = DatasetAppender::open(&path).unwrap();
+        assert_eq!(
+            appender
+                .append(&[item("What is TLS?"), item("What is mTLS?")])
+                .unwrap(),
+            1
+        );
+        assert_eq!(appender.append(&[item("What is mTLS?")]).unwrap(), 0);
+
+        let dataset = schema::read_dataset(&path).unwrap();
+        assert_eq!(dataset.items.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_watcher_reports_new_input_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let mut watcher = ChangeWatcher::new(std::slice::from_ref(&root)).unwrap();
+
+        fs::write(root.join("ignored.json"), "{}").unwrap();
+        fs::write(root.join("notes.md"), "# Notes").unwrap();
+
+        let batch = tokio::time::timeout(
+            Duration::from_secs(10),
+            watcher.next_batch(Duration::from_millis(200)),
+        )
+        .await
+        .expect("no change reported")
+        .unwrap();
+        assert_eq!(batch, BTreeSet::from([root.join("notes.md")]));
+    }
+}
@@ -3,6 +3,14 @@ use predicates::prelude::*;
 use std::env;
 use std::fs;
 
+/// The binary under test; nothing listens at the configured endpoints, so
+/// requests fail right away instead of waiting on retries
+fn builder_command() -> tokio::process::Command {
+    let mut command = tokio::process::Command::new(env!("CARGO_BIN_EXE_llm_dataset_builder"));
+    command.env("OLLAMA_MAX_RETRIES", "0");
+    command
+}
+
 #[tokio::test]
 async fn test_cli_args_override_env_vars() {
     let temp = assert_fs::TempDir::new().unwrap();
@@ -16,7 +24,7 @@ async fn test_cli_args_override_env_vars() {
     env::set_var("OUTPUT_DIR", "env-output");
 
     // Run with CLI args that override env vars
-    let status = tokio::process::Command::new(env!("CARGO_BIN_EXE_llm_dataset_builder"))
+    let status = builder_command()
         .arg("-e")
         .arg("http://cli-endpoint:11434")
         .arg("-m")
@@ -28,7 +36,11 @@ async fn test_cli_args_override_env_vars() {
         .await
         .unwrap();
 
-    assert!(status.success());
+    // Nothing listens at the endpoint, so the file fails as backend unreachable
+    assert_eq!(status.code(), Some(5));
+    temp.child("errors.json").assert(predicate::str::contains(
+        "\"reason\": \"backend_unreachable\"",
+    ));
 
     // Check that CLI args were used instead of env vars
     let output = temp.child("test_qa.jsonl");
@@ -53,13 +65,9 @@ async fn test_env_vars_used_when_no_cli_args() {
     env::set_var("OUTPUT_DIR", temp.path().to_str().unwrap());
 
     // Run without CLI args
-    let status = tokio::process::Command::new(env!("CARGO_BIN_EXE_llm_dataset_builder"))
-        .arg("--test-mode")
-        .status()
-        .await
-        .unwrap();
+    let status = builder_command().arg("--test-mode").status().await.unwrap();
 
-    assert!(status.success());
+    assert_eq!(status.code(), Some(5));
 
     // Check that env vars were used
     let output = temp.child("test_qa.jsonl");
@@ -84,7 +92,7 @@ async fn test_defaults_used_when_no_config() {
     env::remove_var("OUTPUT_DIR");
 
     // Run without any configuration
-    let status = tokio::process::Command::new(env!("CARGO_BIN_EXE_llm_dataset_builder"))
+    let status = builder_command()
         .arg("-d")
         .arg(temp.path().to_str().unwrap())
         .arg("--test-mode")
@@ -92,9 +100,471 @@ async fn test_defaults_used_when_no_config() {
         .await
         .unwrap();
 
-    assert!(status.success());
+    assert_eq!(status.code(), Some(5));
 
     // Check that output file was created
     let output = temp.child("test_qa.jsonl");
     output.assert(predicate::path::exists());
 }
+
+#[tokio::test]
+async fn test_unknown_profile_is_rejected() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let config = temp.child("config.toml");
+    config
+        .write_str("[profile.local]\nollama_host = \"localhost\"\n")
+        .unwrap();
+
+    let output = builder_command()
+        .arg("--config")
+        .arg(config.path())
+        .arg("--profile")
+        .arg("gpu-server")
+        .arg("-d")
+        .arg(temp.path())
+        .arg("--test-mode")
+        .output()
+        .await
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(4));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Unknown profile 'gpu-server'; available profiles: local"));
+    temp.child("errors.json")
+        .assert(predicate::str::contains("\"reason\": \"config\""));
+}
+
+#[tokio::test]
+async fn test_config_show_reports_sources() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let init = builder_command()
+        .current_dir(temp.path())
+        .args(["config", "init"])
+        .status()
+        .await
+        .unwrap();
+    assert!(init.success());
+    temp.child("llm_dataset_builder.toml")
+        .assert(predicate::str::contains("# qdrant_api_key = \"\""));
+
+    temp.child("llm_dataset_builder.toml")
+        .write_str("qdrant_api_key = \"file-secret\"\nqdrant_collection = \"from-file\"\n")
+        .unwrap();
+    let output = builder_command()
+        .current_dir(temp.path())
+        .args(["-m", "cli-model", "config", "show"])
+        .env_remove("QDRANT_API_KEY")
+        .env_remove("QDRANT_COLLECTION")
+        .output()
+        .await
+        .unwrap();
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let line = |name: &str| {
+        stdout
+            .lines()
+            .find(|line| line.starts_with(name))
+            .unwrap_or_default()
+            .to_string()
+    };
+    assert!(line("OLLAMA_MODEL ").contains("cli-model"));
+    assert!(line("OLLAMA_MODEL ").ends_with("(command line)"));
+    assert!(line("QDRANT_COLLECTION ").contains("from-file"));
+    assert!(line("QDRANT_COLLECTION ").ends_with("(config file)"));
+    assert!(line("QDRANT_API_KEY ").contains("********"));
+    assert!(!stdout.contains("file-secret"));
+}
+
+#[tokio::test]
+async fn test_stats_and_export_commands() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    temp.child("all_qa.jsonl")
+        .write_str(
+            "{\"question\":\"What is Qdrant?\",\"answer\":\"A vector database.\"}\n\
+             {\"question\":\"what is qdrant?\",\"answer\":\"A search engine.\"}\n",
+        )
+        .unwrap();
+
+    let output = builder_command()
+        .arg("stats")
+        .arg("-d")
+        .arg(temp.path())
+        .output()
+        .await
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Records:             2"));
+    assert!(stdout.contains("Duplicate questions: 1"));
+
+    let status = builder_command()
+        .args(["export", "--format", "openai", "-d"])
+        .arg(temp.path())
+        .status()
+        .await
+        .unwrap();
+    assert!(status.success());
+    temp.child("export_openai.jsonl")
+        .assert(predicate::str::contains("\"role\":\"assistant\""));
+}
+
+#[tokio::test]
+async fn test_validate_command_repairs_dataset() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    temp.child("all_qa.jsonl")
+        .write_str(
+            "{\"question\":\"What is Qdrant?\",\"answer\":\"A vector database.\"}\n\
+             {\"question\":\"what is qdrant?\",\"answer\":\"A search engine.\"}\n\
+             {\"question\":\"How to index?\",\"answer\":\"\"}\n\
+             not json\n",
+        )
+        .unwrap();
+
+    let output = builder_command()
+        .args(["validate", "--repair", "-d"])
+        .arg(temp.path())
+        .output()
+        .await
+        .unwrap();
+    assert_eq!(output.status.code(), Some(7));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Malformed lines:  1"));
+    assert!(stdout.contains("Empty answers:    1"));
+    assert!(stdout.contains("Duplicates:       1"));
+
+    let repaired = temp.child("all_qa.repaired.jsonl");
+    repaired.assert(predicate::str::contains("What is Qdrant?"));
+    repaired.assert(predicate::str::contains("How to index?").not());
+
+    let status = builder_command()
+        .arg("validate")
+        .arg(repaired.path())
+        .status()
+        .await
+        .unwrap();
+    assert!(status.success());
+}
+
+#[tokio::test]
+async fn test_contamination_command_removes_overlaps() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    temp.child("all_qa.jsonl")
+        .write_str(
+            "{\"question\":\"What is the capital of France?\",\"answer\":\"Paris.\"}\n\
+             {\"question\":\"How is TLS enabled?\",\"answer\":\"Set use_tls.\"}\n",
+        )
+        .unwrap();
+    let benchmark = temp.child("mmlu.jsonl");
+    benchmark
+        .write_str("{\"question\":\"What is the capital of France?\",\"choices\":[\"Paris\"]}\n")
+        .unwrap();
+
+    let output = builder_command()
+        .args(["contamination", "--remove", "-b"])
+        .arg(benchmark.path())
+        .arg("-d")
+        .arg(temp.path())
+        .output()
+        .await
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Contaminated: 1 of 2 records"));
+
+    temp.child("all_qa.contamination.json")
+        .assert(predicate::str::contains("\"line\": 1"));
+    let clean = temp.child("all_qa.decontaminated.jsonl");
+    clean.assert(predicate::str::contains("How is TLS enabled?"));
+    clean.assert(predicate::str::contains("France").not());
+}
+
+#[tokio::test]
+async fn test_balance_command_caps_dominant_document() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let record = |document: &str, i: usize| {
+        format!(
+            "{{\"question\":\"{} Q{}\",\"answer\":\"A\",\"lineage\":{{\"document\":\"{}\",\"span\":{{\"start\":0,\"end\":1}}}}}}\n",
+            document, i, document
+        )
+    };
+    let mut dataset = String::new();
+    for i in 0..8 {
+        dataset.push_str(&record("CHANGELOG.md", i));
+    }
+    for document in ["a.md", "b.md"] {
+        dataset.push_str(&record(document, 0));
+    }
+    temp.child("all_qa.jsonl").write_str(&dataset).unwrap();
+
+    let output = builder_command()
+        .args(["balance", "--max-source-share", "50", "-d"])
+        .arg(temp.path())
+        .output()
+        .await
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Kept 4 records, dropped 6"));
+    assert!(stdout.contains("CHANGELOG.md: 2 of 8"));
+    temp.child("all_qa.balanced.jsonl")
+        .assert(predicate::str::contains("b.md Q0"));
+
+    let output = builder_command()
+        .args(["balance", "-d"])
+        .arg(temp.path())
+        .output()
+        .await
+        .unwrap();
+    assert!(!output.status.success());
+}
+
+#[tokio::test]
+async fn test_benchmark_reports_failed_backend() {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let output = builder_command()
+        .args(["benchmark", "-n", "2", "-c", "1,2", "--skip-embeddings"])
+        .args([
+            "--skip-preflight",
+            "--no-pull",
+            "-e",
+            "http://127.0.0.1:1",
+            "-d",
+        ])
+        .arg(temp.path())
+        .output()
+        .await
+        .unwrap();
+    assert_eq!(output.status.code(), Some(5));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("tokens/s"));
+    assert!(stdout.contains("2 of 2 requests failed"));
+}
+
+#[tokio::test]
+async fn test_max_files_stops_with_budget_exit_code() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    fs::copy("tests/data/test.md", temp.path().join("a.md")).unwrap();
+    fs::copy("tests/data/test.md", temp.path().join("b.md")).unwrap();
+
+    let output = builder_command()
+        .args(["generate", "--test-mode", "--max-files", "1"])
+        .args(["-e", "http://127.0.0.1:1", "-d"])
+        .arg(temp.path())
+        .output()
+        .await
+        .unwrap();
+    assert_eq!(output.status.code(), Some(6));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Processing the first 1"));
+    temp.child("errors.json")
+        .assert(predicate::str::contains("\"budget_exceeded\""));
+
+    let output = builder_command()
+        .args(["generate", "--sample", "150", "-d"])
+        .arg(temp.path())
+        .output()
+        .await
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("not a percentage"));
+}
+
+#[tokio::test]
+async fn test_generation_options_only_apply_to_generating_commands() {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    // After a subcommand that doesn't generate, they are unknown
+    let output = builder_command()
+        .args(["stats", "--candidates", "5", "-d"])
+        .arg(temp.path())
+        .output()
+        .await
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unexpected argument '--candidates'"));
+
+    // Before one, they would only apply to the default pipeline run
+    let output = builder_command()
+        .args(["--publish", "nats://localhost/qa", "stats", "-d"])
+        .arg(temp.path())
+        .output()
+        .await
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("'--publish' cannot be used before 'stats'"));
+}
+
+#[tokio::test]
+async fn test_collect_from_source_flags() {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let status = builder_command()
+        .args(["collect", "--source", "tests/data/test.md", "-d"])
+        .arg(temp.path())
+        .status()
+        .await
+        .unwrap();
+    assert!(status.success());
+    temp.child("test.md").assert(predicate::path::exists());
+    temp.child("sources.json")
+        .assert(predicate::str::contains("\"kind\": \"local\""));
+
+    let output = builder_command()
+        .args(["collect", "--source", "tests/data/missing.md"])
+        .args(["--source-type", "url", "--source-type", "local", "-d"])
+        .arg(temp.path())
+        .output()
+        .await
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Got 2 --source-type values"));
+}
+
+/// Read output lines until one contains `needle`
+async fn wait_for_line<R: tokio::io::AsyncBufRead + Unpin>(
+    lines: &mut tokio::io::Lines<R>,
+    needle: &str,
+) {
+    let found = tokio::time::timeout(std::time::Duration::from_secs(30), async {
+        while let Some(line) = lines.next_line().await.unwrap() {
+            if line.contains(needle) {
+                return true;
+            }
+        }
+        false
+    })
+    .await;
+    assert_eq!(found, Ok(true), "no output line contained '{}'", needle);
+}
+
+#[tokio::test]
+async fn test_watch_processes_new_files() {
+    use tokio::io::AsyncBufReadExt;
+
+    let temp = assert_fs::TempDir::new().unwrap();
+    fs::copy("tests/data/test.md", temp.path().join("test.md")).unwrap();
+
+    let mut child = builder_command()
+        .args(["generate", "--watch", "--test-mode", "-d"])
+        .arg(temp.path())
+        .stdout(std::process::Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .unwrap();
+    let mut lines = tokio::io::BufReader::new(child.stdout.take().unwrap()).lines();
+
+    wait_for_line(&mut lines, "Watching").await;
+    fs::copy("tests/data/test.md", temp.path().join("added.md")).unwrap();
+    wait_for_line(&mut lines, "Processing file").await;
+    wait_for_line(&mut lines, "Error processing section").await;
+    temp.child("added_qa.jsonl")
+        .assert(predicate::path::exists());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_sigterm_stops_with_interrupted_exit_code() {
+    use tokio::io::AsyncBufReadExt;
+
+    let temp = assert_fs::TempDir::new().unwrap();
+    fs::copy("tests/data/test.md", temp.path().join("test.md")).unwrap();
+
+    let mut child = builder_command()
+        .args(["generate", "--watch", "--test-mode", "-d"])
+        .arg(temp.path())
+        .stdout(std::process::Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .unwrap();
+    let mut lines = tokio::io::BufReader::new(child.stdout.take().unwrap()).lines();
+    wait_for_line(&mut lines, "Watching").await;
+
+    let pid = child.id().unwrap().to_string();
+    let killed = std::process::Command::new("kill")
+        .args(["-TERM", &pid])
+        .status()
+        .unwrap();
+    assert!(killed.success());
+
+    wait_for_line(&mut lines, "Stopped watching").await;
+    let status = tokio::time::timeout(std::time::Duration::from_secs(30), child.wait())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(status.code(), Some(130));
+    temp.child("all_qa.jsonl").assert(predicate::path::exists());
+}
+
+#[tokio::test]
+async fn test_completions_cover_subcommands_and_profiles() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let config = temp.child("config.toml");
+    config
+        .write_str("[profile.gpu-server]\nollama_host = \"gpu\"\n")
+        .unwrap();
+
+    let output = builder_command()
+        .arg("--config")
+        .arg(config.path())
+        .args(["completions", "bash"])
+        .output()
+        .await
+        .unwrap();
+    assert!(output.status.success());
+    let script = String::from_utf8_lossy(&output.stdout);
+    for word in [
+        "export",
+        "stats",
+        "sharegpt",
+        "github-releases",
+        "gpu-server",
+    ] {
+        assert!(script.contains(word), "completions lack '{}'", word);
+    }
+
+    let output = builder_command()
+        .args(["completions", "tcsh"])
+        .output()
+        .await
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[tokio::test]
+async fn test_mock_backend_generates_offline() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let docs = temp.child("docs");
+    docs.child("guide.md")
+        .write_str(
+            "# Installation\n\nRun the installer and follow the prompts on screen.\n\n\
+             # Configuration\n\nSettings are read from config.toml in the working directory.\n",
+        )
+        .unwrap();
+    let output_dir = temp.child("output");
+
+    let output = builder_command()
+        .env("LLM_BACKEND", "mock")
+        .arg("pipeline")
+        .arg("--source")
+        .arg(docs.path())
+        .arg("-d")
+        .arg(output_dir.path())
+        .output()
+        .await
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output_dir.child("all_qa.jsonl").assert(
+        predicate::str::contains("What does the documentation say about Installation?").and(
+            predicate::str::contains("Run the installer and follow the prompts"),
+        ),
+    );
+    output_dir
+        .child("manifest.json")
+        .assert(predicate::str::contains("\"llm_backend\": \"mock\""));
+}
@@ -1,12 +1,11 @@
 use anyhow::anyhow;
 use async_trait::async_trait;
+use llm_dataset_builder::glossary::Term;
 use llm_dataset_builder::processor::{OllamaClient, OllamaProcessor, ProcessedItem};
 use mockall::mock;
-use mockall::predicate;
 use std::fs;
 use std::io::Write;
 use std::path::{Path, PathBuf};
-use tempfile;
 
 mock! {
      pub OllamaClient {}
@@ -14,6 +13,11 @@ mock! {
     #[async_trait]
     impl OllamaClient for OllamaClient {
         async fn generate_questions(&self, content: &str, target_count: usize) -> anyhow::Result<Vec<ProcessedItem>>;
+        async fn extract_terms(&self, content: &str) -> anyhow::Result<Vec<Term>>;
+        async fn generate_eval(&self, content: &str, target_count: usize) -> anyhow::Result<Vec<ProcessedItem>>;
+        async fn answer(&self, content: &str, question: &str) -> anyhow::Result<String>;
+        async fn answer_unaided(&self, question: &str) -> anyhow::Result<String>;
+        async fn judge(&self, item: &ProcessedItem) -> anyhow::Result<f64>;
     }
 }
 
@@ -128,12 +132,7 @@ async fn test_process_file_success() {
     mock_client
         .expect_generate_questions()
         .times(1)
-        .returning(|_, _| {
-            Ok(vec![ProcessedItem {
-                question: "test question".to_string(),
-                answer: "test answer".to_string(),
-            }])
-        });
+        .returning(|_, _| Ok(vec![ProcessedItem::new("test question", "test answer")]));
 
     let processor = TestOllamaProcessor::new(Box::new(mock_client), None);
     let temp_dir = tempfile::tempdir().unwrap();
@@ -174,10 +173,10 @@ async fn test_section_by_section_writing() {
         .times(2)
         .returning(|content, _| {
             let section_num = if content.contains("Section 1") { 1 } else { 2 };
-            Ok(vec![ProcessedItem {
-                question: format!("Q{}", section_num),
-                answer: format!("A{}", section_num),
-            }])
+            Ok(vec![ProcessedItem::new(
+                format!("Q{}", section_num),
+                format!("A{}", section_num),
+            )])
         });
 
     let temp_dir = tempfile::tempdir().unwrap();
@@ -219,10 +218,7 @@ async fn test_partial_section_failure() {
         .times(2)
         .returning(|content, _| {
             if content.contains("Section 1") {
-                Ok(vec![ProcessedItem {
-                    question: "Q1".to_string(),
-                    answer: "A1".to_string(),
-                }])
+                Ok(vec![ProcessedItem::new("Q1", "A1")])
             } else {
                 Err(anyhow!("Failed to process section 2"))
             }
@@ -264,12 +260,7 @@ async fn test_empty_sections_handling() {
     mock_client
         .expect_generate_questions()
         .times(1)
-        .returning(|_, _| {
-            Ok(vec![ProcessedItem {
-                question: "Q1".to_string(),
-                answer: "A1".to_string(),
-            }])
-        });
+        .returning(|_, _| Ok(vec![ProcessedItem::new("Q1", "A1")]));
 
     let temp_dir = tempfile::tempdir().unwrap();
     let processor =
@@ -298,3 +289,1260 @@ async fn test_empty_sections_handling() {
     );
     assert!(lines[0].contains("Q1"), "First line should contain Q1");
 }
+
+#[tokio::test]
+async fn test_incremental_regenerates_changed_sections() {
+    use llm_dataset_builder::processor::DefaultOllamaProcessor;
+    use std::sync::{Arc, Mutex};
+
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let make_processor = |output_dir: &Path| {
+        let requests = Arc::clone(&requests);
+        let mut mock_client = MockOllamaClient::new();
+        mock_client
+            .expect_generate_questions()
+            .returning(move |content, target| {
+                requests.lock().unwrap().push(content.to_string());
+                let heading = content.lines().next().unwrap_or_default().to_string();
+                Ok((0..target)
+                    .map(|i| ProcessedItem::new(format!("{} Q{}", heading, i), "A"))
+                    .collect())
+            });
+        DefaultOllamaProcessor::new_with_client(
+            String::new(),
+            String::new(),
+            Box::new(mock_client),
+            Some(output_dir.to_path_buf()),
+        )
+        .with_incremental(true)
+    };
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let test_file = temp_dir.path().join("doc.md");
+    fs::write(
+        &test_file,
+        "# One\nFirst section text.\n# Two\nSecond section text.\n",
+    )
+    .unwrap();
+
+    let first = make_processor(temp_dir.path())
+        .process_file(&test_file)
+        .await
+        .unwrap();
+    assert!(!first.is_empty());
+    assert_eq!(requests.lock().unwrap().len(), 2);
+
+    requests.lock().unwrap().clear();
+    fs::write(
+        &test_file,
+        "# One\nFirst section text.\n# Two\nSecond section, now rewritten.\n",
+    )
+    .unwrap();
+
+    let second = make_processor(temp_dir.path())
+        .process_file(&test_file)
+        .await
+        .unwrap();
+
+    // Only the changed section was sent to the model
+    let requests = requests.lock().unwrap();
+    assert_eq!(requests.len(), 1);
+    assert!(requests[0].starts_with("# Two"));
+
+    // Unchanged records are kept and the file holds both sections
+    let kept: Vec<_> = first
+        .iter()
+        .filter(|item| item.question.starts_with("# One"))
+        .map(|item| item.question.clone())
+        .collect();
+    assert!(kept
+        .iter()
+        .all(|q| second.iter().any(|item| &item.question == q)));
+    let dataset =
+        llm_dataset_builder::schema::read_dataset(&temp_dir.path().join("doc_qa.jsonl")).unwrap();
+    assert_eq!(dataset.items.len(), second.len());
+}
+
+#[tokio::test]
+async fn test_interrupted_run_resumes_incrementally() {
+    use llm_dataset_builder::processor::DefaultOllamaProcessor;
+    use llm_dataset_builder::shutdown::Shutdown;
+    use std::sync::{Arc, Mutex};
+
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let make_processor = |output_dir: &Path, shutdown: Shutdown| {
+        let requests = Arc::clone(&requests);
+        let mut mock_client = MockOllamaClient::new();
+        mock_client
+            .expect_generate_questions()
+            .returning(move |content, target| {
+                requests.lock().unwrap().push(content.to_string());
+                // Ctrl-C arrives while the first section is being generated
+                shutdown.request();
+                let heading = content.lines().next().unwrap_or_default().to_string();
+                Ok((0..target)
+                    .map(|i| ProcessedItem::new(format!("{} Q{}", heading, i), "A"))
+                    .collect())
+            });
+        DefaultOllamaProcessor::new_with_client(
+            String::new(),
+            String::new(),
+            Box::new(mock_client),
+            Some(output_dir.to_path_buf()),
+        )
+        .with_incremental(true)
+    };
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let test_file = temp_dir.path().join("doc.md");
+    fs::write(
+        &test_file,
+        "# One\nFirst section text.\n# Two\nSecond section text.\n",
+    )
+    .unwrap();
+
+    let shutdown = Shutdown::new();
+    let first = make_processor(temp_dir.path(), shutdown.clone())
+        .with_shutdown(shutdown)
+        .process_file(&test_file)
+        .await
+        .unwrap();
+    assert!(first.iter().all(|item| item.question.starts_with("# One")));
+    assert_eq!(requests.lock().unwrap().len(), 1);
+
+    // The rerun keeps the finished section and generates only the rest
+    requests.lock().unwrap().clear();
+    let second = make_processor(temp_dir.path(), Shutdown::new())
+        .process_file(&test_file)
+        .await
+        .unwrap();
+    let requests = requests.lock().unwrap();
+    assert_eq!(requests.len(), 1);
+    assert!(requests[0].starts_with("# Two"));
+    assert!(second.iter().any(|item| item.question.starts_with("# One")));
+    assert!(second.iter().any(|item| item.question.starts_with("# Two")));
+}
+
+#[tokio::test]
+async fn test_question_cap_spans_files() {
+    use llm_dataset_builder::limits::RunLimits;
+    use llm_dataset_builder::processor::DefaultOllamaProcessor;
+
+    let mut mock_client = MockOllamaClient::new();
+    mock_client
+        .expect_generate_questions()
+        .returning(|content, target| {
+            let heading = content.lines().next().unwrap_or_default().to_string();
+            Ok((0..target + 2)
+                .map(|i| ProcessedItem::new(format!("{} Q{}", heading, i), "A"))
+                .collect())
+        });
+    let limits = RunLimits::new(None, Some(5), None, 0);
+    let temp_dir = tempfile::tempdir().unwrap();
+    let processor = DefaultOllamaProcessor::new_with_client(
+        String::new(),
+        String::new(),
+        Box::new(mock_client),
+        Some(temp_dir.path().to_path_buf()),
+    )
+    .with_limits(limits.clone());
+
+    let first_file = temp_dir.path().join("first.md");
+    let second_file = temp_dir.path().join("second.md");
+    fs::write(&first_file, "# One\nFirst section text.\n").unwrap();
+    fs::write(&second_file, "# Two\nSecond section text.\n").unwrap();
+
+    let first = processor.process_file(&first_file).await.unwrap();
+    let second = processor.process_file(&second_file).await.unwrap();
+    assert_eq!(first.len() + second.len(), 5);
+    assert_eq!(limits.generated(), 5);
+    assert!(limits.exceeded(0).is_some());
+}
+
+#[tokio::test]
+async fn test_process_file_with_streams_each_section() {
+    use llm_dataset_builder::processor::DefaultOllamaProcessor;
+
+    let mut mock_client = MockOllamaClient::new();
+    mock_client
+        .expect_generate_questions()
+        .returning(|_, target| {
+            Ok((0..target)
+                .map(|i| ProcessedItem::new(format!("Q{}?", i), "A"))
+                .collect())
+        });
+    let temp_dir = tempfile::tempdir().unwrap();
+    let processor = DefaultOllamaProcessor::new_with_client(
+        String::new(),
+        String::new(),
+        Box::new(mock_client),
+        Some(temp_dir.path().to_path_buf()),
+    );
+
+    // A heading-less dump is cut into bounded sections at blank lines
+    let paragraph = format!("{}\n\n", "word ".repeat(2000));
+    let test_file = temp_dir.path().join("dump.txt");
+    fs::write(&test_file, paragraph.repeat(20)).unwrap();
+
+    let mut batches = Vec::new();
+    let passed = processor
+        .process_file_with(&test_file, &mut |items| {
+            batches.push(items.len());
+            Ok(())
+        })
+        .await
+        .unwrap();
+    assert!(batches.len() > 1);
+    assert!(batches.iter().all(|&len| len > 0));
+    assert_eq!(passed, batches.iter().sum::<usize>());
+}
+
+#[tokio::test]
+async fn test_changelog_versions_are_generated_from_one_at_a_time() {
+    use llm_dataset_builder::processor::DefaultOllamaProcessor;
+    use std::sync::{Arc, Mutex};
+
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let mut mock_client = MockOllamaClient::new();
+    let recorded = Arc::clone(&seen);
+    mock_client
+        .expect_generate_questions()
+        .returning(move |content, target| {
+            recorded.lock().unwrap().push(content.to_string());
+            Ok((0..target)
+                .map(|i| ProcessedItem::new(format!("Q{}?", i), "A"))
+                .collect())
+        });
+    let temp_dir = tempfile::tempdir().unwrap();
+    let processor = DefaultOllamaProcessor::new_with_client(
+        String::new(),
+        String::new(),
+        Box::new(mock_client),
+        Some(temp_dir.path().to_path_buf()),
+    );
+
+    let test_file = temp_dir.path().join("CHANGELOG.md");
+    fs::write(
+        &test_file,
+        "# Changelog\n\n## [2.0.0] - 2024-06-01\n### Changed\n- Config moved to TOML\n\n\
+         ## [1.9.1] - 2024-02-11\n### Fixed\n- Retry on timeouts\n",
+    )
+    .unwrap();
+    processor.process_file(&test_file).await.unwrap();
+
+    let seen = seen.lock().unwrap();
+    assert_eq!(seen.len(), 2);
+    assert!(seen[0].starts_with("# Changelog: version 2.0.0\nReleased: 2024-06-01\n"));
+    assert!(seen[0].contains("- Config moved to TOML"));
+    assert!(!seen[0].contains("Retry on timeouts"));
+    assert!(seen[1].starts_with("# Changelog: version 1.9.1\n"));
+
+    let releases: serde_json::Value = serde_json::from_str(
+        &fs::read_to_string(temp_dir.path().join("CHANGELOG_qa.changelog.json")).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(releases[1]["version"], "1.9.1");
+    assert_eq!(releases[1]["categories"][0]["name"], "Fixed");
+}
+
+#[tokio::test]
+async fn test_glossary_terms_become_definitions_and_go_with_sections() {
+    use llm_dataset_builder::processor::DefaultOllamaProcessor;
+    use std::sync::{Arc, Mutex};
+
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let mut mock_client = MockOllamaClient::new();
+    mock_client.expect_extract_terms().times(1).returning(|_| {
+        Ok(vec![
+            Term {
+                term: "Shard".to_string(),
+                definition: "A partition of an index.".to_string(),
+            },
+            Term {
+                term: "Tombstone".to_string(),
+                definition: "A marker for a deleted document.".to_string(),
+            },
+        ])
+    });
+    let recorded = Arc::clone(&seen);
+    mock_client
+        .expect_generate_questions()
+        .returning(move |content, target| {
+            recorded.lock().unwrap().push(content.to_string());
+            Ok((0..target)
+                .map(|i| ProcessedItem::new(format!("Q{} {}?", i, content.len()), "A"))
+                .collect())
+        });
+    let temp_dir = tempfile::tempdir().unwrap();
+    let processor = DefaultOllamaProcessor::new_with_client(
+        String::new(),
+        String::new(),
+        Box::new(mock_client),
+        Some(temp_dir.path().to_path_buf()),
+    )
+    .with_glossary(true);
+
+    let test_file = temp_dir.path().join("guide.md");
+    fs::write(
+        &test_file,
+        "# Scaling\n\nEach shard holds part of the index.\n\n# Install\n\nRun the installer.\n",
+    )
+    .unwrap();
+    let items = processor.process_file(&test_file).await.unwrap();
+
+    let seen = seen.lock().unwrap();
+    assert_eq!(seen.len(), 2);
+    assert!(
+        seen[0].ends_with("\n\nGlossary of this document:\n- Shard: A partition of an index.\n")
+    );
+    assert!(!seen[1].contains("Glossary"));
+
+    let definitions: Vec<_> = items
+        .iter()
+        .filter(|item| item.question.starts_with("What does"))
+        .map(|item| (item.question.as_str(), item.answer.as_str()))
+        .collect();
+    assert_eq!(
+        definitions,
+        [
+            ("What does \"Shard\" mean?", "A partition of an index."),
+            (
+                "What does \"Tombstone\" mean?",
+                "A marker for a deleted document."
+            )
+        ]
+    );
+    let glossary: serde_json::Value = serde_json::from_str(
+        &fs::read_to_string(temp_dir.path().join("guide_qa.glossary.json")).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(glossary["terms"][1]["term"], "Tombstone");
+}
+
+#[tokio::test]
+async fn test_target_tags_select_sections() {
+    use llm_dataset_builder::processor::DefaultOllamaProcessor;
+    use std::sync::{Arc, Mutex};
+
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let mut mock_client = MockOllamaClient::new();
+    let recorded = Arc::clone(&seen);
+    mock_client
+        .expect_generate_questions()
+        .returning(move |content, target| {
+            recorded.lock().unwrap().push(content.to_string());
+            Ok((0..target)
+                .map(|i| ProcessedItem::new(format!("Q{}?", i), "A"))
+                .collect())
+        });
+    let temp_dir = tempfile::tempdir().unwrap();
+    let processor = DefaultOllamaProcessor::new_with_client(
+        String::new(),
+        String::new(),
+        Box::new(mock_client),
+        Some(temp_dir.path().to_path_buf()),
+    )
+    .with_target_tags(vec!["qdrant".to_string(), "language:python".to_string()]);
+
+    let test_file = temp_dir.path().join("guide.md");
+    fs::write(
+        &test_file,
+        "# Storage\n\nEmbeddings are kept in Qdrant collections.\n\n\
+         # Install\n\nRun the installer.\n\n\
+         # Scripts\n\n```python\nprint(1)\n```\n",
+    )
+    .unwrap();
+    processor.process_file(&test_file).await.unwrap();
+
+    let seen = seen.lock().unwrap();
+    assert_eq!(seen.len(), 2);
+    assert!(seen[0].starts_with("# Storage"));
+    assert!(seen[1].starts_with("# Scripts"));
+}
+
+#[tokio::test]
+async fn test_target_tags_skip_parents_of_tagged_sections() {
+    use llm_dataset_builder::processor::DefaultOllamaProcessor;
+    use std::sync::{Arc, Mutex};
+
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let mut mock_client = MockOllamaClient::new();
+    let recorded = Arc::clone(&seen);
+    mock_client
+        .expect_generate_questions()
+        .returning(move |content, target| {
+            recorded.lock().unwrap().push(content.to_string());
+            Ok((0..target)
+                .map(|i| ProcessedItem::new(format!("Q{}?", i), "A"))
+                .collect())
+        });
+    let temp_dir = tempfile::tempdir().unwrap();
+    let processor = DefaultOllamaProcessor::new_with_client(
+        String::new(),
+        String::new(),
+        Box::new(mock_client),
+        Some(temp_dir.path().to_path_buf()),
+    )
+    .with_target_tags(vec!["switching endpoints".to_string()]);
+
+    let test_file = temp_dir.path().join("guide.md");
+    fs::write(
+        &test_file,
+        "# Client\n\nThe client sends requests to one server.\n\n\
+         ## Usage\n\nCall the client with a request.\n\n\
+         ### Advanced\n\nSwitching endpoints needs a restart of the client.\n",
+    )
+    .unwrap();
+    processor.process_file(&test_file).await.unwrap();
+
+    // The keyword is only under Advanced, so the intro is not generated from
+    let seen = seen.lock().unwrap();
+    assert!(!seen.is_empty());
+    assert!(
+        seen.iter()
+            .all(|chunk| chunk.contains("Switching endpoints")),
+        "{:?}",
+        seen
+    );
+}
+
+#[tokio::test]
+async fn test_only_sections_and_matching_select_sections() {
+    use llm_dataset_builder::processor::DefaultOllamaProcessor;
+    use std::sync::{Arc, Mutex};
+
+    async fn generated_from(sections: &[&str], pattern: Option<&str>) -> Vec<String> {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut mock_client = MockOllamaClient::new();
+        let recorded = Arc::clone(&seen);
+        mock_client
+            .expect_generate_questions()
+            .returning(move |content, _| {
+                recorded.lock().unwrap().push(content.to_string());
+                Ok(vec![ProcessedItem::new("Q?", "A")])
+            });
+        let temp_dir = tempfile::tempdir().unwrap();
+        let processor = DefaultOllamaProcessor::new_with_client(
+            String::new(),
+            String::new(),
+            Box::new(mock_client),
+            Some(temp_dir.path().to_path_buf()),
+        )
+        .with_target_sections(sections.iter().map(|s| s.to_string()).collect())
+        .with_target_pattern(pattern.map(|p| regex::Regex::new(p).unwrap()));
+
+        let test_file = temp_dir.path().join("guide.md");
+        fs::write(
+            &test_file,
+            "# Installation\n\nRun the installer.\n\n\
+             # Configuration\n\nSet cache_dir in config.toml.\n\n\
+             ## Troubleshooting\n\nIf the cache fails to open, check its permissions.\n\n\
+             # Usage\n\nThe server fails fast on bad input.\n",
+        )
+        .unwrap();
+        processor.process_file(&test_file).await.unwrap();
+        let seen = seen.lock().unwrap().clone();
+        seen.iter()
+            .map(|section| section.lines().next().unwrap().to_string())
+            .collect()
+    }
+
+    // Headings of any level match, ignoring case
+    assert_eq!(
+        generated_from(&["installation", "Troubleshooting"], None).await,
+        ["# Installation", "## Troubleshooting"]
+    );
+    assert_eq!(
+        generated_from(&[], Some("(?i)fails?\\b")).await,
+        ["## Troubleshooting", "# Usage"]
+    );
+    // Both at once only keep matching nodes under the headings
+    assert_eq!(
+        generated_from(&["Configuration", "Troubleshooting"], Some("fail")).await,
+        ["## Troubleshooting"]
+    );
+}
+
+#[tokio::test]
+async fn test_rich_schema_writes_metadata_columns() {
+    use llm_dataset_builder::metadata::RecordMetadata;
+    use llm_dataset_builder::processor::DefaultOllamaProcessor;
+
+    fn processor(dir: &Path, rich_schema: bool) -> DefaultOllamaProcessor {
+        let mut mock_client = MockOllamaClient::new();
+        mock_client
+            .expect_generate_questions()
+            .returning(|_, target| {
+                Ok((0..target)
+                    .map(|i| ProcessedItem {
+                        question: format!("What does Qdrant store in collection {}?", i),
+                        answer: "Embeddings are kept in Qdrant collections.".to_string(),
+                        // Columns the model made up are not kept
+                        metadata: RecordMetadata {
+                            model: Some("made-up".to_string()),
+                            ..RecordMetadata::default()
+                        },
+                        ..Default::default()
+                    })
+                    .collect())
+            });
+        DefaultOllamaProcessor::new_with_client(
+            String::new(),
+            "llama3".to_string(),
+            Box::new(mock_client),
+            Some(dir.to_path_buf()),
+        )
+        .with_rich_schema(rich_schema)
+    }
+
+    // Written from a directory of its own, as the ledger skips a file it
+    // generated from already
+    async fn records(rich_schema: bool) -> Vec<serde_json::Value> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let test_file = temp_dir.path().join("storage.md");
+        fs::write(
+            &test_file,
+            "# Storage\n\nEmbeddings are kept in Qdrant collections.\n",
+        )
+        .unwrap();
+        processor(temp_dir.path(), rich_schema)
+            .process_file(&test_file)
+            .await
+            .unwrap();
+        fs::read_to_string(temp_dir.path().join("storage_qa.jsonl"))
+            .unwrap()
+            .lines()
+            .skip(1)
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    let minimal = records(false).await;
+    assert!(!minimal.is_empty());
+    for record in &minimal {
+        assert!(record.get("model").is_none());
+        assert!(record.get("created_at").is_none());
+    }
+
+    let rich = records(true).await;
+    assert!(!rich.is_empty());
+    for record in &rich {
+        assert_eq!(record["source"], "storage.md");
+        assert_eq!(record["model"], "llama3");
+        assert_eq!(record["prompt_template"], "documentation");
+        assert_eq!(record["lang"], "en");
+        assert!(record["created_at"].is_string());
+        assert!(record["score"].as_f64().is_some_and(|s| s > 0.0));
+        let tags = record["tags"].as_array().unwrap();
+        assert!(tags
+            .iter()
+            .any(|tag| tag.as_str().unwrap().contains("qdrant")));
+    }
+}
+
+#[tokio::test]
+async fn test_uncovered_sections_are_topped_up() {
+    use llm_dataset_builder::coverage::{CoverageReport, DocumentSections};
+    use llm_dataset_builder::density::QuestionDensity;
+    use llm_dataset_builder::processor::DefaultOllamaProcessor;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    let failing = Arc::new(AtomicBool::new(true));
+    let fails = Arc::clone(&failing);
+    let mut mock_client = MockOllamaClient::new();
+    mock_client
+        .expect_generate_questions()
+        .returning(move |content, target| {
+            if content.starts_with("# Two") && fails.load(Ordering::SeqCst) {
+                return Err(anyhow!("Backend hiccup"));
+            }
+            Ok((0..target)
+                .map(|i| {
+                    ProcessedItem::new(format!("{} Q{}?", content.lines().next().unwrap(), i), "A")
+                })
+                .collect())
+        });
+    let temp_dir = tempfile::tempdir().unwrap();
+    let processor = DefaultOllamaProcessor::new_with_client(
+        String::new(),
+        String::new(),
+        Box::new(mock_client),
+        Some(temp_dir.path().to_path_buf()),
+    );
+    let test_file = temp_dir.path().join("guide.md");
+    fs::write(
+        &test_file,
+        format!(
+            "# One\n\n{}\n\n# Two\n\n{}\n",
+            "word ".repeat(30),
+            "more ".repeat(30)
+        ),
+    )
+    .unwrap();
+    let items = processor.process_file(&test_file).await.unwrap();
+
+    let documents = [DocumentSections::read(&test_file, "guide.md".to_string()).unwrap()];
+    let report = CoverageReport::new(
+        &documents,
+        QuestionDensity::default(),
+        items.iter().map(|item| item.lineage.as_ref()),
+    );
+    assert_eq!(report.sections[0].questions, items.len());
+    let uncovered: Vec<_> = report.uncovered().cloned().collect();
+    assert_eq!(uncovered.len(), 1);
+    assert_eq!(uncovered[0].heading, "# Two");
+
+    failing.store(false, Ordering::SeqCst);
+    let section = &documents[0].sections[1];
+    let topped_up = processor
+        .top_up("guide.md", section, uncovered[0].expected, None)
+        .await
+        .unwrap();
+    assert_eq!(topped_up.len(), uncovered[0].expected);
+    assert!(topped_up[0].question.starts_with("# Two"));
+    assert!(topped_up.iter().all(|item| item.id.is_some()));
+
+    let all: Vec<_> = items.iter().chain(&topped_up).collect();
+    let report = CoverageReport::new(
+        &documents,
+        QuestionDensity::default(),
+        all.iter().map(|item| item.lineage.as_ref()),
+    );
+    assert_eq!(report.uncovered().count(), 0);
+    assert_eq!(report.unmatched, 0);
+}
+
+#[tokio::test]
+async fn test_pipeline_reports_each_file_in_order() {
+    use llm_dataset_builder::limits::RunLimits;
+    use llm_dataset_builder::pipeline::{send_files, Pipeline};
+    use llm_dataset_builder::processor::DefaultOllamaProcessor;
+    use tokio::sync::mpsc;
+
+    let mut mock_client = MockOllamaClient::new();
+    mock_client
+        .expect_generate_questions()
+        .returning(|content, target| {
+            let heading = content.lines().next().unwrap_or_default().to_string();
+            Ok((0..target)
+                .map(|i| ProcessedItem::new(format!("{} Q{}", heading, i), "A"))
+                .collect())
+        });
+    let temp_dir = tempfile::tempdir().unwrap();
+    let processor = DefaultOllamaProcessor::new_with_client(
+        String::new(),
+        String::new(),
+        Box::new(mock_client),
+        Some(temp_dir.path().to_path_buf()),
+    )
+    .with_limits(RunLimits::new(Some(3), None, None, 0));
+
+    let first = temp_dir.path().join("first.md");
+    let missing = temp_dir.path().join("missing.md");
+    let second = temp_dir.path().join("second.md");
+    let held_back = temp_dir.path().join("held_back.md");
+    fs::write(&first, "# One\nText.\n## Two\nMore text.\n").unwrap();
+    fs::write(&second, "# Three\nText.\n").unwrap();
+    fs::write(&held_back, "# Four\nText.\n").unwrap();
+
+    let (files, received) = mpsc::channel(1);
+    let mut records = Vec::new();
+    let mut outcomes = Vec::new();
+    let mut append = |items: &[ProcessedItem]| {
+        records.extend(items.iter().map(|item| item.question.clone()));
+        Ok(())
+    };
+    let mut record = |path: &Path, result: anyhow::Result<usize>| {
+        outcomes.push((path.to_path_buf(), result.ok()));
+    };
+    let feed = send_files(
+        vec![first.clone(), missing.clone(), second.clone(), held_back],
+        files,
+    );
+    let ((), not_processed) = tokio::join!(
+        feed,
+        Pipeline::new(&processor).run(received, &mut append, &mut record)
+    );
+
+    assert_eq!(not_processed, 1);
+    let paths: Vec<&PathBuf> = outcomes.iter().map(|(path, _)| path).collect();
+    assert_eq!(paths, [&first, &missing, &second]);
+    assert_eq!(outcomes[1].1, None);
+
+    // Records arrive file by file and section by section
+    let headings: Vec<&str> = records
+        .iter()
+        .map(|question| question.rsplit_once(' ').unwrap().0)
+        .collect();
+    let mut order = headings.clone();
+    order.dedup();
+    assert_eq!(order, ["# One", "## Two", "# Three"]);
+    let first_count = headings
+        .iter()
+        .filter(|h| !h.starts_with("# Three"))
+        .count();
+    assert_eq!(outcomes[0].1, Some(first_count));
+    assert_eq!(outcomes[2].1, Some(records.len() - first_count));
+    assert!(temp_dir.path().join("first_qa.sections.json").exists());
+}
+
+#[tokio::test]
+async fn test_unchanged_files_are_skipped_unless_forced() {
+    use llm_dataset_builder::processor::DefaultOllamaProcessor;
+
+    fn processor(dir: &Path, expected_calls: usize) -> DefaultOllamaProcessor {
+        processor_with_model(dir, expected_calls, "llama3")
+    }
+
+    fn processor_with_model(
+        dir: &Path,
+        expected_calls: usize,
+        model: &str,
+    ) -> DefaultOllamaProcessor {
+        let mut mock_client = MockOllamaClient::new();
+        mock_client
+            .expect_generate_questions()
+            .times(expected_calls)
+            .returning(|_, _| {
+                // One record, far short of the question target
+                Ok(vec![ProcessedItem::new("Q?", "A")])
+            });
+        DefaultOllamaProcessor::new_with_client(
+            String::new(),
+            model.to_string(),
+            Box::new(mock_client),
+            Some(dir.to_path_buf()),
+        )
+    }
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let test_file = temp_dir.path().join("doc.md");
+    fs::write(&test_file, format!("# Doc\n{}\n", "word ".repeat(200))).unwrap();
+
+    let first = processor(temp_dir.path(), 1)
+        .process_file(&test_file)
+        .await
+        .unwrap();
+    assert!(temp_dir.path().join("ledger.json").exists());
+
+    let unchanged = processor(temp_dir.path(), 0)
+        .process_file(&test_file)
+        .await
+        .unwrap();
+    assert_eq!(unchanged.len(), first.len());
+
+    // Records of another model don't count as generated
+    processor_with_model(temp_dir.path(), 1, "mistral")
+        .process_file(&test_file)
+        .await
+        .unwrap();
+    processor_with_model(temp_dir.path(), 0, "mistral")
+        .process_file(&test_file)
+        .await
+        .unwrap();
+
+    processor(temp_dir.path(), 1)
+        .with_force(true)
+        .process_file(&test_file)
+        .await
+        .unwrap();
+
+    fs::write(&test_file, "# Doc\nEdited.\n").unwrap();
+    processor(temp_dir.path(), 1)
+        .process_file(&test_file)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_oversized_files_are_split_or_skipped() {
+    use llm_dataset_builder::oversize::{OversizePolicy, SizeLimit};
+    use llm_dataset_builder::processor::DefaultOllamaProcessor;
+
+    fn processor(
+        dir: &Path,
+        policy: OversizePolicy,
+        expected_calls: usize,
+    ) -> DefaultOllamaProcessor {
+        let mut mock_client = MockOllamaClient::new();
+        mock_client
+            .expect_generate_questions()
+            .times(expected_calls)
+            .returning(|content, target| {
+                Ok((0..target)
+                    .map(|i| {
+                        ProcessedItem::new(
+                            format!("Q{} about {}?", i, content.lines().next().unwrap()),
+                            "A",
+                        )
+                    })
+                    .collect())
+            });
+        DefaultOllamaProcessor::new_with_client(
+            String::new(),
+            String::new(),
+            Box::new(mock_client),
+            Some(dir.to_path_buf()),
+        )
+        .with_size_limit(SizeLimit {
+            max_bytes: 400,
+            policy,
+        })
+    }
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let test_file = temp_dir.path().join("doc.md");
+    let text: String = (1..=3)
+        .map(|n| format!("# Part {}\n{}\n", n, "word ".repeat(50)))
+        .collect();
+    fs::write(&test_file, text).unwrap();
+
+    let skipped = processor(temp_dir.path(), OversizePolicy::Skip, 0)
+        .process_file(&test_file)
+        .await;
+    assert!(skipped.is_err());
+    assert!(!temp_dir.path().join("doc_qa.jsonl").exists());
+
+    let items = processor(temp_dir.path(), OversizePolicy::Split, 3)
+        .process_file(&test_file)
+        .await
+        .unwrap();
+    assert!(items.iter().any(|item| item.question.contains("# Part 3")));
+    let ids: std::collections::HashSet<_> = items.iter().map(|item| item.id.unwrap()).collect();
+    assert_eq!(ids.len(), items.len());
+    // Record IDs stay the same when the parts are generated again
+    let again = processor(temp_dir.path(), OversizePolicy::Split, 3)
+        .with_force(true)
+        .process_file(&test_file)
+        .await
+        .unwrap();
+    let again_ids: Vec<_> = again.iter().map(|item| item.id).collect();
+    assert_eq!(
+        again_ids,
+        items.iter().map(|item| item.id).collect::<Vec<_>>()
+    );
+    // Records name the part, span and graph node they were generated from
+    let lineage = items[0].lineage.as_ref().unwrap();
+    assert!(lineage.document.starts_with("parts/doc.part"));
+    assert_eq!(lineage.nodes.len(), 1);
+    assert_eq!(again[0].lineage.as_ref(), Some(lineage));
+
+    for part in 1..=3 {
+        assert!(temp_dir
+            .path()
+            .join(format!("parts/doc.part00{}.md", part))
+            .exists());
+        assert!(temp_dir
+            .path()
+            .join(format!("doc.part00{}_qa.jsonl", part))
+            .exists());
+    }
+}
+
+#[tokio::test]
+async fn test_held_out_sections_get_reference_answers() {
+    use llm_dataset_builder::eval::EvalSplit;
+    use llm_dataset_builder::processor::DefaultOllamaProcessor;
+
+    fn item(content: &str, rubric: Option<Vec<String>>) -> ProcessedItem {
+        ProcessedItem {
+            question: format!("About {}?", content.lines().next().unwrap()),
+            answer: "Reference answer.".to_string(),
+            rubric,
+            ..Default::default()
+        }
+    }
+
+    let mut mock_client = MockOllamaClient::new();
+    mock_client.expect_generate_questions().never();
+    mock_client
+        .expect_generate_eval()
+        .times(2)
+        .returning(|content, _| {
+            Ok(vec![
+                item(content, Some(vec!["Mentions the flag".to_string()])),
+                // Rubric left out by the model
+                item(&format!("{} again", content), None),
+            ])
+        });
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let test_file = temp_dir.path().join("doc.md");
+    fs::write(
+        &test_file,
+        "# One
+First.
+
+# Two
+Second.
+",
+    )
+    .unwrap();
+
+    // Every section held out
+    let processor = DefaultOllamaProcessor::new_with_client(
+        String::new(),
+        String::new(),
+        Box::new(mock_client),
+        Some(temp_dir.path().to_path_buf()),
+    )
+    .with_eval_split(Some(EvalSplit::new(100.0)));
+    let items = processor.process_file(&test_file).await.unwrap();
+
+    assert_eq!(items.len(), 4);
+    assert!(items.iter().all(ProcessedItem::is_eval));
+    assert_eq!(
+        items[0].rubric.as_deref(),
+        Some(&["Mentions the flag".to_string()][..])
+    );
+    assert_eq!(items[1].rubric.as_deref(), Some(&[][..]));
+}
+
+#[tokio::test]
+async fn test_rejection_sampling_keeps_the_best_answer() {
+    use llm_dataset_builder::limits::RunLimits;
+    use llm_dataset_builder::processor::DefaultOllamaProcessor;
+    use llm_dataset_builder::rejection::{CandidateScoring, RejectionSampling};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let mut mock_client = MockOllamaClient::new();
+    mock_client
+        .expect_generate_questions()
+        .times(1)
+        .returning(|_, _| Ok(vec![ProcessedItem::new("How is TLS enabled?", "Bad.")]));
+    let asked = AtomicUsize::new(0);
+    mock_client.expect_answer().times(2).returning(move |_, _| {
+        Ok(match asked.fetch_add(1, Ordering::SeqCst) {
+            0 => "Okay answer.".to_string(),
+            _ => "Good answer.".to_string(),
+        })
+    });
+    mock_client.expect_judge().times(3).returning(|item| {
+        Ok(match item.answer.as_str() {
+            "Good answer." => 5.0,
+            "Okay answer." => 3.0,
+            _ => 1.0,
+        })
+    });
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let test_file = temp_dir.path().join("doc.md");
+    fs::write(&test_file, "# TLS\nSet use_tls to true.\n").unwrap();
+
+    let processor = DefaultOllamaProcessor::new_with_client(
+        String::new(),
+        String::new(),
+        Box::new(mock_client),
+        Some(temp_dir.path().to_path_buf()),
+    )
+    // One question, so the section isn't split for more
+    .with_limits(RunLimits::new(None, Some(1), None, 0))
+    .with_rejection_sampling(Some(RejectionSampling {
+        candidates: 3,
+        scoring: CandidateScoring::Judge,
+        keep_rejected: true,
+    }));
+    let items = processor.process_file(&test_file).await.unwrap();
+
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].answer, "Good answer.");
+    assert_eq!(
+        items[0].rejected.as_deref(),
+        Some(&["Okay answer.".to_string(), "Bad.".to_string()][..])
+    );
+}
+
+#[tokio::test]
+async fn test_student_failures_get_follow_up_questions() {
+    use llm_dataset_builder::distill::Student;
+    use llm_dataset_builder::processor::DefaultOllamaProcessor;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn item(question: &str) -> ProcessedItem {
+        ProcessedItem::new(question, format!("The answer to {}", question))
+    }
+
+    let calls = AtomicUsize::new(0);
+    let mut teacher = MockOllamaClient::new();
+    teacher
+        .expect_generate_questions()
+        .times(2)
+        .returning(move |_, target| {
+            Ok(match calls.fetch_add(1, Ordering::SeqCst) {
+                0 => (0..target).map(|i| item(&format!("Q{}", i))).collect(),
+                // A repeat of a question already asked, then two new ones
+                _ => vec![item("q0"), item("F1"), item("F2")],
+            })
+        });
+    let mut student = MockOllamaClient::new();
+    student.expect_answer_unaided().returning(|question| {
+        Ok(match question {
+            "Q0" => "No idea.".to_string(),
+            _ => format!("The answer to {}", question),
+        })
+    });
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let test_file = temp_dir.path().join("doc.md");
+    fs::write(&test_file, "# TLS\nSet use_tls to true.\n").unwrap();
+
+    let processor = DefaultOllamaProcessor::new_with_client(
+        String::new(),
+        String::new(),
+        Box::new(teacher),
+        Some(temp_dir.path().to_path_buf()),
+    )
+    .with_student(Some(Student::new(Box::new(student), "student", 0.5)));
+    let items = processor.process_file(&test_file).await.unwrap();
+
+    // One failure earns one follow-up question that wasn't asked before
+    assert_eq!(items.last().unwrap().question, "F1");
+    let report = processor.student_report().unwrap();
+    assert_eq!((report.failed, report.follow_up), (1, 1));
+    assert_eq!(report.asked, items.len() - 1);
+    assert_eq!(report.areas[0].document, "doc.md");
+    assert_eq!(report.areas[0].heading, "# TLS");
+}
+
+#[tokio::test]
+async fn test_dataset_builder_runs_the_pipeline() {
+    use llm_dataset_builder::builder::DatasetBuilder;
+    use llm_dataset_builder::datasource::LocalSource;
+    use llm_dataset_builder::schema::read_dataset;
+    use llm_dataset_builder::Config;
+
+    let mut mock_client = MockOllamaClient::new();
+    mock_client
+        .expect_generate_questions()
+        .returning(|_, target| {
+            Ok((0..target.max(2))
+                .map(|i| {
+                    ProcessedItem::new(
+                        format!("Q{}?", i),
+                        if i % 2 == 0 { "Kept" } else { "Dropped" }.to_string(),
+                    )
+                })
+                .collect())
+        });
+    let source_dir = tempfile::tempdir().unwrap();
+    let output_dir = tempfile::tempdir().unwrap();
+    fs::write(
+        source_dir.path().join("guide.md"),
+        format!("# Guide\n\n{}\n", "word ".repeat(40)),
+    )
+    .unwrap();
+
+    let built = DatasetBuilder::new(Config::from_env().unwrap())
+        .with_output_dir(output_dir.path())
+        .add_source(LocalSource::new(source_dir.path()))
+        .with_client(Box::new(mock_client))
+        .with_filter(|item| item.answer == "Kept")
+        .run()
+        .await
+        .unwrap();
+
+    assert_eq!(built.dataset, output_dir.path().join("all_qa.jsonl"));
+    assert_eq!(built.report.files_processed, 1);
+    assert!(built.report.failed_files.is_empty());
+    assert!(built.eval.is_none());
+    assert!(built.manifest.exists());
+
+    let dataset = read_dataset(&built.dataset).unwrap();
+    assert_eq!(dataset.items.len(), built.records);
+    assert!(built.records > 0);
+    assert!(dataset.items.iter().all(|item| item.answer == "Kept"));
+    // The file's own QA file keeps the filtered-out records
+    let per_file = read_dataset(&output_dir.path().join("guide_qa.jsonl")).unwrap();
+    assert!(per_file.items.len() > built.records);
+}
+
+#[tokio::test]
+async fn test_dataset_builder_with_mock_backend() {
+    use llm_dataset_builder::builder::DatasetBuilder;
+    use llm_dataset_builder::datasource::LocalSource;
+    use llm_dataset_builder::external::LLMBackend;
+    use llm_dataset_builder::schema::read_dataset;
+    use llm_dataset_builder::Config;
+
+    let source_dir = tempfile::tempdir().unwrap();
+    let output_dir = tempfile::tempdir().unwrap();
+    fs::write(
+        source_dir.path().join("guide.md"),
+        "# Caching\n\nResponses are cached for five minutes by default.\n",
+    )
+    .unwrap();
+
+    let mut config = Config::from_env().unwrap();
+    config.llm.backend = LLMBackend::Mock;
+    let built = DatasetBuilder::new(config)
+        .with_output_dir(output_dir.path())
+        .add_source(LocalSource::new(source_dir.path()))
+        .run()
+        .await
+        .unwrap();
+
+    let dataset = read_dataset(&built.dataset).unwrap();
+    assert!(built.records > 0);
+    assert_eq!(
+        dataset.items[0].question,
+        "What does the documentation say about Caching?"
+    );
+    assert_eq!(
+        dataset.items[0].answer,
+        "Responses are cached for five minutes by default."
+    );
+}
+
+#[tokio::test]
+async fn test_observers_see_and_filter_pipeline_events() {
+    use llm_dataset_builder::observer::PipelineObserver;
+    use llm_dataset_builder::processor::DefaultOllamaProcessor;
+    use std::ops::Range;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    struct Recorder {
+        events: Mutex<Vec<String>>,
+    }
+
+    impl PipelineObserver for Recorder {
+        fn on_file_start(&self, path: &Path) {
+            let name = path.file_name().unwrap().to_string_lossy();
+            self.events.lock().unwrap().push(format!("start {}", name));
+        }
+
+        fn on_chunk_generated(
+            &self,
+            document: &str,
+            _span: &Range<usize>,
+            items: &mut Vec<ProcessedItem>,
+        ) {
+            items.retain(|item| !item.question.contains("Skip"));
+            let event = format!("chunk {} {}", document, items.len());
+            self.events.lock().unwrap().push(event);
+        }
+
+        fn on_item_accepted(&self, item: &ProcessedItem) {
+            let event = format!("accepted {}", item.question);
+            self.events.lock().unwrap().push(event);
+        }
+
+        fn on_error(&self, _path: &Path, error: &anyhow::Error) {
+            self.events.lock().unwrap().push(format!("error {}", error));
+        }
+    }
+
+    let mut mock_client = MockOllamaClient::new();
+    mock_client
+        .expect_generate_questions()
+        .returning(|content, target| {
+            if content.contains("Broken") {
+                return Err(anyhow!("model went away"));
+            }
+            Ok((0..target)
+                .map(|i| {
+                    ProcessedItem::new(
+                        format!("{} {}?", if i % 2 == 0 { "Keep" } else { "Skip" }, i),
+                        "A",
+                    )
+                })
+                .collect())
+        });
+    let temp_dir = tempfile::tempdir().unwrap();
+    let recorder = Arc::new(Recorder::default());
+    let processor = DefaultOllamaProcessor::new_with_client(
+        String::new(),
+        String::new(),
+        Box::new(mock_client),
+        Some(temp_dir.path().to_path_buf()),
+    )
+    .with_observer(recorder.clone());
+
+    let good = temp_dir.path().join("good.md");
+    let broken = temp_dir.path().join("broken.md");
+    fs::write(&good, "# Good\nSome text.\n").unwrap();
+    fs::write(&broken, "# Broken\nOther text.\n").unwrap();
+
+    // The section of 3 words asks for 4 questions, half of which are skipped
+    let items = processor.process_file(&good).await.unwrap();
+    assert_eq!(items.len(), 2);
+    assert!(processor.process_file(&broken).await.is_err());
+
+    let events = recorder.events.lock().unwrap();
+    assert_eq!(
+        events[..5],
+        [
+            "start good.md",
+            "chunk good.md 2",
+            "accepted Keep 0?",
+            "accepted Keep 2?",
+            "start broken.md"
+        ]
+    );
+    // The failed section, then the failed file
+    assert_eq!(events.len(), 7);
+    assert!(events[5..].iter().all(|event| event.starts_with("error")));
+}
+
+#[tokio::test]
+async fn test_workers_generate_claimed_files() {
+    use llm_dataset_builder::processor::DefaultOllamaProcessor;
+    use llm_dataset_builder::queue::{self, QueueClient, WorkQueue, DEFAULT_LEASE};
+
+    let mut mock_client = MockOllamaClient::new();
+    mock_client
+        .expect_generate_questions()
+        .returning(|_, target| {
+            Ok((0..target)
+                .map(|i| ProcessedItem::new(format!("Q{}?", i), "An answer"))
+                .collect())
+        });
+    let worker_dir = tempfile::tempdir().unwrap();
+    let processor = DefaultOllamaProcessor::new_with_client(
+        "http://localhost:11434".to_string(),
+        "test-model".to_string(),
+        Box::new(mock_client),
+        Some(worker_dir.path().to_path_buf()),
+    );
+
+    let (work_queue, mut results) = WorkQueue::new(DEFAULT_LEASE);
+    work_queue.push(
+        "docs/guide.md".to_string(),
+        format!("# Guide\n\n{}\n", "word ".repeat(40)),
+    );
+    work_queue.close();
+    let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+    let (addr, server) = work_queue
+        .serve("127.0.0.1:0".parse().unwrap(), async {
+            let _ = stopped.await;
+        })
+        .unwrap();
+    let server = tokio::spawn(server);
+
+    let coordinator = QueueClient::new(&format!("http://{}", addr), None);
+    let done = queue::work(
+        &processor,
+        &coordinator,
+        worker_dir.path(),
+        "gpu-1",
+        "test-model",
+    )
+    .await
+    .unwrap();
+    assert_eq!(done, 1);
+
+    let completed = results.recv().await.unwrap();
+    assert_eq!(completed.path, "docs/guide.md");
+    assert_eq!(completed.result.worker, "gpu-1");
+    assert!(completed.result.error.is_none());
+    assert!(!completed.result.items.is_empty());
+    // Records are named after the file's path on the coordinator
+    let lineage = completed.result.items[0].lineage.as_ref().unwrap();
+    assert_eq!(lineage.document, "docs/guide.md");
+    assert!(results.recv().await.is_none());
+    assert!(worker_dir.path().join("guide_qa.jsonl").exists());
+
+    stop.send(()).unwrap();
+    server.await.unwrap().unwrap();
+}